@@ -98,11 +98,53 @@ pub async fn complete(
         let mut body = Vec::new();
         response.body_mut().read_to_end(&mut body).await?;
         let body_str = std::str::from_utf8(&body)?;
-        Err(anyhow!(
-            "Failed to connect to API: {} {}",
-            response.status(),
-            body_str
-        ))
+        Err(RequestError {
+            status: response.status().as_u16(),
+            message: format!("{} {}", response.status(), body_str),
+        }
+        .into())
+    }
+}
+
+/// Counts the input tokens of a request without running it, so that its cost
+/// can be known before it's made.
+pub async fn count_tokens(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    request: &Request,
+) -> Result<CountTokensResponse> {
+    let request = CountTokensRequest {
+        model: &request.model,
+        messages: &request.messages,
+        tools: &request.tools,
+        tool_choice: request.tool_choice.as_ref(),
+        system: request.system.as_deref(),
+    };
+    let uri = format!("{api_url}/v1/messages/count_tokens");
+    let request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Anthropic-Version", "2023-06-01")
+        .header("Anthropic-Beta", "token-counting-2024-11-01")
+        .header("X-Api-Key", api_key)
+        .header("Content-Type", "application/json");
+
+    let serialized_request = serde_json::to_string(&request)?;
+    let request = request_builder.body(AsyncBody::from(serialized_request))?;
+
+    let mut response = client.send(request).await?;
+    let mut body = Vec::new();
+    response.body_mut().read_to_end(&mut body).await?;
+    if response.status().is_success() {
+        Ok(serde_json::from_slice(&body)?)
+    } else {
+        let body_str = std::str::from_utf8(&body)?;
+        Err(RequestError {
+            status: response.status().as_u16(),
+            message: format!("{} {}", response.status(), body_str),
+        }
+        .into())
     }
 }
 
@@ -160,15 +202,31 @@ pub async fn stream_completion(
                 "Unexpected success response while expecting an error: {}",
                 body_str,
             )),
-            Err(_) => Err(anyhow!(
-                "Failed to connect to API: {} {}",
-                response.status(),
-                body_str,
-            )),
+            Err(_) => Err(RequestError {
+                status: response.status().as_u16(),
+                message: format!("{} {}", response.status(), body_str),
+            }
+            .into()),
         }
     }
 }
 
+/// An unsuccessful response from the API.
+#[derive(Debug)]
+pub struct RequestError {
+    /// The HTTP status code of the response.
+    pub status: u16,
+    message: String,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to connect to API: {}", self.message)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
 pub fn extract_text_from_events(
     response: impl Stream<Item = Result<Event>>,
 ) -> impl Stream<Item = Result<String>> {
@@ -190,20 +248,20 @@ pub fn extract_text_from_events(
     })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: Vec<Content>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     User,
     Assistant,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Content {
     #[serde(rename = "text")]
@@ -223,7 +281,7 @@ pub enum Content {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String,
@@ -231,14 +289,14 @@ pub struct ImageSource {
     pub data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
     pub description: String,
     pub input_schema: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ToolChoice {
     Auto,
@@ -246,7 +304,7 @@ pub enum ToolChoice {
     Tool { name: String },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Request {
     pub model: String,
     pub max_tokens: u32,
@@ -276,11 +334,29 @@ struct StreamingRequest {
     pub stream: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Metadata {
     pub user_id: Option<String>,
 }
 
+/// The parts of a [`Request`] that its input tokens are counted from.
+#[derive(Debug, Serialize)]
+struct CountTokensRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [Tool],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'a ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CountTokensResponse {
+    pub input_tokens: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Usage {
     #[serde(default, skip_serializing_if = "Option::is_none")]
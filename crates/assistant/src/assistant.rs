@@ -231,18 +231,30 @@ fn init_completion_provider(cx: &mut AppContext) {
 
 fn update_active_language_model_from_settings(cx: &mut AppContext) {
     let settings = AssistantSettings::get_global(cx);
-    let provider_name = LanguageModelProviderId::from(settings.default_model.provider.clone());
-    let model_id = LanguageModelId::from(settings.default_model.model.clone());
-
-    let Some(provider) = LanguageModelRegistry::global(cx)
-        .read(cx)
-        .provider(&provider_name)
-    else {
-        return;
-    };
-
-    let models = provider.provided_models(cx);
-    if let Some(model) = models.iter().find(|model| model.id() == model_id).cloned() {
+    let registry = LanguageModelRegistry::read_global(cx);
+
+    // Users who haven't chosen a model get the one recommended by the server,
+    // if it's available.
+    let recommended_model = registry
+        .recommended_default_model()
+        .filter(|_| !settings.default_model_pinned)
+        .and_then(|recommended_model| {
+            let provider = registry.provider(&recommended_model.provider)?;
+            let models = provider.provided_models(cx);
+            models
+                .into_iter()
+                .find(|model| model.id() == recommended_model.model)
+        });
+
+    let model = recommended_model.or_else(|| {
+        let provider_name = LanguageModelProviderId::from(settings.default_model.provider.clone());
+        let model_id = LanguageModelId::from(settings.default_model.model.clone());
+        let provider = registry.provider(&provider_name)?;
+        let models = provider.provided_models(cx);
+        models.into_iter().find(|model| model.id() == model_id)
+    });
+
+    if let Some(model) = model {
         LanguageModelCompletionProvider::global(cx).update(cx, |completion_provider, cx| {
             completion_provider.set_active_model(model, cx);
         });
@@ -1,6 +1,7 @@
 use crate::ContextStoreEvent;
 use crate::{
     assistant_settings::{AssistantDockPosition, AssistantSettings},
+    context_store::contexts_dir_for_project,
     humanize_token_count,
     prompt_library::open_prompt_library,
     slash_command::{
@@ -77,6 +78,7 @@ use workspace::{
 use workspace::{searchable::SearchableItemHandle, NewFile};
 
 pub fn init(cx: &mut AppContext) {
+    AssistantSettings::register(cx);
     workspace::FollowableViewRegistry::register::<ContextEditor>(cx);
     cx.observe_new_views(
         |workspace: &mut Workspace, _cx: &mut ViewContext<Workspace>| {
@@ -1527,18 +1529,17 @@ impl ContextEditor {
         match event {
             ContextEvent::MessagesEdited => {
                 self.update_message_headers(cx);
-                self.context.update(cx, |context, cx| {
-                    context.save(Some(Duration::from_millis(500)), self.fs.clone(), cx);
-                });
+                self.save_context(cx);
             }
             ContextEvent::EditStepsChanged => {
                 cx.notify();
             }
             ContextEvent::SummaryChanged => {
                 cx.emit(EditorEvent::TitleChanged);
-                self.context.update(cx, |context, cx| {
-                    context.save(Some(Duration::from_millis(500)), self.fs.clone(), cx);
-                });
+                self.save_context(cx);
+            }
+            ContextEvent::HistoryCompressed => {
+                self.save_context(cx);
             }
             ContextEvent::StreamedCompletion => {
                 self.editor.update(cx, |editor, cx| {
@@ -2253,8 +2254,18 @@ impl ContextEditor {
     }
 
     fn save(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
+        self.save_context(cx);
+    }
+
+    fn save_context(&mut self, cx: &mut ViewContext<Self>) {
+        let contexts_dir = contexts_dir_for_project(self.project.read(cx), cx);
         self.context.update(cx, |context, cx| {
-            context.save(Some(Duration::from_millis(500)), self.fs.clone(), cx)
+            context.save(
+                Some(Duration::from_millis(500)),
+                contexts_dir,
+                self.fs.clone(),
+                cx,
+            )
         });
     }
 
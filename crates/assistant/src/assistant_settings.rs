@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anthropic::Model as AnthropicModel;
 use fs::Fs;
@@ -53,9 +53,46 @@ pub struct AssistantSettings {
     pub default_width: Pixels,
     pub default_height: Pixels,
     pub default_model: AssistantDefaultModel,
+    /// Whether the user chose the default model themselves, rather than
+    /// relying on Zed's default.
+    pub default_model_pinned: bool,
+    pub compression: CompressionSettings,
+    pub data: ConversationDataSettings,
     pub using_outdated_settings_version: bool,
 }
 
+/// Settings for compressing long conversations by summarizing their older
+/// messages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompressionSettings {
+    pub enabled: bool,
+    pub threshold_tokens: usize,
+    pub keep_recent_messages: usize,
+    pub model: Option<AssistantDefaultModel>,
+}
+
+/// Where saved conversations are written.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationDataLocation {
+    /// Zed's global conversations directory.
+    #[default]
+    Global,
+    /// A `.zed/conversations` directory in the workspace's first folder.
+    Workspace,
+    /// Conversations are never written to disk.
+    Disabled,
+}
+
+/// Settings for where conversations are saved and how long they are kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConversationDataSettings {
+    pub location: ConversationDataLocation,
+    /// How long saved conversations are kept before they are deleted, or
+    /// `None` to keep them forever.
+    pub retention: Option<Duration>,
+}
+
 /// Assistant panel settings
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(untagged)]
@@ -131,6 +168,7 @@ impl AssistantSettingsContent {
                                         Some(language_model::settings::OllamaSettingsContent {
                                             api_url,
                                             low_speed_timeout_in_seconds,
+                                            ..Default::default()
                                         });
                                 }
                             },
@@ -150,6 +188,7 @@ impl AssistantSettingsContent {
                                             api_url,
                                             low_speed_timeout_in_seconds,
                                             available_models,
+                                            ..Default::default()
                                         });
                                 }
                             },
@@ -203,6 +242,8 @@ impl AssistantSettingsContent {
                                 })
                             }
                         }),
+                    compression: None,
+                    data: None,
                 },
                 VersionedAssistantSettingsContent::V2(settings) => settings.clone(),
             },
@@ -221,6 +262,8 @@ impl AssistantSettingsContent {
                         .id()
                         .to_string(),
                 }),
+                compression: None,
+                data: None,
             },
         }
     }
@@ -336,6 +379,8 @@ impl Default for VersionedAssistantSettingsContent {
             default_width: None,
             default_height: None,
             default_model: None,
+            compression: None,
+            data: None,
         })
     }
 }
@@ -364,6 +409,45 @@ pub struct AssistantSettingsContentV2 {
     default_height: Option<f32>,
     /// The default model to use when creating new contexts.
     default_model: Option<AssistantDefaultModel>,
+    /// Settings for compressing conversations that outgrow the model's
+    /// context window.
+    compression: Option<CompressionSettingsContent>,
+    /// Settings for where conversations are saved and how long they are
+    /// kept.
+    data: Option<ConversationDataSettingsContent>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct CompressionSettingsContent {
+    /// Whether to summarize the older messages of long conversations.
+    ///
+    /// Default: false
+    pub enabled: Option<bool>,
+    /// The number of tokens a conversation must exceed before its older
+    /// messages are summarized.
+    ///
+    /// Default: 16000
+    pub threshold_tokens: Option<usize>,
+    /// The number of most recent messages that are always sent verbatim.
+    ///
+    /// Default: 6
+    pub keep_recent_messages: Option<usize>,
+    /// The model used to write summaries. Defaults to the active model.
+    pub model: Option<AssistantDefaultModel>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ConversationDataSettingsContent {
+    /// Where to save conversations: "global", "workspace" or "disabled".
+    ///
+    /// Default: global
+    pub location: Option<ConversationDataLocation>,
+    /// The number of days to keep saved conversations for. Older
+    /// conversations are deleted automatically. Set to null to keep them
+    /// forever.
+    ///
+    /// Default: null
+    pub retention_days: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -485,8 +569,33 @@ impl Settings for AssistantSettings {
                 &mut settings.default_model,
                 value.default_model.map(Into::into),
             );
+            if let Some(compression) = value.compression {
+                merge(&mut settings.compression.enabled, compression.enabled);
+                merge(
+                    &mut settings.compression.threshold_tokens,
+                    compression.threshold_tokens,
+                );
+                merge(
+                    &mut settings.compression.keep_recent_messages,
+                    compression.keep_recent_messages,
+                );
+                if compression.model.is_some() {
+                    settings.compression.model = compression.model;
+                }
+            }
+            if let Some(data) = value.data {
+                merge(&mut settings.data.location, data.location);
+                if let Some(retention_days) = data.retention_days {
+                    settings.data.retention =
+                        Some(Duration::from_secs(retention_days * 24 * 60 * 60));
+                }
+            }
         }
 
+        settings.default_model_pinned = sources
+            .customizations()
+            .any(|value| value.upgrade().default_model.is_some());
+
         Ok(settings)
     }
 }
@@ -1,6 +1,8 @@
 use crate::{
-    prompt_library::PromptStore, slash_command::SlashCommandLine, InitialInsertion,
-    LanguageModelCompletionProvider, MessageId, MessageStatus,
+    assistant_settings::{AssistantSettings, CompressionSettings},
+    prompt_library::PromptStore,
+    slash_command::SlashCommandLine,
+    InitialInsertion, LanguageModelCompletionProvider, MessageId, MessageStatus,
 };
 use anyhow::{anyhow, Context as _, Result};
 use assistant_slash_command::{
@@ -18,12 +20,16 @@ use gpui::{AppContext, Context as _, EventEmitter, Model, ModelContext, Subscrip
 use language::{
     AnchorRangeExt, Bias, Buffer, LanguageRegistry, OffsetRangeExt, ParseStatus, Point, ToOffset,
 };
-use language_model::{LanguageModelRequest, LanguageModelRequestMessage, LanguageModelTool, Role};
+use language_model::{
+    LanguageModel, LanguageModelId, LanguageModelProviderId, LanguageModelRegistry,
+    LanguageModelRequest, LanguageModelRequestFeature, LanguageModelRequestMessage,
+    LanguageModelTool, Role,
+};
 use open_ai::Model as OpenAiModel;
-use paths::contexts_dir;
 use project::Project;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use settings::Settings;
 use std::{
     cmp,
     fmt::Debug,
@@ -274,6 +280,7 @@ impl ContextOperation {
 pub enum ContextEvent {
     MessagesEdited,
     SummaryChanged,
+    HistoryCompressed,
     EditStepsChanged,
     StreamedCompletion,
     PendingSlashCommandsUpdated {
@@ -295,6 +302,16 @@ pub struct ContextSummary {
     timestamp: clock::Lamport,
 }
 
+/// A summary that stands in for a conversation's older messages when the
+/// conversation is sent to the model. The summarized messages themselves stay
+/// in the context.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressedHistory {
+    /// The last message covered by the summary.
+    pub through: MessageId,
+    pub summary: String,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MessageAnchor {
     pub id: MessageId,
@@ -742,6 +759,8 @@ pub struct Context {
     pending_completions: Vec<PendingCompletion>,
     token_count: Option<usize>,
     pending_token_count: Task<Option<()>>,
+    compressed_history: Option<CompressedHistory>,
+    pending_compression: Option<Task<()>>,
     pending_save: Task<Result<()>>,
     path: Option<PathBuf>,
     _subscriptions: Vec<Subscription>,
@@ -806,6 +825,8 @@ impl Context {
             pending_completions: Default::default(),
             token_count: None,
             pending_token_count: Task::ready(None),
+            compressed_history: None,
+            pending_compression: None,
             _subscriptions: vec![cx.subscribe(&buffer, Self::handle_buffer_event)],
             pending_save: Task::ready(Ok(())),
             path: None,
@@ -874,12 +895,13 @@ impl Context {
                     }
                 })
                 .collect(),
+            compressed_history: self.compressed_history.clone(),
         }
     }
 
     #[allow(clippy::too_many_arguments)]
     pub fn deserialize(
-        saved_context: SavedContext,
+        mut saved_context: SavedContext,
         path: PathBuf,
         language_registry: Arc<LanguageRegistry>,
         telemetry: Option<Arc<Telemetry>>,
@@ -898,8 +920,10 @@ impl Context {
         this.buffer.update(cx, |buffer, cx| {
             buffer.set_text(saved_context.text.as_str(), cx)
         });
+        let compressed_history = saved_context.compressed_history.take();
         let operations = saved_context.into_ops(&this.buffer, cx);
         this.apply_ops(operations, cx).unwrap();
+        this.compressed_history = compressed_history;
         this
     }
 
@@ -1193,6 +1217,7 @@ impl Context {
                     .await?;
                 this.update(&mut cx, |this, cx| {
                     this.token_count = Some(token_count);
+                    this.compress_if_needed(cx);
                     cx.notify()
                 })
             }
@@ -1200,6 +1225,104 @@ impl Context {
         });
     }
 
+    /// The summary currently sent in place of the conversation's older
+    /// messages, if any.
+    pub fn compressed_history(&self) -> Option<&CompressedHistory> {
+        self.compressed_history.as_ref()
+    }
+
+    /// Returns the number of leading done messages covered by the compressed
+    /// history, or `None` if the message it ends at no longer exists.
+    fn compressed_message_count(&self, messages: &[Message]) -> Option<usize> {
+        let through = self.compressed_history.as_ref()?.through;
+        let ix = messages.iter().position(|message| message.id == through)?;
+        Some(ix + 1)
+    }
+
+    /// Summarizes the older messages of the conversation once it exceeds the
+    /// token threshold configured in the compression settings.
+    fn compress_if_needed(&mut self, cx: &mut ModelContext<Self>) {
+        let settings = AssistantSettings::get_global(cx).compression.clone();
+        if !settings.enabled
+            || self.pending_compression.is_some()
+            || self
+                .token_count
+                .map_or(true, |token_count| token_count <= settings.threshold_tokens)
+        {
+            return;
+        }
+
+        let messages = self
+            .messages(cx)
+            .filter(|message| matches!(message.status, MessageStatus::Done))
+            .collect::<Vec<_>>();
+        let Some(summarized_count) = messages
+            .len()
+            .checked_sub(settings.keep_recent_messages)
+            .filter(|count| *count > 0)
+        else {
+            return;
+        };
+        let already_compressed = self.compressed_message_count(&messages);
+        if already_compressed.map_or(false, |count| count >= summarized_count) {
+            return;
+        }
+        let Some(model) = compression_model(&settings, cx) else {
+            return;
+        };
+
+        // Build on the existing summary, so only messages that haven't been
+        // summarized yet are sent to the model.
+        let previous_summary = already_compressed.and(self.compressed_history.as_ref());
+        let buffer = self.buffer.read(cx);
+        let request = LanguageModelRequest {
+            messages: iter::once(LanguageModelRequestMessage {
+                role: Role::System,
+                content: COMPRESSION_PROMPT.into(),
+            })
+            .chain(previous_summary.map(|history| summary_message(&history.summary)))
+            .chain(
+                messages[already_compressed.unwrap_or(0)..summarized_count]
+                    .iter()
+                    .map(|message| message.to_request_message(buffer)),
+            )
+            .collect(),
+            stop: vec![],
+            temperature: 1.0,
+            feature: Some(LanguageModelRequestFeature::Summarization),
+            preset: Some("precise".to_string()),
+            max_output_tokens: None,
+            raw_events: false,
+        };
+        let through = messages[summarized_count - 1].id;
+
+        let completion = LanguageModelCompletionProvider::read_global(cx)
+            .stream_completion_with_model(model, request, cx);
+        self.pending_compression = Some(cx.spawn(|this, mut cx| async move {
+            let summary = async {
+                let mut chunks = completion.await?;
+                let mut summary = String::new();
+                while let Some(chunk) = chunks.next().await {
+                    summary.push_str(&chunk?);
+                }
+                anyhow::Ok(summary)
+            }
+            .await
+            .log_err();
+
+            this.update(&mut cx, |this, cx| {
+                this.pending_compression = None;
+                if let Some(summary) = summary.filter(|summary| !summary.trim().is_empty()) {
+                    this.compressed_history = Some(CompressedHistory { through, summary });
+                    this.count_remaining_tokens(cx);
+                    cx.emit(ContextEvent::HistoryCompressed);
+                    cx.notify();
+                }
+            })
+            .ok();
+        }));
+    }
+
     pub fn reparse_slash_commands(&mut self, cx: &mut ModelContext<Self>) {
         let buffer = self.buffer.read(cx);
         let mut row_ranges = self
@@ -1688,15 +1811,40 @@ impl Context {
     }
 
     pub fn to_completion_request(&self, cx: &AppContext) -> LanguageModelRequest {
+        let buffer = self.buffer.read(cx);
         let messages = self
             .messages(cx)
             .filter(|message| matches!(message.status, MessageStatus::Done))
-            .map(|message| message.to_request_message(self.buffer.read(cx)));
+            .collect::<Vec<_>>();
+        let compressed_count = self.compressed_message_count(&messages).unwrap_or(0);
+        let summary = self
+            .compressed_history
+            .as_ref()
+            .filter(|_| compressed_count > 0)
+            .map(|history| summary_message(&history.summary));
+        let messages = summary.into_iter().chain(
+            messages[compressed_count..]
+                .iter()
+                .map(|message| message.to_request_message(buffer)),
+        );
+
+        let recommended_temperature = LanguageModelCompletionProvider::read_global(cx)
+            .active_model()
+            .and_then(|model| {
+                LanguageModelRegistry::read_global(cx).recommended_temperature(model.as_ref())
+            });
 
         LanguageModelRequest {
             messages: messages.collect(),
             stop: vec![],
-            temperature: 1.0,
+            temperature: recommended_temperature.unwrap_or(1.0),
+            feature: Some(LanguageModelRequestFeature::AssistantPanel),
+            // The temperature recommended for the model takes precedence.
+            preset: recommended_temperature
+                .is_none()
+                .then(|| "creative".to_string()),
+            max_output_tokens: None,
+            raw_events: false,
         }
     }
 
@@ -1951,6 +2099,10 @@ impl Context {
                 messages: messages.collect(),
                 stop: vec![],
                 temperature: 1.0,
+                feature: Some(LanguageModelRequestFeature::Summarization),
+                preset: Some("creative".to_string()),
+                max_output_tokens: None,
+                raw_events: false,
             };
 
             let stream =
@@ -2081,9 +2233,12 @@ impl Context {
         })
     }
 
+    /// Saves the context to the given directory, which is `None` when saving
+    /// conversations is disabled.
     pub fn save(
         &mut self,
         debounce: Option<Duration>,
+        contexts_dir: Option<PathBuf>,
         fs: Arc<dyn Fs>,
         cx: &mut ModelContext<Context>,
     ) {
@@ -2091,6 +2246,9 @@ impl Context {
             // Prevent saving a remote context for now.
             return;
         }
+        let Some(contexts_dir) = contexts_dir else {
+            return;
+        };
 
         self.pending_save = cx.spawn(|this, mut cx| async move {
             if let Some(debounce) = debounce {
@@ -2116,7 +2274,7 @@ impl Context {
                 let mut discriminant = 1;
                 let mut new_path;
                 loop {
-                    new_path = contexts_dir().join(&format!(
+                    new_path = contexts_dir.join(&format!(
                         "{} - {}.zed.json",
                         summary.trim(),
                         discriminant
@@ -2128,7 +2286,7 @@ impl Context {
                     }
                 }
 
-                fs.create_dir(contexts_dir().as_ref()).await?;
+                fs.create_dir(&contexts_dir).await?;
                 fs.atomic_write(new_path.clone(), serde_json::to_string(&context).unwrap())
                     .await?;
                 if let Some(old_path) = old_path {
@@ -2161,6 +2319,50 @@ impl Context {
     }
 }
 
+const COMPRESSION_PROMPT: &str = "Summarize the conversation that follows, \
+including any earlier summary of it, so that it can be continued from the summary alone. \
+Preserve decisions, requirements, code, file paths and open questions. \
+Reply with the summary only.";
+
+fn summary_message(summary: &str) -> LanguageModelRequestMessage {
+    LanguageModelRequestMessage {
+        role: Role::System,
+        content: format!("Summary of the earlier conversation:\n\n{summary}"),
+    }
+}
+
+/// Returns the model configured to write compression summaries, falling back
+/// to the active model.
+fn compression_model(
+    settings: &CompressionSettings,
+    cx: &AppContext,
+) -> Option<Arc<dyn LanguageModel>> {
+    let completion_provider = LanguageModelCompletionProvider::read_global(cx);
+    let Some(configured_model) = settings.model.as_ref() else {
+        return completion_provider.active_model();
+    };
+
+    let provider_id = LanguageModelProviderId::from(configured_model.provider.clone());
+    let model_id = LanguageModelId::from(configured_model.model.clone());
+    let model = LanguageModelRegistry::read_global(cx)
+        .provider(&provider_id)
+        .filter(|provider| provider.is_authenticated(cx))
+        .and_then(|provider| {
+            provider
+                .provided_models(cx)
+                .into_iter()
+                .find(|model| model.id() == model_id)
+        });
+    if model.is_none() {
+        log::warn!(
+            "compression model {}/{} is unavailable, using the active model",
+            configured_model.provider,
+            configured_model.model
+        );
+    }
+    model.or_else(|| completion_provider.active_model())
+}
+
 #[derive(Debug, Default)]
 pub struct ContextVersion {
     context: clock::Global,
@@ -2216,6 +2418,10 @@ pub struct SavedContext {
     pub summary: String,
     pub slash_command_output_sections:
         Vec<assistant_slash_command::SlashCommandOutputSection<usize>>,
+    /// The summary sent in place of the older messages, saved so that they
+    /// aren't summarized again when the context is reopened.
+    #[serde(default)]
+    pub compressed_history: Option<CompressedHistory>,
 }
 
 impl SavedContext {
@@ -2391,6 +2597,7 @@ impl SavedContextV0_3_0 {
                 .collect(),
             summary: self.summary,
             slash_command_output_sections: self.slash_command_output_sections,
+            compressed_history: None,
         }
     }
 }
@@ -2798,6 +3005,90 @@ mod tests {
         }
     }
 
+    #[gpui::test]
+    fn test_compressed_history(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        language_model::LanguageModelRegistry::test(cx);
+        completion::LanguageModelCompletionProvider::test(cx);
+        cx.set_global(settings_store);
+        assistant_panel::init(cx);
+        let registry = Arc::new(LanguageRegistry::test(cx.background_executor().clone()));
+        let context = cx.new_model(|cx| Context::local(registry.clone(), None, cx));
+        let buffer = context.read(cx).buffer.clone();
+
+        let message_1 = context.read(cx).message_anchors[0].clone();
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "one")], None, cx));
+        let message_2 = context.update(cx, |context, cx| {
+            context
+                .insert_message_after(message_1.id, Role::Assistant, MessageStatus::Done, cx)
+                .unwrap()
+        });
+        buffer.update(cx, |buffer, cx| buffer.edit([(4..4, "two")], None, cx));
+        let message_3 = context.update(cx, |context, cx| {
+            context
+                .insert_message_after(message_2.id, Role::User, MessageStatus::Done, cx)
+                .unwrap()
+        });
+        buffer.update(cx, |buffer, cx| buffer.edit([(8..8, "three")], None, cx));
+
+        let request_contents = |cx: &AppContext| {
+            context
+                .read(cx)
+                .to_completion_request(cx)
+                .messages
+                .into_iter()
+                .map(|message| (message.role, message.content))
+                .collect::<Vec<_>>()
+        };
+
+        context.update(cx, |context, _| {
+            context.compressed_history = Some(CompressedHistory {
+                through: message_2.id,
+                summary: "one and two".into(),
+            });
+        });
+        assert_eq!(
+            request_contents(cx),
+            vec![
+                (
+                    Role::System,
+                    "Summary of the earlier conversation:\n\none and two".into()
+                ),
+                (Role::User, "three".into()),
+            ]
+        );
+
+        // The summarized messages are still in the context.
+        assert_eq!(
+            messages(&context, cx)
+                .into_iter()
+                .map(|(id, _, _)| id)
+                .collect::<Vec<_>>(),
+            vec![message_1.id, message_2.id, message_3.id]
+        );
+
+        // The summary is saved with the context, so it isn't written again
+        // when the context is reopened.
+        let serialized_context = context.read(cx).serialize(cx);
+        let deserialized_context = cx.new_model(|cx| {
+            Context::deserialize(
+                serialized_context,
+                Default::default(),
+                registry.clone(),
+                None,
+                cx,
+            )
+        });
+        assert_eq!(
+            deserialized_context.read(cx).compressed_history(),
+            context.read(cx).compressed_history()
+        );
+
+        // The summary is dropped when the message it ends at is deleted.
+        buffer.update(cx, |buffer, cx| buffer.edit([(3..8, "")], None, cx));
+        assert_eq!(request_contents(cx), vec![(Role::User, "onethree".into())]);
+    }
+
     #[gpui::test]
     async fn test_slash_commands(cx: &mut TestAppContext) {
         let settings_store = cx.update(SettingsStore::test);
@@ -1,11 +1,12 @@
 use crate::{
+    assistant_settings::{AssistantSettings, ConversationDataLocation},
     Context, ContextEvent, ContextId, ContextOperation, ContextVersion, SavedContext,
     SavedContextMetadata,
 };
 use anyhow::{anyhow, Context as _, Result};
 use client::{proto, telemetry::Telemetry, Client, TypedEnvelope};
 use clock::ReplicaId;
-use fs::Fs;
+use fs::{Fs, RemoveOptions};
 use futures::StreamExt;
 use fuzzy::StringMatchCandidate;
 use gpui::{
@@ -15,13 +16,14 @@ use language::LanguageRegistry;
 use paths::contexts_dir;
 use project::Project;
 use regex::Regex;
+use settings::Settings;
 use std::{
     cmp::Reverse,
     ffi::OsStr,
     mem,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 use util::{ResultExt, TryFutureExt};
 
@@ -86,9 +88,13 @@ impl ContextStore {
         let fs = project.read(cx).fs().clone();
         let languages = project.read(cx).languages().clone();
         let telemetry = project.read(cx).client().telemetry().clone();
+        let contexts_dir = contexts_dir_for_project(project.read(cx), cx);
         cx.spawn(|mut cx| async move {
             const CONTEXT_WATCH_DURATION: Duration = Duration::from_millis(100);
-            let (mut events, _) = fs.watch(contexts_dir(), CONTEXT_WATCH_DURATION).await;
+            let mut events = match contexts_dir {
+                Some(contexts_dir) => fs.watch(&contexts_dir, CONTEXT_WATCH_DURATION).await.0,
+                None => futures::stream::pending().boxed(),
+            };
 
             let this = cx.new_model(|cx: &mut ModelContext<Self>| {
                 let mut this = Self {
@@ -678,10 +684,21 @@ impl ContextStore {
 
     fn reload(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
         let fs = self.fs.clone();
+        let contexts_dir = contexts_dir_for_project(self.project.read(cx), cx);
+        let retention = AssistantSettings::get_global(cx).data.retention;
         cx.spawn(|this, mut cx| async move {
-            fs.create_dir(contexts_dir()).await?;
+            let Some(contexts_dir) = contexts_dir else {
+                return this.update(&mut cx, |this, cx| {
+                    this.contexts_metadata.clear();
+                    cx.notify();
+                });
+            };
+            fs.create_dir(&contexts_dir).await?;
+            if let Some(retention) = retention {
+                delete_expired_contexts(fs.as_ref(), &contexts_dir, retention).await?;
+            }
 
-            let mut paths = fs.read_dir(contexts_dir()).await?;
+            let mut paths = fs.read_dir(&contexts_dir).await?;
             let mut contexts = Vec::<SavedContextMetadata>::new();
             while let Some(path) = paths.next().await {
                 let path = path?;
@@ -721,3 +738,93 @@ impl ContextStore {
         })
     }
 }
+
+/// Returns the directory the project's conversations are saved in, or `None`
+/// if they aren't saved.
+pub fn contexts_dir_for_project(project: &Project, cx: &AppContext) -> Option<PathBuf> {
+    match AssistantSettings::get_global(cx).data.location {
+        ConversationDataLocation::Global => Some(contexts_dir().clone()),
+        ConversationDataLocation::Workspace => {
+            // Conversations are always saved on this machine, so they can't
+            // be saved with a remote project.
+            if project.is_remote() {
+                return None;
+            }
+            let worktree = project.visible_worktrees(cx).next()?;
+            let worktree_path = worktree.read(cx).abs_path();
+            Some(worktree_path.join(paths::local_conversations_folder_relative_path()))
+        }
+        ConversationDataLocation::Disabled => None,
+    }
+}
+
+/// Deletes the conversations in the directory that were last saved longer
+/// than `retention` ago.
+async fn delete_expired_contexts(
+    fs: &dyn Fs,
+    contexts_dir: &Path,
+    retention: Duration,
+) -> Result<()> {
+    let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+        return Ok(());
+    };
+
+    let mut paths = fs.read_dir(contexts_dir).await?;
+    while let Some(path) = paths.next().await {
+        let path = path?;
+        let is_context = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.ends_with(".zed.json"));
+        if !is_context {
+            continue;
+        }
+
+        if let Some(metadata) = fs.metadata(&path).await? {
+            if metadata.mtime < cutoff {
+                fs.remove_file(
+                    &path,
+                    RemoveOptions {
+                        recursive: false,
+                        ignore_if_not_exists: true,
+                    },
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::FakeFs;
+    use gpui::TestAppContext;
+
+    #[gpui::test]
+    async fn test_delete_expired_contexts(cx: &mut TestAppContext) {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+        let fs = FakeFs::new(cx.executor());
+        let now = SystemTime::now();
+        fs.set_next_mtime(now - DAY * 10);
+        fs.insert_file("/contexts/Old - 1.zed.json", Vec::new())
+            .await;
+        fs.insert_file("/contexts/notes.txt", Vec::new()).await;
+        fs.set_next_mtime(now - DAY);
+        fs.insert_file("/contexts/Recent - 1.zed.json", Vec::new())
+            .await;
+
+        delete_expired_contexts(fs.as_ref(), Path::new("/contexts"), DAY * 7)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs.files(),
+            [
+                PathBuf::from("/contexts/Recent - 1.zed.json"),
+                PathBuf::from("/contexts/notes.txt"),
+            ]
+        );
+    }
+}
@@ -27,7 +27,9 @@ use gpui::{
     WindowContext,
 };
 use language::{Buffer, IndentKind, Point, Selection, TransactionId};
-use language_model::{LanguageModelRequest, LanguageModelRequestMessage, Role};
+use language_model::{
+    LanguageModelRequest, LanguageModelRequestFeature, LanguageModelRequestMessage, Role,
+};
 use multi_buffer::MultiBufferRow;
 use parking_lot::Mutex;
 use rope::Rope;
@@ -2095,14 +2097,14 @@ impl Codegen {
         // Higher Temperature increases the randomness of model outputs.
         // If Markdown or No Language is Known, increase the randomness for more creative output
         // If Code, decrease temperature to get more deterministic outputs
-        let temperature = if let Some(language) = language_name.clone() {
+        let (preset, temperature) = if let Some(language) = language_name.clone() {
             if language.as_ref() == "Markdown" {
-                1.0
+                ("creative", 1.0)
             } else {
-                0.5
+                ("precise", 0.5)
             }
         } else {
-            1.0
+            ("creative", 1.0)
         };
 
         let language_name = language_name.as_deref();
@@ -2135,6 +2137,10 @@ impl Codegen {
             messages,
             stop: vec!["|END|>".to_string()],
             temperature,
+            feature: Some(LanguageModelRequestFeature::InlineAssist),
+            preset: Some(preset.to_string()),
+            max_output_tokens: None,
+            raw_events: false,
         }
     }
 
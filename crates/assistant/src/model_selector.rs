@@ -3,8 +3,11 @@ use std::sync::Arc;
 use crate::{assistant_settings::AssistantSettings, LanguageModelCompletionProvider};
 use fs::Fs;
 use gpui::SharedString;
-use language_model::LanguageModelRegistry;
-use settings::update_settings_file;
+use language_model::{
+    circuit_breaker::CircuitState, settings::AllLanguageModelSettings,
+    stream_metrics::ModelStreamSummary, LanguageModelRegistry,
+};
+use settings::{update_settings_file, Settings};
 use ui::{prelude::*, ContextMenu, PopoverMenu, PopoverMenuHandle, PopoverTrigger};
 
 #[derive(IntoElement)]
@@ -57,16 +60,57 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
                         .separator();
                 }
 
-                for (index, provider) in LanguageModelRegistry::global(cx)
-                    .read(cx)
+                let settings = AllLanguageModelSettings::get_global(cx);
+                if !settings.locked.is_empty() {
+                    menu = menu
+                        .custom_row(move |_cx| {
+                            h_flex()
+                                .gap_1()
+                                .child(Icon::new(IconName::FileLock).size(IconSize::XSmall))
+                                .child(
+                                    Label::new("Some settings are managed by your organization")
+                                        .size(LabelSize::Small)
+                                        .color(Color::Muted),
+                                )
+                                .into_any_element()
+                        })
+                        .separator();
+                }
+                let registry = LanguageModelRegistry::global(cx).read(cx);
+                let circuit_breakers = registry.circuit_breakers();
+                let stream_metrics = registry.stream_metrics();
+                let allowed_providers = registry
                     .providers()
-                    .enumerate()
-                {
+                    .filter(|provider| settings.is_provider_allowed(&provider.id()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                for (index, provider) in allowed_providers.iter().enumerate() {
                     if index > 0 {
                         menu = menu.separator();
                     }
                     menu = menu.header(provider.name().0);
 
+                    if circuit_breakers.lock().unwrap().state(&provider.id())
+                        != CircuitState::Closed
+                    {
+                        menu = menu.custom_row(move |_cx| {
+                            h_flex()
+                                .gap_1()
+                                .child(
+                                    Icon::new(IconName::ExclamationTriangle)
+                                        .size(IconSize::XSmall)
+                                        .color(Color::Warning),
+                                )
+                                .child(
+                                    Label::new("Temporarily disabled after repeated failures")
+                                        .size(LabelSize::Small)
+                                        .color(Color::Muted),
+                                )
+                                .into_any_element()
+                        });
+                    }
+
                     let available_models = provider.provided_models(cx);
                     if available_models.is_empty() {
                         menu = menu.custom_entry(
@@ -103,6 +147,11 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
                         .map(|m| m.id());
 
                     for available_model in available_models {
+                        let stream_summary = stream_metrics
+                            .lock()
+                            .unwrap()
+                            .summary(&(available_model.provider_id(), available_model.id()))
+                            .and_then(|summary| stream_summary_label(&summary));
                         menu = menu.custom_entry(
                             {
                                 let id = available_model.id();
@@ -113,12 +162,28 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
                                 move |_| {
                                     h_flex()
                                         .w_full()
+                                        .gap_2()
                                         .justify_between()
                                         .child(Label::new(model_name.clone()))
-                                        .when(
-                                            selected_model.as_ref() == Some(&id)
-                                                && selected_provider.as_ref() == Some(&provider_id),
-                                            |this| this.child(Icon::new(IconName::Check)),
+                                        .child(
+                                            h_flex()
+                                                .gap_1()
+                                                .when_some(
+                                                    stream_summary.clone(),
+                                                    |this, stream_summary| {
+                                                        this.child(
+                                                            Label::new(stream_summary)
+                                                                .size(LabelSize::Small)
+                                                                .color(Color::Muted),
+                                                        )
+                                                    },
+                                                )
+                                                .when(
+                                                    selected_model.as_ref() == Some(&id)
+                                                        && selected_provider.as_ref()
+                                                            == Some(&provider_id),
+                                                    |this| this.child(Icon::new(IconName::Check)),
+                                                ),
                                         )
                                         .into_any()
                                 }
@@ -146,3 +211,23 @@ impl<T: PopoverTrigger> RenderOnce for ModelSelector<T> {
         .attach(gpui::AnchorCorner::BottomLeft)
     }
 }
+
+/// Describes how the model has performed over its recent requests, e.g.
+/// "0.4s · 52 tok/s · 10% errors".
+fn stream_summary_label(summary: &ModelStreamSummary) -> Option<SharedString> {
+    let mut parts = Vec::new();
+    if let Some(time_to_first_token) = summary.time_to_first_token {
+        parts.push(format!("{:.1}s", time_to_first_token.as_secs_f32()));
+    }
+    if let Some(tokens_per_second) = summary.tokens_per_second {
+        parts.push(format!("{tokens_per_second:.0} tok/s"));
+    }
+    if summary.error_rate > 0. {
+        parts.push(format!("{:.0}% errors", summary.error_rate * 100.));
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · ").into())
+    }
+}
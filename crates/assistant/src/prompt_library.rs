@@ -744,6 +744,10 @@ impl PromptLibrary {
                                     }],
                                     stop: Vec::new(),
                                     temperature: 1.,
+                                    feature: None,
+                                    preset: None,
+                                    max_output_tokens: None,
+                                    raw_events: false,
                                 },
                                 cx,
                             )
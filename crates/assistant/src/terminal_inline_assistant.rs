@@ -16,7 +16,9 @@ use gpui::{
     Subscription, Task, TextStyle, UpdateGlobal, View, WeakView,
 };
 use language::Buffer;
-use language_model::{LanguageModelRequest, LanguageModelRequestMessage, Role};
+use language_model::{
+    LanguageModelRequest, LanguageModelRequestFeature, LanguageModelRequestMessage, Role,
+};
 use settings::Settings;
 use std::{
     cmp,
@@ -268,6 +270,10 @@ impl TerminalInlineAssistant {
             messages,
             stop: Vec::new(),
             temperature: 1.0,
+            feature: Some(LanguageModelRequestFeature::TerminalInlineAssist),
+            preset: Some("creative".to_string()),
+            max_output_tokens: None,
+            raw_events: false,
         })
     }
 
@@ -4,7 +4,10 @@ use futures::{stream::BoxStream, StreamExt};
 use gpui::{BackgroundExecutor, Context, Model, TestAppContext};
 use parking_lot::Mutex;
 use rpc::{
-    proto::{self, GetPrivateUserInfo, GetPrivateUserInfoResponse},
+    proto::{
+        self, GetBillingStatus, GetBillingStatusResponse, GetPrivateUserInfo,
+        GetPrivateUserInfoResponse,
+    },
     ConnectionId, Peer, Receipt, TypedEnvelope,
 };
 use std::sync::Arc;
@@ -177,6 +180,19 @@ impl FakeServer {
                 continue;
             }
 
+            if message.is::<TypedEnvelope<GetBillingStatus>>() {
+                self.respond(
+                    message
+                        .downcast::<TypedEnvelope<GetBillingStatus>>()
+                        .unwrap()
+                        .receipt(),
+                    GetBillingStatusResponse {
+                        status: Some(Default::default()),
+                    },
+                );
+                continue;
+            }
+
             panic!(
                 "fake server received unexpected message type: {:?}",
                 type_name
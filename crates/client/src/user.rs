@@ -98,6 +98,8 @@ pub struct UserStore {
     outgoing_contact_requests: Vec<Arc<User>>,
     pending_contact_requests: HashMap<u64, usize>,
     invite_info: Option<InviteInfo>,
+    billing_status: Option<proto::BillingStatus>,
+    language_model_usage: Option<proto::LanguageModelUsage>,
     client: Weak<Client>,
     _maintain_contacts: Task<()>,
     _maintain_current_user: Task<Result<()>>,
@@ -142,6 +144,8 @@ impl UserStore {
             client.add_message_handler(cx.weak_model(), Self::handle_update_contacts),
             client.add_message_handler(cx.weak_model(), Self::handle_update_invite_info),
             client.add_message_handler(cx.weak_model(), Self::handle_show_contacts),
+            client.add_message_handler(cx.weak_model(), Self::handle_update_billing_status),
+            client.add_message_handler(cx.weak_model(), Self::handle_update_language_model_usage),
         ];
         Self {
             users: Default::default(),
@@ -152,6 +156,8 @@ impl UserStore {
             participant_indices: Default::default(),
             outgoing_contact_requests: Default::default(),
             invite_info: None,
+            billing_status: None,
+            language_model_usage: None,
             client: Arc::downgrade(&client),
             update_contacts_tx,
             _maintain_contacts: cx.spawn(|this, mut cx| async move {
@@ -188,7 +194,13 @@ impl UserStore {
                                 };
                                 let fetch_metrics_id =
                                     client.request(proto::GetPrivateUserInfo {}).log_err();
-                                let (user, info) = futures::join!(fetch_user, fetch_metrics_id);
+                                let fetch_billing_status =
+                                    client.request(proto::GetBillingStatus {}).log_err();
+                                let (user, info, billing_status) = futures::join!(
+                                    fetch_user,
+                                    fetch_metrics_id,
+                                    fetch_billing_status
+                                );
 
                                 cx.update(|cx| {
                                     if let Some(info) = info {
@@ -205,12 +217,18 @@ impl UserStore {
 
                                 current_user_tx.send(user).await.ok();
 
-                                this.update(&mut cx, |_, cx| cx.notify())?;
+                                this.update(&mut cx, |this, cx| {
+                                    this.billing_status =
+                                        billing_status.and_then(|response| response.status);
+                                    cx.notify()
+                                })?;
                             }
                         }
                         Status::SignedOut => {
                             current_user_tx.send(None).await.ok();
                             this.update(&mut cx, |this, cx| {
+                                this.billing_status = None;
+                                this.language_model_usage = None;
                                 cx.notify();
                                 this.clear_contacts()
                             })?
@@ -254,6 +272,30 @@ impl UserStore {
         Ok(())
     }
 
+    async fn handle_update_billing_status(
+        this: Model<Self>,
+        message: TypedEnvelope<proto::UpdateBillingStatus>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.billing_status = message.payload.status;
+            cx.notify();
+        })?;
+        Ok(())
+    }
+
+    async fn handle_update_language_model_usage(
+        this: Model<Self>,
+        message: TypedEnvelope<proto::UpdateLanguageModelUsage>,
+        mut cx: AsyncAppContext,
+    ) -> Result<()> {
+        this.update(&mut cx, |this, cx| {
+            this.language_model_usage = message.payload.usage;
+            cx.notify();
+        })?;
+        Ok(())
+    }
+
     async fn handle_show_contacts(
         this: Model<Self>,
         _: TypedEnvelope<proto::ShowContacts>,
@@ -267,6 +309,17 @@ impl UserStore {
         self.invite_info.as_ref()
     }
 
+    /// The billing status of the current user, as last reported by the server.
+    pub fn billing_status(&self) -> Option<&proto::BillingStatus> {
+        self.billing_status.as_ref()
+    }
+
+    /// The current user's language model usage for the current period, as
+    /// last pushed by the server after their requests.
+    pub fn language_model_usage(&self) -> Option<&proto::LanguageModelUsage> {
+        self.language_model_usage.as_ref()
+    }
+
     async fn handle_update_contacts(
         this: Model<Self>,
         message: TypedEnvelope<proto::UpdateContacts>,
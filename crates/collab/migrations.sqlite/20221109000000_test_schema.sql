@@ -9,7 +9,8 @@ CREATE TABLE "users" (
     "connected_once" BOOLEAN NOT NULL DEFAULT false,
     "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     "metrics_id" TEXT,
-    "github_user_id" INTEGER
+    "github_user_id" INTEGER,
+    "locale" TEXT
 );
 CREATE UNIQUE INDEX "index_users_github_login" ON "users" ("github_login");
 CREATE UNIQUE INDEX "index_invite_code_users" ON "users" ("invite_code");
@@ -200,7 +201,8 @@ CREATE TABLE "channels" (
     "created_at" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     "visibility" VARCHAR NOT NULL,
     "parent_path" TEXT NOT NULL,
-    "requires_zed_cla" BOOLEAN NOT NULL DEFAULT FALSE
+    "requires_zed_cla" BOOLEAN NOT NULL DEFAULT FALSE,
+    "archived_at" TIMESTAMP
 );
 
 CREATE INDEX "index_channels_on_parent_path" ON "channels" ("parent_path");
@@ -422,18 +424,512 @@ CREATE TABLE IF NOT EXISTS billing_subscriptions (
     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     billing_customer_id INTEGER NOT NULL REFERENCES billing_customers(id),
     stripe_subscription_id TEXT NOT NULL,
-    stripe_subscription_status TEXT NOT NULL
+    stripe_subscription_status TEXT NOT NULL,
+    plan TEXT NOT NULL DEFAULT 'pro',
+    seat_count INTEGER NOT NULL DEFAULT 1,
+    stripe_coupon_id TEXT,
+    stripe_promotion_code_id TEXT,
+    trial_end TIMESTAMP,
+    current_period_start TIMESTAMP,
+    current_period_end TIMESTAMP,
+    current_period_ends_at TIMESTAMP,
+    cancel_at_period_end BOOLEAN NOT NULL DEFAULT FALSE,
+    canceled_at TIMESTAMP,
+    grace_period_ends_at TIMESTAMP,
+    dunning_attempt_count INTEGER NOT NULL DEFAULT 0,
+    next_dunning_attempt_at TIMESTAMP,
+    suspended_at TIMESTAMP,
+    stripe_account_id TEXT
 );
 
 CREATE INDEX "ix_billing_subscriptions_on_billing_customer_id" ON billing_subscriptions (billing_customer_id);
 CREATE UNIQUE INDEX "uix_billing_subscriptions_on_stripe_subscription_id" ON billing_subscriptions (stripe_subscription_id);
 
+CREATE TABLE IF NOT EXISTS billing_subscription_transitions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id),
+    stripe_subscription_status TEXT NOT NULL,
+    transitioned_at TIMESTAMP NOT NULL,
+    plan TEXT
+);
+
+CREATE INDEX "ix_billing_subscription_transitions_on_billing_subscription_id_transitioned_at" ON billing_subscription_transitions (billing_subscription_id, transitioned_at);
+
 CREATE TABLE IF NOT EXISTS billing_customers (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     user_id INTEGER NOT NULL REFERENCES users(id),
-    stripe_customer_id TEXT NOT NULL
+    stripe_customer_id TEXT NOT NULL,
+    encrypted_billing_address TEXT,
+    encrypted_tax_id TEXT,
+    tax_exempt TEXT NOT NULL DEFAULT 'none',
+    billing_email TEXT,
+    stripe_account_id TEXT,
+    payment_method_fingerprint TEXT,
+    spending_cap_in_cents INTEGER
 );
 
 CREATE UNIQUE INDEX "uix_billing_customers_on_user_id" ON billing_customers (user_id);
+CREATE INDEX "ix_billing_customers_on_payment_method_fingerprint" ON billing_customers (payment_method_fingerprint);
 CREATE UNIQUE INDEX "uix_billing_customers_on_stripe_customer_id" ON billing_customers (stripe_customer_id);
+
+CREATE TABLE IF NOT EXISTS referral_codes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_referral_codes_on_user_id" ON referral_codes (user_id);
+CREATE UNIQUE INDEX "uix_referral_codes_on_code" ON referral_codes (code);
+
+CREATE TABLE IF NOT EXISTS referrals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    referrer_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    referred_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    referrer_credited_at TIMESTAMP,
+    referrer_credit_pending_since TIMESTAMP,
+    referrer_credit_skipped_at TIMESTAMP
+);
+
+CREATE INDEX "ix_referrals_on_referrer_user_id_created_at" ON referrals (referrer_user_id, created_at);
+CREATE UNIQUE INDEX "uix_referrals_on_referred_user_id" ON referrals (referred_user_id);
+
+CREATE TABLE IF NOT EXISTS language_model_usages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    feature TEXT,
+    upstream_cost_in_millicents INTEGER NOT NULL DEFAULT 0,
+    organization_id INTEGER REFERENCES organizations(id) ON DELETE SET NULL
+);
+
+CREATE INDEX "ix_language_model_usages_on_user_id_created_at" ON language_model_usages (user_id, created_at);
+CREATE INDEX "ix_language_model_usages_on_created_at" ON language_model_usages (created_at);
+CREATE INDEX "ix_language_model_usages_on_organization_id_created_at" ON language_model_usages (organization_id, created_at);
+
+CREATE TABLE IF NOT EXISTS monthly_language_model_usages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    month DATE NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    feature TEXT,
+    request_count INTEGER NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    upstream_cost_in_millicents INTEGER NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_monthly_language_model_usages_on_user_id_month_provider_model_feature" ON monthly_language_model_usages (user_id, month, provider, model, COALESCE(feature, ''));
+
+CREATE TABLE IF NOT EXISTS usage_anomalies (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    window_tokens INTEGER NOT NULL,
+    baseline_tokens INTEGER NOT NULL,
+    reviewed_at TIMESTAMP
+);
+
+CREATE INDEX "ix_usage_anomalies_on_user_id" ON usage_anomalies (user_id);
+
+CREATE TABLE IF NOT EXISTS email_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempt_count INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL,
+    last_error TEXT,
+    provider_message_id TEXT,
+    sent_at TIMESTAMP
+);
+
+CREATE INDEX "ix_email_deliveries_on_status_next_attempt_at" ON email_deliveries (status, next_attempt_at);
+CREATE INDEX "ix_email_deliveries_on_provider_message_id" ON email_deliveries (provider_message_id);
+
+CREATE TABLE IF NOT EXISTS email_suppressions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    email_address TEXT NOT NULL,
+    reason TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_email_suppressions_on_email_address" ON email_suppressions (email_address);
+
+CREATE TABLE IF NOT EXISTS consents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    version TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_consents_on_user_id_kind_version" ON consents (user_id, kind, version);
+
+CREATE TABLE IF NOT EXISTS organizations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    features_disabled_at TIMESTAMP,
+    usage_rolled_up_through DATE,
+    billing_subscription_id INTEGER REFERENCES billing_subscriptions(id) ON DELETE SET NULL,
+    seat_policy TEXT NOT NULL DEFAULT 'flag'
+);
+
+CREATE UNIQUE INDEX "uix_organizations_on_slug" ON organizations (slug);
+CREATE UNIQUE INDEX "uix_organizations_on_billing_subscription_id" ON organizations (billing_subscription_id);
+
+CREATE TABLE IF NOT EXISTS organization_members (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_organization_members_on_organization_id_user_id" ON organization_members (organization_id, user_id);
+CREATE INDEX "ix_organization_members_on_user_id" ON organization_members (user_id);
+
+CREATE TABLE IF NOT EXISTS organization_secrets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_organization_secrets_on_organization_id_name" ON organization_secrets (organization_id, name);
+
+CREATE TABLE IF NOT EXISTS organization_secret_leases (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    organization_secret_id INTEGER NOT NULL REFERENCES organization_secrets(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "ix_organization_secret_leases_on_organization_secret_id" ON organization_secret_leases (organization_secret_id);
+
+CREATE TABLE IF NOT EXISTS model_experiments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    name TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE UNIQUE INDEX "uix_model_experiments_on_name" ON model_experiments (name);
+
+CREATE TABLE IF NOT EXISTS model_experiment_variants (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    experiment_id INTEGER NOT NULL REFERENCES model_experiments(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    weight INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    temperature REAL
+);
+
+CREATE UNIQUE INDEX "uix_model_experiment_variants_on_experiment_id_name" ON model_experiment_variants (experiment_id, name);
+
+CREATE TABLE IF NOT EXISTS model_experiment_exposures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    experiment_id INTEGER NOT NULL REFERENCES model_experiments(id) ON DELETE CASCADE,
+    variant_id INTEGER NOT NULL REFERENCES model_experiment_variants(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX "uix_model_experiment_exposures_on_experiment_id_user_id" ON model_experiment_exposures (experiment_id, user_id);
+
+CREATE TABLE IF NOT EXISTS user_secrets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    ciphertext BLOB NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_user_secrets_on_user_id_name" ON user_secrets (user_id, name);
+
+CREATE TABLE IF NOT EXISTS server_settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS server_setting_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    key TEXT NOT NULL,
+    previous_value TEXT,
+    value TEXT,
+    changed_by_user_id INTEGER REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX "ix_server_setting_changes_on_key" ON server_setting_changes (key);
+
+CREATE TABLE IF NOT EXISTS impersonation_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    staff_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    impersonated_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    allow_writes BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP,
+    token_hash TEXT
+);
+
+CREATE INDEX "ix_impersonation_sessions_on_impersonated_user_id" ON impersonation_sessions (impersonated_user_id);
+CREATE UNIQUE INDEX "uix_impersonation_sessions_on_token_hash" ON impersonation_sessions (token_hash);
+
+CREATE TABLE IF NOT EXISTS impersonated_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    session_id INTEGER NOT NULL REFERENCES impersonation_sessions(id) ON DELETE CASCADE,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    allowed BOOLEAN NOT NULL
+);
+
+CREATE INDEX "ix_impersonated_requests_on_session_id" ON impersonated_requests (session_id);
+
+CREATE TABLE IF NOT EXISTS language_model_top_ups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    stripe_checkout_session_id TEXT NOT NULL,
+    period_start DATE NOT NULL,
+    tokens INTEGER NOT NULL,
+    fulfilled_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX "uix_language_model_top_ups_on_stripe_checkout_session_id" ON language_model_top_ups (stripe_checkout_session_id);
+CREATE INDEX "ix_language_model_top_ups_on_user_id_period_start" ON language_model_top_ups (user_id, period_start);
+
+CREATE TABLE IF NOT EXISTS usage_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    period_start TIMESTAMP NOT NULL,
+    period_end TIMESTAMP NOT NULL,
+    quantity INTEGER NOT NULL,
+    stripe_usage_record_id TEXT,
+    reported_at TIMESTAMP,
+    attempt_count INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP,
+    last_error TEXT,
+    stripe_timestamp TIMESTAMP
+);
+
+CREATE UNIQUE INDEX "uix_usage_records_on_billing_subscription_id_period_start" ON usage_records (billing_subscription_id, period_start);
+CREATE INDEX "ix_usage_records_on_reported_at" ON usage_records (reported_at);
+
+CREATE TABLE IF NOT EXISTS plan_enforcement_actions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    scheduled_for TIMESTAMP NOT NULL,
+    notified_at TIMESTAMP,
+    enforced_at TIMESTAMP,
+    canceled_at TIMESTAMP,
+    reverted_at TIMESTAMP
+);
+
+CREATE INDEX "ix_plan_enforcement_actions_on_user_id" ON plan_enforcement_actions (user_id);
+CREATE INDEX "ix_plan_enforcement_actions_on_scheduled_for" ON plan_enforcement_actions (scheduled_for);
+
+CREATE TABLE IF NOT EXISTS billing_invoice_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    billing_customer_id INTEGER NOT NULL REFERENCES billing_customers(id) ON DELETE CASCADE,
+    stripe_invoice_id TEXT NOT NULL,
+    stripe_subscription_id TEXT,
+    kind TEXT NOT NULL,
+    billing_reason TEXT,
+    amount_in_cents INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    occurred_at TIMESTAMP NOT NULL,
+    usd_exchange_rate REAL,
+    amount_in_usd_cents INTEGER
+);
+
+CREATE UNIQUE INDEX "uix_billing_invoice_events_on_stripe_invoice_id_kind" ON billing_invoice_events (stripe_invoice_id, kind);
+CREATE INDEX "ix_billing_invoice_events_on_billing_customer_id" ON billing_invoice_events (billing_customer_id);
+CREATE INDEX "ix_billing_invoice_events_on_occurred_at" ON billing_invoice_events (occurred_at);
+
+CREATE TABLE IF NOT EXISTS organization_member_daily_usages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    date DATE NOT NULL,
+    request_count INTEGER NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    estimated_cost_in_millicents INTEGER NOT NULL,
+    top_models TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX "uix_organization_member_daily_usages_on_organization_id_date_user_id" ON organization_member_daily_usages (organization_id, date, user_id);
+
+CREATE TABLE IF NOT EXISTS billing_customer_transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    billing_customer_id INTEGER NOT NULL REFERENCES billing_customers(id) ON DELETE CASCADE,
+    from_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    to_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    organization_id INTEGER REFERENCES organizations(id) ON DELETE SET NULL,
+    transferred_by_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT
+);
+
+CREATE INDEX "ix_billing_customer_transfers_on_billing_customer_id" ON billing_customer_transfers (billing_customer_id);
+
+CREATE TABLE IF NOT EXISTS billing_notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    email_delivery_id INTEGER NOT NULL REFERENCES email_deliveries(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX "uix_billing_notifications_on_user_id_key" ON billing_notifications (user_id, key);
+
+CREATE TABLE IF NOT EXISTS organization_seat_true_ups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    member_count INTEGER NOT NULL,
+    seat_count INTEGER NOT NULL,
+    outcome TEXT NOT NULL
+);
+
+CREATE INDEX "ix_organization_seat_true_ups_on_organization_id" ON organization_seat_true_ups (organization_id);
+
+CREATE TABLE IF NOT EXISTS stripe_event_cursors (
+    name TEXT PRIMARY KEY,
+    last_event_id TEXT NOT NULL,
+    last_event_created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS billing_invoice_line_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    stripe_invoice_id TEXT NOT NULL,
+    stripe_invoice_line_item_id TEXT NOT NULL,
+    period_start TIMESTAMP NOT NULL,
+    period_end TIMESTAMP NOT NULL,
+    quantity INTEGER NOT NULL,
+    description TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_billing_invoice_line_items_on_stripe_invoice_line_item_id" ON billing_invoice_line_items (stripe_invoice_line_item_id);
+CREATE INDEX "ix_billing_invoice_line_items_on_stripe_invoice_id" ON billing_invoice_line_items (stripe_invoice_id);
+
+CREATE TABLE IF NOT EXISTS complimentary_subscriptions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    plan TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    reason TEXT,
+    granted_by_user_id INTEGER NOT NULL REFERENCES users(id),
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    revoked_by_user_id INTEGER REFERENCES users(id)
+);
+
+CREATE INDEX "ix_complimentary_subscriptions_on_user_id" ON complimentary_subscriptions (user_id);
+
+CREATE TABLE IF NOT EXISTS sandbox_organizations (
+    organization_id INTEGER PRIMARY KEY REFERENCES organizations(id) ON DELETE CASCADE,
+    created_by_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "ix_sandbox_organizations_on_expires_at" ON sandbox_organizations (expires_at);
+
+CREATE TABLE IF NOT EXISTS sandbox_organization_users (
+    organization_id INTEGER NOT NULL REFERENCES sandbox_organizations(organization_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS trial_abuse_flags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    details TEXT NOT NULL,
+    decision TEXT,
+    decision_note TEXT,
+    decided_by_user_id INTEGER REFERENCES users(id),
+    decided_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "ix_trial_abuse_flags_on_user_id" ON trial_abuse_flags (user_id);
+CREATE INDEX "ix_trial_abuse_flags_on_decided_at" ON trial_abuse_flags (decided_at);
+
+CREATE TABLE IF NOT EXISTS external_entitlements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    source TEXT NOT NULL,
+    external_id TEXT NOT NULL,
+    plan TEXT NOT NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ended_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX "uix_external_entitlements_on_source_external_id" ON external_entitlements (source, external_id);
+CREATE INDEX "ix_external_entitlements_on_user_id" ON external_entitlements (user_id);
+
+CREATE TABLE IF NOT EXISTS organization_invitations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    invited_by_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX "uix_organization_invitations_on_organization_id_user_id" ON organization_invitations (organization_id, user_id);
+CREATE INDEX "ix_organization_invitations_on_user_id" ON organization_invitations (user_id);
+
+CREATE TABLE IF NOT EXISTS billing_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    event TEXT NOT NULL,
+    billing_subscription_id INTEGER REFERENCES billing_subscriptions(id) ON DELETE SET NULL,
+    previous_state TEXT,
+    state TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX "ix_billing_audit_log_on_user_id" ON billing_audit_log (user_id);
+
+CREATE TABLE IF NOT EXISTS distributed_lock_leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
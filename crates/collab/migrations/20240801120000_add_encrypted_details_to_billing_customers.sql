@@ -0,0 +1,4 @@
+-- These columns hold ciphertext produced by the application-level column cipher,
+-- never plaintext.
+ALTER TABLE billing_customers ADD COLUMN encrypted_billing_address TEXT;
+ALTER TABLE billing_customers ADD COLUMN encrypted_tax_id TEXT;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS billing_subscription_transitions (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    stripe_subscription_status TEXT NOT NULL,
+    transitioned_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE INDEX "ix_billing_subscription_transitions_on_billing_subscription_id_transitioned_at" ON billing_subscription_transitions (billing_subscription_id, transitioned_at);
+
+-- Backfill an initial transition for every existing subscription, so that their
+-- current status can be reconstructed from the transition history.
+INSERT INTO billing_subscription_transitions (billing_subscription_id, stripe_subscription_status, transitioned_at)
+SELECT id, stripe_subscription_status, created_at FROM billing_subscriptions;
@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS referral_codes (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_referral_codes_on_user_id" ON referral_codes (user_id);
+CREATE UNIQUE INDEX "uix_referral_codes_on_code" ON referral_codes (code);
+
+CREATE TABLE IF NOT EXISTS referrals (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    referrer_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    referred_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    referrer_credited_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX "ix_referrals_on_referrer_user_id_created_at" ON referrals (referrer_user_id, created_at);
+CREATE UNIQUE INDEX "uix_referrals_on_referred_user_id" ON referrals (referred_user_id);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS language_model_usages (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_tokens BIGINT NOT NULL,
+    output_tokens BIGINT NOT NULL
+);
+
+CREATE INDEX "ix_language_model_usages_on_user_id_created_at" ON language_model_usages (user_id, created_at);
+CREATE INDEX "ix_language_model_usages_on_created_at" ON language_model_usages (created_at);
+
+CREATE TABLE IF NOT EXISTS usage_anomalies (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    window_tokens BIGINT NOT NULL,
+    baseline_tokens BIGINT NOT NULL,
+    reviewed_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX "ix_usage_anomalies_on_user_id" ON usage_anomalies (user_id);
@@ -0,0 +1,2 @@
+ALTER TABLE language_model_usages ADD COLUMN feature TEXT;
+ALTER TABLE language_model_usages ADD COLUMN upstream_cost_in_millicents BIGINT NOT NULL DEFAULT 0;
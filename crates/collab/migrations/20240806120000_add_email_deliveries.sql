@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS email_deliveries (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempt_count INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    last_error TEXT,
+    provider_message_id TEXT,
+    sent_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX "ix_email_deliveries_on_status_next_attempt_at" ON email_deliveries (status, next_attempt_at);
+CREATE INDEX "ix_email_deliveries_on_provider_message_id" ON email_deliveries (provider_message_id);
+
+CREATE TABLE IF NOT EXISTS email_suppressions (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    email_address TEXT NOT NULL,
+    reason TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_email_suppressions_on_email_address" ON email_suppressions (email_address);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS consents (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    version TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_consents_on_user_id_kind_version" ON consents (user_id, kind, version);
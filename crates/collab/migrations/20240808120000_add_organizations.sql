@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS organizations (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_organizations_on_slug" ON organizations (slug);
+
+CREATE TABLE IF NOT EXISTS organization_members (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_organization_members_on_organization_id_user_id" ON organization_members (organization_id, user_id);
+CREATE INDEX "ix_organization_members_on_user_id" ON organization_members (user_id);
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS model_experiments (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    name TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE UNIQUE INDEX "uix_model_experiments_on_name" ON model_experiments (name);
+
+CREATE TABLE IF NOT EXISTS model_experiment_variants (
+    id SERIAL PRIMARY KEY,
+    experiment_id INTEGER NOT NULL REFERENCES model_experiments(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    weight INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    temperature REAL
+);
+
+CREATE UNIQUE INDEX "uix_model_experiment_variants_on_experiment_id_name" ON model_experiment_variants (experiment_id, name);
+
+CREATE TABLE IF NOT EXISTS model_experiment_exposures (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    experiment_id INTEGER NOT NULL REFERENCES model_experiments(id) ON DELETE CASCADE,
+    variant_id INTEGER NOT NULL REFERENCES model_experiment_variants(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX "uix_model_experiment_exposures_on_experiment_id_user_id" ON model_experiment_exposures (experiment_id, user_id);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS user_secrets (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    ciphertext BYTEA NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_user_secrets_on_user_id_name" ON user_secrets (user_id, name);
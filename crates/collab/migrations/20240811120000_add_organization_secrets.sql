@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS organization_secrets (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_organization_secrets_on_organization_id_name" ON organization_secrets (organization_id, name);
+
+CREATE TABLE IF NOT EXISTS organization_secret_leases (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    organization_secret_id INTEGER NOT NULL REFERENCES organization_secrets(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE INDEX "ix_organization_secret_leases_on_organization_secret_id" ON organization_secret_leases (organization_secret_id);
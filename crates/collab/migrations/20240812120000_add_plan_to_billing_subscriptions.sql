@@ -0,0 +1 @@
+ALTER TABLE billing_subscriptions ADD COLUMN plan TEXT NOT NULL DEFAULT 'pro';
@@ -0,0 +1 @@
+ALTER TABLE billing_subscriptions ADD COLUMN seat_count INTEGER NOT NULL DEFAULT 1;
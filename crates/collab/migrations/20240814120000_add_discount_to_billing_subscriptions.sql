@@ -0,0 +1,2 @@
+ALTER TABLE billing_subscriptions ADD COLUMN stripe_coupon_id TEXT;
+ALTER TABLE billing_subscriptions ADD COLUMN stripe_promotion_code_id TEXT;
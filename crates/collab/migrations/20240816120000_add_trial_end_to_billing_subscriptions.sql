@@ -0,0 +1 @@
+ALTER TABLE billing_subscriptions ADD COLUMN trial_end TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,3 @@
+ALTER TABLE billing_subscriptions ADD COLUMN current_period_end TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE billing_subscriptions ADD COLUMN cancel_at_period_end BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE billing_subscriptions ADD COLUMN canceled_at TIMESTAMP WITHOUT TIME ZONE;
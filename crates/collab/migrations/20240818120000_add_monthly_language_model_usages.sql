@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS monthly_language_model_usages (
+    id SERIAL PRIMARY KEY,
+    month DATE NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    feature TEXT,
+    request_count BIGINT NOT NULL,
+    input_tokens BIGINT NOT NULL,
+    output_tokens BIGINT NOT NULL,
+    upstream_cost_in_millicents BIGINT NOT NULL
+);
+
+CREATE INDEX "ix_monthly_language_model_usages_on_user_id_month" ON monthly_language_model_usages (user_id, month);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS impersonation_sessions (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    staff_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    impersonated_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    allow_writes BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    ended_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX "ix_impersonation_sessions_on_impersonated_user_id" ON impersonation_sessions (impersonated_user_id);
+
+CREATE TABLE IF NOT EXISTS impersonated_requests (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    session_id INTEGER NOT NULL REFERENCES impersonation_sessions(id) ON DELETE CASCADE,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    allowed BOOLEAN NOT NULL
+);
+
+CREATE INDEX "ix_impersonated_requests_on_session_id" ON impersonated_requests (session_id);
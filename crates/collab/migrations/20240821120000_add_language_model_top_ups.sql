@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS language_model_top_ups (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    stripe_checkout_session_id TEXT NOT NULL,
+    period_start DATE NOT NULL,
+    tokens BIGINT NOT NULL,
+    fulfilled_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE UNIQUE INDEX "uix_language_model_top_ups_on_stripe_checkout_session_id" ON language_model_top_ups (stripe_checkout_session_id);
+CREATE INDEX "ix_language_model_top_ups_on_user_id_period_start" ON language_model_top_ups (user_id, period_start);
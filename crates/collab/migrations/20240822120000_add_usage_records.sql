@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS usage_records (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    period_start TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    period_end TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    quantity BIGINT NOT NULL,
+    stripe_usage_record_id TEXT,
+    reported_at TIMESTAMP WITHOUT TIME ZONE,
+    attempt_count INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITHOUT TIME ZONE,
+    last_error TEXT
+);
+
+CREATE UNIQUE INDEX "uix_usage_records_on_billing_subscription_id_period_start" ON usage_records (billing_subscription_id, period_start);
+CREATE INDEX "ix_usage_records_on_reported_at" ON usage_records (reported_at);
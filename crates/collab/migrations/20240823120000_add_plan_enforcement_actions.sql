@@ -0,0 +1,17 @@
+ALTER TABLE channels ADD COLUMN archived_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE organizations ADD COLUMN features_disabled_at TIMESTAMP WITHOUT TIME ZONE;
+
+CREATE TABLE IF NOT EXISTS plan_enforcement_actions (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    scheduled_for TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    notified_at TIMESTAMP WITHOUT TIME ZONE,
+    enforced_at TIMESTAMP WITHOUT TIME ZONE,
+    canceled_at TIMESTAMP WITHOUT TIME ZONE,
+    reverted_at TIMESTAMP WITHOUT TIME ZONE
+);
+
+CREATE INDEX "ix_plan_enforcement_actions_on_user_id" ON plan_enforcement_actions (user_id);
+CREATE INDEX "ix_plan_enforcement_actions_on_scheduled_for" ON plan_enforcement_actions (scheduled_for);
@@ -0,0 +1 @@
+ALTER TABLE billing_customers ADD COLUMN tax_exempt TEXT NOT NULL DEFAULT 'none';
@@ -0,0 +1,17 @@
+ALTER TABLE billing_subscription_transitions ADD COLUMN plan TEXT;
+
+CREATE TABLE IF NOT EXISTS billing_invoice_events (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    billing_customer_id INTEGER NOT NULL REFERENCES billing_customers(id) ON DELETE CASCADE,
+    stripe_invoice_id TEXT NOT NULL,
+    stripe_subscription_id TEXT,
+    kind TEXT NOT NULL,
+    billing_reason TEXT,
+    amount_in_cents BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    occurred_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_billing_invoice_events_on_stripe_invoice_id_kind" ON billing_invoice_events (stripe_invoice_id, kind);
+CREATE INDEX "ix_billing_invoice_events_on_billing_customer_id" ON billing_invoice_events (billing_customer_id);
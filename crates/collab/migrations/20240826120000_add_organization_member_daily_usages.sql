@@ -0,0 +1,16 @@
+ALTER TABLE organizations ADD COLUMN usage_rolled_up_through DATE;
+
+CREATE TABLE IF NOT EXISTS organization_member_daily_usages (
+    id SERIAL PRIMARY KEY,
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    date DATE NOT NULL,
+    request_count BIGINT NOT NULL,
+    input_tokens BIGINT NOT NULL,
+    output_tokens BIGINT NOT NULL,
+    estimated_cost_in_millicents BIGINT NOT NULL,
+    top_models JSONB NOT NULL,
+    updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX "uix_organization_member_daily_usages_on_organization_id_date_user_id" ON organization_member_daily_usages (organization_id, date, user_id);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS billing_customer_transfers (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    billing_customer_id INTEGER NOT NULL REFERENCES billing_customers(id) ON DELETE CASCADE,
+    from_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    to_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    organization_id INTEGER REFERENCES organizations(id) ON DELETE SET NULL,
+    transferred_by_user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT
+);
+
+CREATE INDEX "ix_billing_customer_transfers_on_billing_customer_id" ON billing_customer_transfers (billing_customer_id);
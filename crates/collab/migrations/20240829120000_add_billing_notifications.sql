@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS billing_notifications (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    email_delivery_id INTEGER NOT NULL REFERENCES email_deliveries(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX "uix_billing_notifications_on_user_id_key" ON billing_notifications (user_id, key);
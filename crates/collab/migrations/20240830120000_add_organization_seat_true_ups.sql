@@ -0,0 +1,14 @@
+ALTER TABLE organizations ADD COLUMN billing_subscription_id INTEGER REFERENCES billing_subscriptions(id) ON DELETE SET NULL;
+ALTER TABLE organizations ADD COLUMN seat_policy TEXT NOT NULL DEFAULT 'flag';
+
+CREATE TABLE IF NOT EXISTS organization_seat_true_ups (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    organization_id INTEGER NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    member_count INTEGER NOT NULL,
+    seat_count INTEGER NOT NULL,
+    outcome TEXT NOT NULL
+);
+
+CREATE INDEX "ix_organization_seat_true_ups_on_organization_id" ON organization_seat_true_ups (organization_id);
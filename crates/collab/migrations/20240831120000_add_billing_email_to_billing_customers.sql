@@ -0,0 +1 @@
+ALTER TABLE billing_customers ADD COLUMN billing_email TEXT;
@@ -0,0 +1 @@
+ALTER TABLE billing_subscriptions ADD COLUMN current_period_ends_at TIMESTAMP WITHOUT TIME ZONE;
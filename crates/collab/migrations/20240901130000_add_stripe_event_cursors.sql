@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS stripe_event_cursors (
+    name TEXT PRIMARY KEY,
+    last_event_id TEXT NOT NULL,
+    last_event_created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
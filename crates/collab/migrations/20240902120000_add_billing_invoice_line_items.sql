@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS billing_invoice_line_items (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    billing_subscription_id INTEGER NOT NULL REFERENCES billing_subscriptions(id) ON DELETE CASCADE,
+    stripe_invoice_id TEXT NOT NULL,
+    stripe_invoice_line_item_id TEXT NOT NULL,
+    period_start TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    period_end TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    quantity BIGINT NOT NULL,
+    description TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX "uix_billing_invoice_line_items_on_stripe_invoice_line_item_id" ON billing_invoice_line_items (stripe_invoice_line_item_id);
+CREATE INDEX "ix_billing_invoice_line_items_on_stripe_invoice_id" ON billing_invoice_line_items (stripe_invoice_id);
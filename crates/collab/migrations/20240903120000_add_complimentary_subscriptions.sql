@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS complimentary_subscriptions (
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    plan TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    reason TEXT,
+    granted_by_user_id INTEGER NOT NULL REFERENCES users(id),
+    expires_at TIMESTAMP WITHOUT TIME ZONE,
+    revoked_at TIMESTAMP WITHOUT TIME ZONE,
+    revoked_by_user_id INTEGER REFERENCES users(id)
+);
+
+CREATE INDEX "ix_complimentary_subscriptions_on_user_id" ON complimentary_subscriptions (user_id);
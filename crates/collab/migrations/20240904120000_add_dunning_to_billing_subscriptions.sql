@@ -0,0 +1,7 @@
+ALTER TABLE billing_subscriptions ADD COLUMN dunning_attempt_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE billing_subscriptions ADD COLUMN next_dunning_attempt_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE billing_subscriptions ADD COLUMN suspended_at TIMESTAMP WITHOUT TIME ZONE;
+
+-- Unpaid subscriptions used to stop granting access right away, so those that
+-- already are start out suspended.
+UPDATE billing_subscriptions SET suspended_at = now() WHERE stripe_subscription_status = 'unpaid';
@@ -0,0 +1,8 @@
+ALTER TABLE billing_invoice_events ADD COLUMN usd_exchange_rate DOUBLE PRECISION;
+ALTER TABLE billing_invoice_events ADD COLUMN amount_in_usd_cents BIGINT;
+
+UPDATE billing_invoice_events
+SET usd_exchange_rate = 1, amount_in_usd_cents = amount_in_cents
+WHERE currency = 'usd';
+
+CREATE INDEX "ix_billing_invoice_events_on_occurred_at" ON billing_invoice_events (occurred_at);
@@ -0,0 +1,2 @@
+ALTER TABLE billing_customers ADD COLUMN stripe_account_id VARCHAR;
+ALTER TABLE billing_subscriptions ADD COLUMN stripe_account_id VARCHAR;
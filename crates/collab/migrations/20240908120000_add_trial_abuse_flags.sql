@@ -0,0 +1,18 @@
+ALTER TABLE billing_customers ADD COLUMN payment_method_fingerprint VARCHAR;
+
+CREATE INDEX "ix_billing_customers_on_payment_method_fingerprint" ON billing_customers (payment_method_fingerprint);
+
+CREATE TABLE IF NOT EXISTS trial_abuse_flags (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason VARCHAR NOT NULL,
+    details VARCHAR NOT NULL,
+    decision VARCHAR,
+    decision_note VARCHAR,
+    decided_by_user_id INTEGER REFERENCES users(id),
+    decided_at TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "ix_trial_abuse_flags_on_user_id" ON trial_abuse_flags (user_id);
+CREATE INDEX "ix_trial_abuse_flags_on_decided_at" ON trial_abuse_flags (decided_at);
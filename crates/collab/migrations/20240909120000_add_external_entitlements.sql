@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS external_entitlements (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    source VARCHAR NOT NULL,
+    external_id VARCHAR NOT NULL,
+    plan VARCHAR NOT NULL,
+    started_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now(),
+    ended_at TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX "uix_external_entitlements_on_source_external_id" ON external_entitlements (source, external_id);
+CREATE INDEX "ix_external_entitlements_on_user_id" ON external_entitlements (user_id);
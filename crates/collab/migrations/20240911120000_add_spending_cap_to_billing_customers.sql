@@ -0,0 +1 @@
+ALTER TABLE billing_customers ADD COLUMN spending_cap_in_cents BIGINT;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS billing_audit_log (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    event VARCHAR NOT NULL,
+    billing_subscription_id INTEGER REFERENCES billing_subscriptions(id) ON DELETE SET NULL,
+    previous_state TEXT,
+    state TEXT,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX "ix_billing_audit_log_on_user_id" ON billing_audit_log (user_id);
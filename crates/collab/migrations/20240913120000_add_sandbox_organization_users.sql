@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS sandbox_organization_users (
+    organization_id INTEGER NOT NULL REFERENCES sandbox_organizations(organization_id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (organization_id, user_id)
+);
+
+-- Sandbox users are the only ones with negative GitHub user IDs.
+INSERT INTO sandbox_organization_users (organization_id, user_id)
+SELECT organization_members.organization_id, organization_members.user_id
+FROM organization_members
+JOIN sandbox_organizations ON sandbox_organizations.organization_id = organization_members.organization_id
+JOIN users ON users.id = organization_members.user_id
+WHERE users.github_user_id < 0;
@@ -0,0 +1 @@
+ALTER TABLE usage_records ADD COLUMN stripe_timestamp TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,15 @@
+-- Organizations created before owners existed were created with their
+-- creator as their first admin.
+UPDATE organization_members
+SET role = 'owner'
+WHERE id IN (
+    SELECT MIN(id)
+    FROM organization_members
+    WHERE role = 'admin'
+    GROUP BY organization_id
+)
+AND organization_id NOT IN (
+    SELECT organization_id
+    FROM organization_members
+    WHERE role = 'owner'
+);
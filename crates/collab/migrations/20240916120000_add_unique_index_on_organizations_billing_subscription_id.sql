@@ -0,0 +1,13 @@
+-- A subscription pays for the seats of at most one organization, so any
+-- organization sharing another's subscription is detached from it.
+UPDATE organizations
+SET billing_subscription_id = NULL
+WHERE billing_subscription_id IS NOT NULL
+AND id NOT IN (
+    SELECT MIN(id)
+    FROM organizations
+    WHERE billing_subscription_id IS NOT NULL
+    GROUP BY billing_subscription_id
+);
+
+CREATE UNIQUE INDEX "uix_organizations_on_billing_subscription_id" ON organizations (billing_subscription_id);
@@ -0,0 +1 @@
+ALTER TABLE referrals ADD COLUMN referrer_credit_pending_since TIMESTAMP WITHOUT TIME ZONE;
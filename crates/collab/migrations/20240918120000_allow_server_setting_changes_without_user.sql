@@ -0,0 +1 @@
+ALTER TABLE server_setting_changes ALTER COLUMN changed_by_user_id DROP NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE billing_subscriptions ADD COLUMN current_period_start TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS "ix_monthly_language_model_usages_on_user_id_month";
+CREATE UNIQUE INDEX "uix_monthly_language_model_usages_on_user_id_month_provider_model_feature" ON monthly_language_model_usages (user_id, month, provider, model, COALESCE(feature, ''));
@@ -0,0 +1,15 @@
+ALTER TABLE language_model_usages ADD COLUMN organization_id INTEGER REFERENCES organizations(id) ON DELETE SET NULL;
+
+CREATE INDEX "ix_language_model_usages_on_organization_id_created_at" ON language_model_usages (organization_id, created_at);
+
+-- Attribute the usage that may still be rolled up to the first organization
+-- each member joined.
+UPDATE language_model_usages
+SET organization_id = (
+    SELECT organization_id
+    FROM organization_members
+    WHERE organization_members.user_id = language_model_usages.user_id
+    ORDER BY organization_members.id
+    LIMIT 1
+)
+WHERE created_at >= now() - INTERVAL '31 days';
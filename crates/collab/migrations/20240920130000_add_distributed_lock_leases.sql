@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS distributed_lock_leases (
+    name VARCHAR PRIMARY KEY,
+    holder VARCHAR NOT NULL,
+    expires_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE referrals ADD COLUMN referrer_credit_skipped_at TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,3 @@
+ALTER TABLE impersonation_sessions ADD COLUMN token_hash VARCHAR;
+
+CREATE UNIQUE INDEX "uix_impersonation_sessions_on_token_hash" ON impersonation_sessions (token_hash);
@@ -1,13 +1,33 @@
 pub mod billing;
+pub mod billing_audit_log;
+pub mod billing_grants;
+pub mod billing_history;
+pub mod billing_preview;
+pub mod billing_revenue;
+pub mod billing_spending_caps;
+pub mod billing_transfers;
+pub mod consents;
 pub mod contributors;
+pub mod emails;
 pub mod events;
 pub mod extensions;
+pub mod impersonation;
 pub mod ips_file;
+pub mod language_model_costs;
+pub mod model_experiments;
+pub mod organization_members;
+pub mod organizations;
+pub mod referrals;
+pub mod sandbox_organizations;
+pub mod server_settings;
 pub mod slack;
+pub mod trial_abuse_flags;
+pub mod usage_anomalies;
 
 use crate::{
     auth,
     db::{User, UserId},
+    entitlements::BillingMode,
     rpc, AppState, Error, Result,
 };
 use anyhow::anyhow;
@@ -28,21 +48,68 @@ use tower::ServiceBuilder;
 pub use extensions::fetch_extensions_from_blob_store_periodically;
 
 pub fn routes(rpc_server: Option<Arc<rpc::Server>>, state: Arc<AppState>) -> Router<(), Body> {
-    Router::new()
+    let mut router = Router::new()
         .route("/user", get(get_authenticated_user))
         .route("/users/:id/access_tokens", post(create_access_token))
-        .route("/rpc_server_snapshot", get(get_rpc_server_snapshot))
-        .merge(billing::router())
+        .route("/rpc_server_snapshot", get(get_rpc_server_snapshot));
+    // Servers without billing don't expose any of its routes.
+    if state.config.billing_mode() != BillingMode::Disabled {
+        router = router.merge(
+            Router::new()
+                .merge(billing::router())
+                .merge(billing_audit_log::router())
+                .merge(billing_grants::router())
+                .merge(billing_history::router())
+                .merge(billing_preview::router())
+                .merge(billing_revenue::router())
+                .merge(billing_spending_caps::router())
+                .merge(billing_transfers::router())
+                .merge(referrals::router())
+                .merge(trial_abuse_flags::router())
+                .layer(middleware::from_fn(billing::reject_writes_while_read_only)),
+        );
+    }
+    // Sandbox organizations are only for testing against a test-mode Stripe
+    // account.
+    if state.config.sandbox_organizations_enabled() {
+        router = router.merge(sandbox_organizations::router());
+    }
+
+    // Webhooks authenticate with their own signatures, rather than our API
+    // token.
+    let mut webhook_router = Router::new();
+    if state.config.billing_mode().is_stripe() {
+        webhook_router = webhook_router.merge(billing::webhook_router());
+    }
+    webhook_router = webhook_router.merge(emails::webhook_router());
+
+    router
+        .merge(consents::router())
         .merge(contributors::router())
+        .merge(impersonation::router())
+        .merge(language_model_costs::router())
+        .merge(model_experiments::router())
+        .merge(organization_members::router())
+        .merge(organizations::router())
+        .merge(server_settings::router())
+        .merge(usage_anomalies::router())
         .layer(
             ServiceBuilder::new()
-                .layer(Extension(state))
-                .layer(Extension(rpc_server))
-                .layer(middleware::from_fn(validate_api_token)),
+                .layer(Extension(state.clone()))
+                .layer(Extension(rpc_server.clone()))
+                .layer(middleware::from_fn(validate_api_token))
+                .layer(middleware::from_fn(impersonation::enforce_impersonation)),
+        )
+        .merge(
+            webhook_router.layer(
+                ServiceBuilder::new()
+                    .layer(Extension(state))
+                    .layer(Extension(rpc_server)),
+            ),
         )
 }
 
-pub async fn validate_api_token<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+pub async fn validate_api_token<B>(mut req: Request<B>, next: Next<B>) -> impl IntoResponse {
     let token = req
         .headers()
         .get(http::header::AUTHORIZATION)
@@ -61,18 +128,41 @@ pub async fn validate_api_token<B>(req: Request<B>, next: Next<B>) -> impl IntoR
             )
         })?;
 
-    let state = req.extensions().get::<Arc<AppState>>().unwrap();
+    let state = req.extensions().get::<Arc<AppState>>().unwrap().clone();
 
     if token != state.config.api_token {
-        Err(Error::Http(
-            StatusCode::UNAUTHORIZED,
-            "invalid authorization token".to_string(),
-        ))?
+        // Staff members make requests during an impersonation session with
+        // the session's own token, which limits them to what it allows.
+        let Some(session_token) = impersonation::session_token(&state, token).await? else {
+            Err(Error::Http(
+                StatusCode::UNAUTHORIZED,
+                "invalid authorization token".to_string(),
+            ))?
+        };
+        req.extensions_mut().insert(session_token);
     }
 
     Ok::<_, Error>(next.run(req).await)
 }
 
+/// Returns the user with the given GitHub user ID, provided they're an admin,
+/// refusing the request otherwise, as admins are the only ones allowed to
+/// perform the given action.
+pub(crate) async fn admin_user(app: &AppState, github_user_id: i32, action: &str) -> Result<User> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    if !user.admin {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            format!("only admins can {action}"),
+        ))?
+    }
+    Ok(user)
+}
+
 #[derive(Debug, Deserialize)]
 struct AuthenticatedUserParams {
     github_user_id: Option<i32>,
@@ -1,45 +1,370 @@
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context};
-use axum::{extract, routing::post, Extension, Json, Router};
-use reqwest::StatusCode;
-use serde::{Deserialize, Serialize};
+use axum::{
+    extract::{self, Query},
+    http::{HeaderMap, Method, Request},
+    middleware::Next,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use collab_api_client::{
+    BillingPlanPrice, BillingSubscription, CreateBillingSubscriptionBody,
+    CreateBillingSubscriptionResponse, CreateLanguageModelTopUpBody,
+    CreateLanguageModelTopUpResponse, GetLanguageModelUsageParams, GetLanguageModelUsageResponse,
+    ListBillingPlansParams, ListBillingPlansResponse, ListBillingSubscriptionsParams,
+    ListBillingSubscriptionsResponse, ManageBillingSubscriptionBody,
+    ManageBillingSubscriptionResponse, ManageSubscriptionIntent, UpdateBillingEmailBody,
+    UpdateBillingEmailResponse, UpdateBillingSubscriptionSeatsBody,
+    UpdateBillingSubscriptionSeatsResponse,
+};
+use collections::{BTreeMap, HashMap};
+use parking_lot::Mutex;
+use reqwest::{StatusCode, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use stripe::{
-    BillingPortalSession, CheckoutSession, CreateBillingPortalSession,
-    CreateBillingPortalSessionFlowData, CreateBillingPortalSessionFlowDataAfterCompletion,
-    CreateBillingPortalSessionFlowDataAfterCompletionRedirect,
-    CreateBillingPortalSessionFlowDataType, CreateCheckoutSession, CreateCheckoutSessionLineItems,
-    CreateCustomer, Customer, CustomerId, EventObject, EventType, Expandable, ListEvents,
-    SubscriptionStatus,
+    CheckoutSessionLocale, CheckoutSessionPaymentStatus, Customer, CustomerId, CustomerTaxExempt,
+    EventObject, EventType, Expandable, RequestStrategy, SubscriptionStatus,
 };
+use time::{OffsetDateTime, PrimitiveDateTime};
 use util::ResultExt;
+use uuid::Uuid;
 
-use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::api::consents::ensure_current_consents;
+use crate::api::referrals::{
+    apply_pending_referrer_credits, credit_referrer, referral_coupon_for_user,
+};
+use crate::billing_notifications::{send_billing_notification, BillingNotification};
+use crate::currency::currency_format;
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_customer::TaxExemptStatus;
+use crate::db::billing_invoice_event::BillingInvoiceEventKind;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
 use crate::db::{
-    billing_customer, BillingSubscriptionId, CreateBillingCustomerParams,
-    CreateBillingSubscriptionParams,
+    billing_customer, billing_subscription, BillingCustomerId, BillingCustomerSensitiveDetails,
+    BillingSubscriptionId, CreateBillingAuditLogEntryParams, CreateBillingCustomerParams,
+    CreateBillingInvoiceEventParams, CreateBillingSubscriptionParams,
+    CreateLanguageModelTopUpParams, UpsertBillingSubscriptionOptions, User, UserId,
+};
+use crate::distributed_lock::run_exclusively;
+use crate::dunning;
+use crate::entitlements::{Plan, UsagePeriod};
+use crate::exchange_rates::usd_amount_on;
+use crate::metered_billing;
+use crate::payment_provider::{
+    CheckoutCustomer, CheckoutDiscount, CheckoutLineItem, CheckoutMode,
+    CreateCheckoutSessionParams, CreateCustomerParams, CreatePortalSessionParams, ListEventsParams,
+    PaymentCheckoutSession, PaymentPrice, PaymentProvider, PaymentSubscription, PortalFlow,
 };
-use crate::{AppState, Error, Result};
+use crate::plan_enforcement;
+use crate::stripe_connect::ResellerAccounts;
+use crate::stripe_test_clocks;
+use crate::stripe_webhook;
+use crate::trial_abuse;
+use crate::{AppState, Config, Error, Result};
+
+/// The name our cursor through Stripe's billing events is stored under.
+const STRIPE_EVENT_CURSOR_NAME: &str = "billing";
+
+/// How far back before the last processed event polling starts again.
+const STRIPE_EVENT_CURSOR_OVERLAP: time::Duration = time::Duration::minutes(5);
+
+const DEFAULT_SUCCESS_URL: &str = "https://zed.dev/billing/success";
+const DEFAULT_RETURN_URL: &str = "https://zed.dev/billing";
 
 pub fn router() -> Router {
     Router::new()
-        .route("/billing/subscriptions", post(create_billing_subscription))
+        .route(
+            "/billing/subscriptions",
+            get(list_billing_subscriptions).post(create_billing_subscription),
+        )
         .route(
             "/billing/subscriptions/manage",
             post(manage_billing_subscription),
         )
+        .route(
+            "/billing/subscriptions/seats/add",
+            post(add_billing_subscription_seats),
+        )
+        .route(
+            "/billing/subscriptions/seats/remove",
+            post(remove_billing_subscription_seats),
+        )
+        .route("/billing/email", put(update_billing_email))
+        .route("/billing/top_ups", post(create_language_model_top_up))
+        .route("/billing/usage", get(get_language_model_usage))
+        .route("/billing/plans", get(list_billing_plans))
+}
+
+/// The routes Stripe sends webhooks to, which are authenticated by their
+/// signatures rather than our API token.
+pub fn webhook_router() -> Router {
+    Router::new()
+        .route("/billing/webhooks/stripe", post(handle_stripe_webhook))
+        .route(
+            "/billing/webhooks/stripe/connect",
+            post(handle_stripe_connect_webhook),
+        )
+}
+
+impl From<billing_subscription::Model> for BillingSubscription {
+    fn from(subscription: billing_subscription::Model) -> Self {
+        billing_subscription_json(subscription, OffsetDateTime::now_utc())
+    }
+}
+
+impl From<SubscriptionPlan> for collab_api_client::SubscriptionPlan {
+    fn from(plan: SubscriptionPlan) -> Self {
+        match plan {
+            SubscriptionPlan::Free => Self::Free,
+            SubscriptionPlan::Pro => Self::Pro,
+            SubscriptionPlan::Team => Self::Team,
+        }
+    }
+}
+
+impl From<collab_api_client::SubscriptionPlan> for SubscriptionPlan {
+    fn from(plan: collab_api_client::SubscriptionPlan) -> Self {
+        match plan {
+            collab_api_client::SubscriptionPlan::Free => Self::Free,
+            collab_api_client::SubscriptionPlan::Pro => Self::Pro,
+            collab_api_client::SubscriptionPlan::Team => Self::Team,
+        }
+    }
+}
+
+impl From<StripeSubscriptionStatus> for collab_api_client::SubscriptionStatus {
+    fn from(status: StripeSubscriptionStatus) -> Self {
+        match status {
+            StripeSubscriptionStatus::Incomplete => Self::Incomplete,
+            StripeSubscriptionStatus::IncompleteExpired => Self::IncompleteExpired,
+            StripeSubscriptionStatus::Trialing => Self::Trialing,
+            StripeSubscriptionStatus::Active => Self::Active,
+            StripeSubscriptionStatus::PastDue => Self::PastDue,
+            StripeSubscriptionStatus::Canceled => Self::Canceled,
+            StripeSubscriptionStatus::Unpaid => Self::Unpaid,
+            StripeSubscriptionStatus::Paused => Self::Paused,
+        }
+    }
+}
+
+/// Returns the subscription as the API presents it at the given time.
+fn billing_subscription_json(
+    subscription: billing_subscription::Model,
+    now: OffsetDateTime,
+) -> BillingSubscription {
+    let access_until = if subscription.cancel_at_period_end
+        && subscription.stripe_subscription_status != StripeSubscriptionStatus::Canceled
+    {
+        subscription
+            .current_period_end
+            .map(|date| date.assume_utc())
+    } else {
+        None
+    };
+    let grace_period_ends_at = subscription
+        .grace_period_ends_at
+        .map(|date| date.assume_utc());
+    BillingSubscription {
+        id: subscription.id.0,
+        status: subscription.stripe_subscription_status.into(),
+        plan: subscription.plan.into(),
+        seat_count: subscription.seat_count,
+        trial_end: subscription.trial_end.map(|date| date.assume_utc()),
+        current_period_end: subscription
+            .current_period_end
+            .map(|date| date.assume_utc()),
+        cancel_at_period_end: subscription.cancel_at_period_end,
+        canceled_at: subscription.canceled_at.map(|date| date.assume_utc()),
+        access_until,
+        grace_period_ends_at,
+        grace_period_remaining_seconds: grace_period_ends_at
+            .map(|ends_at| (ends_at - now).whole_seconds().max(0)),
+        suspended_at: subscription.suspended_at.map(|date| date.assume_utc()),
+        created_at: subscription.created_at.assume_utc(),
+    }
+}
+
+/// Returns all of the user's billing subscriptions, regardless of their
+/// status, oldest first.
+async fn list_billing_subscriptions(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<ListBillingSubscriptionsParams>,
+) -> Result<Json<ListBillingSubscriptionsResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let subscriptions = app.db.get_billing_subscriptions(user.id).await?;
+
+    Ok(Json(ListBillingSubscriptionsResponse {
+        subscriptions: subscriptions.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// How long the prices of plans are cached for. Prices rarely change, while
+/// plans are listed whenever the pricing page is shown.
+const PLAN_PRICE_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Caches the prices of plans retrieved from the payment provider.
+#[derive(Default)]
+pub struct PlanPriceCache {
+    prices: Mutex<HashMap<String, (PaymentPrice, Instant)>>,
+}
+
+impl PlanPriceCache {
+    async fn get(
+        &self,
+        payment_provider: &dyn PaymentProvider,
+        price_id: &str,
+    ) -> anyhow::Result<PaymentPrice> {
+        if let Some((price, retrieved_at)) = self.prices.lock().get(price_id) {
+            if retrieved_at.elapsed() < PLAN_PRICE_CACHE_TTL {
+                return Ok(price.clone());
+            }
+        }
+
+        let price = payment_provider.get_price(price_id).await?;
+        self.prices
+            .lock()
+            .insert(price_id.to_string(), (price.clone(), Instant::now()));
+        Ok(price)
+    }
+}
+
+/// Returns the prices of the plans users can subscribe to, as configured in
+/// Stripe, along with how to format them for the requested locale.
+async fn list_billing_plans(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<ListBillingPlansParams>,
+) -> Result<Json<ListBillingPlansResponse>> {
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let locale = params.locale.as_deref().and_then(normalize_locale);
+    let mut prices = Vec::new();
+    for plan_price in PlanCatalog::from_config(&app.config)?.prices {
+        // Resellers' prices are only offered in the countries they sell in.
+        if plan_price.stripe_account_id.is_some() {
+            continue;
+        }
+        let price = app
+            .plan_prices
+            .get(payment_provider.as_ref(), &plan_price.price_id)
+            .await?;
+        // Prices without a unit amount, such as tiered ones, can't be shown
+        // as a single amount.
+        let Some(unit_amount) = price.unit_amount else {
+            log::warn!(
+                "price {} of plan {:?} has no unit amount",
+                plan_price.price_id,
+                plan_price.plan
+            );
+            continue;
+        };
+        let currency = price.currency.unwrap_or(plan_price.currency);
+        prices.push(BillingPlanPrice {
+            plan: plan_price.plan.into(),
+            unit_amount,
+            currency: currency_format(&currency, locale.as_deref()),
+        });
+    }
+
+    Ok(Json(ListBillingPlansResponse { prices }))
 }
 
-#[derive(Debug, Deserialize)]
-struct CreateBillingSubscriptionBody {
-    github_user_id: i32,
+/// Changes the email address of the user's Stripe customer, which receipts
+/// and invoices are sent to.
+async fn update_billing_email(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<UpdateBillingEmailBody>,
+) -> Result<Json<UpdateBillingEmailResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let billing_email = body
+        .billing_email
+        .as_deref()
+        .map(str::trim)
+        .filter(|billing_email| Some(*billing_email) != user.email_address.as_deref());
+    if let Some(billing_email) = billing_email {
+        if !is_valid_email(billing_email) {
+            Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                format!("invalid email address {billing_email:?}"),
+            ))?
+        }
+    }
+
+    let billing_customer = app
+        .db
+        .get_billing_customer_by_user_id(user.id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "no billing customer found".into()))?;
+
+    payment_provider
+        .for_account(billing_customer.stripe_account_id.as_deref())?
+        .update_customer_email(
+            &billing_customer.stripe_customer_id,
+            billing_email.or(user.email_address.as_deref()),
+        )
+        .await?;
+    app.db
+        .update_billing_customer_email(billing_customer.id, billing_email)
+        .await?;
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::BillingEmailChanged,
+            billing_subscription_id: None,
+            previous_state: Some(
+                serde_json::json!({ "billing_email": billing_customer.billing_email }).to_string(),
+            ),
+            state: Some(serde_json::json!({ "billing_email": billing_email }).to_string()),
+        })
+        .await?;
+
+    Ok(Json(UpdateBillingEmailResponse {
+        billing_email: billing_email.map(str::to_string),
+    }))
 }
 
-#[derive(Debug, Serialize)]
-struct CreateBillingSubscriptionResponse {
-    checkout_session_url: String,
+/// Returns whether the address looks like something email can be sent to.
+///
+/// Stripe validates addresses itself, so this only catches obvious mistakes.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local_part, domain)) = email.split_once('@') else {
+        return false;
+    };
+    !local_part.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.chars().any(char::is_whitespace)
 }
 
 /// Initiates a Stripe Checkout session for creating a billing subscription.
@@ -52,83 +377,779 @@ async fn create_billing_subscription(
         .get_user_by_github_user_id(body.github_user_id)
         .await?
         .ok_or_else(|| anyhow!("user not found"))?;
+    let plan = SubscriptionPlan::from(body.plan);
+    let locale = locale_for_request(&app, &user, body.locale.as_deref()).await?;
+    let success_url = billing_redirect_url(
+        body.success_url.as_deref(),
+        app.config.billing_redirect_origins.as_deref(),
+        app.config
+            .billing_success_url
+            .as_deref()
+            .unwrap_or(DEFAULT_SUCCESS_URL),
+    )?;
 
-    let Some((stripe_client, stripe_price_id)) = app
-        .stripe_client
-        .clone()
-        .zip(app.config.stripe_price_id.clone())
-    else {
-        log::error!("failed to retrieve Stripe client or price ID");
+    ensure_current_consents(&app, user.id).await?;
+
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
         Err(Error::Http(
             StatusCode::NOT_IMPLEMENTED,
             "not supported".into(),
         ))?
     };
 
-    let customer_id =
-        if let Some(existing_customer) = app.db.get_billing_customer_by_user_id(user.id).await? {
-            CustomerId::from_str(&existing_customer.stripe_customer_id)
-                .context("failed to parse customer ID")?
-        } else {
-            let customer = Customer::create(
-                &stripe_client,
-                CreateCustomer {
-                    email: user.email_address.as_deref(),
-                    ..Default::default()
-                },
+    // Customers stay on the account they were created on, while new ones are
+    // created on the account of the reseller selling in their country, if any.
+    let existing_customer = app.db.get_billing_customer_by_user_id(user.id).await?;
+    let stripe_account_id = match &existing_customer {
+        Some(customer) => customer.stripe_account_id.clone(),
+        None => match body.country.as_deref() {
+            Some(country) => ResellerAccounts::from_config(&app.config)?
+                .account_for_country(country)
+                .map(str::to_string),
+            None => None,
+        },
+    };
+    let payment_provider = payment_provider.for_account(stripe_account_id.as_deref())?;
+
+    // Check that the plan is available before creating a customer for it.
+    plan_price_id(
+        &app.config,
+        plan,
+        DEFAULT_CURRENCY,
+        stripe_account_id.as_deref(),
+    )?;
+
+    let seat_count = body.seat_count.unwrap_or(1);
+    if seat_count == 0 || (seat_count > 1 && plan != SubscriptionPlan::Team) {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "invalid seat count {seat_count} for the {} plan",
+                plan.as_str()
+            ),
+        ))?
+    }
+
+    let promotion_code_id = if let Some(promo_code) = body.promo_code.as_deref() {
+        let promotion_code_id = payment_provider
+            .find_promotion_code(promo_code)
+            .await?
+            .ok_or_else(|| {
+                Error::Http(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid promo code {promo_code:?}"),
+                )
+            })?;
+        Some(promotion_code_id)
+    } else {
+        None
+    };
+
+    let (customer_id, customer_currency) = if let Some(existing_customer) = existing_customer {
+        let customer_currency = payment_provider
+            .get_customer_currency(&existing_customer.stripe_customer_id)
+            .await?;
+        (existing_customer.stripe_customer_id, customer_currency)
+    } else {
+        // The clock is looked up before the key is computed, so that a
+        // retried request reuses both the clock and the customer on it.
+        let test_clock_id = if app.config.stripe_test_clocks_enabled() {
+            Some(
+                payment_provider
+                    .find_or_create_test_clock(&user.github_login)
+                    .await?,
             )
+        } else {
+            None
+        };
+        let params = CreateCustomerParams {
+            email: user.email_address.clone(),
+            test_clock_id,
+        };
+        let idempotency_key = idempotency_key("create_customer", user.id, &params)?;
+        let customer_id = payment_provider
+            .create_customer(&params, idempotency_key)
             .await?;
 
-            customer.id
-        };
+        // Should concurrent requests still end up creating separate
+        // customers, the first one recorded is the one that's used.
+        let billing_customer = app
+            .db
+            .get_or_create_billing_customer(&CreateBillingCustomerParams {
+                user_id: user.id,
+                stripe_customer_id: customer_id,
+                stripe_account_id: stripe_account_id.clone(),
+            })
+            .await?;
+        apply_pending_referrer_credits(&app, user.id)
+            .await
+            .log_err();
+        (billing_customer.stripe_customer_id, None)
+    };
+
+    let currency = checkout_currency(
+        customer_currency,
+        requested_currency(body.currency.as_deref(), body.country.as_deref()),
+        is_metered(&app.config, stripe_account_id.as_deref()),
+    );
+    let stripe_price_id =
+        plan_price_id(&app.config, plan, &currency, stripe_account_id.as_deref())?;
+
+    let checkout_session = create_subscription_checkout_session(
+        &app,
+        payment_provider.as_ref(),
+        &user,
+        customer_id,
+        stripe_account_id.as_deref(),
+        plan,
+        &stripe_price_id,
+        seat_count,
+        promotion_code_id,
+        &success_url,
+        locale.as_deref(),
+    )
+    .await?;
+
+    Ok(Json(CreateBillingSubscriptionResponse {
+        checkout_session_url: checkout_session.url,
+    }))
+}
+
+/// Returns the Stripe price of the plan in the currency on the given account,
+/// if users can subscribe to it, falling back to its USD price.
+fn plan_price_id(
+    config: &Config,
+    plan: SubscriptionPlan,
+    currency: &str,
+    stripe_account_id: Option<&str>,
+) -> Result<String> {
+    let catalog = PlanCatalog::from_config(config)?;
+    match catalog
+        .price_id_on_account(plan, currency, stripe_account_id)
+        .or_else(|| catalog.price_id_on_account(plan, DEFAULT_CURRENCY, stripe_account_id))
+    {
+        Some(price_id) => Ok(price_id.to_string()),
+        None => Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("the {} plan is not available", plan.as_str()),
+        )),
+    }
+}
+
+/// Returns whether subscriptions on the given account are billed for the
+/// language model tokens used. Usage is only reported to our own account.
+fn is_metered(config: &Config, stripe_account_id: Option<&str>) -> bool {
+    config.stripe_metered_price_id.is_some() && stripe_account_id.is_none()
+}
+
+/// Creates a Checkout session for the customer to subscribe to the plan.
+#[allow(clippy::too_many_arguments)]
+async fn create_subscription_checkout_session(
+    app: &AppState,
+    payment_provider: &dyn PaymentProvider,
+    user: &User,
+    customer_id: String,
+    stripe_account_id: Option<&str>,
+    plan: SubscriptionPlan,
+    stripe_price_id: &str,
+    seat_count: u32,
+    promotion_code_id: Option<String>,
+    success_url: &str,
+    locale: Option<&str>,
+) -> Result<PaymentCheckoutSession> {
+    let mut line_items = vec![CheckoutLineItem {
+        price_id: stripe_price_id.to_string(),
+        quantity: Some(seat_count as u64),
+    }];
+    if let Some(metered_price_id) = app
+        .config
+        .stripe_metered_price_id
+        .as_deref()
+        .filter(|_| is_metered(&app.config, stripe_account_id))
+    {
+        line_items.push(CheckoutLineItem {
+            price_id: metered_price_id.to_string(),
+            quantity: None,
+        });
+    }
+    // Referral coupons only exist on our own account.
+    let referral_coupon_id = if stripe_account_id.is_none() {
+        referral_coupon_for_user(app, user.id).await?
+    } else {
+        None
+    };
+    let discount = checkout_discount(promotion_code_id, referral_coupon_id);
+
+    let params = CreateCheckoutSessionParams {
+        mode: CheckoutMode::Subscription,
+        customer: CheckoutCustomer::Existing { customer_id },
+        client_reference_id: user.github_login.clone(),
+        line_items,
+        metadata: BTreeMap::default(),
+        subscription_metadata: [("plan".to_string(), plan.as_str().to_string())]
+            .into_iter()
+            .collect(),
+        trial_period_days: trial_period_days_for_user(app, user).await?,
+        allow_promotion_codes: discount.is_none(),
+        discount,
+        success_url: success_url.to_string(),
+        locale: locale.map(str::to_string),
+        automatic_tax: app.config.stripe_automatic_tax.unwrap_or(false),
+    };
+    let idempotency_key = attempt_idempotency_key("create_checkout_session", user.id);
+    let checkout_session = payment_provider
+        .create_checkout_session(&params, idempotency_key)
+        .await?;
+
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::CheckoutSessionCreated,
+            billing_subscription_id: None,
+            previous_state: None,
+            state: Some(
+                serde_json::json!({
+                    "checkout_session_id": checkout_session.id,
+                    "plan": plan,
+                    "seat_count": seat_count,
+                    "stripe_account_id": stripe_account_id,
+                })
+                .to_string(),
+            ),
+        })
+        .await?;
+
+    Ok(checkout_session)
+}
+
+/// Returns the length of the free trial the user gets when subscribing. Only
+/// a user's first subscription has a trial, and users suspected of cycling
+/// through trials with other accounts get none.
+async fn trial_period_days_for_user(app: &AppState, user: &User) -> Result<Option<u32>> {
+    let Some(trial_period_days) = app.config.stripe_trial_period_days else {
+        return Ok(None);
+    };
+    if !app.db.get_billing_subscriptions(user.id).await?.is_empty() {
+        return Ok(None);
+    }
+    if !trial_abuse::may_offer_trial(app, user).await? {
+        log::info!("not offering user {} a trial", user.id);
+        return Ok(None);
+    }
+    Ok(Some(trial_period_days))
+}
+
+/// Initiates a one-time Stripe Checkout session for buying a block of
+/// language model tokens on top of those included in the user's plan.
+///
+/// The tokens count toward the current usage period once the checkout
+/// session completes.
+async fn create_language_model_top_up(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<CreateLanguageModelTopUpBody>,
+) -> Result<Json<CreateLanguageModelTopUpResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let locale = locale_for_request(&app, &user, body.locale.as_deref()).await?;
+    let success_url = billing_redirect_url(
+        body.success_url.as_deref(),
+        app.config.billing_redirect_origins.as_deref(),
+        app.config
+            .billing_success_url
+            .as_deref()
+            .unwrap_or(DEFAULT_SUCCESS_URL),
+    )?;
+
+    ensure_current_consents(&app, user.id).await?;
+
+    let (Some(payment_provider), Some(stripe_price_id), Some(tokens)) = (
+        app.payment_provider.clone(),
+        app.config.stripe_top_up_price_id.clone(),
+        app.config.top_up_tokens,
+    ) else {
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let period = UsagePeriod::containing(OffsetDateTime::now_utc());
+    let billing_customer = app.db.get_billing_customer_by_user_id(user.id).await?;
+    // Top-ups are sold on our own account, which resellers' customers aren't
+    // customers of.
+    if billing_customer
+        .as_ref()
+        .map_or(false, |customer| customer.stripe_account_id.is_some())
+    {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "top-ups are not available through resellers".into(),
+        ))?
+    }
 
     let checkout_session = {
-        let mut params = CreateCheckoutSession::new();
-        params.mode = Some(stripe::CheckoutSessionMode::Subscription);
-        params.customer = Some(customer_id);
-        params.client_reference_id = Some(user.github_login.as_str());
-        params.line_items = Some(vec![CreateCheckoutSessionLineItems {
-            price: Some(stripe_price_id.to_string()),
-            quantity: Some(1),
-            ..Default::default()
-        }]);
-        params.success_url = Some("https://zed.dev/billing/success");
+        let params = CreateCheckoutSessionParams {
+            mode: CheckoutMode::Payment,
+            customer: match billing_customer {
+                Some(billing_customer) => CheckoutCustomer::Existing {
+                    customer_id: billing_customer.stripe_customer_id,
+                },
+                None => CheckoutCustomer::New {
+                    email: user.email_address.clone(),
+                },
+            },
+            client_reference_id: user.github_login.clone(),
+            line_items: vec![CheckoutLineItem {
+                price_id: stripe_price_id,
+                quantity: Some(1),
+            }],
+            metadata: [
+                ("top_up_tokens".to_string(), tokens.to_string()),
+                ("period_start".to_string(), period.start.date().to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            subscription_metadata: BTreeMap::default(),
+            trial_period_days: None,
+            discount: None,
+            allow_promotion_codes: false,
+            success_url,
+            locale,
+            automatic_tax: app.config.stripe_automatic_tax.unwrap_or(false),
+        };
 
-        CheckoutSession::create(&stripe_client, params).await?
+        let idempotency_key = attempt_idempotency_key("create_top_up_checkout_session", user.id);
+        payment_provider
+            .create_checkout_session(&params, idempotency_key)
+            .await?
     };
 
-    Ok(Json(CreateBillingSubscriptionResponse {
-        checkout_session_url: checkout_session
-            .url
-            .ok_or_else(|| anyhow!("no checkout session URL"))?,
+    app.db
+        .create_language_model_top_up(&CreateLanguageModelTopUpParams {
+            user_id: user.id,
+            stripe_checkout_session_id: checkout_session.id.clone(),
+            period_start: period.start.date(),
+            tokens,
+        })
+        .await?;
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::TopUpCheckoutSessionCreated,
+            billing_subscription_id: None,
+            previous_state: None,
+            state: Some(
+                serde_json::json!({
+                    "checkout_session_id": checkout_session.id,
+                    "tokens": tokens,
+                })
+                .to_string(),
+            ),
+        })
+        .await?;
+
+    Ok(Json(CreateLanguageModelTopUpResponse {
+        checkout_session_url: checkout_session.url,
+        tokens,
+    }))
+}
+
+/// Returns the user's language model token usage for the current usage
+/// period, along with what's left of their allowance.
+async fn get_language_model_usage(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetLanguageModelUsageParams>,
+) -> Result<Json<GetLanguageModelUsageResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let quota = app
+        .entitlements
+        .language_model_quota(user.id, OffsetDateTime::now_utc())
+        .await?;
+
+    Ok(Json(GetLanguageModelUsageResponse {
+        plan: quota.entitlements.plan.as_str().to_string(),
+        period_start: quota.period.start,
+        period_end: quota.period.end,
+        plan_tokens: quota.entitlements.monthly_language_model_tokens,
+        top_up_tokens: quota.top_up_tokens,
+        used_tokens: quota.used_tokens,
+        remaining_tokens: quota.remaining_tokens(),
+        remaining_top_up_tokens: quota.remaining_top_up_tokens(),
     }))
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "snake_case")]
-enum ManageSubscriptionIntent {
-    /// The user intends to cancel their subscription.
-    Cancel,
+/// Returns the discount to apply to a checkout session.
+///
+/// Checkout sessions take a single discount, so a promotion code the user
+/// entered takes precedence over their referral coupon. Without either, the
+/// user can enter a promotion code during checkout.
+fn checkout_discount(
+    promotion_code_id: Option<String>,
+    referral_coupon_id: Option<String>,
+) -> Option<CheckoutDiscount> {
+    if let Some(promotion_code_id) = promotion_code_id {
+        Some(CheckoutDiscount::PromotionCode { promotion_code_id })
+    } else {
+        referral_coupon_id.map(|coupon_id| CheckoutDiscount::Coupon { coupon_id })
+    }
+}
+
+/// Returns a key identifying a Stripe request by its operation, the user it's
+/// made for, and its parameters.
+///
+/// Sending the key with the request makes Stripe return the original result
+/// when a client retries it within a day, rather than creating a duplicate.
+pub(crate) fn idempotency_key(
+    operation: &str,
+    user_id: UserId,
+    params: &impl Serialize,
+) -> anyhow::Result<String> {
+    let digest = Sha256::digest(serde_json::to_vec(params)?);
+    Ok(format!("{operation}:{user_id}:{}", hex::encode(digest)))
 }
 
-#[derive(Debug, Deserialize)]
-struct ManageBillingSubscriptionBody {
-    github_user_id: i32,
-    intent: ManageSubscriptionIntent,
-    /// The ID of the subscription to manage.
-    ///
-    /// If not provided, we will try to use the active subscription (if there is only one).
-    subscription_id: Option<BillingSubscriptionId>,
+/// Returns a key identifying a single attempt at a Stripe request.
+///
+/// Retries within the attempt share the key, so they don't repeat a request
+/// that went through, but later attempts don't get its result back. That
+/// matters for requests whose results are used up, such as a Checkout session
+/// that's been completed, or that undo each other, such as canceling and
+/// resuming a subscription.
+pub(crate) fn attempt_idempotency_key(operation: &str, user_id: UserId) -> String {
+    format!("{operation}:{user_id}:{}", Uuid::new_v4())
+}
+
+/// Returns a Stripe client whose requests carry the given idempotency key.
+pub(crate) fn idempotent_client(
+    stripe_client: &stripe::Client,
+    idempotency_key: String,
+) -> stripe::Client {
+    stripe_client
+        .clone()
+        .with_strategy(RequestStrategy::Idempotent(idempotency_key))
+}
+
+/// Returns the URL to send the user to once they leave Stripe's pages: the one
+/// the client requested, provided its origin is in `allowed_origins`, or the
+/// default one otherwise.
+fn billing_redirect_url(
+    requested_url: Option<&str>,
+    allowed_origins: Option<&str>,
+    default_url: &str,
+) -> Result<String> {
+    let Some(requested_url) = requested_url else {
+        return Ok(default_url.to_string());
+    };
+
+    let is_allowed = Url::parse(requested_url).map_or(false, |url| {
+        allowed_origins
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|origin| Url::parse(origin.trim()).ok())
+            .any(|origin| origin.origin() == url.origin())
+    });
+    if !is_allowed {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("redirecting to {requested_url:?} is not allowed"),
+        ))?
+    }
+    Ok(requested_url.to_string())
+}
+
+/// Returns the locale to show the user Stripe's pages in, remembering it so
+/// that the emails we send them can use it too.
+///
+/// Only the locale requested in the body is used, since these requests are
+/// made by other servers, whose `Accept-Language` header says nothing about
+/// the user. Without one, the locale last requested for the user is used.
+async fn locale_for_request(
+    app: &AppState,
+    user: &User,
+    requested_locale: Option<&str>,
+) -> Result<Option<String>> {
+    let Some(locale) = requested_locale.and_then(normalize_locale) else {
+        return Ok(user.locale.clone());
+    };
+
+    if user.locale.as_deref() != Some(locale.as_str()) {
+        app.db.set_user_locale(user.id, Some(&locale)).await?;
+    }
+    Ok(Some(locale))
+}
+
+/// Normalizes a language tag to a language, optionally followed by a region,
+/// such as `fr` or `fr-CA`. Returns `None` if the tag isn't valid.
+fn normalize_locale(tag: &str) -> Option<String> {
+    let mut subtags = tag.trim().split(['-', '_']);
+    let language = subtags.next().filter(|language| {
+        (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic())
+    })?;
+
+    let mut locale = language.to_ascii_lowercase();
+    if let Some(region) = subtags
+        .next()
+        .filter(|region| region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        locale.push('-');
+        locale.push_str(&region.to_ascii_uppercase());
+    }
+    Some(locale)
+}
+
+/// Returns the locale Stripe supports that best matches the given one, falling
+/// back to the locale's language when Stripe doesn't support its region.
+pub(crate) fn stripe_locale<L: DeserializeOwned>(locale: &str) -> Option<L> {
+    let parse = |locale: &str| serde_json::from_value(locale.into()).ok();
+    parse(locale).or_else(|| {
+        locale
+            .split_once('-')
+            .and_then(|(language, _)| parse(language))
+    })
+}
+
+/// The currency plans are priced in unless a price in another currency is
+/// chosen.
+const DEFAULT_CURRENCY: &str = "usd";
+
+/// The currencies of the countries whose users are offered local pricing,
+/// keyed by ISO 3166-1 alpha-2 country code.
+const COUNTRY_CURRENCIES: &[(&str, &str)] = &[
+    ("AT", "eur"),
+    ("AU", "aud"),
+    ("BE", "eur"),
+    ("BR", "brl"),
+    ("CA", "cad"),
+    ("CH", "chf"),
+    ("CY", "eur"),
+    ("DE", "eur"),
+    ("DK", "dkk"),
+    ("EE", "eur"),
+    ("ES", "eur"),
+    ("FI", "eur"),
+    ("FR", "eur"),
+    ("GB", "gbp"),
+    ("GR", "eur"),
+    ("HR", "eur"),
+    ("IE", "eur"),
+    ("IN", "inr"),
+    ("IT", "eur"),
+    ("JP", "jpy"),
+    ("LT", "eur"),
+    ("LU", "eur"),
+    ("LV", "eur"),
+    ("MT", "eur"),
+    ("NL", "eur"),
+    ("NO", "nok"),
+    ("NZ", "nzd"),
+    ("PL", "pln"),
+    ("PT", "eur"),
+    ("SE", "sek"),
+    ("SI", "eur"),
+    ("SK", "eur"),
+];
+
+/// Returns the currency a user asked to pay in, either directly or through
+/// their country.
+fn requested_currency(currency: Option<&str>, country: Option<&str>) -> Option<String> {
+    if let Some(currency) = currency
+        .map(str::trim)
+        .filter(|currency| !currency.is_empty())
+    {
+        return Some(currency.to_ascii_lowercase());
+    }
+    let country = country?.trim();
+    COUNTRY_CURRENCIES
+        .iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(country))
+        .map(|(_, currency)| currency.to_string())
+}
+
+/// Returns the currency to check out in.
+///
+/// Stripe bills a customer in a single currency, so once a customer has
+/// paid in one, they keep paying in it. The metered price is only in USD,
+/// and a subscription's prices must share a currency, so local pricing is
+/// only offered without one.
+fn checkout_currency(
+    customer_currency: Option<String>,
+    requested_currency: Option<String>,
+    has_metered_price: bool,
+) -> String {
+    customer_currency
+        .or(requested_currency.filter(|_| !has_metered_price))
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string())
+}
+
+/// A Stripe price of a plan.
+#[derive(Debug, PartialEq, Eq)]
+struct PlanPrice {
+    plan: SubscriptionPlan,
+    /// The ISO 4217 code of the price's currency, in lowercase.
+    currency: String,
+    /// The reseller's connected account the price is on, if it's not on ours.
+    stripe_account_id: Option<String>,
+    price_id: String,
 }
 
-#[derive(Debug, Serialize)]
-struct ManageBillingSubscriptionResponse {
-    billing_portal_session_url: String,
+/// The Stripe prices of the plans users can subscribe to.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct PlanCatalog {
+    prices: Vec<PlanPrice>,
 }
 
-/// Initiates a Stripe customer portal session for managing a billing subscription.
+impl PlanCatalog {
+    /// Builds the catalog from `stripe_plan_price_ids`, falling back to
+    /// `stripe_price_id` for the Pro plan.
+    fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Self::parse(
+            config.stripe_plan_price_ids.as_deref(),
+            config.stripe_price_id.as_deref(),
+        )
+    }
+
+    fn parse(plan_price_ids: Option<&str>, pro_price_id: Option<&str>) -> anyhow::Result<Self> {
+        let mut catalog = Self::default();
+        if let Some(plan_price_ids) = plan_price_ids {
+            for entry in plan_price_ids.split(',').map(str::trim) {
+                if entry.is_empty() {
+                    continue;
+                }
+                let (plan, price_id) = entry
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("invalid plan price entry {entry:?}"))?;
+                let (plan, stripe_account_id) = plan
+                    .split_once('@')
+                    .map_or((plan, None), |(plan, account_id)| {
+                        (plan, Some(account_id.trim().to_string()))
+                    });
+                let (plan, currency) = plan
+                    .split_once('/')
+                    .map_or((plan, DEFAULT_CURRENCY), |(plan, currency)| {
+                        (plan, currency.trim())
+                    });
+                if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                    bail!("invalid currency {currency:?} in plan price entry {entry:?}");
+                }
+                let plan = <SubscriptionPlan as sea_orm::ActiveEnum>::try_from_value(
+                    &plan.trim().to_string(),
+                )
+                .map_err(|_| anyhow!("unknown plan {plan:?}"))?;
+                catalog.prices.push(PlanPrice {
+                    plan,
+                    currency: currency.to_ascii_lowercase(),
+                    stripe_account_id,
+                    price_id: price_id.trim().to_string(),
+                });
+            }
+        }
+        if catalog.price_id(SubscriptionPlan::Pro).is_none() {
+            if let Some(price_id) = pro_price_id {
+                catalog.prices.push(PlanPrice {
+                    plan: SubscriptionPlan::Pro,
+                    currency: DEFAULT_CURRENCY.to_string(),
+                    stripe_account_id: None,
+                    price_id: price_id.to_string(),
+                });
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Returns the USD price of the plan.
+    fn price_id(&self, plan: SubscriptionPlan) -> Option<&str> {
+        self.price_id_in_currency(plan, DEFAULT_CURRENCY)
+    }
+
+    fn price_id_in_currency(&self, plan: SubscriptionPlan, currency: &str) -> Option<&str> {
+        self.price_id_on_account(plan, currency, None)
+    }
+
+    /// Returns the price of the plan in the currency on the given account, or
+    /// on ours for `None`.
+    fn price_id_on_account(
+        &self,
+        plan: SubscriptionPlan,
+        currency: &str,
+        stripe_account_id: Option<&str>,
+    ) -> Option<&str> {
+        self.prices
+            .iter()
+            .find(|price| {
+                price.plan == plan
+                    && price.currency == currency
+                    && price.stripe_account_id.as_deref() == stripe_account_id
+            })
+            .map(|price| price.price_id.as_str())
+    }
+
+    fn plan_for_price_id(&self, price_id: &str) -> Option<SubscriptionPlan> {
+        self.prices
+            .iter()
+            .find(|price| price.price_id == price_id)
+            .map(|price| price.plan)
+    }
+
+    /// Returns the plan of the given subscription, as determined by its
+    /// prices or, failing that, the plan recorded when it was checked out.
+    fn plan_for_subscription(&self, subscription: &PaymentSubscription) -> SubscriptionPlan {
+        subscription
+            .items
+            .iter()
+            .filter_map(|item| item.price_id.as_deref())
+            .find_map(|price_id| self.plan_for_price_id(price_id))
+            .or_else(|| {
+                let plan = subscription.metadata.get("plan")?;
+                <SubscriptionPlan as sea_orm::ActiveEnum>::try_from_value(plan).ok()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the IDs of every Stripe price on our own account that we're
+/// configured with.
+pub(crate) fn configured_price_ids(config: &Config) -> anyhow::Result<Vec<String>> {
+    let mut price_ids = PlanCatalog::from_config(config)?
+        .prices
+        .into_iter()
+        .filter(|price| price.stripe_account_id.is_none())
+        .map(|price| price.price_id)
+        .collect::<Vec<_>>();
+    price_ids.extend(config.stripe_metered_price_id.clone());
+    Ok(price_ids)
+}
+
+/// Refuses requests that would change billing while it's read-only, such as
+/// during a Stripe key rotation. Reads are still served from the database.
+pub async fn reject_writes_while_read_only<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let app = req.extensions().get::<Arc<AppState>>().unwrap();
+    if req.method() != Method::GET && app.server_settings.get().billing_read_only() {
+        Err(Error::Http(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "billing is temporarily read-only".into(),
+        ))?
+    }
+
+    Ok::<_, Error>(next.run(req).await)
+}
+
+/// Initiates a Stripe customer portal session for managing a billing
+/// subscription, or the portal as a whole, or cancels the subscription right
+/// away for the intents that don't need one.
 async fn manage_billing_subscription(
     Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
     extract::Json(body): extract::Json<ManageBillingSubscriptionBody>,
 ) -> Result<Json<ManageBillingSubscriptionResponse>> {
     let user = app
@@ -136,191 +1157,1290 @@ async fn manage_billing_subscription(
         .get_user_by_github_user_id(body.github_user_id)
         .await?
         .ok_or_else(|| anyhow!("user not found"))?;
+    let subscription_id = body.subscription_id.map(BillingSubscriptionId);
+    let locale = locale_for_request(&app, &user, body.locale.as_deref()).await?;
+    let return_url = billing_redirect_url(
+        body.return_url.as_deref(),
+        app.config.billing_redirect_origins.as_deref(),
+        app.config
+            .billing_return_url
+            .as_deref()
+            .unwrap_or(DEFAULT_RETURN_URL),
+    )?;
+
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let customer = app
+        .db
+        .get_billing_customer_by_user_id(user.id)
+        .await?
+        .ok_or_else(|| anyhow!("billing customer not found"))?;
+    let stripe_account_id = customer.stripe_account_id.as_deref();
+    let payment_provider = payment_provider.for_account(stripe_account_id)?;
+
+    let flow = match body.intent {
+        ManageSubscriptionIntent::CancelImmediately
+        | ManageSubscriptionIntent::CancelAtPeriodEnd => {
+            let subscription = cancel_subscription(
+                &app,
+                rpc_server.as_ref(),
+                payment_provider.as_ref(),
+                &customer,
+                subscription_id,
+                body.intent == ManageSubscriptionIntent::CancelAtPeriodEnd,
+            )
+            .await?;
+            return Ok(Json(ManageBillingSubscriptionResponse {
+                billing_portal_session_url: None,
+                checkout_session_url: None,
+                subscription: Some(subscription.into()),
+            }));
+        }
+        ManageSubscriptionIntent::Resume => {
+            let subscription =
+                find_customer_subscription_to_manage(&app, &customer, subscription_id).await?;
+
+            // Subscriptions that have ended can't be reactivated, so the user
+            // subscribes to the same plan again instead. Checkout offers the
+            // payment methods saved for the customer.
+            if subscription.stripe_subscription_status == StripeSubscriptionStatus::Canceled {
+                ensure_current_consents(&app, user.id).await?;
+                let currency = checkout_currency(
+                    payment_provider
+                        .get_customer_currency(&customer.stripe_customer_id)
+                        .await?,
+                    None,
+                    is_metered(&app.config, stripe_account_id),
+                );
+                let stripe_price_id =
+                    plan_price_id(&app.config, subscription.plan, &currency, stripe_account_id)?;
+                let checkout_session = create_subscription_checkout_session(
+                    &app,
+                    payment_provider.as_ref(),
+                    &user,
+                    customer.stripe_customer_id.clone(),
+                    stripe_account_id,
+                    subscription.plan,
+                    &stripe_price_id,
+                    subscription.seat_count.max(1) as u32,
+                    None,
+                    &return_url,
+                    locale.as_deref(),
+                )
+                .await?;
+                return Ok(Json(ManageBillingSubscriptionResponse {
+                    billing_portal_session_url: None,
+                    checkout_session_url: Some(checkout_session.url),
+                    subscription: None,
+                }));
+            }
+
+            if !subscription.cancel_at_period_end {
+                Err(Error::Http(
+                    StatusCode::BAD_REQUEST,
+                    "subscription is not canceled".into(),
+                ))?
+            }
+
+            let payment_subscription = payment_provider
+                .resume_subscription(
+                    &subscription.stripe_subscription_id,
+                    attempt_idempotency_key("resume_subscription", user.id),
+                )
+                .await?;
+            store_billing_subscription(
+                &app,
+                rpc_server.as_ref(),
+                &customer,
+                &payment_subscription,
+                Some(user.id),
+                None,
+            )
+            .await?;
+
+            let subscription = app
+                .db
+                .get_billing_subscription_by_id(subscription.id)
+                .await?
+                .ok_or_else(|| anyhow!("subscription not found"))?;
+            return Ok(Json(ManageBillingSubscriptionResponse {
+                billing_portal_session_url: None,
+                checkout_session_url: None,
+                subscription: Some(subscription.into()),
+            }));
+        }
+        ManageSubscriptionIntent::Cancel => {
+            let subscription = find_subscription_to_manage(&app, user.id, subscription_id).await?;
+            Some(PortalFlow::CancelSubscription {
+                subscription_id: subscription.stripe_subscription_id,
+            })
+        }
+        ManageSubscriptionIntent::Upgrade | ManageSubscriptionIntent::Downgrade => {
+            let subscription = find_subscription_to_manage(&app, user.id, subscription_id).await?;
+            let plan = validate_plan_change(
+                body.intent,
+                subscription.plan,
+                body.plan.map(SubscriptionPlan::from),
+            )?;
+            // Subscriptions can only have prices in the currency the customer
+            // is billed in.
+            let currency = checkout_currency(
+                payment_provider
+                    .get_customer_currency(&customer.stripe_customer_id)
+                    .await?,
+                None,
+                is_metered(&app.config, stripe_account_id),
+            );
+            let price_id = plan_price_id(&app.config, plan, &currency, stripe_account_id)?;
+
+            let payment_subscription = payment_provider
+                .get_subscription(&subscription.stripe_subscription_id)
+                .await?;
+            let item = payment_subscription.licensed_item()?;
+            // Team subscriptions keep their seats, while other plans only
+            // have one.
+            let seat_count = if plan == SubscriptionPlan::Team {
+                payment_subscription.seat_count()
+            } else {
+                1
+            };
+
+            // The portal shows the user the prorated cost of the change before
+            // they confirm it.
+            Some(PortalFlow::ConfirmSubscriptionUpdate {
+                subscription_id: subscription.stripe_subscription_id,
+                item_id: item.id.clone(),
+                price_id,
+                quantity: seat_count as u64,
+            })
+        }
+        // The payment method belongs to the customer, so it applies to all of
+        // their subscriptions.
+        ManageSubscriptionIntent::UpdatePaymentMethod => Some(PortalFlow::UpdatePaymentMethod),
+        ManageSubscriptionIntent::Portal => None,
+    };
+
+    let billing_portal_session_url = payment_provider
+        .create_portal_session(
+            &CreatePortalSessionParams {
+                customer_id: customer.stripe_customer_id,
+                flow,
+                // Portal configurations belong to an account, so those of our
+                // own can't be used on connected accounts.
+                configuration_id: app
+                    .config
+                    .stripe_billing_portal_configuration_id
+                    .clone()
+                    .filter(|_| stripe_account_id.is_none()),
+                return_url,
+                locale,
+            },
+            attempt_idempotency_key("create_portal_session", user.id),
+        )
+        .await?;
+
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::PortalSessionCreated,
+            billing_subscription_id: subscription_id,
+            previous_state: None,
+            state: Some(serde_json::json!({ "intent": body.intent }).to_string()),
+        })
+        .await?;
+
+    Ok(Json(ManageBillingSubscriptionResponse {
+        billing_portal_session_url: Some(billing_portal_session_url),
+        checkout_session_url: None,
+        subscription: None,
+    }))
+}
+
+/// Cancels one of the customer's subscriptions, either right away or at the
+/// end of its current billing period, and returns it as updated.
+async fn cancel_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    customer: &billing_customer::Model,
+    subscription_id: Option<BillingSubscriptionId>,
+    at_period_end: bool,
+) -> Result<billing_subscription::Model> {
+    let subscription = find_customer_subscription_to_manage(app, customer, subscription_id).await?;
+    if subscription.stripe_subscription_status == StripeSubscriptionStatus::Canceled {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "subscription is already canceled".into(),
+        ))?
+    }
+
+    let payment_subscription = payment_provider
+        .cancel_subscription(
+            &subscription.stripe_subscription_id,
+            at_period_end,
+            attempt_idempotency_key("cancel_subscription", customer.user_id),
+        )
+        .await?;
+
+    // Store the result right away, rather than once the resulting event is
+    // polled, so the client can show when access ends.
+    store_billing_subscription(
+        app,
+        rpc_server,
+        customer,
+        &payment_subscription,
+        Some(customer.user_id),
+        None,
+    )
+    .await?;
+
+    Ok(app
+        .db
+        .get_billing_subscription_by_id(subscription.id)
+        .await?
+        .ok_or_else(|| anyhow!("subscription not found"))?)
+}
+
+/// Returns the plan to switch to, provided it's an upgrade or a downgrade from
+/// the current plan, as the intent says.
+fn validate_plan_change(
+    intent: ManageSubscriptionIntent,
+    current_plan: SubscriptionPlan,
+    plan: Option<SubscriptionPlan>,
+) -> Result<SubscriptionPlan> {
+    let Some(plan) = plan else {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "a plan to switch to is required".into(),
+        ))?
+    };
+
+    let is_valid = match intent {
+        ManageSubscriptionIntent::Upgrade => Plan::from(plan) > Plan::from(current_plan),
+        ManageSubscriptionIntent::Downgrade => Plan::from(plan) < Plan::from(current_plan),
+        ManageSubscriptionIntent::Cancel
+        | ManageSubscriptionIntent::CancelImmediately
+        | ManageSubscriptionIntent::CancelAtPeriodEnd
+        | ManageSubscriptionIntent::Resume
+        | ManageSubscriptionIntent::UpdatePaymentMethod
+        | ManageSubscriptionIntent::Portal => false,
+    };
+    if !is_valid || plan == SubscriptionPlan::Free {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "cannot {} from the {} plan to the {} plan",
+                match intent {
+                    ManageSubscriptionIntent::Upgrade => "upgrade",
+                    ManageSubscriptionIntent::Downgrade => "downgrade",
+                    ManageSubscriptionIntent::Cancel
+                    | ManageSubscriptionIntent::CancelImmediately
+                    | ManageSubscriptionIntent::CancelAtPeriodEnd => "cancel",
+                    ManageSubscriptionIntent::Resume => "resume",
+                    ManageSubscriptionIntent::UpdatePaymentMethod
+                    | ManageSubscriptionIntent::Portal => "switch",
+                },
+                current_plan.as_str(),
+                plan.as_str()
+            ),
+        ))?
+    }
+
+    Ok(plan)
+}
+
+/// Like [`find_subscription_to_manage`], but only finds subscriptions that
+/// belong to the given customer.
+pub(crate) async fn find_customer_subscription_to_manage(
+    app: &AppState,
+    customer: &billing_customer::Model,
+    subscription_id: Option<BillingSubscriptionId>,
+) -> Result<billing_subscription::Model> {
+    let subscription = find_subscription_to_manage(app, customer.user_id, subscription_id).await?;
+    if subscription.billing_customer_id != customer.id {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "subscription not found".into(),
+        ))?
+    }
+    Ok(subscription)
+}
+
+/// Returns the subscription with the given ID or, if no ID was provided, the
+/// user's only active subscription.
+async fn find_subscription_to_manage(
+    app: &AppState,
+    user_id: UserId,
+    subscription_id: Option<BillingSubscriptionId>,
+) -> Result<billing_subscription::Model> {
+    if let Some(subscription_id) = subscription_id {
+        Ok(app
+            .db
+            .get_billing_subscription_by_id(subscription_id)
+            .await?
+            .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "subscription not found".into()))?)
+    } else {
+        // If no subscription ID was provided, try to find the only active subscription ID.
+        let subscriptions = app.db.get_active_billing_subscriptions(user_id).await?;
+        if subscriptions.len() > 1 {
+            Err(anyhow!("user has multiple active subscriptions"))?;
+        }
+
+        Ok(subscriptions
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("user has no active subscriptions"))?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SeatChange {
+    Add(u32),
+    Remove(u32),
+}
+
+impl SeatChange {
+    /// Returns the seat count after applying this change to `seat_count`.
+    fn apply(self, seat_count: i32) -> Result<i32> {
+        let new_seat_count = match self {
+            SeatChange::Add(0) | SeatChange::Remove(0) => Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                "the number of seats must be positive".into(),
+            ))?,
+            SeatChange::Add(seats) => seat_count.checked_add_unsigned(seats),
+            SeatChange::Remove(seats) => seat_count.checked_sub_unsigned(seats),
+        };
+        match new_seat_count {
+            Some(new_seat_count) if new_seat_count >= 1 => Ok(new_seat_count),
+            _ => Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                "a team subscription must have at least one seat".into(),
+            )),
+        }
+    }
+}
+
+/// Adds seats to a team subscription, charging a prorated amount for the
+/// rest of the billing period.
+async fn add_billing_subscription_seats(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<UpdateBillingSubscriptionSeatsBody>,
+) -> Result<Json<UpdateBillingSubscriptionSeatsResponse>> {
+    let change = SeatChange::Add(body.seats);
+    update_billing_subscription_seats(&app, body, change).await
+}
+
+/// Removes seats from a team subscription, crediting a prorated amount for
+/// the rest of the billing period.
+async fn remove_billing_subscription_seats(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<UpdateBillingSubscriptionSeatsBody>,
+) -> Result<Json<UpdateBillingSubscriptionSeatsResponse>> {
+    let change = SeatChange::Remove(body.seats);
+    update_billing_subscription_seats(&app, body, change).await
+}
+
+async fn update_billing_subscription_seats(
+    app: &AppState,
+    body: UpdateBillingSubscriptionSeatsBody,
+    change: SeatChange,
+) -> Result<Json<UpdateBillingSubscriptionSeatsResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let customer = app
+        .db
+        .get_billing_customer_by_user_id(user.id)
+        .await?
+        .ok_or_else(|| anyhow!("billing customer not found"))?;
+    let subscription = find_customer_subscription_to_manage(
+        app,
+        &customer,
+        body.subscription_id.map(BillingSubscriptionId),
+    )
+    .await?;
+    let payment_provider = payment_provider.for_account(customer.stripe_account_id.as_deref())?;
+    if subscription.plan != SubscriptionPlan::Team {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "only team subscriptions have seats".into(),
+        ))?;
+    }
+
+    // The payment provider is the source of truth for the seat count, which
+    // our record may lag behind until the subscription's events are processed.
+    let payment_subscription = payment_provider
+        .get_subscription(&subscription.stripe_subscription_id)
+        .await?;
+    if !matches!(
+        payment_subscription.status,
+        StripeSubscriptionStatus::Active | StripeSubscriptionStatus::Trialing
+    ) {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "only active subscriptions can change their seats".into(),
+        ))?;
+    }
+    let item = payment_subscription.licensed_item()?;
+    let previous_seat_count = payment_subscription.seat_count();
+    let seat_count = change.apply(previous_seat_count)?;
+    // Requests that saw the same seat count make the same change, so that
+    // concurrent or retried requests don't prorate it more than once.
+    let idempotency_key = idempotency_key(
+        "update_subscription_seats",
+        user.id,
+        &(
+            &subscription.stripe_subscription_id,
+            previous_seat_count,
+            seat_count,
+        ),
+    )?;
+    payment_provider
+        .update_subscription_seats(
+            &subscription.stripe_subscription_id,
+            &item.id,
+            seat_count as u64,
+            idempotency_key,
+        )
+        .await?;
+
+    let subscription = app
+        .db
+        .update_billing_subscription_seat_count(subscription.id, seat_count)
+        .await?;
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::SubscriptionSeatsChanged,
+            billing_subscription_id: Some(subscription.id),
+            previous_state: Some(
+                serde_json::json!({ "seat_count": previous_seat_count }).to_string(),
+            ),
+            state: Some(serde_json::json!({ "seat_count": seat_count }).to_string()),
+        })
+        .await?;
+
+    Ok(Json(UpdateBillingSubscriptionSeatsResponse {
+        seat_count: subscription.seat_count,
+    }))
+}
+
+/// Polls the Stripe events API periodically to reconcile the records in our
+/// database with the data in Stripe.
+///
+/// When running alongside the RPC server, subscription changes are pushed to
+/// the affected user's connected clients.
+pub fn poll_stripe_events_periodically(
+    app: Arc<AppState>,
+    rpc_server: Option<Arc<crate::rpc::Server>>,
+) {
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::warn!("failed to retrieve payment provider");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "poll_stripe_events",
+                        poll_stripe_events(&app, rpc_server.as_ref(), payment_provider.as_ref()),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown
+                    .sleep(
+                        &executor,
+                        app.server_settings.get().stripe_events_poll_interval(),
+                    )
+                    .await;
+            }
+        }
+    });
+}
+
+/// Polls the events of our own account, followed by those of each reseller's
+/// connected account.
+async fn poll_stripe_events(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+) -> anyhow::Result<()> {
+    let result = poll_stripe_account_events(app, rpc_server, payment_provider, None).await;
+    for account_id in ResellerAccounts::from_config(&app.config)?.account_ids() {
+        poll_stripe_account_events(app, rpc_server, payment_provider, Some(account_id))
+            .await
+            .with_context(|| format!("failed to poll events of account {account_id}"))
+            .log_err();
+    }
+    result
+}
+
+async fn poll_stripe_account_events(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+) -> anyhow::Result<()> {
+    let account_provider = payment_provider.for_account(stripe_account_id)?;
+    let cursor_name = stripe_event_cursor_name(stripe_account_id);
+    let event_types = [
+        EventType::CustomerCreated.to_string(),
+        EventType::CustomerUpdated.to_string(),
+        EventType::CustomerSubscriptionCreated.to_string(),
+        EventType::CustomerSubscriptionUpdated.to_string(),
+        EventType::CustomerSubscriptionPaused.to_string(),
+        EventType::CustomerSubscriptionResumed.to_string(),
+        EventType::CustomerSubscriptionDeleted.to_string(),
+        EventType::CheckoutSessionCompleted.to_string(),
+        EventType::CheckoutSessionAsyncPaymentSucceeded.to_string(),
+        EventType::InvoiceCreated.to_string(),
+        EventType::InvoicePaid.to_string(),
+        EventType::InvoicePaymentFailed.to_string(),
+        EventType::TestHelpersTestClockReady.to_string(),
+    ]
+    .into_iter()
+    .map(|event_type| {
+        // Calling `to_string` on `stripe::EventType` members gives us a quoted string,
+        // so we need to unquote it.
+        event_type.trim_matches('"').to_string()
+    })
+    .collect::<Vec<_>>();
+
+    let cursor = app.db.get_stripe_event_cursor(&cursor_name).await?;
+    let created_after = stripe_events_created_after(
+        cursor
+            .as_ref()
+            .map(|cursor| cursor.last_event_created_at.assume_utc()),
+    );
+
+    log::info!(
+        "retrieving events from Stripe account {}: {}",
+        stripe_account_id.unwrap_or("of our own"),
+        event_types.join(", ")
+    );
+
+    let mut params = ListEventsParams {
+        types: event_types,
+        created_after,
+        ending_before: None,
+        limit: 100,
+    };
+
+    // Events are listed newest first. Usually they all fit on the first page,
+    // and without a cursor, only that page is handled, as the events before
+    // it have long been handled.
+    let page = account_provider.list_events(&params).await?;
+    let Some(cursor) = cursor.filter(|_| page.has_more) else {
+        handle_stripe_event_page(
+            app,
+            rpc_server,
+            payment_provider,
+            stripe_account_id,
+            &cursor_name,
+            page.events,
+        )
+        .await?;
+        return Ok(());
+    };
+
+    // Otherwise, the events after the cursor are paged through oldest page
+    // first, handling each page before fetching the next.
+    let mut ending_before = Some(cursor.last_event_id);
+    while let Some(event_id) = ending_before.take() {
+        params.ending_before = Some(event_id);
+        let page = account_provider.list_events(&params).await?;
+        if page.has_more {
+            ending_before = page.events.first().map(|event| event.id.to_string());
+        }
+        if !handle_stripe_event_page(
+            app,
+            rpc_server,
+            payment_provider,
+            stripe_account_id,
+            &cursor_name,
+            page.events,
+        )
+        .await?
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles a page of events, listed newest first, in the order they happened,
+/// advancing the cursor past each one that is handled.
+///
+/// Stops at the first event that fails to be handled, so that it is retried
+/// on the next poll, rather than the cursor moving past it. Returns whether
+/// every event was handled.
+async fn handle_stripe_event_page(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    cursor_name: &str,
+    events: Vec<stripe::Event>,
+) -> anyhow::Result<bool> {
+    for event in events.into_iter().rev() {
+        // Leave the remaining events to whichever server polls next,
+        // rather than being stopped partway through handling one.
+        if app.shutdown.is_shutting_down() {
+            return Ok(false);
+        }
+
+        let event_id = event.id.clone();
+        let created_at = primitive_date_time_from_timestamp(
+            stripe_test_clocks::event_cursor_timestamp(event.created, OffsetDateTime::now_utc()),
+        )?;
+        handle_stripe_event(app, rpc_server, payment_provider, stripe_account_id, event)
+            .await
+            .with_context(|| format!("failed to handle event {event_id}"))?;
+        app.db
+            .update_stripe_event_cursor(cursor_name, event_id.as_str(), created_at)
+            .await?;
+    }
+
+    Ok(true)
+}
+
+/// Returns the name the cursor through the events of the given account is
+/// stored under, which for our own account is [`STRIPE_EVENT_CURSOR_NAME`].
+fn stripe_event_cursor_name(stripe_account_id: Option<&str>) -> String {
+    match stripe_account_id {
+        Some(account_id) => format!("{STRIPE_EVENT_CURSOR_NAME}:{account_id}"),
+        None => STRIPE_EVENT_CURSOR_NAME.to_string(),
+    }
+}
+
+/// Returns the time that polled events must have been created after, given
+/// when the last processed event was.
+///
+/// The window overlaps the last processed event by [`STRIPE_EVENT_CURSOR_OVERLAP`],
+/// as events can show up in Stripe's list a little after they were created.
+/// Handling events is idempotent, so seeing some of them twice is harmless.
+fn stripe_events_created_after(last_event_created_at: Option<OffsetDateTime>) -> Option<i64> {
+    last_event_created_at
+        .map(|created_at| (created_at - STRIPE_EVENT_CURSOR_OVERLAP).unix_timestamp())
+}
+
+/// Handles the events Stripe sends to our webhook endpoint. These are the
+/// same events we poll for, delivered as soon as they happen.
+async fn handle_stripe_webhook(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<()> {
+    let Some(secret) = app.config.stripe_webhook_secret.as_deref() else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "Stripe webhooks are not configured".into(),
+        ))?
+    };
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let event = stripe_webhook::verify_event(
+        &body,
+        headers
+            .get(stripe_webhook::SIGNATURE_HEADER)
+            .and_then(|header| header.to_str().ok()),
+        secret,
+        OffsetDateTime::now_utc(),
+    )?;
+    handle_stripe_event(
+        &app,
+        rpc_server.as_ref(),
+        payment_provider.as_ref(),
+        None,
+        event,
+    )
+    .await?;
+
+    Ok(())
+}
 
-    let Some(stripe_client) = app.stripe_client.clone() else {
-        log::error!("failed to retrieve Stripe client");
+/// Handles the events of resellers' connected accounts, which Stripe sends to
+/// our Connect webhook endpoint.
+async fn handle_stripe_connect_webhook(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<()> {
+    let Some(secret) = app.config.stripe_connect_webhook_secret.as_deref() else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "Stripe Connect webhooks are not configured".into(),
+        ))?
+    };
+    let Some(payment_provider) = app.payment_provider.clone() else {
+        log::error!("failed to retrieve payment provider");
         Err(Error::Http(
             StatusCode::NOT_IMPLEMENTED,
             "not supported".into(),
         ))?
     };
 
-    let customer = app
-        .db
-        .get_billing_customer_by_user_id(user.id)
-        .await?
-        .ok_or_else(|| anyhow!("billing customer not found"))?;
-    let customer_id = CustomerId::from_str(&customer.stripe_customer_id)
-        .context("failed to parse customer ID")?;
+    let event = stripe_webhook::verify_event(
+        &body,
+        headers
+            .get(stripe_webhook::SIGNATURE_HEADER)
+            .and_then(|header| header.to_str().ok()),
+        secret,
+        OffsetDateTime::now_utc(),
+    )?;
+    let Some(stripe_account_id) = event.account.as_ref().map(|account| account.to_string()) else {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "event is not from a connected account".into(),
+        ))?
+    };
+    handle_stripe_event(
+        &app,
+        rpc_server.as_ref(),
+        payment_provider.as_ref(),
+        Some(&stripe_account_id),
+        event,
+    )
+    .await?;
 
-    let subscription = if let Some(subscription_id) = body.subscription_id {
-        app.db
-            .get_billing_subscription_by_id(subscription_id)
-            .await?
-            .ok_or_else(|| anyhow!("subscription not found"))?
-    } else {
-        // If no subscription ID was provided, try to find the only active subscription ID.
-        let subscriptions = app.db.get_active_billing_subscriptions(user.id).await?;
-        if subscriptions.len() > 1 {
-            Err(anyhow!("user has multiple active subscriptions"))?;
+    Ok(())
+}
+
+/// Handles a billing event from Stripe, whether it was polled for or sent to
+/// our webhook. Events may be handled more than once, so handling them must
+/// be idempotent.
+///
+/// Events of a reseller's connected account are handled on its behalf.
+async fn handle_stripe_event(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    event: stripe::Event,
+) -> anyhow::Result<()> {
+    if let Some(account_id) = stripe_account_id {
+        if !ResellerAccounts::from_config(&app.config)?.contains(account_id) {
+            bail!("event {} is from unknown account {account_id}", event.id);
         }
+    }
+    let payment_provider = payment_provider.for_account(stripe_account_id)?;
+    let payment_provider = payment_provider.as_ref();
 
-        subscriptions
-            .into_iter()
-            .next()
-            .ok_or_else(|| anyhow!("user has no active subscriptions"))?
+    match event.type_ {
+        EventType::CustomerCreated | EventType::CustomerUpdated => {
+            handle_customer_event(app, payment_provider, stripe_account_id, event).await
+        }
+        EventType::CustomerSubscriptionCreated
+        | EventType::CustomerSubscriptionUpdated
+        | EventType::CustomerSubscriptionPaused
+        | EventType::CustomerSubscriptionResumed
+        | EventType::CustomerSubscriptionDeleted => {
+            handle_customer_subscription_event(
+                app,
+                rpc_server,
+                payment_provider,
+                stripe_account_id,
+                event,
+            )
+            .await
+        }
+        EventType::CheckoutSessionCompleted | EventType::CheckoutSessionAsyncPaymentSucceeded => {
+            handle_checkout_session_event(app, event).await
+        }
+        // Usage is only reported to our own account.
+        EventType::InvoiceCreated if stripe_account_id.is_none() => {
+            handle_invoice_created_event(app, payment_provider, event).await
+        }
+        EventType::InvoicePaid | EventType::InvoicePaymentFailed => {
+            handle_invoice_event(app, rpc_server, payment_provider, stripe_account_id, event).await
+        }
+        EventType::TestHelpersTestClockReady => {
+            stripe_test_clocks::handle_test_clock_ready_event(
+                app,
+                rpc_server,
+                payment_provider,
+                stripe_account_id,
+                event,
+            )
+            .await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn handle_customer_event(
+    app: &Arc<AppState>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    event: stripe::Event,
+) -> anyhow::Result<()> {
+    let EventObject::Customer(customer) = event.data.object else {
+        bail!("unexpected event payload for {}", event.id);
     };
 
-    let flow = match body.intent {
-        ManageSubscriptionIntent::Cancel => CreateBillingPortalSessionFlowData {
-            type_: CreateBillingPortalSessionFlowDataType::SubscriptionCancel,
-            after_completion: Some(CreateBillingPortalSessionFlowDataAfterCompletion {
-                type_: stripe::CreateBillingPortalSessionFlowDataAfterCompletionType::Redirect,
-                redirect: Some(CreateBillingPortalSessionFlowDataAfterCompletionRedirect {
-                    return_url: "https://zed.dev/billing".into(),
-                }),
-                ..Default::default()
-            }),
-            subscription_cancel: Some(
-                stripe::CreateBillingPortalSessionFlowDataSubscriptionCancel {
-                    subscription: subscription.stripe_subscription_id,
-                    retention: None,
-                },
-            ),
-            ..Default::default()
-        },
+    let sensitive_details = BillingCustomerSensitiveDetails {
+        billing_address: customer
+            .address
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?,
+        tax_id: customer
+            .tax_ids
+            .as_ref()
+            .and_then(|tax_ids| tax_ids.data.first())
+            .and_then(|tax_id| tax_id.value.clone()),
     };
 
-    let mut params = CreateBillingPortalSession::new(customer_id);
-    params.flow_data = Some(flow);
-    params.return_url = Some("https://zed.dev/billing");
+    let tax_exempt = customer
+        .tax_exempt
+        .map_or(TaxExemptStatus::None, TaxExemptStatus::from);
+    let customer_email = customer.email.clone();
 
-    let session = BillingPortalSession::create(&stripe_client, params).await?;
+    let billing_customer = find_or_create_billing_customer(
+        app,
+        payment_provider,
+        stripe_account_id,
+        Expandable::Object(Box::new(customer)),
+    )
+    .await?;
 
-    Ok(Json(ManageBillingSubscriptionResponse {
-        billing_portal_session_url: session.url,
-    }))
-}
+    if let Some(billing_customer) = &billing_customer {
+        if billing_customer.tax_exempt != tax_exempt {
+            app.db
+                .update_billing_customer_tax_exempt(billing_customer.id, tax_exempt)
+                .await?;
+        }
 
-const POLL_EVENTS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+        // The email address can also be changed from the billing portal, in
+        // which case we only learn about it here.
+        let user = app.db.get_user_by_id(billing_customer.user_id).await?;
+        let billing_email = customer_email.filter(|customer_email| {
+            user.as_ref().and_then(|user| user.email_address.as_ref()) != Some(customer_email)
+        });
+        if billing_customer.billing_email != billing_email {
+            app.db
+                .update_billing_customer_email(billing_customer.id, billing_email.as_deref())
+                .await?;
+        }
+    }
 
-/// Polls the Stripe events API periodically to reconcile the records in our
-/// database with the data in Stripe.
-pub fn poll_stripe_events_periodically(app: Arc<AppState>) {
-    let Some(stripe_client) = app.stripe_client.clone() else {
-        log::warn!("failed to retrieve Stripe client");
-        return;
+    // We only persist the customer's address and tax ID when we're able to
+    // encrypt them at rest.
+    if let Some(billing_customer) = billing_customer {
+        if app.config.billing_encryption_keys.is_some() {
+            app.db
+                .update_billing_customer_sensitive_details(billing_customer.id, &sensitive_details)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_customer_subscription_event(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    event: stripe::Event,
+) -> anyhow::Result<()> {
+    let EventObject::Subscription(subscription) = event.data.object else {
+        bail!("unexpected event payload for {}", event.id);
     };
 
-    let executor = app.executor.clone();
-    executor.spawn_detached({
-        let executor = executor.clone();
-        async move {
-            loop {
-                poll_stripe_events(&app, &stripe_client).await.log_err();
+    sync_billing_subscription_at(
+        app,
+        rpc_server,
+        payment_provider,
+        stripe_account_id,
+        &PaymentSubscription::try_from(&subscription)?,
+        Some(primitive_date_time_from_timestamp(event.created)?),
+    )
+    .await
+}
 
-                executor.sleep(POLL_EVENTS_INTERVAL).await;
-            }
-        }
-    });
+/// Stores the current state of the subscription, and follows up on changes
+/// to its status.
+///
+/// The provider must act on behalf of the account the subscription is on.
+pub(crate) async fn sync_billing_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    subscription: &PaymentSubscription,
+) -> anyhow::Result<()> {
+    sync_billing_subscription_at(
+        app,
+        rpc_server,
+        payment_provider,
+        stripe_account_id,
+        subscription,
+        None,
+    )
+    .await
 }
 
-async fn poll_stripe_events(
+/// Like [`sync_billing_subscription`], for a subscription whose state the
+/// payment provider reported as of the given time, such as in a webhook
+/// event, so that changes are recorded as happening then.
+async fn sync_billing_subscription_at(
     app: &Arc<AppState>,
-    stripe_client: &stripe::Client,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    subscription: &PaymentSubscription,
+    changed_at: Option<PrimitiveDateTime>,
 ) -> anyhow::Result<()> {
-    let event_types = [
-        EventType::CustomerCreated.to_string(),
-        EventType::CustomerSubscriptionCreated.to_string(),
-        EventType::CustomerSubscriptionUpdated.to_string(),
-        EventType::CustomerSubscriptionPaused.to_string(),
-        EventType::CustomerSubscriptionResumed.to_string(),
-        EventType::CustomerSubscriptionDeleted.to_string(),
-    ]
-    .into_iter()
-    .map(|event_type| {
-        // Calling `to_string` on `stripe::EventType` members gives us a quoted string,
-        // so we need to unquote it.
-        event_type.trim_matches('"').to_string()
-    })
-    .collect::<Vec<_>>();
+    let customer_id =
+        CustomerId::from_str(&subscription.customer_id).context("failed to parse customer ID")?;
+    let billing_customer = find_or_create_billing_customer(
+        app,
+        payment_provider,
+        stripe_account_id,
+        Expandable::Id(customer_id),
+    )
+    .await?
+    .ok_or_else(|| anyhow!("billing customer not found"))?;
 
-    loop {
-        log::info!("retrieving events from Stripe: {}", event_types.join(", "));
+    store_billing_subscription(
+        app,
+        rpc_server,
+        &billing_customer,
+        subscription,
+        None,
+        changed_at,
+    )
+    .await
+}
 
-        let mut params = ListEvents::new();
-        params.types = Some(event_types.clone());
-        params.limit = Some(100);
+/// Returns the payment provider, acting on behalf of the given account.
+pub(crate) fn payment_provider_for_account(
+    app: &AppState,
+    stripe_account_id: Option<&str>,
+) -> anyhow::Result<Arc<dyn PaymentProvider>> {
+    app.payment_provider
+        .as_ref()
+        .ok_or_else(|| anyhow!("payment provider is not configured"))?
+        .for_account(stripe_account_id)
+}
 
-        let events = stripe::Event::list(stripe_client, &params).await?;
-        for event in events.data {
-            match event.type_ {
-                EventType::CustomerCreated => {
-                    handle_customer_event(app, stripe_client, event)
-                        .await
-                        .log_err();
-                }
-                EventType::CustomerSubscriptionCreated
-                | EventType::CustomerSubscriptionUpdated
-                | EventType::CustomerSubscriptionPaused
-                | EventType::CustomerSubscriptionResumed
-                | EventType::CustomerSubscriptionDeleted => {
-                    handle_customer_subscription_event(app, stripe_client, event)
-                        .await
-                        .log_err();
-                }
-                _ => {}
-            }
+/// Stores the current state of the customer's subscription, as reported by
+/// the payment provider, and follows up on changes to its status.
+///
+/// The actor is the user whose request changed the subscription, which is
+/// recorded in the billing audit log. Changes are recorded as happening when
+/// the payment provider says they did, if known, or now.
+async fn store_billing_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    billing_customer: &billing_customer::Model,
+    subscription: &PaymentSubscription,
+    actor_user_id: Option<UserId>,
+    changed_at: Option<PrimitiveDateTime>,
+) -> anyhow::Result<()> {
+    let past_due_grace_period =
+        time::Duration::days(app.server_settings.get().past_due_grace_period_in_days);
+    let status_changed = app
+        .db
+        .upsert_billing_subscription_by_stripe_subscription_id(
+            &billing_subscription_params(
+                &app.config,
+                billing_customer.id,
+                billing_customer.stripe_account_id.as_deref(),
+                subscription,
+            )?,
+            &UpsertBillingSubscriptionOptions {
+                past_due_grace_period,
+                actor_user_id,
+                transitioned_at: changed_at,
+            },
+        )
+        .await?;
+    dunning::reinstate_subscription(app, rpc_server, &subscription.id).await?;
+
+    if subscription.status == StripeSubscriptionStatus::Trialing
+        && billing_customer.payment_method_fingerprint.is_none()
+    {
+        if let Some(payment_method_id) = &subscription.default_payment_method_id {
+            trial_abuse::check_trial_payment_method(app, billing_customer, payment_method_id)
+                .await
+                .log_err();
         }
+    }
 
-        if !events.has_more {
-            break;
+    if status_changed {
+        plan_enforcement::plan_changed(app, billing_customer.user_id)
+            .await
+            .log_err();
+
+        if subscription.status == StripeSubscriptionStatus::Active {
+            credit_referrer(app, billing_customer.user_id)
+                .await
+                .log_err();
+        }
+
+        if let Some(rpc_server) = rpc_server {
+            rpc_server
+                .billing_status_updated(billing_customer.user_id)
+                .await
+                .log_err();
         }
     }
 
     Ok(())
 }
 
-async fn handle_customer_event(
+/// Returns the state of the subscription, as it's stored in
+/// `billing_subscriptions`.
+pub(crate) fn billing_subscription_params(
+    config: &Config,
+    billing_customer_id: BillingCustomerId,
+    stripe_account_id: Option<&str>,
+    subscription: &PaymentSubscription,
+) -> anyhow::Result<CreateBillingSubscriptionParams> {
+    Ok(CreateBillingSubscriptionParams {
+        billing_customer_id,
+        stripe_subscription_id: subscription.id.clone(),
+        stripe_subscription_status: subscription.status,
+        plan: PlanCatalog::from_config(config)?.plan_for_subscription(subscription),
+        seat_count: subscription.seat_count(),
+        stripe_coupon_id: subscription.coupon_id.clone(),
+        stripe_promotion_code_id: subscription.promotion_code_id.clone(),
+        trial_end: subscription.trial_end.map(primitive_date_time),
+        current_period_start: Some(primitive_date_time(subscription.current_period_start)),
+        current_period_end: Some(primitive_date_time(subscription.current_period_end)),
+        cancel_at_period_end: subscription.cancel_at_period_end,
+        canceled_at: subscription.canceled_at.map(primitive_date_time),
+        stripe_account_id: stripe_account_id.map(str::to_string),
+    })
+}
+
+/// Fulfills the language model top-up paid for through the checkout session,
+/// if any, once its payment has gone through.
+///
+/// Payments made with delayed methods, such as bank debits, complete the
+/// session before they succeed, so those top-ups are fulfilled on the
+/// subsequent `checkout.session.async_payment_succeeded` event.
+async fn handle_checkout_session_event(
     app: &Arc<AppState>,
-    stripe_client: &stripe::Client,
     event: stripe::Event,
 ) -> anyhow::Result<()> {
-    let EventObject::Customer(customer) = event.data.object else {
+    let EventObject::CheckoutSession(checkout_session) = event.data.object else {
         bail!("unexpected event payload for {}", event.id);
     };
+    if checkout_session.payment_status != CheckoutSessionPaymentStatus::Paid {
+        return Ok(());
+    }
 
-    find_or_create_billing_customer(app, stripe_client, Expandable::Object(Box::new(customer)))
-        .await?;
+    if let Some(top_up) = app
+        .db
+        .fulfill_language_model_top_up(checkout_session.id.as_str())
+        .await?
+    {
+        log::info!(
+            "fulfilled language model top-up {} of {} tokens for user {}",
+            top_up.id,
+            top_up.tokens,
+            top_up.user_id
+        );
+    }
 
     Ok(())
 }
 
-async fn handle_customer_subscription_event(
+/// Describes the metered usage on newly created invoices, while they can
+/// still be changed.
+async fn handle_invoice_created_event(
     app: &Arc<AppState>,
-    stripe_client: &stripe::Client,
+    payment_provider: &dyn PaymentProvider,
     event: stripe::Event,
 ) -> anyhow::Result<()> {
-    let EventObject::Subscription(subscription) = event.data.object else {
+    let Some(metered_price_id) = app.config.stripe_metered_price_id.as_deref() else {
+        return Ok(());
+    };
+    let EventObject::Invoice(invoice) = event.data.object else {
+        bail!("unexpected event payload for {}", event.id);
+    };
+
+    metered_billing::describe_invoice_usage(app, payment_provider, metered_price_id, &invoice).await
+}
+
+/// Records the outcome of an invoice's payment, for the billing history.
+async fn handle_invoice_event(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    event: stripe::Event,
+) -> anyhow::Result<()> {
+    let EventObject::Invoice(invoice) = event.data.object else {
         bail!("unexpected event payload for {}", event.id);
     };
+    let Some(customer) = invoice.customer else {
+        return Ok(());
+    };
 
     let billing_customer =
-        find_or_create_billing_customer(app, stripe_client, subscription.customer)
+        find_or_create_billing_customer(app, payment_provider, stripe_account_id, customer)
             .await?
             .ok_or_else(|| anyhow!("billing customer not found"))?;
 
+    let (kind, amount_in_cents) = match event.type_ {
+        EventType::InvoicePaid => (BillingInvoiceEventKind::Paid, invoice.amount_paid),
+        EventType::InvoicePaymentFailed => {
+            (BillingInvoiceEventKind::PaymentFailed, invoice.amount_due)
+        }
+        _ => bail!("unexpected event type for {}", event.id),
+    };
+
+    let currency = invoice
+        .currency
+        .map_or_else(String::new, |currency| currency.to_string());
+    let amount_in_cents = amount_in_cents.unwrap_or_default();
+    let occurred_at = primitive_date_time_from_timestamp(event.created)?;
+    // Invoices on an advanced test clock occur in the future, which has no
+    // exchange rates yet.
+    let rate_date = occurred_at.date().min(OffsetDateTime::now_utc().date());
+    let usd_amount = usd_amount_on(app, amount_in_cents, &currency, rate_date).await;
     app.db
-        .upsert_billing_subscription_by_stripe_subscription_id(&CreateBillingSubscriptionParams {
+        .record_billing_invoice_event(&CreateBillingInvoiceEventParams {
             billing_customer_id: billing_customer.id,
-            stripe_subscription_id: subscription.id.to_string(),
-            stripe_subscription_status: subscription.status.into(),
+            stripe_invoice_id: invoice.id.to_string(),
+            stripe_subscription_id: invoice
+                .subscription
+                .as_ref()
+                .map(|subscription| subscription.id().to_string()),
+            kind,
+            billing_reason: invoice
+                .billing_reason
+                .map(|billing_reason| billing_reason.as_str().to_string()),
+            amount_in_cents,
+            currency: currency.clone(),
+            occurred_at,
+            usd_exchange_rate: usd_amount.map(|usd_amount| usd_amount.exchange_rate),
+            amount_in_usd_cents: usd_amount.map(|usd_amount| usd_amount.amount_in_cents),
         })
         .await?;
 
+    if kind == BillingInvoiceEventKind::Paid {
+        // Paying an unpaid subscription's invoice makes it active again, which
+        // reinstates it if it was suspended. Its own event for that may not
+        // have been processed yet.
+        if let Some(stripe_subscription_id) = invoice
+            .subscription
+            .as_ref()
+            .map(|subscription| subscription.id())
+        {
+            let is_unpaid = app
+                .db
+                .get_billing_subscription_by_stripe_subscription_id(stripe_subscription_id.as_str())
+                .await?
+                .map_or(false, |subscription| {
+                    subscription.stripe_subscription_status == StripeSubscriptionStatus::Unpaid
+                });
+            if is_unpaid {
+                let subscription = payment_provider
+                    .get_subscription(stripe_subscription_id.as_str())
+                    .await?;
+                sync_billing_subscription(
+                    app,
+                    rpc_server,
+                    payment_provider,
+                    stripe_account_id,
+                    &subscription,
+                )
+                .await?;
+            }
+        }
+    }
+
+    if kind == BillingInvoiceEventKind::PaymentFailed {
+        let stripe_subscription_id = invoice
+            .subscription
+            .as_ref()
+            .map(|subscription| subscription.id().to_string());
+        let grace_period_ends_at = app
+            .db
+            .get_billing_subscriptions(billing_customer.user_id)
+            .await?
+            .into_iter()
+            .find(|subscription| {
+                Some(&subscription.stripe_subscription_id) == stripe_subscription_id.as_ref()
+            })
+            .and_then(|subscription| subscription.grace_period_ends_at)
+            .map(|date| date.assume_utc());
+        send_billing_notification(
+            app,
+            billing_customer.user_id,
+            &BillingNotification::PaymentFailed {
+                stripe_invoice_id: invoice.id.to_string(),
+                amount_in_cents,
+                currency,
+                attempt_count: invoice.attempt_count.unwrap_or_default(),
+                next_payment_attempt: invoice
+                    .next_payment_attempt
+                    .map(OffsetDateTime::from_unix_timestamp)
+                    .transpose()?,
+                grace_period_ends_at,
+            },
+        )
+        .await?;
+    }
+
     Ok(())
 }
 
+fn primitive_date_time_from_timestamp(
+    timestamp: stripe::Timestamp,
+) -> anyhow::Result<PrimitiveDateTime> {
+    Ok(primitive_date_time(OffsetDateTime::from_unix_timestamp(
+        timestamp,
+    )?))
+}
+
+fn primitive_date_time(date_time: OffsetDateTime) -> PrimitiveDateTime {
+    PrimitiveDateTime::new(date_time.date(), date_time.time())
+}
+
 impl From<SubscriptionStatus> for StripeSubscriptionStatus {
     fn from(value: SubscriptionStatus) -> Self {
         match value {
@@ -336,10 +2456,22 @@ impl From<SubscriptionStatus> for StripeSubscriptionStatus {
     }
 }
 
-/// Finds or creates a billing customer using the provided customer.
+impl From<CustomerTaxExempt> for TaxExemptStatus {
+    fn from(value: CustomerTaxExempt) -> Self {
+        match value {
+            CustomerTaxExempt::None => Self::None,
+            CustomerTaxExempt::Exempt => Self::Exempt,
+            CustomerTaxExempt::Reverse => Self::Reverse,
+        }
+    }
+}
+
+/// Finds or creates a billing customer using the provided customer, which is
+/// on the given account.
 async fn find_or_create_billing_customer(
     app: &Arc<AppState>,
-    stripe_client: &stripe::Client,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
     customer_or_id: Expandable<Customer>,
 ) -> anyhow::Result<Option<billing_customer::Model>> {
     let customer_id = match &customer_or_id {
@@ -357,14 +2489,17 @@ async fn find_or_create_billing_customer(
         return Ok(Some(billing_customer));
     }
 
-    // If all we have is a customer ID, resolve it to a full customer record by
-    // hitting the Stripe API.
-    let customer = match customer_or_id {
-        Expandable::Id(id) => Customer::retrieve(&stripe_client, &id, &[]).await?,
-        Expandable::Object(customer) => *customer,
+    // If all we have is a customer ID, look up their email address with the
+    // payment provider.
+    let (customer_id, email) = match customer_or_id {
+        Expandable::Id(id) => {
+            let email = payment_provider.get_customer_email(id.as_str()).await?;
+            (id, email)
+        }
+        Expandable::Object(customer) => (customer.id, customer.email),
     };
 
-    let Some(email) = customer.email else {
+    let Some(email) = email else {
         return Ok(None);
     };
 
@@ -376,9 +2511,513 @@ async fn find_or_create_billing_customer(
         .db
         .create_billing_customer(&CreateBillingCustomerParams {
             user_id: user.id,
-            stripe_customer_id: customer.id.to_string(),
+            stripe_customer_id: customer_id.to_string(),
+            stripe_account_id: stripe_account_id.map(str::to_string),
         })
         .await?;
+    apply_pending_referrer_credits(app, user.id).await.log_err();
 
     Ok(Some(billing_customer))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_enums_match_database_enums() {
+        use sea_orm::Iterable as _;
+
+        for plan in SubscriptionPlan::iter() {
+            assert_eq!(
+                serde_json::to_value(plan).unwrap(),
+                serde_json::to_value(collab_api_client::SubscriptionPlan::from(plan)).unwrap()
+            );
+            assert_eq!(
+                SubscriptionPlan::from(collab_api_client::SubscriptionPlan::from(plan)),
+                plan
+            );
+        }
+        for status in StripeSubscriptionStatus::iter() {
+            assert_eq!(
+                serde_json::to_value(status).unwrap(),
+                serde_json::to_value(collab_api_client::SubscriptionStatus::from(status)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_plan_catalog() {
+        let catalog = PlanCatalog::parse(Some("team:price_team"), Some("price_legacy")).unwrap();
+        assert_eq!(
+            catalog.price_id(SubscriptionPlan::Pro),
+            Some("price_legacy")
+        );
+        assert_eq!(catalog.price_id(SubscriptionPlan::Team), Some("price_team"));
+        assert_eq!(catalog.price_id(SubscriptionPlan::Free), None);
+        assert_eq!(
+            catalog.plan_for_price_id("price_team"),
+            Some(SubscriptionPlan::Team)
+        );
+
+        let catalog =
+            PlanCatalog::parse(Some("pro:price_pro, team:price_team"), Some("price_legacy"))
+                .unwrap();
+        assert_eq!(catalog.price_id(SubscriptionPlan::Pro), Some("price_pro"));
+
+        assert!(PlanCatalog::parse(Some("enterprise:price_enterprise"), None).is_err());
+    }
+
+    #[test]
+    fn test_plan_catalog_currencies() {
+        let catalog = PlanCatalog::parse(
+            Some("pro:price_pro, pro/EUR:price_pro_eur, team/gbp:price_team_gbp"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(catalog.price_id(SubscriptionPlan::Pro), Some("price_pro"));
+        assert_eq!(
+            catalog.price_id_in_currency(SubscriptionPlan::Pro, "eur"),
+            Some("price_pro_eur")
+        );
+        assert_eq!(
+            catalog.price_id_in_currency(SubscriptionPlan::Team, "gbp"),
+            Some("price_team_gbp")
+        );
+        assert_eq!(catalog.price_id(SubscriptionPlan::Team), None);
+        assert_eq!(
+            catalog.plan_for_price_id("price_pro_eur"),
+            Some(SubscriptionPlan::Pro)
+        );
+
+        assert!(PlanCatalog::parse(Some("pro/euro:price_pro_eur"), None).is_err());
+    }
+
+    #[test]
+    fn test_plan_catalog_connected_accounts() {
+        let catalog = PlanCatalog::parse(
+            Some("pro:price_pro, pro/brl@acct_123:price_pro_brl, pro@acct_123:price_pro_usd"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(catalog.price_id(SubscriptionPlan::Pro), Some("price_pro"));
+        assert_eq!(
+            catalog.price_id_in_currency(SubscriptionPlan::Pro, "brl"),
+            None
+        );
+        assert_eq!(
+            catalog.price_id_on_account(SubscriptionPlan::Pro, "brl", Some("acct_123")),
+            Some("price_pro_brl")
+        );
+        assert_eq!(
+            catalog.price_id_on_account(SubscriptionPlan::Pro, "usd", Some("acct_123")),
+            Some("price_pro_usd")
+        );
+        assert_eq!(
+            catalog.price_id_on_account(SubscriptionPlan::Pro, "usd", Some("acct_456")),
+            None
+        );
+        assert_eq!(
+            catalog.plan_for_price_id("price_pro_brl"),
+            Some(SubscriptionPlan::Pro)
+        );
+    }
+
+    #[test]
+    fn test_checkout_currency() {
+        assert_eq!(
+            requested_currency(Some(" EUR "), Some("GB")),
+            Some("eur".into())
+        );
+        assert_eq!(requested_currency(None, Some("de")), Some("eur".into()));
+        assert_eq!(requested_currency(Some(""), Some("GB")), Some("gbp".into()));
+        assert_eq!(requested_currency(None, Some("US")), None);
+        assert_eq!(requested_currency(None, None), None);
+
+        assert_eq!(checkout_currency(None, None, false), "usd");
+        assert_eq!(checkout_currency(None, Some("eur".into()), false), "eur");
+        // Customers keep paying in the currency they first paid in.
+        assert_eq!(
+            checkout_currency(Some("gbp".into()), Some("eur".into()), false),
+            "gbp"
+        );
+        // The metered price is only in USD.
+        assert_eq!(checkout_currency(None, Some("eur".into()), true), "usd");
+    }
+
+    #[test]
+    fn test_checkout_discount() {
+        assert!(checkout_discount(None, None).is_none());
+
+        assert_eq!(
+            checkout_discount(None, Some("coupon_referral".into())),
+            Some(CheckoutDiscount::Coupon {
+                coupon_id: "coupon_referral".into()
+            })
+        );
+        assert_eq!(
+            checkout_discount(Some("promo_launch".into()), Some("coupon_referral".into())),
+            Some(CheckoutDiscount::PromotionCode {
+                promotion_code_id: "promo_launch".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let key = idempotency_key("create_customer", UserId(1), &("a", 1)).unwrap();
+        assert!(key.starts_with("create_customer:1:"));
+        assert_eq!(
+            key,
+            idempotency_key("create_customer", UserId(1), &("a", 1)).unwrap()
+        );
+        assert_ne!(
+            key,
+            idempotency_key("create_customer", UserId(2), &("a", 1)).unwrap()
+        );
+        assert_ne!(
+            key,
+            idempotency_key("create_customer", UserId(1), &("a", 2)).unwrap()
+        );
+        assert_ne!(
+            key,
+            idempotency_key("create_checkout_session", UserId(1), &("a", 1)).unwrap()
+        );
+        // Stripe rejects keys longer than 255 characters.
+        assert!(key.len() <= 255);
+
+        let key = attempt_idempotency_key("create_checkout_session", UserId(1));
+        assert!(key.starts_with("create_checkout_session:1:"));
+        assert_ne!(
+            key,
+            attempt_idempotency_key("create_checkout_session", UserId(1))
+        );
+        assert!(key.len() <= 255);
+    }
+
+    #[test]
+    fn test_is_valid_email() {
+        assert!(is_valid_email("finance@example.com"));
+        assert!(is_valid_email("billing+zed@sub.example.co.uk"));
+        assert!(!is_valid_email("finance"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("finance@example"));
+        assert!(!is_valid_email("finance@example.com."));
+        assert!(!is_valid_email("finance@@example.com"));
+        assert!(!is_valid_email("fin ance@example.com"));
+    }
+
+    #[test]
+    fn test_billing_redirect_url() {
+        let allowed_origins = Some("https://staging.zed.dev, http://localhost:3000");
+        assert_eq!(
+            billing_redirect_url(None, allowed_origins, DEFAULT_RETURN_URL).unwrap(),
+            DEFAULT_RETURN_URL
+        );
+        assert_eq!(
+            billing_redirect_url(
+                Some("https://staging.zed.dev/billing"),
+                allowed_origins,
+                DEFAULT_RETURN_URL
+            )
+            .unwrap(),
+            "https://staging.zed.dev/billing"
+        );
+        assert_eq!(
+            billing_redirect_url(
+                Some("http://localhost:3000/billing/success"),
+                allowed_origins,
+                DEFAULT_SUCCESS_URL
+            )
+            .unwrap(),
+            "http://localhost:3000/billing/success"
+        );
+
+        // Only the exact origins are allowed.
+        for url in [
+            "https://evil.example.com/billing",
+            "http://staging.zed.dev/billing",
+            "http://localhost:3001/billing",
+            "https://staging.zed.dev.example.com/billing",
+            "javascript:alert(1)",
+            "/billing",
+        ] {
+            assert!(
+                billing_redirect_url(Some(url), allowed_origins, DEFAULT_RETURN_URL).is_err(),
+                "{url} should not be allowed"
+            );
+        }
+        assert!(billing_redirect_url(
+            Some("https://staging.zed.dev/billing"),
+            None,
+            DEFAULT_RETURN_URL
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_locales() {
+        assert_eq!(normalize_locale("fr_ca").as_deref(), Some("fr-CA"));
+        assert_eq!(normalize_locale(" DE ").as_deref(), Some("de"));
+        assert_eq!(normalize_locale("zh-Hant-TW").as_deref(), Some("zh"));
+        assert_eq!(normalize_locale("*"), None);
+        assert_eq!(normalize_locale(""), None);
+
+        assert_eq!(
+            stripe_locale::<CheckoutSessionLocale>("fr-CA"),
+            Some(CheckoutSessionLocale::FrCa)
+        );
+        assert_eq!(
+            stripe_locale::<CheckoutSessionLocale>("de-AT"),
+            Some(CheckoutSessionLocale::De)
+        );
+        assert_eq!(stripe_locale::<CheckoutSessionLocale>("tlh"), None);
+    }
+
+    #[test]
+    fn test_validate_plan_change() {
+        use ManageSubscriptionIntent::*;
+        use SubscriptionPlan::*;
+
+        assert_eq!(
+            validate_plan_change(Upgrade, Pro, Some(Team)).unwrap(),
+            Team
+        );
+        assert_eq!(
+            validate_plan_change(Downgrade, Team, Some(Pro)).unwrap(),
+            Pro
+        );
+        assert!(validate_plan_change(Upgrade, Team, Some(Pro)).is_err());
+        assert!(validate_plan_change(Downgrade, Pro, Some(Team)).is_err());
+        assert!(validate_plan_change(Upgrade, Pro, Some(Pro)).is_err());
+        assert!(validate_plan_change(Upgrade, Pro, None).is_err());
+        // Dropping to the free plan is a cancellation.
+        assert!(validate_plan_change(Downgrade, Pro, Some(Free)).is_err());
+        assert!(validate_plan_change(UpdatePaymentMethod, Pro, Some(Team)).is_err());
+        assert!(validate_plan_change(CancelAtPeriodEnd, Pro, Some(Team)).is_err());
+        assert!(validate_plan_change(Resume, Pro, Some(Team)).is_err());
+        assert!(validate_plan_change(Portal, Pro, Some(Team)).is_err());
+    }
+
+    #[test]
+    fn test_stripe_event_cursor_name() {
+        assert_eq!(stripe_event_cursor_name(None), "billing");
+        assert_eq!(
+            stripe_event_cursor_name(Some("acct_123")),
+            "billing:acct_123"
+        );
+    }
+
+    #[test]
+    fn test_stripe_events_created_after() {
+        use time::macros::datetime;
+
+        assert_eq!(stripe_events_created_after(None), None);
+        assert_eq!(
+            stripe_events_created_after(Some(datetime!(2024-09-01 0:10 UTC))),
+            Some(datetime!(2024-09-01 0:05 UTC).unix_timestamp())
+        );
+    }
+
+    #[test]
+    fn test_subscription_access_until() {
+        use time::macros::datetime;
+
+        let subscription = billing_subscription::Model {
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            current_period_end: Some(datetime!(2024-09-01 0:00)),
+            ..Default::default()
+        };
+        assert_eq!(
+            BillingSubscription::from(subscription.clone()).access_until,
+            None
+        );
+
+        let subscription = billing_subscription::Model {
+            cancel_at_period_end: true,
+            ..subscription
+        };
+        assert_eq!(
+            BillingSubscription::from(subscription.clone()).access_until,
+            Some(datetime!(2024-09-01 0:00 UTC))
+        );
+
+        // Subscriptions that were canceled outright have already ended.
+        let subscription = billing_subscription::Model {
+            stripe_subscription_status: StripeSubscriptionStatus::Canceled,
+            ..subscription
+        };
+        assert_eq!(BillingSubscription::from(subscription).access_until, None);
+    }
+
+    #[test]
+    fn test_subscription_grace_period() {
+        use time::macros::datetime;
+
+        let subscription = billing_subscription::Model {
+            stripe_subscription_status: StripeSubscriptionStatus::PastDue,
+            grace_period_ends_at: Some(datetime!(2024-09-08 12:00)),
+            ..Default::default()
+        };
+        let json = billing_subscription_json(subscription.clone(), datetime!(2024-09-07 12:00 UTC));
+        assert_eq!(
+            json.grace_period_ends_at,
+            Some(datetime!(2024-09-08 12:00 UTC))
+        );
+        assert_eq!(json.grace_period_remaining_seconds, Some(24 * 60 * 60));
+
+        let json = billing_subscription_json(subscription, datetime!(2024-09-09 12:00 UTC));
+        assert_eq!(json.grace_period_remaining_seconds, Some(0));
+
+        let subscription = billing_subscription::Model {
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            ..Default::default()
+        };
+        let json = billing_subscription_json(subscription, datetime!(2024-09-07 12:00 UTC));
+        assert_eq!(json.grace_period_ends_at, None);
+        assert_eq!(json.grace_period_remaining_seconds, None);
+    }
+
+    #[test]
+    fn test_seat_change() {
+        assert_eq!(SeatChange::Add(2).apply(3).unwrap(), 5);
+        assert_eq!(SeatChange::Remove(2).apply(3).unwrap(), 1);
+
+        // Subscriptions keep at least one seat, and changes must change something.
+        assert!(SeatChange::Remove(3).apply(3).is_err());
+        assert!(SeatChange::Add(0).apply(3).is_err());
+        assert!(SeatChange::Remove(0).apply(3).is_err());
+    }
+
+    #[test]
+    fn test_plan_price_cache() {
+        use crate::payment_provider::FakePaymentProvider;
+
+        let payment_provider = FakePaymentProvider::default();
+        let price = PaymentPrice {
+            unit_amount: Some(2000),
+            currency: Some("usd".into()),
+        };
+        payment_provider
+            .state()
+            .prices
+            .insert("price_pro".into(), price.clone());
+
+        let cache = PlanPriceCache::default();
+        assert_eq!(
+            futures::executor::block_on(cache.get(&payment_provider, "price_pro")).unwrap(),
+            price
+        );
+
+        // Until the cached price expires, it's served without retrieving it.
+        payment_provider.state().prices.clear();
+        assert_eq!(
+            futures::executor::block_on(cache.get(&payment_provider, "price_pro")).unwrap(),
+            price
+        );
+        assert!(futures::executor::block_on(cache.get(&payment_provider, "price_team")).is_err());
+    }
+
+    #[gpui::test]
+    async fn test_create_billing_subscription(cx: &mut gpui::TestAppContext) {
+        use crate::db::{tests::TestDb, NewUserParams};
+        use crate::executor::Executor;
+        use crate::payment_provider::FakePaymentProvider;
+        use crate::tests::TestServer;
+
+        let test_db = TestDb::sqlite(cx.executor());
+        let live_kit_server = live_kit_client::TestServer::create(
+            "http://livekit.billing.test".into(),
+            "devkey-billing".into(),
+            "secret-billing".into(),
+            cx.executor(),
+        )
+        .unwrap();
+        let payment_provider = FakePaymentProvider::default();
+        let app = TestServer::build_app_state_with(
+            &test_db,
+            &live_kit_server,
+            Executor::Deterministic(cx.executor()),
+            |app| {
+                app.config.stripe_price_id = Some("price_pro".into());
+                app.payment_provider = Some(Arc::new(payment_provider.clone()));
+            },
+        )
+        .await;
+        let user_id = app
+            .db
+            .create_user(
+                "user@example.com",
+                false,
+                NewUserParams {
+                    github_login: "user".into(),
+                    github_user_id: 1,
+                },
+            )
+            .await
+            .unwrap()
+            .user_id;
+
+        let body = CreateBillingSubscriptionBody {
+            github_user_id: 1,
+            plan: collab_api_client::SubscriptionPlan::Pro,
+            seat_count: None,
+            promo_code: None,
+            currency: None,
+            country: None,
+            locale: None,
+            success_url: None,
+        };
+        let response =
+            create_billing_subscription(Extension(app.clone()), extract::Json(body.clone()))
+                .await
+                .unwrap();
+
+        // The user becomes a customer, who's sent to checkout for the plan.
+        let billing_customer = app
+            .db
+            .get_billing_customer_by_user_id(user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        {
+            let state = payment_provider.state();
+            assert_eq!(
+                state.customers[&billing_customer.stripe_customer_id]
+                    .email
+                    .as_deref(),
+                Some("user@example.com")
+            );
+            assert_eq!(state.checkout_sessions.len(), 1);
+            let checkout_session = &state.checkout_sessions[0];
+            assert_eq!(
+                checkout_session.customer,
+                CheckoutCustomer::Existing {
+                    customer_id: billing_customer.stripe_customer_id.clone()
+                }
+            );
+            assert_eq!(
+                checkout_session.line_items,
+                vec![CheckoutLineItem {
+                    price_id: "price_pro".into(),
+                    quantity: Some(1),
+                }]
+            );
+        }
+
+        // Retrying the request reuses the customer, but starts a new checkout
+        // session, since the previous one may have been completed.
+        let retried_response =
+            create_billing_subscription(Extension(app.clone()), extract::Json(body))
+                .await
+                .unwrap();
+        assert_ne!(
+            retried_response.checkout_session_url,
+            response.checkout_session_url
+        );
+        assert_eq!(payment_provider.state().customers.len(), 1);
+        assert_eq!(payment_provider.state().checkout_sessions.len(), 2);
+
+        live_kit_server.teardown().unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, routing::get, Extension, Json, Router};
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::api::admin_user;
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::{BillingAuditLogEntryId, BillingSubscriptionId};
+use crate::{AppState, Error, Result};
+
+/// The number of entries returned by default.
+const DEFAULT_LIMIT: u64 = 100;
+/// The most entries that can be requested at once.
+const MAX_LIMIT: u64 = 1000;
+
+pub fn router() -> Router {
+    Router::new().route("/admin/billing_audit_log", get(get_billing_audit_log))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingAuditLogParams {
+    /// The GitHub user ID of the admin viewing the log.
+    github_user_id: i32,
+    /// The GitHub user ID of the user to only return entries about.
+    user_github_user_id: Option<i32>,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingAuditLogEntryJson {
+    id: BillingAuditLogEntryId,
+    github_login: Option<String>,
+    /// Who made the change, or `None` if it was made by the payment provider
+    /// or by us.
+    actor_github_login: Option<String>,
+    event: BillingAuditEvent,
+    billing_subscription_id: Option<BillingSubscriptionId>,
+    previous_state: Option<serde_json::Value>,
+    state: Option<serde_json::Value>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingAuditLogResponse {
+    entries: Vec<BillingAuditLogEntryJson>,
+}
+
+/// Returns the most recent changes to users' billing, newest first, for
+/// support investigations.
+async fn get_billing_audit_log(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingAuditLogParams>,
+) -> Result<Json<GetBillingAuditLogResponse>> {
+    admin_user(&app, params.github_user_id, "view the billing audit log").await?;
+    let user_id = match params.user_github_user_id {
+        Some(github_user_id) => Some(
+            app.db
+                .get_user_by_github_user_id(github_user_id)
+                .await?
+                .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "user not found".into()))?
+                .id,
+        ),
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let entries = app.db.get_billing_audit_log(user_id, limit).await?;
+    let logins_by_id = app
+        .db
+        .get_users_by_ids(
+            entries
+                .iter()
+                .flat_map(|entry| [Some(entry.user_id), entry.actor_user_id])
+                .flatten()
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user.github_login))
+        .collect::<HashMap<_, _>>();
+
+    let parse = |state: Option<String>| state.and_then(|state| serde_json::from_str(&state).ok());
+    Ok(Json(GetBillingAuditLogResponse {
+        entries: entries
+            .into_iter()
+            .map(|entry| BillingAuditLogEntryJson {
+                id: entry.id,
+                github_login: logins_by_id.get(&entry.user_id).cloned(),
+                actor_github_login: entry
+                    .actor_user_id
+                    .and_then(|actor_user_id| logins_by_id.get(&actor_user_id).cloned()),
+                event: entry.event,
+                billing_subscription_id: entry.billing_subscription_id,
+                previous_state: parse(entry.previous_state),
+                state: parse(entry.state),
+                created_at: entry.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
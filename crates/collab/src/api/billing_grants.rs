@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::api::admin_user;
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::complimentary_subscription::{self, ComplimentarySubscriptionKind};
+use crate::db::{
+    ComplimentarySubscriptionId, CreateBillingAuditLogEntryParams,
+    CreateComplimentarySubscriptionParams, User, UserId,
+};
+use crate::plan_enforcement;
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/admin/billing/grants",
+            get(get_billing_grants).post(grant_billing),
+        )
+        .route("/admin/billing/grants/:id", delete(revoke_billing_grant))
+}
+
+#[derive(Debug, Deserialize)]
+struct GrantBillingBody {
+    /// The GitHub user ID of the admin making the grant.
+    github_user_id: i32,
+    grantee_github_user_id: i32,
+    plan: SubscriptionPlan,
+    kind: ComplimentarySubscriptionKind,
+    reason: Option<String>,
+    /// When the grant ends. Grants without an expiry last until they're
+    /// revoked.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingGrantJson {
+    id: ComplimentarySubscriptionId,
+    user_id: UserId,
+    plan: SubscriptionPlan,
+    kind: ComplimentarySubscriptionKind,
+    reason: Option<String>,
+    granted_by_github_login: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    granted_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    revoked_at: Option<OffsetDateTime>,
+    active: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingGrantResponse {
+    grant: BillingGrantJson,
+}
+
+/// Grants a user a plan without going through Stripe, such as for staff,
+/// partners, and giveaways.
+async fn grant_billing(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    Json(body): Json<GrantBillingBody>,
+) -> Result<Json<BillingGrantResponse>> {
+    let admin = admin_user(
+        &app,
+        body.github_user_id,
+        "grant complimentary subscriptions",
+    )
+    .await?;
+    let grantee = app
+        .db
+        .get_user_by_github_user_id(body.grantee_github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    if body.plan == SubscriptionPlan::Free {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "the free plan can't be granted".into(),
+        ))?
+    }
+    if body
+        .expires_at
+        .map_or(false, |expires_at| expires_at <= OffsetDateTime::now_utc())
+    {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "the grant must expire in the future".into(),
+        ))?
+    }
+
+    let grant = app
+        .db
+        .create_complimentary_subscription(&CreateComplimentarySubscriptionParams {
+            user_id: grantee.id,
+            plan: body.plan,
+            kind: body.kind,
+            reason: body.reason,
+            granted_by_user_id: admin.id,
+            expires_at: body
+                .expires_at
+                .map(|expires_at| PrimitiveDateTime::new(expires_at.date(), expires_at.time())),
+        })
+        .await?;
+    log::info!(
+        "admin {} granted user {} a complimentary {} subscription",
+        admin.github_login,
+        grantee.id,
+        grant.plan.as_str()
+    );
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: grantee.id,
+            actor_user_id: Some(admin.id),
+            event: BillingAuditEvent::BillingGrantCreated,
+            billing_subscription_id: None,
+            previous_state: None,
+            state: Some(
+                serde_json::json!({
+                    "grant_id": grant.id,
+                    "plan": grant.plan,
+                    "kind": grant.kind,
+                    "reason": grant.reason,
+                    "expires_at": grant
+                        .expires_at
+                        .map(|expires_at| expires_at.assume_utc().unix_timestamp()),
+                })
+                .to_string(),
+            ),
+        })
+        .await?;
+
+    billing_status_updated(&app, rpc_server.as_ref(), grantee.id).await;
+    Ok(Json(BillingGrantResponse {
+        grant: billing_grant_json(grant, Some(&admin), OffsetDateTime::now_utc()),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct RevokeBillingGrantParams {
+    /// The GitHub user ID of the admin revoking the grant.
+    github_user_id: i32,
+}
+
+/// Revokes a complimentary subscription, ending it right away.
+async fn revoke_billing_grant(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    Path(id): Path<ComplimentarySubscriptionId>,
+    Query(params): Query<RevokeBillingGrantParams>,
+) -> Result<Json<BillingGrantResponse>> {
+    let admin = admin_user(
+        &app,
+        params.github_user_id,
+        "grant complimentary subscriptions",
+    )
+    .await?;
+    let Some(grant) = app
+        .db
+        .revoke_complimentary_subscription(id, admin.id)
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "no unrevoked grant found".into(),
+        ))?
+    };
+    log::info!(
+        "admin {} revoked complimentary subscription {} of user {}",
+        admin.github_login,
+        grant.id,
+        grant.user_id
+    );
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: grant.user_id,
+            actor_user_id: Some(admin.id),
+            event: BillingAuditEvent::BillingGrantRevoked,
+            billing_subscription_id: None,
+            previous_state: None,
+            state: Some(serde_json::json!({ "grant_id": grant.id }).to_string()),
+        })
+        .await?;
+
+    billing_status_updated(&app, rpc_server.as_ref(), grant.user_id).await;
+    let granted_by = app.db.get_user_by_id(grant.granted_by_user_id).await?;
+    Ok(Json(BillingGrantResponse {
+        grant: billing_grant_json(grant, granted_by.as_ref(), OffsetDateTime::now_utc()),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingGrantsParams {
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingGrantsResponse {
+    grants: Vec<BillingGrantJson>,
+}
+
+/// Returns every complimentary subscription granted to the user, including
+/// those that have ended.
+async fn get_billing_grants(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingGrantsParams>,
+) -> Result<Json<GetBillingGrantsResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let grants = app.db.get_complimentary_subscriptions(user.id).await?;
+    let users = app
+        .db
+        .get_users_by_ids(
+            grants
+                .iter()
+                .map(|grant| grant.granted_by_user_id)
+                .collect(),
+        )
+        .await?;
+
+    let now = OffsetDateTime::now_utc();
+    Ok(Json(GetBillingGrantsResponse {
+        grants: grants
+            .into_iter()
+            .map(|grant| {
+                let granted_by = users
+                    .iter()
+                    .find(|user| user.id == grant.granted_by_user_id);
+                billing_grant_json(grant, granted_by, now)
+            })
+            .collect(),
+    }))
+}
+
+/// Follows up on a change to the user's plan.
+async fn billing_status_updated(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    user_id: UserId,
+) {
+    plan_enforcement::plan_changed(app, user_id).await.log_err();
+    if let Some(rpc_server) = rpc_server {
+        rpc_server.billing_status_updated(user_id).await.log_err();
+    }
+}
+
+fn billing_grant_json(
+    grant: complimentary_subscription::Model,
+    granted_by: Option<&User>,
+    now: OffsetDateTime,
+) -> BillingGrantJson {
+    BillingGrantJson {
+        id: grant.id,
+        user_id: grant.user_id,
+        plan: grant.plan,
+        kind: grant.kind,
+        active: grant.is_active_at(PrimitiveDateTime::new(now.date(), now.time())),
+        reason: grant.reason,
+        granted_by_github_login: granted_by.map(|user| user.github_login.clone()),
+        granted_at: grant.created_at.assume_utc(),
+        expires_at: grant.expires_at.map(PrimitiveDateTime::assume_utc),
+        revoked_at: grant.revoked_at.map(PrimitiveDateTime::assume_utc),
+    }
+}
@@ -0,0 +1,536 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{extract::Query, http::StatusCode, routing::get, Extension, Json, Router};
+use collab_api_client::CurrencyFormat;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::api::admin_user;
+use crate::currency::{self, currency_format};
+use crate::db::billing_invoice_event::{self, BillingInvoiceEventKind};
+use crate::db::billing_subscription::{self, StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::{billing_subscription_transition, referral, BillingSubscriptionId};
+use crate::{AppState, Error, Result};
+
+/// The billing reason of invoices created when a subscription renews.
+const RENEWAL_BILLING_REASON: &str = "subscription_cycle";
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/billing/history", get(get_billing_history))
+        .route(
+            "/billing/invoice_line_items",
+            get(get_billing_invoice_line_items),
+        )
+        .route(
+            "/admin/billing/subscriptions_at",
+            get(get_billing_subscriptions_at),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingHistoryParams {
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingHistoryResponse {
+    entries: Vec<BillingHistoryEntry>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BillingHistoryEntryKind {
+    Subscribed,
+    Renewed,
+    PaymentFailed,
+    PlanChanged,
+    Canceled,
+    CreditApplied,
+}
+
+/// An entry in a user's billing activity feed.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BillingHistoryEntry {
+    kind: BillingHistoryEntryKind,
+    #[serde(with = "time::serde::rfc3339")]
+    occurred_at: OffsetDateTime,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<SubscriptionPlan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount_in_cents: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<String>,
+    /// How to format the amount, for the user's locale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency_format: Option<CurrencyFormat>,
+}
+
+/// Returns the user's billing activity, newest first.
+async fn get_billing_history(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingHistoryParams>,
+) -> Result<Json<GetBillingHistoryResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let mut subscriptions = Vec::new();
+    for subscription in app.db.get_billing_subscriptions(user.id).await? {
+        let transitions = app
+            .db
+            .get_billing_subscription_transitions(subscription.id)
+            .await?;
+        subscriptions.push((subscription, transitions));
+    }
+    let invoice_events = app.db.get_billing_invoice_events(user.id).await?;
+    let credited_referrals = app.db.get_credited_referrals_for_referrer(user.id).await?;
+
+    let mut entries = billing_history(
+        &subscriptions,
+        &invoice_events,
+        &credited_referrals,
+        app.config.stripe_referral_credit_in_cents,
+    );
+    for entry in &mut entries {
+        entry.currency_format = entry
+            .currency
+            .as_deref()
+            .map(|currency| currency_format(currency, user.locale.as_deref()));
+    }
+
+    Ok(Json(GetBillingHistoryResponse { entries }))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingInvoiceLineItemsParams {
+    stripe_invoice_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingInvoiceLineItemsResponse {
+    line_items: Vec<BillingInvoiceLineItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingInvoiceLineItem {
+    stripe_invoice_line_item_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    period_start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    period_end: OffsetDateTime,
+    quantity: i64,
+    description: String,
+}
+
+/// Returns the metered usage lines we described on a Stripe invoice, for
+/// support to explain a customer's bill with.
+async fn get_billing_invoice_line_items(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingInvoiceLineItemsParams>,
+) -> Result<Json<GetBillingInvoiceLineItemsResponse>> {
+    let line_items = app
+        .db
+        .get_billing_invoice_line_items(&params.stripe_invoice_id)
+        .await?;
+
+    Ok(Json(GetBillingInvoiceLineItemsResponse {
+        line_items: line_items
+            .into_iter()
+            .map(|line_item| BillingInvoiceLineItem {
+                stripe_invoice_line_item_id: line_item.stripe_invoice_line_item_id,
+                period_start: line_item.period_start.assume_utc(),
+                period_end: line_item.period_end.assume_utc(),
+                quantity: line_item.quantity,
+                description: line_item.description,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingSubscriptionsAtParams {
+    /// The GitHub user ID of the admin making the request.
+    github_user_id: i32,
+    /// The GitHub user ID of the user whose subscriptions to return.
+    user_github_user_id: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingSubscriptionsAtResponse {
+    subscriptions: Vec<BillingSubscriptionSnapshotJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingSubscriptionSnapshotJson {
+    id: BillingSubscriptionId,
+    stripe_subscription_id: String,
+    status: StripeSubscriptionStatus,
+    plan: Option<SubscriptionPlan>,
+    #[serde(with = "time::serde::rfc3339")]
+    transitioned_at: OffsetDateTime,
+}
+
+/// Returns the state of the user's subscriptions at a point in the past, for
+/// support to answer questions about what a user had access to when.
+async fn get_billing_subscriptions_at(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingSubscriptionsAtParams>,
+) -> Result<Json<GetBillingSubscriptionsAtResponse>> {
+    admin_user(
+        &app,
+        params.github_user_id,
+        "view past billing subscriptions",
+    )
+    .await?;
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.user_github_user_id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "user not found".into()))?;
+
+    let at = params.at.to_offset(time::UtcOffset::UTC);
+    let snapshots = app
+        .db
+        .get_billing_subscriptions_at(user.id, PrimitiveDateTime::new(at.date(), at.time()))
+        .await?;
+
+    Ok(Json(GetBillingSubscriptionsAtResponse {
+        subscriptions: snapshots
+            .into_iter()
+            .map(|snapshot| BillingSubscriptionSnapshotJson {
+                id: snapshot.billing_subscription_id,
+                stripe_subscription_id: snapshot.stripe_subscription_id,
+                status: snapshot.stripe_subscription_status,
+                plan: snapshot.plan,
+                transitioned_at: snapshot.transitioned_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
+
+/// Merges the subscription transitions, invoice events, and referral credits
+/// of a user into a single timeline, newest first.
+fn billing_history(
+    subscriptions: &[(
+        billing_subscription::Model,
+        Vec<billing_subscription_transition::Model>,
+    )],
+    invoice_events: &[billing_invoice_event::Model],
+    credited_referrals: &[referral::Model],
+    referral_credit_in_cents: Option<i64>,
+) -> Vec<BillingHistoryEntry> {
+    let mut entries = Vec::new();
+
+    for (_subscription, transitions) in subscriptions {
+        let mut was_active = false;
+        let mut previous_plan = None;
+        for transition in transitions {
+            let occurred_at = transition.transitioned_at.assume_utc();
+            let is_active = matches!(
+                transition.stripe_subscription_status,
+                StripeSubscriptionStatus::Active | StripeSubscriptionStatus::Trialing
+            );
+
+            if is_active && !was_active {
+                entries.push(BillingHistoryEntry {
+                    kind: BillingHistoryEntryKind::Subscribed,
+                    occurred_at,
+                    description: match transition.plan {
+                        Some(plan) => format!("Subscribed to {}", plan_name(plan)),
+                        None => "Subscribed".into(),
+                    },
+                    plan: transition.plan,
+                    amount_in_cents: None,
+                    currency: None,
+                    currency_format: None,
+                });
+            } else if let (true, Some(previous_plan), Some(plan)) =
+                (is_active, previous_plan, transition.plan)
+            {
+                if plan != previous_plan {
+                    entries.push(BillingHistoryEntry {
+                        kind: BillingHistoryEntryKind::PlanChanged,
+                        occurred_at,
+                        description: format!(
+                            "Changed plan from {} to {}",
+                            plan_name(previous_plan),
+                            plan_name(plan)
+                        ),
+                        plan: Some(plan),
+                        amount_in_cents: None,
+                        currency: None,
+                        currency_format: None,
+                    });
+                }
+            }
+
+            if transition.stripe_subscription_status == StripeSubscriptionStatus::Canceled
+                && was_active
+            {
+                entries.push(BillingHistoryEntry {
+                    kind: BillingHistoryEntryKind::Canceled,
+                    occurred_at,
+                    description: match previous_plan {
+                        Some(plan) => format!("Canceled {}", plan_name(plan)),
+                        None => "Canceled subscription".into(),
+                    },
+                    plan: previous_plan,
+                    amount_in_cents: None,
+                    currency: None,
+                    currency_format: None,
+                });
+            }
+
+            was_active = is_active;
+            if transition.plan.is_some() {
+                previous_plan = transition.plan;
+            }
+        }
+    }
+
+    for event in invoice_events {
+        let amount = format_amount(event.amount_in_cents, &event.currency);
+        let (kind, description) = match event.kind {
+            BillingInvoiceEventKind::Paid
+                if event.billing_reason.as_deref() == Some(RENEWAL_BILLING_REASON) =>
+            {
+                (
+                    BillingHistoryEntryKind::Renewed,
+                    format!("Subscription renewed for {amount}"),
+                )
+            }
+            // Payments for new subscriptions are already covered by the
+            // transitions, and one-off purchases aren't part of the feed.
+            BillingInvoiceEventKind::Paid => continue,
+            BillingInvoiceEventKind::PaymentFailed => (
+                BillingHistoryEntryKind::PaymentFailed,
+                format!("Payment of {amount} failed"),
+            ),
+        };
+        entries.push(BillingHistoryEntry {
+            kind,
+            occurred_at: event.occurred_at.assume_utc(),
+            description,
+            plan: None,
+            amount_in_cents: Some(event.amount_in_cents),
+            currency: Some(event.currency.clone()),
+            currency_format: None,
+        });
+    }
+
+    for referral in credited_referrals {
+        let Some(credited_at) = referral.referrer_credited_at else {
+            continue;
+        };
+        entries.push(BillingHistoryEntry {
+            kind: BillingHistoryEntryKind::CreditApplied,
+            occurred_at: credited_at.assume_utc(),
+            description: match referral_credit_in_cents {
+                Some(amount) => format!(
+                    "Referral credit of {} applied",
+                    format_amount(amount, "usd")
+                ),
+                None => "Referral credit applied".into(),
+            },
+            plan: None,
+            amount_in_cents: referral_credit_in_cents,
+            currency: referral_credit_in_cents.map(|_| "usd".into()),
+            currency_format: None,
+        });
+    }
+
+    entries.sort_by(|a, b| b.occurred_at.cmp(&a.occurred_at));
+    entries
+}
+
+fn plan_name(plan: SubscriptionPlan) -> &'static str {
+    match plan {
+        SubscriptionPlan::Free => "Zed Free",
+        SubscriptionPlan::Pro => "Zed Pro",
+        SubscriptionPlan::Team => "Zed Team",
+    }
+}
+
+/// Formats an amount in the smallest unit of a currency, such as
+/// `20.00 USD`.
+pub(crate) fn format_amount(amount: i64, currency: &str) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    let currency_code = currency.to_uppercase();
+    let minor_units = currency::minor_units(currency);
+    if minor_units == 0 {
+        return format!("{sign}{amount} {currency_code}");
+    }
+    let divisor = 10u64.pow(minor_units);
+    format!(
+        "{sign}{}.{:0width$} {currency_code}",
+        amount / divisor,
+        amount % divisor,
+        width = minor_units as usize
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        BillingCustomerId, BillingInvoiceEventId, BillingSubscriptionId,
+        BillingSubscriptionTransitionId, ReferralId, UserId,
+    };
+    use time::macros::datetime;
+    use time::PrimitiveDateTime;
+
+    fn transition(
+        id: i32,
+        status: StripeSubscriptionStatus,
+        plan: Option<SubscriptionPlan>,
+        transitioned_at: PrimitiveDateTime,
+    ) -> billing_subscription_transition::Model {
+        billing_subscription_transition::Model {
+            id: BillingSubscriptionTransitionId(id),
+            billing_subscription_id: BillingSubscriptionId(1),
+            stripe_subscription_status: status,
+            plan,
+            transitioned_at,
+            created_at: transitioned_at,
+        }
+    }
+
+    fn invoice_event(
+        id: i32,
+        kind: BillingInvoiceEventKind,
+        billing_reason: &str,
+        occurred_at: PrimitiveDateTime,
+    ) -> billing_invoice_event::Model {
+        billing_invoice_event::Model {
+            id: BillingInvoiceEventId(id),
+            billing_customer_id: BillingCustomerId(1),
+            stripe_invoice_id: format!("in_{id}"),
+            stripe_subscription_id: Some("sub_1".into()),
+            kind,
+            billing_reason: Some(billing_reason.into()),
+            amount_in_cents: 2000,
+            currency: "usd".into(),
+            occurred_at,
+            created_at: occurred_at,
+            usd_exchange_rate: Some(1.0),
+            amount_in_usd_cents: Some(2000),
+        }
+    }
+
+    #[test]
+    fn test_billing_history() {
+        let subscription = billing_subscription::Model {
+            id: BillingSubscriptionId(1),
+            billing_customer_id: BillingCustomerId(1),
+            stripe_subscription_id: "sub_1".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Canceled,
+            plan: SubscriptionPlan::Team,
+            ..Default::default()
+        };
+        let transitions = vec![
+            transition(
+                1,
+                StripeSubscriptionStatus::Incomplete,
+                Some(SubscriptionPlan::Pro),
+                datetime!(2024-06-01 10:00),
+            ),
+            transition(
+                2,
+                StripeSubscriptionStatus::Active,
+                Some(SubscriptionPlan::Pro),
+                datetime!(2024-06-01 10:01),
+            ),
+            transition(
+                3,
+                StripeSubscriptionStatus::Active,
+                Some(SubscriptionPlan::Team),
+                datetime!(2024-07-15 12:00),
+            ),
+            transition(
+                4,
+                StripeSubscriptionStatus::Canceled,
+                Some(SubscriptionPlan::Team),
+                datetime!(2024-08-20 09:00),
+            ),
+        ];
+        let invoice_events = vec![
+            invoice_event(
+                1,
+                BillingInvoiceEventKind::Paid,
+                "subscription_create",
+                datetime!(2024-06-01 10:01),
+            ),
+            invoice_event(
+                2,
+                BillingInvoiceEventKind::Paid,
+                "subscription_cycle",
+                datetime!(2024-07-01 10:00),
+            ),
+            invoice_event(
+                3,
+                BillingInvoiceEventKind::PaymentFailed,
+                "subscription_cycle",
+                datetime!(2024-08-01 10:00),
+            ),
+        ];
+        let credited_referrals = vec![referral::Model {
+            id: ReferralId(1),
+            referrer_user_id: UserId(1),
+            referred_user_id: UserId(2),
+            referrer_credited_at: Some(datetime!(2024-06-10 08:00)),
+            referrer_credit_pending_since: None,
+            referrer_credit_skipped_at: None,
+            created_at: datetime!(2024-06-09 08:00),
+        }];
+
+        let entries = billing_history(
+            &[(subscription, transitions)],
+            &invoice_events,
+            &credited_referrals,
+            Some(1000),
+        );
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.kind, entry.description.as_str()))
+                .collect::<Vec<_>>(),
+            &[
+                (BillingHistoryEntryKind::Canceled, "Canceled Zed Team"),
+                (
+                    BillingHistoryEntryKind::PaymentFailed,
+                    "Payment of 20.00 USD failed"
+                ),
+                (
+                    BillingHistoryEntryKind::PlanChanged,
+                    "Changed plan from Zed Pro to Zed Team"
+                ),
+                (
+                    BillingHistoryEntryKind::Renewed,
+                    "Subscription renewed for 20.00 USD"
+                ),
+                (
+                    BillingHistoryEntryKind::CreditApplied,
+                    "Referral credit of 10.00 USD applied"
+                ),
+                (BillingHistoryEntryKind::Subscribed, "Subscribed to Zed Pro"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(2000, "usd"), "20.00 USD");
+        assert_eq!(format_amount(5, "eur"), "0.05 EUR");
+        assert_eq!(format_amount(-1050, "usd"), "-10.50 USD");
+        assert_eq!(format_amount(2000, "jpy"), "2000 JPY");
+        assert_eq!(format_amount(20005, "kwd"), "20.005 KWD");
+    }
+}
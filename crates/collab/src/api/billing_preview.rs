@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _};
+use axum::{extract::Query, http::StatusCode, routing::get, Extension, Json, Router};
+use collab_api_client::CurrencyFormat;
+use serde::{Deserialize, Serialize};
+use stripe::{Expandable, Invoice};
+use time::OffsetDateTime;
+
+use crate::api::billing::find_customer_subscription_to_manage;
+use crate::currency::currency_format;
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::BillingSubscriptionId;
+use crate::stripe_connect::client_for_account;
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new().route("/billing/preview", get(get_billing_preview))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingPreviewParams {
+    github_user_id: i32,
+    /// The subscription to preview the next invoice of. Defaults to the
+    /// user's only active subscription.
+    subscription_id: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct GetBillingPreviewResponse {
+    /// The amount that will be charged, in the currency's smallest unit,
+    /// after discounts, taxes, and any credit on the customer's balance.
+    amount_due: i64,
+    currency: String,
+    /// How to format the amounts, for the user's locale.
+    currency_format: CurrencyFormat,
+    /// When the charge will be made.
+    #[serde(with = "time::serde::rfc3339::option")]
+    charged_at: Option<OffsetDateTime>,
+    /// The adjustments for changes made to the subscription partway through
+    /// the current period.
+    proration_lines: Vec<BillingPreviewLine>,
+    discounts: Vec<BillingPreviewDiscount>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BillingPreviewLine {
+    description: Option<String>,
+    /// Negative for credits of unused time.
+    amount_in_cents: i64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    period_start: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    period_end: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BillingPreviewDiscount {
+    /// The customer-facing name of the coupon, or its ID if it has none.
+    name: String,
+    amount_in_cents: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct RetrieveUpcomingInvoice<'a> {
+    customer: &'a str,
+    subscription: &'a str,
+    /// The discounts are expanded so that their coupons can be named.
+    expand: &'a [&'a str],
+}
+
+/// Returns what the user will be charged next for the subscription, and
+/// when, according to Stripe's preview of its upcoming invoice.
+async fn get_billing_preview(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingPreviewParams>,
+) -> Result<Json<GetBillingPreviewResponse>> {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::error!("failed to retrieve Stripe client");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let billing_customer = app
+        .db
+        .get_billing_customer_by_user_id(user.id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "no billing customer found".into()))?;
+    let subscription = find_customer_subscription_to_manage(
+        &app,
+        &billing_customer,
+        params.subscription_id.map(BillingSubscriptionId),
+    )
+    .await?;
+
+    // Subscriptions that have ended, or will at the end of the period, have
+    // nothing more to charge for.
+    if subscription.stripe_subscription_status == StripeSubscriptionStatus::Canceled
+        || subscription.cancel_at_period_end
+    {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "the subscription has no upcoming invoice".into(),
+        ))?
+    }
+
+    let stripe_client = client_for_account(
+        &stripe_client,
+        billing_customer.stripe_account_id.as_deref(),
+    )?;
+    let invoice = stripe_client
+        .get_query::<Invoice, _>(
+            "/invoices/upcoming",
+            RetrieveUpcomingInvoice {
+                customer: &billing_customer.stripe_customer_id,
+                subscription: &subscription.stripe_subscription_id,
+                expand: &["total_discount_amounts.discount"],
+            },
+        )
+        .await?;
+
+    Ok(Json(billing_preview(&invoice, user.locale.as_deref())?))
+}
+
+fn billing_preview(
+    invoice: &Invoice,
+    locale: Option<&str>,
+) -> anyhow::Result<GetBillingPreviewResponse> {
+    let currency = invoice
+        .currency
+        .context("upcoming invoice has no currency")?
+        .to_string();
+
+    let mut proration_lines = Vec::new();
+    for line in invoice.lines.iter().flat_map(|lines| &lines.data) {
+        if !line.proration {
+            continue;
+        }
+        let period = line.period.as_ref();
+        proration_lines.push(BillingPreviewLine {
+            description: line.description.clone(),
+            amount_in_cents: line.amount,
+            period_start: period
+                .and_then(|period| period.start)
+                .map(OffsetDateTime::from_unix_timestamp)
+                .transpose()?,
+            period_end: period
+                .and_then(|period| period.end)
+                .map(OffsetDateTime::from_unix_timestamp)
+                .transpose()?,
+        });
+    }
+
+    let discounts = invoice
+        .total_discount_amounts
+        .iter()
+        .flatten()
+        .map(|discount_amount| BillingPreviewDiscount {
+            name: match &discount_amount.discount {
+                Expandable::Object(discount) => discount
+                    .coupon
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| discount.coupon.id.to_string()),
+                Expandable::Id(discount_id) => discount_id.to_string(),
+            },
+            amount_in_cents: discount_amount.amount,
+        })
+        .collect();
+
+    Ok(GetBillingPreviewResponse {
+        amount_due: invoice.amount_due.unwrap_or_default(),
+        currency_format: currency_format(&currency, locale),
+        currency,
+        // Invoices are created, and charged for shortly after, when the
+        // period ends.
+        charged_at: invoice
+            .next_payment_attempt
+            .or(invoice.created)
+            .map(OffsetDateTime::from_unix_timestamp)
+            .transpose()?,
+        proration_lines,
+        discounts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_billing_preview() {
+        let invoice: Invoice = serde_json::from_value(json!({
+            // Upcoming invoices have yet to be given an ID.
+            "id": null,
+            "object": "invoice",
+            "amount_due": 1500,
+            "currency": "eur",
+            "created": 1727740800,
+            "next_payment_attempt": 1727744400,
+            "lines": {
+                "object": "list",
+                "data": [
+                    {
+                        "id": "il_1",
+                        "object": "line_item",
+                        "amount": -1000,
+                        "currency": "eur",
+                        "description": "Unused time on Zed Pro after 15 Sep 2024",
+                        "discountable": false,
+                        "livemode": false,
+                        "metadata": {},
+                        "period": { "start": 1726358400, "end": 1727740800 },
+                        "proration": true,
+                        "type": "invoiceitem"
+                    },
+                    {
+                        "id": "il_2",
+                        "object": "line_item",
+                        "amount": 2500,
+                        "currency": "eur",
+                        "description": "1 × Zed Business (at €25.00 / month)",
+                        "discountable": true,
+                        "livemode": false,
+                        "metadata": {},
+                        "period": { "start": 1727740800, "end": 1730419200 },
+                        "proration": false,
+                        "type": "subscription"
+                    }
+                ],
+                "has_more": false,
+                "url": "/v1/invoices/upcoming/lines"
+            },
+            "total_discount_amounts": [
+                {
+                    "amount": 500,
+                    "discount": {
+                        "id": "di_1",
+                        "object": "discount",
+                        "coupon": {
+                            "id": "REFERRAL",
+                            "object": "coupon",
+                            "name": "Referral discount",
+                            "livemode": false,
+                            "metadata": {},
+                            "valid": true
+                        },
+                        "start": 1726358400
+                    }
+                },
+                { "amount": 100, "discount": "di_2" }
+            ]
+        }))
+        .unwrap();
+
+        let preview = billing_preview(&invoice, None).unwrap();
+        assert_eq!(preview.amount_due, 1500);
+        assert_eq!(preview.currency, "eur");
+        assert_eq!(preview.charged_at, Some(datetime!(2024-10-01 1:00 UTC)));
+        assert_eq!(
+            preview.proration_lines,
+            [BillingPreviewLine {
+                description: Some("Unused time on Zed Pro after 15 Sep 2024".into()),
+                amount_in_cents: -1000,
+                period_start: Some(datetime!(2024-09-15 0:00 UTC)),
+                period_end: Some(datetime!(2024-10-01 0:00 UTC)),
+            }]
+        );
+        assert_eq!(
+            preview.discounts,
+            [
+                BillingPreviewDiscount {
+                    name: "Referral discount".into(),
+                    amount_in_cents: 500,
+                },
+                BillingPreviewDiscount {
+                    name: "di_2".into(),
+                    amount_in_cents: 100,
+                },
+            ]
+        );
+    }
+}
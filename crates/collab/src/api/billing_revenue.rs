@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use axum::{extract::Query, http::StatusCode, routing::get, Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::db::billing_invoice_event;
+use crate::{AppState, Error, Result};
+
+/// How many months of revenue are reported, unless asked otherwise.
+const DEFAULT_REVENUE_MONTHS: u32 = 12;
+const MAX_REVENUE_MONTHS: u32 = 36;
+
+pub fn router() -> Router {
+    Router::new().route("/billing/revenue", get(get_billing_revenue))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetBillingRevenueParams {
+    /// How many months to report, including the current one.
+    months: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingRevenueResponse {
+    months: Vec<RevenueMonth>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct RevenueMonth {
+    /// The month, as `YYYY-MM`.
+    month: String,
+    /// The revenue in USD, converted at the rate of the day each invoice was
+    /// paid on.
+    amount_in_usd_cents: i64,
+    paid_invoice_count: usize,
+    /// How many of the paid invoices couldn't be converted to USD, and are
+    /// therefore missing from `amount_in_usd_cents`.
+    unconverted_invoice_count: usize,
+    currencies: Vec<CurrencyRevenue>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct CurrencyRevenue {
+    currency: String,
+    /// The revenue in the currency's smallest unit.
+    amount_in_cents: i64,
+    amount_in_usd_cents: i64,
+}
+
+/// Returns the revenue from paid invoices for each month, normalized to USD.
+async fn get_billing_revenue(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetBillingRevenueParams>,
+) -> Result<Json<GetBillingRevenueResponse>> {
+    let months = params.months.unwrap_or(DEFAULT_REVENUE_MONTHS);
+    if months == 0 || months > MAX_REVENUE_MONTHS {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("months must be between 1 and {MAX_REVENUE_MONTHS}"),
+        ))?
+    }
+
+    let today = OffsetDateTime::now_utc().date();
+    let month_index = today.year() * 12 + today.month() as i32 - 1 - (months as i32 - 1);
+    let since = Date::from_calendar_date(
+        month_index.div_euclid(12),
+        Month::try_from(month_index.rem_euclid(12) as u8 + 1).context("invalid month")?,
+        1,
+    )
+    .context("invalid date")?;
+    let events = app
+        .db
+        .get_paid_billing_invoice_events(PrimitiveDateTime::new(since, Time::MIDNIGHT))
+        .await?;
+
+    Ok(Json(GetBillingRevenueResponse {
+        months: monthly_revenue(&events),
+    }))
+}
+
+/// Adds up paid invoices by the month they were paid in, oldest first.
+fn monthly_revenue(events: &[billing_invoice_event::Model]) -> Vec<RevenueMonth> {
+    let mut months = BTreeMap::<(i32, u8), RevenueMonth>::new();
+    for event in events {
+        let date: Date = event.occurred_at.date();
+        let month = months
+            .entry((date.year(), date.month() as u8))
+            .or_insert_with(|| RevenueMonth {
+                month: format!("{:04}-{:02}", date.year(), date.month() as u8),
+                amount_in_usd_cents: 0,
+                paid_invoice_count: 0,
+                unconverted_invoice_count: 0,
+                currencies: Vec::new(),
+            });
+        month.paid_invoice_count += 1;
+
+        let currency = event.currency.to_ascii_lowercase();
+        let index = match month
+            .currencies
+            .binary_search_by(|revenue| revenue.currency.as_str().cmp(&currency))
+        {
+            Ok(index) => index,
+            Err(index) => {
+                month.currencies.insert(
+                    index,
+                    CurrencyRevenue {
+                        currency,
+                        amount_in_cents: 0,
+                        amount_in_usd_cents: 0,
+                    },
+                );
+                index
+            }
+        };
+        let currency_revenue = &mut month.currencies[index];
+        currency_revenue.amount_in_cents += event.amount_in_cents;
+
+        if let Some(amount_in_usd_cents) = event.amount_in_usd_cents {
+            currency_revenue.amount_in_usd_cents += amount_in_usd_cents;
+            month.amount_in_usd_cents += amount_in_usd_cents;
+        } else {
+            month.unconverted_invoice_count += 1;
+        }
+    }
+    months.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::billing_invoice_event::BillingInvoiceEventKind;
+    use crate::db::{BillingCustomerId, BillingInvoiceEventId};
+    use time::macros::datetime;
+
+    fn paid_invoice_event(
+        id: i32,
+        amount_in_cents: i64,
+        currency: &str,
+        amount_in_usd_cents: Option<i64>,
+        occurred_at: PrimitiveDateTime,
+    ) -> billing_invoice_event::Model {
+        billing_invoice_event::Model {
+            id: BillingInvoiceEventId(id),
+            billing_customer_id: BillingCustomerId(1),
+            stripe_invoice_id: format!("in_{id}"),
+            stripe_subscription_id: None,
+            kind: BillingInvoiceEventKind::Paid,
+            billing_reason: Some("subscription_cycle".into()),
+            amount_in_cents,
+            currency: currency.into(),
+            occurred_at,
+            created_at: occurred_at,
+            usd_exchange_rate: None,
+            amount_in_usd_cents,
+        }
+    }
+
+    #[test]
+    fn test_monthly_revenue() {
+        let events = [
+            paid_invoice_event(1, 2000, "usd", Some(2000), datetime!(2024-07-01 10:00)),
+            paid_invoice_event(2, 2000, "eur", Some(2222), datetime!(2024-07-15 10:00)),
+            paid_invoice_event(3, 2000, "usd", Some(2000), datetime!(2024-08-01 10:00)),
+            // An invoice whose exchange rate couldn't be looked up.
+            paid_invoice_event(4, 3000, "jpy", None, datetime!(2024-08-02 10:00)),
+        ];
+
+        assert_eq!(
+            monthly_revenue(&events),
+            [
+                RevenueMonth {
+                    month: "2024-07".into(),
+                    amount_in_usd_cents: 4222,
+                    paid_invoice_count: 2,
+                    unconverted_invoice_count: 0,
+                    currencies: vec![
+                        CurrencyRevenue {
+                            currency: "eur".into(),
+                            amount_in_cents: 2000,
+                            amount_in_usd_cents: 2222,
+                        },
+                        CurrencyRevenue {
+                            currency: "usd".into(),
+                            amount_in_cents: 2000,
+                            amount_in_usd_cents: 2000,
+                        },
+                    ],
+                },
+                RevenueMonth {
+                    month: "2024-08".into(),
+                    amount_in_usd_cents: 2000,
+                    paid_invoice_count: 2,
+                    unconverted_invoice_count: 1,
+                    currencies: vec![
+                        CurrencyRevenue {
+                            currency: "jpy".into(),
+                            amount_in_cents: 3000,
+                            amount_in_usd_cents: 0,
+                        },
+                        CurrencyRevenue {
+                            currency: "usd".into(),
+                            amount_in_cents: 2000,
+                            amount_in_usd_cents: 2000,
+                        },
+                    ],
+                },
+            ]
+        );
+    }
+}
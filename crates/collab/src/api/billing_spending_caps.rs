@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{self, Query},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::{billing_customer, CreateBillingAuditLogEntryParams, User};
+use crate::spending_caps::MeteredSpend;
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new().route(
+        "/billing/spending_cap",
+        get(get_spending_cap).put(update_spending_cap),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct GetSpendingCapParams {
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct SpendingCapResponse {
+    /// The most the user may spend on metered usage each billing period, or
+    /// `None` if they haven't capped it.
+    spending_cap_in_cents: Option<i64>,
+    /// What the user's metered usage has cost so far this billing period.
+    spent_in_cents: i64,
+    /// When the billing period ends, and usage is allowed again if the cap
+    /// was reached.
+    #[serde(with = "time::serde::rfc3339")]
+    period_ends_at: OffsetDateTime,
+}
+
+/// Returns the user's spending cap, and what they've spent against it this
+/// billing period.
+async fn get_spending_cap(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetSpendingCapParams>,
+) -> Result<Json<SpendingCapResponse>> {
+    let (user, _) = user_and_billing_customer(&app, params.github_user_id).await?;
+    spending_cap_response(&app, &user).await
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateSpendingCapBody {
+    github_user_id: i32,
+    /// The new cap, or `None` to remove it.
+    spending_cap_in_cents: Option<i64>,
+}
+
+/// Sets the most the user may spend on metered usage each billing period.
+/// Once their usage costs that much, further usage is refused until the next
+/// period, or until the cap is raised.
+async fn update_spending_cap(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<UpdateSpendingCapBody>,
+) -> Result<Json<SpendingCapResponse>> {
+    if body
+        .spending_cap_in_cents
+        .map_or(false, |spending_cap_in_cents| spending_cap_in_cents < 0)
+    {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "the spending cap can't be negative".into(),
+        ))?
+    }
+
+    let (user, billing_customer) = user_and_billing_customer(&app, body.github_user_id).await?;
+    app.db
+        .update_billing_customer_spending_cap(billing_customer.id, body.spending_cap_in_cents)
+        .await?;
+    app.db
+        .record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+            user_id: user.id,
+            actor_user_id: Some(user.id),
+            event: BillingAuditEvent::SpendingCapChanged,
+            billing_subscription_id: None,
+            previous_state: Some(
+                serde_json::json!({
+                    "spending_cap_in_cents": billing_customer.spending_cap_in_cents,
+                })
+                .to_string(),
+            ),
+            state: Some(
+                serde_json::json!({ "spending_cap_in_cents": body.spending_cap_in_cents })
+                    .to_string(),
+            ),
+        })
+        .await?;
+    log::info!(
+        "user {} set their spending cap to {:?} cents",
+        user.github_login,
+        body.spending_cap_in_cents
+    );
+
+    spending_cap_response(&app, &user).await
+}
+
+async fn user_and_billing_customer(
+    app: &AppState,
+    github_user_id: i32,
+) -> Result<(User, billing_customer::Model)> {
+    if app.config.stripe_metered_price_id.is_none()
+        || app
+            .config
+            .metered_price_per_million_tokens_in_cents
+            .is_none()
+    {
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    }
+
+    let user = app
+        .db
+        .get_user_by_github_user_id(github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let billing_customer = app
+        .db
+        .get_billing_customer_by_user_id(user.id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "no billing customer found".into()))?;
+    if billing_customer.stripe_account_id.is_some() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "usage isn't metered for this customer".into(),
+        ))?
+    }
+    Ok((user, billing_customer))
+}
+
+async fn spending_cap_response(app: &AppState, user: &User) -> Result<Json<SpendingCapResponse>> {
+    let spend = MeteredSpend::for_user(&app.db, &app.config, user.id, OffsetDateTime::now_utc())
+        .await?
+        .ok_or_else(|| anyhow!("usage isn't metered for user {}", user.id))?;
+    Ok(Json(SpendingCapResponse {
+        spending_cap_in_cents: spend.cap_in_cents,
+        spent_in_cents: spend.spent_in_cents,
+        period_ends_at: spend.period.end,
+    }))
+}
@@ -0,0 +1,314 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use axum::{http::StatusCode, routing::get, Extension, Json, Router};
+use collab_api_client::BillingSubscription;
+use serde::{Deserialize, Serialize};
+use stripe::{Customer, CustomerId, UpdateCustomer};
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::api::admin_user;
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::{
+    billing_customer, BillingCustomerTransferId, OrganizationId, TransferBillingCustomerParams,
+    User, UserId,
+};
+use crate::plan_enforcement;
+use crate::stripe_connect::client_for_account;
+use crate::{AppState, Error, Result};
+
+/// The number of transfers returned by the audit log endpoint.
+const TRANSFER_LOG_LIMIT: u64 = 100;
+
+pub fn router() -> Router {
+    Router::new().route(
+        "/billing/transfers",
+        get(get_billing_transfers).post(transfer_billing),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferBillingBody {
+    /// The GitHub user ID of the admin making the transfer.
+    github_user_id: i32,
+    from_github_user_id: i32,
+    to_github_user_id: i32,
+    /// The organization the subscriptions are transferred to. The recipient
+    /// takes over its billing, so they must be one of its admins.
+    organization_id: Option<OrganizationId>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BillingTransferJson {
+    id: BillingCustomerTransferId,
+    from_github_login: Option<String>,
+    to_github_login: Option<String>,
+    organization_id: Option<OrganizationId>,
+    transferred_by_github_login: Option<String>,
+    reason: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    transferred_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct TransferBillingResponse {
+    transfer: BillingTransferJson,
+    /// The subscriptions that now belong to the recipient.
+    subscriptions: Vec<BillingSubscription>,
+}
+
+/// Transfers a user's Stripe customer, along with all of its subscriptions,
+/// to another user, or to an organization through one of its admins, whose
+/// seats its team subscription then pays for.
+///
+/// Stripe subscriptions can't be moved between customers, so the customer
+/// itself changes hands.
+async fn transfer_billing(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    Json(body): Json<TransferBillingBody>,
+) -> Result<Json<TransferBillingResponse>> {
+    let admin = admin_user(&app, body.github_user_id, "transfer subscriptions").await?;
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::error!("failed to retrieve Stripe client");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+
+    let from_user = app
+        .db
+        .get_user_by_github_user_id(body.from_github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let to_user = app
+        .db
+        .get_user_by_github_user_id(body.to_github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    if from_user.id == to_user.id {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "cannot transfer a subscription to the same user".into(),
+        ))?
+    }
+
+    let mut previous_organization_billing_subscription_id = None;
+    if let Some(organization_id) = body.organization_id {
+        let membership = app
+            .db
+            .get_organization_member(organization_id, to_user.id)
+            .await?;
+        if !membership.map_or(false, |member| member.role.can_administer()) {
+            Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                "the recipient must be an admin of the organization".into(),
+            ))?
+        }
+        let organization = app
+            .db
+            .get_organization_by_id(organization_id)
+            .await?
+            .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "organization not found".into()))?;
+        previous_organization_billing_subscription_id = organization.billing_subscription_id;
+    }
+
+    let billing_customer = app
+        .db
+        .get_billing_customer_by_user_id(from_user.id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "no billing customer found".into()))?;
+    let from_subscriptions = app.db.get_billing_subscriptions(from_user.id).await?;
+    if from_subscriptions.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "the user has no subscriptions to transfer".into(),
+        ))?
+    }
+    if body.organization_id.is_some() {
+        let team_subscription = app
+            .db
+            .get_active_billing_subscriptions(from_user.id)
+            .await?
+            .into_iter()
+            .filter(|subscription| subscription.plan == SubscriptionPlan::Team)
+            .max_by_key(|subscription| subscription.id);
+        let Some(team_subscription) = team_subscription else {
+            Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                "the user has no team subscription to pay for the organization".into(),
+            ))?
+        };
+        if previous_organization_billing_subscription_id
+            .map_or(false, |id| id != team_subscription.id)
+        {
+            Err(Error::Http(
+                StatusCode::CONFLICT,
+                "the organization is already paid for by another subscription".into(),
+            ))?
+        }
+    }
+    if app
+        .db
+        .get_billing_customer_by_user_id(to_user.id)
+        .await?
+        .is_some()
+    {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "the recipient already has a billing customer".into(),
+        ))?
+    }
+
+    // Record the transfer first, since it's what checks that the recipient
+    // can take the customer, and undo it if Stripe can't be updated to match.
+    let transfer = app
+        .db
+        .transfer_billing_customer(&TransferBillingCustomerParams {
+            billing_customer_id: billing_customer.id,
+            to_user_id: to_user.id,
+            organization_id: body.organization_id,
+            transferred_by_user_id: admin.id,
+            reason: body.reason,
+        })
+        .await?;
+    // The previous owner's billing email goes along with the Stripe email.
+    app.db
+        .update_billing_customer_email(billing_customer.id, None)
+        .await?;
+
+    if let Err(error) = update_stripe_customer(
+        &stripe_client,
+        &billing_customer,
+        &to_user,
+        body.organization_id,
+    )
+    .await
+    {
+        app.db
+            .revert_billing_customer_transfer(
+                transfer.id,
+                previous_organization_billing_subscription_id,
+            )
+            .await?;
+        app.db
+            .update_billing_customer_email(
+                billing_customer.id,
+                billing_customer.billing_email.as_deref(),
+            )
+            .await?;
+        return Err(error);
+    }
+    log::info!(
+        "admin {} transferred billing customer {} from user {} to user {}",
+        admin.github_login,
+        billing_customer.id,
+        from_user.id,
+        to_user.id
+    );
+
+    for user_id in [from_user.id, to_user.id] {
+        plan_enforcement::plan_changed(&app, user_id)
+            .await
+            .log_err();
+        if let Some(rpc_server) = rpc_server.as_ref() {
+            rpc_server.billing_status_updated(user_id).await.log_err();
+        }
+    }
+
+    let subscriptions = app.db.get_billing_subscriptions(to_user.id).await?;
+    Ok(Json(TransferBillingResponse {
+        transfer: BillingTransferJson {
+            id: transfer.id,
+            from_github_login: Some(from_user.github_login),
+            to_github_login: Some(to_user.github_login),
+            organization_id: transfer.organization_id,
+            transferred_by_github_login: Some(admin.github_login),
+            reason: transfer.reason,
+            transferred_at: transfer.created_at.assume_utc(),
+        },
+        subscriptions: subscriptions.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Points the Stripe customer at its new owner, so that receipts and
+/// subscription events are attributed to them.
+async fn update_stripe_customer(
+    stripe_client: &stripe::Client,
+    billing_customer: &billing_customer::Model,
+    to_user: &User,
+    organization_id: Option<OrganizationId>,
+) -> Result<()> {
+    let stripe_client =
+        client_for_account(stripe_client, billing_customer.stripe_account_id.as_deref())?;
+    let customer_id = CustomerId::from_str(&billing_customer.stripe_customer_id)
+        .context("failed to parse customer ID")?;
+    let mut metadata = [("zed_user_id".to_string(), to_user.id.to_string())]
+        .into_iter()
+        .collect::<std::collections::HashMap<_, _>>();
+    if let Some(organization_id) = organization_id {
+        metadata.insert("zed_organization_id".into(), organization_id.to_string());
+    }
+    Customer::update(
+        &stripe_client,
+        &customer_id,
+        UpdateCustomer {
+            email: to_user.email_address.as_deref(),
+            metadata: Some(metadata),
+            ..Default::default()
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct GetBillingTransfersResponse {
+    transfers: Vec<BillingTransferJson>,
+}
+
+/// Returns the most recent billing transfers, newest first.
+async fn get_billing_transfers(
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<GetBillingTransfersResponse>> {
+    let transfers = app
+        .db
+        .get_billing_customer_transfers(TRANSFER_LOG_LIMIT)
+        .await?;
+    let user_ids = transfers
+        .iter()
+        .flat_map(|transfer| {
+            [
+                transfer.from_user_id,
+                transfer.to_user_id,
+                transfer.transferred_by_user_id,
+            ]
+        })
+        .collect::<Vec<_>>();
+    let users = app.db.get_users_by_ids(user_ids).await?;
+    let github_login = |user_id: UserId| {
+        users
+            .iter()
+            .find(|user| user.id == user_id)
+            .map(|user| user.github_login.clone())
+    };
+
+    Ok(Json(GetBillingTransfersResponse {
+        transfers: transfers
+            .into_iter()
+            .map(|transfer| BillingTransferJson {
+                id: transfer.id,
+                from_github_login: github_login(transfer.from_user_id),
+                to_github_login: github_login(transfer.to_user_id),
+                organization_id: transfer.organization_id,
+                transferred_by_github_login: github_login(transfer.transferred_by_user_id),
+                reason: transfer.reason,
+                transferred_at: transfer.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{self, Query},
+    routing::get,
+    Extension, Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::db::{consent::ConsentKind, UserId};
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new().route("/consents", get(get_consents).post(record_consent))
+}
+
+/// Returns the version of the document that users are currently required to
+/// accept, if any.
+fn current_version(app: &AppState, kind: ConsentKind) -> Option<&str> {
+    match kind {
+        ConsentKind::TermsOfService => app.config.terms_of_service_version.as_deref(),
+        ConsentKind::Pricing => app.config.pricing_terms_version.as_deref(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GetConsentsParams {
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct Consent {
+    kind: ConsentKind,
+    version: String,
+    #[serde(with = "time::serde::rfc3339")]
+    accepted_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct RequiredConsent {
+    kind: ConsentKind,
+    version: String,
+}
+
+#[derive(Debug, Serialize)]
+struct GetConsentsResponse {
+    consents: Vec<Consent>,
+    /// The current versions the user has not accepted yet.
+    missing: Vec<RequiredConsent>,
+}
+
+/// Returns the consents the user has given, along with any current versions
+/// they still need to accept.
+async fn get_consents(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetConsentsParams>,
+) -> Result<Json<GetConsentsResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let consents = app.db.get_consents(user.id).await?;
+    let missing = missing_consents(&app, user.id).await?;
+
+    Ok(Json(GetConsentsResponse {
+        consents: consents
+            .into_iter()
+            .map(|consent| Consent {
+                kind: consent.kind,
+                version: consent.version,
+                accepted_at: consent.created_at.assume_utc(),
+            })
+            .collect(),
+        missing,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordConsentBody {
+    github_user_id: i32,
+    kind: ConsentKind,
+    /// The version the user was shown when they accepted.
+    version: String,
+}
+
+/// Records that the user accepted the current version of a document.
+async fn record_consent(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<RecordConsentBody>,
+) -> Result<()> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    // Only allow accepting the current version, so that a client holding on to
+    // stale terms can't record consent to something we no longer offer.
+    if current_version(&app, body.kind) != Some(body.version.as_str()) {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            format!("{:?} version {} is not current", body.kind, body.version),
+        ))?
+    }
+
+    app.db
+        .record_consent(user.id, body.kind, &body.version)
+        .await?;
+
+    Ok(())
+}
+
+async fn missing_consents(app: &AppState, user_id: UserId) -> Result<Vec<RequiredConsent>> {
+    let mut missing = Vec::new();
+    for kind in [ConsentKind::TermsOfService, ConsentKind::Pricing] {
+        let Some(version) = current_version(app, kind) else {
+            continue;
+        };
+
+        if !app.db.has_consented(user_id, kind, version).await? {
+            missing.push(RequiredConsent {
+                kind,
+                version: version.to_string(),
+            });
+        }
+    }
+    Ok(missing)
+}
+
+/// Records that the user accepted the current version of every document
+/// required before purchasing a subscription, for users created by us rather
+/// than signing up themselves.
+pub(crate) async fn record_current_consents(app: &AppState, user_id: UserId) -> Result<()> {
+    for consent in missing_consents(app, user_id).await? {
+        app.db
+            .record_consent(user_id, consent.kind, &consent.version)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Returns an error if the user has not accepted the current version of every
+/// document required before purchasing a subscription.
+pub async fn ensure_current_consents(app: &AppState, user_id: UserId) -> Result<()> {
+    let missing = missing_consents(app, user_id).await?;
+    if let Some(consent) = missing.first() {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            format!(
+                "user must accept {:?} version {} first",
+                consent.kind, consent.version
+            ),
+        ))?
+    }
+
+    Ok(())
+}
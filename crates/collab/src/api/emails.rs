@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{
+    extract,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    routing::post,
+    Extension, Router,
+};
+use base64::prelude::*;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::db::email_suppression::EmailSuppressionReason;
+use crate::{AppState, Error, Result};
+
+/// Postmark authenticates its webhooks with HTTP basic auth, rather than our
+/// API token.
+pub fn webhook_router() -> Router {
+    Router::new().route("/emails/webhooks/postmark", post(handle_postmark_webhook))
+}
+
+/// The subset of a Postmark bounce or spam complaint webhook payload that we use.
+///
+/// See <https://postmarkapp.com/developer/webhooks/bounce-webhook>.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkWebhookBody {
+    record_type: String,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    email: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+/// Suppresses future emails to recipients whose emails bounced permanently or
+/// who marked our emails as spam.
+async fn handle_postmark_webhook(
+    Extension(app): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    extract::Json(body): extract::Json<PostmarkWebhookBody>,
+) -> Result<()> {
+    let Some(credentials) = app.config.postmark_webhook_credentials.as_deref() else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "Postmark webhooks are not configured".into(),
+        ))?
+    };
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    if !is_authorized(authorization, credentials) {
+        Err(Error::Http(
+            StatusCode::UNAUTHORIZED,
+            "invalid credentials".into(),
+        ))?
+    }
+
+    let reason = match (body.record_type.as_str(), body.bounce_type.as_deref()) {
+        ("Bounce", Some("HardBounce")) => EmailSuppressionReason::Bounce,
+        ("SpamComplaint", _) => EmailSuppressionReason::Complaint,
+        (record_type, bounce_type) => {
+            log::info!(
+                "ignoring Postmark webhook: record type {record_type:?}, bounce type {bounce_type:?}"
+            );
+            return Ok(());
+        }
+    };
+
+    log::info!("suppressing emails to {}: {reason:?}", body.email);
+    app.db
+        .suppress_email_address(&body.email, reason, body.message_id.as_deref())
+        .await?;
+
+    Ok(())
+}
+
+/// Returns whether the `Authorization` header carries the given credentials,
+/// as `<username>:<password>`, using HTTP basic auth.
+fn is_authorized(authorization: Option<&str>, credentials: &str) -> bool {
+    let Some(encoded) = authorization.and_then(|header| header.strip_prefix("Basic ")) else {
+        return false;
+    };
+    let Ok(decoded) = BASE64_STANDARD.decode(encoded.trim()) else {
+        return false;
+    };
+    decoded.ct_eq(credentials.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_authorized() {
+        let header = format!("Basic {}", BASE64_STANDARD.encode("postmark:secret"));
+        assert!(is_authorized(Some(&header), "postmark:secret"));
+        assert!(!is_authorized(Some(&header), "postmark:other"));
+        assert!(!is_authorized(Some("Basic not-base64!"), "postmark:secret"));
+        assert!(!is_authorized(Some("Bearer secret"), "postmark:secret"));
+        assert!(!is_authorized(None, "postmark:secret"));
+    }
+}
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Path, Query},
+    http::{HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use collab_api_client::BillingSubscription;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::auth::{hash_access_token, random_token};
+use crate::db::{
+    impersonation_session, CreateImpersonationSessionParams, ImpersonationSessionId, User,
+};
+use crate::{AppState, Error, Result};
+
+/// The header identifying the impersonation session a request is made in.
+pub const IMPERSONATION_SESSION_HEADER: &str = "x-zed-impersonation-session-id";
+
+/// The fields through which a request names the user it acts as.
+const USER_IDENTIFIER_FIELDS: &[&str] = &["user_id", "github_user_id", "github_login", "login"];
+
+/// The feature flag that allows a staff member to start sessions in which
+/// they can make changes on the user's behalf.
+const IMPERSONATION_WRITES_FLAG: &str = "impersonation-writes";
+
+const DEFAULT_SESSION_DURATION: Duration = Duration::minutes(15);
+const MAX_SESSION_DURATION: Duration = Duration::hours(1);
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/impersonation_sessions",
+            post(create_impersonation_session),
+        )
+        .route(
+            "/impersonation_sessions/:id/end",
+            post(end_impersonation_session),
+        )
+        .route(
+            "/impersonation_sessions/:id/billing",
+            get(get_impersonated_billing),
+        )
+        .route(
+            "/impersonation_sessions/:id/requests",
+            get(get_impersonated_requests),
+        )
+}
+
+/// Returns the user with the given GitHub user ID, provided they're an admin.
+async fn staff_user(app: &AppState, github_user_id: i32) -> Result<User> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    if !user.admin {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "only staff can impersonate users".into(),
+        ))?
+    }
+    Ok(user)
+}
+
+/// Returns the impersonation session with the given ID, provided it's still
+/// active.
+async fn active_session(
+    app: &AppState,
+    id: ImpersonationSessionId,
+) -> Result<impersonation_session::Model> {
+    let session = app
+        .db
+        .get_impersonation_session(id)
+        .await?
+        .ok_or_else(|| anyhow!("impersonation session not found"))?;
+    if !session.is_active(now()) {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "impersonation session has ended".into(),
+        ))?
+    }
+    Ok(session)
+}
+
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Describes an impersonation session, so that clients can show a banner
+/// while it's active.
+#[derive(Debug, Serialize)]
+struct ImpersonationSessionJson {
+    id: ImpersonationSessionId,
+    staff_github_login: String,
+    impersonated_github_login: String,
+    reason: String,
+    allow_writes: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+}
+
+async fn session_json(
+    app: &AppState,
+    session: impersonation_session::Model,
+) -> Result<ImpersonationSessionJson> {
+    let github_login = |user_id| async move {
+        Ok::<_, Error>(
+            app.db
+                .get_user_by_id(user_id)
+                .await?
+                .ok_or_else(|| anyhow!("user {user_id} not found"))?
+                .github_login,
+        )
+    };
+    Ok(ImpersonationSessionJson {
+        id: session.id,
+        staff_github_login: github_login(session.staff_user_id).await?,
+        impersonated_github_login: github_login(session.impersonated_user_id).await?,
+        reason: session.reason,
+        allow_writes: session.allow_writes,
+        expires_at: session.expires_at.assume_utc(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct CreateImpersonationSessionResponse {
+    impersonation: ImpersonationSessionJson,
+    /// The API token to make requests with during the session. It's only
+    /// returned once.
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateImpersonationSessionBody {
+    /// The GitHub user ID of the staff member impersonating the user.
+    github_user_id: i32,
+    impersonated_github_user_id: i32,
+    reason: String,
+    /// How long the session lasts, up to an hour. Defaults to 15 minutes.
+    duration_in_minutes: Option<i64>,
+    /// Whether to allow requests that change state. Only staff members with
+    /// the `impersonation-writes` flag can start such sessions.
+    #[serde(default)]
+    allow_writes: bool,
+}
+
+/// Starts a session in which a staff member can see the service as the given
+/// user sees it.
+async fn create_impersonation_session(
+    Extension(app): Extension<Arc<AppState>>,
+    Json(body): Json<CreateImpersonationSessionBody>,
+) -> Result<Json<CreateImpersonationSessionResponse>> {
+    let staff = staff_user(&app, body.github_user_id).await?;
+    let impersonated_user = app
+        .db
+        .get_user_by_github_user_id(body.impersonated_github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "a reason for impersonating the user is required".into(),
+        ))?
+    }
+
+    let duration = body
+        .duration_in_minutes
+        .map_or(DEFAULT_SESSION_DURATION, Duration::minutes);
+    if duration <= Duration::ZERO || duration > MAX_SESSION_DURATION {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "impersonation sessions can last up to {} minutes",
+                MAX_SESSION_DURATION.whole_minutes()
+            ),
+        ))?
+    }
+
+    if body.allow_writes
+        && !app
+            .db
+            .get_user_flags(staff.id)
+            .await?
+            .iter()
+            .any(|flag| flag == IMPERSONATION_WRITES_FLAG)
+    {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "not allowed to make changes on behalf of users".into(),
+        ))?
+    }
+
+    let token = random_token();
+    let session = app
+        .db
+        .create_impersonation_session(&CreateImpersonationSessionParams {
+            staff_user_id: staff.id,
+            impersonated_user_id: impersonated_user.id,
+            reason: reason.to_string(),
+            allow_writes: body.allow_writes,
+            expires_at: now() + duration,
+            token_hash: hash_access_token(&token),
+        })
+        .await?;
+    log::info!(
+        "{} started impersonating {} (session {}): {reason}",
+        staff.github_login,
+        impersonated_user.github_login,
+        session.id
+    );
+
+    Ok(Json(CreateImpersonationSessionResponse {
+        impersonation: session_json(&app, session).await?,
+        token,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct StaffParams {
+    /// The GitHub user ID of the staff member.
+    github_user_id: i32,
+}
+
+/// Ends an impersonation session before it expires.
+async fn end_impersonation_session(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<ImpersonationSessionId>,
+    Json(body): Json<StaffParams>,
+) -> Result<()> {
+    staff_user(&app, body.github_user_id).await?;
+    app.db.end_impersonation_session(id).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct ImpersonatedEntitlements {
+    plan: &'static str,
+    max_call_participants: u64,
+    max_root_channels: u64,
+    monthly_language_model_tokens: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct GetImpersonatedBillingResponse {
+    impersonation: ImpersonationSessionJson,
+    entitlements: ImpersonatedEntitlements,
+    subscriptions: Vec<BillingSubscription>,
+}
+
+/// Returns the billing state and entitlements of the impersonated user, as
+/// they see them.
+async fn get_impersonated_billing(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<ImpersonationSessionId>,
+    Query(params): Query<StaffParams>,
+) -> Result<Json<GetImpersonatedBillingResponse>> {
+    staff_user(&app, params.github_user_id).await?;
+    let session = active_session(&app, id).await?;
+    app.db
+        .record_impersonated_request(
+            session.id,
+            Method::GET.as_str(),
+            &format!("/impersonation_sessions/{id}/billing"),
+            true,
+        )
+        .await?;
+
+    let user_id = session.impersonated_user_id;
+    let entitlements = app.entitlements.for_user(user_id).await?;
+    let subscriptions = app.db.get_billing_subscriptions(user_id).await?;
+
+    Ok(Json(GetImpersonatedBillingResponse {
+        impersonation: session_json(&app, session).await?,
+        entitlements: ImpersonatedEntitlements {
+            plan: entitlements.plan.as_str(),
+            max_call_participants: entitlements.max_call_participants,
+            max_root_channels: entitlements.max_root_channels,
+            monthly_language_model_tokens: entitlements.monthly_language_model_tokens,
+        },
+        subscriptions: subscriptions.into_iter().map(Into::into).collect(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct ImpersonatedRequest {
+    method: String,
+    path: String,
+    allowed: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    requested_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetImpersonatedRequestsResponse {
+    requests: Vec<ImpersonatedRequest>,
+}
+
+/// Returns the audit log of the requests made during an impersonation
+/// session.
+async fn get_impersonated_requests(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<ImpersonationSessionId>,
+    Query(params): Query<StaffParams>,
+) -> Result<Json<GetImpersonatedRequestsResponse>> {
+    staff_user(&app, params.github_user_id).await?;
+    let requests = app.db.get_impersonated_requests(id).await?;
+    Ok(Json(GetImpersonatedRequestsResponse {
+        requests: requests
+            .into_iter()
+            .map(|request| ImpersonatedRequest {
+                method: request.method,
+                path: request.path,
+                allowed: request.allowed,
+                requested_at: request.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
+
+/// Identifies the impersonation session whose token a request was
+/// authenticated with.
+#[derive(Clone, Copy, Debug)]
+pub struct ImpersonationSessionToken(ImpersonationSessionId);
+
+/// Returns the impersonation session the given API token was issued for, if
+/// any.
+pub async fn session_token(
+    app: &AppState,
+    token: &str,
+) -> Result<Option<ImpersonationSessionToken>> {
+    Ok(app
+        .db
+        .get_impersonation_session_by_token_hash(&hash_access_token(token))
+        .await?
+        .map(|session| ImpersonationSessionToken(session.id)))
+}
+
+/// A field of a request that names the user it acts as.
+#[derive(Debug, PartialEq, Eq)]
+struct UserIdentifier {
+    field: &'static str,
+    value: String,
+}
+
+impl UserIdentifier {
+    fn matches(&self, user: &User) -> bool {
+        match self.field {
+            "user_id" => self.value == user.id.to_string(),
+            "github_user_id" => user
+                .github_user_id
+                .map_or(false, |id| self.value == id.to_string()),
+            _ => self.value.eq_ignore_ascii_case(&user.github_login),
+        }
+    }
+}
+
+/// Returns why a request made during an impersonation session must be
+/// refused, if it must.
+///
+/// The request names the users it acts as through the identifiers of its
+/// query string and of its JSON body, which must all be the impersonated
+/// user. Requests that change state must name them.
+fn check_impersonated_request(
+    session: &impersonation_session::Model,
+    impersonated_user: &User,
+    method: &Method,
+    identifiers: &[UserIdentifier],
+    now: PrimitiveDateTime,
+) -> Option<&'static str> {
+    if !session.is_active(now) {
+        return Some("impersonation session has ended");
+    }
+    let is_read = *method == Method::GET || *method == Method::HEAD;
+    if !is_read && !session.allow_writes {
+        return Some("impersonation session is read-only");
+    }
+    if identifiers
+        .iter()
+        .any(|identifier| !identifier.matches(impersonated_user))
+    {
+        return Some("impersonation session is for a different user");
+    }
+    if !is_read && identifiers.is_empty() {
+        return Some("requests made while impersonating must name the impersonated user");
+    }
+    None
+}
+
+/// Returns the user identifiers of the request's query string.
+fn query_user_identifiers(params: &HashMap<String, String>) -> Vec<UserIdentifier> {
+    USER_IDENTIFIER_FIELDS
+        .iter()
+        .filter_map(|&field| {
+            Some(UserIdentifier {
+                field,
+                value: params.get(field)?.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the user identifiers of the request's JSON body.
+fn body_user_identifiers(body: &[u8]) -> Vec<UserIdentifier> {
+    let Ok(body) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return Vec::new();
+    };
+    USER_IDENTIFIER_FIELDS
+        .iter()
+        .filter_map(|&field| {
+            let value = match body.get(field)? {
+                serde_json::Value::Number(value) => value.to_string(),
+                serde_json::Value::String(value) => value.clone(),
+                // Identifiers of the wrong type never match the impersonated
+                // user.
+                _ => String::new(),
+            };
+            Some(UserIdentifier { field, value })
+        })
+        .collect()
+}
+
+/// Restricts requests made during an impersonation session to what the
+/// session allows, records them in the session's audit log, and tells the
+/// client who is being impersonated through response headers.
+///
+/// Requests are made during a session when they're authenticated with its
+/// token, or name it in their headers.
+pub async fn enforce_impersonation(req: Request<Body>, next: Next<Body>) -> impl IntoResponse {
+    let token_session_id = req
+        .extensions()
+        .get::<ImpersonationSessionToken>()
+        .map(|token| token.0);
+    let header_session_id = req.headers().get(IMPERSONATION_SESSION_HEADER);
+    if token_session_id.is_none() && header_session_id.is_none() {
+        return Ok::<_, Error>(next.run(req).await);
+    }
+    let header_session_id = header_session_id
+        .map(|session_id| {
+            session_id
+                .to_str()
+                .ok()
+                .and_then(|id| id.parse().ok())
+                .map(ImpersonationSessionId)
+                .ok_or_else(|| {
+                    Error::Http(
+                        StatusCode::BAD_REQUEST,
+                        "invalid impersonation session header".into(),
+                    )
+                })
+        })
+        .transpose()?;
+    let session_id = match (token_session_id, header_session_id) {
+        (Some(token_session_id), Some(header_session_id))
+            if token_session_id != header_session_id =>
+        {
+            Err(Error::Http(
+                StatusCode::FORBIDDEN,
+                "impersonation session doesn't match the token".into(),
+            ))?
+        }
+        (Some(session_id), _) | (None, Some(session_id)) => session_id,
+        (None, None) => unreachable!(),
+    };
+
+    let app = req.extensions().get::<Arc<AppState>>().unwrap().clone();
+    let session = app
+        .db
+        .get_impersonation_session(session_id)
+        .await?
+        .ok_or_else(|| {
+            Error::Http(
+                StatusCode::FORBIDDEN,
+                "impersonation session not found".into(),
+            )
+        })?;
+    let impersonated_user = app
+        .db
+        .get_user_by_id(session.impersonated_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("impersonated user not found"))?;
+
+    let params = Query::<HashMap<String, String>>::try_from_uri(req.uri())
+        .map(|Query(params)| params)
+        .unwrap_or_default();
+    let mut identifiers = query_user_identifiers(&params);
+
+    // The body is read to find the user it names, and then put back for the
+    // handler.
+    let (parts, body) = req.into_parts();
+    let body = Bytes::from_request(Request::new(body), &())
+        .await
+        .map_err(|_| {
+            Error::Http(
+                StatusCode::BAD_REQUEST,
+                "failed to read request body".into(),
+            )
+        })?;
+    identifiers.extend(body_user_identifiers(&body));
+    let req = Request::from_parts(parts, Body::from(body));
+
+    let refusal = check_impersonated_request(
+        &session,
+        &impersonated_user,
+        req.method(),
+        &identifiers,
+        now(),
+    );
+    app.db
+        .record_impersonated_request(
+            session.id,
+            req.method().as_str(),
+            req.uri().path(),
+            refusal.is_none(),
+        )
+        .await?;
+    if let Some(refusal) = refusal {
+        Err(Error::Http(StatusCode::FORBIDDEN, refusal.into()))?
+    }
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    if let Ok(login) = HeaderValue::from_str(&impersonated_user.github_login) {
+        headers.insert("x-zed-impersonating", login);
+    }
+    if let Ok(expires_at) = session
+        .expires_at
+        .assume_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+    {
+        if let Ok(expires_at) = HeaderValue::from_str(&expires_at) {
+            headers.insert("x-zed-impersonation-expires-at", expires_at);
+        }
+    }
+    headers.insert(
+        "x-zed-impersonation-read-only",
+        HeaderValue::from_static(if session.allow_writes {
+            "false"
+        } else {
+            "true"
+        }),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::UserId;
+
+    #[test]
+    fn test_check_impersonated_request() {
+        let now = now();
+        let mut session = impersonation_session::Model {
+            id: ImpersonationSessionId(1),
+            staff_user_id: UserId(1),
+            impersonated_user_id: UserId(2),
+            reason: "support ticket 123".into(),
+            allow_writes: false,
+            expires_at: now + DEFAULT_SESSION_DURATION,
+            ended_at: None,
+            token_hash: None,
+            created_at: now,
+        };
+        let user = User {
+            id: UserId(2),
+            github_login: "octocat".into(),
+            github_user_id: Some(42),
+            ..Default::default()
+        };
+
+        let ids = |ids: &[&str]| {
+            ids.iter()
+                .map(|id| UserIdentifier {
+                    field: "github_user_id",
+                    value: id.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            check_impersonated_request(&session, &user, &Method::GET, &ids(&["42"]), now),
+            None
+        );
+        assert_eq!(
+            check_impersonated_request(&session, &user, &Method::GET, &[], now),
+            None
+        );
+        assert!(
+            check_impersonated_request(&session, &user, &Method::GET, &ids(&["7"]), now).is_some()
+        );
+        assert!(
+            check_impersonated_request(&session, &user, &Method::POST, &ids(&["42"]), now)
+                .is_some()
+        );
+        assert!(check_impersonated_request(
+            &session,
+            &user,
+            &Method::GET,
+            &[],
+            now + MAX_SESSION_DURATION
+        )
+        .is_some());
+
+        // Writes need to be granted separately, and must be made as the
+        // impersonated user.
+        session.allow_writes = true;
+        assert_eq!(
+            check_impersonated_request(&session, &user, &Method::POST, &ids(&["42"]), now),
+            None
+        );
+        assert!(check_impersonated_request(&session, &user, &Method::POST, &[], now).is_some());
+        assert!(check_impersonated_request(
+            &session,
+            &user,
+            &Method::POST,
+            &ids(&["42", "7"]),
+            now
+        )
+        .is_some());
+
+        // Every identifier the request names the user by must match.
+        let identifiers = body_user_identifiers(br#"{"github_user_id": 42, "user_id": 3}"#);
+        assert!(
+            check_impersonated_request(&session, &user, &Method::POST, &identifiers, now).is_some()
+        );
+        let identifiers = body_user_identifiers(br#"{"user_id": 2, "login": "OctoCat"}"#);
+        assert_eq!(
+            check_impersonated_request(&session, &user, &Method::POST, &identifiers, now),
+            None
+        );
+
+        session.ended_at = Some(now);
+        assert!(check_impersonated_request(&session, &user, &Method::GET, &[], now).is_some());
+    }
+
+    #[test]
+    fn test_user_identifiers() {
+        let identifier = |field, value: &str| UserIdentifier {
+            field,
+            value: value.into(),
+        };
+        assert_eq!(
+            body_user_identifiers(br#"{"github_user_id": 42, "github_login": "octocat"}"#),
+            [
+                identifier("github_user_id", "42"),
+                identifier("github_login", "octocat")
+            ]
+        );
+        assert!(body_user_identifiers(br#"{"plan": "pro"}"#).is_empty());
+        assert!(body_user_identifiers(b"").is_empty());
+        // Identifiers of the wrong type never match the impersonated user.
+        assert_eq!(
+            body_user_identifiers(br#"{"user_id": [2]}"#),
+            [identifier("user_id", "")]
+        );
+
+        let params = HashMap::from_iter([
+            ("user_id".to_string(), "2".to_string()),
+            ("login".to_string(), "octocat".to_string()),
+            ("plan".to_string(), "pro".to_string()),
+        ]);
+        assert_eq!(
+            query_user_identifiers(&params),
+            [identifier("user_id", "2"), identifier("login", "octocat")]
+        );
+    }
+}
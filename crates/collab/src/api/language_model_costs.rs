@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, http::StatusCode, routing::get, Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date, Duration, Time};
+
+use crate::{AppState, Error, Result};
+
+/// The maximum number of days that can be requested at once.
+pub(crate) const MAX_REPORT_DAYS: i64 = 92;
+
+pub fn router() -> Router {
+    Router::new().route("/language_model_costs", get(get_language_model_costs))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetLanguageModelCostsParams {
+    /// The first day to report on, in `YYYY-MM-DD` format.
+    start_date: String,
+    /// The last day to report on (inclusive), in `YYYY-MM-DD` format.
+    end_date: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LanguageModelCost {
+    date: String,
+    feature: Option<String>,
+    provider: String,
+    request_count: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    upstream_cost_in_cents: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct GetLanguageModelCostsResponse {
+    costs: Vec<LanguageModelCost>,
+}
+
+/// Reports the upstream cost of language model requests per feature per day.
+async fn get_language_model_costs(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetLanguageModelCostsParams>,
+) -> Result<Json<GetLanguageModelCostsResponse>> {
+    let start_date = parse_date(&params.start_date)?;
+    let end_date = parse_date(&params.end_date)?;
+    if end_date < start_date {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "end_date must not be before start_date".into(),
+        ))?
+    }
+    if (end_date - start_date).whole_days() >= MAX_REPORT_DAYS {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("cannot report on more than {MAX_REPORT_DAYS} days at once"),
+        ))?
+    }
+
+    let start = start_date.with_time(Time::MIDNIGHT).assume_utc();
+    let end = (end_date + Duration::days(1))
+        .with_time(Time::MIDNIGHT)
+        .assume_utc();
+    let costs = app
+        .db
+        .get_language_model_costs_by_feature(start, end)
+        .await?;
+
+    Ok(Json(GetLanguageModelCostsResponse {
+        costs: costs
+            .into_iter()
+            .map(|cost| LanguageModelCost {
+                date: cost.date.to_string(),
+                feature: cost.feature,
+                provider: cost.provider,
+                request_count: cost.request_count,
+                input_tokens: cost.input_tokens,
+                output_tokens: cost.output_tokens,
+                upstream_cost_in_cents: cost.upstream_cost_in_millicents as f64 / 1000.,
+            })
+            .collect(),
+    }))
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    Date::parse(date, format_description!("[year]-[month]-[day]")).map_err(|_| {
+        Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("invalid date {date:?}, expected YYYY-MM-DD"),
+        )
+    })
+}
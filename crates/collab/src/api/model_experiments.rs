@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{self, Path},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{CreateModelExperimentVariantParams, ModelExperimentId};
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/model_experiments", post(create_model_experiment))
+        .route(
+            "/model_experiments/:id/deactivate",
+            post(deactivate_model_experiment),
+        )
+        .route(
+            "/model_experiments/:id/exposures",
+            get(get_model_experiment_exposures),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelExperimentVariantBody {
+    name: String,
+    weight: i32,
+    provider: String,
+    model: String,
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateModelExperimentBody {
+    name: String,
+    variants: Vec<ModelExperimentVariantBody>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateModelExperimentResponse {
+    id: ModelExperimentId,
+}
+
+/// Starts a new experiment. Users who haven't chosen a default model will be
+/// served the model of the variant they are assigned to.
+async fn create_model_experiment(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<CreateModelExperimentBody>,
+) -> Result<Json<CreateModelExperimentResponse>> {
+    if body.variants.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "an experiment needs at least one variant".into(),
+        ))?
+    }
+    if body.variants.iter().any(|variant| variant.weight < 0) {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "variant weights must not be negative".into(),
+        ))?
+    }
+
+    let variants = body
+        .variants
+        .into_iter()
+        .map(|variant| CreateModelExperimentVariantParams {
+            name: variant.name,
+            weight: variant.weight,
+            provider: variant.provider,
+            model: variant.model,
+            temperature: variant.temperature,
+        })
+        .collect::<Vec<_>>();
+    let (experiment, _) = app
+        .db
+        .create_model_experiment(&body.name, &variants)
+        .await?;
+
+    Ok(Json(CreateModelExperimentResponse { id: experiment.id }))
+}
+
+/// Stops serving an experiment's models to users.
+async fn deactivate_model_experiment(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<ModelExperimentId>,
+) -> Result<()> {
+    app.db.deactivate_model_experiment(id).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct GetModelExperimentExposuresResponse {
+    /// The number of users exposed to each variant, keyed by variant ID.
+    exposures: Vec<VariantExposures>,
+}
+
+#[derive(Debug, Serialize)]
+struct VariantExposures {
+    variant_id: i32,
+    user_count: i64,
+}
+
+/// Returns the number of users exposed to each variant of an experiment.
+async fn get_model_experiment_exposures(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<ModelExperimentId>,
+) -> Result<Json<GetModelExperimentExposuresResponse>> {
+    let mut exposures = app
+        .db
+        .get_model_experiment_exposure_counts(id)
+        .await?
+        .into_iter()
+        .map(|(variant_id, user_count)| VariantExposures {
+            variant_id: variant_id.0,
+            user_count,
+        })
+        .collect::<Vec<_>>();
+    exposures.sort_by_key(|exposure| exposure.variant_id);
+
+    Ok(Json(GetModelExperimentExposuresResponse { exposures }))
+}
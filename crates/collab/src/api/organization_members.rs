@@ -0,0 +1,456 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::db::organization::SeatPolicy;
+use crate::db::organization_member::{self, OrganizationRole};
+use crate::db::{organization, OrganizationId, User, UserId};
+use crate::plan_enforcement;
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/orgs/:id/members", get(get_organization_members))
+        .route(
+            "/orgs/:id/members/:member_github_user_id",
+            delete(remove_organization_member),
+        )
+        .route("/orgs/:id/invitations", post(invite_organization_member))
+        .route(
+            "/orgs/:id/invitations/accept",
+            post(accept_organization_invitation),
+        )
+        .route(
+            "/orgs/:id/invitations/:invitee_github_user_id",
+            delete(delete_organization_invitation),
+        )
+}
+
+/// Returns the organization and the user with the given GitHub user ID,
+/// along with their membership in it, if any.
+async fn organization_and_user(
+    app: &AppState,
+    organization_id: OrganizationId,
+    github_user_id: i32,
+) -> Result<(
+    organization::Model,
+    User,
+    Option<organization_member::Model>,
+)> {
+    let user = user_by_github_user_id(app, github_user_id).await?;
+    let organization = app
+        .db
+        .get_organization_by_id(organization_id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "organization not found".into()))?;
+    let membership = app
+        .db
+        .get_organization_member(organization.id, user.id)
+        .await?;
+    Ok((organization, user, membership))
+}
+
+async fn user_by_github_user_id(app: &AppState, github_user_id: i32) -> Result<User> {
+    Ok(app
+        .db
+        .get_user_by_github_user_id(github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?)
+}
+
+/// Returns an error if every seat the organization's subscription pays for
+/// is taken, by members and by the invitations awaiting an answer when
+/// `include_invitations` is set.
+///
+/// Organizations whose seats are adjusted to their member count, or that no
+/// subscription pays for, have no limit.
+async fn ensure_seat_available(
+    app: &AppState,
+    organization: &organization::Model,
+    include_invitations: bool,
+) -> Result<()> {
+    if organization.seat_policy == SeatPolicy::AutoAdjust {
+        return Ok(());
+    }
+    let Some(billing_subscription_id) = organization.billing_subscription_id else {
+        return Ok(());
+    };
+    let Some(subscription) = app
+        .db
+        .get_billing_subscription_by_id(billing_subscription_id)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let mut taken_seat_count = app
+        .db
+        .get_organization_member_count(organization.id)
+        .await?;
+    if include_invitations {
+        taken_seat_count += app
+            .db
+            .get_organization_invitations(organization.id)
+            .await?
+            .len() as u64;
+    }
+    if !has_free_seat(subscription.seat_count, taken_seat_count) {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            format!(
+                "all {} seats of the organization's subscription are taken",
+                subscription.seat_count
+            ),
+        ))?
+    }
+    Ok(())
+}
+
+fn has_free_seat(seat_count: i32, taken_seat_count: u64) -> bool {
+    taken_seat_count < seat_count.max(0) as u64
+}
+
+#[derive(Debug, Deserialize)]
+struct GetOrganizationMembersParams {
+    /// The GitHub user ID of the member requesting the members.
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct OrganizationMemberJson {
+    github_user_id: Option<i32>,
+    github_login: String,
+    role: OrganizationRole,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetOrganizationMembersResponse {
+    members: Vec<OrganizationMemberJson>,
+    /// The invitations that haven't been accepted yet. Only shown to the
+    /// organization's owners and admins.
+    invitations: Vec<OrganizationMemberJson>,
+}
+
+/// Returns the members of the organization, and for its owners and admins,
+/// who has been invited to it.
+async fn get_organization_members(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<GetOrganizationMembersParams>,
+) -> Result<Json<GetOrganizationMembersResponse>> {
+    let (organization, _, membership) =
+        organization_and_user(&app, organization_id, params.github_user_id).await?;
+    let Some(membership) = membership else {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "only organization members can do this".into(),
+        ))?
+    };
+
+    let members = app.db.get_organization_members(organization.id).await?;
+    let invitations = if membership.role.can_administer() {
+        app.db.get_organization_invitations(organization.id).await?
+    } else {
+        Vec::new()
+    };
+    let users_by_id = app
+        .db
+        .get_users_by_ids(
+            members
+                .iter()
+                .map(|member| member.user_id)
+                .chain(invitations.iter().map(|invitation| invitation.user_id))
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect::<HashMap<_, _>>();
+    let member_json = |user_id: UserId, role: OrganizationRole, created_at: PrimitiveDateTime| {
+        let user = users_by_id.get(&user_id);
+        OrganizationMemberJson {
+            github_user_id: user.and_then(|user| user.github_user_id),
+            github_login: user
+                .map(|user| user.github_login.clone())
+                .unwrap_or_default(),
+            role,
+            created_at: created_at.assume_utc(),
+        }
+    };
+
+    Ok(Json(GetOrganizationMembersResponse {
+        members: members
+            .iter()
+            .map(|member| member_json(member.user_id, member.role, member.created_at))
+            .collect(),
+        invitations: invitations
+            .iter()
+            .map(|invitation| {
+                member_json(invitation.user_id, invitation.role, invitation.created_at)
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteOrganizationMemberBody {
+    /// The GitHub user ID of the owner or admin sending the invitation.
+    github_user_id: i32,
+    invitee_github_user_id: i32,
+    role: OrganizationRole,
+}
+
+/// Invites a user to join the organization. Once they accept, they're
+/// covered by the subscription that pays for its seats.
+async fn invite_organization_member(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<InviteOrganizationMemberBody>,
+) -> Result<Json<OrganizationMemberJson>> {
+    let (organization, inviter, membership) =
+        organization_and_user(&app, organization_id, body.github_user_id).await?;
+    if !membership.map_or(false, |membership| membership.role.can_manage(body.role)) {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            format!(
+                "you can't invite {}s to this organization",
+                role_name(body.role)
+            ),
+        ))?
+    }
+
+    let invitee = user_by_github_user_id(&app, body.invitee_github_user_id).await?;
+    if app
+        .db
+        .get_organization_member(organization.id, invitee.id)
+        .await?
+        .is_some()
+    {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "the user is already a member of the organization".into(),
+        ))?
+    }
+    ensure_seat_available(&app, &organization, true).await?;
+
+    let invitation = app
+        .db
+        .invite_organization_member(organization.id, invitee.id, body.role, inviter.id)
+        .await?;
+    log::info!(
+        "user {} invited {} to organization {} as {}",
+        inviter.github_login,
+        invitee.github_login,
+        organization.slug,
+        role_name(invitation.role)
+    );
+    Ok(Json(OrganizationMemberJson {
+        github_user_id: invitee.github_user_id,
+        github_login: invitee.github_login,
+        role: invitation.role,
+        created_at: invitation.created_at.assume_utc(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct AcceptOrganizationInvitationBody {
+    /// The GitHub user ID of the invitee.
+    github_user_id: i32,
+}
+
+/// Accepts the user's invitation to the organization, making them a member.
+async fn accept_organization_invitation(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<AcceptOrganizationInvitationBody>,
+) -> Result<Json<OrganizationMemberJson>> {
+    let (organization, user, _) =
+        organization_and_user(&app, organization_id, body.github_user_id).await?;
+    // Seats may have been removed since the user was invited.
+    ensure_seat_available(&app, &organization, false).await?;
+    let Some(member) = app
+        .db
+        .accept_organization_invitation(organization.id, user.id)
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "no invitation to the organization found".into(),
+        ))?
+    };
+    log::info!(
+        "user {} joined organization {}",
+        user.github_login,
+        organization.slug
+    );
+
+    billing_status_updated(&app, rpc_server.as_ref(), user.id).await;
+    Ok(Json(OrganizationMemberJson {
+        github_user_id: user.github_user_id,
+        github_login: user.github_login,
+        role: member.role,
+        created_at: member.created_at.assume_utc(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteOrganizationInvitationParams {
+    /// The GitHub user ID of the owner or admin withdrawing the invitation,
+    /// or of the invitee declining it.
+    github_user_id: i32,
+}
+
+/// Withdraws or declines an invitation to the organization.
+async fn delete_organization_invitation(
+    Extension(app): Extension<Arc<AppState>>,
+    Path((organization_id, invitee_github_user_id)): Path<(OrganizationId, i32)>,
+    Query(params): Query<DeleteOrganizationInvitationParams>,
+) -> Result<()> {
+    let (organization, user, membership) =
+        organization_and_user(&app, organization_id, params.github_user_id).await?;
+    let invitee = user_by_github_user_id(&app, invitee_github_user_id).await?;
+    if invitee.id != user.id
+        && !membership.map_or(false, |membership| membership.role.can_administer())
+    {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "only organization admins can do this".into(),
+        ))?
+    }
+
+    if !app
+        .db
+        .delete_organization_invitation(organization.id, invitee.id)
+        .await?
+    {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "no invitation to the organization found".into(),
+        ))?
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoveOrganizationMemberParams {
+    /// The GitHub user ID of the owner or admin removing the member, or of
+    /// the member leaving.
+    github_user_id: i32,
+}
+
+/// Removes a member from the organization, which stops its subscription from
+/// covering them. Members can also remove themselves, except for owners.
+async fn remove_organization_member(
+    Extension(app): Extension<Arc<AppState>>,
+    Extension(rpc_server): Extension<Option<Arc<crate::rpc::Server>>>,
+    Path((organization_id, member_github_user_id)): Path<(OrganizationId, i32)>,
+    Query(params): Query<RemoveOrganizationMemberParams>,
+) -> Result<()> {
+    let (organization, user, membership) =
+        organization_and_user(&app, organization_id, params.github_user_id).await?;
+    let removed_user = user_by_github_user_id(&app, member_github_user_id).await?;
+    let Some(removed_member) = app
+        .db
+        .get_organization_member(organization.id, removed_user.id)
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "the user isn't a member of the organization".into(),
+        ))?
+    };
+
+    let is_leaving = removed_user.id == user.id;
+    let allowed = if is_leaving {
+        removed_member.role != OrganizationRole::Owner
+    } else {
+        membership.map_or(false, |membership| {
+            membership.role.can_manage(removed_member.role)
+        })
+    };
+    if !allowed {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            format!(
+                "you can't remove {}s from this organization",
+                role_name(removed_member.role)
+            ),
+        ))?
+    }
+
+    app.db
+        .remove_organization_member(organization.id, removed_user.id)
+        .await?;
+    log::info!(
+        "user {} removed {} from organization {}",
+        user.github_login,
+        removed_user.github_login,
+        organization.slug
+    );
+
+    billing_status_updated(&app, rpc_server.as_ref(), removed_user.id).await;
+    Ok(())
+}
+
+fn role_name(role: OrganizationRole) -> &'static str {
+    match role {
+        OrganizationRole::Owner => "owner",
+        OrganizationRole::Admin => "admin",
+        OrganizationRole::Member => "member",
+    }
+}
+
+async fn billing_status_updated(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    user_id: UserId,
+) {
+    plan_enforcement::plan_changed(app, user_id).await.log_err();
+    if let Some(rpc_server) = rpc_server {
+        rpc_server.billing_status_updated(user_id).await.log_err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        use OrganizationRole::*;
+
+        assert!(Owner.can_manage(Admin));
+        assert!(Owner.can_manage(Member));
+        assert!(Admin.can_manage(Member));
+        assert!(!Admin.can_manage(Admin));
+        assert!(!Member.can_manage(Member));
+        for role in [Owner, Admin, Member] {
+            assert!(!role.can_manage(Owner));
+        }
+
+        assert!(Owner.can_administer());
+        assert!(Admin.can_administer());
+        assert!(!Member.can_administer());
+    }
+
+    #[test]
+    fn test_has_free_seat() {
+        assert!(has_free_seat(5, 4));
+        assert!(!has_free_seat(5, 5));
+        assert!(!has_free_seat(5, 6));
+        assert!(!has_free_seat(0, 0));
+    }
+}
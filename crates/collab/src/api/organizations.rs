@@ -0,0 +1,741 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    body::StreamBody,
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date, Duration, Month, Time};
+
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::organization::SeatPolicy;
+use crate::db::organization_member_daily_usage::{self, ModelUsage, TopModels};
+use crate::db::organization_seat_true_up::SeatTrueUpOutcome;
+use crate::db::{organization, BillingSubscriptionId, OrganizationId, UserId};
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/orgs", post(create_organization))
+        .route("/orgs/:id/usage", get(get_organization_usage))
+        .route(
+            "/orgs/:id/usage/members",
+            get(get_organization_member_usage),
+        )
+        .route("/orgs/:id/usage/export", get(export_organization_usage))
+        .route(
+            "/orgs/:id/secrets/:name",
+            put(update_organization_secret).delete(delete_organization_secret),
+        )
+        .route("/orgs/:id/billing", put(update_organization_billing))
+        .route(
+            "/orgs/:id/seat_true_ups",
+            get(get_organization_seat_true_ups),
+        )
+}
+
+/// Returns the organization, provided the user with the given GitHub user ID
+/// is one of its admins.
+async fn organization_for_admin(
+    app: &AppState,
+    organization_id: OrganizationId,
+    github_user_id: i32,
+) -> Result<organization::Model> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let organization = app
+        .db
+        .get_organization_by_id(organization_id)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "organization not found".into()))?;
+
+    let membership = app
+        .db
+        .get_organization_member(organization.id, user.id)
+        .await?;
+    if !membership.map_or(false, |membership| membership.role.can_administer()) {
+        Err(Error::Http(
+            StatusCode::FORBIDDEN,
+            "only organization admins can do this".into(),
+        ))?
+    }
+
+    Ok(organization)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateOrganizationBody {
+    /// The GitHub user ID of the user creating the organization, who becomes
+    /// its owner.
+    github_user_id: i32,
+    name: String,
+    slug: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OrganizationJson {
+    id: OrganizationId,
+    name: String,
+    slug: String,
+}
+
+/// Creates an organization, owned by the user creating it. Its seats can
+/// then be paid for with one of their team subscriptions, covering everyone
+/// they invite to it.
+async fn create_organization(
+    Extension(app): Extension<Arc<AppState>>,
+    Json(body): Json<CreateOrganizationBody>,
+) -> Result<Json<OrganizationJson>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "organization name must not be empty".into(),
+        ))?
+    }
+    if !is_valid_slug(&body.slug) {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "invalid slug {:?}, expected lowercase letters, digits, and dashes",
+                body.slug
+            ),
+        ))?
+    }
+
+    let Some(organization) = app
+        .db
+        .create_organization_with_owner(name, &body.slug, user.id)
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "an organization with that slug already exists".into(),
+        ))?
+    };
+    log::info!(
+        "user {} created organization {}",
+        user.github_login,
+        organization.slug
+    );
+    Ok(Json(OrganizationJson {
+        id: organization.id,
+        name: organization.name,
+        slug: organization.slug,
+    }))
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 64
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateOrganizationSecretBody {
+    /// The GitHub user ID of the admin updating the secret.
+    github_user_id: i32,
+    value: String,
+}
+
+/// Stores a secret that the organization's members can lease, such as a
+/// shared provider API key.
+async fn update_organization_secret(
+    Extension(app): Extension<Arc<AppState>>,
+    Path((organization_id, name)): Path<(OrganizationId, String)>,
+    Json(body): Json<UpdateOrganizationSecretBody>,
+) -> Result<()> {
+    let organization = organization_for_admin(&app, organization_id, body.github_user_id).await?;
+    if body.value.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "secret value must not be empty".into(),
+        ))?
+    }
+
+    app.db
+        .upsert_organization_secret(organization.id, &name, &body.value)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteOrganizationSecretParams {
+    /// The GitHub user ID of the admin deleting the secret.
+    github_user_id: i32,
+}
+
+async fn delete_organization_secret(
+    Extension(app): Extension<Arc<AppState>>,
+    Path((organization_id, name)): Path<(OrganizationId, String)>,
+    Query(params): Query<DeleteOrganizationSecretParams>,
+) -> Result<()> {
+    let organization = organization_for_admin(&app, organization_id, params.github_user_id).await?;
+    app.db
+        .delete_organization_secret(organization.id, &name)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateOrganizationBillingBody {
+    /// The GitHub user ID of the admin updating the billing.
+    github_user_id: i32,
+    /// The team subscription that pays for the organization's seats. It must
+    /// belong to the admin making the request.
+    billing_subscription_id: Option<BillingSubscriptionId>,
+    #[serde(default)]
+    seat_policy: SeatPolicy,
+}
+
+#[derive(Debug, Serialize)]
+struct OrganizationBillingJson {
+    billing_subscription_id: Option<BillingSubscriptionId>,
+    seat_policy: SeatPolicy,
+}
+
+/// Sets the subscription that pays for the organization's seats, and whether
+/// the seats are adjusted automatically as its membership changes.
+async fn update_organization_billing(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<UpdateOrganizationBillingBody>,
+) -> Result<Json<OrganizationBillingJson>> {
+    let organization = organization_for_admin(&app, organization_id, body.github_user_id).await?;
+
+    if let Some(billing_subscription_id) = body.billing_subscription_id {
+        let user = app
+            .db
+            .get_user_by_github_user_id(body.github_user_id)
+            .await?
+            .ok_or_else(|| anyhow!("user not found"))?;
+        let billing_customer = app.db.get_billing_customer_by_user_id(user.id).await?;
+        let subscription = app
+            .db
+            .get_billing_subscription_by_id(billing_subscription_id)
+            .await?
+            .filter(|subscription| {
+                billing_customer.as_ref().map(|customer| customer.id)
+                    == Some(subscription.billing_customer_id)
+            })
+            .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "subscription not found".into()))?;
+        if subscription.plan != SubscriptionPlan::Team {
+            Err(Error::Http(
+                StatusCode::BAD_REQUEST,
+                "only team subscriptions can pay for an organization's seats".into(),
+            ))?
+        }
+    }
+
+    let Some(organization) = app
+        .db
+        .set_organization_seat_billing(
+            organization.id,
+            body.billing_subscription_id,
+            body.seat_policy,
+        )
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "the subscription already pays for another organization's seats".into(),
+        ))?
+    };
+    Ok(Json(OrganizationBillingJson {
+        billing_subscription_id: organization.billing_subscription_id,
+        seat_policy: organization.seat_policy,
+    }))
+}
+
+/// The number of seat true-ups returned at once.
+const SEAT_TRUE_UP_LIMIT: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+struct GetOrganizationSeatTrueUpsParams {
+    /// The GitHub user ID of the admin requesting the true-ups.
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct SeatTrueUpJson {
+    billing_subscription_id: BillingSubscriptionId,
+    member_count: i32,
+    seat_count: i32,
+    outcome: SeatTrueUpOutcome,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: time::OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetOrganizationSeatTrueUpsResponse {
+    true_ups: Vec<SeatTrueUpJson>,
+}
+
+/// Returns the most recent discrepancies found between the organization's
+/// members and its seats, newest first.
+async fn get_organization_seat_true_ups(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<GetOrganizationSeatTrueUpsParams>,
+) -> Result<Json<GetOrganizationSeatTrueUpsResponse>> {
+    let organization = organization_for_admin(&app, organization_id, params.github_user_id).await?;
+    let true_ups = app
+        .db
+        .get_organization_seat_true_ups(organization.id, SEAT_TRUE_UP_LIMIT)
+        .await?;
+    Ok(Json(GetOrganizationSeatTrueUpsResponse {
+        true_ups: true_ups
+            .into_iter()
+            .map(|true_up| SeatTrueUpJson {
+                billing_subscription_id: true_up.billing_subscription_id,
+                member_count: true_up.member_count,
+                seat_count: true_up.seat_count,
+                outcome: true_up.outcome,
+                created_at: true_up.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
+
+/// The longest date range that usage can be queried for at once.
+const MAX_USAGE_RANGE_DAYS: i64 = 366;
+
+/// The number of models listed in a member's top models over a date range.
+const TOP_MODEL_COUNT: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct GetOrganizationUsageParams {
+    /// The GitHub user ID of the user requesting the usage.
+    github_user_id: i32,
+    /// The first day to return usage for, in `YYYY-MM-DD` format.
+    start: String,
+    /// The last day to return usage for, in `YYYY-MM-DD` format.
+    end: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MemberUsageJson {
+    user_id: UserId,
+    github_login: String,
+    request_count: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    estimated_cost_in_millicents: i64,
+    top_models: Vec<ModelUsage>,
+}
+
+#[derive(Debug, Serialize)]
+struct DailyMemberUsageJson {
+    date: String,
+    #[serde(flatten)]
+    usage: MemberUsageJson,
+}
+
+#[derive(Debug, Serialize)]
+struct GetOrganizationUsageResponse {
+    usages: Vec<DailyMemberUsageJson>,
+}
+
+/// Returns the per-member, per-day language model usage of an organization
+/// within a date range, as rolled up by the usage rollup job.
+///
+/// Only organization admins may view usage.
+async fn get_organization_usage(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<GetOrganizationUsageParams>,
+) -> Result<Json<GetOrganizationUsageResponse>> {
+    let (usages, github_logins) = organization_usage(&app, organization_id, &params).await?;
+    Ok(Json(GetOrganizationUsageResponse {
+        usages: usages
+            .into_iter()
+            .map(|usage| DailyMemberUsageJson {
+                date: usage.date.to_string(),
+                usage: MemberUsageJson {
+                    github_login: github_logins
+                        .get(&usage.user_id)
+                        .cloned()
+                        .unwrap_or_default(),
+                    user_id: usage.user_id,
+                    request_count: usage.request_count,
+                    input_tokens: usage.input_tokens,
+                    output_tokens: usage.output_tokens,
+                    estimated_cost_in_millicents: usage.estimated_cost_in_millicents,
+                    top_models: usage.top_models.0,
+                },
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct GetOrganizationMemberUsageResponse {
+    members: Vec<MemberUsageJson>,
+}
+
+/// Returns the language model usage of each of an organization's members
+/// within a date range, heaviest users first.
+///
+/// Only organization admins may view usage.
+async fn get_organization_member_usage(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<GetOrganizationUsageParams>,
+) -> Result<Json<GetOrganizationMemberUsageResponse>> {
+    let (usages, github_logins) = organization_usage(&app, organization_id, &params).await?;
+    let mut members = member_usage_totals(usages);
+    for member in &mut members {
+        member.github_login = github_logins
+            .get(&member.user_id)
+            .cloned()
+            .unwrap_or_default();
+    }
+    Ok(Json(GetOrganizationMemberUsageResponse { members }))
+}
+
+/// Returns the rolled up usage of an organization's members within the
+/// requested date range, along with the members' GitHub logins.
+async fn organization_usage(
+    app: &AppState,
+    organization_id: OrganizationId,
+    params: &GetOrganizationUsageParams,
+) -> Result<(
+    Vec<organization_member_daily_usage::Model>,
+    HashMap<UserId, String>,
+)> {
+    let organization = organization_for_admin(app, organization_id, params.github_user_id).await?;
+
+    let start = parse_date(&params.start)?;
+    let end = parse_date(&params.end)?;
+    if end < start || end - start >= Duration::days(MAX_USAGE_RANGE_DAYS) {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("the date range must span between 1 and {MAX_USAGE_RANGE_DAYS} days"),
+        ))?
+    }
+
+    let usages = app
+        .db
+        .get_organization_member_daily_usages(organization.id, start, end)
+        .await?;
+    let mut user_ids = usages.iter().map(|usage| usage.user_id).collect::<Vec<_>>();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    let github_logins = app
+        .db
+        .get_users_by_ids(user_ids)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user.github_login))
+        .collect();
+
+    Ok((usages, github_logins))
+}
+
+/// Sums up the daily usages of each member, ordering the members by the
+/// number of tokens they used, most first.
+fn member_usage_totals(
+    usages: Vec<organization_member_daily_usage::Model>,
+) -> Vec<MemberUsageJson> {
+    let mut totals =
+        HashMap::<UserId, (MemberUsageJson, HashMap<(String, String), i64>)>::default();
+    for usage in usages {
+        let (total, tokens_by_model) = totals.entry(usage.user_id).or_insert_with(|| {
+            (
+                MemberUsageJson {
+                    user_id: usage.user_id,
+                    github_login: String::new(),
+                    request_count: 0,
+                    input_tokens: 0,
+                    output_tokens: 0,
+                    estimated_cost_in_millicents: 0,
+                    top_models: Vec::new(),
+                },
+                HashMap::default(),
+            )
+        });
+        total.request_count += usage.request_count;
+        total.input_tokens += usage.input_tokens;
+        total.output_tokens += usage.output_tokens;
+        total.estimated_cost_in_millicents += usage.estimated_cost_in_millicents;
+        // Only each day's top models are kept, so these are approximate.
+        for model in usage.top_models.0 {
+            *tokens_by_model
+                .entry((model.provider, model.model))
+                .or_default() += model.tokens;
+        }
+    }
+
+    let mut members = totals
+        .into_values()
+        .map(|(mut total, tokens_by_model)| {
+            total.top_models = TopModels::from_tokens_by_model(tokens_by_model, TOP_MODEL_COUNT).0;
+            total
+        })
+        .collect::<Vec<_>>();
+    members.sort_by(|a, b| {
+        (b.input_tokens + b.output_tokens)
+            .cmp(&(a.input_tokens + a.output_tokens))
+            .then(a.user_id.cmp(&b.user_id))
+    });
+    members
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UsageExportFormat {
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportOrganizationUsageParams {
+    /// The GitHub user ID of the user requesting the export.
+    github_user_id: i32,
+    format: UsageExportFormat,
+    /// The month to export, in `YYYY-MM` format.
+    month: String,
+}
+
+const CSV_HEADER: &str =
+    "date,user_id,github_login,request_count,input_tokens,output_tokens,estimated_cost_usd\n";
+
+/// Exports the per-member, per-day language model usage of an organization.
+///
+/// Only organization admins may export usage.
+async fn export_organization_usage(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<ExportOrganizationUsageParams>,
+) -> Result<impl IntoResponse> {
+    let UsageExportFormat::Csv = params.format;
+    let organization = organization_for_admin(&app, organization_id, params.github_user_id).await?;
+
+    let start_date = parse_month(&params.month)?;
+    let end_date = next_month(start_date);
+    let start = start_date.with_time(Time::MIDNIGHT).assume_utc();
+    let end = end_date.with_time(Time::MIDNIGHT).assume_utc();
+
+    let members = app.db.get_organization_members(organization.id).await?;
+    let member_ids = members
+        .iter()
+        .map(|member| member.user_id)
+        .collect::<Vec<_>>();
+    let github_logins = app
+        .db
+        .get_users_by_ids(member_ids.clone())
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user.github_login))
+        .collect::<HashMap<_, _>>();
+    let usages = app
+        .db
+        .get_daily_language_model_usage_for_users(&member_ids, start, end)
+        .await?;
+
+    let rows = usages.into_iter().map(move |usage| {
+        let github_login = github_logins
+            .get(&usage.user_id)
+            .map(String::as_str)
+            .unwrap_or_default();
+        Ok::<_, Infallible>(format!(
+            "{},{},{},{},{},{},{:.5}\n",
+            usage.date,
+            usage.user_id,
+            csv_field(github_login),
+            usage.request_count,
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.upstream_cost_in_millicents as f64 / 100_000.,
+        ))
+    });
+    let body = StreamBody::new(futures::stream::iter(
+        std::iter::once(Ok(CSV_HEADER.to_string())).chain(rows),
+    ));
+
+    let filename = format!("{}-usage-{}.csv", organization.slug, params.month);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        body,
+    ))
+}
+
+fn parse_month(month: &str) -> Result<Date> {
+    Date::parse(
+        &format!("{month}-01"),
+        format_description!("[year]-[month]-[day]"),
+    )
+    .map_err(|_| {
+        Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("invalid month {month:?}, expected YYYY-MM"),
+        )
+    })
+}
+
+fn parse_date(date: &str) -> Result<Date> {
+    Date::parse(date, format_description!("[year]-[month]-[day]")).map_err(|_| {
+        Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("invalid date {date:?}, expected YYYY-MM-DD"),
+        )
+    })
+}
+
+fn next_month(date: Date) -> Date {
+    let (year, month) = match date.month() {
+        Month::December => (date.year() + 1, Month::January),
+        month => (date.year(), month.next()),
+    };
+    Date::from_calendar_date(year, month, 1).unwrap()
+}
+
+/// Quotes a CSV field if it contains characters that would otherwise break
+/// the row apart.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_range() {
+        let start = parse_month("2024-12").unwrap();
+        assert_eq!(
+            start,
+            Date::from_calendar_date(2024, Month::December, 1).unwrap()
+        );
+        assert_eq!(
+            next_month(start),
+            Date::from_calendar_date(2025, Month::January, 1).unwrap()
+        );
+        assert!(parse_month("2024-13").is_err());
+        assert!(parse_month("December").is_err());
+    }
+
+    #[test]
+    fn test_is_valid_slug() {
+        assert!(is_valid_slug("acme"));
+        assert!(is_valid_slug("acme-2"));
+        assert!(!is_valid_slug(""));
+        assert!(!is_valid_slug("Acme"));
+        assert!(!is_valid_slug("-acme"));
+        assert!(!is_valid_slug("acme corp"));
+        assert!(!is_valid_slug(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2024-08-26").unwrap(),
+            Date::from_calendar_date(2024, Month::August, 26).unwrap()
+        );
+        assert!(parse_date("2024-08").is_err());
+        assert!(parse_date("2024-02-30").is_err());
+    }
+
+    #[test]
+    fn test_member_usage_totals() {
+        use crate::db::OrganizationMemberDailyUsageId;
+
+        let usage = |id, user_id, day, tokens, models: &[(&str, i64)]| {
+            organization_member_daily_usage::Model {
+                id: OrganizationMemberDailyUsageId(id),
+                organization_id: OrganizationId(1),
+                user_id: UserId(user_id),
+                date: Date::from_calendar_date(2024, Month::August, day).unwrap(),
+                request_count: 1,
+                input_tokens: tokens,
+                output_tokens: 0,
+                estimated_cost_in_millicents: tokens / 10,
+                top_models: TopModels(
+                    models
+                        .iter()
+                        .map(|(model, tokens)| ModelUsage {
+                            provider: "anthropic".into(),
+                            model: model.to_string(),
+                            tokens: *tokens,
+                        })
+                        .collect(),
+                ),
+                updated_at: Date::from_calendar_date(2024, Month::August, day)
+                    .unwrap()
+                    .midnight(),
+            }
+        };
+
+        let totals = member_usage_totals(vec![
+            usage(1, 1, 1, 100, &[("claude-3-5-sonnet", 100)]),
+            usage(2, 2, 1, 300, &[("claude-3-haiku", 300)]),
+            usage(
+                3,
+                1,
+                2,
+                500,
+                &[("claude-3-haiku", 200), ("claude-3-opus", 300)],
+            ),
+        ]);
+        assert_eq!(
+            totals
+                .iter()
+                .map(|total| (
+                    total.user_id,
+                    total.request_count,
+                    total.input_tokens,
+                    total.estimated_cost_in_millicents
+                ))
+                .collect::<Vec<_>>(),
+            [(UserId(1), 2, 600, 60), (UserId(2), 1, 300, 30)]
+        );
+        assert_eq!(
+            totals[0]
+                .top_models
+                .iter()
+                .map(|model| (model.model.as_str(), model.tokens))
+                .collect::<Vec<_>>(),
+            [
+                ("claude-3-opus", 300),
+                ("claude-3-haiku", 200),
+                ("claude-3-5-sonnet", 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("octocat"), "octocat");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
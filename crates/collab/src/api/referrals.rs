@@ -0,0 +1,268 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{self, Query},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use util::ResultExt;
+
+use crate::api::billing::idempotency_key;
+use crate::db::{referral, UserId};
+use crate::payment_provider::{AdjustCustomerBalanceParams, PaymentProvider};
+use crate::{AppState, Error, Result};
+
+/// The maximum number of users a single user may refer within
+/// [`REFERRAL_VELOCITY_WINDOW`].
+const MAX_REFERRALS_PER_WINDOW: u64 = 10;
+const REFERRAL_VELOCITY_WINDOW: Duration = Duration::days(1);
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/referrals", post(create_referral))
+        .route("/referrals/code", get(get_referral_code))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetReferralCodeParams {
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct GetReferralCodeResponse {
+    code: String,
+}
+
+/// Returns the referral code for the user, creating it if necessary.
+async fn get_referral_code(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<GetReferralCodeParams>,
+) -> Result<Json<GetReferralCodeResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(params.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let referral_code = app.db.get_or_create_referral_code(user.id).await?;
+
+    Ok(Json(GetReferralCodeResponse {
+        code: referral_code.code,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateReferralBody {
+    /// The GitHub user ID of the user that was referred.
+    github_user_id: i32,
+    referral_code: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateReferralResponse {}
+
+/// Records that a user signed up using another user's referral code.
+async fn create_referral(
+    Extension(app): Extension<Arc<AppState>>,
+    extract::Json(body): extract::Json<CreateReferralBody>,
+) -> Result<Json<CreateReferralResponse>> {
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+
+    let referral_code = app
+        .db
+        .get_referral_code_by_code(&body.referral_code)
+        .await?
+        .ok_or_else(|| Error::Http(StatusCode::NOT_FOUND, "invalid referral code".into()))?;
+
+    if referral_code.user_id == user.id {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "users cannot refer themselves".into(),
+        ))?
+    }
+
+    if app
+        .db
+        .get_referral_for_referred_user(user.id)
+        .await?
+        .is_some()
+    {
+        Err(Error::Http(
+            StatusCode::CONFLICT,
+            "user has already been referred".into(),
+        ))?
+    }
+
+    // Referrals only apply to new customers.
+    if !app.db.get_billing_subscriptions(user.id).await?.is_empty() {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "user already has a subscription".into(),
+        ))?
+    }
+
+    let recent_referral_count = app
+        .db
+        .count_referrals_since(
+            referral_code.user_id,
+            OffsetDateTime::now_utc() - REFERRAL_VELOCITY_WINDOW,
+        )
+        .await?;
+    if recent_referral_count >= MAX_REFERRALS_PER_WINDOW {
+        log::warn!(
+            "referral velocity limit reached for user {}",
+            referral_code.user_id
+        );
+        Err(Error::Http(
+            StatusCode::TOO_MANY_REQUESTS,
+            "referral limit reached".into(),
+        ))?
+    }
+
+    app.db
+        .create_referral(referral_code.user_id, user.id)
+        .await?;
+
+    Ok(Json(CreateReferralResponse {}))
+}
+
+/// Returns the Stripe coupon to apply to the user's first subscription, if they
+/// were referred by another user.
+pub async fn referral_coupon_for_user(app: &AppState, user_id: UserId) -> Result<Option<String>> {
+    let Some(coupon_id) = app.config.stripe_referral_coupon_id.as_ref() else {
+        return Ok(None);
+    };
+
+    if app
+        .db
+        .get_referral_for_referred_user(user_id)
+        .await?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    if !app.db.get_billing_subscriptions(user_id).await?.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(coupon_id.to_string()))
+}
+
+/// Credits the user that referred the given user, if any.
+///
+/// This should be called once the referred user has an active subscription.
+/// Each referral is credited at most once.
+pub async fn credit_referrer(app: &AppState, referred_user_id: UserId) -> anyhow::Result<()> {
+    let (Some(payment_provider), Some(credit_in_cents)) = (
+        app.payment_provider.as_ref(),
+        app.config.stripe_referral_credit_in_cents,
+    ) else {
+        return Ok(());
+    };
+
+    let Some(referral) = app
+        .db
+        .get_referral_for_referred_user(referred_user_id)
+        .await?
+    else {
+        return Ok(());
+    };
+    if referral.referrer_credited_at.is_some() {
+        return Ok(());
+    }
+
+    credit_referral(app, payment_provider.as_ref(), credit_in_cents, &referral).await
+}
+
+/// Applies the referral credits the user became due before they were a
+/// billing customer. This should be called once they become one.
+pub async fn apply_pending_referrer_credits(
+    app: &AppState,
+    referrer_user_id: UserId,
+) -> anyhow::Result<()> {
+    let (Some(payment_provider), Some(credit_in_cents)) = (
+        app.payment_provider.as_ref(),
+        app.config.stripe_referral_credit_in_cents,
+    ) else {
+        return Ok(());
+    };
+
+    for referral in app
+        .db
+        .get_pending_referrer_credits(referrer_user_id)
+        .await?
+    {
+        credit_referral(app, payment_provider.as_ref(), credit_in_cents, &referral).await?;
+    }
+
+    Ok(())
+}
+
+/// Credits the referrer for the referral, or, if they aren't a billing
+/// customer yet, records the credit to be applied once they are. Referrers
+/// that can't be credited are recorded as such.
+async fn credit_referral(
+    app: &AppState,
+    payment_provider: &dyn PaymentProvider,
+    credit_in_cents: i64,
+    referral: &referral::Model,
+) -> anyhow::Result<()> {
+    let Some(referrer_customer) = app
+        .db
+        .get_billing_customer_by_user_id(referral.referrer_user_id)
+        .await?
+    else {
+        log::info!(
+            "referrer {} has no billing customer; crediting them once they do",
+            referral.referrer_user_id
+        );
+        app.db.mark_referrer_credit_pending(referral.id).await?;
+        return Ok(());
+    };
+    // Referral credits are in USD, and only apply to customers of our own
+    // Stripe account.
+    if let Some(stripe_account_id) = &referrer_customer.stripe_account_id {
+        log::info!(
+            "referrer {} is a customer of {stripe_account_id}; skipping referral credit",
+            referral.referrer_user_id
+        );
+        app.db.mark_referrer_credit_skipped(referral.id).await?;
+        return Ok(());
+    }
+
+    if !app.db.mark_referrer_credited(referral.id).await? {
+        return Ok(());
+    }
+
+    // Each referral is credited once, even if crediting it is retried.
+    let idempotency_key =
+        idempotency_key("credit_referrer", referral.referrer_user_id, &referral.id)?;
+    let result = payment_provider
+        .adjust_customer_balance(
+            &AdjustCustomerBalanceParams {
+                customer_id: referrer_customer.stripe_customer_id,
+                // A negative amount is a credit applied to the customer's next invoice.
+                amount: -credit_in_cents,
+                currency: "usd".into(),
+                description: format!("Referral credit for user {}", referral.referred_user_id),
+            },
+            idempotency_key,
+        )
+        .await;
+
+    if let Err(error) = result {
+        app.db.unmark_referrer_credited(referral.id).await.log_err();
+        return Err(error.into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{delete, post},
+    Extension, Json, Router,
+};
+use collab_api_client::{
+    CreateSandboxOrganizationBody, CreateSandboxOrganizationResponse,
+    DeleteSandboxOrganizationParams, DeleteSandboxOrganizationResponse, RecordSandboxUsageBody,
+    RecordSandboxUsageResponse, SandboxUser,
+};
+use stripe::{
+    CreateCustomer, Customer, CustomerId, CustomerInvoiceSettings, PaymentMethodId,
+    TestHelpersTestClock,
+};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::api::admin_user;
+use crate::api::consents::record_current_consents;
+use crate::db::{
+    CreateBillingCustomerParams, CreateLanguageModelUsageParams, CreateSandboxOrganizationParams,
+    OrganizationId,
+};
+use crate::distributed_lock::run_exclusively;
+use crate::stripe_test_clocks;
+use crate::{AppState, Error, Result};
+
+/// The most users a sandbox organization can have.
+const MAX_SANDBOX_MEMBERS: u32 = 20;
+
+/// How long a sandbox organization lasts, unless the test provisioning it
+/// asks otherwise.
+const DEFAULT_SANDBOX_LIFETIME_IN_MINUTES: u32 = 60;
+
+/// A Stripe test card that always succeeds.
+///
+/// [Stripe docs](https://docs.stripe.com/testing#cards)
+const TEST_PAYMENT_METHOD_ID: &str = "pm_card_visa";
+
+const SANDBOX_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/admin/sandbox_orgs", post(create_sandbox_organization))
+        .route(
+            "/admin/sandbox_orgs/:id",
+            delete(delete_sandbox_organization),
+        )
+        .route("/admin/sandbox_orgs/:id/usage", post(record_sandbox_usage))
+}
+
+fn stripe_client(app: &AppState) -> Result<Arc<stripe::Client>> {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::error!("failed to retrieve Stripe client");
+        Err(Error::Http(
+            StatusCode::NOT_IMPLEMENTED,
+            "not supported".into(),
+        ))?
+    };
+    Ok(stripe_client)
+}
+
+/// Provisions a sandbox organization, its users, and a Stripe customer with
+/// a test card for its admin.
+async fn create_sandbox_organization(
+    Extension(app): Extension<Arc<AppState>>,
+    Json(body): Json<CreateSandboxOrganizationBody>,
+) -> Result<Json<CreateSandboxOrganizationResponse>> {
+    let admin = admin_user(&app, body.github_user_id, "manage sandbox organizations").await?;
+    if body.member_count == 0 || body.member_count > MAX_SANDBOX_MEMBERS {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            format!("sandbox organizations must have between 1 and {MAX_SANDBOX_MEMBERS} members"),
+        ))?
+    }
+    let stripe_client = stripe_client(&app)?;
+
+    let lifetime_in_minutes = body
+        .expires_in_minutes
+        .unwrap_or(DEFAULT_SANDBOX_LIFETIME_IN_MINUTES);
+    let expires_at =
+        OffsetDateTime::now_utc() + time::Duration::minutes(lifetime_in_minutes as i64);
+    let (organization, users) = app
+        .db
+        .create_sandbox_organization(&CreateSandboxOrganizationParams {
+            created_by_user_id: admin.id,
+            member_count: body.member_count as usize,
+            expires_at: PrimitiveDateTime::new(expires_at.date(), expires_at.time()),
+        })
+        .await?;
+
+    // Sandbox users never see the terms, so they accept them on creation, as
+    // they'd otherwise be unable to subscribe.
+    for user in &users {
+        record_current_consents(&app, user.id).await?;
+    }
+
+    let owner = &users[0];
+    let organization_id = organization.id.to_string();
+    let test_clock_id = if app.config.stripe_test_clocks_enabled() {
+        match stripe_test_clocks::create_test_clock(&stripe_client, &owner.github_login).await {
+            Ok(test_clock_id) => Some(test_clock_id),
+            Err(error) => {
+                app.db
+                    .destroy_sandbox_organization(organization.id)
+                    .await
+                    .log_err();
+                Err(error)?
+            }
+        }
+    } else {
+        None
+    };
+    let customer = Customer::create(
+        &stripe_client,
+        CreateCustomer {
+            email: owner.email_address.as_deref(),
+            // Attaching a test card up front lets tests subscribe the
+            // customer without going through Checkout in a browser.
+            payment_method: Some(
+                PaymentMethodId::from_str(TEST_PAYMENT_METHOD_ID)
+                    .context("failed to parse payment method ID")?,
+            ),
+            invoice_settings: Some(CustomerInvoiceSettings {
+                default_payment_method: Some(TEST_PAYMENT_METHOD_ID.to_string()),
+                ..Default::default()
+            }),
+            metadata: Some(
+                [("sandbox_organization_id".to_string(), organization_id)]
+                    .into_iter()
+                    .collect(),
+            ),
+            test_clock: test_clock_id.as_ref().map(|id| id.as_str()),
+            ..Default::default()
+        },
+    )
+    .await;
+    let customer = match customer {
+        Ok(customer) => customer,
+        Err(error) => {
+            app.db
+                .destroy_sandbox_organization(organization.id)
+                .await
+                .log_err();
+            Err(error)?
+        }
+    };
+    app.db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id: owner.id,
+            stripe_customer_id: customer.id.to_string(),
+            stripe_account_id: None,
+        })
+        .await?;
+    log::info!(
+        "admin {} provisioned sandbox organization {} with {} members",
+        admin.github_login,
+        organization.id,
+        users.len()
+    );
+
+    Ok(Json(CreateSandboxOrganizationResponse {
+        organization_id: organization.id.0,
+        expires_at,
+        users: users
+            .iter()
+            .map(|user| SandboxUser {
+                user_id: user.id.0,
+                github_user_id: user.github_user_id.unwrap_or_default(),
+                github_login: user.github_login.clone(),
+                stripe_customer_id: (user.id == owner.id).then(|| customer.id.to_string()),
+                stripe_test_clock_id: test_clock_id
+                    .as_ref()
+                    .filter(|_| user.id == owner.id)
+                    .map(|id| id.to_string()),
+            })
+            .collect(),
+    }))
+}
+
+/// Records language model usage for a sandbox user, so that tests can use up
+/// their quota without making requests to a provider.
+async fn record_sandbox_usage(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<RecordSandboxUsageBody>,
+) -> Result<Json<RecordSandboxUsageResponse>> {
+    admin_user(&app, body.github_user_id, "manage sandbox organizations").await?;
+    if app
+        .db
+        .get_sandbox_organization(organization_id)
+        .await?
+        .is_none()
+    {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "sandbox organization not found".into(),
+        ))?
+    }
+
+    let user = app
+        .db
+        .get_user_by_github_user_id(body.member_github_user_id)
+        .await?
+        .ok_or_else(|| anyhow!("user not found"))?;
+    if app
+        .db
+        .get_organization_member(organization_id, user.id)
+        .await?
+        .is_none()
+    {
+        Err(Error::Http(
+            StatusCode::BAD_REQUEST,
+            "usage can only be recorded for members of the sandbox organization".into(),
+        ))?
+    }
+
+    app.db
+        .record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id: user.id,
+            provider: "sandbox".into(),
+            model: "sandbox".into(),
+            input_tokens: body.tokens as i64,
+            output_tokens: 0,
+            feature: None,
+            upstream_cost_in_millicents: 0,
+        })
+        .await?;
+    Ok(Json(RecordSandboxUsageResponse {}))
+}
+
+/// Tears down a sandbox organization, along with its users and their Stripe
+/// customers.
+async fn delete_sandbox_organization(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(organization_id): Path<OrganizationId>,
+    Query(params): Query<DeleteSandboxOrganizationParams>,
+) -> Result<Json<DeleteSandboxOrganizationResponse>> {
+    let admin = admin_user(&app, params.github_user_id, "manage sandbox organizations").await?;
+    let stripe_client = stripe_client(&app)?;
+    let Some(deleted_user_count) =
+        destroy_sandbox_organization(&app, &stripe_client, organization_id).await?
+    else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "sandbox organization not found".into(),
+        ))?
+    };
+    log::info!(
+        "admin {} tore down sandbox organization {}",
+        admin.github_login,
+        organization_id
+    );
+
+    Ok(Json(DeleteSandboxOrganizationResponse {
+        deleted_user_count,
+    }))
+}
+
+/// Deletes the Stripe customers of the users provisioned with the sandbox
+/// organization, and any test clocks they're on, which cancels their
+/// subscriptions, and then the organization and those users.
+///
+/// Users that were added to the organization after it was provisioned are
+/// left alone, along with their Stripe customers.
+///
+/// Returns how many users were deleted, or `None` if the organization isn't
+/// a sandbox.
+async fn destroy_sandbox_organization(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    organization_id: OrganizationId,
+) -> Result<Option<usize>> {
+    if app
+        .db
+        .get_sandbox_organization(organization_id)
+        .await?
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    for user_id in app
+        .db
+        .get_sandbox_organization_user_ids(organization_id)
+        .await?
+    {
+        let Some(customer) = app.db.get_billing_customer_by_user_id(user_id).await? else {
+            continue;
+        };
+        let customer_id = CustomerId::from_str(&customer.stripe_customer_id)
+            .context("failed to parse customer ID")?;
+        // Deleting a test clock deletes its customers along with it.
+        let customer = Customer::retrieve(stripe_client, &customer_id, &[]).await?;
+        if let Some(test_clock) = customer.test_clock {
+            TestHelpersTestClock::delete(stripe_client, &test_clock.id()).await?;
+        } else {
+            Customer::delete(stripe_client, &customer_id).await?;
+        }
+    }
+
+    Ok(app
+        .db
+        .destroy_sandbox_organization(organization_id)
+        .await?
+        .map(|user_ids| user_ids.len()))
+}
+
+/// Periodically tears down the sandbox organizations that tests left behind.
+pub fn delete_expired_sandbox_organizations_periodically(app: Arc<AppState>) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "delete_expired_sandbox_organizations",
+                        delete_expired_sandbox_organizations(&app, &stripe_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown
+                    .sleep(&executor, SANDBOX_CLEANUP_INTERVAL)
+                    .await;
+            }
+        }
+    });
+}
+
+async fn delete_expired_sandbox_organizations(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+) -> anyhow::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let expired = app
+        .db
+        .get_expired_sandbox_organizations(PrimitiveDateTime::new(now.date(), now.time()))
+        .await?;
+    for sandbox in expired {
+        // Keep going, so that one organization failing to be torn down
+        // doesn't leave the rest behind.
+        if destroy_sandbox_organization(app, stripe_client, sandbox.organization_id)
+            .await
+            .log_err()
+            .is_some()
+        {
+            log::info!(
+                "tore down expired sandbox organization {}",
+                sandbox.organization_id
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::api::admin_user;
+use crate::db::ServerSettingChangeId;
+use crate::server_settings::ServerSettings;
+use crate::{AppState, Error, Result};
+
+/// The number of changes returned by the audit log endpoint.
+const CHANGE_LOG_LIMIT: u64 = 100;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/server_settings", get(get_server_settings))
+        .route("/server_settings/changes", get(get_server_setting_changes))
+        .route(
+            "/server_settings/:key",
+            put(update_server_setting).delete(reset_server_setting),
+        )
+}
+
+/// Returns the settings currently in effect on this server.
+async fn get_server_settings(
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<ServerSettings>> {
+    Ok(Json(app.server_settings.get()))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateServerSettingBody {
+    /// The GitHub user ID of the admin changing the setting.
+    github_user_id: i32,
+    value: serde_json::Value,
+}
+
+/// Overrides a server setting. The change takes effect on this server
+/// immediately, and on other servers once they reload their settings.
+async fn update_server_setting(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(key): Path<String>,
+    Json(body): Json<UpdateServerSettingBody>,
+) -> Result<Json<ServerSettings>> {
+    let user = admin_user(&app, body.github_user_id, "change server settings").await?;
+    if !ServerSettings::is_known_key(&key) {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            format!("unknown server setting {key:?}"),
+        ))?
+    }
+
+    let value = body.value.to_string();
+    if let Err(error) = ServerSettings::from_values([(key.as_str(), value.as_str())])
+        .and_then(|settings| settings.validate())
+    {
+        Err(Error::Http(StatusCode::BAD_REQUEST, format!("{error:#}")))?
+    }
+
+    app.db
+        .set_server_setting(&key, Some(&value), Some(user.id))
+        .await?;
+    app.server_settings.reload().await?;
+    Ok(Json(app.server_settings.get()))
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetServerSettingParams {
+    /// The GitHub user ID of the admin resetting the setting.
+    github_user_id: i32,
+}
+
+/// Resets a server setting to its default.
+async fn reset_server_setting(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(key): Path<String>,
+    Query(params): Query<ResetServerSettingParams>,
+) -> Result<Json<ServerSettings>> {
+    let user = admin_user(&app, params.github_user_id, "change server settings").await?;
+    if !ServerSettings::is_known_key(&key) {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            format!("unknown server setting {key:?}"),
+        ))?
+    }
+
+    app.db.set_server_setting(&key, None, Some(user.id)).await?;
+    app.server_settings.reload().await?;
+    Ok(Json(app.server_settings.get()))
+}
+
+#[derive(Debug, Serialize)]
+struct ServerSettingChange {
+    id: ServerSettingChangeId,
+    key: String,
+    previous_value: Option<serde_json::Value>,
+    value: Option<serde_json::Value>,
+    changed_by_github_login: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    changed_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct GetServerSettingChangesResponse {
+    changes: Vec<ServerSettingChange>,
+}
+
+/// Returns the most recent changes to server settings, newest first.
+async fn get_server_setting_changes(
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<GetServerSettingChangesResponse>> {
+    let changes = app.db.get_server_setting_changes(CHANGE_LOG_LIMIT).await?;
+    let user_ids = changes
+        .iter()
+        .filter_map(|change| change.changed_by_user_id)
+        .collect::<Vec<_>>();
+    let users = app.db.get_users_by_ids(user_ids).await?;
+
+    let parse = |value: Option<String>| value.and_then(|value| serde_json::from_str(&value).ok());
+    Ok(Json(GetServerSettingChangesResponse {
+        changes: changes
+            .into_iter()
+            .map(|change| ServerSettingChange {
+                id: change.id,
+                changed_by_github_login: users
+                    .iter()
+                    .find(|user| Some(user.id) == change.changed_by_user_id)
+                    .map(|user| user.github_login.clone()),
+                key: change.key,
+                previous_value: parse(change.previous_value),
+                value: parse(change.value),
+                changed_at: change.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
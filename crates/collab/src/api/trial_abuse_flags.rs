@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::api::admin_user;
+use crate::db::trial_abuse_flag::{self, TrialAbuseDecision, TrialAbuseReason};
+use crate::db::{TrialAbuseFlagId, User, UserId};
+use crate::{AppState, Error, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/admin/trial_abuse_flags", get(list_trial_abuse_flags))
+        .route(
+            "/admin/trial_abuse_flags/:id/decision",
+            post(decide_trial_abuse_flag),
+        )
+}
+
+#[derive(Debug, Serialize)]
+struct TrialAbuseFlagJson {
+    id: TrialAbuseFlagId,
+    user_id: UserId,
+    reason: TrialAbuseReason,
+    details: String,
+    decision: Option<TrialAbuseDecision>,
+    decision_note: Option<String>,
+    decided_by_github_login: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    flagged_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    decided_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListTrialAbuseFlagsParams {
+    /// The GitHub user ID of the admin reviewing the flags.
+    github_user_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct ListTrialAbuseFlagsResponse {
+    flags: Vec<TrialAbuseFlagJson>,
+}
+
+/// Returns the trial abuse flags that are awaiting review, oldest first.
+async fn list_trial_abuse_flags(
+    Extension(app): Extension<Arc<AppState>>,
+    Query(params): Query<ListTrialAbuseFlagsParams>,
+) -> Result<Json<ListTrialAbuseFlagsResponse>> {
+    admin_user(&app, params.github_user_id, "review trial abuse flags").await?;
+    let flags = app.db.get_undecided_trial_abuse_flags().await?;
+
+    Ok(Json(ListTrialAbuseFlagsResponse {
+        flags: flags
+            .into_iter()
+            .map(|flag| trial_abuse_flag_json(flag, None))
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DecideTrialAbuseFlagBody {
+    /// The GitHub user ID of the admin deciding on the flag.
+    github_user_id: i32,
+    decision: TrialAbuseDecision,
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DecideTrialAbuseFlagResponse {
+    flag: TrialAbuseFlagJson,
+}
+
+/// Records an admin's decision on a trial abuse flag, which either lets the
+/// user have trials again or keeps them from having any.
+///
+/// Decisions are final, and are kept along with who made them.
+async fn decide_trial_abuse_flag(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<TrialAbuseFlagId>,
+    Json(body): Json<DecideTrialAbuseFlagBody>,
+) -> Result<Json<DecideTrialAbuseFlagResponse>> {
+    let admin = admin_user(&app, body.github_user_id, "review trial abuse flags").await?;
+    let Some(flag) = app
+        .db
+        .decide_trial_abuse_flag(id, body.decision, body.note.as_deref(), admin.id)
+        .await?
+    else {
+        Err(Error::Http(
+            StatusCode::NOT_FOUND,
+            "no undecided flag found".into(),
+        ))?
+    };
+    let decision = match body.decision {
+        TrialAbuseDecision::Allow => "allowed",
+        TrialAbuseDecision::Block => "blocked",
+    };
+    log::info!(
+        "admin {} {decision} trials for user {} on reviewing trial abuse flag {}",
+        admin.github_login,
+        flag.user_id,
+        flag.id
+    );
+
+    Ok(Json(DecideTrialAbuseFlagResponse {
+        flag: trial_abuse_flag_json(flag, Some(&admin)),
+    }))
+}
+
+fn trial_abuse_flag_json(
+    flag: trial_abuse_flag::Model,
+    decided_by: Option<&User>,
+) -> TrialAbuseFlagJson {
+    TrialAbuseFlagJson {
+        id: flag.id,
+        user_id: flag.user_id,
+        reason: flag.reason,
+        details: flag.details,
+        decision: flag.decision,
+        decision_note: flag.decision_note,
+        decided_by_github_login: decided_by.map(|user| user.github_login.clone()),
+        flagged_at: flag.created_at.assume_utc(),
+        decided_at: flag.decided_at.map(PrimitiveDateTime::assume_utc),
+    }
+}
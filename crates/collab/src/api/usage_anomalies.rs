@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::db::{UsageAnomalyId, UserId};
+use crate::distributed_lock::run_exclusively;
+use crate::{AppState, Result};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/usage_anomalies", get(list_usage_anomalies))
+        .route("/usage_anomalies/:id/review", post(review_usage_anomaly))
+}
+
+#[derive(Debug, Serialize)]
+struct UsageAnomaly {
+    id: UsageAnomalyId,
+    user_id: UserId,
+    window_tokens: i64,
+    baseline_tokens: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    detected_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+struct ListUsageAnomaliesResponse {
+    anomalies: Vec<UsageAnomaly>,
+}
+
+/// Returns the usage anomalies that are awaiting review.
+async fn list_usage_anomalies(
+    Extension(app): Extension<Arc<AppState>>,
+) -> Result<Json<ListUsageAnomaliesResponse>> {
+    let anomalies = app.db.get_unreviewed_usage_anomalies().await?;
+
+    Ok(Json(ListUsageAnomaliesResponse {
+        anomalies: anomalies
+            .into_iter()
+            .map(|anomaly| UsageAnomaly {
+                id: anomaly.id,
+                user_id: anomaly.user_id,
+                window_tokens: anomaly.window_tokens,
+                baseline_tokens: anomaly.baseline_tokens,
+                detected_at: anomaly.created_at.assume_utc(),
+            })
+            .collect(),
+    }))
+}
+
+/// Marks a usage anomaly as reviewed, lifting any throttling caused by it.
+async fn review_usage_anomaly(
+    Extension(app): Extension<Arc<AppState>>,
+    Path(id): Path<UsageAnomalyId>,
+) -> Result<()> {
+    app.db.mark_usage_anomaly_reviewed(id).await?;
+    Ok(())
+}
+
+const DETECT_ANOMALIES_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The window of recent usage that is checked for anomalies.
+pub(crate) const DETECTION_WINDOW: time::Duration = time::Duration::days(1);
+
+/// The period preceding the detection window used to establish a user's usual usage.
+pub(crate) const BASELINE_PERIOD_IN_DAYS: i64 = 28;
+
+/// How many times the expected usage a user must exceed to be flagged.
+const ANOMALY_THRESHOLD_MULTIPLIER: i64 = 10;
+
+/// Periodically checks for users whose language model usage deviates wildly
+/// from their history or their plan's norm.
+pub fn detect_usage_anomalies_periodically(app: Arc<AppState>) {
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "detect_usage_anomalies",
+                        detect_usage_anomalies(&app),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown
+                    .sleep(&executor, DETECT_ANOMALIES_INTERVAL)
+                    .await;
+            }
+        }
+    });
+}
+
+async fn detect_usage_anomalies(app: &AppState) -> anyhow::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let window_start = now - DETECTION_WINDOW;
+    let baseline_start = window_start - time::Duration::days(BASELINE_PERIOD_IN_DAYS);
+
+    let window_usage = app
+        .db
+        .get_language_model_token_usage_by_user(window_start, now)
+        .await?;
+    let baseline_usage = app
+        .db
+        .get_language_model_token_usage_by_user(baseline_start, window_start)
+        .await?;
+
+    // The number of tokens per day we consider normal regardless of a user's
+    // history depends on whether they're on a paid plan.
+    let settings = app.server_settings.get();
+    for (user_id, window_tokens) in window_usage {
+        let plan_tokens_per_day = if app.entitlements.has_paid_plan(user_id).await? {
+            settings.paid_plan_tokens_per_day
+        } else {
+            settings.free_plan_tokens_per_day
+        };
+        let historical_tokens_per_day =
+            baseline_usage.get(&user_id).copied().unwrap_or(0) / BASELINE_PERIOD_IN_DAYS;
+        let baseline_tokens = historical_tokens_per_day.max(plan_tokens_per_day);
+
+        if window_tokens <= baseline_tokens * ANOMALY_THRESHOLD_MULTIPLIER {
+            continue;
+        }
+
+        if app.db.has_unreviewed_usage_anomaly(user_id).await? {
+            continue;
+        }
+
+        log::warn!(
+            "usage anomaly detected for user {user_id}: {window_tokens} tokens used in the last day, expected around {baseline_tokens}"
+        );
+        app.db
+            .create_usage_anomaly(user_id, window_tokens, baseline_tokens)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::{
     db::{self, dev_server, AccessTokenId, Database, DevServerId, UserId},
     rpc::Principal,
+    tenant::Tenant,
     AppState, Error, Result,
 };
 use anyhow::{anyhow, Context};
@@ -21,6 +22,8 @@ use sha2::Digest;
 use std::sync::OnceLock;
 use std::{sync::Arc, time::Instant};
 use subtle::ConstantTimeEq;
+use tracing::Instrument;
+use util::ResultExt;
 
 /// Validates the authorization header and adds an Extension<Principal> to the request.
 /// Authorization: <user-id> <token>
@@ -96,6 +99,15 @@ pub async fn validate_header<B>(mut req: Request<B>, next: Next<B>) -> impl Into
                 .await?
                 .ok_or_else(|| anyhow!("user {} not found", user_id))?;
 
+            // Attribute the logs of everything done for this request to the
+            // user, their organization and their plan.
+            let tenant = Tenant::for_user(&state.db, &state.entitlements, user.id)
+                .await
+                .log_err();
+            let span = tenant
+                .as_ref()
+                .map_or_else(tracing::Span::none, Tenant::span);
+
             if let Some(impersonator_id) = validate_result.impersonator_id {
                 let admin = state
                     .db
@@ -107,7 +119,10 @@ pub async fn validate_header<B>(mut req: Request<B>, next: Next<B>) -> impl Into
             } else {
                 req.extensions_mut().insert(Principal::User(user));
             };
-            return Ok::<_, Error>(next.run(req).await);
+            if let Some(tenant) = tenant {
+                req.extensions_mut().insert(tenant);
+            }
+            return Ok::<_, Error>(next.run(req).instrument(span).await);
         }
     }
 
@@ -0,0 +1,590 @@
+mod fake_upstreams;
+
+use std::{
+    future::Future,
+    net::TcpListener,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context as _};
+use async_tungstenite::tungstenite::Message as WebSocketMessage;
+use futures::{channel::mpsc, Sink, SinkExt as _, Stream, StreamExt as _};
+use parking_lot::Mutex;
+use rand::Rng as _;
+use rpc::{proto, Connection, Peer};
+use semantic_version::SemanticVersion;
+use serde_json::json;
+use util::ResultExt as _;
+
+use crate::{
+    db::{NewUserParams, PoolStatus, User},
+    entitlements::BillingModeKind,
+    executor::Executor,
+    rpc::{Principal, Server, ZedVersion},
+    tenant::Tenant,
+    AppState, Config,
+};
+
+use fake_upstreams::{serve_fake_anthropic, serve_fake_stripe, FakeModelOptions};
+
+/// How long the fake Stripe takes to respond, roughly matching the real one.
+const FAKE_STRIPE_LATENCY: Duration = Duration::from_millis(150);
+
+/// How the fake model provider streams its responses.
+const FAKE_MODEL: FakeModelOptions = FakeModelOptions {
+    time_to_first_token: Duration::from_millis(500),
+    token_interval: Duration::from_millis(10),
+    output_tokens: 200,
+};
+
+/// How often the database connection pool's usage is sampled.
+const POOL_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+const USAGE: &str = "usage: collab bench [checkout|llm] [--requests <n>] [--concurrency <n>]";
+
+/// A path through the server the bench can put under load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    /// Creating Stripe checkout sessions through `POST /billing/subscriptions`.
+    Checkout,
+    /// Streaming Anthropic completions through the RPC server.
+    LanguageModel,
+}
+
+impl Scenario {
+    fn name(&self) -> &'static str {
+        match self {
+            Scenario::Checkout => "checkout",
+            Scenario::LanguageModel => "llm",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BenchOptions {
+    pub scenarios: Vec<Scenario>,
+    /// The number of requests to send in each scenario.
+    pub requests: usize,
+    /// The number of requests in flight at once, which is also the number of
+    /// simulated users.
+    pub concurrency: usize,
+}
+
+impl BenchOptions {
+    /// Parses the arguments following `collab bench`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Self {
+            scenarios: Vec::new(),
+            requests: 200,
+            concurrency: 20,
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "checkout" => options.scenarios.push(Scenario::Checkout),
+                "llm" => options.scenarios.push(Scenario::LanguageModel),
+                "--requests" | "--concurrency" => {
+                    let value = args
+                        .next()
+                        .and_then(|value| value.parse::<usize>().ok())
+                        .filter(|value| *value > 0)
+                        .with_context(|| format!("{arg} takes a positive number\n{USAGE}"))?;
+                    if arg == "--requests" {
+                        options.requests = value;
+                    } else {
+                        options.concurrency = value;
+                    }
+                }
+                _ => bail!("unexpected argument {arg:?}\n{USAGE}"),
+            }
+        }
+        if options.scenarios.is_empty() {
+            options.scenarios = vec![Scenario::Checkout, Scenario::LanguageModel];
+        }
+        Ok(options)
+    }
+}
+
+/// Runs the bench and prints its report.
+pub async fn run(mut config: Config, options: BenchOptions) -> anyhow::Result<()> {
+    if !config.is_development() {
+        bail!(
+            "the bench only runs in development, not in {:?}",
+            config.zed_environment
+        );
+    }
+
+    config.billing_mode = Some(BillingModeKind::Stripe);
+    config.stripe_api_key = Some("sk_test_bench".into());
+    config.stripe_api_url = Some(serve_fake_stripe(FAKE_STRIPE_LATENCY)?);
+    config.stripe_price_id = Some("price_bench".into());
+    config.stripe_plan_price_ids = None;
+    config.stripe_trial_period_days = None;
+    config.terms_of_service_version = None;
+    config.pricing_terms_version = None;
+    config.anthropic_api_key = Some("bench".into());
+    config.anthropic_api_url = Some(serve_fake_anthropic(FAKE_MODEL)?);
+    config.throttle_usage_anomalies = None;
+    // Each simulated user sends far more requests than a real one would.
+    std::env::set_var(
+        "COMPLETE_WITH_LANGUAGE_MODEL_RATE_LIMIT_PER_HOUR",
+        options.requests.to_string(),
+    );
+
+    let max_connections = config.database_max_connections;
+    let state = AppState::new(config, Executor::Production).await?;
+    let users = create_users(&state, options.concurrency).await?;
+
+    for scenario in &options.scenarios {
+        let pool_usage = Arc::new(Mutex::new(PoolUsage::new(max_connections)));
+        let sampler = tokio::spawn({
+            let state = state.clone();
+            let pool_usage = pool_usage.clone();
+            async move {
+                loop {
+                    if let Some(status) = state.db.pool_status() {
+                        pool_usage.lock().record(status);
+                    }
+                    tokio::time::sleep(POOL_SAMPLE_INTERVAL).await;
+                }
+            }
+        });
+
+        let report = match scenario {
+            Scenario::Checkout => bench_checkout(&state, &users, &options).await?,
+            Scenario::LanguageModel => bench_language_model(&state, &users, &options).await?,
+        };
+        sampler.abort();
+
+        println!("{}", report.summary(scenario.name()));
+        println!("  {}", pool_usage.lock().summary());
+    }
+
+    Ok(())
+}
+
+async fn create_users(state: &AppState, count: usize) -> anyhow::Result<Vec<User>> {
+    let run_id = nanoid::nanoid!(8).to_lowercase();
+    let language_models_flag = state.db.get_or_create_user_flag("language-models").await?;
+    let mut users = Vec::with_capacity(count);
+    for ix in 0..count {
+        let github_login = format!("bench-{run_id}-{ix}");
+        let user_id = state
+            .db
+            .create_user(
+                &format!("{github_login}@example.com"),
+                false,
+                NewUserParams {
+                    github_login,
+                    github_user_id: rand::thread_rng().gen_range(1_000_000_000..i32::MAX),
+                },
+            )
+            .await?
+            .user_id;
+        state
+            .db
+            .add_user_flag(user_id, language_models_flag)
+            .await?;
+        users.push(
+            state
+                .db
+                .get_user_by_id(user_id)
+                .await?
+                .ok_or_else(|| anyhow!("user {user_id} not found"))?,
+        );
+    }
+    Ok(users)
+}
+
+/// Sends `options.requests` requests, `options.concurrency` at a time, with
+/// `send` receiving the index of each request.
+async fn send_requests<F, Fut>(options: &BenchOptions, send: F) -> ScenarioReport
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = anyhow::Result<Sample>>,
+{
+    let started_at = Instant::now();
+    let results = futures::stream::iter(0..options.requests)
+        .map(send)
+        .buffer_unordered(options.concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut report = ScenarioReport {
+        elapsed: started_at.elapsed(),
+        ..Default::default()
+    };
+    for result in results {
+        match result {
+            Ok(sample) => report.samples.push(sample),
+            Err(error) => {
+                if report.errors.len() < 5 {
+                    report.errors.push(format!("{error:#}"));
+                }
+                report.error_count += 1;
+            }
+        }
+    }
+    report
+}
+
+async fn bench_checkout(
+    state: &Arc<AppState>,
+    users: &[User],
+    options: &BenchOptions,
+) -> anyhow::Result<ScenarioReport> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let server = axum::Server::from_tcp(listener)
+        .map_err(|error| anyhow!(error))?
+        .serve(crate::api::routes(None, state.clone()).into_make_service());
+    let server = tokio::spawn(async move { server.await.log_err() });
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{address}/billing/subscriptions");
+    let api_token = state.config.api_token.clone();
+    let report = send_requests(options, |ix| {
+        let request = client
+            .post(&url)
+            .header("Authorization", format!("token {api_token}"))
+            .json(&json!({ "github_user_id": users[ix % users.len()].github_user_id }));
+        async move {
+            let started_at = Instant::now();
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                bail!(
+                    "{}: {}",
+                    response.status(),
+                    response.text().await.unwrap_or_default()
+                );
+            }
+            Ok(Sample {
+                latency: started_at.elapsed(),
+                first_event: None,
+            })
+        }
+    })
+    .await;
+
+    server.abort();
+    Ok(report)
+}
+
+async fn bench_language_model(
+    state: &Arc<AppState>,
+    users: &[User],
+    options: &BenchOptions,
+) -> anyhow::Result<ScenarioReport> {
+    // Connecting through memory rather than a socket leaves out the cost of
+    // the network, which the server doesn't control.
+    let server_id = state
+        .db
+        .create_server(&state.config.zed_environment)
+        .await?;
+    let server = Server::new(server_id, state.clone());
+    let peer = Peer::new(0);
+    let mut connection_ids = Vec::with_capacity(users.len());
+    for user in users {
+        let (client_connection, server_connection) = in_memory_connection();
+        let tenant = Tenant::for_user(&state.db, &state.entitlements, user.id).await?;
+        tokio::spawn(server.handle_connection(
+            server_connection,
+            "bench".into(),
+            Principal::User(user.clone()),
+            Some(tenant),
+            ZedVersion(SemanticVersion::new(1, 0, 0)),
+            None,
+            Executor::Production,
+        ));
+
+        let (connection_id, io, incoming) =
+            peer.add_connection(client_connection, tokio::time::sleep);
+        tokio::spawn(async move { io.await.log_err() });
+        // Drop the messages the server pushes to clients, such as contact
+        // updates, so that they don't apply backpressure.
+        tokio::spawn(incoming.for_each(|_| futures::future::ready(())));
+        connection_ids.push(connection_id);
+    }
+
+    let request = serde_json::to_string(&anthropic::Request {
+        model: "claude-3-5-sonnet-20240620".into(),
+        max_tokens: 4096,
+        messages: vec![anthropic::Message {
+            role: anthropic::Role::User,
+            content: vec![anthropic::Content::Text {
+                text: "Write a haiku about load testing.".into(),
+            }],
+        }],
+        tools: Vec::new(),
+        tool_choice: None,
+        system: None,
+        metadata: None,
+        stop_sequences: Vec::new(),
+        temperature: None,
+        top_k: None,
+        top_p: None,
+    })?;
+
+    let report = send_requests(options, |ix| {
+        let events = peer.request_stream(
+            connection_ids[ix % connection_ids.len()],
+            proto::StreamCompleteWithLanguageModel {
+                provider: proto::LanguageModelProvider::Anthropic as i32,
+                request: request.clone(),
+                feature: Some("bench".into()),
+                stream_id: None,
+                allow_vendor_failover: false,
+            },
+        );
+        async move {
+            let started_at = Instant::now();
+            let mut events = events.await?;
+            let mut first_event = None;
+            while let Some(event) = events.next().await {
+                event?;
+                first_event.get_or_insert_with(|| started_at.elapsed());
+            }
+            Ok(Sample {
+                latency: started_at.elapsed(),
+                first_event,
+            })
+        }
+    })
+    .await;
+
+    server.teardown();
+    Ok(report)
+}
+
+/// Returns both ends of a connection that passes messages through memory.
+fn in_memory_connection() -> (Connection, Connection) {
+    let (a_tx, a_rx) = mpsc::unbounded();
+    let (b_tx, b_rx) = mpsc::unbounded();
+    (
+        Connection::new(InMemoryStream { tx: a_tx, rx: b_rx }),
+        Connection::new(InMemoryStream { tx: b_tx, rx: a_rx }),
+    )
+}
+
+struct InMemoryStream {
+    tx: mpsc::UnboundedSender<WebSocketMessage>,
+    rx: mpsc::UnboundedReceiver<WebSocketMessage>,
+}
+
+impl Stream for InMemoryStream {
+    type Item = anyhow::Result<WebSocketMessage>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_next_unpin(cx).map(|message| message.map(Ok))
+    }
+}
+
+impl Sink<WebSocketMessage> for InMemoryStream {
+    type Error = anyhow::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        self.tx.poll_ready_unpin(cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: WebSocketMessage) -> anyhow::Result<()> {
+        self.tx.start_send_unpin(message).map_err(Into::into)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        self.tx.poll_flush_unpin(cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        self.tx.poll_close_unpin(cx).map_err(Into::into)
+    }
+}
+
+/// The measurements of a request that succeeded.
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    latency: Duration,
+    /// How long the first event of a streaming response took to arrive.
+    first_event: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct ScenarioReport {
+    elapsed: Duration,
+    samples: Vec<Sample>,
+    error_count: usize,
+    /// The first few errors requests failed with.
+    errors: Vec<String>,
+}
+
+impl ScenarioReport {
+    fn summary(&self, name: &str) -> String {
+        let request_count = self.samples.len() + self.error_count;
+        let mut lines = vec![format!(
+            "{name}: {request_count} requests, {} failed, {:.1} requests/s",
+            self.error_count,
+            request_count as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        )];
+        if let Some(latency) =
+            LatencySummary::new(self.samples.iter().map(|sample| sample.latency).collect())
+        {
+            lines.push(format!("  latency:     {latency}"));
+        }
+        if let Some(first_event) = LatencySummary::new(
+            self.samples
+                .iter()
+                .filter_map(|sample| sample.first_event)
+                .collect(),
+        ) {
+            lines.push(format!("  first event: {first_event}"));
+        }
+        for error in &self.errors {
+            lines.push(format!("  error: {error}"));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LatencySummary {
+    p50: Duration,
+    p90: Duration,
+    p99: Duration,
+    max: Duration,
+}
+
+impl LatencySummary {
+    fn new(mut samples: Vec<Duration>) -> Option<Self> {
+        samples.sort();
+        Some(Self {
+            p50: percentile(&samples, 50)?,
+            p90: percentile(&samples, 90)?,
+            p99: percentile(&samples, 99)?,
+            max: *samples.last()?,
+        })
+    }
+}
+
+impl std::fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "p50 {:?}  p90 {:?}  p99 {:?}  max {:?}",
+            self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Returns the nearest-rank percentile of the sorted samples.
+fn percentile(sorted_samples: &[Duration], percentile: usize) -> Option<Duration> {
+    let rank = (percentile * sorted_samples.len()).div_ceil(100);
+    sorted_samples.get(rank.saturating_sub(1)).copied()
+}
+
+/// How busy the database connection pool was over the course of a scenario.
+#[derive(Debug)]
+struct PoolUsage {
+    max_connections: u32,
+    sample_count: usize,
+    total_in_use: u64,
+    peak_in_use: u32,
+    /// The number of samples in which every connection was in use, so new
+    /// queries had to wait for one.
+    saturated_sample_count: usize,
+}
+
+impl PoolUsage {
+    fn new(max_connections: u32) -> Self {
+        Self {
+            max_connections,
+            sample_count: 0,
+            total_in_use: 0,
+            peak_in_use: 0,
+            saturated_sample_count: 0,
+        }
+    }
+
+    fn record(&mut self, status: PoolStatus) {
+        let in_use = status.in_use();
+        self.sample_count += 1;
+        self.total_in_use += in_use as u64;
+        self.peak_in_use = self.peak_in_use.max(in_use);
+        if in_use >= self.max_connections {
+            self.saturated_sample_count += 1;
+        }
+    }
+
+    fn summary(&self) -> String {
+        if self.sample_count == 0 {
+            return "database pool: no samples (only Postgres pools report their usage)".into();
+        }
+        format!(
+            "database pool: {:.1} connections in use on average, peak {} of {}, saturated {:.0}% of the time",
+            self.total_in_use as f64 / self.sample_count as f64,
+            self.peak_in_use,
+            self.max_connections,
+            100. * self.saturated_sample_count as f64 / self.sample_count as f64
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bench_options() {
+        assert_eq!(
+            BenchOptions::parse([]).unwrap(),
+            BenchOptions {
+                scenarios: vec![Scenario::Checkout, Scenario::LanguageModel],
+                requests: 200,
+                concurrency: 20,
+            }
+        );
+        assert_eq!(
+            BenchOptions::parse(
+                ["llm", "--requests", "1000", "--concurrency", "50"].map(String::from)
+            )
+            .unwrap(),
+            BenchOptions {
+                scenarios: vec![Scenario::LanguageModel],
+                requests: 1000,
+                concurrency: 50,
+            }
+        );
+        assert!(BenchOptions::parse(["--requests", "0"].map(String::from)).is_err());
+        assert!(BenchOptions::parse(["stripe".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        assert_eq!(LatencySummary::new(Vec::new()), None);
+
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(
+            LatencySummary::new(samples),
+            Some(LatencySummary {
+                p50: Duration::from_millis(50),
+                p90: Duration::from_millis(90),
+                p99: Duration::from_millis(99),
+                max: Duration::from_millis(100),
+            })
+        );
+    }
+
+    #[test]
+    fn test_pool_usage() {
+        let mut usage = PoolUsage::new(4);
+        usage.record(PoolStatus { size: 4, idle: 3 });
+        usage.record(PoolStatus { size: 4, idle: 0 });
+        assert_eq!(usage.peak_in_use, 4);
+        assert_eq!(usage.saturated_sample_count, 1);
+        assert_eq!(
+            usage.summary(),
+            "database pool: 2.5 connections in use on average, peak 4 of 4, saturated 50% of the time"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    convert::Infallible,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use axum::{body::StreamBody, response::IntoResponse, routing::post, Extension, Json, Router};
+use futures::StreamExt as _;
+use serde_json::json;
+use time::OffsetDateTime;
+use util::ResultExt;
+
+/// How a fake model provider streams its responses.
+#[derive(Clone, Copy, Debug)]
+pub struct FakeModelOptions {
+    pub time_to_first_token: Duration,
+    pub token_interval: Duration,
+    pub output_tokens: u32,
+}
+
+/// The number of input tokens the fake model provider reports for every
+/// request.
+const FAKE_INPUT_TOKENS: u32 = 1_000;
+
+struct FakeStripe {
+    latency: Duration,
+    next_id: AtomicUsize,
+}
+
+/// Serves the Stripe endpoints used when creating a checkout session,
+/// responding after `latency`. Returns the base URL to configure as the
+/// server's `stripe_api_url`.
+pub fn serve_fake_stripe(latency: Duration) -> anyhow::Result<String> {
+    let router = Router::new()
+        .route("/v1/customers", post(create_customer))
+        .route("/v1/checkout/sessions", post(create_checkout_session))
+        .layer(Extension(Arc::new(FakeStripe {
+            latency,
+            next_id: AtomicUsize::new(1),
+        })));
+    serve(router).map(|address| format!("http://{address}/"))
+}
+
+async fn create_customer(Extension(stripe): Extension<Arc<FakeStripe>>) -> impl IntoResponse {
+    tokio::time::sleep(stripe.latency).await;
+    let id = stripe.next_id.fetch_add(1, SeqCst);
+    Json(json!({
+        "id": format!("cus_bench{id}"),
+        "object": "customer",
+        "address": null,
+        "balance": 0,
+        "created": OffsetDateTime::now_utc().unix_timestamp(),
+        "currency": null,
+        "default_source": null,
+        "delinquent": false,
+        "description": null,
+        "discount": null,
+        "email": null,
+        "invoice_prefix": format!("BENCH{id}"),
+        "invoice_settings": {
+            "custom_fields": null,
+            "default_payment_method": null,
+            "footer": null,
+            "rendering_options": null
+        },
+        "livemode": false,
+        "metadata": {},
+        "name": null,
+        "next_invoice_sequence": 1,
+        "phone": null,
+        "preferred_locales": [],
+        "shipping": null,
+        "tax_exempt": "none",
+        "test_clock": null
+    }))
+}
+
+async fn create_checkout_session(
+    Extension(stripe): Extension<Arc<FakeStripe>>,
+) -> impl IntoResponse {
+    tokio::time::sleep(stripe.latency).await;
+    let id = stripe.next_id.fetch_add(1, SeqCst);
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    Json(json!({
+        "id": format!("cs_test_bench{id}"),
+        "object": "checkout.session",
+        "after_expiration": null,
+        "allow_promotion_codes": null,
+        "amount_subtotal": 2000,
+        "amount_total": 2000,
+        "automatic_tax": { "enabled": false, "liability": null, "status": null },
+        "billing_address_collection": null,
+        "cancel_url": null,
+        "client_reference_id": null,
+        "consent": null,
+        "consent_collection": null,
+        "created": now,
+        "currency": "usd",
+        "custom_fields": [],
+        "custom_text": {
+            "after_submit": null,
+            "shipping_address": null,
+            "submit": null,
+            "terms_of_service_acceptance": null
+        },
+        "customer": null,
+        "customer_creation": null,
+        "customer_details": null,
+        "customer_email": null,
+        "expires_at": now + 24 * 60 * 60,
+        "invoice": null,
+        "invoice_creation": null,
+        "livemode": false,
+        "locale": null,
+        "metadata": {},
+        "mode": "subscription",
+        "payment_intent": null,
+        "payment_link": null,
+        "payment_method_collection": "always",
+        "payment_method_options": {},
+        "payment_method_types": ["card"],
+        "payment_status": "unpaid",
+        "phone_number_collection": { "enabled": false },
+        "recovered_from": null,
+        "setup_intent": null,
+        "shipping_address_collection": null,
+        "shipping_cost": null,
+        "shipping_details": null,
+        "shipping_options": [],
+        "status": "open",
+        "submit_type": null,
+        "subscription": null,
+        "success_url": "https://zed.dev/billing/success",
+        "total_details": null,
+        "ui_mode": "hosted",
+        "url": format!("https://checkout.stripe.com/c/pay/cs_test_bench{id}")
+    }))
+}
+
+/// Serves a fake Anthropic messages API that streams a canned response.
+/// Returns the base URL to configure as the server's `anthropic_api_url`.
+pub fn serve_fake_anthropic(options: FakeModelOptions) -> anyhow::Result<String> {
+    let router = Router::new()
+        .route("/v1/messages", post(stream_message))
+        .layer(Extension(options));
+    serve(router).map(|address| format!("http://{address}"))
+}
+
+async fn stream_message(
+    Extension(options): Extension<FakeModelOptions>,
+    Json(request): Json<anthropic::Request>,
+) -> impl IntoResponse {
+    let mut events = vec![
+        anthropic::Event::MessageStart {
+            message: anthropic::Response {
+                id: "msg_bench".into(),
+                response_type: "message".into(),
+                role: anthropic::Role::Assistant,
+                content: Vec::new(),
+                model: request.model,
+                stop_reason: None,
+                stop_sequence: None,
+                usage: anthropic::Usage {
+                    input_tokens: Some(FAKE_INPUT_TOKENS),
+                    output_tokens: Some(1),
+                },
+            },
+        },
+        anthropic::Event::ContentBlockStart {
+            index: 0,
+            content_block: anthropic::Content::Text {
+                text: String::new(),
+            },
+        },
+    ];
+    for _ in 0..options.output_tokens {
+        events.push(anthropic::Event::ContentBlockDelta {
+            index: 0,
+            delta: anthropic::ContentDelta::TextDelta {
+                text: "token ".into(),
+            },
+        });
+    }
+    events.extend([
+        anthropic::Event::ContentBlockStop { index: 0 },
+        anthropic::Event::MessageDelta {
+            delta: anthropic::MessageDelta {
+                stop_reason: Some("end_turn".into()),
+                stop_sequence: None,
+            },
+            usage: anthropic::Usage {
+                input_tokens: None,
+                output_tokens: Some(options.output_tokens),
+            },
+        },
+        anthropic::Event::MessageStop,
+    ]);
+
+    let chunks =
+        futures::stream::iter(events.into_iter().enumerate()).then(move |(ix, event)| async move {
+            let delay = if ix == 0 {
+                options.time_to_first_token
+            } else {
+                options.token_interval
+            };
+            tokio::time::sleep(delay).await;
+            let data = serde_json::to_string(&event).unwrap_or_default();
+            Ok::<_, Infallible>(format!("data: {data}\n\n"))
+        });
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+        StreamBody::new(chunks),
+    )
+}
+
+/// Serves the router on an unused local port.
+fn serve(router: Router) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let server = axum::Server::from_tcp(listener)
+        .map_err(|error| anyhow!(error))?
+        .serve(router.into_make_service());
+    tokio::spawn(async move { server.await.log_err() });
+    Ok(address)
+}
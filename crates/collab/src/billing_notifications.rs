@@ -0,0 +1,223 @@
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+use crate::api::billing_history::format_amount;
+use crate::db::{CreateEmailDeliveryParams, UserId};
+use crate::AppState;
+
+const BILLING_URL: &str = "https://zed.dev/billing";
+
+/// A notification about a user's billing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BillingNotification {
+    /// An attempt to collect payment for an invoice failed.
+    PaymentFailed {
+        stripe_invoice_id: String,
+        amount_in_cents: i64,
+        currency: String,
+        /// The number of attempts made to collect payment so far.
+        attempt_count: u64,
+        /// When Stripe retries collecting payment, if it does.
+        next_payment_attempt: Option<OffsetDateTime>,
+        /// When the subscription stops granting access if payment still
+        /// hasn't been collected.
+        grace_period_ends_at: Option<OffsetDateTime>,
+    },
+    /// An unpaid subscription stopped granting access, after every retry of
+    /// collecting payment for it failed.
+    SubscriptionSuspended {
+        stripe_subscription_id: String,
+        suspended_at: OffsetDateTime,
+    },
+    /// A suspended subscription was paid for, and grants access again.
+    SubscriptionReinstated {
+        stripe_subscription_id: String,
+        /// When the subscription was suspended, which tells its suspensions
+        /// apart.
+        suspended_at: OffsetDateTime,
+    },
+}
+
+impl BillingNotification {
+    /// Identifies the notification among those sent to a user.
+    fn key(&self) -> String {
+        match self {
+            BillingNotification::PaymentFailed {
+                stripe_invoice_id,
+                attempt_count,
+                ..
+            } => format!("payment_failed:{stripe_invoice_id}:{attempt_count}"),
+            BillingNotification::SubscriptionSuspended {
+                stripe_subscription_id,
+                suspended_at,
+            } => format!(
+                "subscription_suspended:{stripe_subscription_id}:{}",
+                suspended_at.unix_timestamp()
+            ),
+            BillingNotification::SubscriptionReinstated {
+                stripe_subscription_id,
+                suspended_at,
+            } => format!(
+                "subscription_reinstated:{stripe_subscription_id}:{}",
+                suspended_at.unix_timestamp()
+            ),
+        }
+    }
+
+    /// Returns the subject and body of the notification's email.
+    fn email(&self) -> (&'static str, String) {
+        match self {
+            BillingNotification::PaymentFailed {
+                amount_in_cents,
+                currency,
+                next_payment_attempt,
+                grace_period_ends_at,
+                ..
+            } => {
+                let amount = format_amount(*amount_in_cents, currency);
+                let retry = match next_payment_attempt {
+                    Some(next_payment_attempt) => {
+                        format!("We'll try again on {}.", format_date(*next_payment_attempt))
+                    }
+                    None => "We won't try to collect it again.".to_string(),
+                };
+                let access = match grace_period_ends_at {
+                    Some(grace_period_ends_at) => format!(
+                        "Your subscription stays active until {}. ",
+                        format_date(*grace_period_ends_at)
+                    ),
+                    None => String::new(),
+                };
+                (
+                    "Your payment for Zed failed",
+                    format!(
+                        "We couldn't collect your payment of {amount} for Zed. {retry}\n\n\
+                         {access}Update your payment method at {BILLING_URL} \
+                         to keep your subscription from lapsing."
+                    ),
+                )
+            }
+            BillingNotification::SubscriptionSuspended { .. } => (
+                "Your Zed subscription has been suspended",
+                format!(
+                    "We couldn't collect payment for your Zed subscription, so it has been \
+                     suspended, and you're on the Free plan until it's paid for.\n\n\
+                     Update your payment method at {BILLING_URL} to pay the outstanding \
+                     invoice, and your subscription will be reinstated right away."
+                ),
+            ),
+            BillingNotification::SubscriptionReinstated { .. } => (
+                "Your Zed subscription has been reinstated",
+                format!(
+                    "Thanks for your payment. Your Zed subscription has been reinstated, \
+                     and you have access to everything in your plan again.\n\n\
+                     You can manage your subscription at {BILLING_URL}."
+                ),
+            ),
+        }
+    }
+}
+
+/// Queues the notification's email to the user, unless it was already sent.
+///
+/// Returns whether the email was queued.
+pub async fn send_billing_notification(
+    app: &AppState,
+    user_id: UserId,
+    notification: &BillingNotification,
+) -> anyhow::Result<bool> {
+    let Some(user) = app.db.get_user_by_id(user_id).await? else {
+        return Ok(false);
+    };
+    let billing_email = app
+        .db
+        .get_billing_customer_by_user_id(user_id)
+        .await?
+        .and_then(|billing_customer| billing_customer.billing_email);
+    let Some(email_address) = billing_email.or(user.email_address) else {
+        return Ok(false);
+    };
+
+    let (subject, body) = notification.email();
+    let delivery = app
+        .db
+        .enqueue_billing_notification(
+            user_id,
+            &notification.key(),
+            &CreateEmailDeliveryParams {
+                recipient: email_address,
+                subject: subject.to_string(),
+                body,
+            },
+        )
+        .await?;
+    Ok(delivery.is_some())
+}
+
+fn format_date(date_time: OffsetDateTime) -> String {
+    date_time
+        .format(format_description!(
+            "[month repr:long] [day padding:none], [year]"
+        ))
+        .unwrap_or_else(|_| date_time.date().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_payment_failed_email() {
+        let notification = BillingNotification::PaymentFailed {
+            stripe_invoice_id: "in_1".into(),
+            amount_in_cents: 2000,
+            currency: "usd".into(),
+            attempt_count: 2,
+            next_payment_attempt: Some(datetime!(2024-09-03 10:00 UTC)),
+            grace_period_ends_at: Some(datetime!(2024-09-08 10:00 UTC)),
+        };
+        assert_eq!(notification.key(), "payment_failed:in_1:2");
+        let (_, body) = notification.email();
+        assert_eq!(
+            body,
+            "We couldn't collect your payment of 20.00 USD for Zed. \
+             We'll try again on September 3, 2024.\n\n\
+             Your subscription stays active until September 8, 2024. \
+             Update your payment method at https://zed.dev/billing \
+             to keep your subscription from lapsing."
+        );
+
+        let notification = BillingNotification::PaymentFailed {
+            stripe_invoice_id: "in_1".into(),
+            amount_in_cents: 2000,
+            currency: "usd".into(),
+            attempt_count: 4,
+            next_payment_attempt: None,
+            grace_period_ends_at: None,
+        };
+        let (_, body) = notification.email();
+        assert_eq!(
+            body,
+            "We couldn't collect your payment of 20.00 USD for Zed. \
+             We won't try to collect it again.\n\n\
+             Update your payment method at https://zed.dev/billing \
+             to keep your subscription from lapsing."
+        );
+    }
+
+    #[test]
+    fn test_suspension_emails() {
+        let suspended = BillingNotification::SubscriptionSuspended {
+            stripe_subscription_id: "sub_1".into(),
+            suspended_at: datetime!(2024-09-10 10:00 UTC),
+        };
+        let reinstated = BillingNotification::SubscriptionReinstated {
+            stripe_subscription_id: "sub_1".into(),
+            suspended_at: datetime!(2024-09-10 10:00 UTC),
+        };
+        assert_eq!(suspended.key(), "subscription_suspended:sub_1:1725962400");
+        assert_eq!(reinstated.key(), "subscription_reinstated:sub_1:1725962400");
+        assert!(suspended.email().1.contains("https://zed.dev/billing"));
+    }
+}
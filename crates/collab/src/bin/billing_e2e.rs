@@ -0,0 +1,342 @@
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, ensure, Context as _, Result};
+use collab_api_client::{
+    CollabApiClient, CreateBillingSubscriptionBody, CreateSandboxOrganizationBody,
+    CreateSandboxOrganizationResponse, DeleteSandboxOrganizationParams,
+    GetLanguageModelUsageParams, ListBillingSubscriptionsParams, RecordSandboxUsageBody,
+    SandboxUser, SubscriptionPlan, SubscriptionStatus,
+};
+use http_client::IsahcHttpClient;
+use stripe::{
+    AdvanceTestClock, CancelSubscription, CreateSubscription, CreateSubscriptionItems, CustomerId,
+    Subscription, TestHelpersTestClock, TestHelpersTestClockId, TestHelpersTestClockStatus,
+};
+
+/// How long to wait for Stripe to deliver a webhook and for it to be
+/// processed.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+const WEBHOOK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+struct Env {
+    client: CollabApiClient,
+    stripe_client: stripe::Client,
+    admin_github_user_id: i32,
+    price_id: String,
+}
+
+/// Runs the end-to-end billing tests against a deployment with a test-mode
+/// Stripe account, configured through `COLLAB_API_URL`, `COLLAB_API_TOKEN`,
+/// `ADMIN_GITHUB_USER_ID`, `STRIPE_API_KEY` and `STRIPE_PRICE_ID`.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let env = Env {
+        client: CollabApiClient::new(
+            env_var("COLLAB_API_URL")?,
+            env_var("COLLAB_API_TOKEN")?,
+            Arc::new(IsahcHttpClient::new()?),
+        ),
+        stripe_client: stripe::Client::new(env_var("STRIPE_API_KEY")?),
+        admin_github_user_id: env_var("ADMIN_GITHUB_USER_ID")?
+            .parse()
+            .context("invalid ADMIN_GITHUB_USER_ID")?,
+        price_id: env_var("STRIPE_PRICE_ID")?,
+    };
+
+    let sandbox = env
+        .client
+        .create_sandbox_organization(&CreateSandboxOrganizationBody {
+            github_user_id: env.admin_github_user_id,
+            member_count: 2,
+            expires_in_minutes: Some(30),
+        })
+        .await
+        .context("failed to provision sandbox organization")?;
+    println!(
+        "provisioned sandbox organization {}",
+        sandbox.organization_id
+    );
+
+    let result = run_tests(&env, &sandbox).await;
+
+    let teardown = env
+        .client
+        .delete_sandbox_organization(
+            sandbox.organization_id,
+            &DeleteSandboxOrganizationParams {
+                github_user_id: env.admin_github_user_id,
+            },
+        )
+        .await;
+    match &teardown {
+        Ok(_) => println!("tore down sandbox organization {}", sandbox.organization_id),
+        Err(error) => eprintln!(
+            "failed to tear down sandbox organization {}: {error:?}",
+            sandbox.organization_id
+        ),
+    }
+
+    result?;
+    teardown?;
+    Ok(())
+}
+
+async fn run_tests(env: &Env, sandbox: &CreateSandboxOrganizationResponse) -> Result<()> {
+    let owner = &sandbox.users[0];
+    let customer_id = owner
+        .stripe_customer_id
+        .as_deref()
+        .ok_or_else(|| anyhow!("sandbox owner has no Stripe customer"))?;
+    let customer_id = CustomerId::from_str(customer_id).context("invalid customer ID")?;
+
+    step(
+        "free quota is enforced",
+        test_free_quota(env, sandbox, owner),
+    )
+    .await?;
+    step("checkout", test_checkout(env, owner)).await?;
+
+    // The sandbox owner has a test card, so their subscription can be
+    // created as Checkout would once they'd paid.
+    let mut params = CreateSubscription::new(customer_id);
+    params.items = Some(vec![CreateSubscriptionItems {
+        price: Some(env.price_id.clone()),
+        ..Default::default()
+    }]);
+    params.metadata = Some(
+        [("plan".to_string(), "pro".to_string())]
+            .into_iter()
+            .collect(),
+    );
+    let stripe_subscription = Subscription::create(&env.stripe_client, params).await?;
+
+    step(
+        "subscription webhook delivery",
+        wait_for_subscription(env, owner, SubscriptionStatus::Active),
+    )
+    .await?;
+    step("paid quota", test_paid_quota(env, owner)).await?;
+    if let Some(test_clock_id) = owner.stripe_test_clock_id.as_deref() {
+        step(
+            "renewal",
+            test_renewal(env, owner, test_clock_id, &stripe_subscription),
+        )
+        .await?;
+    }
+
+    Subscription::cancel(
+        &env.stripe_client,
+        &stripe_subscription.id,
+        CancelSubscription::new(),
+    )
+    .await?;
+    step("cancellation", test_cancellation(env, owner)).await?;
+
+    Ok(())
+}
+
+async fn step(name: &str, test: impl Future<Output = Result<()>>) -> Result<()> {
+    let start = Instant::now();
+    match test.await {
+        Ok(()) => {
+            println!("{name} ... ok ({:?})", start.elapsed());
+            Ok(())
+        }
+        Err(error) => {
+            println!("{name} ... FAILED");
+            Err(error.context(format!("{name} failed")))
+        }
+    }
+}
+
+async fn test_free_quota(
+    env: &Env,
+    sandbox: &CreateSandboxOrganizationResponse,
+    owner: &SandboxUser,
+) -> Result<()> {
+    let usage = env
+        .client
+        .get_language_model_usage(&GetLanguageModelUsageParams {
+            github_user_id: owner.github_user_id,
+        })
+        .await?;
+    ensure!(
+        usage.plan == "free",
+        "expected free plan, got {}",
+        usage.plan
+    );
+    ensure!(usage.remaining_tokens > 0, "expected tokens to remain");
+
+    env.client
+        .record_sandbox_usage(
+            sandbox.organization_id,
+            &RecordSandboxUsageBody {
+                github_user_id: env.admin_github_user_id,
+                member_github_user_id: owner.github_user_id,
+                tokens: usage.remaining_tokens,
+            },
+        )
+        .await?;
+    let usage = env
+        .client
+        .get_language_model_usage(&GetLanguageModelUsageParams {
+            github_user_id: owner.github_user_id,
+        })
+        .await?;
+    ensure!(
+        usage.remaining_tokens == 0,
+        "expected the quota to be used up, but {} tokens remain",
+        usage.remaining_tokens
+    );
+    Ok(())
+}
+
+async fn test_checkout(env: &Env, owner: &SandboxUser) -> Result<()> {
+    let response = env
+        .client
+        .create_billing_subscription(&CreateBillingSubscriptionBody {
+            github_user_id: owner.github_user_id,
+            plan: SubscriptionPlan::Pro,
+            seat_count: None,
+            promo_code: None,
+            currency: None,
+            country: None,
+            locale: None,
+            success_url: None,
+        })
+        .await?;
+    ensure!(
+        response
+            .checkout_session_url
+            .starts_with("https://checkout.stripe.com/"),
+        "unexpected checkout session URL {}",
+        response.checkout_session_url
+    );
+    Ok(())
+}
+
+async fn test_paid_quota(env: &Env, owner: &SandboxUser) -> Result<()> {
+    let usage = env
+        .client
+        .get_language_model_usage(&GetLanguageModelUsageParams {
+            github_user_id: owner.github_user_id,
+        })
+        .await?;
+    ensure!(usage.plan == "pro", "expected pro plan, got {}", usage.plan);
+    ensure!(
+        usage.remaining_tokens > 0,
+        "expected the pro plan to lift the quota"
+    );
+    Ok(())
+}
+
+/// Advances the test clock past the end of the subscription's first period,
+/// and checks that it renewed.
+async fn test_renewal(
+    env: &Env,
+    owner: &SandboxUser,
+    test_clock_id: &str,
+    stripe_subscription: &Subscription,
+) -> Result<()> {
+    let test_clock_id =
+        TestHelpersTestClockId::from_str(test_clock_id).context("invalid test clock ID")?;
+    let period_end = stripe_subscription.current_period_end;
+    TestHelpersTestClock::advance(
+        &env.stripe_client,
+        &test_clock_id,
+        &AdvanceTestClock {
+            frozen_time: period_end + 24 * 60 * 60,
+        },
+    )
+    .await?;
+
+    let deadline = Instant::now() + WEBHOOK_TIMEOUT;
+    loop {
+        let test_clock = TestHelpersTestClock::retrieve(&env.stripe_client, &test_clock_id).await?;
+        if test_clock.status == Some(TestHelpersTestClockStatus::Ready) {
+            break;
+        }
+        ensure!(
+            Instant::now() < deadline,
+            "timed out waiting for test clock to advance, status is {:?}",
+            test_clock.status
+        );
+        tokio::time::sleep(WEBHOOK_POLL_INTERVAL).await;
+    }
+
+    loop {
+        let subscriptions = env
+            .client
+            .list_billing_subscriptions(&ListBillingSubscriptionsParams {
+                github_user_id: owner.github_user_id,
+            })
+            .await?
+            .subscriptions;
+        if subscriptions.iter().any(|subscription| {
+            subscription.status == SubscriptionStatus::Active
+                && subscription
+                    .current_period_end
+                    .map_or(false, |end| end.unix_timestamp() > period_end)
+        }) {
+            return Ok(());
+        }
+        ensure!(
+            Instant::now() < deadline,
+            "timed out waiting for the subscription to renew, got {subscriptions:?}"
+        );
+        tokio::time::sleep(WEBHOOK_POLL_INTERVAL).await;
+    }
+}
+
+async fn test_cancellation(env: &Env, owner: &SandboxUser) -> Result<()> {
+    wait_for_subscription(env, owner, SubscriptionStatus::Canceled).await?;
+    let usage = env
+        .client
+        .get_language_model_usage(&GetLanguageModelUsageParams {
+            github_user_id: owner.github_user_id,
+        })
+        .await?;
+    ensure!(
+        usage.plan == "free",
+        "expected free plan after cancellation, got {}",
+        usage.plan
+    );
+    Ok(())
+}
+
+/// Waits for the user's subscription to reach the given status, which
+/// happens once Stripe has delivered the webhook for it.
+async fn wait_for_subscription(
+    env: &Env,
+    user: &SandboxUser,
+    status: SubscriptionStatus,
+) -> Result<()> {
+    let deadline = Instant::now() + WEBHOOK_TIMEOUT;
+    loop {
+        let subscriptions = env
+            .client
+            .list_billing_subscriptions(&ListBillingSubscriptionsParams {
+                github_user_id: user.github_user_id,
+            })
+            .await?
+            .subscriptions;
+        if subscriptions
+            .iter()
+            .any(|subscription| subscription.status == status)
+        {
+            return Ok(());
+        }
+        ensure!(
+            Instant::now() < deadline,
+            "timed out waiting for a {status:?} subscription, got {subscriptions:?}"
+        );
+        tokio::time::sleep(WEBHOOK_POLL_INTERVAL).await;
+    }
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("{name} must be set"))
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use util::ResultExt;
+
+use crate::db::column_renames::{ColumnRename, COLUMN_RENAMES};
+use crate::distributed_lock::run_exclusively;
+use crate::AppState;
+
+const BACKFILL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of rows backfilled in a single transaction.
+const BACKFILL_BATCH_SIZE: u64 = 1_000;
+
+/// The number of mismatched rows to report for each rename.
+const MISMATCH_REPORT_LIMIT: u64 = 100;
+
+/// Periodically backfills and verifies the columns that are being renamed.
+pub fn backfill_renamed_columns_periodically(app: Arc<AppState>) {
+    if COLUMN_RENAMES.is_empty() {
+        return;
+    }
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "backfill_renamed_columns",
+                        backfill_renamed_columns(&app),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, BACKFILL_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn backfill_renamed_columns(app: &AppState) -> anyhow::Result<()> {
+    for rename in COLUMN_RENAMES {
+        backfill_renamed_column(app, rename)
+            .await
+            .with_context(|| format!("failed to backfill column rename {}", rename.name))
+            .log_err();
+    }
+    Ok(())
+}
+
+async fn backfill_renamed_column(app: &AppState, rename: &ColumnRename) -> anyhow::Result<()> {
+    let mut backfilled_count = 0;
+    while !app.shutdown.is_shutting_down() {
+        let count = app
+            .db
+            .backfill_renamed_column(rename, BACKFILL_BATCH_SIZE)
+            .await?;
+        backfilled_count += count;
+        if count < BACKFILL_BATCH_SIZE {
+            break;
+        }
+    }
+    if backfilled_count > 0 {
+        log::info!(
+            "backfilled {backfilled_count} rows for column rename {}",
+            rename.name
+        );
+    }
+
+    let mismatched_ids = app
+        .db
+        .find_renamed_column_mismatches(rename, MISMATCH_REPORT_LIMIT)
+        .await?;
+    if mismatched_ids.is_empty() {
+        log::info!("column rename {} is consistent", rename.name);
+    } else {
+        log::error!(
+            "column rename {} has mismatched rows in {}: {mismatched_ids:?}",
+            rename.name,
+            rename.table
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use collab_api_client::{CurrencyFormat, CurrencySymbolPosition};
+
+/// The symbols of the currencies we price plans in, keyed by their lowercase
+/// ISO 4217 code. Other currencies are shown with their code.
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("aud", "A$"),
+    ("brl", "R$"),
+    ("cad", "CA$"),
+    ("eur", "€"),
+    ("gbp", "£"),
+    ("inr", "₹"),
+    ("jpy", "¥"),
+    ("nzd", "NZ$"),
+    ("usd", "$"),
+];
+
+/// The currencies Stripe represents in whole units.
+///
+/// See <https://docs.stripe.com/currencies#zero-decimal>.
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &[
+    "bif", "clp", "djf", "gnf", "jpy", "kmf", "krw", "mga", "pyg", "rwf", "ugx", "vnd", "vuv",
+    "xaf", "xof", "xpf",
+];
+
+/// The currencies Stripe represents in thousandths.
+///
+/// See <https://docs.stripe.com/currencies#three-decimal>.
+const THREE_DECIMAL_CURRENCIES: &[&str] = &["bhd", "jod", "kwd", "omr", "tnd"];
+
+/// The languages that write a currency's symbol after the amount, such as
+/// `20,00 €`.
+const SYMBOL_AFTER_LANGUAGES: &[&str] = &[
+    "cs", "da", "de", "es", "fi", "fr", "it", "nb", "pl", "pt", "ru", "sv",
+];
+
+/// Returns how to format amounts of the currency with the given ISO 4217
+/// code for a reader in the given locale, such as `fr-CA`.
+pub fn currency_format(currency: &str, locale: Option<&str>) -> CurrencyFormat {
+    let currency = currency.trim().to_ascii_lowercase();
+    let symbol = CURRENCY_SYMBOLS
+        .iter()
+        .find(|(code, _)| *code == currency)
+        .map_or_else(
+            || currency.to_ascii_uppercase(),
+            |(_, symbol)| symbol.to_string(),
+        );
+    CurrencyFormat {
+        code: currency.to_ascii_uppercase(),
+        symbol,
+        symbol_position: symbol_position(locale),
+        minor_units: minor_units(&currency),
+    }
+}
+
+/// Returns the number of decimal places of the currency, which amounts in
+/// its smallest unit are divided by 10 to the power of.
+pub fn minor_units(currency: &str) -> u32 {
+    let currency = currency.to_ascii_lowercase();
+    if ZERO_DECIMAL_CURRENCIES.contains(&currency.as_str()) {
+        0
+    } else if THREE_DECIMAL_CURRENCIES.contains(&currency.as_str()) {
+        3
+    } else {
+        2
+    }
+}
+
+fn symbol_position(locale: Option<&str>) -> CurrencySymbolPosition {
+    let language = locale
+        .and_then(|locale| locale.split(['-', '_']).next())
+        .map(str::to_ascii_lowercase);
+    match language {
+        Some(language) if SYMBOL_AFTER_LANGUAGES.contains(&language.as_str()) => {
+            CurrencySymbolPosition::After
+        }
+        _ => CurrencySymbolPosition::Before,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_currency_format() {
+        assert_eq!(
+            currency_format("usd", None),
+            CurrencyFormat {
+                code: "USD".into(),
+                symbol: "$".into(),
+                symbol_position: CurrencySymbolPosition::Before,
+                minor_units: 2,
+            }
+        );
+        assert_eq!(
+            currency_format("EUR", Some("fr-CA")),
+            CurrencyFormat {
+                code: "EUR".into(),
+                symbol: "€".into(),
+                symbol_position: CurrencySymbolPosition::After,
+                minor_units: 2,
+            }
+        );
+        assert_eq!(
+            currency_format("jpy", Some("ja")),
+            CurrencyFormat {
+                code: "JPY".into(),
+                symbol: "¥".into(),
+                symbol_position: CurrencySymbolPosition::Before,
+                minor_units: 0,
+            }
+        );
+        // Currencies without a well-known symbol are shown with their code.
+        assert_eq!(currency_format("chf", Some("de-CH")).symbol, "CHF");
+    }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(minor_units("usd"), 2);
+        assert_eq!(minor_units("KRW"), 0);
+        assert_eq!(minor_units("kwd"), 3);
+    }
+}
@@ -1,3 +1,5 @@
+mod column_encryption;
+pub mod column_renames;
 mod ids;
 mod queries;
 mod tables;
@@ -44,14 +46,53 @@ use tokio::sync::{Mutex, OwnedMutexGuard};
 #[cfg(test)]
 pub use tests::TestDb;
 
+pub use column_encryption::ColumnCipher;
+use column_renames::ColumnRename;
 pub use ids::*;
-pub use queries::billing_customers::CreateBillingCustomerParams;
-pub use queries::billing_subscriptions::CreateBillingSubscriptionParams;
+pub use queries::billing_audit_log::CreateBillingAuditLogEntryParams;
+pub use queries::billing_customer_transfers::TransferBillingCustomerParams;
+pub use queries::billing_customers::{
+    BillingCustomerSensitiveDetails, CreateBillingCustomerParams,
+};
+pub use queries::billing_invoice_events::CreateBillingInvoiceEventParams;
+pub use queries::billing_invoice_line_items::CreateBillingInvoiceLineItemParams;
+pub use queries::billing_subscriptions::{
+    BillingSubscriptionSnapshot, CreateBillingSubscriptionParams, UpsertBillingSubscriptionOptions,
+};
+pub use queries::complimentary_subscriptions::CreateComplimentarySubscriptionParams;
 pub use queries::contributors::ContributorSelector;
+pub use queries::emails::CreateEmailDeliveryParams;
+pub use queries::external_entitlements::GrantExternalEntitlementParams;
+pub use queries::impersonation_sessions::CreateImpersonationSessionParams;
+pub use queries::language_model_top_ups::CreateLanguageModelTopUpParams;
+pub use queries::language_model_usages::{
+    CreateLanguageModelUsageParams, DailyLanguageModelUsage, LanguageModelFeatureCost,
+};
+pub use queries::model_experiments::CreateModelExperimentVariantParams;
+pub use queries::organization_seat_true_ups::CreateOrganizationSeatTrueUpParams;
+pub use queries::organization_secrets::OrganizationSecretLease;
+pub use queries::sandbox_organizations::CreateSandboxOrganizationParams;
+pub use queries::trial_abuse_flags::PastTrial;
+pub use queries::usage_records::CreateUsageRecordParams;
 pub use sea_orm::ConnectOptions;
 pub use tables::user::Model as User;
 pub use tables::*;
 
+/// A snapshot of the database connection pool's usage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// The number of open connections.
+    pub size: u32,
+    /// The number of open connections that aren't in use.
+    pub idle: u32,
+}
+
+impl PoolStatus {
+    pub fn in_use(&self) -> u32 {
+        self.size.saturating_sub(self.idle)
+    }
+}
+
 /// Database gives you a handle that lets you access the database.
 /// It handles pooling internally.
 pub struct Database {
@@ -63,6 +104,8 @@ pub struct Database {
     executor: Executor,
     notification_kinds_by_id: HashMap<NotificationKindId, &'static str>,
     notification_kinds_by_name: HashMap<String, NotificationKindId>,
+    column_cipher: Option<ColumnCipher>,
+    renamed_column_reads: parking_lot::RwLock<HashSet<String>>,
     #[cfg(test)]
     runtime: Option<tokio::runtime::Runtime>,
 }
@@ -81,12 +124,57 @@ impl Database {
             rng: Mutex::new(StdRng::seed_from_u64(0)),
             notification_kinds_by_id: HashMap::default(),
             notification_kinds_by_name: HashMap::default(),
+            column_cipher: None,
+            renamed_column_reads: Default::default(),
             executor,
             #[cfg(test)]
             runtime: None,
         })
     }
 
+    /// Closes the connections in the pool, waiting for those in use to be
+    /// returned to it.
+    pub async fn close(&self) -> Result<()> {
+        self.pool.clone().close().await?;
+        Ok(())
+    }
+
+    /// Returns how many of the pool's connections are open, and how many of
+    /// those are idle. Only Postgres pools report this.
+    pub fn pool_status(&self) -> Option<PoolStatus> {
+        match &self.pool {
+            DatabaseConnection::SqlxPostgresPoolConnection(_) => {
+                let pool = self.pool.get_postgres_connection_pool();
+                Some(PoolStatus {
+                    size: pool.size(),
+                    idle: pool.num_idle() as u32,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the cipher used to encrypt and decrypt sensitive columns.
+    pub fn set_column_cipher(&mut self, cipher: ColumnCipher) {
+        self.column_cipher = Some(cipher);
+    }
+
+    fn column_cipher(&self) -> Result<&ColumnCipher> {
+        Ok(self
+            .column_cipher
+            .as_ref()
+            .ok_or_else(|| anyhow!("column encryption is not configured"))?)
+    }
+
+    /// Sets the column renames whose reads use the new column, by name.
+    pub fn set_renamed_column_reads(&self, names: impl IntoIterator<Item = String>) {
+        *self.renamed_column_reads.write() = names.into_iter().collect();
+    }
+
+    fn reads_renamed_column(&self, rename: &ColumnRename) -> bool {
+        self.renamed_column_reads.read().contains(rename.name)
+    }
+
     #[cfg(test)]
     pub fn reset(&self) {
         self.rooms.clear();
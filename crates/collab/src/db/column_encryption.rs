@@ -0,0 +1,188 @@
+use anyhow::{anyhow, bail, Context as _};
+use base64::prelude::*;
+use collections::HashMap;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Encrypts and decrypts sensitive column values (such as billing addresses and
+/// tax IDs) before they are written to or after they are read from the database.
+///
+/// Ciphertexts are stored as `<key_id>:<base64(nonce || ciphertext || tag)>`, so
+/// that values encrypted with an older key can still be decrypted after the
+/// primary key has been rotated.
+pub struct ColumnCipher {
+    primary_key_id: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl ColumnCipher {
+    /// Parses a list of encryption keys in the form `<key_id>:<base64 key>`,
+    /// separated by commas.
+    ///
+    /// The first key in the list is the primary key, which is used to encrypt new
+    /// values. All keys in the list may be used for decryption.
+    pub fn from_config(keys: &str) -> anyhow::Result<Self> {
+        let mut primary_key_id = None;
+        let mut parsed_keys = HashMap::default();
+        for entry in keys
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (key_id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("encryption key must be in the form <key_id>:<key>"))?;
+            if key_id.is_empty() {
+                bail!("encryption key ID must not be empty");
+            }
+
+            let key = BASE64_STANDARD
+                .decode(key)
+                .with_context(|| format!("failed to decode encryption key {key_id}"))?;
+            let key = UnboundKey::new(&AES_256_GCM, &key)
+                .map_err(|_| anyhow!("encryption key {key_id} must be 32 bytes"))?;
+
+            if parsed_keys
+                .insert(key_id.to_string(), LessSafeKey::new(key))
+                .is_some()
+            {
+                bail!("duplicate encryption key ID {key_id}");
+            }
+            primary_key_id.get_or_insert_with(|| key_id.to_string());
+        }
+
+        let primary_key_id =
+            primary_key_id.ok_or_else(|| anyhow!("no encryption keys provided"))?;
+
+        Ok(Self {
+            primary_key_id,
+            keys: parsed_keys,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Encrypts the given value with the primary key.
+    ///
+    /// The column name is used as associated data so that a ciphertext cannot be
+    /// copied from one column into another.
+    pub fn encrypt(&self, column: &str, plaintext: &str) -> anyhow::Result<String> {
+        let key = &self.keys[&self.primary_key_id];
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("failed to generate nonce"))?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(column.as_bytes()),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow!("failed to encrypt {column}"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&in_out);
+
+        Ok(format!(
+            "{}:{}",
+            self.primary_key_id,
+            BASE64_STANDARD.encode(payload)
+        ))
+    }
+
+    /// Decrypts a value that was previously produced by [`ColumnCipher::encrypt`].
+    pub fn decrypt(&self, column: &str, ciphertext: &str) -> anyhow::Result<String> {
+        let (key_id, payload) = ciphertext
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed ciphertext for {column}"))?;
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow!("unknown encryption key {key_id} for {column}"))?;
+
+        let payload = BASE64_STANDARD
+            .decode(payload)
+            .with_context(|| format!("failed to decode ciphertext for {column}"))?;
+        if payload.len() < NONCE_LEN {
+            bail!("malformed ciphertext for {column}");
+        }
+
+        let (nonce, sealed) = payload.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("malformed nonce for {column}"))?;
+        let mut in_out = sealed.to_vec();
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(column.as_bytes()), &mut in_out)
+            .map_err(|_| anyhow!("failed to decrypt {column}"))?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    /// Returns whether the given ciphertext was encrypted with a key other than
+    /// the primary key, and should therefore be re-encrypted.
+    pub fn needs_reencryption(&self, ciphertext: &str) -> bool {
+        ciphertext
+            .split_once(':')
+            .map_or(true, |(key_id, _)| key_id != self.primary_key_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "a:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const KEY_B: &str = "b:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = ColumnCipher::from_config(KEY_A).unwrap();
+
+        let ciphertext = cipher.encrypt("tax_id", "DE123456789").unwrap();
+        assert!(ciphertext.starts_with("a:"));
+        assert!(!ciphertext.contains("DE123456789"));
+        assert_eq!(
+            cipher.decrypt("tax_id", &ciphertext).unwrap(),
+            "DE123456789"
+        );
+
+        // Ciphertexts are bound to the column they were written to.
+        assert!(cipher.decrypt("billing_address", &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old_cipher = ColumnCipher::from_config(KEY_A).unwrap();
+        let ciphertext = old_cipher.encrypt("tax_id", "DE123456789").unwrap();
+
+        // After rotating to a new primary key, old values can still be read, but
+        // are flagged for re-encryption.
+        let new_cipher = ColumnCipher::from_config(&format!("{KEY_B},{KEY_A}")).unwrap();
+        assert!(new_cipher.needs_reencryption(&ciphertext));
+        assert_eq!(
+            new_cipher.decrypt("tax_id", &ciphertext).unwrap(),
+            "DE123456789"
+        );
+
+        let reencrypted = new_cipher.encrypt("tax_id", "DE123456789").unwrap();
+        assert!(!new_cipher.needs_reencryption(&reencrypted));
+
+        // Once the old key is removed, old values can no longer be read.
+        let rotated_cipher = ColumnCipher::from_config(KEY_B).unwrap();
+        assert!(rotated_cipher.decrypt("tax_id", &ciphertext).is_err());
+        assert_eq!(
+            rotated_cipher.decrypt("tax_id", &reencrypted).unwrap(),
+            "DE123456789"
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(ColumnCipher::from_config("").is_err());
+        assert!(ColumnCipher::from_config("missing-separator").is_err());
+        assert!(ColumnCipher::from_config("short:AAAA").is_err());
+        assert!(ColumnCipher::from_config(&format!("{KEY_A},{KEY_A}")).is_err());
+    }
+}
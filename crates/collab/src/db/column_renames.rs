@@ -0,0 +1,60 @@
+/// A column that's being renamed.
+///
+/// Renamed columns must belong to a table whose primary key is `id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnRename {
+    /// The name the rename is referred to by in the `read_renamed_columns`
+    /// server setting.
+    pub name: &'static str,
+    pub table: &'static str,
+    pub old_column: &'static str,
+    pub new_column: &'static str,
+}
+
+impl ColumnRename {
+    /// Returns the value of the column that reads currently use, given the
+    /// values of both columns.
+    pub fn read<T>(&self, reads_new_column: bool, old_value: T, new_value: T) -> T {
+        if reads_new_column {
+            new_value
+        } else {
+            old_value
+        }
+    }
+}
+
+pub const BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END: ColumnRename = ColumnRename {
+    name: "billing_subscriptions.current_period_end",
+    table: "billing_subscriptions",
+    old_column: "current_period_end",
+    new_column: "current_period_ends_at",
+};
+
+/// The renames that are in progress, which the backfill job keeps in sync.
+///
+/// A rename is added once every write sets both columns, and removed once
+/// the old column is no longer written, before a migration drops it.
+pub const COLUMN_RENAMES: &[ColumnRename] = &[BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::HashSet;
+
+    #[test]
+    fn test_column_renames() {
+        let mut names = HashSet::default();
+        for rename in COLUMN_RENAMES {
+            assert!(
+                names.insert(rename.name),
+                "duplicate rename {}",
+                rename.name
+            );
+            assert_ne!(rename.old_column, rename.new_column);
+        }
+
+        let rename = BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END;
+        assert_eq!(rename.read(false, Some(1), Some(2)), Some(1));
+        assert_eq!(rename.read(true, Some(1), Some(2)), Some(2));
+    }
+}
@@ -68,31 +68,65 @@ macro_rules! id_type {
 }
 
 id_type!(AccessTokenId);
+id_type!(BillingAuditLogEntryId);
 id_type!(BillingCustomerId);
+id_type!(BillingCustomerTransferId);
+id_type!(BillingInvoiceEventId);
+id_type!(BillingInvoiceLineItemId);
+id_type!(BillingNotificationId);
 id_type!(BillingSubscriptionId);
+id_type!(BillingSubscriptionTransitionId);
 id_type!(BufferId);
 id_type!(ChannelBufferCollaboratorId);
 id_type!(ChannelChatParticipantId);
 id_type!(ChannelId);
 id_type!(ChannelMemberId);
+id_type!(ComplimentarySubscriptionId);
+id_type!(ConsentId);
 id_type!(ContactId);
 id_type!(DevServerId);
+id_type!(EmailDeliveryId);
+id_type!(EmailSuppressionId);
 id_type!(ExtensionId);
+id_type!(ExternalEntitlementId);
 id_type!(FlagId);
 id_type!(FollowerId);
 id_type!(HostedProjectId);
+id_type!(ImpersonatedRequestId);
+id_type!(ImpersonationSessionId);
+id_type!(LanguageModelTopUpId);
+id_type!(LanguageModelUsageId);
 id_type!(MessageId);
+id_type!(ModelExperimentExposureId);
+id_type!(ModelExperimentId);
+id_type!(ModelExperimentVariantId);
+id_type!(MonthlyLanguageModelUsageId);
 id_type!(NotificationId);
 id_type!(NotificationKindId);
+id_type!(OrganizationId);
+id_type!(OrganizationInvitationId);
+id_type!(OrganizationMemberDailyUsageId);
+id_type!(OrganizationMemberId);
+id_type!(OrganizationSeatTrueUpId);
+id_type!(OrganizationSecretId);
+id_type!(OrganizationSecretLeaseId);
+id_type!(PlanEnforcementActionId);
 id_type!(ProjectCollaboratorId);
 id_type!(ProjectId);
+id_type!(ReferralCodeId);
+id_type!(ReferralId);
+id_type!(UsageAnomalyId);
+id_type!(UsageRecordId);
 id_type!(DevServerProjectId);
 id_type!(ReplicaId);
 id_type!(RoomId);
 id_type!(RoomParticipantId);
 id_type!(ServerId);
+id_type!(ServerSettingChangeId);
 id_type!(SignupId);
+id_type!(TrialAbuseFlagId);
 id_type!(UserId);
+id_type!(UserSecretId);
 
 /// ChannelRole gives you permissions for both channels and calls.
 #[derive(
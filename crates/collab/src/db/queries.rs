@@ -1,21 +1,49 @@
 use super::*;
 
 pub mod access_tokens;
+pub mod billing_audit_log;
+pub mod billing_customer_transfers;
 pub mod billing_customers;
+pub mod billing_invoice_events;
+pub mod billing_invoice_line_items;
+pub mod billing_notifications;
 pub mod billing_subscriptions;
 pub mod buffers;
 pub mod channels;
+pub mod column_renames;
+pub mod complimentary_subscriptions;
+pub mod consents;
 pub mod contacts;
 pub mod contributors;
 pub mod dev_server_projects;
 pub mod dev_servers;
+pub mod distributed_lock_leases;
+pub mod emails;
 pub mod embeddings;
 pub mod extensions;
+pub mod external_entitlements;
 pub mod hosted_projects;
+pub mod impersonation_sessions;
+pub mod language_model_top_ups;
+pub mod language_model_usages;
 pub mod messages;
+pub mod model_experiments;
 pub mod notifications;
+pub mod organization_member_daily_usages;
+pub mod organization_seat_true_ups;
+pub mod organization_secrets;
+pub mod organizations;
+pub mod plan_enforcement_actions;
 pub mod projects;
 pub mod rate_buckets;
+pub mod referrals;
 pub mod rooms;
+pub mod sandbox_organizations;
+pub mod server_settings;
 pub mod servers;
+pub mod stripe_event_cursors;
+pub mod trial_abuse_flags;
+pub mod usage_anomalies;
+pub mod usage_records;
+pub mod user_secrets;
 pub mod users;
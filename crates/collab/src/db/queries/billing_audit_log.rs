@@ -0,0 +1,67 @@
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateBillingAuditLogEntryParams {
+    pub user_id: UserId,
+    pub actor_user_id: Option<UserId>,
+    pub event: BillingAuditEvent,
+    pub billing_subscription_id: Option<BillingSubscriptionId>,
+    pub previous_state: Option<String>,
+    pub state: Option<String>,
+}
+
+impl Database {
+    /// Records a change to a user's billing in the audit log.
+    pub async fn record_billing_audit_event(
+        &self,
+        params: &CreateBillingAuditLogEntryParams,
+    ) -> Result<()> {
+        self.transaction(
+            |tx| async move { self.insert_billing_audit_log_entry(params, &*tx).await },
+        )
+        .await
+    }
+
+    /// Returns the most recent entries in the billing audit log, newest
+    /// first, optionally only those about the given user.
+    pub async fn get_billing_audit_log(
+        &self,
+        user_id: Option<UserId>,
+        limit: u64,
+    ) -> Result<Vec<billing_audit_log_entry::Model>> {
+        self.transaction(|tx| async move {
+            let mut query = billing_audit_log_entry::Entity::find();
+            if let Some(user_id) = user_id {
+                query = query.filter(billing_audit_log_entry::Column::UserId.eq(user_id));
+            }
+            Ok(query
+                .order_by_desc(billing_audit_log_entry::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    pub(super) async fn insert_billing_audit_log_entry(
+        &self,
+        params: &CreateBillingAuditLogEntryParams,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        billing_audit_log_entry::Entity::insert(billing_audit_log_entry::ActiveModel {
+            user_id: ActiveValue::set(params.user_id),
+            actor_user_id: ActiveValue::set(params.actor_user_id),
+            event: ActiveValue::set(params.event),
+            billing_subscription_id: ActiveValue::set(params.billing_subscription_id),
+            previous_state: ActiveValue::set(params.previous_state.clone()),
+            state: ActiveValue::set(params.state.clone()),
+            ..Default::default()
+        })
+        .exec_without_returning(tx)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::SubscriptionPlan;
+use time::OffsetDateTime;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct TransferBillingCustomerParams {
+    pub billing_customer_id: BillingCustomerId,
+    pub to_user_id: UserId,
+    pub organization_id: Option<OrganizationId>,
+    pub transferred_by_user_id: UserId,
+    pub reason: Option<String>,
+}
+
+impl Database {
+    /// Moves the billing customer, along with its subscriptions, to another
+    /// user, recording the transfer in the audit log of both users.
+    ///
+    /// When transferred to an organization, the customer's team subscription
+    /// starts paying for the organization's seats.
+    ///
+    /// Fails if the recipient already has a billing customer of their own, or
+    /// if the organization's seats are paid for by another subscription.
+    pub async fn transfer_billing_customer(
+        &self,
+        params: &TransferBillingCustomerParams,
+    ) -> Result<billing_customer_transfer::Model> {
+        self.transaction(|tx| async move {
+            let customer = billing_customer::Entity::find_by_id(params.billing_customer_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("billing customer not found"))?;
+
+            let existing_customer = billing_customer::Entity::find()
+                .filter(billing_customer::Column::UserId.eq(params.to_user_id))
+                .one(&*tx)
+                .await?;
+            if existing_customer.is_some() {
+                Err(anyhow!(
+                    "user {} already has a billing customer",
+                    params.to_user_id
+                ))?;
+            }
+
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(customer.id),
+                user_id: ActiveValue::set(params.to_user_id),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            let mut billing_subscription_id = None;
+            if let Some(organization_id) = params.organization_id {
+                let organization = organization::Entity::find_by_id(organization_id)
+                    .one(&*tx)
+                    .await?
+                    .ok_or_else(|| anyhow!("organization not found"))?;
+                let subscription = billing_subscription::Entity::find()
+                    .filter(billing_subscription::Column::BillingCustomerId.eq(customer.id))
+                    .filter(billing_subscription::Column::Plan.eq(SubscriptionPlan::Team))
+                    .filter(
+                        super::billing_subscriptions::active_billing_subscription_condition(
+                            OffsetDateTime::now_utc(),
+                        ),
+                    )
+                    .order_by_desc(billing_subscription::Column::Id)
+                    .one(&*tx)
+                    .await?
+                    .ok_or_else(|| anyhow!("billing customer has no team subscription"))?;
+                let attached_elsewhere = organization::Entity::find()
+                    .filter(
+                        organization::Column::BillingSubscriptionId
+                            .eq(subscription.id)
+                            .and(organization::Column::Id.ne(organization_id)),
+                    )
+                    .one(&*tx)
+                    .await?
+                    .is_some();
+                if attached_elsewhere
+                    || organization
+                        .billing_subscription_id
+                        .map_or(false, |id| id != subscription.id)
+                {
+                    Err(anyhow!(
+                        "organization {organization_id} is paid for by another subscription"
+                    ))?;
+                }
+
+                organization::Entity::update(organization::ActiveModel {
+                    id: ActiveValue::unchanged(organization_id),
+                    billing_subscription_id: ActiveValue::set(Some(subscription.id)),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+                billing_subscription_id = Some(subscription.id);
+            }
+
+            let transfer =
+                billing_customer_transfer::Entity::insert(billing_customer_transfer::ActiveModel {
+                    billing_customer_id: ActiveValue::set(customer.id),
+                    from_user_id: ActiveValue::set(customer.user_id),
+                    to_user_id: ActiveValue::set(params.to_user_id),
+                    organization_id: ActiveValue::set(params.organization_id),
+                    transferred_by_user_id: ActiveValue::set(params.transferred_by_user_id),
+                    reason: ActiveValue::set(params.reason.clone()),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?;
+            self.record_billing_customer_transfer(&transfer, billing_subscription_id, &*tx)
+                .await?;
+            Ok(transfer)
+        })
+        .await
+    }
+
+    /// Undoes a transfer whose counterpart with the payment provider failed,
+    /// moving the billing customer back to the user it was transferred from.
+    ///
+    /// The transfer stays in the audit log, followed by its reversal.
+    pub async fn revert_billing_customer_transfer(
+        &self,
+        transfer_id: BillingCustomerTransferId,
+        previous_organization_billing_subscription_id: Option<BillingSubscriptionId>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let transfer = billing_customer_transfer::Entity::find_by_id(transfer_id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("billing customer transfer not found"))?;
+
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(transfer.billing_customer_id),
+                user_id: ActiveValue::set(transfer.from_user_id),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+            if let Some(organization_id) = transfer.organization_id {
+                organization::Entity::update(organization::ActiveModel {
+                    id: ActiveValue::unchanged(organization_id),
+                    billing_subscription_id: ActiveValue::set(
+                        previous_organization_billing_subscription_id,
+                    ),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            }
+
+            billing_customer_transfer::Entity::delete_by_id(transfer.id)
+                .exec(&*tx)
+                .await?;
+            let reversal = billing_customer_transfer::Model {
+                from_user_id: transfer.to_user_id,
+                to_user_id: transfer.from_user_id,
+                organization_id: None,
+                ..transfer
+            };
+            self.record_billing_customer_transfer(&reversal, None, &*tx)
+                .await
+        })
+        .await
+    }
+
+    async fn record_billing_customer_transfer(
+        &self,
+        transfer: &billing_customer_transfer::Model,
+        billing_subscription_id: Option<BillingSubscriptionId>,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let state = serde_json::json!({
+            "billing_customer_id": transfer.billing_customer_id,
+            "user_id": transfer.to_user_id,
+            "organization_id": transfer.organization_id,
+        });
+        let previous_state = serde_json::json!({
+            "billing_customer_id": transfer.billing_customer_id,
+            "user_id": transfer.from_user_id,
+        });
+        for user_id in [transfer.from_user_id, transfer.to_user_id] {
+            self.insert_billing_audit_log_entry(
+                &CreateBillingAuditLogEntryParams {
+                    user_id,
+                    actor_user_id: Some(transfer.transferred_by_user_id),
+                    event: BillingAuditEvent::BillingCustomerTransferred,
+                    billing_subscription_id,
+                    previous_state: Some(previous_state.to_string()),
+                    state: Some(state.to_string()),
+                },
+                tx,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the most recent billing customer transfers, newest first.
+    pub async fn get_billing_customer_transfers(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<billing_customer_transfer::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_customer_transfer::Entity::find()
+                .order_by_desc(billing_customer_transfer::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -1,9 +1,13 @@
+use crate::db::billing_customer::TaxExemptStatus;
+
 use super::*;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct CreateBillingCustomerParams {
     pub user_id: UserId,
     pub stripe_customer_id: String,
+    /// The connected reseller account the customer was created on, if any.
+    pub stripe_account_id: Option<String>,
 }
 
 impl Database {
@@ -16,6 +20,7 @@ impl Database {
             let customer = billing_customer::Entity::insert(billing_customer::ActiveModel {
                 user_id: ActiveValue::set(params.user_id),
                 stripe_customer_id: ActiveValue::set(params.stripe_customer_id.clone()),
+                stripe_account_id: ActiveValue::set(params.stripe_account_id.clone()),
                 ..Default::default()
             })
             .exec_with_returning(&*tx)
@@ -26,6 +31,36 @@ impl Database {
         .await
     }
 
+    /// Creates a billing customer for the user, unless they already have one,
+    /// in which case the existing customer is returned instead.
+    pub async fn get_or_create_billing_customer(
+        &self,
+        params: &CreateBillingCustomerParams,
+    ) -> Result<billing_customer::Model> {
+        self.transaction(|tx| async move {
+            billing_customer::Entity::insert(billing_customer::ActiveModel {
+                user_id: ActiveValue::set(params.user_id),
+                stripe_customer_id: ActiveValue::set(params.stripe_customer_id.clone()),
+                stripe_account_id: ActiveValue::set(params.stripe_account_id.clone()),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(billing_customer::Column::UserId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(billing_customer::Entity::find()
+                .filter(billing_customer::Column::UserId.eq(params.user_id))
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("billing customer not found"))?)
+        })
+        .await
+    }
+
     /// Returns the billing customer for the user with the specified ID.
     pub async fn get_billing_customer_by_user_id(
         &self,
@@ -54,3 +89,210 @@ impl Database {
         .await
     }
 }
+
+/// The sensitive details of a billing customer.
+///
+/// These are encrypted at rest and transparently decrypted when read.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BillingCustomerSensitiveDetails {
+    pub billing_address: Option<String>,
+    pub tax_id: Option<String>,
+}
+
+const BILLING_ADDRESS_COLUMN: &str = "billing_customers.billing_address";
+const TAX_ID_COLUMN: &str = "billing_customers.tax_id";
+
+impl Database {
+    /// Updates the sensitive details of the billing customer with the specified ID.
+    pub async fn update_billing_customer_sensitive_details(
+        &self,
+        id: BillingCustomerId,
+        details: &BillingCustomerSensitiveDetails,
+    ) -> Result<()> {
+        let cipher = self.column_cipher()?;
+        let encrypted_billing_address = details
+            .billing_address
+            .as_deref()
+            .map(|billing_address| cipher.encrypt(BILLING_ADDRESS_COLUMN, billing_address))
+            .transpose()?;
+        let encrypted_tax_id = details
+            .tax_id
+            .as_deref()
+            .map(|tax_id| cipher.encrypt(TAX_ID_COLUMN, tax_id))
+            .transpose()?;
+
+        let encrypted_billing_address = &encrypted_billing_address;
+        let encrypted_tax_id = &encrypted_tax_id;
+        self.transaction(|tx| async move {
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                encrypted_billing_address: ActiveValue::set(encrypted_billing_address.clone()),
+                encrypted_tax_id: ActiveValue::set(encrypted_tax_id.clone()),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Updates the tax exemption status of the billing customer with the
+    /// specified ID.
+    pub async fn update_billing_customer_tax_exempt(
+        &self,
+        id: BillingCustomerId,
+        tax_exempt: TaxExemptStatus,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                tax_exempt: ActiveValue::set(tax_exempt),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Updates the address the billing customer with the specified ID is
+    /// billed at. `None` bills the user's own email address.
+    pub async fn update_billing_customer_email(
+        &self,
+        id: BillingCustomerId,
+        billing_email: Option<&str>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                billing_email: ActiveValue::set(billing_email.map(str::to_string)),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records the fingerprint of the card the billing customer with the
+    /// specified ID started their trial with.
+    pub async fn update_billing_customer_payment_method_fingerprint(
+        &self,
+        id: BillingCustomerId,
+        fingerprint: &str,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                payment_method_fingerprint: ActiveValue::set(Some(fingerprint.to_string())),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets the most the billing customer with the specified ID may spend on
+    /// metered usage within a usage period. `None` removes the cap.
+    pub async fn update_billing_customer_spending_cap(
+        &self,
+        id: BillingCustomerId,
+        spending_cap_in_cents: Option<i64>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_customer::Entity::update(billing_customer::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                spending_cap_in_cents: ActiveValue::set(spending_cap_in_cents),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the decrypted sensitive details of the billing customer with the specified ID.
+    pub async fn get_billing_customer_sensitive_details(
+        &self,
+        id: BillingCustomerId,
+    ) -> Result<Option<BillingCustomerSensitiveDetails>> {
+        let Some(customer) = self
+            .transaction(|tx| async move {
+                Ok(billing_customer::Entity::find_by_id(id).one(&*tx).await?)
+            })
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let cipher = self.column_cipher()?;
+        Ok(Some(BillingCustomerSensitiveDetails {
+            billing_address: customer
+                .encrypted_billing_address
+                .as_deref()
+                .map(|value| cipher.decrypt(BILLING_ADDRESS_COLUMN, value))
+                .transpose()?,
+            tax_id: customer
+                .encrypted_tax_id
+                .as_deref()
+                .map(|value| cipher.decrypt(TAX_ID_COLUMN, value))
+                .transpose()?,
+        }))
+    }
+
+    /// Re-encrypts the sensitive details of every billing customer that was
+    /// encrypted with a key other than the current primary key.
+    ///
+    /// Returns the number of billing customers that were re-encrypted.
+    pub async fn reencrypt_billing_customer_sensitive_details(&self) -> Result<usize> {
+        let cipher = self.column_cipher()?;
+
+        let customers = self
+            .transaction(|tx| async move {
+                Ok(billing_customer::Entity::find()
+                    .filter(
+                        billing_customer::Column::EncryptedBillingAddress
+                            .is_not_null()
+                            .or(billing_customer::Column::EncryptedTaxId.is_not_null()),
+                    )
+                    .all(&*tx)
+                    .await?)
+            })
+            .await?;
+
+        let mut reencrypted_count = 0;
+        for customer in customers {
+            let needs_reencryption = [
+                &customer.encrypted_billing_address,
+                &customer.encrypted_tax_id,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|value| cipher.needs_reencryption(value));
+            if !needs_reencryption {
+                continue;
+            }
+
+            let details = self
+                .get_billing_customer_sensitive_details(customer.id)
+                .await?
+                .unwrap_or_default();
+            self.update_billing_customer_sensitive_details(customer.id, &details)
+                .await?;
+            reencrypted_count += 1;
+        }
+
+        Ok(reencrypted_count)
+    }
+}
@@ -0,0 +1,92 @@
+use crate::db::billing_invoice_event::BillingInvoiceEventKind;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateBillingInvoiceEventParams {
+    pub billing_customer_id: BillingCustomerId,
+    pub stripe_invoice_id: String,
+    pub stripe_subscription_id: Option<String>,
+    pub kind: BillingInvoiceEventKind,
+    pub billing_reason: Option<String>,
+    pub amount_in_cents: i64,
+    pub currency: String,
+    pub occurred_at: PrimitiveDateTime,
+    pub usd_exchange_rate: Option<f64>,
+    pub amount_in_usd_cents: Option<i64>,
+}
+
+impl Database {
+    /// Records the outcome of an invoice's payment, unless the same outcome
+    /// was already recorded for the invoice.
+    pub async fn record_billing_invoice_event(
+        &self,
+        params: &CreateBillingInvoiceEventParams,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_invoice_event::Entity::insert(billing_invoice_event::ActiveModel {
+                billing_customer_id: ActiveValue::set(params.billing_customer_id),
+                stripe_invoice_id: ActiveValue::set(params.stripe_invoice_id.clone()),
+                stripe_subscription_id: ActiveValue::set(params.stripe_subscription_id.clone()),
+                kind: ActiveValue::set(params.kind),
+                billing_reason: ActiveValue::set(params.billing_reason.clone()),
+                amount_in_cents: ActiveValue::set(params.amount_in_cents),
+                currency: ActiveValue::set(params.currency.clone()),
+                occurred_at: ActiveValue::set(params.occurred_at),
+                usd_exchange_rate: ActiveValue::set(params.usd_exchange_rate),
+                amount_in_usd_cents: ActiveValue::set(params.amount_in_usd_cents),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    billing_invoice_event::Column::StripeInvoiceId,
+                    billing_invoice_event::Column::Kind,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the invoice events of the user's billing customer, oldest first.
+    pub async fn get_billing_invoice_events(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<billing_invoice_event::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_invoice_event::Entity::find()
+                .inner_join(billing_customer::Entity)
+                .filter(billing_customer::Column::UserId.eq(user_id))
+                .order_by_asc(billing_invoice_event::Column::OccurredAt)
+                .order_by_asc(billing_invoice_event::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the events of invoices paid at or after `since`, oldest first.
+    pub async fn get_paid_billing_invoice_events(
+        &self,
+        since: PrimitiveDateTime,
+    ) -> Result<Vec<billing_invoice_event::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_invoice_event::Entity::find()
+                .filter(
+                    billing_invoice_event::Column::Kind
+                        .eq(BillingInvoiceEventKind::Paid)
+                        .and(billing_invoice_event::Column::OccurredAt.gte(since)),
+                )
+                .order_by_asc(billing_invoice_event::Column::OccurredAt)
+                .order_by_asc(billing_invoice_event::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,66 @@
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateBillingInvoiceLineItemParams {
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub stripe_invoice_id: String,
+    pub stripe_invoice_line_item_id: String,
+    pub period_start: PrimitiveDateTime,
+    pub period_end: PrimitiveDateTime,
+    pub quantity: i64,
+    pub description: String,
+}
+
+impl Database {
+    /// Records the description given to a metered line item on a Stripe
+    /// invoice, replacing any recorded for the same line item before.
+    pub async fn record_billing_invoice_line_item(
+        &self,
+        params: &CreateBillingInvoiceLineItemParams,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_invoice_line_item::Entity::insert(billing_invoice_line_item::ActiveModel {
+                billing_subscription_id: ActiveValue::set(params.billing_subscription_id),
+                stripe_invoice_id: ActiveValue::set(params.stripe_invoice_id.clone()),
+                stripe_invoice_line_item_id: ActiveValue::set(
+                    params.stripe_invoice_line_item_id.clone(),
+                ),
+                period_start: ActiveValue::set(params.period_start),
+                period_end: ActiveValue::set(params.period_end),
+                quantity: ActiveValue::set(params.quantity),
+                description: ActiveValue::set(params.description.clone()),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(billing_invoice_line_item::Column::StripeInvoiceLineItemId)
+                    .update_columns([
+                        billing_invoice_line_item::Column::PeriodStart,
+                        billing_invoice_line_item::Column::PeriodEnd,
+                        billing_invoice_line_item::Column::Quantity,
+                        billing_invoice_line_item::Column::Description,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the metered line items recorded for the given Stripe invoice.
+    pub async fn get_billing_invoice_line_items(
+        &self,
+        stripe_invoice_id: &str,
+    ) -> Result<Vec<billing_invoice_line_item::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_invoice_line_item::Entity::find()
+                .filter(billing_invoice_line_item::Column::StripeInvoiceId.eq(stripe_invoice_id))
+                .order_by_asc(billing_invoice_line_item::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,41 @@
+use super::*;
+
+impl Database {
+    /// Queues the email of a billing notification, unless the notification
+    /// with the same key was already sent to the user.
+    ///
+    /// Returns the queued email, if any.
+    pub async fn enqueue_billing_notification(
+        &self,
+        user_id: UserId,
+        key: &str,
+        email: &CreateEmailDeliveryParams,
+    ) -> Result<Option<email_delivery::Model>> {
+        self.transaction(|tx| async move {
+            let existing_notification = billing_notification::Entity::find()
+                .filter(
+                    billing_notification::Column::UserId
+                        .eq(user_id)
+                        .and(billing_notification::Column::Key.eq(key)),
+                )
+                .one(&*tx)
+                .await?;
+            if existing_notification.is_some() {
+                return Ok(None);
+            }
+
+            let delivery = self.insert_email_delivery(email, &*tx).await?;
+            billing_notification::Entity::insert(billing_notification::ActiveModel {
+                user_id: ActiveValue::set(user_id),
+                key: ActiveValue::set(key.to_string()),
+                email_delivery_id: ActiveValue::set(delivery.id),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(Some(delivery))
+        })
+        .await
+    }
+}
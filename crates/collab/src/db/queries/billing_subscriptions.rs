@@ -1,4 +1,9 @@
-use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::column_renames::BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END;
+use crate::db::CreateBillingAuditLogEntryParams;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
 
 use super::*;
 
@@ -7,6 +12,64 @@ pub struct CreateBillingSubscriptionParams {
     pub billing_customer_id: BillingCustomerId,
     pub stripe_subscription_id: String,
     pub stripe_subscription_status: StripeSubscriptionStatus,
+    pub plan: SubscriptionPlan,
+    pub seat_count: i32,
+    pub stripe_coupon_id: Option<String>,
+    pub stripe_promotion_code_id: Option<String>,
+    pub trial_end: Option<PrimitiveDateTime>,
+    pub current_period_start: Option<PrimitiveDateTime>,
+    pub current_period_end: Option<PrimitiveDateTime>,
+    pub cancel_at_period_end: bool,
+    pub canceled_at: Option<PrimitiveDateTime>,
+    /// The connected reseller account the subscription is on, if any.
+    pub stripe_account_id: Option<String>,
+}
+
+impl Default for CreateBillingSubscriptionParams {
+    fn default() -> Self {
+        Self {
+            billing_customer_id: BillingCustomerId::default(),
+            stripe_subscription_id: String::new(),
+            stripe_subscription_status: StripeSubscriptionStatus::default(),
+            plan: SubscriptionPlan::default(),
+            seat_count: 1,
+            stripe_coupon_id: None,
+            stripe_promotion_code_id: None,
+            trial_end: None,
+            current_period_start: None,
+            current_period_end: None,
+            cancel_at_period_end: false,
+            canceled_at: None,
+            stripe_account_id: None,
+        }
+    }
+}
+
+/// How [`Database::upsert_billing_subscription_by_stripe_subscription_id`]
+/// treats the changes it makes.
+#[derive(Debug)]
+pub struct UpsertBillingSubscriptionOptions {
+    /// The grace period a subscription that becomes past due is given, which
+    /// ends early once it's no longer past due.
+    pub past_due_grace_period: Duration,
+    /// The user who changed the subscription, if it wasn't the payment
+    /// provider, as recorded in the billing audit log.
+    pub actor_user_id: Option<UserId>,
+    /// When the payment provider says the subscription changed, if known.
+    /// Otherwise, the change is recorded as happening now.
+    pub transitioned_at: Option<PrimitiveDateTime>,
+}
+
+/// The state of a billing subscription at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BillingSubscriptionSnapshot {
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub stripe_subscription_id: String,
+    pub stripe_subscription_status: StripeSubscriptionStatus,
+    /// The plan the subscription was for, if it was recorded at the time.
+    pub plan: Option<SubscriptionPlan>,
+    /// When the subscription entered this status or plan.
+    pub transitioned_at: PrimitiveDateTime,
 }
 
 impl Database {
@@ -16,41 +79,159 @@ impl Database {
         params: &CreateBillingSubscriptionParams,
     ) -> Result<()> {
         self.transaction(|tx| async move {
-            billing_subscription::Entity::insert(billing_subscription::ActiveModel {
-                billing_customer_id: ActiveValue::set(params.billing_customer_id),
-                stripe_subscription_id: ActiveValue::set(params.stripe_subscription_id.clone()),
-                stripe_subscription_status: ActiveValue::set(params.stripe_subscription_status),
-                ..Default::default()
-            })
-            .exec_without_returning(&*tx)
-            .await?;
+            let subscription =
+                billing_subscription::Entity::insert(billing_subscription::ActiveModel {
+                    billing_customer_id: ActiveValue::set(params.billing_customer_id),
+                    stripe_subscription_id: ActiveValue::set(params.stripe_subscription_id.clone()),
+                    stripe_subscription_status: ActiveValue::set(params.stripe_subscription_status),
+                    plan: ActiveValue::set(params.plan),
+                    seat_count: ActiveValue::set(params.seat_count),
+                    stripe_coupon_id: ActiveValue::set(params.stripe_coupon_id.clone()),
+                    stripe_promotion_code_id: ActiveValue::set(
+                        params.stripe_promotion_code_id.clone(),
+                    ),
+                    trial_end: ActiveValue::set(params.trial_end),
+                    current_period_start: ActiveValue::set(params.current_period_start),
+                    current_period_end: ActiveValue::set(params.current_period_end),
+                    current_period_ends_at: ActiveValue::set(params.current_period_end),
+                    cancel_at_period_end: ActiveValue::set(params.cancel_at_period_end),
+                    canceled_at: ActiveValue::set(params.canceled_at),
+                    stripe_account_id: ActiveValue::set(params.stripe_account_id.clone()),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?;
+
+            self.record_billing_subscription_transition(&subscription, None, &*tx)
+                .await?;
 
             Ok(())
         })
         .await
     }
 
-    /// Upserts the billing subscription by its Stripe subscription ID.
+    /// Upserts the billing subscription by its Stripe subscription ID,
+    /// recording a transition whenever its status or plan changes, and an
+    /// entry in the billing audit log whenever anything about it changes.
+    ///
+    /// Returns whether the status of the subscription changed.
     pub async fn upsert_billing_subscription_by_stripe_subscription_id(
         &self,
         params: &CreateBillingSubscriptionParams,
-    ) -> Result<()> {
+        options: &UpsertBillingSubscriptionOptions,
+    ) -> Result<bool> {
         self.transaction(|tx| async move {
-            billing_subscription::Entity::insert(billing_subscription::ActiveModel {
-                billing_customer_id: ActiveValue::set(params.billing_customer_id),
-                stripe_subscription_id: ActiveValue::set(params.stripe_subscription_id.clone()),
-                stripe_subscription_status: ActiveValue::set(params.stripe_subscription_status),
-                ..Default::default()
-            })
-            .on_conflict(
-                OnConflict::columns([billing_subscription::Column::StripeSubscriptionId])
-                    .update_columns([billing_subscription::Column::StripeSubscriptionStatus])
-                    .to_owned(),
-            )
-            .exec_with_returning(&*tx)
-            .await?;
+            let previous_subscription = billing_subscription::Entity::find()
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionId
+                        .eq(params.stripe_subscription_id.clone()),
+                )
+                .one(&*tx)
+                .await?;
+            let previous_status = previous_subscription
+                .as_ref()
+                .map(|subscription| subscription.stripe_subscription_status);
+            let previous_plan = previous_subscription
+                .as_ref()
+                .map(|subscription| subscription.plan);
+            let previous_state = previous_subscription
+                .as_ref()
+                .map(billing_subscription_audit_state);
 
-            Ok(())
+            let grace_period_ends_at =
+                if params.stripe_subscription_status != StripeSubscriptionStatus::PastDue {
+                    None
+                } else if previous_status == Some(StripeSubscriptionStatus::PastDue) {
+                    previous_subscription
+                        .as_ref()
+                        .and_then(|subscription| subscription.grace_period_ends_at)
+                } else {
+                    let ends_at = OffsetDateTime::now_utc() + options.past_due_grace_period;
+                    Some(PrimitiveDateTime::new(ends_at.date(), ends_at.time()))
+                };
+
+            let subscription =
+                billing_subscription::Entity::insert(billing_subscription::ActiveModel {
+                    billing_customer_id: ActiveValue::set(params.billing_customer_id),
+                    stripe_subscription_id: ActiveValue::set(params.stripe_subscription_id.clone()),
+                    stripe_subscription_status: ActiveValue::set(params.stripe_subscription_status),
+                    plan: ActiveValue::set(params.plan),
+                    seat_count: ActiveValue::set(params.seat_count),
+                    stripe_coupon_id: ActiveValue::set(params.stripe_coupon_id.clone()),
+                    stripe_promotion_code_id: ActiveValue::set(
+                        params.stripe_promotion_code_id.clone(),
+                    ),
+                    trial_end: ActiveValue::set(params.trial_end),
+                    current_period_start: ActiveValue::set(params.current_period_start),
+                    current_period_end: ActiveValue::set(params.current_period_end),
+                    current_period_ends_at: ActiveValue::set(params.current_period_end),
+                    cancel_at_period_end: ActiveValue::set(params.cancel_at_period_end),
+                    canceled_at: ActiveValue::set(params.canceled_at),
+                    stripe_account_id: ActiveValue::set(params.stripe_account_id.clone()),
+                    grace_period_ends_at: ActiveValue::set(grace_period_ends_at),
+                    ..Default::default()
+                })
+                .on_conflict(
+                    OnConflict::columns([billing_subscription::Column::StripeSubscriptionId])
+                        .update_columns([
+                            billing_subscription::Column::StripeSubscriptionStatus,
+                            billing_subscription::Column::Plan,
+                            billing_subscription::Column::SeatCount,
+                            billing_subscription::Column::StripeCouponId,
+                            billing_subscription::Column::StripePromotionCodeId,
+                            billing_subscription::Column::TrialEnd,
+                            billing_subscription::Column::CurrentPeriodStart,
+                            billing_subscription::Column::CurrentPeriodEnd,
+                            billing_subscription::Column::CurrentPeriodEndsAt,
+                            billing_subscription::Column::CancelAtPeriodEnd,
+                            billing_subscription::Column::CanceledAt,
+                            billing_subscription::Column::GracePeriodEndsAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec_with_returning(&*tx)
+                .await?;
+
+            let status_changed = previous_status != Some(subscription.stripe_subscription_status);
+            let plan_changed = previous_plan != Some(subscription.plan);
+            if status_changed || plan_changed {
+                self.record_billing_subscription_transition(
+                    &subscription,
+                    options.transitioned_at,
+                    &*tx,
+                )
+                .await?;
+            }
+
+            let state = billing_subscription_audit_state(&subscription);
+            if previous_state.as_ref() != Some(&state) {
+                let billing_customer =
+                    billing_customer::Entity::find_by_id(subscription.billing_customer_id)
+                        .one(&*tx)
+                        .await?
+                        .ok_or_else(|| anyhow!("billing customer not found"))?;
+                let event = if previous_subscription.is_none() {
+                    BillingAuditEvent::SubscriptionCreated
+                } else if status_changed {
+                    BillingAuditEvent::SubscriptionStatusChanged
+                } else {
+                    BillingAuditEvent::SubscriptionUpdated
+                };
+                self.insert_billing_audit_log_entry(
+                    &CreateBillingAuditLogEntryParams {
+                        user_id: billing_customer.user_id,
+                        actor_user_id: options.actor_user_id,
+                        event,
+                        billing_subscription_id: Some(subscription.id),
+                        previous_state,
+                        state: Some(state),
+                    },
+                    &*tx,
+                )
+                .await?;
+            }
+
+            Ok(status_changed)
         })
         .await
     }
@@ -63,7 +244,70 @@ impl Database {
         self.transaction(|tx| async move {
             Ok(billing_subscription::Entity::find_by_id(id)
                 .one(&*tx)
-                .await?)
+                .await?
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription)))
+        })
+        .await
+    }
+
+    /// Returns the billing subscription for the given Stripe subscription.
+    pub async fn get_billing_subscription_by_stripe_subscription_id(
+        &self,
+        stripe_subscription_id: &str,
+    ) -> Result<Option<billing_subscription::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_subscription::Entity::find()
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionId.eq(stripe_subscription_id),
+                )
+                .one(&*tx)
+                .await?
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription)))
+        })
+        .await
+    }
+
+    /// Returns every billing subscription that hasn't ended, meaning it's
+    /// neither canceled nor expired before it was ever paid for.
+    pub async fn get_unended_billing_subscriptions(
+        &self,
+    ) -> Result<Vec<billing_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionStatus.is_not_in([
+                        StripeSubscriptionStatus::Canceled,
+                        StripeSubscriptionStatus::IncompleteExpired,
+                    ]),
+                )
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            Ok(subscriptions
+                .into_iter()
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription))
+                .collect())
+        })
+        .await
+    }
+
+    /// Updates the number of seats paid for by the given billing subscription.
+    pub async fn update_billing_subscription_seat_count(
+        &self,
+        id: BillingSubscriptionId,
+        seat_count: i32,
+    ) -> Result<billing_subscription::Model> {
+        self.transaction(|tx| async move {
+            let subscription =
+                billing_subscription::Entity::update(billing_subscription::ActiveModel {
+                    id: ActiveValue::unchanged(id),
+                    seat_count: ActiveValue::set(seat_count),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            Ok(self.read_renamed_billing_subscription_columns(subscription))
         })
         .await
     }
@@ -85,12 +329,18 @@ impl Database {
                 .all(&*tx)
                 .await?;
 
-            Ok(subscriptions)
+            Ok(subscriptions
+                .into_iter()
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription))
+                .collect())
         })
         .await
     }
 
     /// Returns all of the active billing subscriptions for the user with the specified ID.
+    ///
+    /// Trialing subscriptions count as active, and past-due ones until their
+    /// grace period ends.
     pub async fn get_active_billing_subscriptions(
         &self,
         user_id: UserId,
@@ -98,18 +348,398 @@ impl Database {
         self.transaction(|tx| async move {
             let subscriptions = billing_subscription::Entity::find()
                 .inner_join(billing_customer::Entity)
+                .filter(billing_customer::Column::UserId.eq(user_id))
+                .filter(active_billing_subscription_condition(
+                    OffsetDateTime::now_utc(),
+                ))
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            Ok(subscriptions
+                .into_iter()
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription))
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the state of each of the user's billing subscriptions as it was at
+    /// the given point in time.
+    ///
+    /// Subscriptions that did not exist yet at that time are omitted.
+    pub async fn get_billing_subscriptions_at(
+        &self,
+        user_id: UserId,
+        at: PrimitiveDateTime,
+    ) -> Result<Vec<BillingSubscriptionSnapshot>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .inner_join(billing_customer::Entity)
+                .filter(billing_customer::Column::UserId.eq(user_id))
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            let transitions = billing_subscription_transition::Entity::find()
+                .filter(
+                    billing_subscription_transition::Column::BillingSubscriptionId
+                        .is_in(subscriptions.iter().map(|subscription| subscription.id))
+                        .and(billing_subscription_transition::Column::TransitionedAt.lte(at)),
+                )
+                .order_by_asc(billing_subscription_transition::Column::TransitionedAt)
+                .order_by_asc(billing_subscription_transition::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            let mut latest_transitions = HashMap::default();
+            for transition in transitions {
+                latest_transitions.insert(transition.billing_subscription_id, transition);
+            }
+
+            Ok(subscriptions
+                .into_iter()
+                .filter_map(|subscription| {
+                    let transition = latest_transitions.remove(&subscription.id)?;
+                    Some(BillingSubscriptionSnapshot {
+                        billing_subscription_id: subscription.id,
+                        stripe_subscription_id: subscription.stripe_subscription_id,
+                        stripe_subscription_status: transition.stripe_subscription_status,
+                        plan: transition.plan,
+                        transitioned_at: transition.transitioned_at,
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the full transition history of the given billing subscription,
+    /// oldest first.
+    pub async fn get_billing_subscription_transitions(
+        &self,
+        billing_subscription_id: BillingSubscriptionId,
+    ) -> Result<Vec<billing_subscription_transition::Model>> {
+        self.transaction(|tx| async move {
+            Ok(billing_subscription_transition::Entity::find()
+                .filter(
+                    billing_subscription_transition::Column::BillingSubscriptionId
+                        .eq(billing_subscription_id),
+                )
+                .order_by_asc(billing_subscription_transition::Column::TransitionedAt)
+                .order_by_asc(billing_subscription_transition::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Ends the grace periods of past-due subscriptions that are over as of
+    /// the given time.
+    ///
+    /// Returns the IDs of the users whose subscriptions stopped granting
+    /// access.
+    pub async fn end_expired_billing_subscription_grace_periods(
+        &self,
+        now: PrimitiveDateTime,
+    ) -> Result<Vec<UserId>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .find_also_related(billing_customer::Entity)
+                .filter(billing_subscription::Column::GracePeriodEndsAt.lte(now))
+                .all(&*tx)
+                .await?;
+
+            billing_subscription::Entity::update_many()
+                .set(billing_subscription::ActiveModel {
+                    grace_period_ends_at: ActiveValue::set(None),
+                    ..Default::default()
+                })
                 .filter(
-                    billing_customer::Column::UserId.eq(user_id).and(
-                        billing_subscription::Column::StripeSubscriptionStatus
-                            .eq(StripeSubscriptionStatus::Active),
+                    billing_subscription::Column::Id.is_in(
+                        subscriptions
+                            .iter()
+                            .map(|(subscription, _)| subscription.id),
                     ),
                 )
+                .exec(&*tx)
+                .await?;
+
+            let mut user_ids = subscriptions
+                .into_iter()
+                .filter_map(|(_, customer)| Some(customer?.user_id))
+                .collect::<Vec<_>>();
+            user_ids.sort();
+            user_ids.dedup();
+            Ok(user_ids)
+        })
+        .await
+    }
+
+    /// Returns the unpaid subscriptions that haven't been suspended, and
+    /// whose next attempt at collecting payment is due as of the given time,
+    /// along with their customers.
+    pub async fn get_billing_subscriptions_due_for_dunning(
+        &self,
+        now: PrimitiveDateTime,
+    ) -> Result<Vec<(billing_subscription::Model, billing_customer::Model)>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .find_also_related(billing_customer::Entity)
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionStatus
+                        .eq(StripeSubscriptionStatus::Unpaid)
+                        .and(billing_subscription::Column::SuspendedAt.is_null())
+                        .and(
+                            billing_subscription::Column::NextDunningAttemptAt
+                                .is_null()
+                                .or(billing_subscription::Column::NextDunningAttemptAt.lte(now)),
+                        ),
+                )
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            Ok(subscriptions
+                .into_iter()
+                .filter_map(|(subscription, customer)| {
+                    Some((
+                        self.read_renamed_billing_subscription_columns(subscription),
+                        customer?,
+                    ))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Records a failed attempt at collecting payment for an unpaid
+    /// subscription, scheduling the next one for the given time.
+    pub async fn record_billing_subscription_dunning_attempt(
+        &self,
+        id: BillingSubscriptionId,
+        next_attempt_at: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            billing_subscription::Entity::update_many()
+                .set(billing_subscription::ActiveModel {
+                    next_dunning_attempt_at: ActiveValue::set(Some(next_attempt_at)),
+                    ..Default::default()
+                })
+                .col_expr(
+                    billing_subscription::Column::DunningAttemptCount,
+                    Expr::col(billing_subscription::Column::DunningAttemptCount).add(1),
+                )
+                .filter(billing_subscription::Column::Id.eq(id))
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Suspends an unpaid subscription, so that it stops granting access.
+    ///
+    /// Returns the suspended subscription, or `None` if it's no longer unpaid
+    /// or was already suspended.
+    pub async fn suspend_billing_subscription(
+        &self,
+        id: BillingSubscriptionId,
+        now: PrimitiveDateTime,
+    ) -> Result<Option<billing_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let result = billing_subscription::Entity::update_many()
+                .set(billing_subscription::ActiveModel {
+                    next_dunning_attempt_at: ActiveValue::set(None),
+                    suspended_at: ActiveValue::set(Some(now)),
+                    ..Default::default()
+                })
+                .filter(
+                    billing_subscription::Column::Id
+                        .eq(id)
+                        .and(
+                            billing_subscription::Column::StripeSubscriptionStatus
+                                .eq(StripeSubscriptionStatus::Unpaid),
+                        )
+                        .and(billing_subscription::Column::SuspendedAt.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                return Ok(None);
+            }
+
+            Ok(billing_subscription::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription)))
+        })
+        .await
+    }
+
+    /// Returns the subscriptions that are no longer unpaid, but are still
+    /// suspended or have attempts at collecting payment recorded.
+    pub async fn get_billing_subscriptions_to_reinstate(
+        &self,
+    ) -> Result<Vec<billing_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .filter(needs_reinstatement())
                 .order_by_asc(billing_subscription::Column::Id)
                 .all(&*tx)
                 .await?;
 
-            Ok(subscriptions)
+            Ok(subscriptions
+                .into_iter()
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription))
+                .collect())
+        })
+        .await
+    }
+
+    /// Clears the suspension and attempts at collecting payment of the given
+    /// subscription, once it's no longer unpaid.
+    ///
+    /// Returns the subscription as it was beforehand, along with its
+    /// customer, or `None` if there was nothing to clear.
+    pub async fn reinstate_billing_subscription(
+        &self,
+        stripe_subscription_id: &str,
+    ) -> Result<Option<(billing_subscription::Model, billing_customer::Model)>> {
+        self.transaction(|tx| async move {
+            let Some((subscription, Some(customer))) = billing_subscription::Entity::find()
+                .find_also_related(billing_customer::Entity)
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionId
+                        .eq(stripe_subscription_id)
+                        .and(needs_reinstatement()),
+                )
+                .one(&*tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+
+            billing_subscription::Entity::update_many()
+                .set(billing_subscription::ActiveModel {
+                    dunning_attempt_count: ActiveValue::set(0),
+                    next_dunning_attempt_at: ActiveValue::set(None),
+                    suspended_at: ActiveValue::set(None),
+                    ..Default::default()
+                })
+                .filter(billing_subscription::Column::Id.eq(subscription.id))
+                .exec(&*tx)
+                .await?;
+
+            Ok(Some((
+                self.read_renamed_billing_subscription_columns(subscription),
+                customer,
+            )))
         })
         .await
     }
+
+    /// Fills in the columns of a billing subscription that are being renamed
+    /// from whichever of their old and new columns reads currently use.
+    pub(crate) fn read_renamed_billing_subscription_columns(
+        &self,
+        mut subscription: billing_subscription::Model,
+    ) -> billing_subscription::Model {
+        let rename = BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END;
+        subscription.current_period_end = rename.read(
+            self.reads_renamed_column(&rename),
+            subscription.current_period_end,
+            subscription.current_period_ends_at,
+        );
+        subscription
+    }
+
+    async fn record_billing_subscription_transition(
+        &self,
+        subscription: &billing_subscription::Model,
+        transitioned_at: Option<PrimitiveDateTime>,
+        tx: &DatabaseTransaction,
+    ) -> Result<()> {
+        let transitioned_at = transitioned_at.unwrap_or_else(|| {
+            let now = OffsetDateTime::now_utc();
+            PrimitiveDateTime::new(now.date(), now.time())
+        });
+        billing_subscription_transition::Entity::insert(
+            billing_subscription_transition::ActiveModel {
+                billing_subscription_id: ActiveValue::set(subscription.id),
+                stripe_subscription_status: ActiveValue::set(
+                    subscription.stripe_subscription_status,
+                ),
+                plan: ActiveValue::set(Some(subscription.plan)),
+                transitioned_at: ActiveValue::set(transitioned_at),
+                ..Default::default()
+            },
+        )
+        .exec_without_returning(tx)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the state of the subscription that's recorded in the billing audit
+/// log, as JSON.
+fn billing_subscription_audit_state(subscription: &billing_subscription::Model) -> String {
+    let timestamp = |timestamp: Option<PrimitiveDateTime>| {
+        timestamp.and_then(|timestamp| timestamp.assume_utc().format(&Rfc3339).ok())
+    };
+    serde_json::json!({
+        "stripe_subscription_id": subscription.stripe_subscription_id,
+        "status": subscription.stripe_subscription_status,
+        "plan": subscription.plan,
+        "seat_count": subscription.seat_count,
+        "cancel_at_period_end": subscription.cancel_at_period_end,
+        "canceled_at": timestamp(subscription.canceled_at),
+        "trial_end": timestamp(subscription.trial_end),
+        "current_period_end": timestamp(subscription.current_period_end),
+    })
+    .to_string()
+}
+
+/// Matches subscriptions that are no longer unpaid, but whose suspension or
+/// attempts at collecting payment haven't been cleared.
+fn needs_reinstatement() -> Condition {
+    Condition::all()
+        .add(
+            billing_subscription::Column::StripeSubscriptionStatus
+                .ne(StripeSubscriptionStatus::Unpaid),
+        )
+        .add(
+            Condition::any()
+                .add(billing_subscription::Column::SuspendedAt.is_not_null())
+                .add(billing_subscription::Column::DunningAttemptCount.gt(0)),
+        )
+}
+
+/// Returns the condition that billing subscriptions granting access at the
+/// given time meet. Trialing subscriptions count as active, past-due ones
+/// until their grace period ends, and unpaid ones until they're suspended.
+pub(super) fn active_billing_subscription_condition(now: OffsetDateTime) -> Condition {
+    Condition::any()
+        .add(
+            billing_subscription::Column::StripeSubscriptionStatus
+                .eq(StripeSubscriptionStatus::Active),
+        )
+        .add(
+            billing_subscription::Column::StripeSubscriptionStatus
+                .eq(StripeSubscriptionStatus::Trialing),
+        )
+        .add(
+            billing_subscription::Column::StripeSubscriptionStatus
+                .eq(StripeSubscriptionStatus::PastDue)
+                .and(
+                    billing_subscription::Column::GracePeriodEndsAt
+                        .gt(PrimitiveDateTime::new(now.date(), now.time())),
+                ),
+        )
+        .add(
+            billing_subscription::Column::StripeSubscriptionStatus
+                .eq(StripeSubscriptionStatus::Unpaid)
+                .and(billing_subscription::Column::SuspendedAt.is_null()),
+        )
 }
@@ -4,6 +4,7 @@ use rpc::{
     ErrorCode, ErrorCodeExt,
 };
 use sea_orm::{DbBackend, TryGetableMany};
+use time::OffsetDateTime;
 
 impl Database {
     #[cfg(test)]
@@ -68,6 +69,7 @@ impl Database {
                         .map_or(String::new(), |parent| parent.path()),
                 ),
                 requires_zed_cla: ActiveValue::NotSet,
+                archived_at: ActiveValue::NotSet,
             }
             .insert(&*tx)
             .await?;
@@ -100,6 +102,14 @@ impl Database {
     ) -> Result<(JoinRoom, Option<MembershipUpdated>, ChannelRole)> {
         self.transaction(move |tx| async move {
             let channel = self.get_channel_internal(channel_id, &tx).await?;
+            let root_channel = if channel.is_root() {
+                channel.clone()
+            } else {
+                self.get_channel_internal(channel.root_id(), &tx).await?
+            };
+            if root_channel.archived_at.is_some() {
+                Err(anyhow!("channel is archived"))?;
+            }
             let mut role = self.channel_role_for_user(&channel, user_id, &tx).await?;
 
             let mut accept_invite_result = None;
@@ -517,6 +527,9 @@ impl Database {
             .await?;
         while let Some(row) = rows.next().await {
             if let (membership, Some(channel)) = row? {
+                if channel.archived_at.is_some() {
+                    continue;
+                }
                 if membership.accepted {
                     channel_memberships.push(membership);
                     channels.push(channel);
@@ -992,6 +1005,138 @@ impl Database {
         })
         .await
     }
+
+    /// Returns the number of root channels the user is an admin of.
+    pub async fn get_administered_root_channel_count(&self, user_id: UserId) -> Result<u64> {
+        self.transaction(|tx| async move {
+            Ok(channel_member::Entity::find()
+                .inner_join(channel::Entity)
+                .filter(
+                    channel_member::Column::UserId
+                        .eq(user_id)
+                        .and(channel_member::Column::Role.eq(ChannelRole::Admin))
+                        .and(channel_member::Column::Accepted.eq(true))
+                        .and(channel::Column::ParentPath.eq(""))
+                        .and(channel::Column::ArchivedAt.is_null()),
+                )
+                .count(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Archives the private root channels the user administers beyond the
+    /// given limit, newest first, so that they're left with the oldest ones.
+    ///
+    /// Returns the IDs of the channels that were archived.
+    pub async fn archive_excess_root_channels(
+        &self,
+        user_id: UserId,
+        limit: u64,
+    ) -> Result<Vec<ChannelId>> {
+        self.transaction(|tx| async move {
+            let channels = self
+                .get_administered_root_channels(user_id, &tx)
+                .await?
+                .into_iter()
+                .filter(|channel| {
+                    channel.archived_at.is_none()
+                        && channel.visibility == ChannelVisibility::Members
+                })
+                .collect::<Vec<_>>();
+            let excess_count = channels.len().saturating_sub(limit as usize);
+            let channel_ids_to_archive = channels
+                .iter()
+                .rev()
+                .take(excess_count)
+                .map(|channel| channel.id)
+                .collect::<Vec<_>>();
+            if channel_ids_to_archive.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let now = OffsetDateTime::now_utc();
+            channel::Entity::update_many()
+                .col_expr(
+                    channel::Column::ArchivedAt,
+                    Expr::value(PrimitiveDateTime::new(now.date(), now.time())),
+                )
+                .filter(channel::Column::Id.is_in(channel_ids_to_archive.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            Ok(channel_ids_to_archive)
+        })
+        .await
+    }
+
+    /// Returns the number of private root channels the user administers,
+    /// including archived ones.
+    pub async fn get_administered_private_root_channel_count_including_archived(
+        &self,
+        user_id: UserId,
+    ) -> Result<u64> {
+        self.transaction(|tx| async move {
+            Ok(self
+                .get_administered_root_channels(user_id, &tx)
+                .await?
+                .iter()
+                .filter(|channel| channel.visibility == ChannelVisibility::Members)
+                .count() as u64)
+        })
+        .await
+    }
+
+    /// Restores the archived root channels the user administers.
+    ///
+    /// Returns the IDs of the channels that were restored.
+    pub async fn unarchive_administered_root_channels(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<ChannelId>> {
+        self.transaction(|tx| async move {
+            let channel_ids = self
+                .get_administered_root_channels(user_id, &tx)
+                .await?
+                .into_iter()
+                .filter(|channel| channel.archived_at.is_some())
+                .map(|channel| channel.id)
+                .collect::<Vec<_>>();
+            if channel_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            channel::Entity::update_many()
+                .col_expr(
+                    channel::Column::ArchivedAt,
+                    Expr::value(Option::<PrimitiveDateTime>::None),
+                )
+                .filter(channel::Column::Id.is_in(channel_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            Ok(channel_ids)
+        })
+        .await
+    }
+
+    /// Returns the root channels the user administers, oldest first.
+    async fn get_administered_root_channels(
+        &self,
+        user_id: UserId,
+        tx: &DatabaseTransaction,
+    ) -> Result<Vec<channel::Model>> {
+        Ok(channel::Entity::find()
+            .inner_join(channel_member::Entity)
+            .filter(
+                channel_member::Column::UserId
+                    .eq(user_id)
+                    .and(channel_member::Column::Role.eq(ChannelRole::Admin))
+                    .and(channel_member::Column::Accepted.eq(true))
+                    .and(channel::Column::ParentPath.eq("")),
+            )
+            .order_by_asc(channel::Column::Id)
+            .all(tx)
+            .await?)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
@@ -0,0 +1,85 @@
+use super::*;
+use crate::db::column_renames::ColumnRename;
+use sea_orm::DbBackend;
+
+impl Database {
+    /// Copies the old column of a rename into the new one for up to
+    /// `batch_size` rows that were written before the dual-write began.
+    ///
+    /// Returns the number of rows that were backfilled, which is less than
+    /// `batch_size` once the backfill is complete.
+    pub async fn backfill_renamed_column(
+        &self,
+        rename: &ColumnRename,
+        batch_size: u64,
+    ) -> Result<u64> {
+        self.transaction(|tx| async move {
+            let ColumnRename {
+                table,
+                old_column,
+                new_column,
+                ..
+            } = rename;
+            let sql = format!(
+                r#"
+                UPDATE {table}
+                SET {new_column} = {old_column}
+                WHERE id IN (
+                    SELECT id FROM {table}
+                    WHERE {new_column} IS NULL AND {old_column} IS NOT NULL
+                    ORDER BY id
+                    LIMIT {batch_size}
+                )
+                "#
+            );
+            let result = tx
+                .execute(Statement::from_string(
+                    self.pool.get_database_backend(),
+                    sql,
+                ))
+                .await?;
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+
+    /// Returns the IDs of up to `limit` rows whose old and new columns differ.
+    pub async fn find_renamed_column_mismatches(
+        &self,
+        rename: &ColumnRename,
+        limit: u64,
+    ) -> Result<Vec<i32>> {
+        self.transaction(|tx| async move {
+            let ColumnRename {
+                table,
+                old_column,
+                new_column,
+                ..
+            } = rename;
+            let is_distinct_from = if self.pool.get_database_backend() == DbBackend::Sqlite {
+                "IS NOT"
+            } else {
+                "IS DISTINCT FROM"
+            };
+            let sql = format!(
+                r#"
+                SELECT id FROM {table}
+                WHERE {new_column} {is_distinct_from} {old_column}
+                ORDER BY id
+                LIMIT {limit}
+                "#
+            );
+            let rows = tx
+                .query_all(Statement::from_string(
+                    self.pool.get_database_backend(),
+                    sql,
+                ))
+                .await?;
+            Ok(rows
+                .iter()
+                .map(|row| row.try_get("", "id"))
+                .collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,119 @@
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::complimentary_subscription::ComplimentarySubscriptionKind;
+use time::OffsetDateTime;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateComplimentarySubscriptionParams {
+    pub user_id: UserId,
+    pub plan: SubscriptionPlan,
+    pub kind: ComplimentarySubscriptionKind,
+    pub reason: Option<String>,
+    pub granted_by_user_id: UserId,
+    pub expires_at: Option<PrimitiveDateTime>,
+}
+
+impl Database {
+    /// Grants a user a complimentary subscription.
+    pub async fn create_complimentary_subscription(
+        &self,
+        params: &CreateComplimentarySubscriptionParams,
+    ) -> Result<complimentary_subscription::Model> {
+        self.transaction(|tx| async move {
+            Ok(complimentary_subscription::Entity::insert(
+                complimentary_subscription::ActiveModel {
+                    user_id: ActiveValue::set(params.user_id),
+                    plan: ActiveValue::set(params.plan),
+                    kind: ActiveValue::set(params.kind),
+                    reason: ActiveValue::set(params.reason.clone()),
+                    granted_by_user_id: ActiveValue::set(params.granted_by_user_id),
+                    expires_at: ActiveValue::set(params.expires_at),
+                    ..Default::default()
+                },
+            )
+            .exec_with_returning(&*tx)
+            .await?)
+        })
+        .await
+    }
+
+    /// Returns all of the complimentary subscriptions granted to the user,
+    /// including those that have ended, oldest first.
+    pub async fn get_complimentary_subscriptions(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<complimentary_subscription::Model>> {
+        self.transaction(|tx| async move {
+            Ok(complimentary_subscription::Entity::find()
+                .filter(complimentary_subscription::Column::UserId.eq(user_id))
+                .order_by_asc(complimentary_subscription::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the complimentary subscriptions of the user that are neither
+    /// revoked nor expired.
+    pub async fn get_active_complimentary_subscriptions(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<complimentary_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            Ok(complimentary_subscription::Entity::find()
+                .filter(
+                    complimentary_subscription::Column::UserId
+                        .eq(user_id)
+                        .and(complimentary_subscription::Column::RevokedAt.is_null())
+                        .and(
+                            complimentary_subscription::Column::ExpiresAt
+                                .is_null()
+                                .or(complimentary_subscription::Column::ExpiresAt.gt(now)),
+                        ),
+                )
+                .order_by_asc(complimentary_subscription::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Revokes a complimentary subscription, returning it, unless it was
+    /// already revoked.
+    pub async fn revoke_complimentary_subscription(
+        &self,
+        id: ComplimentarySubscriptionId,
+        revoked_by_user_id: UserId,
+    ) -> Result<Option<complimentary_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let result = complimentary_subscription::Entity::update_many()
+                .set(complimentary_subscription::ActiveModel {
+                    revoked_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    revoked_by_user_id: ActiveValue::set(Some(revoked_by_user_id)),
+                    ..Default::default()
+                })
+                .filter(
+                    complimentary_subscription::Column::Id
+                        .eq(id)
+                        .and(complimentary_subscription::Column::RevokedAt.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                return Ok(None);
+            }
+
+            Ok(complimentary_subscription::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
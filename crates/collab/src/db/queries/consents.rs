@@ -0,0 +1,72 @@
+use crate::db::consent::ConsentKind;
+
+use super::*;
+
+impl Database {
+    /// Records that the user accepted the given version of a document.
+    ///
+    /// Accepting the same version more than once keeps the original acceptance.
+    pub async fn record_consent(
+        &self,
+        user_id: UserId,
+        kind: ConsentKind,
+        version: &str,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            consent::Entity::insert(consent::ActiveModel {
+                user_id: ActiveValue::set(user_id),
+                kind: ActiveValue::set(kind),
+                version: ActiveValue::set(version.to_string()),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    consent::Column::UserId,
+                    consent::Column::Kind,
+                    consent::Column::Version,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns whether the user accepted the given version of a document.
+    pub async fn has_consented(
+        &self,
+        user_id: UserId,
+        kind: ConsentKind,
+        version: &str,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            Ok(consent::Entity::find()
+                .filter(
+                    consent::Column::UserId
+                        .eq(user_id)
+                        .and(consent::Column::Kind.eq(kind))
+                        .and(consent::Column::Version.eq(version)),
+                )
+                .one(&*tx)
+                .await?
+                .is_some())
+        })
+        .await
+    }
+
+    /// Returns all of the consents the user has given, oldest first.
+    pub async fn get_consents(&self, user_id: UserId) -> Result<Vec<consent::Model>> {
+        self.transaction(|tx| async move {
+            Ok(consent::Entity::find()
+                .filter(consent::Column::UserId.eq(user_id))
+                .order_by_asc(consent::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use super::*;
+
+impl Database {
+    /// Tries to lease the lock with the given name for `holder`, without
+    /// waiting for it. A lease that has expired is taken over.
+    ///
+    /// Returns whether the lease was acquired.
+    pub async fn try_acquire_distributed_lock(
+        &self,
+        name: &str,
+        holder: &str,
+        lease_duration: Duration,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            let expires_at = now + lease_duration;
+
+            let result = distributed_lock_lease::Entity::update_many()
+                .set(distributed_lock_lease::ActiveModel {
+                    holder: ActiveValue::set(holder.to_string()),
+                    expires_at: ActiveValue::set(expires_at),
+                    ..Default::default()
+                })
+                .filter(
+                    distributed_lock_lease::Column::Name
+                        .eq(name)
+                        .and(distributed_lock_lease::Column::ExpiresAt.lte(now)),
+                )
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected > 0 {
+                return Ok(true);
+            }
+
+            let inserted =
+                distributed_lock_lease::Entity::insert(distributed_lock_lease::ActiveModel {
+                    name: ActiveValue::set(name.to_string()),
+                    holder: ActiveValue::set(holder.to_string()),
+                    expires_at: ActiveValue::set(expires_at),
+                })
+                .on_conflict(
+                    OnConflict::column(distributed_lock_lease::Column::Name)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            Ok(inserted > 0)
+        })
+        .await
+    }
+
+    /// Extends the holder's lease on the lock with the given name.
+    ///
+    /// Returns whether the holder still held the lease.
+    pub async fn renew_distributed_lock(
+        &self,
+        name: &str,
+        holder: &str,
+        lease_duration: Duration,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            let result = distributed_lock_lease::Entity::update_many()
+                .set(distributed_lock_lease::ActiveModel {
+                    expires_at: ActiveValue::set(now + lease_duration),
+                    ..Default::default()
+                })
+                .filter(
+                    distributed_lock_lease::Column::Name
+                        .eq(name)
+                        .and(distributed_lock_lease::Column::Holder.eq(holder)),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(result.rows_affected > 0)
+        })
+        .await
+    }
+
+    /// Releases the holder's lease on the lock with the given name, if it
+    /// still holds it.
+    pub async fn release_distributed_lock(&self, name: &str, holder: &str) -> Result<()> {
+        self.transaction(|tx| async move {
+            distributed_lock_lease::Entity::delete_many()
+                .filter(
+                    distributed_lock_lease::Column::Name
+                        .eq(name)
+                        .and(distributed_lock_lease::Column::Holder.eq(holder)),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,207 @@
+use crate::db::email_delivery::EmailDeliveryStatus;
+use crate::db::email_suppression::EmailSuppressionReason;
+use time::OffsetDateTime;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateEmailDeliveryParams {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+impl Database {
+    /// Queues an email for delivery.
+    ///
+    /// Emails to suppressed addresses are recorded, but never sent.
+    pub async fn enqueue_email(
+        &self,
+        params: &CreateEmailDeliveryParams,
+    ) -> Result<email_delivery::Model> {
+        self.transaction(|tx| async move { self.insert_email_delivery(params, &*tx).await })
+            .await
+    }
+
+    pub(super) async fn insert_email_delivery(
+        &self,
+        params: &CreateEmailDeliveryParams,
+        tx: &DatabaseTransaction,
+    ) -> Result<email_delivery::Model> {
+        let recipient = params.recipient.trim().to_lowercase();
+        let is_suppressed = email_suppression::Entity::find()
+            .filter(email_suppression::Column::EmailAddress.eq(recipient.clone()))
+            .one(tx)
+            .await?
+            .is_some();
+
+        Ok(email_delivery::Entity::insert(email_delivery::ActiveModel {
+            recipient: ActiveValue::set(recipient),
+            subject: ActiveValue::set(params.subject.clone()),
+            body: ActiveValue::set(params.body.clone()),
+            status: ActiveValue::set(if is_suppressed {
+                EmailDeliveryStatus::Suppressed
+            } else {
+                EmailDeliveryStatus::Pending
+            }),
+            next_attempt_at: ActiveValue::set(now()),
+            ..Default::default()
+        })
+        .exec_with_returning(tx)
+        .await?)
+    }
+
+    /// Returns the pending emails whose next delivery attempt is due.
+    pub async fn get_due_email_deliveries(&self, limit: u64) -> Result<Vec<email_delivery::Model>> {
+        self.transaction(|tx| async move {
+            Ok(email_delivery::Entity::find()
+                .filter(
+                    email_delivery::Column::Status
+                        .eq(EmailDeliveryStatus::Pending)
+                        .and(email_delivery::Column::NextAttemptAt.lte(now())),
+                )
+                .order_by_asc(email_delivery::Column::NextAttemptAt)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Records that the email was accepted by the provider.
+    pub async fn mark_email_delivery_sent(
+        &self,
+        id: EmailDeliveryId,
+        provider_message_id: &str,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            email_delivery::Entity::update_many()
+                .set(email_delivery::ActiveModel {
+                    status: ActiveValue::set(EmailDeliveryStatus::Sent),
+                    provider_message_id: ActiveValue::set(Some(provider_message_id.to_string())),
+                    sent_at: ActiveValue::set(Some(now())),
+                    last_error: ActiveValue::set(None),
+                    ..Default::default()
+                })
+                .col_expr(
+                    email_delivery::Column::AttemptCount,
+                    Expr::col(email_delivery::Column::AttemptCount).add(1),
+                )
+                .filter(email_delivery::Column::Id.eq(id))
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a failed delivery attempt.
+    ///
+    /// If `retry_at` is provided, the email will be retried at that time.
+    /// Otherwise, it is marked as failed permanently.
+    pub async fn mark_email_delivery_failed(
+        &self,
+        id: EmailDeliveryId,
+        error: &str,
+        retry_at: Option<OffsetDateTime>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let mut delivery = email_delivery::ActiveModel {
+                last_error: ActiveValue::set(Some(error.to_string())),
+                ..Default::default()
+            };
+            if let Some(retry_at) = retry_at {
+                delivery.status = ActiveValue::set(EmailDeliveryStatus::Pending);
+                delivery.next_attempt_at =
+                    ActiveValue::set(PrimitiveDateTime::new(retry_at.date(), retry_at.time()));
+            } else {
+                delivery.status = ActiveValue::set(EmailDeliveryStatus::Failed);
+            }
+
+            email_delivery::Entity::update_many()
+                .set(delivery)
+                .col_expr(
+                    email_delivery::Column::AttemptCount,
+                    Expr::col(email_delivery::Column::AttemptCount).add(1),
+                )
+                .filter(email_delivery::Column::Id.eq(id))
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Suppresses all future emails to the given address, and records that the
+    /// message with the given provider ID bounced, if any.
+    pub async fn suppress_email_address(
+        &self,
+        email_address: &str,
+        reason: EmailSuppressionReason,
+        provider_message_id: Option<&str>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let email_address = email_address.trim().to_lowercase();
+            email_suppression::Entity::insert(email_suppression::ActiveModel {
+                email_address: ActiveValue::set(email_address.clone()),
+                reason: ActiveValue::set(reason),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(email_suppression::Column::EmailAddress)
+                    .update_column(email_suppression::Column::Reason)
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            if let Some(provider_message_id) = provider_message_id {
+                email_delivery::Entity::update_many()
+                    .set(email_delivery::ActiveModel {
+                        status: ActiveValue::set(EmailDeliveryStatus::Bounced),
+                        ..Default::default()
+                    })
+                    .filter(email_delivery::Column::ProviderMessageId.eq(provider_message_id))
+                    .exec(&*tx)
+                    .await?;
+            }
+
+            email_delivery::Entity::update_many()
+                .set(email_delivery::ActiveModel {
+                    status: ActiveValue::set(EmailDeliveryStatus::Suppressed),
+                    ..Default::default()
+                })
+                .filter(
+                    email_delivery::Column::Recipient
+                        .eq(email_address)
+                        .and(email_delivery::Column::Status.eq(EmailDeliveryStatus::Pending)),
+                )
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns whether emails to the given address are suppressed.
+    pub async fn is_email_address_suppressed(&self, email_address: &str) -> Result<bool> {
+        self.transaction(|tx| async move {
+            Ok(email_suppression::Entity::find()
+                .filter(
+                    email_suppression::Column::EmailAddress.eq(email_address.trim().to_lowercase()),
+                )
+                .one(&*tx)
+                .await?
+                .is_some())
+        })
+        .await
+    }
+}
@@ -0,0 +1,115 @@
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::external_entitlement::ExternalEntitlementSource;
+use time::OffsetDateTime;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct GrantExternalEntitlementParams {
+    pub user_id: UserId,
+    pub source: ExternalEntitlementSource,
+    pub external_id: String,
+    pub plan: SubscriptionPlan,
+}
+
+impl Database {
+    /// Puts an external entitlement into effect, resuming it if it had ended,
+    /// or updates the plan it grants.
+    pub async fn grant_external_entitlement(
+        &self,
+        params: &GrantExternalEntitlementParams,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            external_entitlement::Entity::insert(external_entitlement::ActiveModel {
+                user_id: ActiveValue::set(params.user_id),
+                source: ActiveValue::set(params.source),
+                external_id: ActiveValue::set(params.external_id.clone()),
+                plan: ActiveValue::set(params.plan),
+                started_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+                ended_at: ActiveValue::set(None),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    external_entitlement::Column::Source,
+                    external_entitlement::Column::ExternalId,
+                ])
+                .update_columns([
+                    external_entitlement::Column::UserId,
+                    external_entitlement::Column::Plan,
+                    external_entitlement::Column::StartedAt,
+                    external_entitlement::Column::EndedAt,
+                ])
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Ends an external entitlement that its source no longer grants.
+    pub async fn end_external_entitlement(&self, id: ExternalEntitlementId) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            external_entitlement::Entity::update_many()
+                .filter(
+                    external_entitlement::Column::Id
+                        .eq(id)
+                        .and(external_entitlement::Column::EndedAt.is_null()),
+                )
+                .set(external_entitlement::ActiveModel {
+                    ended_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the external entitlements of the user that are in effect.
+    pub async fn get_active_external_entitlements(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<external_entitlement::Model>> {
+        self.transaction(|tx| async move {
+            Ok(external_entitlement::Entity::find()
+                .filter(
+                    external_entitlement::Column::UserId
+                        .eq(user_id)
+                        .and(external_entitlement::Column::EndedAt.is_null()),
+                )
+                .order_by_asc(external_entitlement::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns every external entitlement from the given source that's in
+    /// effect.
+    pub async fn get_active_external_entitlements_from_source(
+        &self,
+        source: ExternalEntitlementSource,
+    ) -> Result<Vec<external_entitlement::Model>> {
+        self.transaction(|tx| async move {
+            Ok(external_entitlement::Entity::find()
+                .filter(
+                    external_entitlement::Column::Source
+                        .eq(source)
+                        .and(external_entitlement::Column::EndedAt.is_null()),
+                )
+                .order_by_asc(external_entitlement::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
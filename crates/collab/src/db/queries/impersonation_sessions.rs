@@ -0,0 +1,129 @@
+use time::OffsetDateTime;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateImpersonationSessionParams {
+    pub staff_user_id: UserId,
+    pub impersonated_user_id: UserId,
+    pub reason: String,
+    pub allow_writes: bool,
+    pub expires_at: PrimitiveDateTime,
+    pub token_hash: String,
+}
+
+impl Database {
+    /// Starts an impersonation session.
+    pub async fn create_impersonation_session(
+        &self,
+        params: &CreateImpersonationSessionParams,
+    ) -> Result<impersonation_session::Model> {
+        self.transaction(|tx| async move {
+            Ok(
+                impersonation_session::Entity::insert(impersonation_session::ActiveModel {
+                    staff_user_id: ActiveValue::set(params.staff_user_id),
+                    impersonated_user_id: ActiveValue::set(params.impersonated_user_id),
+                    reason: ActiveValue::set(params.reason.clone()),
+                    allow_writes: ActiveValue::set(params.allow_writes),
+                    expires_at: ActiveValue::set(params.expires_at),
+                    token_hash: ActiveValue::set(Some(params.token_hash.clone())),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            )
+        })
+        .await
+    }
+
+    /// Returns the impersonation session with the given ID.
+    pub async fn get_impersonation_session(
+        &self,
+        id: ImpersonationSessionId,
+    ) -> Result<Option<impersonation_session::Model>> {
+        self.transaction(|tx| async move {
+            Ok(impersonation_session::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the impersonation session whose API token has the given hash.
+    pub async fn get_impersonation_session_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<impersonation_session::Model>> {
+        self.transaction(|tx| async move {
+            Ok(impersonation_session::Entity::find()
+                .filter(impersonation_session::Column::TokenHash.eq(token_hash))
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Ends the impersonation session with the given ID, unless it has
+    /// already ended.
+    pub async fn end_impersonation_session(&self, id: ImpersonationSessionId) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            impersonation_session::Entity::update_many()
+                .filter(
+                    impersonation_session::Column::Id
+                        .eq(id)
+                        .and(impersonation_session::Column::EndedAt.is_null()),
+                )
+                .set(impersonation_session::ActiveModel {
+                    ended_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a request made during an impersonation session.
+    pub async fn record_impersonated_request(
+        &self,
+        session_id: ImpersonationSessionId,
+        method: &str,
+        path: &str,
+        allowed: bool,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            impersonated_request::Entity::insert(impersonated_request::ActiveModel {
+                session_id: ActiveValue::set(session_id),
+                method: ActiveValue::set(method.to_string()),
+                path: ActiveValue::set(path.to_string()),
+                allowed: ActiveValue::set(allowed),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the requests made during the given impersonation session,
+    /// oldest first.
+    pub async fn get_impersonated_requests(
+        &self,
+        session_id: ImpersonationSessionId,
+    ) -> Result<Vec<impersonated_request::Model>> {
+        self.transaction(|tx| async move {
+            Ok(impersonated_request::Entity::find()
+                .filter(impersonated_request::Column::SessionId.eq(session_id))
+                .order_by_asc(impersonated_request::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,111 @@
+use time::{Date, OffsetDateTime};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateLanguageModelTopUpParams {
+    pub user_id: UserId,
+    pub stripe_checkout_session_id: String,
+    pub period_start: Date,
+    pub tokens: i64,
+}
+
+impl Database {
+    /// Records a top-up that is awaiting payment.
+    ///
+    /// Retried requests get the same Checkout session back from Stripe, so
+    /// the top-up already recorded for the session is returned instead.
+    pub async fn create_language_model_top_up(
+        &self,
+        params: &CreateLanguageModelTopUpParams,
+    ) -> Result<language_model_top_up::Model> {
+        self.transaction(|tx| async move {
+            language_model_top_up::Entity::insert(language_model_top_up::ActiveModel {
+                user_id: ActiveValue::set(params.user_id),
+                stripe_checkout_session_id: ActiveValue::set(
+                    params.stripe_checkout_session_id.clone(),
+                ),
+                period_start: ActiveValue::set(params.period_start),
+                tokens: ActiveValue::set(params.tokens),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(language_model_top_up::Column::StripeCheckoutSessionId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(language_model_top_up::Entity::find()
+                .filter(
+                    language_model_top_up::Column::StripeCheckoutSessionId
+                        .eq(params.stripe_checkout_session_id.as_str()),
+                )
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("failed to record top-up"))?)
+        })
+        .await
+    }
+
+    /// Marks the top-up paid through the given Checkout session as fulfilled.
+    ///
+    /// Returns the top-up if it was fulfilled by this call, or `None` if there
+    /// is no such top-up or it was already fulfilled.
+    pub async fn fulfill_language_model_top_up(
+        &self,
+        stripe_checkout_session_id: &str,
+    ) -> Result<Option<language_model_top_up::Model>> {
+        self.transaction(|tx| async move {
+            let Some(top_up) = language_model_top_up::Entity::find()
+                .filter(
+                    language_model_top_up::Column::StripeCheckoutSessionId
+                        .eq(stripe_checkout_session_id)
+                        .and(language_model_top_up::Column::FulfilledAt.is_null()),
+                )
+                .one(&*tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+
+            let now = OffsetDateTime::now_utc();
+            let top_up =
+                language_model_top_up::Entity::update(language_model_top_up::ActiveModel {
+                    id: ActiveValue::unchanged(top_up.id),
+                    fulfilled_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            Ok(Some(top_up))
+        })
+        .await
+    }
+
+    /// Returns the number of tokens the user topped up for the usage period
+    /// starting on the given day.
+    pub async fn get_language_model_top_up_tokens(
+        &self,
+        user_id: UserId,
+        period_start: Date,
+    ) -> Result<i64> {
+        self.transaction(|tx| async move {
+            let top_ups = language_model_top_up::Entity::find()
+                .filter(
+                    language_model_top_up::Column::UserId
+                        .eq(user_id)
+                        .and(language_model_top_up::Column::PeriodStart.eq(period_start))
+                        .and(language_model_top_up::Column::FulfilledAt.is_not_null()),
+                )
+                .all(&*tx)
+                .await?;
+            Ok(top_ups.iter().map(|top_up| top_up.tokens).sum())
+        })
+        .await
+    }
+}
@@ -0,0 +1,416 @@
+use sea_orm::sea_query::{Func, SimpleExpr};
+use time::{Date, Duration, OffsetDateTime};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateLanguageModelUsageParams {
+    pub user_id: UserId,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub feature: Option<String>,
+    pub upstream_cost_in_millicents: i64,
+}
+
+/// The aggregated usage of a product feature with a single provider on a single day.
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct LanguageModelFeatureCost {
+    pub date: Date,
+    pub feature: Option<String>,
+    pub provider: String,
+    pub request_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub upstream_cost_in_millicents: i64,
+}
+
+/// The aggregated usage of a single user on a single day.
+#[derive(Debug, Clone, PartialEq, Eq, FromQueryResult)]
+pub struct DailyLanguageModelUsage {
+    pub date: Date,
+    pub user_id: UserId,
+    pub request_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub upstream_cost_in_millicents: i64,
+}
+
+impl Database {
+    /// Records the tokens consumed by a language model request.
+    pub async fn record_language_model_usage(
+        &self,
+        params: &CreateLanguageModelUsageParams,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let organization_id = self.get_usage_organization_id(params.user_id, &tx).await?;
+            language_model_usage::Entity::insert(language_model_usage::ActiveModel {
+                user_id: ActiveValue::set(params.user_id),
+                provider: ActiveValue::set(params.provider.clone()),
+                model: ActiveValue::set(params.model.clone()),
+                input_tokens: ActiveValue::set(params.input_tokens),
+                output_tokens: ActiveValue::set(params.output_tokens),
+                feature: ActiveValue::set(params.feature.clone()),
+                upstream_cost_in_millicents: ActiveValue::set(params.upstream_cost_in_millicents),
+                organization_id: ActiveValue::set(organization_id),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the total number of tokens (input and output) used by each user
+    /// within the given time range.
+    ///
+    /// Users without any usage in the time range are omitted.
+    pub async fn get_language_model_token_usage_by_user(
+        &self,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<HashMap<UserId, i64>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            UserId,
+            Tokens,
+        }
+
+        self.transaction(|tx| async move {
+            let start = PrimitiveDateTime::new(start.date(), start.time());
+            let end = PrimitiveDateTime::new(end.date(), end.time());
+
+            let usage_by_user = language_model_usage::Entity::find()
+                .select_only()
+                .column(language_model_usage::Column::UserId)
+                .column_as(total_tokens(), QueryAs::Tokens)
+                .filter(
+                    language_model_usage::Column::CreatedAt
+                        .gte(start)
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .group_by(language_model_usage::Column::UserId)
+                .into_values::<(UserId, i64), QueryAs>()
+                .all(&*tx)
+                .await?;
+
+            Ok(usage_by_user.into_iter().collect())
+        })
+        .await
+    }
+
+    /// Returns the total number of tokens (input and output) used by the given
+    /// user within the given time range.
+    pub async fn get_language_model_token_usage_for_user(
+        &self,
+        user_id: UserId,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<i64> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Tokens,
+        }
+
+        self.transaction(|tx| async move {
+            let start = PrimitiveDateTime::new(start.date(), start.time());
+            let end = PrimitiveDateTime::new(end.date(), end.time());
+
+            let tokens = language_model_usage::Entity::find()
+                .select_only()
+                .column_as(total_tokens(), QueryAs::Tokens)
+                .filter(
+                    language_model_usage::Column::UserId
+                        .eq(user_id)
+                        .and(language_model_usage::Column::CreatedAt.gte(start))
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .into_values::<Option<i64>, QueryAs>()
+                .one(&*tx)
+                .await?
+                .flatten();
+
+            Ok(tokens.unwrap_or(0))
+        })
+        .await
+    }
+
+    /// Returns the total number of tokens (input and output) used by the given
+    /// user within the given time range, for each model they used, most used
+    /// first.
+    pub async fn get_language_model_token_usage_by_model_for_user(
+        &self,
+        user_id: UserId,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<(String, i64)>> {
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveColumn)]
+        enum QueryAs {
+            Model,
+            Tokens,
+        }
+
+        self.transaction(|tx| async move {
+            let start = PrimitiveDateTime::new(start.date(), start.time());
+            let end = PrimitiveDateTime::new(end.date(), end.time());
+
+            let mut usage_by_model = language_model_usage::Entity::find()
+                .select_only()
+                .column(language_model_usage::Column::Model)
+                .column_as(total_tokens(), QueryAs::Tokens)
+                .filter(
+                    language_model_usage::Column::UserId
+                        .eq(user_id)
+                        .and(language_model_usage::Column::CreatedAt.gte(start))
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .group_by(language_model_usage::Column::Model)
+                .into_values::<(String, i64), QueryAs>()
+                .all(&*tx)
+                .await?;
+
+            usage_by_model.sort_by(|(model_a, tokens_a), (model_b, tokens_b)| {
+                tokens_b.cmp(tokens_a).then_with(|| model_a.cmp(model_b))
+            });
+            Ok(usage_by_model)
+        })
+        .await
+    }
+
+    /// Returns the upstream cost of language model requests within the given
+    /// time range, grouped by day, feature, and provider.
+    pub async fn get_language_model_costs_by_feature(
+        &self,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<LanguageModelFeatureCost>> {
+        self.transaction(|tx| async move {
+            let start = PrimitiveDateTime::new(start.date(), start.time());
+            let end = PrimitiveDateTime::new(end.date(), end.time());
+
+            let mut costs = language_model_usage::Entity::find()
+                .select_only()
+                .column_as(usage_date(), "date")
+                .column(language_model_usage::Column::Feature)
+                .column(language_model_usage::Column::Provider)
+                .column_as(request_count(), "request_count")
+                .column_as(
+                    sum(language_model_usage::Column::InputTokens),
+                    "input_tokens",
+                )
+                .column_as(
+                    sum(language_model_usage::Column::OutputTokens),
+                    "output_tokens",
+                )
+                .column_as(
+                    sum(language_model_usage::Column::UpstreamCostInMillicents),
+                    "upstream_cost_in_millicents",
+                )
+                .filter(
+                    language_model_usage::Column::CreatedAt
+                        .gte(start)
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .group_by(usage_date())
+                .group_by(language_model_usage::Column::Feature)
+                .group_by(language_model_usage::Column::Provider)
+                .into_model::<LanguageModelFeatureCost>()
+                .all(&*tx)
+                .await?;
+
+            // Sorted here, as databases disagree on where nulls are sorted.
+            costs.sort_by(|a, b| {
+                (a.date, &a.feature, &a.provider).cmp(&(b.date, &b.feature, &b.provider))
+            });
+            Ok(costs)
+        })
+        .await
+    }
+
+    /// Returns the language model usage of the given users within the given
+    /// time range, grouped by day and user.
+    pub async fn get_daily_language_model_usage_for_users(
+        &self,
+        user_ids: &[UserId],
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<DailyLanguageModelUsage>> {
+        self.transaction(|tx| async move {
+            let start = PrimitiveDateTime::new(start.date(), start.time());
+            let end = PrimitiveDateTime::new(end.date(), end.time());
+
+            let usages = language_model_usage::Entity::find()
+                .select_only()
+                .column_as(usage_date(), "date")
+                .column(language_model_usage::Column::UserId)
+                .column_as(request_count(), "request_count")
+                .column_as(
+                    sum(language_model_usage::Column::InputTokens),
+                    "input_tokens",
+                )
+                .column_as(
+                    sum(language_model_usage::Column::OutputTokens),
+                    "output_tokens",
+                )
+                .column_as(
+                    sum(language_model_usage::Column::UpstreamCostInMillicents),
+                    "upstream_cost_in_millicents",
+                )
+                .filter(
+                    language_model_usage::Column::UserId
+                        .is_in(user_ids.iter().copied())
+                        .and(language_model_usage::Column::CreatedAt.gte(start))
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .group_by(usage_date())
+                .group_by(language_model_usage::Column::UserId)
+                .order_by_asc(usage_date())
+                .order_by_asc(language_model_usage::Column::UserId)
+                .into_model::<DailyLanguageModelUsage>()
+                .all(&*tx)
+                .await?;
+
+            Ok(usages)
+        })
+        .await
+    }
+
+    /// Rolls up to `limit` of the oldest language model usages recorded before
+    /// the given time into the monthly usages, and deletes them.
+    ///
+    /// Returns the number of usages that were archived.
+    pub async fn archive_language_model_usages(
+        &self,
+        before: OffsetDateTime,
+        limit: u64,
+    ) -> Result<usize> {
+        self.transaction(|tx| async move {
+            let before = PrimitiveDateTime::new(before.date(), before.time());
+
+            let usages = language_model_usage::Entity::find()
+                .filter(language_model_usage::Column::CreatedAt.lt(before))
+                .order_by_asc(language_model_usage::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?;
+
+            let mut monthly_usages =
+                BTreeMap::<(Date, UserId, String, String, Option<String>), [i64; 4]>::new();
+            for usage in &usages {
+                let date = usage.created_at.date();
+                let month = date - Duration::days(date.day() as i64 - 1);
+                let totals = monthly_usages
+                    .entry((
+                        month,
+                        usage.user_id,
+                        usage.provider.clone(),
+                        usage.model.clone(),
+                        usage.feature.clone(),
+                    ))
+                    .or_default();
+                totals[0] += 1;
+                totals[1] += usage.input_tokens;
+                totals[2] += usage.output_tokens;
+                totals[3] += usage.upstream_cost_in_millicents;
+            }
+
+            // Each group is added to its monthly usage in a single statement, so
+            // that concurrent archivers can't lose each other's totals.
+            for ((month, user_id, provider, model, feature), totals) in monthly_usages {
+                let [request_count, input_tokens, output_tokens, upstream_cost_in_millicents] =
+                    totals;
+                tx.execute(Statement::from_sql_and_values(
+                    self.pool.get_database_backend(),
+                    "
+                    INSERT INTO monthly_language_model_usages
+                        (month, user_id, provider, model, feature, request_count, input_tokens,
+                         output_tokens, upstream_cost_in_millicents)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                    ON CONFLICT (user_id, month, provider, model, COALESCE(feature, ''))
+                    DO UPDATE SET
+                        request_count =
+                            monthly_language_model_usages.request_count + excluded.request_count,
+                        input_tokens =
+                            monthly_language_model_usages.input_tokens + excluded.input_tokens,
+                        output_tokens =
+                            monthly_language_model_usages.output_tokens + excluded.output_tokens,
+                        upstream_cost_in_millicents =
+                            monthly_language_model_usages.upstream_cost_in_millicents
+                            + excluded.upstream_cost_in_millicents
+                    ",
+                    [
+                        month.into(),
+                        user_id.into(),
+                        provider.into(),
+                        model.into(),
+                        feature.into(),
+                        request_count.into(),
+                        input_tokens.into(),
+                        output_tokens.into(),
+                        upstream_cost_in_millicents.into(),
+                    ],
+                ))
+                .await?;
+            }
+
+            language_model_usage::Entity::delete_many()
+                .filter(language_model_usage::Column::Id.is_in(usages.iter().map(|usage| usage.id)))
+                .exec(&*tx)
+                .await?;
+
+            Ok(usages.len())
+        })
+        .await
+    }
+
+    /// Returns the monthly usages of the given user that have been archived,
+    /// oldest first.
+    pub async fn get_monthly_language_model_usages(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<monthly_language_model_usage::Model>> {
+        self.transaction(|tx| async move {
+            Ok(monthly_language_model_usage::Entity::find()
+                .filter(monthly_language_model_usage::Column::UserId.eq(user_id))
+                .order_by_asc(monthly_language_model_usage::Column::Month)
+                .order_by_asc(monthly_language_model_usage::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
+
+/// Returns the day a language model usage was recorded on.
+fn usage_date() -> SimpleExpr {
+    Func::cust(Alias::new("DATE"))
+        .arg(Expr::col(language_model_usage::Column::CreatedAt))
+        .into()
+}
+
+fn request_count() -> SimpleExpr {
+    Expr::cast_as(
+        Func::count(Expr::col(language_model_usage::Column::Id)),
+        Alias::new("BIGINT"),
+    )
+}
+
+/// Sums the column. Postgres sums `BIGINT`s into a `NUMERIC`, so the sum is
+/// cast back.
+fn sum(column: language_model_usage::Column) -> SimpleExpr {
+    Expr::cast_as(Func::sum(Expr::col(column)), Alias::new("BIGINT"))
+}
+
+/// Sums the input and output tokens, like [`sum`].
+fn total_tokens() -> SimpleExpr {
+    Expr::cast_as(
+        Func::sum(
+            Expr::col(language_model_usage::Column::InputTokens)
+                .add(Expr::col(language_model_usage::Column::OutputTokens)),
+        ),
+        Alias::new("BIGINT"),
+    )
+}
@@ -0,0 +1,173 @@
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateModelExperimentVariantParams {
+    pub name: String,
+    pub weight: i32,
+    pub provider: String,
+    pub model: String,
+    pub temperature: Option<f32>,
+}
+
+impl Database {
+    /// Creates a new, active model experiment with the given variants.
+    pub async fn create_model_experiment(
+        &self,
+        name: &str,
+        variants: &[CreateModelExperimentVariantParams],
+    ) -> Result<(
+        model_experiment::Model,
+        Vec<model_experiment_variant::Model>,
+    )> {
+        self.transaction(|tx| async move {
+            let experiment = model_experiment::Entity::insert(model_experiment::ActiveModel {
+                name: ActiveValue::set(name.to_string()),
+                is_active: ActiveValue::set(true),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+
+            let mut created_variants = Vec::with_capacity(variants.len());
+            for variant in variants {
+                created_variants.push(
+                    model_experiment_variant::Entity::insert(
+                        model_experiment_variant::ActiveModel {
+                            experiment_id: ActiveValue::set(experiment.id),
+                            name: ActiveValue::set(variant.name.clone()),
+                            weight: ActiveValue::set(variant.weight),
+                            provider: ActiveValue::set(variant.provider.clone()),
+                            model: ActiveValue::set(variant.model.clone()),
+                            temperature: ActiveValue::set(variant.temperature),
+                            ..Default::default()
+                        },
+                    )
+                    .exec_with_returning(&*tx)
+                    .await?,
+                );
+            }
+
+            Ok((experiment, created_variants))
+        })
+        .await
+    }
+
+    /// Returns the most recently created active model experiment and its variants.
+    pub async fn get_active_model_experiment(
+        &self,
+    ) -> Result<
+        Option<(
+            model_experiment::Model,
+            Vec<model_experiment_variant::Model>,
+        )>,
+    > {
+        self.transaction(|tx| async move {
+            let Some(experiment) = model_experiment::Entity::find()
+                .filter(model_experiment::Column::IsActive.eq(true))
+                .order_by_desc(model_experiment::Column::Id)
+                .one(&*tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+
+            let variants = model_experiment_variant::Entity::find()
+                .filter(model_experiment_variant::Column::ExperimentId.eq(experiment.id))
+                .order_by_asc(model_experiment_variant::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            Ok(Some((experiment, variants)))
+        })
+        .await
+    }
+
+    /// Stops assigning users to the given model experiment.
+    pub async fn deactivate_model_experiment(&self, id: ModelExperimentId) -> Result<()> {
+        self.transaction(|tx| async move {
+            model_experiment::Entity::update_many()
+                .set(model_experiment::ActiveModel {
+                    is_active: ActiveValue::set(false),
+                    ..Default::default()
+                })
+                .filter(model_experiment::Column::Id.eq(id))
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the variant the user was first exposed to in the given experiment, if any.
+    pub async fn get_model_experiment_exposure(
+        &self,
+        experiment_id: ModelExperimentId,
+        user_id: UserId,
+    ) -> Result<Option<model_experiment_exposure::Model>> {
+        self.transaction(|tx| async move {
+            Ok(model_experiment_exposure::Entity::find()
+                .filter(
+                    model_experiment_exposure::Column::ExperimentId
+                        .eq(experiment_id)
+                        .and(model_experiment_exposure::Column::UserId.eq(user_id)),
+                )
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Records that the user was served the given variant.
+    ///
+    /// Only the first exposure of a user to an experiment is recorded.
+    pub async fn record_model_experiment_exposure(
+        &self,
+        experiment_id: ModelExperimentId,
+        variant_id: ModelExperimentVariantId,
+        user_id: UserId,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            model_experiment_exposure::Entity::insert(model_experiment_exposure::ActiveModel {
+                experiment_id: ActiveValue::set(experiment_id),
+                variant_id: ActiveValue::set(variant_id),
+                user_id: ActiveValue::set(user_id),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    model_experiment_exposure::Column::ExperimentId,
+                    model_experiment_exposure::Column::UserId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the number of users exposed to each variant of the experiment.
+    pub async fn get_model_experiment_exposure_counts(
+        &self,
+        experiment_id: ModelExperimentId,
+    ) -> Result<HashMap<ModelExperimentVariantId, i64>> {
+        self.transaction(|tx| async move {
+            let mut rows = model_experiment_exposure::Entity::find()
+                .filter(model_experiment_exposure::Column::ExperimentId.eq(experiment_id))
+                .stream(&*tx)
+                .await?;
+
+            let mut counts = HashMap::default();
+            while let Some(row) = rows.next().await {
+                *counts.entry(row?.variant_id).or_default() += 1;
+            }
+
+            Ok(counts)
+        })
+        .await
+    }
+}
@@ -0,0 +1,173 @@
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::db::organization_member_daily_usage::TopModels;
+
+use super::*;
+
+/// The number of models listed in a member's top models for a day.
+const TOP_MODEL_COUNT: usize = 3;
+
+#[derive(Default)]
+struct MemberUsage {
+    request_count: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    upstream_cost_in_millicents: i64,
+    tokens_by_model: HashMap<(String, String), i64>,
+}
+
+impl Database {
+    /// Rolls up the language model usage attributed to the organization on the
+    /// given day by each of its members, replacing an earlier rollup of that
+    /// day, including the rows of members who have left since.
+    ///
+    /// Returns the number of members that used any language models that day.
+    pub async fn roll_up_organization_member_daily_usages(
+        &self,
+        organization_id: OrganizationId,
+        date: Date,
+    ) -> Result<usize> {
+        self.transaction(|tx| async move {
+            let member_ids = organization_member::Entity::find()
+                .filter(organization_member::Column::OrganizationId.eq(organization_id))
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|member| member.user_id)
+                .collect::<Vec<_>>();
+
+            let start = date.midnight();
+            let end = (date + Duration::days(1)).midnight();
+            let mut rows = language_model_usage::Entity::find()
+                .filter(
+                    language_model_usage::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(language_model_usage::Column::UserId.is_in(member_ids))
+                        .and(language_model_usage::Column::CreatedAt.gte(start))
+                        .and(language_model_usage::Column::CreatedAt.lt(end)),
+                )
+                .stream(&*tx)
+                .await?;
+
+            let mut usages = BTreeMap::<UserId, MemberUsage>::default();
+            while let Some(row) = rows.next().await {
+                let row = row?;
+                let usage = usages.entry(row.user_id).or_default();
+                usage.request_count += 1;
+                usage.input_tokens += row.input_tokens;
+                usage.output_tokens += row.output_tokens;
+                usage.upstream_cost_in_millicents += row.upstream_cost_in_millicents;
+                *usage
+                    .tokens_by_model
+                    .entry((row.provider, row.model))
+                    .or_default() += row.input_tokens + row.output_tokens;
+            }
+            drop(rows);
+
+            organization_member_daily_usage::Entity::delete_many()
+                .filter(
+                    organization_member_daily_usage::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member_daily_usage::Column::Date.eq(date))
+                        .and(
+                            organization_member_daily_usage::Column::UserId
+                                .is_not_in(usages.keys().copied()),
+                        ),
+                )
+                .exec(&*tx)
+                .await?;
+
+            let now = OffsetDateTime::now_utc();
+            let member_count = usages.len();
+            for (user_id, usage) in usages {
+                organization_member_daily_usage::Entity::insert(
+                    organization_member_daily_usage::ActiveModel {
+                        organization_id: ActiveValue::set(organization_id),
+                        user_id: ActiveValue::set(user_id),
+                        date: ActiveValue::set(date),
+                        request_count: ActiveValue::set(usage.request_count),
+                        input_tokens: ActiveValue::set(usage.input_tokens),
+                        output_tokens: ActiveValue::set(usage.output_tokens),
+                        estimated_cost_in_millicents: ActiveValue::set(
+                            usage.upstream_cost_in_millicents,
+                        ),
+                        top_models: ActiveValue::set(TopModels::from_tokens_by_model(
+                            usage.tokens_by_model,
+                            TOP_MODEL_COUNT,
+                        )),
+                        updated_at: ActiveValue::set(PrimitiveDateTime::new(
+                            now.date(),
+                            now.time(),
+                        )),
+                        ..Default::default()
+                    },
+                )
+                .on_conflict(
+                    OnConflict::columns([
+                        organization_member_daily_usage::Column::OrganizationId,
+                        organization_member_daily_usage::Column::Date,
+                        organization_member_daily_usage::Column::UserId,
+                    ])
+                    .update_columns([
+                        organization_member_daily_usage::Column::RequestCount,
+                        organization_member_daily_usage::Column::InputTokens,
+                        organization_member_daily_usage::Column::OutputTokens,
+                        organization_member_daily_usage::Column::EstimatedCostInMillicents,
+                        organization_member_daily_usage::Column::TopModels,
+                        organization_member_daily_usage::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            }
+
+            Ok(member_count)
+        })
+        .await
+    }
+
+    /// Records the last day the organization's usage was rolled up for.
+    pub async fn set_organization_usage_rolled_up_through(
+        &self,
+        organization_id: OrganizationId,
+        date: Date,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            organization::Entity::update_many()
+                .set(organization::ActiveModel {
+                    usage_rolled_up_through: ActiveValue::set(Some(date)),
+                    ..Default::default()
+                })
+                .filter(organization::Column::Id.eq(organization_id))
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the rolled up usage of the organization's members from `start`
+    /// through `end`, ordered by day and member.
+    pub async fn get_organization_member_daily_usages(
+        &self,
+        organization_id: OrganizationId,
+        start: Date,
+        end: Date,
+    ) -> Result<Vec<organization_member_daily_usage::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_member_daily_usage::Entity::find()
+                .filter(
+                    organization_member_daily_usage::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member_daily_usage::Column::Date.gte(start))
+                        .and(organization_member_daily_usage::Column::Date.lte(end)),
+                )
+                .order_by_asc(organization_member_daily_usage::Column::Date)
+                .order_by_asc(organization_member_daily_usage::Column::UserId)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,125 @@
+use crate::db::organization::SeatPolicy;
+use crate::db::organization_seat_true_up::SeatTrueUpOutcome;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateOrganizationSeatTrueUpParams {
+    pub organization_id: OrganizationId,
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub member_count: i32,
+    pub seat_count: i32,
+    pub outcome: SeatTrueUpOutcome,
+}
+
+impl Database {
+    /// Sets the subscription that pays for the organization's seats, and how
+    /// those seats are kept in line with its member count.
+    ///
+    /// Returns `None` if the subscription already pays for the seats of
+    /// another organization.
+    pub async fn set_organization_seat_billing(
+        &self,
+        organization_id: OrganizationId,
+        billing_subscription_id: Option<BillingSubscriptionId>,
+        seat_policy: SeatPolicy,
+    ) -> Result<Option<organization::Model>> {
+        self.transaction(|tx| async move {
+            if let Some(billing_subscription_id) = billing_subscription_id {
+                let attached_elsewhere = organization::Entity::find()
+                    .filter(
+                        organization::Column::BillingSubscriptionId
+                            .eq(billing_subscription_id)
+                            .and(organization::Column::Id.ne(organization_id)),
+                    )
+                    .one(&*tx)
+                    .await?
+                    .is_some();
+                if attached_elsewhere {
+                    return Ok(None);
+                }
+            }
+
+            Ok(Some(
+                organization::Entity::update(organization::ActiveModel {
+                    id: ActiveValue::unchanged(organization_id),
+                    billing_subscription_id: ActiveValue::set(billing_subscription_id),
+                    seat_policy: ActiveValue::set(seat_policy),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?,
+            ))
+        })
+        .await
+    }
+
+    /// Returns the organizations whose seats are paid for by a subscription.
+    pub async fn get_organizations_with_billing_subscriptions(
+        &self,
+    ) -> Result<Vec<organization::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization::Entity::find()
+                .filter(organization::Column::BillingSubscriptionId.is_not_null())
+                .order_by_asc(organization::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the number of members in the organization.
+    pub async fn get_organization_member_count(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<u64> {
+        self.transaction(|tx| async move {
+            Ok(organization_member::Entity::find()
+                .filter(organization_member::Column::OrganizationId.eq(organization_id))
+                .count(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Records a discrepancy found between the organization's members and
+    /// its seats.
+    pub async fn record_organization_seat_true_up(
+        &self,
+        params: &CreateOrganizationSeatTrueUpParams,
+    ) -> Result<organization_seat_true_up::Model> {
+        self.transaction(|tx| async move {
+            Ok(
+                organization_seat_true_up::Entity::insert(organization_seat_true_up::ActiveModel {
+                    organization_id: ActiveValue::set(params.organization_id),
+                    billing_subscription_id: ActiveValue::set(params.billing_subscription_id),
+                    member_count: ActiveValue::set(params.member_count),
+                    seat_count: ActiveValue::set(params.seat_count),
+                    outcome: ActiveValue::set(params.outcome),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            )
+        })
+        .await
+    }
+
+    /// Returns the most recent seat discrepancies of the organization, newest
+    /// first.
+    pub async fn get_organization_seat_true_ups(
+        &self,
+        organization_id: OrganizationId,
+        limit: u64,
+    ) -> Result<Vec<organization_seat_true_up::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_seat_true_up::Entity::find()
+                .filter(organization_seat_true_up::Column::OrganizationId.eq(organization_id))
+                .order_by_desc(organization_seat_true_up::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+}
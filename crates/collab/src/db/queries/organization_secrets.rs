@@ -0,0 +1,155 @@
+use super::*;
+
+const ORGANIZATION_SECRET_VALUE_COLUMN: &str = "organization_secrets.encrypted_value";
+
+/// A secret leased to an organization member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrganizationSecretLease {
+    pub value: String,
+    pub expires_at: PrimitiveDateTime,
+}
+
+impl Database {
+    /// Stores the given secret for the organization, replacing any existing
+    /// secret with the same name.
+    pub async fn upsert_organization_secret(
+        &self,
+        organization_id: OrganizationId,
+        name: &str,
+        value: &str,
+    ) -> Result<()> {
+        let encrypted_value = self
+            .column_cipher()?
+            .encrypt(ORGANIZATION_SECRET_VALUE_COLUMN, value)?;
+        let encrypted_value = &encrypted_value;
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let now = PrimitiveDateTime::new(now.date(), now.time());
+            organization_secret::Entity::insert(organization_secret::ActiveModel {
+                organization_id: ActiveValue::set(organization_id),
+                name: ActiveValue::set(name.to_string()),
+                encrypted_value: ActiveValue::set(encrypted_value.clone()),
+                created_at: ActiveValue::set(now),
+                updated_at: ActiveValue::set(now),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    organization_secret::Column::OrganizationId,
+                    organization_secret::Column::Name,
+                ])
+                .update_columns([
+                    organization_secret::Column::EncryptedValue,
+                    organization_secret::Column::UpdatedAt,
+                ])
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes the organization's secret with the given name, if it exists.
+    pub async fn delete_organization_secret(
+        &self,
+        organization_id: OrganizationId,
+        name: &str,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            organization_secret::Entity::delete_many()
+                .filter(
+                    organization_secret::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_secret::Column::Name.eq(name)),
+                )
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Leases the secret with the given name to the user, recording the lease.
+    ///
+    /// The secret is looked up in the organization with the given slug, or in
+    /// whichever of the user's organizations has a secret with that name. The
+    /// user must be a member of the organization.
+    pub async fn lease_organization_secret(
+        &self,
+        user_id: UserId,
+        organization_slug: Option<&str>,
+        name: &str,
+        lease_duration: Duration,
+    ) -> Result<Option<OrganizationSecretLease>> {
+        let secret = self
+            .transaction(|tx| async move {
+                let mut organization_ids = organization_member::Entity::find()
+                    .filter(organization_member::Column::UserId.eq(user_id))
+                    .all(&*tx)
+                    .await?
+                    .into_iter()
+                    .map(|member| member.organization_id)
+                    .collect::<Vec<_>>();
+                if let Some(organization_slug) = organization_slug {
+                    let organization = organization::Entity::find()
+                        .filter(organization::Column::Slug.eq(organization_slug))
+                        .one(&*tx)
+                        .await?;
+                    organization_ids.retain(|id| {
+                        organization
+                            .as_ref()
+                            .map_or(false, |organization| organization.id == *id)
+                    });
+                }
+
+                // The secrets of organizations whose features are disabled
+                // can't be leased.
+                let mut secrets = organization_secret::Entity::find()
+                    .inner_join(organization::Entity)
+                    .filter(
+                        organization_secret::Column::OrganizationId
+                            .is_in(organization_ids)
+                            .and(organization_secret::Column::Name.eq(name))
+                            .and(organization::Column::FeaturesDisabledAt.is_null()),
+                    )
+                    .all(&*tx)
+                    .await?;
+                if secrets.len() > 1 {
+                    Err(anyhow!(
+                        "more than one of your organizations has a secret named {name:?}"
+                    ))?;
+                }
+                let Some(secret) = secrets.pop() else {
+                    return Ok(None);
+                };
+
+                let now = OffsetDateTime::now_utc();
+                let expires_at = now + lease_duration;
+                let expires_at = PrimitiveDateTime::new(expires_at.date(), expires_at.time());
+                organization_secret_lease::Entity::insert(organization_secret_lease::ActiveModel {
+                    organization_secret_id: ActiveValue::set(secret.id),
+                    user_id: ActiveValue::set(user_id),
+                    expires_at: ActiveValue::set(expires_at),
+                    created_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+                    ..Default::default()
+                })
+                .exec_without_returning(&*tx)
+                .await?;
+
+                Ok(Some((secret, expires_at)))
+            })
+            .await?;
+
+        let Some((secret, expires_at)) = secret else {
+            return Ok(None);
+        };
+        let value = self
+            .column_cipher()?
+            .decrypt(ORGANIZATION_SECRET_VALUE_COLUMN, &secret.encrypted_value)?;
+        Ok(Some(OrganizationSecretLease { value, expires_at }))
+    }
+}
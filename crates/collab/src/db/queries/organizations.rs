@@ -0,0 +1,441 @@
+use crate::db::organization_member::OrganizationRole;
+use time::OffsetDateTime;
+
+use super::*;
+
+impl Database {
+    /// Creates a new organization.
+    pub async fn create_organization(&self, name: &str, slug: &str) -> Result<organization::Model> {
+        self.transaction(|tx| async move {
+            Ok(organization::Entity::insert(organization::ActiveModel {
+                name: ActiveValue::set(name.to_string()),
+                slug: ActiveValue::set(slug.to_string()),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?)
+        })
+        .await
+    }
+
+    /// Creates a new organization, owned by the given user, returning `None`
+    /// if its slug is taken.
+    pub async fn create_organization_with_owner(
+        &self,
+        name: &str,
+        slug: &str,
+        owner_id: UserId,
+    ) -> Result<Option<organization::Model>> {
+        self.transaction(|tx| async move {
+            let slug_taken = organization::Entity::find()
+                .filter(organization::Column::Slug.eq(slug))
+                .one(&*tx)
+                .await?
+                .is_some();
+            if slug_taken {
+                return Ok(None);
+            }
+
+            let organization = organization::Entity::insert(organization::ActiveModel {
+                name: ActiveValue::set(name.to_string()),
+                slug: ActiveValue::set(slug.to_string()),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+            organization_member::Entity::insert(organization_member::ActiveModel {
+                organization_id: ActiveValue::set(organization.id),
+                user_id: ActiveValue::set(owner_id),
+                role: ActiveValue::set(OrganizationRole::Owner),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+            Ok(Some(organization))
+        })
+        .await
+    }
+
+    /// Returns the organization with the given ID.
+    pub async fn get_organization_by_id(
+        &self,
+        id: OrganizationId,
+    ) -> Result<Option<organization::Model>> {
+        self.transaction(
+            |tx| async move { Ok(organization::Entity::find_by_id(id).one(&*tx).await?) },
+        )
+        .await
+    }
+
+    /// Adds the user to the organization with the given role, or updates their
+    /// role if they are already a member.
+    pub async fn add_organization_member(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+        role: OrganizationRole,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            organization_member::Entity::insert(organization_member::ActiveModel {
+                organization_id: ActiveValue::set(organization_id),
+                user_id: ActiveValue::set(user_id),
+                role: ActiveValue::set(role),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    organization_member::Column::OrganizationId,
+                    organization_member::Column::UserId,
+                ])
+                .update_column(organization_member::Column::Role)
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the user's membership in the organization, if any.
+    pub async fn get_organization_member(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<Option<organization_member::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_member::Entity::find()
+                .filter(
+                    organization_member::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member::Column::UserId.eq(user_id)),
+                )
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns all organizations.
+    pub async fn get_organizations(&self) -> Result<Vec<organization::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization::Entity::find()
+                .order_by_asc(organization::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns all members of the organization.
+    pub async fn get_organization_members(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<Vec<organization_member::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_member::Entity::find()
+                .filter(organization_member::Column::OrganizationId.eq(organization_id))
+                .order_by_asc(organization_member::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the user's memberships in organizations, oldest first.
+    pub async fn get_organization_memberships_for_user(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<organization_member::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_member::Entity::find()
+                .filter(organization_member::Column::UserId.eq(user_id))
+                .order_by_asc(organization_member::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Disables or re-enables the features of the organizations the user
+    /// administers.
+    ///
+    /// Returns the IDs of the organizations whose features were changed.
+    pub async fn set_administered_organization_features_disabled(
+        &self,
+        user_id: UserId,
+        disabled: bool,
+    ) -> Result<Vec<OrganizationId>> {
+        self.transaction(|tx| async move {
+            let organization_ids = organization::Entity::find()
+                .inner_join(organization_member::Entity)
+                .filter(
+                    organization_member::Column::UserId
+                        .eq(user_id)
+                        .and(
+                            organization_member::Column::Role
+                                .is_in([OrganizationRole::Owner, OrganizationRole::Admin]),
+                        )
+                        .and(if disabled {
+                            organization::Column::FeaturesDisabledAt.is_null()
+                        } else {
+                            organization::Column::FeaturesDisabledAt.is_not_null()
+                        }),
+                )
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|organization| organization.id)
+                .collect::<Vec<_>>();
+            if organization_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let features_disabled_at = disabled.then(|| {
+                let now = OffsetDateTime::now_utc();
+                PrimitiveDateTime::new(now.date(), now.time())
+            });
+            organization::Entity::update_many()
+                .col_expr(
+                    organization::Column::FeaturesDisabledAt,
+                    Expr::value(features_disabled_at),
+                )
+                .filter(organization::Column::Id.is_in(organization_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            Ok(organization_ids)
+        })
+        .await
+    }
+
+    /// Removes the user from the organization, along with their rolled up
+    /// usage, returning whether they were a member of it.
+    pub async fn remove_organization_member(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let result = organization_member::Entity::delete_many()
+                .filter(
+                    organization_member::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member::Column::UserId.eq(user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            organization_member_daily_usage::Entity::delete_many()
+                .filter(
+                    organization_member_daily_usage::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member_daily_usage::Column::UserId.eq(user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(result.rows_affected > 0)
+        })
+        .await
+    }
+
+    /// Invites the user to join the organization with the given role,
+    /// replacing any invitation they already have to it.
+    pub async fn invite_organization_member(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+        role: OrganizationRole,
+        invited_by_user_id: UserId,
+    ) -> Result<organization_invitation::Model> {
+        self.transaction(|tx| async move {
+            organization_invitation::Entity::delete_many()
+                .filter(
+                    organization_invitation::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_invitation::Column::UserId.eq(user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(
+                organization_invitation::Entity::insert(organization_invitation::ActiveModel {
+                    organization_id: ActiveValue::set(organization_id),
+                    user_id: ActiveValue::set(user_id),
+                    role: ActiveValue::set(role),
+                    invited_by_user_id: ActiveValue::set(invited_by_user_id),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            )
+        })
+        .await
+    }
+
+    /// Returns the pending invitations to the organization, oldest first.
+    pub async fn get_organization_invitations(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<Vec<organization_invitation::Model>> {
+        self.transaction(|tx| async move {
+            Ok(organization_invitation::Entity::find()
+                .filter(organization_invitation::Column::OrganizationId.eq(organization_id))
+                .order_by_asc(organization_invitation::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Withdraws the user's invitation to the organization, returning
+    /// whether they had one.
+    pub async fn delete_organization_invitation(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let result = organization_invitation::Entity::delete_many()
+                .filter(
+                    organization_invitation::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_invitation::Column::UserId.eq(user_id)),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(result.rows_affected > 0)
+        })
+        .await
+    }
+
+    /// Makes the user a member of the organization, with the role they were
+    /// invited with, returning `None` if they weren't invited to it.
+    pub async fn accept_organization_invitation(
+        &self,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<Option<organization_member::Model>> {
+        self.transaction(|tx| async move {
+            let Some(invitation) = organization_invitation::Entity::find()
+                .filter(
+                    organization_invitation::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_invitation::Column::UserId.eq(user_id)),
+                )
+                .one(&*tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+            organization_invitation::Entity::delete_by_id(invitation.id)
+                .exec(&*tx)
+                .await?;
+
+            // Accepting never takes away the role of an existing member.
+            let existing_member = organization_member::Entity::find()
+                .filter(
+                    organization_member::Column::OrganizationId
+                        .eq(organization_id)
+                        .and(organization_member::Column::UserId.eq(user_id)),
+                )
+                .one(&*tx)
+                .await?;
+            if let Some(member) = existing_member {
+                return Ok(Some(member));
+            }
+
+            Ok(Some(
+                organization_member::Entity::insert(organization_member::ActiveModel {
+                    organization_id: ActiveValue::set(organization_id),
+                    user_id: ActiveValue::set(user_id),
+                    role: ActiveValue::set(invitation.role),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            ))
+        })
+        .await
+    }
+
+    /// Returns the active subscriptions that pay for the seats of the
+    /// organizations the user is a member of.
+    pub async fn get_organization_billing_subscriptions_for_member(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<billing_subscription::Model>> {
+        self.transaction(|tx| async move {
+            let billing_subscription_ids = organization::Entity::find()
+                .inner_join(organization_member::Entity)
+                .filter(
+                    organization_member::Column::UserId
+                        .eq(user_id)
+                        .and(organization::Column::BillingSubscriptionId.is_not_null()),
+                )
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .filter_map(|organization| organization.billing_subscription_id)
+                .collect::<Vec<_>>();
+            if billing_subscription_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let subscriptions = billing_subscription::Entity::find()
+                .filter(billing_subscription::Column::Id.is_in(billing_subscription_ids))
+                .filter(
+                    super::billing_subscriptions::active_billing_subscription_condition(
+                        OffsetDateTime::now_utc(),
+                    ),
+                )
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+            Ok(subscriptions
+                .into_iter()
+                .map(|subscription| self.read_renamed_billing_subscription_columns(subscription))
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the organization the user's language model usage is
+    /// attributed to: the one whose subscription pays for their seat, or the
+    /// first one they joined when none does.
+    pub(super) async fn get_usage_organization_id(
+        &self,
+        user_id: UserId,
+        tx: &DatabaseTransaction,
+    ) -> Result<Option<OrganizationId>> {
+        let organizations = organization::Entity::find()
+            .inner_join(organization_member::Entity)
+            .filter(organization_member::Column::UserId.eq(user_id))
+            .order_by_asc(organization_member::Column::Id)
+            .all(tx)
+            .await?;
+        let billing_subscription_ids = organizations
+            .iter()
+            .filter_map(|organization| organization.billing_subscription_id)
+            .collect::<Vec<_>>();
+        if !billing_subscription_ids.is_empty() {
+            let paying_subscription = billing_subscription::Entity::find()
+                .filter(billing_subscription::Column::Id.is_in(billing_subscription_ids))
+                .filter(
+                    super::billing_subscriptions::active_billing_subscription_condition(
+                        OffsetDateTime::now_utc(),
+                    ),
+                )
+                .order_by_asc(billing_subscription::Column::Id)
+                .one(tx)
+                .await?;
+            if let Some(subscription) = paying_subscription {
+                return Ok(organizations
+                    .iter()
+                    .find(|organization| {
+                        organization.billing_subscription_id == Some(subscription.id)
+                    })
+                    .map(|organization| organization.id));
+            }
+        }
+        Ok(organizations.first().map(|organization| organization.id))
+    }
+}
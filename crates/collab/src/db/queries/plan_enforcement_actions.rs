@@ -0,0 +1,173 @@
+use crate::db::plan_enforcement_action::PlanEnforcementActionKind;
+
+use super::*;
+
+impl Database {
+    /// Schedules the action to be enforced against the user at the given time,
+    /// unless it's already pending, in which case the pending action is
+    /// returned as is.
+    pub async fn schedule_plan_enforcement_action(
+        &self,
+        user_id: UserId,
+        action: PlanEnforcementActionKind,
+        scheduled_for: PrimitiveDateTime,
+    ) -> Result<plan_enforcement_action::Model> {
+        self.transaction(|tx| async move {
+            let pending_action = plan_enforcement_action::Entity::find()
+                .filter(
+                    plan_enforcement_action::Column::UserId
+                        .eq(user_id)
+                        .and(plan_enforcement_action::Column::Action.eq(action))
+                        .and(plan_enforcement_action::Column::EnforcedAt.is_null())
+                        .and(plan_enforcement_action::Column::CanceledAt.is_null()),
+                )
+                .one(&*tx)
+                .await?;
+            if let Some(pending_action) = pending_action {
+                return Ok(pending_action);
+            }
+
+            Ok(
+                plan_enforcement_action::Entity::insert(plan_enforcement_action::ActiveModel {
+                    user_id: ActiveValue::set(user_id),
+                    action: ActiveValue::set(action),
+                    scheduled_for: ActiveValue::set(scheduled_for),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            )
+        })
+        .await
+    }
+
+    /// Returns the user's actions that are pending or in force, oldest first.
+    pub async fn get_plan_enforcement_actions(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<plan_enforcement_action::Model>> {
+        self.transaction(|tx| async move {
+            Ok(plan_enforcement_action::Entity::find()
+                .filter(
+                    plan_enforcement_action::Column::UserId
+                        .eq(user_id)
+                        .and(plan_enforcement_action::Column::CanceledAt.is_null())
+                        .and(plan_enforcement_action::Column::RevertedAt.is_null()),
+                )
+                .order_by_asc(plan_enforcement_action::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the pending actions scheduled for before the given time that
+    /// the user hasn't been notified of yet.
+    pub async fn get_plan_enforcement_actions_to_notify(
+        &self,
+        scheduled_before: PrimitiveDateTime,
+    ) -> Result<Vec<plan_enforcement_action::Model>> {
+        self.transaction(|tx| async move {
+            Ok(plan_enforcement_action::Entity::find()
+                .filter(
+                    plan_enforcement_action::Column::ScheduledFor
+                        .lte(scheduled_before)
+                        .and(plan_enforcement_action::Column::NotifiedAt.is_null())
+                        .and(plan_enforcement_action::Column::EnforcedAt.is_null())
+                        .and(plan_enforcement_action::Column::CanceledAt.is_null()),
+                )
+                .order_by_asc(plan_enforcement_action::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the pending actions that are due to be enforced.
+    pub async fn get_due_plan_enforcement_actions(
+        &self,
+        now: PrimitiveDateTime,
+    ) -> Result<Vec<plan_enforcement_action::Model>> {
+        self.transaction(|tx| async move {
+            Ok(plan_enforcement_action::Entity::find()
+                .filter(
+                    plan_enforcement_action::Column::ScheduledFor
+                        .lte(now)
+                        .and(plan_enforcement_action::Column::EnforcedAt.is_null())
+                        .and(plan_enforcement_action::Column::CanceledAt.is_null()),
+                )
+                .order_by_asc(plan_enforcement_action::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    pub async fn mark_plan_enforcement_action_notified(
+        &self,
+        id: PlanEnforcementActionId,
+        now: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.update_plan_enforcement_action(plan_enforcement_action::ActiveModel {
+            id: ActiveValue::unchanged(id),
+            notified_at: ActiveValue::set(Some(now)),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn mark_plan_enforcement_action_enforced(
+        &self,
+        id: PlanEnforcementActionId,
+        now: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.update_plan_enforcement_action(plan_enforcement_action::ActiveModel {
+            id: ActiveValue::unchanged(id),
+            enforced_at: ActiveValue::set(Some(now)),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn mark_plan_enforcement_action_canceled(
+        &self,
+        id: PlanEnforcementActionId,
+        now: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.update_plan_enforcement_action(plan_enforcement_action::ActiveModel {
+            id: ActiveValue::unchanged(id),
+            canceled_at: ActiveValue::set(Some(now)),
+            ..Default::default()
+        })
+        .await
+    }
+
+    pub async fn mark_plan_enforcement_action_reverted(
+        &self,
+        id: PlanEnforcementActionId,
+        now: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.update_plan_enforcement_action(plan_enforcement_action::ActiveModel {
+            id: ActiveValue::unchanged(id),
+            reverted_at: ActiveValue::set(Some(now)),
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn update_plan_enforcement_action(
+        &self,
+        action: plan_enforcement_action::ActiveModel,
+    ) -> Result<()> {
+        self.transaction(|tx| {
+            let action = action.clone();
+            async move {
+                plan_enforcement_action::Entity::update(action)
+                    .exec(&*tx)
+                    .await?;
+                Ok(())
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,284 @@
+use rand::distributions::{Alphanumeric, DistString};
+use time::OffsetDateTime;
+
+use super::*;
+
+const REFERRAL_CODE_LENGTH: usize = 10;
+
+/// How many times to try creating a referral code before giving up. Creating
+/// one can fail if the random code is already taken.
+const MAX_REFERRAL_CODE_ATTEMPTS: usize = 3;
+
+impl Database {
+    /// Returns the referral code for the user with the specified ID, creating
+    /// one if the user doesn't have one yet.
+    pub async fn get_or_create_referral_code(
+        &self,
+        user_id: UserId,
+    ) -> Result<referral_code::Model> {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .transaction(|tx| async move {
+                    if let Some(referral_code) = referral_code::Entity::find()
+                        .filter(referral_code::Column::UserId.eq(user_id))
+                        .one(&*tx)
+                        .await?
+                    {
+                        return Ok(referral_code);
+                    }
+
+                    let code =
+                        Alphanumeric.sample_string(&mut rand::thread_rng(), REFERRAL_CODE_LENGTH);
+                    let referral_code = referral_code::Entity::insert(referral_code::ActiveModel {
+                        user_id: ActiveValue::set(user_id),
+                        code: ActiveValue::set(code),
+                        ..Default::default()
+                    })
+                    .exec_with_returning(&*tx)
+                    .await?;
+
+                    Ok(referral_code)
+                })
+                .await;
+
+            match result {
+                Ok(referral_code) => return Ok(referral_code),
+                Err(error) => {
+                    // Another request may have created the user's code
+                    // concurrently, in which case that's the one to return.
+                    if let Some(referral_code) = self.get_referral_code_for_user(user_id).await? {
+                        return Ok(referral_code);
+                    }
+                    if attempt == MAX_REFERRAL_CODE_ATTEMPTS {
+                        return Err(error);
+                    }
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn get_referral_code_for_user(
+        &self,
+        user_id: UserId,
+    ) -> Result<Option<referral_code::Model>> {
+        self.transaction(|tx| async move {
+            Ok(referral_code::Entity::find()
+                .filter(referral_code::Column::UserId.eq(user_id))
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the referral code with the given code.
+    pub async fn get_referral_code_by_code(
+        &self,
+        code: &str,
+    ) -> Result<Option<referral_code::Model>> {
+        self.transaction(|tx| async move {
+            Ok(referral_code::Entity::find()
+                .filter(referral_code::Column::Code.eq(code))
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Records that the referred user was referred by the referrer.
+    pub async fn create_referral(
+        &self,
+        referrer_user_id: UserId,
+        referred_user_id: UserId,
+    ) -> Result<referral::Model> {
+        self.transaction(|tx| async move {
+            let referral = referral::Entity::insert(referral::ActiveModel {
+                referrer_user_id: ActiveValue::set(referrer_user_id),
+                referred_user_id: ActiveValue::set(referred_user_id),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+
+            Ok(referral)
+        })
+        .await
+    }
+
+    /// Returns the referral through which the specified user was referred, if any.
+    pub async fn get_referral_for_referred_user(
+        &self,
+        referred_user_id: UserId,
+    ) -> Result<Option<referral::Model>> {
+        self.transaction(|tx| async move {
+            Ok(referral::Entity::find()
+                .filter(referral::Column::ReferredUserId.eq(referred_user_id))
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the number of referrals made by the specified user since the given time.
+    pub async fn count_referrals_since(
+        &self,
+        referrer_user_id: UserId,
+        since: OffsetDateTime,
+    ) -> Result<u64> {
+        self.transaction(|tx| async move {
+            let since = PrimitiveDateTime::new(since.date(), since.time());
+            Ok(referral::Entity::find()
+                .filter(
+                    referral::Column::ReferrerUserId
+                        .eq(referrer_user_id)
+                        .and(referral::Column::CreatedAt.gte(since)),
+                )
+                .count(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the referrals the given user has been credited for, oldest
+    /// credit first.
+    pub async fn get_credited_referrals_for_referrer(
+        &self,
+        referrer_user_id: UserId,
+    ) -> Result<Vec<referral::Model>> {
+        self.transaction(|tx| async move {
+            Ok(referral::Entity::find()
+                .filter(
+                    referral::Column::ReferrerUserId
+                        .eq(referrer_user_id)
+                        .and(referral::Column::ReferrerCreditedAt.is_not_null()),
+                )
+                .order_by_asc(referral::Column::ReferrerCreditedAt)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Marks the referrer as having been credited for the given referral.
+    ///
+    /// Returns `false` if the referrer was already credited, so that a referral
+    /// is only ever credited once.
+    pub async fn mark_referrer_credited(&self, referral_id: ReferralId) -> Result<bool> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let rows_affected = referral::Entity::update_many()
+                .set(referral::ActiveModel {
+                    referrer_credited_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .filter(
+                    referral::Column::Id
+                        .eq(referral_id)
+                        .and(referral::Column::ReferrerCreditedAt.is_null()),
+                )
+                .exec(&*tx)
+                .await?
+                .rows_affected;
+
+            Ok(rows_affected == 1)
+        })
+        .await
+    }
+
+    /// Records that the referrer is due a credit for the given referral that
+    /// can't be applied until they're a billing customer.
+    pub async fn mark_referrer_credit_pending(&self, referral_id: ReferralId) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            referral::Entity::update_many()
+                .set(referral::ActiveModel {
+                    referrer_credit_pending_since: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .filter(
+                    referral::Column::Id
+                        .eq(referral_id)
+                        .and(referral::Column::ReferrerCreditPendingSince.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records that the referrer won't be credited for the given referral, so
+    /// that it's no longer considered pending.
+    pub async fn mark_referrer_credit_skipped(&self, referral_id: ReferralId) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            referral::Entity::update_many()
+                .set(referral::ActiveModel {
+                    referrer_credit_skipped_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .filter(
+                    referral::Column::Id
+                        .eq(referral_id)
+                        .and(referral::Column::ReferrerCreditedAt.is_null())
+                        .and(referral::Column::ReferrerCreditSkippedAt.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the referrals the given user is due a credit for that's yet to
+    /// be applied, oldest first.
+    pub async fn get_pending_referrer_credits(
+        &self,
+        referrer_user_id: UserId,
+    ) -> Result<Vec<referral::Model>> {
+        self.transaction(|tx| async move {
+            Ok(referral::Entity::find()
+                .filter(
+                    referral::Column::ReferrerUserId
+                        .eq(referrer_user_id)
+                        .and(referral::Column::ReferrerCreditPendingSince.is_not_null())
+                        .and(referral::Column::ReferrerCreditedAt.is_null())
+                        .and(referral::Column::ReferrerCreditSkippedAt.is_null()),
+                )
+                .order_by_asc(referral::Column::ReferrerCreditPendingSince)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Clears the credit marker for the given referral, e.g., when crediting the
+    /// referrer failed and should be retried.
+    pub async fn unmark_referrer_credited(&self, referral_id: ReferralId) -> Result<()> {
+        self.transaction(|tx| async move {
+            referral::Entity::update_many()
+                .set(referral::ActiveModel {
+                    referrer_credited_at: ActiveValue::set(None),
+                    ..Default::default()
+                })
+                .filter(referral::Column::Id.eq(referral_id))
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+}
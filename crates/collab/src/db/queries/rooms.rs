@@ -1192,6 +1192,18 @@ impl Database {
         Ok(room)
     }
 
+    /// Returns the number of participants in the room, including those who
+    /// are being called.
+    pub async fn get_room_participant_count(&self, room_id: RoomId) -> Result<u64> {
+        self.transaction(|tx| async move {
+            Ok(room_participant::Entity::find()
+                .filter(room_participant::Column::RoomId.eq(room_id))
+                .count(&*tx)
+                .await?)
+        })
+        .await
+    }
+
     pub async fn room_connection_ids(
         &self,
         room_id: RoomId,
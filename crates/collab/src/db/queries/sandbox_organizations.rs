@@ -0,0 +1,188 @@
+use crate::db::organization_member::OrganizationRole;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateSandboxOrganizationParams {
+    pub created_by_user_id: UserId,
+    /// How many users to create in the organization, the first of which is
+    /// its admin.
+    pub member_count: usize,
+    pub expires_at: PrimitiveDateTime,
+}
+
+impl Database {
+    /// Creates a sandbox organization along with its users.
+    ///
+    /// The users are given negative GitHub user IDs, so they can't collide
+    /// with real GitHub accounts. The organization's admin is returned first.
+    pub async fn create_sandbox_organization(
+        &self,
+        params: &CreateSandboxOrganizationParams,
+    ) -> Result<(organization::Model, Vec<User>)> {
+        self.transaction(|tx| async move {
+            let token = Uuid::new_v4().simple().to_string()[..12].to_string();
+            let organization = organization::Entity::insert(organization::ActiveModel {
+                name: ActiveValue::set(format!("Sandbox {token}")),
+                slug: ActiveValue::set(format!("sandbox-{token}")),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+
+            let mut users = Vec::with_capacity(params.member_count);
+            for index in 0..params.member_count {
+                let github_login = format!("sandbox-{token}-{index}");
+                let user = user::Entity::insert(user::ActiveModel {
+                    email_address: ActiveValue::set(Some(format!("{github_login}@example.com"))),
+                    github_user_id: ActiveValue::set(Some(
+                        -(organization.id.0 * 100 + index as i32 + 1),
+                    )),
+                    github_login: ActiveValue::set(github_login),
+                    admin: ActiveValue::set(false),
+                    metrics_id: ActiveValue::set(Uuid::new_v4()),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?;
+
+                organization_member::Entity::insert(organization_member::ActiveModel {
+                    organization_id: ActiveValue::set(organization.id),
+                    user_id: ActiveValue::set(user.id),
+                    role: ActiveValue::set(if index == 0 {
+                        OrganizationRole::Admin
+                    } else {
+                        OrganizationRole::Member
+                    }),
+                    ..Default::default()
+                })
+                .exec_without_returning(&*tx)
+                .await?;
+                users.push(user);
+            }
+
+            sandbox_organization::Entity::insert(sandbox_organization::ActiveModel {
+                organization_id: ActiveValue::set(organization.id),
+                created_by_user_id: ActiveValue::set(params.created_by_user_id),
+                expires_at: ActiveValue::set(params.expires_at),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+            sandbox_organization_user::Entity::insert_many(users.iter().map(|user| {
+                sandbox_organization_user::ActiveModel {
+                    organization_id: ActiveValue::set(organization.id),
+                    user_id: ActiveValue::set(user.id),
+                }
+            }))
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok((organization, users))
+        })
+        .await
+    }
+
+    /// Returns the sandbox organization with the given ID, if that
+    /// organization is a sandbox.
+    pub async fn get_sandbox_organization(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<Option<sandbox_organization::Model>> {
+        self.transaction(|tx| async move {
+            Ok(sandbox_organization::Entity::find_by_id(organization_id)
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the IDs of the users provisioned along with the sandbox
+    /// organization.
+    ///
+    /// These may differ from the organization's members, as tests can add
+    /// existing users to the organization, or remove the ones provisioned.
+    pub async fn get_sandbox_organization_user_ids(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<Vec<UserId>> {
+        self.transaction(|tx| async move {
+            Ok(sandbox_organization_user::Entity::find()
+                .filter(sandbox_organization_user::Column::OrganizationId.eq(organization_id))
+                .order_by_asc(sandbox_organization_user::Column::UserId)
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|sandbox_user| sandbox_user.user_id)
+                .collect())
+        })
+        .await
+    }
+
+    /// Returns the sandbox organizations that expired before `now`.
+    pub async fn get_expired_sandbox_organizations(
+        &self,
+        now: PrimitiveDateTime,
+    ) -> Result<Vec<sandbox_organization::Model>> {
+        self.transaction(|tx| async move {
+            Ok(sandbox_organization::Entity::find()
+                .filter(sandbox_organization::Column::ExpiresAt.lt(now))
+                .order_by_asc(sandbox_organization::Column::ExpiresAt)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Deletes a sandbox organization along with the users provisioned with
+    /// it, and everything they own.
+    ///
+    /// Other members of the organization are left alone.
+    ///
+    /// Returns the IDs of the deleted users, or `None` if the organization
+    /// isn't a sandbox.
+    pub async fn destroy_sandbox_organization(
+        &self,
+        organization_id: OrganizationId,
+    ) -> Result<Option<Vec<UserId>>> {
+        self.transaction(|tx| async move {
+            if sandbox_organization::Entity::find_by_id(organization_id)
+                .one(&*tx)
+                .await?
+                .is_none()
+            {
+                return Ok(None);
+            }
+
+            let user_ids = sandbox_organization_user::Entity::find()
+                .filter(sandbox_organization_user::Column::OrganizationId.eq(organization_id))
+                .order_by_asc(sandbox_organization_user::Column::UserId)
+                .all(&*tx)
+                .await?
+                .into_iter()
+                .map(|sandbox_user| sandbox_user.user_id)
+                .collect::<Vec<_>>();
+
+            // These don't cascade when their user is deleted.
+            access_token::Entity::delete_many()
+                .filter(access_token::Column::UserId.is_in(user_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            rate_buckets::Entity::delete_many()
+                .filter(rate_buckets::Column::UserId.is_in(user_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+
+            user::Entity::delete_many()
+                .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            organization::Entity::delete_by_id(organization_id)
+                .exec(&*tx)
+                .await?;
+
+            Ok(Some(user_ids))
+        })
+        .await
+    }
+}
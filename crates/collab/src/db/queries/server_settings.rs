@@ -0,0 +1,130 @@
+use time::OffsetDateTime;
+
+use super::*;
+
+impl Database {
+    /// Returns all of the server settings that are set.
+    pub async fn get_server_settings(&self) -> Result<Vec<server_setting::Model>> {
+        self.transaction(|tx| async move {
+            Ok(server_setting::Entity::find()
+                .order_by_asc(server_setting::Column::Key)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Sets the server setting with the given key to the given JSON value, or
+    /// resets it to its default when the value is `None`, recording the change
+    /// as made by the given admin, or by the server when there's none.
+    pub async fn set_server_setting(
+        &self,
+        key: &str,
+        value: Option<&str>,
+        changed_by_user_id: Option<UserId>,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let previous_value = server_setting::Entity::find_by_id(key.to_string())
+                .one(&*tx)
+                .await?
+                .map(|setting| setting.value);
+
+            if let Some(value) = value {
+                let now = OffsetDateTime::now_utc();
+                server_setting::Entity::insert(server_setting::ActiveModel {
+                    key: ActiveValue::set(key.to_string()),
+                    value: ActiveValue::set(value.to_string()),
+                    updated_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+                })
+                .on_conflict(
+                    OnConflict::column(server_setting::Column::Key)
+                        .update_columns([
+                            server_setting::Column::Value,
+                            server_setting::Column::UpdatedAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+            } else {
+                server_setting::Entity::delete_by_id(key.to_string())
+                    .exec(&*tx)
+                    .await?;
+            }
+
+            server_setting_change::Entity::insert(server_setting_change::ActiveModel {
+                key: ActiveValue::set(key.to_string()),
+                previous_value: ActiveValue::set(previous_value),
+                value: ActiveValue::set(value.map(str::to_string)),
+                changed_by_user_id: ActiveValue::set(changed_by_user_id),
+                ..Default::default()
+            })
+            .exec_without_returning(&*tx)
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the most recent change to the server setting with the given key.
+    pub async fn get_latest_server_setting_change(
+        &self,
+        key: &str,
+    ) -> Result<Option<server_setting_change::Model>> {
+        self.transaction(|tx| async move {
+            Ok(server_setting_change::Entity::find()
+                .filter(server_setting_change::Column::Key.eq(key))
+                .order_by_desc(server_setting_change::Column::Id)
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the most recent changes to server settings, newest first.
+    pub async fn get_server_setting_changes(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<server_setting_change::Model>> {
+        self.transaction(|tx| async move {
+            Ok(server_setting_change::Entity::find()
+                .order_by_desc(server_setting_change::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns up to `limit` of the oldest changes to server settings made
+    /// before the given time.
+    pub async fn get_server_setting_changes_before(
+        &self,
+        before: OffsetDateTime,
+        limit: u64,
+    ) -> Result<Vec<server_setting_change::Model>> {
+        self.transaction(|tx| async move {
+            let before = PrimitiveDateTime::new(before.date(), before.time());
+            Ok(server_setting_change::Entity::find()
+                .filter(server_setting_change::Column::CreatedAt.lt(before))
+                .order_by_asc(server_setting_change::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Deletes the given changes to server settings from the audit log.
+    pub async fn delete_server_setting_changes(&self, ids: &[ServerSettingChangeId]) -> Result<()> {
+        self.transaction(|tx| async move {
+            server_setting_change::Entity::delete_many()
+                .filter(server_setting_change::Column::Id.is_in(ids.iter().copied()))
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
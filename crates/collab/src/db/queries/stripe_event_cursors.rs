@@ -0,0 +1,51 @@
+use time::OffsetDateTime;
+
+use super::*;
+
+impl Database {
+    /// Returns the cursor of the Stripe event poller with the given name, if
+    /// it has processed any events.
+    pub async fn get_stripe_event_cursor(
+        &self,
+        name: &str,
+    ) -> Result<Option<stripe_event_cursor::Model>> {
+        self.transaction(|tx| async move {
+            Ok(stripe_event_cursor::Entity::find_by_id(name.to_string())
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Moves the cursor of the Stripe event poller with the given name past
+    /// the given event.
+    pub async fn update_stripe_event_cursor(
+        &self,
+        name: &str,
+        last_event_id: &str,
+        last_event_created_at: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            stripe_event_cursor::Entity::insert(stripe_event_cursor::ActiveModel {
+                name: ActiveValue::set(name.to_string()),
+                last_event_id: ActiveValue::set(last_event_id.to_string()),
+                last_event_created_at: ActiveValue::set(last_event_created_at),
+                updated_at: ActiveValue::set(PrimitiveDateTime::new(now.date(), now.time())),
+            })
+            .on_conflict(
+                OnConflict::column(stripe_event_cursor::Column::Name)
+                    .update_columns([
+                        stripe_event_cursor::Column::LastEventId,
+                        stripe_event_cursor::Column::LastEventCreatedAt,
+                        stripe_event_cursor::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,258 @@
+use sea_orm::sea_query::{Func, IntoCondition, Query, SelectStatement};
+use time::OffsetDateTime;
+
+use super::*;
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::trial_abuse_flag::{TrialAbuseDecision, TrialAbuseReason};
+
+/// A free trial or complimentary subscription that a user has had.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PastTrial {
+    pub user_id: UserId,
+    pub email_address: Option<String>,
+    pub started_at: PrimitiveDateTime,
+}
+
+impl Database {
+    /// Flags the user as suspected of abusing trials.
+    pub async fn create_trial_abuse_flag(
+        &self,
+        user_id: UserId,
+        reason: TrialAbuseReason,
+        details: &str,
+    ) -> Result<trial_abuse_flag::Model> {
+        self.transaction(|tx| async move {
+            Ok(
+                trial_abuse_flag::Entity::insert(trial_abuse_flag::ActiveModel {
+                    user_id: ActiveValue::set(user_id),
+                    reason: ActiveValue::set(reason),
+                    details: ActiveValue::set(details.to_string()),
+                    ..Default::default()
+                })
+                .exec_with_returning(&*tx)
+                .await?,
+            )
+        })
+        .await
+    }
+
+    /// Returns all of the user's trial abuse flags, including those that have
+    /// been decided on, oldest first.
+    pub async fn get_trial_abuse_flags(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<trial_abuse_flag::Model>> {
+        self.transaction(|tx| async move {
+            Ok(trial_abuse_flag::Entity::find()
+                .filter(trial_abuse_flag::Column::UserId.eq(user_id))
+                .order_by_asc(trial_abuse_flag::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the trial abuse flags that are awaiting review, oldest first.
+    pub async fn get_undecided_trial_abuse_flags(&self) -> Result<Vec<trial_abuse_flag::Model>> {
+        self.transaction(|tx| async move {
+            Ok(trial_abuse_flag::Entity::find()
+                .filter(trial_abuse_flag::Column::DecidedAt.is_null())
+                .order_by_asc(trial_abuse_flag::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Records the decision made on reviewing the given trial abuse flag.
+    ///
+    /// Returns `None` if there's no such flag, or it has already been decided
+    /// on.
+    pub async fn decide_trial_abuse_flag(
+        &self,
+        id: TrialAbuseFlagId,
+        decision: TrialAbuseDecision,
+        decision_note: Option<&str>,
+        decided_by_user_id: UserId,
+    ) -> Result<Option<trial_abuse_flag::Model>> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let result = trial_abuse_flag::Entity::update_many()
+                .set(trial_abuse_flag::ActiveModel {
+                    decision: ActiveValue::set(Some(decision)),
+                    decision_note: ActiveValue::set(decision_note.map(str::to_string)),
+                    decided_by_user_id: ActiveValue::set(Some(decided_by_user_id)),
+                    decided_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .filter(
+                    trial_abuse_flag::Column::Id
+                        .eq(id)
+                        .and(trial_abuse_flag::Column::DecidedAt.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                return Ok(None);
+            }
+            let flag = trial_abuse_flag::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("trial abuse flag {id} not found"))?;
+
+            self.insert_billing_audit_log_entry(
+                &CreateBillingAuditLogEntryParams {
+                    user_id: flag.user_id,
+                    actor_user_id: Some(decided_by_user_id),
+                    event: BillingAuditEvent::TrialAbuseFlagDecided,
+                    billing_subscription_id: None,
+                    previous_state: None,
+                    state: Some(
+                        serde_json::json!({
+                            "trial_abuse_flag_id": flag.id,
+                            "reason": flag.reason,
+                            "decision": decision,
+                            "decision_note": decision_note,
+                        })
+                        .to_string(),
+                    ),
+                },
+                &*tx,
+            )
+            .await?;
+
+            Ok(Some(flag))
+        })
+        .await
+    }
+
+    /// Returns the trials and complimentary subscriptions of the users with
+    /// email addresses at the given domain.
+    pub async fn get_past_trials_by_email_domain(&self, domain: &str) -> Result<Vec<PastTrial>> {
+        // Addresses are matched by their domain with `LIKE`, which only
+        // treats `%` and `_` specially, neither of which is valid in a domain.
+        if domain.contains(['%', '_']) {
+            return Ok(Vec::new());
+        }
+        let pattern = format!("%@{}", domain.to_lowercase());
+        self.transaction(|tx| async move {
+            self.get_past_trials_of_users(
+                Expr::expr(Func::lower(Expr::col(user::Column::EmailAddress)))
+                    .like(pattern.as_str())
+                    .into_condition(),
+                &*tx,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Returns the trials and complimentary subscriptions of the users whose
+    /// billing customers have the given payment method fingerprint.
+    pub async fn get_past_trials_by_payment_method_fingerprint(
+        &self,
+        fingerprint: &str,
+    ) -> Result<Vec<PastTrial>> {
+        self.transaction(|tx| async move {
+            self.get_past_trials_of_users(
+                user::Column::Id
+                    .in_subquery(
+                        Query::select()
+                            .column(billing_customer::Column::UserId)
+                            .from(billing_customer::Entity)
+                            .and_where(
+                                billing_customer::Column::PaymentMethodFingerprint.eq(fingerprint),
+                            )
+                            .to_owned(),
+                    )
+                    .into_condition(),
+                &*tx,
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn get_past_trials_of_users(
+        &self,
+        users: Condition,
+        tx: &DatabaseTransaction,
+    ) -> Result<Vec<PastTrial>> {
+        // Only users that have had a trial or complimentary subscription are
+        // loaded, as the others can be numerous for common email domains.
+        let users = user::Entity::find()
+            .filter(users)
+            .filter(
+                Condition::any()
+                    .add(user::Column::Id.in_subquery(Self::trial_user_ids_query()))
+                    .add(
+                        user::Column::Id.in_subquery(
+                            Query::select()
+                                .column(complimentary_subscription::Column::UserId)
+                                .from(complimentary_subscription::Entity)
+                                .to_owned(),
+                        ),
+                    ),
+            )
+            .all(tx)
+            .await?;
+        let email_addresses = users
+            .into_iter()
+            .map(|user| (user.id, user.email_address))
+            .collect::<HashMap<_, _>>();
+        let user_ids = email_addresses.keys().copied().collect::<Vec<_>>();
+
+        let trials = billing_subscription::Entity::find()
+            .find_also_related(billing_customer::Entity)
+            .filter(billing_customer::Column::UserId.is_in(user_ids.iter().copied()))
+            .filter(billing_subscription::Column::TrialEnd.is_not_null())
+            .all(tx)
+            .await?
+            .into_iter()
+            .filter_map(|(subscription, customer)| {
+                Some((customer?.user_id, subscription.created_at))
+            });
+        let grants = complimentary_subscription::Entity::find()
+            .filter(complimentary_subscription::Column::UserId.is_in(user_ids))
+            .all(tx)
+            .await?
+            .into_iter()
+            .map(|grant| (grant.user_id, grant.created_at));
+
+        let mut past_trials = trials
+            .chain(grants)
+            .map(|(user_id, started_at)| PastTrial {
+                user_id,
+                email_address: email_addresses.get(&user_id).cloned().flatten(),
+                started_at,
+            })
+            .collect::<Vec<_>>();
+        past_trials.sort_by_key(|trial| (trial.started_at, trial.user_id));
+        Ok(past_trials)
+    }
+
+    fn trial_user_ids_query() -> SelectStatement {
+        Query::select()
+            .column((billing_customer::Entity, billing_customer::Column::UserId))
+            .from(billing_customer::Entity)
+            .inner_join(
+                billing_subscription::Entity,
+                Expr::col((
+                    billing_subscription::Entity,
+                    billing_subscription::Column::BillingCustomerId,
+                ))
+                .equals((billing_customer::Entity, billing_customer::Column::Id)),
+            )
+            .and_where(
+                Expr::col((
+                    billing_subscription::Entity,
+                    billing_subscription::Column::TrialEnd,
+                ))
+                .is_not_null(),
+            )
+            .to_owned()
+    }
+}
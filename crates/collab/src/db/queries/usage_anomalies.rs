@@ -0,0 +1,102 @@
+use time::OffsetDateTime;
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+
+use super::*;
+
+impl Database {
+    /// Records a usage anomaly for the given user, along with an entry in the
+    /// billing audit log.
+    pub async fn create_usage_anomaly(
+        &self,
+        user_id: UserId,
+        window_tokens: i64,
+        baseline_tokens: i64,
+    ) -> Result<usage_anomaly::Model> {
+        self.transaction(|tx| async move {
+            let anomaly = usage_anomaly::Entity::insert(usage_anomaly::ActiveModel {
+                user_id: ActiveValue::set(user_id),
+                window_tokens: ActiveValue::set(window_tokens),
+                baseline_tokens: ActiveValue::set(baseline_tokens),
+                ..Default::default()
+            })
+            .exec_with_returning(&*tx)
+            .await?;
+
+            self.insert_billing_audit_log_entry(
+                &CreateBillingAuditLogEntryParams {
+                    user_id,
+                    actor_user_id: None,
+                    event: BillingAuditEvent::UsageAnomalyDetected,
+                    billing_subscription_id: None,
+                    previous_state: None,
+                    state: Some(
+                        serde_json::json!({
+                            "usage_anomaly_id": anomaly.id,
+                            "window_tokens": window_tokens,
+                            "baseline_tokens": baseline_tokens,
+                        })
+                        .to_string(),
+                    ),
+                },
+                &*tx,
+            )
+            .await?;
+
+            Ok(anomaly)
+        })
+        .await
+    }
+
+    /// Returns all of the usage anomalies that have not been reviewed yet.
+    pub async fn get_unreviewed_usage_anomalies(&self) -> Result<Vec<usage_anomaly::Model>> {
+        self.transaction(|tx| async move {
+            Ok(usage_anomaly::Entity::find()
+                .filter(usage_anomaly::Column::ReviewedAt.is_null())
+                .order_by_asc(usage_anomaly::Column::Id)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns whether the user has any usage anomalies that have not been reviewed yet.
+    pub async fn has_unreviewed_usage_anomaly(&self, user_id: UserId) -> Result<bool> {
+        self.transaction(|tx| async move {
+            Ok(usage_anomaly::Entity::find()
+                .filter(
+                    usage_anomaly::Column::UserId
+                        .eq(user_id)
+                        .and(usage_anomaly::Column::ReviewedAt.is_null()),
+                )
+                .one(&*tx)
+                .await?
+                .is_some())
+        })
+        .await
+    }
+
+    /// Marks the given usage anomaly as reviewed.
+    pub async fn mark_usage_anomaly_reviewed(&self, id: UsageAnomalyId) -> Result<()> {
+        self.transaction(|tx| async move {
+            let now = OffsetDateTime::now_utc();
+            let result = usage_anomaly::Entity::update_many()
+                .set(usage_anomaly::ActiveModel {
+                    reviewed_at: ActiveValue::set(Some(PrimitiveDateTime::new(
+                        now.date(),
+                        now.time(),
+                    ))),
+                    ..Default::default()
+                })
+                .filter(usage_anomaly::Column::Id.eq(id))
+                .exec(&*tx)
+                .await?;
+            if result.rows_affected == 0 {
+                Err(anyhow!("usage anomaly {id} not found"))?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,211 @@
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct CreateUsageRecordParams {
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub period_start: PrimitiveDateTime,
+    pub period_end: PrimitiveDateTime,
+    pub quantity: i64,
+}
+
+impl Database {
+    /// Returns the active billing subscriptions, along with their customers,
+    /// whose usage is reported to Stripe. Only subscriptions on our own Stripe
+    /// account are metered, rather than those on resellers' accounts.
+    ///
+    /// A customer's usage is only reported to one subscription, their oldest
+    /// active one, so that customers with several aren't billed for it more
+    /// than once.
+    pub async fn get_metered_billing_subscriptions(
+        &self,
+    ) -> Result<Vec<(billing_subscription::Model, billing_customer::Model)>> {
+        self.transaction(|tx| async move {
+            let subscriptions = billing_subscription::Entity::find()
+                .find_also_related(billing_customer::Entity)
+                .filter(
+                    billing_subscription::Column::StripeSubscriptionStatus
+                        .eq(StripeSubscriptionStatus::Active),
+                )
+                .filter(billing_subscription::Column::StripeAccountId.is_null())
+                .order_by_asc(billing_subscription::Column::Id)
+                .all(&*tx)
+                .await?;
+
+            let mut customer_ids = HashSet::default();
+            Ok(subscriptions
+                .into_iter()
+                .filter_map(|(subscription, customer)| {
+                    let customer = customer?;
+                    customer_ids.insert(customer.id).then(|| {
+                        (
+                            self.read_renamed_billing_subscription_columns(subscription),
+                            customer,
+                        )
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Records the usage of a subscription within a period.
+    ///
+    /// Returns `None` if usage was already recorded for the period, in which
+    /// case the existing record is left untouched.
+    pub async fn create_usage_record(
+        &self,
+        params: &CreateUsageRecordParams,
+    ) -> Result<Option<usage_record::Model>> {
+        self.transaction(|tx| async move {
+            let inserted_count = usage_record::Entity::insert(usage_record::ActiveModel {
+                billing_subscription_id: ActiveValue::set(params.billing_subscription_id),
+                period_start: ActiveValue::set(params.period_start),
+                period_end: ActiveValue::set(params.period_end),
+                quantity: ActiveValue::set(params.quantity),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    usage_record::Column::BillingSubscriptionId,
+                    usage_record::Column::PeriodStart,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(&*tx)
+            .await?;
+            if inserted_count == 0 {
+                return Ok(None);
+            }
+
+            Ok(usage_record::Entity::find()
+                .filter(
+                    usage_record::Column::BillingSubscriptionId
+                        .eq(params.billing_subscription_id)
+                        .and(usage_record::Column::PeriodStart.eq(params.period_start)),
+                )
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the most recent usage record of the given subscription.
+    pub async fn get_latest_usage_record(
+        &self,
+        billing_subscription_id: BillingSubscriptionId,
+    ) -> Result<Option<usage_record::Model>> {
+        self.transaction(|tx| async move {
+            Ok(usage_record::Entity::find()
+                .filter(usage_record::Column::BillingSubscriptionId.eq(billing_subscription_id))
+                .order_by_desc(usage_record::Column::PeriodStart)
+                .one(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Returns the usage records that haven't been reported yet and are due to
+    /// be, oldest first.
+    pub async fn get_usage_records_to_report(
+        &self,
+        now: PrimitiveDateTime,
+        limit: u64,
+    ) -> Result<Vec<usage_record::Model>> {
+        self.transaction(|tx| async move {
+            Ok(usage_record::Entity::find()
+                .filter(
+                    usage_record::Column::ReportedAt.is_null().and(
+                        usage_record::Column::NextAttemptAt
+                            .is_null()
+                            .or(usage_record::Column::NextAttemptAt.lte(now)),
+                    ),
+                )
+                .order_by_asc(usage_record::Column::Id)
+                .limit(limit)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Sets the time the given usage record is reported to Stripe at, unless
+    /// it was already set by an earlier attempt to report it.
+    pub async fn set_usage_record_stripe_timestamp(
+        &self,
+        id: UsageRecordId,
+        stripe_timestamp: PrimitiveDateTime,
+    ) -> Result<usage_record::Model> {
+        self.transaction(|tx| async move {
+            usage_record::Entity::update_many()
+                .set(usage_record::ActiveModel {
+                    stripe_timestamp: ActiveValue::set(Some(stripe_timestamp)),
+                    ..Default::default()
+                })
+                .filter(
+                    usage_record::Column::Id
+                        .eq(id)
+                        .and(usage_record::Column::StripeTimestamp.is_null()),
+                )
+                .exec(&*tx)
+                .await?;
+            Ok(usage_record::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("usage record {id} not found"))?)
+        })
+        .await
+    }
+
+    /// Marks the given usage record as reported to Stripe.
+    pub async fn mark_usage_record_reported(
+        &self,
+        id: UsageRecordId,
+        stripe_usage_record_id: Option<&str>,
+        reported_at: PrimitiveDateTime,
+    ) -> Result<()> {
+        self.transaction(|tx| async move {
+            usage_record::Entity::update(usage_record::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                stripe_usage_record_id: ActiveValue::set(
+                    stripe_usage_record_id.map(str::to_string),
+                ),
+                reported_at: ActiveValue::set(Some(reported_at)),
+                next_attempt_at: ActiveValue::set(None),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a failed attempt to report the given usage record, to be
+    /// retried at `next_attempt_at`.
+    pub async fn record_usage_record_failure(
+        &self,
+        id: UsageRecordId,
+        error: &str,
+        next_attempt_at: PrimitiveDateTime,
+    ) -> Result<usage_record::Model> {
+        self.transaction(|tx| async move {
+            let record = usage_record::Entity::find_by_id(id)
+                .one(&*tx)
+                .await?
+                .ok_or_else(|| anyhow!("usage record {id} not found"))?;
+            Ok(usage_record::Entity::update(usage_record::ActiveModel {
+                id: ActiveValue::unchanged(id),
+                attempt_count: ActiveValue::set(record.attempt_count + 1),
+                next_attempt_at: ActiveValue::set(Some(next_attempt_at)),
+                last_error: ActiveValue::set(Some(error.to_string())),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,70 @@
+use super::*;
+
+impl Database {
+    /// Returns all of the secrets the user has synced.
+    pub async fn get_user_secrets(&self, user_id: UserId) -> Result<Vec<user_secret::Model>> {
+        self.transaction(|tx| async move {
+            Ok(user_secret::Entity::find()
+                .filter(user_secret::Column::UserId.eq(user_id))
+                .order_by_asc(user_secret::Column::Name)
+                .all(&*tx)
+                .await?)
+        })
+        .await
+    }
+
+    /// Stores the given secret for the user, replacing any existing secret with
+    /// the same name.
+    pub async fn upsert_user_secret(
+        &self,
+        user_id: UserId,
+        name: &str,
+        ciphertext: Vec<u8>,
+    ) -> Result<()> {
+        self.transaction(|tx| {
+            let ciphertext = ciphertext.clone();
+            async move {
+                let now = OffsetDateTime::now_utc();
+                let now = PrimitiveDateTime::new(now.date(), now.time());
+                user_secret::Entity::insert(user_secret::ActiveModel {
+                    user_id: ActiveValue::set(user_id),
+                    name: ActiveValue::set(name.to_string()),
+                    ciphertext: ActiveValue::set(ciphertext),
+                    created_at: ActiveValue::set(now),
+                    updated_at: ActiveValue::set(now),
+                    ..Default::default()
+                })
+                .on_conflict(
+                    OnConflict::columns([user_secret::Column::UserId, user_secret::Column::Name])
+                        .update_columns([
+                            user_secret::Column::Ciphertext,
+                            user_secret::Column::UpdatedAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec_without_returning(&*tx)
+                .await?;
+
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// Deletes the user's secret with the given name, if it exists.
+    pub async fn delete_user_secret(&self, user_id: UserId, name: &str) -> Result<()> {
+        self.transaction(|tx| async move {
+            user_secret::Entity::delete_many()
+                .filter(
+                    user_secret::Column::UserId
+                        .eq(user_id)
+                        .and(user_secret::Column::Name.eq(name)),
+                )
+                .exec(&*tx)
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+}
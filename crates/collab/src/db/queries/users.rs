@@ -225,6 +225,22 @@ impl Database {
         .await
     }
 
+    /// Sets the locale the user prefers, or clears it when `None`.
+    pub async fn set_user_locale(&self, id: UserId, locale: Option<&str>) -> Result<()> {
+        self.transaction(|tx| async move {
+            user::Entity::update_many()
+                .filter(user::Column::Id.eq(id))
+                .set(user::ActiveModel {
+                    locale: ActiveValue::set(locale.map(str::to_string)),
+                    ..Default::default()
+                })
+                .exec(&*tx)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
     /// hard delete the user.
     pub async fn destroy_user(&self, id: UserId) -> Result<()> {
         self.transaction(|tx| async move {
@@ -293,6 +309,29 @@ impl Database {
         .await
     }
 
+    /// Returns the ID of the feature flag with the given name, creating the
+    /// flag if it doesn't exist yet.
+    pub async fn get_or_create_user_flag(&self, flag: &str) -> Result<FlagId> {
+        self.transaction(|tx| async move {
+            if let Some(existing) = feature_flag::Entity::find()
+                .filter(feature_flag::Column::Flag.eq(flag))
+                .one(&*tx)
+                .await?
+            {
+                return Ok(existing.id);
+            }
+
+            Ok(feature_flag::Entity::insert(feature_flag::ActiveModel {
+                flag: ActiveValue::set(flag.to_string()),
+                ..Default::default()
+            })
+            .exec(&*tx)
+            .await?
+            .last_insert_id)
+        })
+        .await
+    }
+
     /// Add the given user to the feature flag
     pub async fn add_user_flag(&self, user: UserId, flag: FlagId) -> Result<()> {
         self.transaction(|tx| async move {
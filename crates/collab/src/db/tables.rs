@@ -1,6 +1,12 @@
 pub mod access_token;
+pub mod billing_audit_log_entry;
 pub mod billing_customer;
+pub mod billing_customer_transfer;
+pub mod billing_invoice_event;
+pub mod billing_invoice_line_item;
+pub mod billing_notification;
 pub mod billing_subscription;
+pub mod billing_subscription_transition;
 pub mod buffer;
 pub mod buffer_operation;
 pub mod buffer_snapshot;
@@ -10,30 +16,63 @@ pub mod channel_chat_participant;
 pub mod channel_member;
 pub mod channel_message;
 pub mod channel_message_mention;
+pub mod complimentary_subscription;
+pub mod consent;
 pub mod contact;
 pub mod contributor;
 pub mod dev_server;
 pub mod dev_server_project;
+pub mod distributed_lock_lease;
+pub mod email_delivery;
+pub mod email_suppression;
 pub mod embedding;
 pub mod extension;
 pub mod extension_version;
+pub mod external_entitlement;
 pub mod feature_flag;
 pub mod follower;
 pub mod hosted_project;
+pub mod impersonated_request;
+pub mod impersonation_session;
+pub mod language_model_top_up;
+pub mod language_model_usage;
 pub mod language_server;
+pub mod model_experiment;
+pub mod model_experiment_exposure;
+pub mod model_experiment_variant;
+pub mod monthly_language_model_usage;
 pub mod notification;
 pub mod notification_kind;
 pub mod observed_buffer_edits;
 pub mod observed_channel_messages;
+pub mod organization;
+pub mod organization_invitation;
+pub mod organization_member;
+pub mod organization_member_daily_usage;
+pub mod organization_seat_true_up;
+pub mod organization_secret;
+pub mod organization_secret_lease;
+pub mod plan_enforcement_action;
 pub mod project;
 pub mod project_collaborator;
 pub mod rate_buckets;
+pub mod referral;
+pub mod referral_code;
 pub mod room;
 pub mod room_participant;
+pub mod sandbox_organization;
+pub mod sandbox_organization_user;
 pub mod server;
+pub mod server_setting;
+pub mod server_setting_change;
 pub mod signup;
+pub mod stripe_event_cursor;
+pub mod trial_abuse_flag;
+pub mod usage_anomaly;
+pub mod usage_record;
 pub mod user;
 pub mod user_feature;
+pub mod user_secret;
 pub mod worktree;
 pub mod worktree_diagnostic_summary;
 pub mod worktree_entry;
@@ -0,0 +1,85 @@
+use crate::db::{BillingAuditLogEntryId, BillingSubscriptionId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use time::PrimitiveDateTime;
+
+/// An audit record of a change to a user's billing, kept for support
+/// investigations.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingAuditLogEntryId,
+    /// The user whose billing changed.
+    pub user_id: UserId,
+    /// The user who made the change, or `None` if it was made by the payment
+    /// provider or by us.
+    pub actor_user_id: Option<UserId>,
+    pub event: BillingAuditEvent,
+    /// The billing subscription that changed, if any.
+    pub billing_subscription_id: Option<BillingSubscriptionId>,
+    /// The state before the change, as JSON, or `None` if there was none.
+    pub previous_state: Option<String>,
+    /// The state after the change, as JSON.
+    pub state: Option<String>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// A change to a user's billing.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum BillingAuditEvent {
+    /// A Checkout session was created for the user to subscribe.
+    #[sea_orm(string_value = "checkout_session_created")]
+    CheckoutSessionCreated,
+    /// The user opened the billing portal.
+    #[sea_orm(string_value = "portal_session_created")]
+    PortalSessionCreated,
+    /// A subscription was stored for the first time.
+    #[sea_orm(string_value = "subscription_created")]
+    SubscriptionCreated,
+    /// The status of a subscription changed.
+    #[sea_orm(string_value = "subscription_status_changed")]
+    SubscriptionStatusChanged,
+    /// A subscription changed without its status changing, such as when its
+    /// plan or seats did.
+    #[sea_orm(string_value = "subscription_updated")]
+    SubscriptionUpdated,
+    /// An admin decided whether a flag raised over the user's trials means
+    /// they can't start any more.
+    #[sea_orm(string_value = "trial_abuse_flag_decided")]
+    TrialAbuseFlagDecided,
+    /// An admin transferred the user's billing customer, along with its
+    /// subscriptions, to or from them.
+    #[sea_orm(string_value = "billing_customer_transferred")]
+    BillingCustomerTransferred,
+    /// A Checkout session was created for the user to buy a language model
+    /// top-up.
+    #[sea_orm(string_value = "top_up_checkout_session_created")]
+    TopUpCheckoutSessionCreated,
+    /// The user changed how many seats their subscription has.
+    #[sea_orm(string_value = "subscription_seats_changed")]
+    SubscriptionSeatsChanged,
+    /// The user changed the address their receipts and invoices are sent to.
+    #[sea_orm(string_value = "billing_email_changed")]
+    BillingEmailChanged,
+    /// The user changed or removed their spending cap.
+    #[sea_orm(string_value = "spending_cap_changed")]
+    SpendingCapChanged,
+    /// An admin granted the user a complimentary subscription.
+    #[sea_orm(string_value = "billing_grant_created")]
+    BillingGrantCreated,
+    /// An admin revoked the user's complimentary subscription.
+    #[sea_orm(string_value = "billing_grant_revoked")]
+    BillingGrantRevoked,
+    /// The user's language model usage was flagged as deviating from their
+    /// usual usage, which may get them throttled until it's reviewed.
+    #[sea_orm(string_value = "usage_anomaly_detected")]
+    UsageAnomalyDetected,
+}
@@ -1,5 +1,6 @@
 use crate::db::{BillingCustomerId, UserId};
 use sea_orm::entity::prelude::*;
+use serde::Serialize;
 
 /// A billing customer.
 #[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel)]
@@ -9,6 +10,24 @@ pub struct Model {
     pub id: BillingCustomerId,
     pub user_id: UserId,
     pub stripe_customer_id: String,
+    /// The customer's billing address, encrypted with the [`ColumnCipher`](crate::db::ColumnCipher).
+    pub encrypted_billing_address: Option<String>,
+    /// The customer's tax ID, encrypted with the [`ColumnCipher`](crate::db::ColumnCipher).
+    pub encrypted_tax_id: Option<String>,
+    /// Whether the customer is exempt from tax, as determined in Stripe.
+    pub tax_exempt: TaxExemptStatus,
+    /// The address Stripe sends the customer's receipts and invoices to, when
+    /// it differs from the user's own email address.
+    pub billing_email: Option<String>,
+    /// The connected reseller account the customer was created on, or `None`
+    /// if they're a customer of our own Stripe account.
+    pub stripe_account_id: Option<String>,
+    /// The fingerprint of the card the customer's trial was started with,
+    /// which is the same for every customer that uses that card.
+    pub payment_method_fingerprint: Option<String>,
+    /// The most the customer is willing to spend on metered usage within a
+    /// usage period, or `None` if they haven't capped it.
+    pub spending_cap_in_cents: Option<i64>,
     pub created_at: DateTime,
 }
 
@@ -37,3 +56,25 @@ impl Related<super::billing_subscription::Entity> for Entity {
 }
 
 impl ActiveModelBehavior for ActiveModel {}
+
+/// The tax exemption status of a Stripe customer.
+///
+/// [Stripe docs](https://docs.stripe.com/api/customers/object#customer_object-tax_exempt)
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Default, Hash, Serialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum TaxExemptStatus {
+    /// The customer is taxed.
+    #[default]
+    #[sea_orm(string_value = "none")]
+    None,
+    /// The customer is exempt from tax, and no tax is charged.
+    #[sea_orm(string_value = "exempt")]
+    Exempt,
+    /// The customer accounts for the tax themselves, and invoices are marked
+    /// as subject to reverse charge.
+    #[sea_orm(string_value = "reverse")]
+    Reverse,
+}
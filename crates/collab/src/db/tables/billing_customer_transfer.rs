@@ -0,0 +1,39 @@
+use crate::db::{BillingCustomerId, BillingCustomerTransferId, OrganizationId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An audit record of a billing customer, along with its subscriptions, being
+/// transferred from one user to another by staff.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_customer_transfers")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingCustomerTransferId,
+    pub billing_customer_id: BillingCustomerId,
+    pub from_user_id: UserId,
+    pub to_user_id: UserId,
+    /// The organization the customer was transferred to, when the recipient
+    /// took over its billing as one of its admins.
+    pub organization_id: Option<OrganizationId>,
+    pub transferred_by_user_id: UserId,
+    pub reason: Option<String>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::billing_customer::Entity",
+        from = "Column::BillingCustomerId",
+        to = "super::billing_customer::Column::Id"
+    )]
+    BillingCustomer,
+}
+
+impl Related<super::billing_customer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BillingCustomer.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
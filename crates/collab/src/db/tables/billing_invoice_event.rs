@@ -0,0 +1,60 @@
+use crate::db::{BillingCustomerId, BillingInvoiceEventId};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use time::PrimitiveDateTime;
+
+/// The outcome of an attempt to collect payment for a Stripe invoice.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_invoice_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingInvoiceEventId,
+    pub billing_customer_id: BillingCustomerId,
+    pub stripe_invoice_id: String,
+    /// The subscription the invoice is for, if any.
+    pub stripe_subscription_id: Option<String>,
+    pub kind: BillingInvoiceEventKind,
+    /// Why the invoice was created, such as `subscription_cycle` for renewals.
+    ///
+    /// [Stripe docs](https://docs.stripe.com/api/invoices/object#invoice_object-billing_reason)
+    pub billing_reason: Option<String>,
+    /// The amount paid, or due in the case of a failed payment.
+    pub amount_in_cents: i64,
+    pub currency: String,
+    pub occurred_at: PrimitiveDateTime,
+    pub created_at: PrimitiveDateTime,
+    /// How many units of the currency a US dollar bought when the event
+    /// occurred, or `None` if the rate couldn't be looked up.
+    pub usd_exchange_rate: Option<f64>,
+    /// The amount converted to USD at `usd_exchange_rate`, so that reports
+    /// don't change as exchange rates do.
+    pub amount_in_usd_cents: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::billing_customer::Entity",
+        from = "Column::BillingCustomerId",
+        to = "super::billing_customer::Column::Id"
+    )]
+    BillingCustomer,
+}
+
+impl Related<super::billing_customer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BillingCustomer.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum BillingInvoiceEventKind {
+    #[sea_orm(string_value = "paid")]
+    Paid,
+    #[sea_orm(string_value = "payment_failed")]
+    PaymentFailed,
+}
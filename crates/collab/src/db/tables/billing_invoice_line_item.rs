@@ -0,0 +1,39 @@
+use crate::db::{BillingInvoiceLineItemId, BillingSubscriptionId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A line item for metered usage on a Stripe invoice, and the description we
+/// gave it, so that support can explain any usage line on a customer's bill.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_invoice_line_items")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingInvoiceLineItemId,
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub stripe_invoice_id: String,
+    pub stripe_invoice_line_item_id: String,
+    pub period_start: PrimitiveDateTime,
+    pub period_end: PrimitiveDateTime,
+    /// The number of tokens billed on the line.
+    pub quantity: i64,
+    pub description: String,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::billing_subscription::Entity",
+        from = "Column::BillingSubscriptionId",
+        to = "super::billing_subscription::Column::Id"
+    )]
+    BillingSubscription,
+}
+
+impl Related<super::billing_subscription::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BillingSubscription.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,24 @@
+use crate::db::{BillingNotificationId, EmailDeliveryId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A billing notification sent to a user, recorded so that it's only sent
+/// once.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_notifications")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingNotificationId,
+    pub user_id: UserId,
+    /// Identifies the notification among those sent to the user, such as
+    /// `payment_failed:in_123:2` for the second failed attempt to pay an
+    /// invoice.
+    pub key: String,
+    pub email_delivery_id: EmailDeliveryId,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
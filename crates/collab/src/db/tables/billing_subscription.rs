@@ -1,5 +1,7 @@
 use crate::db::{BillingCustomerId, BillingSubscriptionId};
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
 
 /// A billing subscription.
 #[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel)]
@@ -10,6 +12,44 @@ pub struct Model {
     pub billing_customer_id: BillingCustomerId,
     pub stripe_subscription_id: String,
     pub stripe_subscription_status: StripeSubscriptionStatus,
+    pub plan: SubscriptionPlan,
+    /// The number of seats paid for, which is the quantity of the
+    /// subscription's line item. Only team subscriptions have more than one.
+    pub seat_count: i32,
+    /// The Stripe coupon discounting the subscription, if any.
+    pub stripe_coupon_id: Option<String>,
+    /// The Stripe promotion code the discount was applied with, if any.
+    pub stripe_promotion_code_id: Option<String>,
+    /// When the subscription's free trial ends, if it has one.
+    pub trial_end: Option<PrimitiveDateTime>,
+    /// When the current billing period started.
+    pub current_period_start: Option<PrimitiveDateTime>,
+    /// When the current billing period ends, and the subscription renews.
+    pub current_period_end: Option<PrimitiveDateTime>,
+    /// The new name of `current_period_end`, which is being renamed.
+    ///
+    /// Read `current_period_end` instead, which the database fills in from
+    /// whichever of the two columns reads currently use.
+    pub current_period_ends_at: Option<PrimitiveDateTime>,
+    /// Whether the subscription is canceled at the end of the current period,
+    /// rather than renewed.
+    pub cancel_at_period_end: bool,
+    /// When the subscription was canceled, if it was.
+    pub canceled_at: Option<PrimitiveDateTime>,
+    /// When a past-due subscription stops granting access, unless it's paid
+    /// before then.
+    pub grace_period_ends_at: Option<PrimitiveDateTime>,
+    /// The number of times we've retried collecting payment for an unpaid
+    /// subscription.
+    pub dunning_attempt_count: i32,
+    /// When we next retry collecting payment for an unpaid subscription.
+    pub next_dunning_attempt_at: Option<PrimitiveDateTime>,
+    /// When an unpaid subscription stopped granting access, after we failed
+    /// to collect payment for it.
+    pub suspended_at: Option<PrimitiveDateTime>,
+    /// The connected reseller account the subscription was created on, or
+    /// `None` if it's on our own Stripe account.
+    pub stripe_account_id: Option<String>,
     pub created_at: DateTime,
 }
 
@@ -34,8 +74,11 @@ impl ActiveModelBehavior for ActiveModel {}
 /// The status of a Stripe subscription.
 ///
 /// [Stripe docs](https://docs.stripe.com/api/subscriptions/object#subscription_object-status)
-#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Default, Hash)]
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Default, Hash, Serialize,
+)]
 #[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
 pub enum StripeSubscriptionStatus {
     #[default]
     #[sea_orm(string_value = "incomplete")]
@@ -55,3 +98,39 @@ pub enum StripeSubscriptionStatus {
     #[sea_orm(string_value = "paused")]
     Paused,
 }
+
+/// The plan a billing subscription is for.
+#[derive(
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    Debug,
+    EnumIter,
+    DeriveActiveEnum,
+    Default,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionPlan {
+    #[sea_orm(string_value = "free")]
+    Free,
+    #[default]
+    #[sea_orm(string_value = "pro")]
+    Pro,
+    #[sea_orm(string_value = "team")]
+    Team,
+}
+
+impl SubscriptionPlan {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionPlan::Free => "free",
+            SubscriptionPlan::Pro => "pro",
+            SubscriptionPlan::Team => "team",
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::{BillingSubscriptionId, BillingSubscriptionTransitionId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A change in the status or plan of a billing subscription.
+///
+/// Transitions are append-only, so that the state of a subscription at any
+/// point in the past can be reconstructed.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "billing_subscription_transitions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: BillingSubscriptionTransitionId,
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub stripe_subscription_status: StripeSubscriptionStatus,
+    /// The plan the subscription was for. Only recorded since plan changes
+    /// started being tracked.
+    pub plan: Option<SubscriptionPlan>,
+    pub transitioned_at: PrimitiveDateTime,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::billing_subscription::Entity",
+        from = "Column::BillingSubscriptionId",
+        to = "super::billing_subscription::Column::Id"
+    )]
+    BillingSubscription,
+}
+
+impl Related<super::billing_subscription::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BillingSubscription.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,5 +1,6 @@
 use crate::db::{ChannelId, ChannelVisibility};
 use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "channels")]
@@ -10,6 +11,10 @@ pub struct Model {
     pub visibility: ChannelVisibility,
     pub parent_path: String,
     pub requires_zed_cla: bool,
+    /// When the channel was archived for exceeding the limits of its
+    /// administrator's plan. Archived channels are hidden from their members
+    /// and can't be joined.
+    pub archived_at: Option<PrimitiveDateTime>,
 }
 
 impl Model {
@@ -0,0 +1,50 @@
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::{ComplimentarySubscriptionId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+/// A plan granted to a user by staff, without going through Stripe.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "complimentary_subscriptions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ComplimentarySubscriptionId,
+    pub user_id: UserId,
+    pub plan: SubscriptionPlan,
+    pub kind: ComplimentarySubscriptionKind,
+    pub reason: Option<String>,
+    pub granted_by_user_id: UserId,
+    /// When the grant ends, or `None` if it lasts until it's revoked.
+    pub expires_at: Option<PrimitiveDateTime>,
+    pub revoked_at: Option<PrimitiveDateTime>,
+    pub revoked_by_user_id: Option<UserId>,
+    pub created_at: PrimitiveDateTime,
+}
+
+impl Model {
+    /// Returns whether the grant is in effect at the given time.
+    pub fn is_active_at(&self, now: PrimitiveDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Who a complimentary subscription was granted to.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum ComplimentarySubscriptionKind {
+    #[sea_orm(string_value = "staff")]
+    Staff,
+    #[sea_orm(string_value = "partner")]
+    Partner,
+    #[sea_orm(string_value = "giveaway")]
+    Giveaway,
+}
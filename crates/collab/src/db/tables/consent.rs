@@ -0,0 +1,34 @@
+use crate::db::{ConsentId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A record of a user accepting a version of one of our legal documents.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "consents")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ConsentId,
+    pub user_id: UserId,
+    pub kind: ConsentKind,
+    pub version: String,
+    /// When the user accepted this version.
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// The document a user consented to.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum ConsentKind {
+    #[sea_orm(string_value = "terms_of_service")]
+    TermsOfService,
+    #[sea_orm(string_value = "pricing")]
+    Pricing,
+}
@@ -0,0 +1,21 @@
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A lease on a lock shared between all collab instances, held by one of them
+/// until it's released or expires.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "distributed_lock_leases")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    /// Identifies the holder of the lease, so that only it can renew or
+    /// release it.
+    pub holder: String,
+    /// When the lease expires, after which another instance may take it over.
+    pub expires_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
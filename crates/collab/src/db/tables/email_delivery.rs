@@ -0,0 +1,50 @@
+use crate::db::EmailDeliveryId;
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An email queued for delivery.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "email_deliveries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: EmailDeliveryId,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub status: EmailDeliveryStatus,
+    pub attempt_count: i32,
+    /// When the next delivery attempt should be made, if the email is pending.
+    pub next_attempt_at: PrimitiveDateTime,
+    pub last_error: Option<String>,
+    /// The ID the email provider assigned to the message once it was accepted.
+    pub provider_message_id: Option<String>,
+    pub sent_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// The delivery status of an email.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Default, Hash)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum EmailDeliveryStatus {
+    /// The email is waiting to be (re)sent.
+    #[default]
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    /// The email was accepted by the provider.
+    #[sea_orm(string_value = "sent")]
+    Sent,
+    /// Delivery failed permanently, or ran out of retries.
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    /// The provider reported that the email bounced.
+    #[sea_orm(string_value = "bounced")]
+    Bounced,
+    /// The email was not sent because the recipient is suppressed.
+    #[sea_orm(string_value = "suppressed")]
+    Suppressed,
+}
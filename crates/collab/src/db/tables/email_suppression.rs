@@ -0,0 +1,31 @@
+use crate::db::EmailSuppressionId;
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An email address that we must not send any more emails to.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "email_suppressions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: EmailSuppressionId,
+    pub email_address: String,
+    pub reason: EmailSuppressionReason,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Why an email address was suppressed.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum EmailSuppressionReason {
+    /// An email to the address bounced permanently.
+    #[sea_orm(string_value = "bounce")]
+    Bounce,
+    /// The recipient marked one of our emails as spam.
+    #[sea_orm(string_value = "complaint")]
+    Complaint,
+}
@@ -0,0 +1,46 @@
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::{ExternalEntitlementId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+/// A plan a user is entitled to through something other than Stripe, such
+/// as sponsoring us on GitHub.
+///
+/// Entitlements are kept in sync with their source, which is the source of
+/// truth for whether they're in effect. Ended entitlements are kept, and
+/// resume if the user becomes eligible again.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "external_entitlements")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ExternalEntitlementId,
+    pub user_id: UserId,
+    pub source: ExternalEntitlementSource,
+    /// What the source identifies the entitlement by, such as the GitHub user
+    /// ID of a sponsor.
+    pub external_id: String,
+    pub plan: SubscriptionPlan,
+    /// When the entitlement last came into effect.
+    pub started_at: PrimitiveDateTime,
+    /// When the source stopped granting the entitlement, or `None` while it
+    /// still does.
+    pub ended_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Where an external entitlement comes from.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalEntitlementSource {
+    #[sea_orm(string_value = "github_sponsors")]
+    GithubSponsors,
+}
@@ -0,0 +1,23 @@
+use crate::db::{ImpersonatedRequestId, ImpersonationSessionId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An audit record of a request made on a user's behalf during an
+/// impersonation session.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "impersonated_requests")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ImpersonatedRequestId,
+    pub session_id: ImpersonationSessionId,
+    pub method: String,
+    pub path: String,
+    /// Whether the request was allowed to go through.
+    pub allowed: bool,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,41 @@
+use crate::db::{ImpersonationSessionId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A time-limited grant for a staff member to see the service as a user sees
+/// it, kept as an audit record once it's over.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "impersonation_sessions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ImpersonationSessionId,
+    pub staff_user_id: UserId,
+    pub impersonated_user_id: UserId,
+    /// Why the staff member needs to impersonate the user, such as a support
+    /// ticket.
+    pub reason: String,
+    /// Whether requests that change state are allowed, rather than only ones
+    /// that read it.
+    pub allow_writes: bool,
+    pub expires_at: PrimitiveDateTime,
+    /// When the staff member ended the session, if they did so before it
+    /// expired.
+    pub ended_at: Option<PrimitiveDateTime>,
+    /// The hash of the API token the staff member makes requests with during
+    /// the session. Sessions started before these tokens were issued don't
+    /// have one.
+    pub token_hash: Option<String>,
+    pub created_at: PrimitiveDateTime,
+}
+
+impl Model {
+    /// Returns whether the session can still be used at the given time.
+    pub fn is_active(&self, now: PrimitiveDateTime) -> bool {
+        self.ended_at.is_none() && now < self.expires_at
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
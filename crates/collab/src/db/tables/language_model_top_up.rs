@@ -0,0 +1,26 @@
+use crate::db::{LanguageModelTopUpId, UserId};
+use sea_orm::entity::prelude::*;
+use time::{Date, PrimitiveDateTime};
+
+/// A block of language model tokens bought on top of those included in the
+/// user's plan, for a single usage period.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "language_model_top_ups")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: LanguageModelTopUpId,
+    pub user_id: UserId,
+    /// The Stripe Checkout session the top-up is paid through.
+    pub stripe_checkout_session_id: String,
+    /// The first day of the usage period the tokens can be used in.
+    pub period_start: Date,
+    pub tokens: i64,
+    /// When the payment completed. Top-ups only count once they're fulfilled.
+    pub fulfilled_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
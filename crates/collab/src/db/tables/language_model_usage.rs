@@ -0,0 +1,29 @@
+use crate::db::{LanguageModelUsageId, OrganizationId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// The tokens consumed by a single language model request proxied through collab.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "language_model_usages")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: LanguageModelUsageId,
+    pub user_id: UserId,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    /// The product feature the request originated from, if known.
+    pub feature: Option<String>,
+    /// The cost of the request charged by the upstream provider.
+    pub upstream_cost_in_millicents: i64,
+    /// The organization the usage is attributed to, if the user was a member
+    /// of any when it was recorded.
+    pub organization_id: Option<OrganizationId>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,20 @@
+use crate::db::ModelExperimentId;
+use sea_orm::entity::prelude::*;
+
+/// An experiment that serves different default language models to different
+/// users.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "model_experiments")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ModelExperimentId,
+    pub name: String,
+    /// Whether users are currently being assigned to this experiment.
+    pub is_active: bool,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use crate::db::{ModelExperimentExposureId, ModelExperimentId, ModelExperimentVariantId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// Records the first time a user was served a variant of a model experiment.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "model_experiment_exposures")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ModelExperimentExposureId,
+    pub experiment_id: ModelExperimentId,
+    pub variant_id: ModelExperimentVariantId,
+    pub user_id: UserId,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
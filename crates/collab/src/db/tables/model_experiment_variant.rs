@@ -0,0 +1,23 @@
+use crate::db::{ModelExperimentId, ModelExperimentVariantId};
+use sea_orm::entity::prelude::*;
+
+/// One arm of a model experiment.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "model_experiment_variants")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ModelExperimentVariantId,
+    pub experiment_id: ModelExperimentId,
+    pub name: String,
+    /// The relative share of users assigned to this variant.
+    pub weight: i32,
+    /// The ID of the client-side language model provider, e.g. `zed.dev`.
+    pub provider: String,
+    pub model: String,
+    pub temperature: Option<f32>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,28 @@
+use crate::db::{MonthlyLanguageModelUsageId, UserId};
+use sea_orm::entity::prelude::*;
+use time::Date;
+
+/// The language model usage of a single user with a single model over a
+/// calendar month, rolled up from the individual usages once they're past
+/// their retention window.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "monthly_language_model_usages")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: MonthlyLanguageModelUsageId,
+    /// The first day of the month.
+    pub month: Date,
+    pub user_id: UserId,
+    pub provider: String,
+    pub model: String,
+    pub feature: Option<String>,
+    pub request_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub upstream_cost_in_millicents: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,58 @@
+use crate::db::{BillingSubscriptionId, OrganizationId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+/// A group of users that are billed and administered together.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organizations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationId,
+    pub name: String,
+    pub slug: String,
+    /// When the organization's features were disabled, because none of its
+    /// admins are on a plan that includes them.
+    pub features_disabled_at: Option<DateTime>,
+    /// The last day the usage of the organization's members was rolled up
+    /// for, which may have been partway through that day.
+    pub usage_rolled_up_through: Option<Date>,
+    /// The team subscription that pays for the organization's seats, if any.
+    pub billing_subscription_id: Option<BillingSubscriptionId>,
+    /// What happens when the organization's member count no longer matches
+    /// the seats its subscription pays for.
+    pub seat_policy: SeatPolicy,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::organization_member::Entity")]
+    OrganizationMember,
+}
+
+impl Related<super::organization_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrganizationMember.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// How the seats of an organization's subscription are kept in line with its
+/// member count.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, Default, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum SeatPolicy {
+    /// Discrepancies are recorded for an admin to resolve.
+    #[default]
+    #[sea_orm(string_value = "flag")]
+    Flag,
+    /// The subscription's seats are adjusted to the member count, prorated
+    /// for the rest of the billing period.
+    #[sea_orm(string_value = "auto_adjust")]
+    AutoAdjust,
+}
@@ -0,0 +1,23 @@
+use crate::db::organization_member::OrganizationRole;
+use crate::db::{OrganizationId, OrganizationInvitationId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// An invitation for a user to join an organization, which they become a
+/// member of, with the given role, once they accept it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_invitations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationInvitationId,
+    pub organization_id: OrganizationId,
+    /// The user who is invited.
+    pub user_id: UserId,
+    pub role: OrganizationRole,
+    pub invited_by_user_id: UserId,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,69 @@
+use crate::db::{OrganizationId, OrganizationMemberId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_members")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationMemberId,
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    pub role: OrganizationRole,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// The role of a user within an organization.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+    /// The user who created the organization, who can do everything admins
+    /// can, and manage its admins too.
+    #[sea_orm(string_value = "owner")]
+    Owner,
+    /// Admins can manage the organization and see its members' usage.
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    #[sea_orm(string_value = "member")]
+    Member,
+}
+
+impl OrganizationRole {
+    /// Returns whether users with the role can manage the organization.
+    pub fn can_administer(&self) -> bool {
+        matches!(self, Self::Owner | Self::Admin)
+    }
+
+    /// Returns whether users with the role can add or remove members with
+    /// the other role. Only owners can manage admins, and nobody can manage
+    /// owners.
+    pub fn can_manage(&self, role: OrganizationRole) -> bool {
+        match (self, role) {
+            (_, Self::Owner) => false,
+            (Self::Owner, _) => true,
+            (Self::Admin, Self::Member) => true,
+            _ => false,
+        }
+    }
+}
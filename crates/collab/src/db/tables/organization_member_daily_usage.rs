@@ -0,0 +1,79 @@
+use crate::db::{OrganizationId, OrganizationMemberDailyUsageId, UserId};
+use collections::HashMap;
+use sea_orm::{entity::prelude::*, FromJsonQueryResult};
+use serde::{Deserialize, Serialize};
+use time::{Date, PrimitiveDateTime};
+
+/// The language model usage of an organization member on a single day, rolled
+/// up from the individual usages so that team dashboards can be served
+/// without scanning them.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_member_daily_usages")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationMemberDailyUsageId,
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    pub date: Date,
+    pub request_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    /// The cost of the member's requests charged by the upstream providers.
+    pub estimated_cost_in_millicents: i64,
+    /// The models the member used the most tokens of, most used first.
+    pub top_models: TopModels,
+    pub updated_at: PrimitiveDateTime,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct TopModels(pub Vec<ModelUsage>);
+
+impl TopModels {
+    /// Returns the `count` models with the most tokens used, most used first.
+    pub fn from_tokens_by_model(
+        tokens_by_model: HashMap<(String, String), i64>,
+        count: usize,
+    ) -> Self {
+        let mut models = tokens_by_model
+            .into_iter()
+            .map(|((provider, model), tokens)| ModelUsage {
+                provider,
+                model,
+                tokens,
+            })
+            .collect::<Vec<_>>();
+        models.sort_by(|a, b| {
+            b.tokens
+                .cmp(&a.tokens)
+                .then_with(|| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)))
+        });
+        models.truncate(count);
+        Self(models)
+    }
+}
+
+/// The tokens used of a single model.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub provider: String,
+    pub model: String,
+    pub tokens: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
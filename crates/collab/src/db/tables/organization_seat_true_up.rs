@@ -0,0 +1,38 @@
+use crate::db::{BillingSubscriptionId, OrganizationId, OrganizationSeatTrueUpId};
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+use time::PrimitiveDateTime;
+
+/// A discrepancy found between the members of an organization and the seats
+/// its subscription pays for, and what was done about it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_seat_true_ups")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationSeatTrueUpId,
+    pub organization_id: OrganizationId,
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub member_count: i32,
+    /// The seats the subscription paid for when the discrepancy was found.
+    pub seat_count: i32,
+    pub outcome: SeatTrueUpOutcome,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What was done about a seat discrepancy.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum SeatTrueUpOutcome {
+    /// The subscription's seats were adjusted to the member count.
+    #[sea_orm(string_value = "adjusted")]
+    Adjusted,
+    /// The discrepancy was left for an admin to resolve.
+    #[sea_orm(string_value = "flagged")]
+    Flagged,
+}
@@ -0,0 +1,38 @@
+use crate::db::{OrganizationId, OrganizationSecretId};
+use sea_orm::entity::prelude::*;
+
+/// A secret (such as a language model provider API key) that an organization
+/// shares with its members.
+///
+/// Members never read the secret directly. Instead, their clients lease it for
+/// a short time whenever they need it.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_secrets")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationSecretId,
+    pub organization_id: OrganizationId,
+    pub name: String,
+    /// The secret's value, encrypted with the [`ColumnCipher`](crate::db::ColumnCipher).
+    pub encrypted_value: String,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organization::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organization::Column::Id"
+    )]
+    Organization,
+}
+
+impl Related<super::organization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use crate::db::{OrganizationSecretId, OrganizationSecretLeaseId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// A record of a member leasing one of their organization's secrets.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "organization_secret_leases")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: OrganizationSecretLeaseId,
+    pub organization_secret_id: OrganizationSecretId,
+    pub user_id: UserId,
+    pub expires_at: DateTime,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
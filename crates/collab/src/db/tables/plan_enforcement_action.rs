@@ -0,0 +1,62 @@
+use crate::db::{PlanEnforcementActionId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An action taken against what a user has beyond the limits of their plan,
+/// once their subscription lapses or is downgraded and the grace period is
+/// over.
+///
+/// Actions are canceled before they're enforced, and reverted after, if the
+/// user subscribes to a plan that covers what they have again.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "plan_enforcement_actions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: PlanEnforcementActionId,
+    pub user_id: UserId,
+    pub action: PlanEnforcementActionKind,
+    /// When the action is to be enforced, once the grace period is over.
+    pub scheduled_for: PrimitiveDateTime,
+    /// When the user was told about the upcoming action.
+    pub notified_at: Option<PrimitiveDateTime>,
+    pub enforced_at: Option<PrimitiveDateTime>,
+    pub canceled_at: Option<PrimitiveDateTime>,
+    pub reverted_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+impl Model {
+    /// Returns whether the action is still waiting to be enforced.
+    pub fn is_pending(&self) -> bool {
+        self.enforced_at.is_none() && self.canceled_at.is_none()
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What a plan enforcement action does.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum PlanEnforcementActionKind {
+    /// Archives the private root channels the user administers beyond the
+    /// number their plan allows, newest first.
+    #[sea_orm(string_value = "archive_excess_channels")]
+    ArchiveExcessChannels,
+    /// Disables the features of the organizations the user administers.
+    #[sea_orm(string_value = "disable_organization_features")]
+    DisableOrganizationFeatures,
+}
+
+impl PlanEnforcementActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanEnforcementActionKind::ArchiveExcessChannels => "archive_excess_channels",
+            PlanEnforcementActionKind::DisableOrganizationFeatures => {
+                "disable_organization_features"
+            }
+        }
+    }
+}
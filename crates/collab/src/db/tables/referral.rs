@@ -0,0 +1,28 @@
+use crate::db::{ReferralId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A record of one user referring another.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "referrals")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ReferralId,
+    pub referrer_user_id: UserId,
+    pub referred_user_id: UserId,
+    /// When the referrer was credited for this referral, once the referred
+    /// user started a paid subscription.
+    pub referrer_credited_at: Option<PrimitiveDateTime>,
+    /// When the referrer became due a credit that couldn't be applied yet, as
+    /// they weren't a billing customer. It's applied once they become one.
+    pub referrer_credit_pending_since: Option<PrimitiveDateTime>,
+    /// When the referrer was found to be ineligible for the credit, e.g.,
+    /// because they're billed through a connected Stripe account.
+    pub referrer_credit_skipped_at: Option<PrimitiveDateTime>,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,31 @@
+use crate::db::{ReferralCodeId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// A code that a user can share to refer other users.
+#[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "referral_codes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ReferralCodeId,
+    pub user_id: UserId,
+    pub code: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,22 @@
+use crate::db::{OrganizationId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An organization provisioned, along with its users, for end-to-end tests
+/// against a test-mode Stripe account.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "sandbox_organizations")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub organization_id: OrganizationId,
+    pub created_by_user_id: UserId,
+    /// When the organization is torn down, should the test that provisioned
+    /// it not do so itself.
+    pub expires_at: PrimitiveDateTime,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,18 @@
+use crate::db::{OrganizationId, UserId};
+use sea_orm::entity::prelude::*;
+
+/// A user provisioned along with a sandbox organization, who is deleted when
+/// the organization is torn down.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "sandbox_organization_users")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub organization_id: OrganizationId,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: UserId,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An operational setting that overrides the server's default at runtime.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "server_settings")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    /// The setting's value, as JSON.
+    pub value: String,
+    pub updated_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,25 @@
+use crate::db::{ServerSettingChangeId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// An audit record of a server setting being changed.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "server_setting_changes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: ServerSettingChangeId,
+    pub key: String,
+    /// The value before the change, as JSON, or `None` if the setting wasn't set.
+    pub previous_value: Option<String>,
+    /// The value after the change, as JSON, or `None` if the setting was reset
+    /// to its default.
+    pub value: Option<String>,
+    /// The admin who made the change, or `None` if the server made it.
+    pub changed_by_user_id: Option<UserId>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
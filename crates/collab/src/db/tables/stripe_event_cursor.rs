@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// How far a poller has gotten through the events in Stripe, so it can pick
+/// up where it left off.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "stripe_event_cursors")]
+pub struct Model {
+    /// The name of the poller the cursor belongs to.
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub name: String,
+    /// The ID of the last event that was processed.
+    pub last_event_id: String,
+    /// When the last event that was processed was created in Stripe.
+    pub last_event_created_at: PrimitiveDateTime,
+    pub updated_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,75 @@
+use crate::db::{TrialAbuseFlagId, UserId};
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+/// A user suspected of cycling through free trials or complimentary
+/// subscriptions, such as with several accounts.
+///
+/// Flagged users aren't offered trials while the flag awaits review. Flags
+/// are kept once decided on, as a record of who decided what.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "trial_abuse_flags")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: TrialAbuseFlagId,
+    pub user_id: UserId,
+    pub reason: TrialAbuseReason,
+    /// A description of what the user was flagged for, for reviewers.
+    pub details: String,
+    /// What staff decided on reviewing the flag, or `None` while it awaits
+    /// review.
+    pub decision: Option<TrialAbuseDecision>,
+    pub decision_note: Option<String>,
+    pub decided_by_user_id: Option<UserId>,
+    pub decided_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+impl Model {
+    /// Returns whether the flag keeps the user from being offered trials.
+    pub fn blocks_trials(&self) -> bool {
+        self.decision != Some(TrialAbuseDecision::Allow)
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Why a user was flagged.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum TrialAbuseReason {
+    /// The user's email address is another address of a user who's had a
+    /// trial, such as one with a different `+` tag.
+    #[sea_orm(string_value = "shared_email_address")]
+    SharedEmailAddress,
+    /// Many users with addresses at the user's email domain have started
+    /// trials recently.
+    #[sea_orm(string_value = "email_domain")]
+    EmailDomain,
+    /// The user's trial was started with a card that other users have had
+    /// trials with.
+    #[sea_orm(string_value = "shared_payment_method")]
+    SharedPaymentMethod,
+}
+
+/// What staff decided on reviewing a flag.
+#[derive(
+    Eq, PartialEq, Copy, Clone, Debug, EnumIter, DeriveActiveEnum, Hash, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+#[serde(rename_all = "snake_case")]
+pub enum TrialAbuseDecision {
+    /// The flag was a false positive, and the user may have trials.
+    #[sea_orm(string_value = "allow")]
+    Allow,
+    /// The user keeps being denied trials.
+    #[sea_orm(string_value = "block")]
+    Block,
+}
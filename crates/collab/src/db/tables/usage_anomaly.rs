@@ -0,0 +1,25 @@
+use crate::db::{UsageAnomalyId, UserId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// A record of a user's language model usage deviating from their usual usage.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "usage_anomalies")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: UsageAnomalyId,
+    pub user_id: UserId,
+    /// The number of tokens used within the detection window.
+    pub window_tokens: i64,
+    /// The number of tokens the user was expected to use within the detection window.
+    pub baseline_tokens: i64,
+    /// When the anomaly was reviewed by staff. Unreviewed anomalies may cause
+    /// the user to be throttled.
+    pub reviewed_at: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,52 @@
+use crate::db::{BillingSubscriptionId, UsageRecordId};
+use sea_orm::entity::prelude::*;
+use time::PrimitiveDateTime;
+
+/// The metered usage of a billing subscription within a period, as reported
+/// to Stripe.
+///
+/// There is at most one record per subscription and period, so that usage is
+/// never reported twice.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "usage_records")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: UsageRecordId,
+    pub billing_subscription_id: BillingSubscriptionId,
+    pub period_start: PrimitiveDateTime,
+    pub period_end: PrimitiveDateTime,
+    /// The number of language model tokens used within the period.
+    pub quantity: i64,
+    /// The ID of the Stripe usage record, once reported. Periods without any
+    /// usage are never sent to Stripe.
+    pub stripe_usage_record_id: Option<String>,
+    pub reported_at: Option<PrimitiveDateTime>,
+    /// The number of failed attempts to report the usage.
+    pub attempt_count: i32,
+    /// When to retry reporting the usage after a failed attempt.
+    pub next_attempt_at: Option<PrimitiveDateTime>,
+    pub last_error: Option<String>,
+    /// The time the usage is reported to Stripe at, chosen on the first
+    /// attempt to report it, so that every attempt sets the usage at that
+    /// time rather than adding to it.
+    pub stripe_timestamp: Option<PrimitiveDateTime>,
+    pub created_at: PrimitiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::billing_subscription::Entity",
+        from = "Column::BillingSubscriptionId",
+        to = "super::billing_subscription::Column::Id"
+    )]
+    BillingSubscription,
+}
+
+impl Related<super::billing_subscription::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BillingSubscription.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
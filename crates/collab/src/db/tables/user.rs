@@ -18,6 +18,9 @@ pub struct Model {
     pub connected_once: bool,
     pub metrics_id: Uuid,
     pub created_at: DateTime,
+    /// The user's preferred locale, as a language tag such as `fr-CA`, used
+    /// to localize the billing pages and emails they're shown.
+    pub locale: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,24 @@
+use crate::db::{UserId, UserSecretId};
+use sea_orm::entity::prelude::*;
+
+/// A secret (such as a language model provider API key) that a user syncs
+/// between their devices.
+///
+/// Secrets are encrypted on the client with a key derived from a passphrase
+/// that never leaves the user's devices, so we only ever store ciphertext.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "user_secrets")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: UserSecretId,
+    pub user_id: UserId,
+    pub name: String,
+    pub ciphertext: Vec<u8>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
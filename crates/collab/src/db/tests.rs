@@ -1,14 +1,40 @@
+mod billing_audit_log_tests;
+mod billing_customer_transfer_tests;
+mod billing_invoice_event_tests;
+mod billing_invoice_line_item_tests;
+mod billing_notification_tests;
 mod billing_subscription_tests;
 mod buffer_tests;
 mod channel_tests;
+mod column_rename_tests;
+mod complimentary_subscription_tests;
+mod consent_tests;
 mod contributor_tests;
 mod db_tests;
+mod distributed_lock_lease_tests;
+mod email_tests;
 // we only run postgres tests on macos right now
 #[cfg(target_os = "macos")]
 mod embedding_tests;
 mod extension_tests;
+mod external_entitlement_tests;
 mod feature_flag_tests;
+mod impersonation_tests;
+mod language_model_top_up_tests;
+mod language_model_usage_tests;
 mod message_tests;
+mod model_experiment_tests;
+mod organization_secret_tests;
+mod organization_tests;
+mod plan_enforcement_tests;
+mod referral_tests;
+mod sandbox_organization_tests;
+mod server_setting_tests;
+mod stripe_event_cursor_tests;
+mod trial_abuse_flag_tests;
+mod usage_anomaly_tests;
+mod usage_record_tests;
+mod user_secret_tests;
 
 use super::*;
 use gpui::BackgroundExecutor;
@@ -52,6 +78,7 @@ impl TestDb {
                 .await
                 .unwrap();
             db.initialize_notification_kinds().await.unwrap();
+            db.set_column_cipher(test_column_cipher());
             db
         });
 
@@ -92,6 +119,7 @@ impl TestDb {
             let migrations_path = concat!(env!("CARGO_MANIFEST_DIR"), "/migrations");
             db.migrate(Path::new(migrations_path), false).await.unwrap();
             db.initialize_notification_kinds().await.unwrap();
+            db.set_column_cipher(test_column_cipher());
             db
         });
 
@@ -108,6 +136,10 @@ impl TestDb {
     }
 }
 
+fn test_column_cipher() -> ColumnCipher {
+    ColumnCipher::from_config("test:AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap()
+}
+
 #[macro_export]
 macro_rules! test_both_dbs {
     ($test_name:ident, $postgres_test_name:ident, $sqlite_test_name:ident) => {
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use time::Duration;
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingAuditLogEntryParams, CreateBillingCustomerParams, CreateBillingSubscriptionParams,
+    UpsertBillingSubscriptionOptions,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_billing_audit_log,
+    test_billing_audit_log_postgres,
+    test_billing_audit_log_sqlite
+);
+
+async fn test_billing_audit_log(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "audited-user@example.com").await;
+    let other_user_id = new_test_user(db, "other-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_audited_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let params = |status, cancel_at_period_end| CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_audited_user".into(),
+        stripe_subscription_status: status,
+        cancel_at_period_end,
+        ..Default::default()
+    };
+
+    db.record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+        user_id,
+        actor_user_id: Some(user_id),
+        event: BillingAuditEvent::CheckoutSessionCreated,
+        billing_subscription_id: None,
+        previous_state: None,
+        state: Some(r#"{"checkout_session_id":"cs_1"}"#.into()),
+    })
+    .await
+    .unwrap();
+    db.record_billing_audit_event(&CreateBillingAuditLogEntryParams {
+        user_id: other_user_id,
+        actor_user_id: Some(other_user_id),
+        event: BillingAuditEvent::PortalSessionCreated,
+        billing_subscription_id: None,
+        previous_state: None,
+        state: None,
+    })
+    .await
+    .unwrap();
+
+    // Stripe creates the subscription, and then the user cancels it.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active, false),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active, true),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: Some(user_id),
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    // Upserts that change nothing aren't recorded.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active, true),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Canceled, true),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let entries = db.get_billing_audit_log(Some(user_id), 10).await.unwrap();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (entry.event, entry.actor_user_id))
+            .collect::<Vec<_>>(),
+        &[
+            (BillingAuditEvent::SubscriptionStatusChanged, None),
+            (BillingAuditEvent::SubscriptionUpdated, Some(user_id)),
+            (BillingAuditEvent::SubscriptionCreated, None),
+            (BillingAuditEvent::CheckoutSessionCreated, Some(user_id)),
+        ]
+    );
+    assert!(entries.iter().all(|entry| entry.user_id == user_id));
+
+    let created = &entries[2];
+    assert_eq!(created.previous_state, None);
+    let state: serde_json::Value = serde_json::from_str(created.state.as_deref().unwrap()).unwrap();
+    assert_eq!(state["status"], "active");
+    assert_eq!(state["cancel_at_period_end"], false);
+
+    let updated = &entries[1];
+    assert_eq!(updated.previous_state, created.state);
+    assert_eq!(
+        updated.billing_subscription_id,
+        created.billing_subscription_id
+    );
+    let state: serde_json::Value = serde_json::from_str(updated.state.as_deref().unwrap()).unwrap();
+    assert_eq!(state["cancel_at_period_end"], true);
+
+    let entries = db.get_billing_audit_log(None, 2).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].event, BillingAuditEvent::SubscriptionUpdated);
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams, TransferBillingCustomerParams,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_transfer_billing_customer,
+    test_transfer_billing_customer_postgres,
+    test_transfer_billing_customer_sqlite
+);
+
+async fn test_transfer_billing_customer(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let from_user_id = new_test_user(db, "from@example.com").await;
+    let to_user_id = new_test_user(db, "to@example.com").await;
+    let other_user_id = new_test_user(db, "other@example.com").await;
+
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id: from_user_id,
+            stripe_customer_id: "cus_from".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let subscription = db
+        .create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_from".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            plan: SubscriptionPlan::Team,
+            seat_count: 3,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    db.create_billing_customer(&CreateBillingCustomerParams {
+        user_id: other_user_id,
+        stripe_customer_id: "cus_other".into(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let transfer = db
+        .transfer_billing_customer(&TransferBillingCustomerParams {
+            billing_customer_id: customer.id,
+            to_user_id,
+            organization_id: None,
+            transferred_by_user_id: admin_id,
+            reason: Some("moving to a work account".into()),
+        })
+        .await
+        .unwrap();
+    assert_eq!(transfer.from_user_id, from_user_id);
+    assert_eq!(transfer.to_user_id, to_user_id);
+
+    // The subscriptions move along with the customer.
+    assert!(db
+        .get_active_billing_subscriptions(from_user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    let subscriptions = db
+        .get_active_billing_subscriptions(to_user_id)
+        .await
+        .unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].stripe_subscription_id, "sub_from");
+    assert_eq!(
+        db.get_billing_customer_by_stripe_customer_id("cus_from")
+            .await
+            .unwrap()
+            .map(|customer| customer.user_id),
+        Some(to_user_id)
+    );
+
+    // Users can't end up with two billing customers.
+    assert!(db
+        .transfer_billing_customer(&TransferBillingCustomerParams {
+            billing_customer_id: customer.id,
+            to_user_id: other_user_id,
+            organization_id: None,
+            transferred_by_user_id: admin_id,
+            reason: None,
+        })
+        .await
+        .is_err());
+
+    let transfers = db.get_billing_customer_transfers(10).await.unwrap();
+    assert_eq!(transfers, [transfer.clone()]);
+
+    // Both users' audit logs record the transfer.
+    for user_id in [from_user_id, to_user_id] {
+        let entries = db.get_billing_audit_log(Some(user_id), 10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].event,
+            BillingAuditEvent::BillingCustomerTransferred
+        );
+        assert_eq!(entries[0].actor_user_id, Some(admin_id));
+    }
+
+    // Transferring to an organization makes the team subscription pay for
+    // its seats, until the transfer is reverted.
+    let organization = db
+        .create_organization_with_owner("Acme", "acme", from_user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let transfer = db
+        .transfer_billing_customer(&TransferBillingCustomerParams {
+            billing_customer_id: customer.id,
+            to_user_id: from_user_id,
+            organization_id: Some(organization.id),
+            transferred_by_user_id: admin_id,
+            reason: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_organization_by_id(organization.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .billing_subscription_id,
+        Some(subscription.id)
+    );
+
+    db.revert_billing_customer_transfer(transfer.id, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_organization_by_id(organization.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .billing_subscription_id,
+        None
+    );
+    assert_eq!(
+        db.get_billing_customer_by_stripe_customer_id("cus_from")
+            .await
+            .unwrap()
+            .map(|customer| customer.user_id),
+        Some(to_user_id)
+    );
+    assert_eq!(
+        db.get_billing_customer_transfers(10).await.unwrap().len(),
+        1
+    );
+    assert_eq!(
+        db.get_billing_audit_log(Some(to_user_id), 10)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
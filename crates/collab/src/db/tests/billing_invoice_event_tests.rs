@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+
+use crate::db::billing_invoice_event::BillingInvoiceEventKind;
+use crate::db::tests::new_test_user;
+use crate::db::{CreateBillingCustomerParams, CreateBillingInvoiceEventParams};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_billing_invoice_events,
+    test_billing_invoice_events_postgres,
+    test_billing_invoice_events_sqlite
+);
+
+async fn test_billing_invoice_events(db: &Arc<Database>) {
+    let user = new_test_user(db, "user@example.com").await;
+    let other_user = new_test_user(db, "other@example.com").await;
+
+    let mut billing_customer_ids = Vec::new();
+    for (user_id, stripe_customer_id) in [(user, "cus_user"), (other_user, "cus_other")] {
+        let billing_customer = db
+            .create_billing_customer(&CreateBillingCustomerParams {
+                user_id,
+                stripe_customer_id: stripe_customer_id.into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        billing_customer_ids.push(billing_customer.id);
+    }
+
+    let event = |billing_customer_id, stripe_invoice_id: &str, kind, occurred_at| {
+        CreateBillingInvoiceEventParams {
+            billing_customer_id,
+            stripe_invoice_id: stripe_invoice_id.into(),
+            stripe_subscription_id: Some("sub_1".into()),
+            kind,
+            billing_reason: Some("subscription_cycle".into()),
+            amount_in_cents: 2000,
+            currency: "usd".into(),
+            occurred_at,
+            usd_exchange_rate: Some(1.0),
+            amount_in_usd_cents: Some(2000),
+        }
+    };
+
+    for params in [
+        event(
+            billing_customer_ids[0],
+            "in_2",
+            BillingInvoiceEventKind::Paid,
+            datetime!(2024-08-02 10:00),
+        ),
+        event(
+            billing_customer_ids[0],
+            "in_2",
+            BillingInvoiceEventKind::PaymentFailed,
+            datetime!(2024-08-01 10:00),
+        ),
+        event(
+            billing_customer_ids[0],
+            "in_1",
+            BillingInvoiceEventKind::Paid,
+            datetime!(2024-07-01 10:00),
+        ),
+        // Stripe may deliver the same event more than once.
+        event(
+            billing_customer_ids[0],
+            "in_1",
+            BillingInvoiceEventKind::Paid,
+            datetime!(2024-07-01 10:00),
+        ),
+        event(
+            billing_customer_ids[1],
+            "in_3",
+            BillingInvoiceEventKind::Paid,
+            datetime!(2024-07-15 10:00),
+        ),
+    ] {
+        db.record_billing_invoice_event(&params).await.unwrap();
+    }
+
+    let events = db.get_billing_invoice_events(user).await.unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| (event.stripe_invoice_id.as_str(), event.kind))
+            .collect::<Vec<_>>(),
+        &[
+            ("in_1", BillingInvoiceEventKind::Paid),
+            ("in_2", BillingInvoiceEventKind::PaymentFailed),
+            ("in_2", BillingInvoiceEventKind::Paid),
+        ]
+    );
+
+    let paid_events = db
+        .get_paid_billing_invoice_events(datetime!(2024-07-10 0:00))
+        .await
+        .unwrap();
+    assert_eq!(
+        paid_events
+            .iter()
+            .map(|event| (event.stripe_invoice_id.as_str(), event.amount_in_usd_cents))
+            .collect::<Vec<_>>(),
+        &[("in_3", Some(2000)), ("in_2", Some(2000))]
+    );
+}
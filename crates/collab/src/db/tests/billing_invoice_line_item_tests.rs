@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+use time::{Duration, OffsetDateTime};
+
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingInvoiceLineItemParams,
+    CreateBillingSubscriptionParams, CreateLanguageModelUsageParams,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_billing_invoice_line_items,
+    test_billing_invoice_line_items_postgres,
+    test_billing_invoice_line_items_sqlite
+);
+
+async fn test_billing_invoice_line_items(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let subscription = db
+        .create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_user".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let params = CreateBillingInvoiceLineItemParams {
+        billing_subscription_id: subscription.id,
+        stripe_invoice_id: "in_1".into(),
+        stripe_invoice_line_item_id: "il_1".into(),
+        period_start: datetime!(2024-08-01 0:00),
+        period_end: datetime!(2024-09-01 0:00),
+        quantity: 1_500,
+        description: "Language model usage".into(),
+    };
+    db.record_billing_invoice_line_item(&params).await.unwrap();
+
+    // Describing a line again replaces its description.
+    db.record_billing_invoice_line_item(&CreateBillingInvoiceLineItemParams {
+        description: "Language model usage: gpt-4o 1.5K tokens".into(),
+        ..params
+    })
+    .await
+    .unwrap();
+
+    let line_items = db.get_billing_invoice_line_items("in_1").await.unwrap();
+    assert_eq!(line_items.len(), 1);
+    assert_eq!(line_items[0].billing_subscription_id, subscription.id);
+    assert_eq!(line_items[0].stripe_invoice_line_item_id, "il_1");
+    assert_eq!(line_items[0].quantity, 1_500);
+    assert_eq!(
+        line_items[0].description,
+        "Language model usage: gpt-4o 1.5K tokens"
+    );
+    assert!(db
+        .get_billing_invoice_line_items("in_2")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_language_model_token_usage_by_model,
+    test_language_model_token_usage_by_model_postgres,
+    test_language_model_token_usage_by_model_sqlite
+);
+
+async fn test_language_model_token_usage_by_model(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let other_user_id = new_test_user(db, "other-user@example.com").await;
+
+    for (user_id, model, input_tokens, output_tokens) in [
+        (user_id, "gpt-4o", 100, 50),
+        (user_id, "claude-3-5-sonnet", 1_000, 200),
+        (user_id, "gpt-4o", 20, 10),
+        (other_user_id, "gpt-4o", 5_000, 0),
+    ] {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: "provider".into(),
+            model: model.into(),
+            input_tokens,
+            output_tokens,
+            feature: None,
+            upstream_cost_in_millicents: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(
+        db.get_language_model_token_usage_by_model_for_user(
+            user_id,
+            now - Duration::hours(1),
+            now + Duration::hours(1)
+        )
+        .await
+        .unwrap(),
+        vec![
+            ("claude-3-5-sonnet".to_string(), 1_200),
+            ("gpt-4o".to_string(), 180)
+        ]
+    );
+    assert!(db
+        .get_language_model_token_usage_by_model_for_user(
+            user_id,
+            now - Duration::hours(2),
+            now - Duration::hours(1)
+        )
+        .await
+        .unwrap()
+        .is_empty());
+}
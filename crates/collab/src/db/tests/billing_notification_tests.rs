@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use crate::db::tests::new_test_user;
+use crate::db::CreateEmailDeliveryParams;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_enqueue_billing_notification,
+    test_enqueue_billing_notification_postgres,
+    test_enqueue_billing_notification_sqlite
+);
+
+async fn test_enqueue_billing_notification(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let email = CreateEmailDeliveryParams {
+        recipient: "user@example.com".into(),
+        subject: "Your payment for Zed failed".into(),
+        body: "We couldn't collect your payment.".into(),
+    };
+
+    let delivery = db
+        .enqueue_billing_notification(user_id, "payment_failed:in_1:1", &email)
+        .await
+        .unwrap();
+    assert_eq!(
+        delivery.map(|delivery| delivery.subject),
+        Some("Your payment for Zed failed".to_string())
+    );
+
+    // The same notification is only sent once.
+    assert_eq!(
+        db.enqueue_billing_notification(user_id, "payment_failed:in_1:1", &email)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // A later attempt is a separate notification.
+    assert!(db
+        .enqueue_billing_notification(user_id, "payment_failed:in_1:2", &email)
+        .await
+        .unwrap()
+        .is_some());
+
+    assert_eq!(db.get_due_email_deliveries(10).await.unwrap().len(), 2);
+}
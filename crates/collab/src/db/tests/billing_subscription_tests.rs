@@ -1,8 +1,14 @@
 use std::sync::Arc;
 
-use crate::db::billing_subscription::StripeSubscriptionStatus;
+use time::macros::datetime;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::db::billing_customer::TaxExemptStatus;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
 use crate::db::tests::new_test_user;
-use crate::db::{CreateBillingCustomerParams, CreateBillingSubscriptionParams};
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams, UpsertBillingSubscriptionOptions,
+};
 use crate::test_both_dbs;
 
 use super::Database;
@@ -29,6 +35,7 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
             .create_billing_customer(&CreateBillingCustomerParams {
                 user_id,
                 stripe_customer_id: "cus_active_user".into(),
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -38,6 +45,7 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
             billing_customer_id: customer.id,
             stripe_subscription_id: "sub_active_user".into(),
             stripe_subscription_status: StripeSubscriptionStatus::Active,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -54,6 +62,36 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
             subscription.stripe_subscription_status,
             StripeSubscriptionStatus::Active
         );
+        assert_eq!(subscription.plan, SubscriptionPlan::Pro);
+    }
+
+    // A user on a trial has an active billing subscription.
+    {
+        let user_id = new_test_user(db, "trialing-user@example.com").await;
+        let customer = db
+            .create_billing_customer(&CreateBillingCustomerParams {
+                user_id,
+                stripe_customer_id: "cus_trialing_user".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        db.create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_trialing_user".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Trialing,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(
+            subscriptions[0].stripe_subscription_status,
+            StripeSubscriptionStatus::Trialing
+        );
     }
 
     // A user with a past-due subscription has no active billing subscriptions.
@@ -63,6 +101,7 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
             .create_billing_customer(&CreateBillingCustomerParams {
                 user_id,
                 stripe_customer_id: "cus_past_due_user".into(),
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -72,6 +111,7 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
             billing_customer_id: customer.id,
             stripe_subscription_id: "sub_past_due_user".into(),
             stripe_subscription_status: StripeSubscriptionStatus::PastDue,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -80,3 +120,692 @@ async fn test_get_active_billing_subscriptions(db: &Arc<Database>) {
         assert_eq!(subscriptions.len(), 0);
     }
 }
+
+test_both_dbs!(
+    test_get_billing_subscriptions_at,
+    test_get_billing_subscriptions_at_postgres,
+    test_get_billing_subscriptions_at_sqlite
+);
+
+async fn test_get_billing_subscriptions_at(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "time-travel-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_time_travel_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let before_subscribing = now();
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_time_travel_user".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let while_active = now();
+
+    // Re-syncing a subscription without a status change does not record a transition.
+    let status_changed = db
+        .upsert_billing_subscription_by_stripe_subscription_id(
+            &CreateBillingSubscriptionParams {
+                billing_customer_id: customer.id,
+                stripe_subscription_id: "sub_time_travel_user".into(),
+                stripe_subscription_status: StripeSubscriptionStatus::Active,
+                ..Default::default()
+            },
+            &UpsertBillingSubscriptionOptions {
+                past_due_grace_period: Duration::days(7),
+                actor_user_id: None,
+                transitioned_at: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(!status_changed);
+
+    // Transitions happen when the payment provider says they did, rather than
+    // when we learn about them.
+    let canceled_at = (now() + Duration::hours(1)).replace_nanosecond(0).unwrap();
+    let status_changed = db
+        .upsert_billing_subscription_by_stripe_subscription_id(
+            &CreateBillingSubscriptionParams {
+                billing_customer_id: customer.id,
+                stripe_subscription_id: "sub_time_travel_user".into(),
+                stripe_subscription_status: StripeSubscriptionStatus::Canceled,
+                ..Default::default()
+            },
+            &UpsertBillingSubscriptionOptions {
+                past_due_grace_period: Duration::days(7),
+                actor_user_id: None,
+                transitioned_at: Some(canceled_at),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(status_changed);
+    let after_canceling = canceled_at + Duration::seconds(1);
+
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    let transitions = db
+        .get_billing_subscription_transitions(subscriptions[0].id)
+        .await
+        .unwrap();
+    assert_eq!(
+        transitions
+            .iter()
+            .map(|transition| transition.stripe_subscription_status)
+            .collect::<Vec<_>>(),
+        &[
+            StripeSubscriptionStatus::Active,
+            StripeSubscriptionStatus::Canceled
+        ]
+    );
+
+    let snapshots = db
+        .get_billing_subscriptions_at(user_id, before_subscribing)
+        .await
+        .unwrap();
+    assert_eq!(snapshots.len(), 0);
+
+    let snapshots = db
+        .get_billing_subscriptions_at(user_id, while_active)
+        .await
+        .unwrap();
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(
+        snapshots[0].stripe_subscription_status,
+        StripeSubscriptionStatus::Active
+    );
+    assert_eq!(snapshots[0].plan, Some(SubscriptionPlan::Pro));
+
+    let snapshots = db
+        .get_billing_subscriptions_at(user_id, now())
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshots[0].stripe_subscription_status,
+        StripeSubscriptionStatus::Active
+    );
+
+    let snapshots = db
+        .get_billing_subscriptions_at(user_id, after_canceling)
+        .await
+        .unwrap();
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(
+        snapshots[0].stripe_subscription_status,
+        StripeSubscriptionStatus::Canceled
+    );
+    assert_eq!(snapshots[0].transitioned_at, canceled_at);
+}
+
+test_both_dbs!(
+    test_upsert_billing_subscription_plan,
+    test_upsert_billing_subscription_plan_postgres,
+    test_upsert_billing_subscription_plan_sqlite
+);
+
+async fn test_upsert_billing_subscription_plan(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "team-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_team_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_team_user".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    // Switching plans updates the existing subscription.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_team_user".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            plan: SubscriptionPlan::Team,
+            seat_count: 5,
+            stripe_coupon_id: Some("coupon_launch".into()),
+            stripe_promotion_code_id: Some("promo_launch".into()),
+            trial_end: Some(datetime!(2024-09-01 0:00)),
+            current_period_end: Some(datetime!(2024-10-01 0:00)),
+            cancel_at_period_end: true,
+            canceled_at: Some(datetime!(2024-08-20 0:00)),
+            ..Default::default()
+        },
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].plan, SubscriptionPlan::Team);
+    assert_eq!(subscriptions[0].seat_count, 5);
+    assert_eq!(subscriptions[0].trial_end, Some(datetime!(2024-09-01 0:00)));
+    assert_eq!(
+        subscriptions[0].current_period_end,
+        Some(datetime!(2024-10-01 0:00))
+    );
+    assert!(subscriptions[0].cancel_at_period_end);
+    assert_eq!(
+        subscriptions[0].canceled_at,
+        Some(datetime!(2024-08-20 0:00))
+    );
+    assert_eq!(
+        subscriptions[0].stripe_coupon_id.as_deref(),
+        Some("coupon_launch")
+    );
+    assert_eq!(
+        subscriptions[0].stripe_promotion_code_id.as_deref(),
+        Some("promo_launch")
+    );
+
+    let subscription = db
+        .update_billing_subscription_seat_count(subscriptions[0].id, 3)
+        .await
+        .unwrap();
+    assert_eq!(subscription.seat_count, 3);
+    assert_eq!(subscription.plan, SubscriptionPlan::Team);
+}
+
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+test_both_dbs!(
+    test_get_or_create_billing_customer,
+    test_get_or_create_billing_customer_postgres,
+    test_get_or_create_billing_customer_sqlite
+);
+
+async fn test_get_or_create_billing_customer(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .get_or_create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_first".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(customer.stripe_customer_id, "cus_first");
+
+    // A user only ever has one billing customer, so the first one recorded wins.
+    let duplicate_customer = db
+        .get_or_create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_second".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(duplicate_customer, customer);
+    assert_eq!(
+        db.get_billing_customer_by_stripe_customer_id("cus_second")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+test_both_dbs!(
+    test_update_billing_customer_tax_exempt,
+    test_update_billing_customer_tax_exempt_postgres,
+    test_update_billing_customer_tax_exempt_sqlite
+);
+
+async fn test_update_billing_customer_tax_exempt(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(customer.tax_exempt, TaxExemptStatus::None);
+
+    db.update_billing_customer_tax_exempt(customer.id, TaxExemptStatus::Reverse)
+        .await
+        .unwrap();
+    let customer = db
+        .get_billing_customer_by_user_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(customer.tax_exempt, TaxExemptStatus::Reverse);
+}
+
+test_both_dbs!(
+    test_update_billing_customer_email,
+    test_update_billing_customer_email_postgres,
+    test_update_billing_customer_email_sqlite
+);
+
+async fn test_update_billing_customer_email(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(customer.billing_email, None);
+
+    db.update_billing_customer_email(customer.id, Some("finance@example.com"))
+        .await
+        .unwrap();
+    let customer = db
+        .get_billing_customer_by_user_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        customer.billing_email.as_deref(),
+        Some("finance@example.com")
+    );
+
+    db.update_billing_customer_email(customer.id, None)
+        .await
+        .unwrap();
+    let customer = db
+        .get_billing_customer_by_user_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(customer.billing_email, None);
+}
+
+test_both_dbs!(
+    test_update_billing_customer_spending_cap,
+    test_update_billing_customer_spending_cap_postgres,
+    test_update_billing_customer_spending_cap_sqlite
+);
+
+async fn test_update_billing_customer_spending_cap(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(customer.spending_cap_in_cents, None);
+
+    db.update_billing_customer_spending_cap(customer.id, Some(5000))
+        .await
+        .unwrap();
+    let customer = db
+        .get_billing_customer_by_user_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(customer.spending_cap_in_cents, Some(5000));
+
+    db.update_billing_customer_spending_cap(customer.id, None)
+        .await
+        .unwrap();
+    let customer = db
+        .get_billing_customer_by_user_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(customer.spending_cap_in_cents, None);
+}
+
+test_both_dbs!(
+    test_past_due_grace_period,
+    test_past_due_grace_period_postgres,
+    test_past_due_grace_period_sqlite
+);
+
+async fn test_past_due_grace_period(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "grace-period-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_grace_period_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let params = |stripe_subscription_status| CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_grace_period_user".into(),
+        stripe_subscription_status,
+        ..Default::default()
+    };
+
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions[0].grace_period_ends_at, None);
+
+    // A subscription that becomes past due stays active during its grace period.
+    let before_past_due = now();
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::PastDue),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    let grace_period_ends_at = subscriptions[0].grace_period_ends_at.unwrap();
+    // Allow for the database storing timestamps with less precision.
+    assert!(grace_period_ends_at >= before_past_due + Duration::days(7) - Duration::seconds(1));
+    assert!(grace_period_ends_at <= now() + Duration::days(7));
+
+    // Further events while past due don't extend the grace period.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::PastDue),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(30),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(
+        subscriptions[0].grace_period_ends_at,
+        Some(grace_period_ends_at)
+    );
+
+    assert!(db
+        .end_expired_billing_subscription_grace_periods(now())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.end_expired_billing_subscription_grace_periods(grace_period_ends_at)
+            .await
+            .unwrap(),
+        [user_id]
+    );
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions[0].grace_period_ends_at, None);
+
+    // Once the grace period has ended, the subscription no longer counts as
+    // active, and later events don't start a new one.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::PastDue),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(db
+        .get_active_billing_subscriptions(user_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Paying for the subscription makes it active again.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].grace_period_ends_at, None);
+
+    // A grace period that has already ended doesn't grant access.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::PastDue),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::ZERO,
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(db
+        .get_active_billing_subscriptions(user_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_get_unended_billing_subscriptions,
+    test_get_unended_billing_subscriptions_postgres,
+    test_get_unended_billing_subscriptions_sqlite
+);
+
+async fn test_get_unended_billing_subscriptions(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    for (stripe_subscription_id, stripe_subscription_status) in [
+        ("sub_active", StripeSubscriptionStatus::Active),
+        ("sub_canceled", StripeSubscriptionStatus::Canceled),
+        ("sub_past_due", StripeSubscriptionStatus::PastDue),
+        ("sub_expired", StripeSubscriptionStatus::IncompleteExpired),
+    ] {
+        db.create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: stripe_subscription_id.into(),
+            stripe_subscription_status,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    }
+
+    let subscriptions = db.get_unended_billing_subscriptions().await.unwrap();
+    assert_eq!(
+        subscriptions
+            .iter()
+            .map(|subscription| subscription.stripe_subscription_id.as_str())
+            .collect::<Vec<_>>(),
+        ["sub_active", "sub_past_due"]
+    );
+
+    let subscription = db
+        .get_billing_subscription_by_stripe_subscription_id("sub_canceled")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        subscription.stripe_subscription_status,
+        StripeSubscriptionStatus::Canceled
+    );
+    assert!(db
+        .get_billing_subscription_by_stripe_subscription_id("sub_unknown")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+test_both_dbs!(
+    test_unpaid_subscription_dunning,
+    test_unpaid_subscription_dunning_postgres,
+    test_unpaid_subscription_dunning_sqlite
+);
+
+async fn test_unpaid_subscription_dunning(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "dunning-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_dunning_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let params = |stripe_subscription_status| CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_dunning_user".into(),
+        stripe_subscription_status,
+        ..Default::default()
+    };
+
+    // An unpaid subscription keeps granting access while payment is retried.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Unpaid),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get_active_billing_subscriptions(user_id)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    let due = db
+        .get_billing_subscriptions_due_for_dunning(now())
+        .await
+        .unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].1.user_id, user_id);
+    let subscription_id = due[0].0.id;
+
+    let next_attempt_at = now() + Duration::days(3);
+    db.record_billing_subscription_dunning_attempt(subscription_id, next_attempt_at)
+        .await
+        .unwrap();
+    assert!(db
+        .get_billing_subscriptions_due_for_dunning(now())
+        .await
+        .unwrap()
+        .is_empty());
+    let due = db
+        .get_billing_subscriptions_due_for_dunning(next_attempt_at)
+        .await
+        .unwrap();
+    assert_eq!(due[0].0.dunning_attempt_count, 1);
+
+    // Once suspended, it stops granting access, and isn't retried anymore.
+    let suspended_at = datetime!(2024-09-10 10:00);
+    let subscription = db
+        .suspend_billing_subscription(subscription_id, suspended_at)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(subscription.suspended_at, Some(suspended_at));
+    assert!(db
+        .suspend_billing_subscription(subscription_id, suspended_at)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .get_active_billing_subscriptions(user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db
+        .get_billing_subscriptions_due_for_dunning(next_attempt_at)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // It's only reinstated once it's no longer unpaid.
+    assert!(db
+        .reinstate_billing_subscription("sub_dunning_user")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .get_billing_subscriptions_to_reinstate()
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &params(StripeSubscriptionStatus::Active),
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get_billing_subscriptions_to_reinstate()
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    let (subscription, reinstated_customer) = db
+        .reinstate_billing_subscription("sub_dunning_user")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(subscription.suspended_at, Some(suspended_at));
+    assert_eq!(reinstated_customer.id, customer.id);
+
+    let subscriptions = db.get_active_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].suspended_at, None);
+    assert_eq!(subscriptions[0].dunning_attempt_count, 0);
+    assert!(db
+        .get_billing_subscriptions_to_reinstate()
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use sea_orm::{ConnectionTrait, Statement};
+use time::macros::datetime;
+use time::Duration;
+
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::column_renames::BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END;
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams, UpsertBillingSubscriptionOptions,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_backfill_renamed_column,
+    test_backfill_renamed_column_postgres,
+    test_backfill_renamed_column_sqlite
+);
+
+async fn test_backfill_renamed_column(db: &Arc<Database>) {
+    let rename = BILLING_SUBSCRIPTIONS_CURRENT_PERIOD_END;
+    let user_id = new_test_user(db, "renamed-column-user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_renamed_column_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    for (stripe_subscription_id, current_period_end) in [
+        ("sub_1", Some(datetime!(2024-10-01 0:00))),
+        ("sub_2", Some(datetime!(2024-11-01 0:00))),
+        ("sub_3", None),
+    ] {
+        db.create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: stripe_subscription_id.into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            current_period_end,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    }
+
+    // New subscriptions are written to both columns.
+    assert!(db
+        .find_renamed_column_mismatches(&rename, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Simulate subscriptions written before the dual-write began.
+    execute(
+        db,
+        "UPDATE billing_subscriptions SET current_period_ends_at = NULL",
+    )
+    .await;
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    let ids = subscriptions
+        .iter()
+        .map(|subscription| subscription.id.0)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        db.find_renamed_column_mismatches(&rename, 10)
+            .await
+            .unwrap(),
+        &ids[..2]
+    );
+
+    // Reads use the old column until they're switched over.
+    assert_eq!(
+        subscriptions[0].current_period_end,
+        Some(datetime!(2024-10-01 0:00))
+    );
+    db.set_renamed_column_reads([rename.name.to_string()]);
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(subscriptions[0].current_period_end, None);
+    db.set_renamed_column_reads([]);
+
+    // The backfill copies the old column in batches.
+    assert_eq!(db.backfill_renamed_column(&rename, 1).await.unwrap(), 1);
+    assert_eq!(db.backfill_renamed_column(&rename, 10).await.unwrap(), 1);
+    assert_eq!(db.backfill_renamed_column(&rename, 10).await.unwrap(), 0);
+    assert!(db
+        .find_renamed_column_mismatches(&rename, 10)
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.set_renamed_column_reads([rename.name.to_string()]);
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(
+        subscriptions
+            .iter()
+            .map(|subscription| subscription.current_period_end)
+            .collect::<Vec<_>>(),
+        [
+            Some(datetime!(2024-10-01 0:00)),
+            Some(datetime!(2024-11-01 0:00)),
+            None
+        ]
+    );
+
+    // Updates keep both columns in sync.
+    db.upsert_billing_subscription_by_stripe_subscription_id(
+        &CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_3".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            current_period_end: Some(datetime!(2024-12-01 0:00)),
+            ..Default::default()
+        },
+        &UpsertBillingSubscriptionOptions {
+            past_due_grace_period: Duration::days(7),
+            actor_user_id: None,
+            transitioned_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(db
+        .find_renamed_column_mismatches(&rename, 10)
+        .await
+        .unwrap()
+        .is_empty());
+    let subscriptions = db.get_billing_subscriptions(user_id).await.unwrap();
+    assert_eq!(
+        subscriptions[2].current_period_end,
+        Some(datetime!(2024-12-01 0:00))
+    );
+}
+
+async fn execute(db: &Database, sql: &'static str) {
+    db.transaction(|tx| async move {
+        tx.execute(Statement::from_string(tx.get_database_backend(), sql))
+            .await?;
+        Ok(())
+    })
+    .await
+    .unwrap();
+}
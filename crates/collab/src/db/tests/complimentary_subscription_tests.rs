@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::complimentary_subscription::ComplimentarySubscriptionKind;
+use crate::db::tests::new_test_user;
+use crate::db::CreateComplimentarySubscriptionParams;
+use crate::entitlements::{BillingMode, Entitlements, Plan};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_complimentary_subscriptions,
+    test_complimentary_subscriptions_postgres,
+    test_complimentary_subscriptions_sqlite
+);
+
+async fn test_complimentary_subscriptions(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let user_id = new_test_user(db, "user@example.com").await;
+    let plan = |db: Arc<Database>| async move {
+        Entitlements::for_user(&db, BillingMode::Stripe, user_id)
+            .await
+            .unwrap()
+            .plan
+    };
+    assert_eq!(plan(db.clone()).await, Plan::Free);
+
+    let now = OffsetDateTime::now_utc();
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let expired_grant = db
+        .create_complimentary_subscription(&CreateComplimentarySubscriptionParams {
+            user_id,
+            plan: SubscriptionPlan::Team,
+            kind: ComplimentarySubscriptionKind::Giveaway,
+            reason: None,
+            granted_by_user_id: admin_id,
+            expires_at: Some(now - Duration::days(1)),
+        })
+        .await
+        .unwrap();
+    assert!(!expired_grant.is_active_at(now));
+    let grant = db
+        .create_complimentary_subscription(&CreateComplimentarySubscriptionParams {
+            user_id,
+            plan: SubscriptionPlan::Pro,
+            kind: ComplimentarySubscriptionKind::Staff,
+            reason: Some("Zed staff".into()),
+            granted_by_user_id: admin_id,
+            expires_at: None,
+        })
+        .await
+        .unwrap();
+    assert!(grant.is_active_at(now));
+
+    // Only grants that haven't expired count towards the user's plan.
+    assert_eq!(
+        db.get_active_complimentary_subscriptions(user_id)
+            .await
+            .unwrap(),
+        [grant.clone()]
+    );
+    assert_eq!(plan(db.clone()).await, Plan::Pro);
+
+    let revoked_grant = db
+        .revoke_complimentary_subscription(grant.id, admin_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(revoked_grant.revoked_by_user_id, Some(admin_id));
+    assert!(!revoked_grant.is_active_at(now + Duration::seconds(1)));
+    assert_eq!(
+        db.revoke_complimentary_subscription(grant.id, admin_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert!(db
+        .get_active_complimentary_subscriptions(user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(plan(db.clone()).await, Plan::Free);
+
+    assert_eq!(
+        db.get_complimentary_subscriptions(user_id).await.unwrap(),
+        [expired_grant, revoked_grant]
+    );
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::db::consent::ConsentKind;
+use crate::db::tests::new_test_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(test_consents, test_consents_postgres, test_consents_sqlite);
+
+async fn test_consents(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+
+    assert!(!db
+        .has_consented(user_id, ConsentKind::TermsOfService, "2024-08-01")
+        .await
+        .unwrap());
+
+    db.record_consent(user_id, ConsentKind::TermsOfService, "2024-08-01")
+        .await
+        .unwrap();
+    // Recording the same consent twice is a no-op.
+    db.record_consent(user_id, ConsentKind::TermsOfService, "2024-08-01")
+        .await
+        .unwrap();
+
+    assert!(db
+        .has_consented(user_id, ConsentKind::TermsOfService, "2024-08-01")
+        .await
+        .unwrap());
+    assert!(!db
+        .has_consented(user_id, ConsentKind::TermsOfService, "2024-09-01")
+        .await
+        .unwrap());
+    assert!(!db
+        .has_consented(user_id, ConsentKind::Pricing, "2024-08-01")
+        .await
+        .unwrap());
+
+    db.record_consent(user_id, ConsentKind::Pricing, "v2")
+        .await
+        .unwrap();
+    let consents = db.get_consents(user_id).await.unwrap();
+    assert_eq!(
+        consents
+            .iter()
+            .map(|consent| (consent.kind, consent.version.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            (ConsentKind::TermsOfService, "2024-08-01"),
+            (ConsentKind::Pricing, "v2")
+        ]
+    );
+}
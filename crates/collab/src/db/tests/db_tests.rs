@@ -481,6 +481,26 @@ async fn test_metrics_id(db: &Arc<Database>) {
     assert_ne!(metrics_id1, metrics_id2);
 }
 
+test_both_dbs!(
+    test_user_locale,
+    test_user_locale_postgres,
+    test_user_locale_sqlite
+);
+
+async fn test_user_locale(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let user = db.get_user_by_id(user_id).await.unwrap().unwrap();
+    assert_eq!(user.locale, None);
+
+    db.set_user_locale(user_id, Some("fr-CA")).await.unwrap();
+    let user = db.get_user_by_id(user_id).await.unwrap().unwrap();
+    assert_eq!(user.locale.as_deref(), Some("fr-CA"));
+
+    db.set_user_locale(user_id, None).await.unwrap();
+    let user = db.get_user_by_id(user_id).await.unwrap().unwrap();
+    assert_eq!(user.locale, None);
+}
+
 test_both_dbs!(
     test_project_count,
     test_project_count_postgres,
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_distributed_lock_leases,
+    test_distributed_lock_leases_postgres,
+    test_distributed_lock_leases_sqlite
+);
+
+async fn test_distributed_lock_leases(db: &Arc<Database>) {
+    let lease_duration = Duration::from_secs(60);
+
+    assert!(db
+        .try_acquire_distributed_lock("job", "a", lease_duration)
+        .await
+        .unwrap());
+    assert!(!db
+        .try_acquire_distributed_lock("job", "b", lease_duration)
+        .await
+        .unwrap());
+    assert!(db
+        .try_acquire_distributed_lock("other-job", "b", lease_duration)
+        .await
+        .unwrap());
+
+    // Only the holder can renew or release its lease.
+    assert!(db
+        .renew_distributed_lock("job", "a", lease_duration)
+        .await
+        .unwrap());
+    assert!(!db
+        .renew_distributed_lock("job", "b", lease_duration)
+        .await
+        .unwrap());
+    db.release_distributed_lock("job", "b").await.unwrap();
+    assert!(!db
+        .try_acquire_distributed_lock("job", "b", lease_duration)
+        .await
+        .unwrap());
+
+    db.release_distributed_lock("job", "a").await.unwrap();
+    assert!(db
+        .try_acquire_distributed_lock("job", "b", Duration::ZERO)
+        .await
+        .unwrap());
+
+    // Expired leases are taken over, after which the previous holder can no
+    // longer renew them.
+    assert!(db
+        .try_acquire_distributed_lock("job", "a", lease_duration)
+        .await
+        .unwrap());
+    assert!(!db
+        .renew_distributed_lock("job", "b", lease_duration)
+        .await
+        .unwrap());
+}
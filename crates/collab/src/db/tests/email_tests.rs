@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::email_delivery::EmailDeliveryStatus;
+use crate::db::email_suppression::EmailSuppressionReason;
+use crate::db::CreateEmailDeliveryParams;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_email_deliveries,
+    test_email_deliveries_postgres,
+    test_email_deliveries_sqlite
+);
+
+async fn test_email_deliveries(db: &Arc<Database>) {
+    let email = db
+        .enqueue_email(&CreateEmailDeliveryParams {
+            recipient: "user@example.com".into(),
+            subject: "Welcome".into(),
+            body: "Hello!".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(email.status, EmailDeliveryStatus::Pending);
+
+    let due = db.get_due_email_deliveries(10).await.unwrap();
+    assert_eq!(
+        due.iter().map(|email| email.id).collect::<Vec<_>>(),
+        [email.id]
+    );
+
+    // A transient failure reschedules the email.
+    db.mark_email_delivery_failed(
+        email.id,
+        "timed out",
+        Some(OffsetDateTime::now_utc() + Duration::hours(1)),
+    )
+    .await
+    .unwrap();
+    assert!(db.get_due_email_deliveries(10).await.unwrap().is_empty());
+
+    db.mark_email_delivery_failed(email.id, "timed out", Some(OffsetDateTime::now_utc()))
+        .await
+        .unwrap();
+    let due = db.get_due_email_deliveries(10).await.unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].attempt_count, 2);
+    assert_eq!(due[0].last_error.as_deref(), Some("timed out"));
+
+    db.mark_email_delivery_sent(email.id, "message-1")
+        .await
+        .unwrap();
+    assert!(db.get_due_email_deliveries(10).await.unwrap().is_empty());
+
+    // A hard bounce suppresses the address and any pending emails to it.
+    let pending_email = db
+        .enqueue_email(&CreateEmailDeliveryParams {
+            recipient: "user@example.com".into(),
+            subject: "Your invoice".into(),
+            body: "Thanks!".into(),
+        })
+        .await
+        .unwrap();
+    db.suppress_email_address(
+        "User@Example.com",
+        EmailSuppressionReason::Bounce,
+        Some("message-1"),
+    )
+    .await
+    .unwrap();
+    assert!(db
+        .is_email_address_suppressed("user@example.com")
+        .await
+        .unwrap());
+    assert!(db.get_due_email_deliveries(10).await.unwrap().is_empty());
+
+    let suppressed_email = db
+        .enqueue_email(&CreateEmailDeliveryParams {
+            recipient: "user@example.com".into(),
+            subject: "Are you there?".into(),
+            body: "Hello?".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(suppressed_email.status, EmailDeliveryStatus::Suppressed);
+    assert_ne!(pending_email.id, suppressed_email.id);
+}
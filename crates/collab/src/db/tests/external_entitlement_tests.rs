@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::external_entitlement::ExternalEntitlementSource;
+use crate::db::tests::new_test_user;
+use crate::db::GrantExternalEntitlementParams;
+use crate::entitlements::{BillingMode, Entitlements, Plan};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_external_entitlements,
+    test_external_entitlements_postgres,
+    test_external_entitlements_sqlite
+);
+
+async fn test_external_entitlements(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "sponsor@example.com").await;
+    let plan = |db: Arc<Database>| async move {
+        Entitlements::for_user(&db, BillingMode::Stripe, user_id)
+            .await
+            .unwrap()
+            .plan
+    };
+    assert_eq!(plan(db.clone()).await, Plan::Free);
+
+    let params = |plan| GrantExternalEntitlementParams {
+        user_id,
+        source: ExternalEntitlementSource::GithubSponsors,
+        external_id: "1234".into(),
+        plan,
+    };
+    db.grant_external_entitlement(&params(SubscriptionPlan::Pro))
+        .await
+        .unwrap();
+    assert_eq!(plan(db.clone()).await, Plan::Pro);
+
+    // Granting it again updates the existing entitlement.
+    db.grant_external_entitlement(&params(SubscriptionPlan::Team))
+        .await
+        .unwrap();
+    let entitlements = db
+        .get_active_external_entitlements_from_source(ExternalEntitlementSource::GithubSponsors)
+        .await
+        .unwrap();
+    assert_eq!(entitlements.len(), 1);
+    assert_eq!(entitlements[0].plan, SubscriptionPlan::Team);
+    assert_eq!(plan(db.clone()).await, Plan::Team);
+
+    db.end_external_entitlement(entitlements[0].id)
+        .await
+        .unwrap();
+    assert!(db
+        .get_active_external_entitlements(user_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(plan(db.clone()).await, Plan::Free);
+
+    // Ended entitlements resume when granted again.
+    db.grant_external_entitlement(&params(SubscriptionPlan::Pro))
+        .await
+        .unwrap();
+    let resumed = db.get_active_external_entitlements(user_id).await.unwrap();
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].id, entitlements[0].id);
+    assert_eq!(resumed[0].ended_at, None);
+    assert_eq!(plan(db.clone()).await, Plan::Pro);
+}
@@ -42,6 +42,10 @@ async fn test_get_user_flags(db: &Arc<Database>) {
 
     let channels_flag = db.create_user_flag(CHANNELS_ALPHA).await.unwrap();
     let search_flag = db.create_user_flag(NEW_SEARCH).await.unwrap();
+    assert_eq!(
+        db.get_or_create_user_flag(CHANNELS_ALPHA).await.unwrap(),
+        channels_flag
+    );
 
     db.add_user_flag(user_1, channels_flag).await.unwrap();
     db.add_user_flag(user_1, search_flag).await.unwrap();
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use crate::db::tests::new_test_user;
+use crate::db::CreateImpersonationSessionParams;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_impersonation_sessions,
+    test_impersonation_sessions_postgres,
+    test_impersonation_sessions_sqlite
+);
+
+async fn test_impersonation_sessions(db: &Arc<Database>) {
+    let staff = new_test_user(db, "staff@example.com").await;
+    let user = new_test_user(db, "user@example.com").await;
+
+    let now = OffsetDateTime::now_utc();
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let session = db
+        .create_impersonation_session(&CreateImpersonationSessionParams {
+            staff_user_id: staff,
+            impersonated_user_id: user,
+            reason: "support ticket 123".into(),
+            allow_writes: false,
+            expires_at: now + Duration::minutes(15),
+            token_hash: "token-hash".into(),
+        })
+        .await
+        .unwrap();
+    assert!(session.is_active(now));
+    assert!(!session.is_active(now + Duration::minutes(15)));
+    assert_eq!(
+        db.get_impersonation_session(session.id).await.unwrap(),
+        Some(session.clone())
+    );
+    assert_eq!(
+        db.get_impersonation_session_by_token_hash("token-hash")
+            .await
+            .unwrap(),
+        Some(session.clone())
+    );
+    assert_eq!(
+        db.get_impersonation_session_by_token_hash("other-token-hash")
+            .await
+            .unwrap(),
+        None
+    );
+
+    db.record_impersonated_request(session.id, "GET", "/billing/subscriptions", true)
+        .await
+        .unwrap();
+    db.record_impersonated_request(session.id, "POST", "/billing/subscriptions", false)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_impersonated_requests(session.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|request| (request.method, request.path, request.allowed))
+            .collect::<Vec<_>>(),
+        [
+            (
+                "GET".to_string(),
+                "/billing/subscriptions".to_string(),
+                true
+            ),
+            (
+                "POST".to_string(),
+                "/billing/subscriptions".to_string(),
+                false
+            ),
+        ]
+    );
+
+    // Ending a session is final.
+    db.end_impersonation_session(session.id).await.unwrap();
+    let ended_session = db
+        .get_impersonation_session(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!ended_session.is_active(now));
+
+    db.end_impersonation_session(session.id).await.unwrap();
+    assert_eq!(
+        db.get_impersonation_session(session.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .ended_at,
+        ended_session.ended_at
+    );
+}
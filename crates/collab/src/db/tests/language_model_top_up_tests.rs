@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::tests::new_test_user;
+use crate::db::{CreateLanguageModelTopUpParams, CreateLanguageModelUsageParams};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_language_model_top_ups,
+    test_language_model_top_ups_postgres,
+    test_language_model_top_ups_sqlite
+);
+
+async fn test_language_model_top_ups(db: &Arc<Database>) {
+    let user = new_test_user(db, "user@example.com").await;
+    let period_start = time::macros::date!(2024 - 08 - 01);
+
+    for (session_id, tokens) in [("cs_1", 1_000_000), ("cs_2", 2_000_000)] {
+        db.create_language_model_top_up(&CreateLanguageModelTopUpParams {
+            user_id: user,
+            stripe_checkout_session_id: session_id.into(),
+            period_start,
+            tokens,
+        })
+        .await
+        .unwrap();
+    }
+
+    // Retrying a checkout gets the same session back, which is recorded once.
+    let top_up = db
+        .create_language_model_top_up(&CreateLanguageModelTopUpParams {
+            user_id: user,
+            stripe_checkout_session_id: "cs_1".into(),
+            period_start,
+            tokens: 1_000_000,
+        })
+        .await
+        .unwrap();
+    assert_eq!(top_up.stripe_checkout_session_id, "cs_1");
+
+    // Top-ups don't count until they're paid for.
+    assert_eq!(
+        db.get_language_model_top_up_tokens(user, period_start)
+            .await
+            .unwrap(),
+        0
+    );
+
+    let top_up = db.fulfill_language_model_top_up("cs_1").await.unwrap();
+    assert_eq!(top_up.map(|top_up| top_up.tokens), Some(1_000_000));
+    assert_eq!(
+        db.get_language_model_top_up_tokens(user, period_start)
+            .await
+            .unwrap(),
+        1_000_000
+    );
+
+    // Fulfilling a top-up again, or one that doesn't exist, is a no-op.
+    assert_eq!(
+        db.fulfill_language_model_top_up("cs_1").await.unwrap(),
+        None
+    );
+    assert_eq!(
+        db.fulfill_language_model_top_up("cs_unknown")
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.get_language_model_top_up_tokens(user, period_start)
+            .await
+            .unwrap(),
+        1_000_000
+    );
+
+    // Top-ups only apply to the period they were bought for.
+    assert_eq!(
+        db.get_language_model_top_up_tokens(user, time::macros::date!(2024 - 09 - 01))
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+test_both_dbs!(
+    test_language_model_token_usage_for_user,
+    test_language_model_token_usage_for_user_postgres,
+    test_language_model_token_usage_for_user_sqlite
+);
+
+async fn test_language_model_token_usage_for_user(db: &Arc<Database>) {
+    let user_1 = new_test_user(db, "user1@example.com").await;
+    let user_2 = new_test_user(db, "user2@example.com").await;
+
+    for (user_id, input_tokens, output_tokens) in
+        [(user_1, 100, 20), (user_1, 50, 5), (user_2, 7, 3)]
+    {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: "anthropic".into(),
+            model: "claude-3-5-sonnet-20240620".into(),
+            input_tokens,
+            output_tokens,
+            feature: None,
+            upstream_cost_in_millicents: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(
+        db.get_language_model_token_usage_for_user(
+            user_1,
+            now - Duration::days(1),
+            now + Duration::days(1)
+        )
+        .await
+        .unwrap(),
+        175
+    );
+    assert_eq!(
+        db.get_language_model_token_usage_for_user(
+            user_1,
+            now + Duration::days(1),
+            now + Duration::days(2)
+        )
+        .await
+        .unwrap(),
+        0
+    );
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::tests::new_test_user;
+use crate::db::CreateLanguageModelUsageParams;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_archive_language_model_usages,
+    test_archive_language_model_usages_postgres,
+    test_archive_language_model_usages_sqlite
+);
+
+async fn test_archive_language_model_usages(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let record_usage = |feature: Option<&str>, cost| CreateLanguageModelUsageParams {
+        user_id,
+        provider: "anthropic".into(),
+        model: "model".into(),
+        input_tokens: 10,
+        output_tokens: 1,
+        feature: feature.map(|feature| feature.to_string()),
+        upstream_cost_in_millicents: cost,
+    };
+    for (feature, cost) in [(Some("assistant_panel"), 100), (None, 20), (None, 5)] {
+        db.record_language_model_usage(&record_usage(feature, cost))
+            .await
+            .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    assert_eq!(
+        db.archive_language_model_usages(now - Duration::days(1), 10)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Usages are archived in batches, oldest first.
+    assert_eq!(
+        db.archive_language_model_usages(now + Duration::days(1), 2)
+            .await
+            .unwrap(),
+        2
+    );
+    db.record_language_model_usage(&record_usage(None, 1))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.archive_language_model_usages(now + Duration::days(1), 10)
+            .await
+            .unwrap(),
+        2
+    );
+
+    let usage = db
+        .get_language_model_token_usage_by_user(now - Duration::days(1), now + Duration::days(1))
+        .await
+        .unwrap();
+    assert!(usage.is_empty());
+
+    let month = now.date() - Duration::days(now.day() as i64 - 1);
+    let monthly_usages = db.get_monthly_language_model_usages(user_id).await.unwrap();
+    assert_eq!(
+        monthly_usages
+            .iter()
+            .map(|usage| (
+                usage.month,
+                usage.feature.as_deref(),
+                usage.request_count,
+                usage.input_tokens,
+                usage.output_tokens,
+                usage.upstream_cost_in_millicents
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            (month, None, 3, 30, 3, 26),
+            (month, Some("assistant_panel"), 1, 10, 1, 100),
+        ]
+    );
+}
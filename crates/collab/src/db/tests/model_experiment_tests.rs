@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::db::tests::new_test_user;
+use crate::db::CreateModelExperimentVariantParams;
+use crate::model_experiments::recommended_model_for_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_model_experiments,
+    test_model_experiments_postgres,
+    test_model_experiments_sqlite
+);
+
+async fn test_model_experiments(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+
+    assert_eq!(recommended_model_for_user(db, user_id).await.unwrap(), None);
+
+    let (experiment, variants) = db
+        .create_model_experiment(
+            "default-model",
+            &[CreateModelExperimentVariantParams {
+                name: "sonnet".into(),
+                weight: 1,
+                provider: "zed.dev".into(),
+                model: "claude-3-5-sonnet".into(),
+                temperature: Some(0.5),
+            }],
+        )
+        .await
+        .unwrap();
+
+    let recommendation = recommended_model_for_user(db, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(recommendation.experiment, "default-model");
+    assert_eq!(recommendation.variant, "sonnet");
+    assert_eq!(recommendation.model, "claude-3-5-sonnet");
+    assert_eq!(recommendation.temperature, Some(0.5));
+
+    // Exposures are only recorded once per user.
+    recommended_model_for_user(db, user_id).await.unwrap();
+    let counts = db
+        .get_model_experiment_exposure_counts(experiment.id)
+        .await
+        .unwrap();
+    assert_eq!(counts.get(&variants[0].id), Some(&1));
+
+    db.deactivate_model_experiment(experiment.id).await.unwrap();
+    assert_eq!(recommended_model_for_user(db, user_id).await.unwrap(), None);
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::organization_member::OrganizationRole;
+use crate::db::tests::new_test_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_organization_secrets,
+    test_organization_secrets_postgres,
+    test_organization_secrets_sqlite
+);
+
+async fn test_organization_secrets(db: &Arc<Database>) {
+    let member_id = new_test_user(db, "member@example.com").await;
+    let outsider_id = new_test_user(db, "outsider@example.com").await;
+
+    let acme = db.create_organization("Acme", "acme").await.unwrap();
+    let initech = db.create_organization("Initech", "initech").await.unwrap();
+    for organization in [&acme, &initech] {
+        db.add_organization_member(organization.id, member_id, OrganizationRole::Member)
+            .await
+            .unwrap();
+    }
+
+    db.upsert_organization_secret(acme.id, "anthropic", "sk-ant-1")
+        .await
+        .unwrap();
+    db.upsert_organization_secret(acme.id, "anthropic", "sk-ant-2")
+        .await
+        .unwrap();
+    db.upsert_organization_secret(acme.id, "openai", "sk-acme")
+        .await
+        .unwrap();
+    db.upsert_organization_secret(initech.id, "openai", "sk-initech")
+        .await
+        .unwrap();
+
+    let lease_duration = Duration::from_secs(15 * 60);
+    let lease = db
+        .lease_organization_secret(member_id, None, "anthropic", lease_duration)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.value, "sk-ant-2");
+
+    // Secrets that more than one organization has must be qualified.
+    assert!(db
+        .lease_organization_secret(member_id, None, "openai", lease_duration)
+        .await
+        .is_err());
+    assert_eq!(
+        db.lease_organization_secret(member_id, Some("initech"), "openai", lease_duration)
+            .await
+            .unwrap()
+            .unwrap()
+            .value,
+        "sk-initech"
+    );
+
+    // Only members can lease an organization's secrets.
+    assert_eq!(
+        db.lease_organization_secret(outsider_id, Some("acme"), "anthropic", lease_duration)
+            .await
+            .unwrap(),
+        None
+    );
+
+    db.delete_organization_secret(acme.id, "anthropic")
+        .await
+        .unwrap();
+    assert_eq!(
+        db.lease_organization_secret(member_id, None, "anthropic", lease_duration)
+            .await
+            .unwrap(),
+        None
+    );
+}
@@ -0,0 +1,527 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::organization::SeatPolicy;
+use crate::db::organization_member::OrganizationRole;
+use crate::db::organization_seat_true_up::SeatTrueUpOutcome;
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams, CreateLanguageModelUsageParams,
+    CreateOrganizationSeatTrueUpParams,
+};
+use crate::entitlements::{BillingMode, Entitlements, Plan};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_organization_usage,
+    test_organization_usage_postgres,
+    test_organization_usage_sqlite
+);
+
+async fn test_organization_usage(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let member_id = new_test_user(db, "member@example.com").await;
+    let outsider_id = new_test_user(db, "outsider@example.com").await;
+
+    let organization = db.create_organization("Acme", "acme").await.unwrap();
+    db.add_organization_member(organization.id, admin_id, OrganizationRole::Admin)
+        .await
+        .unwrap();
+    db.add_organization_member(organization.id, member_id, OrganizationRole::Member)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        db.get_organization_member(organization.id, admin_id)
+            .await
+            .unwrap()
+            .map(|member| member.role),
+        Some(OrganizationRole::Admin)
+    );
+    assert_eq!(
+        db.get_organization_member(organization.id, outsider_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.get_organization_memberships_for_user(member_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|member| member.organization_id)
+            .collect::<Vec<_>>(),
+        [organization.id]
+    );
+    assert!(db
+        .get_organization_memberships_for_user(outsider_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    for user_id in [admin_id, member_id, member_id, outsider_id] {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: "anthropic".into(),
+            model: "claude-3-5-sonnet".into(),
+            input_tokens: 100,
+            output_tokens: 10,
+            feature: None,
+            upstream_cost_in_millicents: 50,
+        })
+        .await
+        .unwrap();
+    }
+
+    let member_ids = db
+        .get_organization_members(organization.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect::<Vec<_>>();
+    let now = OffsetDateTime::now_utc();
+    let usages = db
+        .get_daily_language_model_usage_for_users(
+            &member_ids,
+            now - Duration::days(1),
+            now + Duration::days(1),
+        )
+        .await
+        .unwrap();
+
+    let mut totals = usages
+        .iter()
+        .map(|usage| {
+            (
+                usage.user_id,
+                usage.request_count,
+                usage.input_tokens,
+                usage.upstream_cost_in_millicents,
+            )
+        })
+        .collect::<Vec<_>>();
+    totals.sort();
+    assert_eq!(totals, [(admin_id, 1, 100, 50), (member_id, 2, 200, 100)]);
+}
+
+test_both_dbs!(
+    test_organization_member_daily_usages,
+    test_organization_member_daily_usages_postgres,
+    test_organization_member_daily_usages_sqlite
+);
+
+async fn test_organization_member_daily_usages(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let member_id = new_test_user(db, "member@example.com").await;
+    let outsider_id = new_test_user(db, "outsider@example.com").await;
+
+    let organization = db.create_organization("Acme", "acme").await.unwrap();
+    db.add_organization_member(organization.id, admin_id, OrganizationRole::Admin)
+        .await
+        .unwrap();
+    db.add_organization_member(organization.id, member_id, OrganizationRole::Member)
+        .await
+        .unwrap();
+
+    // Usage is attributed to a single organization, the first one the member
+    // joined here, rather than to every organization they're a member of.
+    let other_organization = db.create_organization("Globex", "globex").await.unwrap();
+    db.add_organization_member(other_organization.id, admin_id, OrganizationRole::Member)
+        .await
+        .unwrap();
+
+    for (user_id, model, input_tokens) in [
+        (admin_id, "claude-3-5-sonnet", 100),
+        (member_id, "claude-3-5-sonnet", 100),
+        (member_id, "claude-3-haiku", 1_000),
+        (outsider_id, "claude-3-5-sonnet", 100),
+    ] {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: "anthropic".into(),
+            model: model.into(),
+            input_tokens,
+            output_tokens: 10,
+            feature: None,
+            upstream_cost_in_millicents: 50,
+        })
+        .await
+        .unwrap();
+    }
+
+    // Roll up the day before as well, in case the usages were recorded just
+    // before midnight.
+    let today = OffsetDateTime::now_utc().date();
+    let yesterday = today - Duration::days(1);
+    for _ in 0..2 {
+        // Rolling up the same day again replaces the earlier rollup.
+        for date in [yesterday, today] {
+            db.roll_up_organization_member_daily_usages(organization.id, date)
+                .await
+                .unwrap();
+        }
+    }
+    db.set_organization_usage_rolled_up_through(organization.id, today)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_organization_by_id(organization.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .usage_rolled_up_through,
+        Some(today)
+    );
+
+    let usages = db
+        .get_organization_member_daily_usages(organization.id, yesterday, today)
+        .await
+        .unwrap();
+    let mut totals = collections::HashMap::<_, (i64, i64, i64)>::default();
+    for usage in &usages {
+        let total = totals.entry(usage.user_id).or_default();
+        total.0 += usage.request_count;
+        total.1 += usage.input_tokens;
+        total.2 += usage.estimated_cost_in_millicents;
+    }
+    assert_eq!(totals.len(), 2);
+    assert_eq!(totals[&admin_id], (1, 100, 50));
+    assert_eq!(totals[&member_id], (2, 1_100, 100));
+
+    let member_usage = usages
+        .iter()
+        .find(|usage| usage.user_id == member_id)
+        .unwrap();
+    assert_eq!(
+        member_usage
+            .top_models
+            .0
+            .iter()
+            .map(|model| model.model.as_str())
+            .collect::<Vec<_>>(),
+        ["claude-3-haiku", "claude-3-5-sonnet"]
+    );
+
+    assert!(db
+        .get_organization_member_daily_usages(
+            organization.id,
+            today + Duration::days(1),
+            today + Duration::days(2)
+        )
+        .await
+        .unwrap()
+        .is_empty());
+
+    for date in [yesterday, today] {
+        db.roll_up_organization_member_daily_usages(other_organization.id, date)
+            .await
+            .unwrap();
+    }
+    assert!(db
+        .get_organization_member_daily_usages(other_organization.id, yesterday, today)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // The usage of members who leave is no longer rolled up.
+    db.remove_organization_member(organization.id, member_id)
+        .await
+        .unwrap();
+    for date in [yesterday, today] {
+        db.roll_up_organization_member_daily_usages(organization.id, date)
+            .await
+            .unwrap();
+    }
+    let user_ids = db
+        .get_organization_member_daily_usages(organization.id, yesterday, today)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|usage| usage.user_id)
+        .collect::<Vec<_>>();
+    assert_eq!(user_ids, [admin_id]);
+}
+
+test_both_dbs!(
+    test_organization_seat_true_ups,
+    test_organization_seat_true_ups_postgres,
+    test_organization_seat_true_ups_sqlite
+);
+
+async fn test_organization_seat_true_ups(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let member_id = new_test_user(db, "member@example.com").await;
+
+    let organization = db.create_organization("Acme", "acme").await.unwrap();
+    db.add_organization_member(organization.id, admin_id, OrganizationRole::Admin)
+        .await
+        .unwrap();
+    db.add_organization_member(organization.id, member_id, OrganizationRole::Member)
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_organization_member_count(organization.id)
+            .await
+            .unwrap(),
+        2
+    );
+    assert!(db
+        .get_organizations_with_billing_subscriptions()
+        .await
+        .unwrap()
+        .is_empty());
+
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id: admin_id,
+            stripe_customer_id: "cus_admin".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_team".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        plan: SubscriptionPlan::Team,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let subscription = db
+        .get_billing_subscriptions(admin_id)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    let organization = db
+        .set_organization_seat_billing(
+            organization.id,
+            Some(subscription.id),
+            SeatPolicy::AutoAdjust,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(organization.billing_subscription_id, Some(subscription.id));
+
+    // A subscription can only pay for one organization's seats.
+    let other_organization = db.create_organization("Other", "other").await.unwrap();
+    assert_eq!(
+        db.set_organization_seat_billing(
+            other_organization.id,
+            Some(subscription.id),
+            SeatPolicy::Flag
+        )
+        .await
+        .unwrap(),
+        None
+    );
+    assert_eq!(organization.seat_policy, SeatPolicy::AutoAdjust);
+    let organizations = db
+        .get_organizations_with_billing_subscriptions()
+        .await
+        .unwrap();
+    assert_eq!(
+        organizations
+            .iter()
+            .map(|organization| organization.id)
+            .collect::<Vec<_>>(),
+        vec![organization.id]
+    );
+
+    for (member_count, outcome) in [
+        (2, SeatTrueUpOutcome::Adjusted),
+        (3, SeatTrueUpOutcome::Flagged),
+    ] {
+        db.record_organization_seat_true_up(&CreateOrganizationSeatTrueUpParams {
+            organization_id: organization.id,
+            billing_subscription_id: subscription.id,
+            member_count,
+            seat_count: 1,
+            outcome,
+        })
+        .await
+        .unwrap();
+    }
+    let true_ups = db
+        .get_organization_seat_true_ups(organization.id, 10)
+        .await
+        .unwrap();
+    assert_eq!(
+        true_ups
+            .iter()
+            .map(|true_up| (true_up.member_count, true_up.outcome))
+            .collect::<Vec<_>>(),
+        vec![
+            (3, SeatTrueUpOutcome::Flagged),
+            (2, SeatTrueUpOutcome::Adjusted)
+        ]
+    );
+    assert_eq!(
+        db.get_organization_seat_true_ups(organization.id, 1)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let organization = db
+        .set_organization_seat_billing(organization.id, None, SeatPolicy::Flag)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(organization.billing_subscription_id, None);
+    assert!(db
+        .get_organizations_with_billing_subscriptions()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_organization_invitations,
+    test_organization_invitations_postgres,
+    test_organization_invitations_sqlite
+);
+
+async fn test_organization_invitations(db: &Arc<Database>) {
+    let owner_id = new_test_user(db, "owner@example.com").await;
+    let invitee_id = new_test_user(db, "invitee@example.com").await;
+    let plan = |db: Arc<Database>, user_id| async move {
+        Entitlements::for_user(&db, BillingMode::Stripe, user_id)
+            .await
+            .unwrap()
+            .plan
+    };
+
+    let organization = db
+        .create_organization_with_owner("Acme", "acme", owner_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        db.get_organization_member(organization.id, owner_id)
+            .await
+            .unwrap()
+            .map(|member| member.role),
+        Some(OrganizationRole::Owner)
+    );
+    assert_eq!(
+        db.create_organization_with_owner("Acme 2", "acme", invitee_id)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // The owner's team subscription pays for the organization's seats.
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id: owner_id,
+            stripe_customer_id: "cus_owner".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_team".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        plan: SubscriptionPlan::Team,
+        seat_count: 2,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let subscription = db
+        .get_billing_subscriptions(owner_id)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    db.set_organization_seat_billing(organization.id, Some(subscription.id), SeatPolicy::Flag)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Invitees aren't covered until they accept.
+    db.invite_organization_member(
+        organization.id,
+        invitee_id,
+        OrganizationRole::Admin,
+        owner_id,
+    )
+    .await
+    .unwrap();
+    let invitation = db
+        .invite_organization_member(
+            organization.id,
+            invitee_id,
+            OrganizationRole::Member,
+            owner_id,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_organization_invitations(organization.id)
+            .await
+            .unwrap(),
+        [invitation]
+    );
+    assert_eq!(plan(db.clone(), invitee_id).await, Plan::Free);
+
+    let member = db
+        .accept_organization_invitation(organization.id, invitee_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(member.role, OrganizationRole::Member);
+    assert!(db
+        .get_organization_invitations(organization.id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.accept_organization_invitation(organization.id, invitee_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(plan(db.clone(), invitee_id).await, Plan::Team);
+
+    assert!(db
+        .remove_organization_member(organization.id, invitee_id)
+        .await
+        .unwrap());
+    assert!(!db
+        .remove_organization_member(organization.id, invitee_id)
+        .await
+        .unwrap());
+    assert_eq!(plan(db.clone(), invitee_id).await, Plan::Free);
+
+    // Declined invitations are gone.
+    db.invite_organization_member(
+        organization.id,
+        invitee_id,
+        OrganizationRole::Member,
+        owner_id,
+    )
+    .await
+    .unwrap();
+    assert!(db
+        .delete_organization_invitation(organization.id, invitee_id)
+        .await
+        .unwrap());
+    assert_eq!(
+        db.accept_organization_invitation(organization.id, invitee_id)
+            .await
+            .unwrap(),
+        None
+    );
+}
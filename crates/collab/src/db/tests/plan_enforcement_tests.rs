@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+use time::Duration;
+
+use crate::db::organization_member::OrganizationRole;
+use crate::db::plan_enforcement_action::PlanEnforcementActionKind;
+use crate::db::tests::new_test_user;
+use crate::db::ChannelVisibility;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_plan_enforcement_actions,
+    test_plan_enforcement_actions_postgres,
+    test_plan_enforcement_actions_sqlite
+);
+
+async fn test_plan_enforcement_actions(db: &Arc<Database>) {
+    let user = new_test_user(db, "user@example.com").await;
+    let scheduled_for = datetime!(2024-09-01 12:00);
+
+    let action = db
+        .schedule_plan_enforcement_action(
+            user,
+            PlanEnforcementActionKind::ArchiveExcessChannels,
+            scheduled_for,
+        )
+        .await
+        .unwrap();
+    assert!(action.is_pending());
+
+    // Scheduling a pending action again keeps the original schedule.
+    assert_eq!(
+        db.schedule_plan_enforcement_action(
+            user,
+            PlanEnforcementActionKind::ArchiveExcessChannels,
+            scheduled_for + Duration::days(7),
+        )
+        .await
+        .unwrap(),
+        action
+    );
+
+    assert!(db
+        .get_plan_enforcement_actions_to_notify(scheduled_for - Duration::days(4))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.get_plan_enforcement_actions_to_notify(scheduled_for - Duration::days(3))
+            .await
+            .unwrap(),
+        vec![action.clone()]
+    );
+    db.mark_plan_enforcement_action_notified(action.id, scheduled_for - Duration::days(3))
+        .await
+        .unwrap();
+    assert!(db
+        .get_plan_enforcement_actions_to_notify(scheduled_for)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert!(db
+        .get_due_plan_enforcement_actions(scheduled_for - Duration::minutes(1))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.get_due_plan_enforcement_actions(scheduled_for)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    db.mark_plan_enforcement_action_enforced(action.id, scheduled_for)
+        .await
+        .unwrap();
+    assert!(db
+        .get_due_plan_enforcement_actions(scheduled_for)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Enforced actions remain in force until they're reverted.
+    let actions = db.get_plan_enforcement_actions(user).await.unwrap();
+    assert_eq!(actions.len(), 1);
+    assert!(!actions[0].is_pending());
+    db.mark_plan_enforcement_action_reverted(action.id, scheduled_for + Duration::days(1))
+        .await
+        .unwrap();
+    assert!(db
+        .get_plan_enforcement_actions(user)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Once an action is enforced, a new one can be scheduled.
+    let new_action = db
+        .schedule_plan_enforcement_action(
+            user,
+            PlanEnforcementActionKind::ArchiveExcessChannels,
+            scheduled_for + Duration::days(30),
+        )
+        .await
+        .unwrap();
+    assert_ne!(new_action.id, action.id);
+    db.mark_plan_enforcement_action_canceled(new_action.id, scheduled_for + Duration::days(2))
+        .await
+        .unwrap();
+    assert!(db
+        .get_plan_enforcement_actions(user)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_archive_excess_root_channels,
+    test_archive_excess_root_channels_postgres,
+    test_archive_excess_root_channels_sqlite
+);
+
+async fn test_archive_excess_root_channels(db: &Arc<Database>) {
+    let user = new_test_user(db, "user@example.com").await;
+    let channel_1 = db.create_root_channel("one", user).await.unwrap();
+    let channel_2 = db.create_root_channel("two", user).await.unwrap();
+    let channel_3 = db.create_root_channel("three", user).await.unwrap();
+    let channel_4 = db.create_root_channel("four", user).await.unwrap();
+    db.set_channel_visibility(channel_4, ChannelVisibility::Public, user)
+        .await
+        .unwrap();
+
+    // The newest private channels beyond the limit are archived, while
+    // public ones are kept and don't count towards it.
+    assert_eq!(
+        db.archive_excess_root_channels(user, 2).await.unwrap(),
+        vec![channel_3]
+    );
+    assert_eq!(
+        db.archive_excess_root_channels(user, 2).await.unwrap(),
+        Vec::new()
+    );
+    assert_eq!(
+        db.get_administered_root_channel_count(user).await.unwrap(),
+        3
+    );
+    assert_eq!(
+        db.get_administered_private_root_channel_count_including_archived(user)
+            .await
+            .unwrap(),
+        3
+    );
+    let channel_ids = db
+        .get_channels_for_user(user)
+        .await
+        .unwrap()
+        .channels
+        .iter()
+        .map(|channel| channel.id)
+        .collect::<Vec<_>>();
+    assert_eq!(channel_ids, vec![channel_1, channel_2, channel_4]);
+
+    assert_eq!(
+        db.unarchive_administered_root_channels(user).await.unwrap(),
+        vec![channel_3]
+    );
+    assert_eq!(
+        db.get_administered_root_channel_count(user).await.unwrap(),
+        4
+    );
+}
+
+test_both_dbs!(
+    test_disable_organization_features,
+    test_disable_organization_features_postgres,
+    test_disable_organization_features_sqlite
+);
+
+async fn test_disable_organization_features(db: &Arc<Database>) {
+    let admin = new_test_user(db, "admin@example.com").await;
+    let member = new_test_user(db, "member@example.com").await;
+    let organization = db.create_organization("Zed", "zed").await.unwrap();
+    db.add_organization_member(organization.id, admin, OrganizationRole::Admin)
+        .await
+        .unwrap();
+    db.add_organization_member(organization.id, member, OrganizationRole::Member)
+        .await
+        .unwrap();
+
+    // Only the organizations a user administers are affected.
+    assert!(db
+        .set_administered_organization_features_disabled(member, true)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.set_administered_organization_features_disabled(admin, true)
+            .await
+            .unwrap(),
+        vec![organization.id]
+    );
+    assert!(db
+        .get_organization_by_id(organization.id)
+        .await
+        .unwrap()
+        .unwrap()
+        .features_disabled_at
+        .is_some());
+
+    assert_eq!(
+        db.set_administered_organization_features_disabled(admin, false)
+            .await
+            .unwrap(),
+        vec![organization.id]
+    );
+    assert!(db
+        .get_organization_by_id(organization.id)
+        .await
+        .unwrap()
+        .unwrap()
+        .features_disabled_at
+        .is_none());
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::tests::new_test_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_referrals,
+    test_referrals_postgres,
+    test_referrals_sqlite
+);
+
+async fn test_referrals(db: &Arc<Database>) {
+    let referrer_id = new_test_user(db, "referrer@example.com").await;
+    let referred_id = new_test_user(db, "referred@example.com").await;
+
+    // Referral codes are stable once created.
+    let referral_code = db.get_or_create_referral_code(referrer_id).await.unwrap();
+    assert_eq!(
+        db.get_or_create_referral_code(referrer_id).await.unwrap(),
+        referral_code
+    );
+    assert_eq!(
+        db.get_referral_code_by_code(&referral_code.code)
+            .await
+            .unwrap()
+            .map(|code| code.user_id),
+        Some(referrer_id)
+    );
+
+    let referral = db.create_referral(referrer_id, referred_id).await.unwrap();
+    assert_eq!(
+        db.get_referral_for_referred_user(referred_id)
+            .await
+            .unwrap()
+            .map(|referral| referral.referrer_user_id),
+        Some(referrer_id)
+    );
+
+    // A user can only be referred once.
+    assert!(db.create_referral(referrer_id, referred_id).await.is_err());
+
+    assert_eq!(
+        db.count_referrals_since(referrer_id, OffsetDateTime::now_utc() - Duration::days(1))
+            .await
+            .unwrap(),
+        1
+    );
+
+    // A referral is only credited once.
+    assert!(db.mark_referrer_credited(referral.id).await.unwrap());
+    assert!(!db.mark_referrer_credited(referral.id).await.unwrap());
+
+    db.unmark_referrer_credited(referral.id).await.unwrap();
+
+    // Credits that can't be applied yet are kept until they can be.
+    assert!(db
+        .get_pending_referrer_credits(referrer_id)
+        .await
+        .unwrap()
+        .is_empty());
+    db.mark_referrer_credit_pending(referral.id).await.unwrap();
+    let pending = db.get_pending_referrer_credits(referrer_id).await.unwrap();
+    assert_eq!(
+        pending
+            .iter()
+            .map(|referral| referral.id)
+            .collect::<Vec<_>>(),
+        [referral.id]
+    );
+    assert!(pending[0].referrer_credit_pending_since.is_some());
+
+    assert!(db.mark_referrer_credited(referral.id).await.unwrap());
+    assert!(db
+        .get_pending_referrer_credits(referrer_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Credits the referrer isn't eligible for are no longer pending.
+    let other_referred_id = new_test_user(db, "other-referred@example.com").await;
+    let other_referral = db
+        .create_referral(referrer_id, other_referred_id)
+        .await
+        .unwrap();
+    db.mark_referrer_credit_pending(other_referral.id)
+        .await
+        .unwrap();
+    db.mark_referrer_credit_skipped(other_referral.id)
+        .await
+        .unwrap();
+    assert!(db
+        .get_pending_referrer_credits(referrer_id)
+        .await
+        .unwrap()
+        .is_empty());
+    let other_referral = db
+        .get_referral_for_referred_user(other_referred_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(other_referral.referrer_credit_skipped_at.is_some());
+    assert!(other_referral.referrer_credited_at.is_none());
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+
+use crate::db::organization_member::OrganizationRole;
+use crate::db::tests::new_test_user;
+use crate::db::{CreateBillingCustomerParams, CreateSandboxOrganizationParams};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_sandbox_organizations,
+    test_sandbox_organizations_postgres,
+    test_sandbox_organizations_sqlite
+);
+
+async fn test_sandbox_organizations(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@example.com").await;
+    let (organization, users) = db
+        .create_sandbox_organization(&CreateSandboxOrganizationParams {
+            created_by_user_id: admin_id,
+            member_count: 3,
+            expires_at: datetime!(2024-09-10 12:00),
+        })
+        .await
+        .unwrap();
+    assert_eq!(users.len(), 3);
+    assert!(users
+        .iter()
+        .all(|user| user.github_user_id.unwrap() < 0 && !user.admin));
+
+    let members = db.get_organization_members(organization.id).await.unwrap();
+    assert_eq!(
+        members
+            .iter()
+            .map(|member| (member.user_id, member.role))
+            .collect::<Vec<_>>(),
+        vec![
+            (users[0].id, OrganizationRole::Admin),
+            (users[1].id, OrganizationRole::Member),
+            (users[2].id, OrganizationRole::Member),
+        ]
+    );
+
+    assert!(db
+        .get_expired_sandbox_organizations(datetime!(2024-09-10 11:00))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.get_expired_sandbox_organizations(datetime!(2024-09-10 13:00))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|sandbox| sandbox.organization_id)
+            .collect::<Vec<_>>(),
+        vec![organization.id]
+    );
+
+    db.create_billing_customer(&CreateBillingCustomerParams {
+        user_id: users[0].id,
+        stripe_customer_id: "cus_sandbox".into(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    // Real users added to the sandbox survive its teardown.
+    let real_user_id = new_test_user(db, "real@example.com").await;
+    db.add_organization_member(organization.id, real_user_id, OrganizationRole::Member)
+        .await
+        .unwrap();
+    db.create_billing_customer(&CreateBillingCustomerParams {
+        user_id: real_user_id,
+        stripe_customer_id: "cus_real".into(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        db.get_sandbox_organization_user_ids(organization.id)
+            .await
+            .unwrap(),
+        users.iter().map(|user| user.id).collect::<Vec<_>>()
+    );
+
+    // Organizations that aren't sandboxes can't be destroyed this way.
+    let other_organization = db.create_organization("Zed", "zed").await.unwrap();
+    assert_eq!(
+        db.destroy_sandbox_organization(other_organization.id)
+            .await
+            .unwrap(),
+        None
+    );
+
+    assert_eq!(
+        db.destroy_sandbox_organization(organization.id)
+            .await
+            .unwrap(),
+        Some(users.iter().map(|user| user.id).collect())
+    );
+    assert!(db
+        .get_organization_by_id(organization.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .get_sandbox_organization(organization.id)
+        .await
+        .unwrap()
+        .is_none());
+    for user in &users {
+        assert!(db.get_user_by_id(user.id).await.unwrap().is_none());
+    }
+    assert!(db
+        .get_billing_customer_by_stripe_customer_id("cus_sandbox")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db.get_user_by_id(admin_id).await.unwrap().is_some());
+    assert!(db.get_user_by_id(real_user_id).await.unwrap().is_some());
+    assert!(db
+        .get_billing_customer_by_stripe_customer_id("cus_real")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(db
+        .get_organization_by_id(other_organization.id)
+        .await
+        .unwrap()
+        .is_some());
+}
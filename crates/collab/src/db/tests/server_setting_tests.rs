@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::tests::new_test_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_server_settings,
+    test_server_settings_postgres,
+    test_server_settings_sqlite
+);
+
+async fn test_server_settings(db: &Arc<Database>) {
+    let admin = new_test_user(db, "admin@example.com").await;
+    assert!(db.get_server_settings().await.unwrap().is_empty());
+
+    db.set_server_setting("maintenance_mode", Some("true"), Some(admin))
+        .await
+        .unwrap();
+    db.set_server_setting("maintenance_mode", Some("false"), Some(admin))
+        .await
+        .unwrap();
+    db.set_server_setting(
+        "stripe_events_poll_interval_in_seconds",
+        Some("60"),
+        Some(admin),
+    )
+    .await
+    .unwrap();
+
+    let settings = db
+        .get_server_settings()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|setting| (setting.key, setting.value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        settings,
+        [
+            ("maintenance_mode".to_string(), "false".to_string()),
+            (
+                "stripe_events_poll_interval_in_seconds".to_string(),
+                "60".to_string()
+            ),
+        ]
+    );
+
+    // Resetting a setting removes it, and every change is recorded.
+    db.set_server_setting("maintenance_mode", None, Some(admin))
+        .await
+        .unwrap();
+    assert_eq!(db.get_server_settings().await.unwrap().len(), 1);
+
+    let changes = db
+        .get_server_setting_changes(10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|change| (change.key, change.previous_value, change.value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            (
+                "maintenance_mode".to_string(),
+                Some("false".to_string()),
+                None
+            ),
+            (
+                "stripe_events_poll_interval_in_seconds".to_string(),
+                None,
+                Some("60".to_string())
+            ),
+            (
+                "maintenance_mode".to_string(),
+                Some("true".to_string()),
+                Some("false".to_string())
+            ),
+            (
+                "maintenance_mode".to_string(),
+                None,
+                Some("true".to_string())
+            ),
+        ]
+    );
+    let latest_change = db
+        .get_latest_server_setting_change("maintenance_mode")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest_change.value, None);
+    assert_eq!(latest_change.changed_by_user_id, Some(admin));
+    assert!(db
+        .get_latest_server_setting_change("rate_limits")
+        .await
+        .unwrap()
+        .is_none());
+
+    // Old changes can be removed from the audit log, oldest first.
+    let now = OffsetDateTime::now_utc();
+    assert!(db
+        .get_server_setting_changes_before(now - Duration::days(1), 10)
+        .await
+        .unwrap()
+        .is_empty());
+    let old_changes = db
+        .get_server_setting_changes_before(now + Duration::days(1), 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        old_changes
+            .iter()
+            .map(|change| change.value.as_deref())
+            .collect::<Vec<_>>(),
+        [Some("true"), Some("false")]
+    );
+    db.delete_server_setting_changes(
+        &old_changes
+            .iter()
+            .map(|change| change.id)
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(db.get_server_setting_changes(10).await.unwrap().len(), 2);
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_stripe_event_cursors,
+    test_stripe_event_cursors_postgres,
+    test_stripe_event_cursors_sqlite
+);
+
+async fn test_stripe_event_cursors(db: &Arc<Database>) {
+    assert!(db
+        .get_stripe_event_cursor("billing")
+        .await
+        .unwrap()
+        .is_none());
+
+    db.update_stripe_event_cursor("billing", "evt_1", datetime!(2024-09-01 0:00))
+        .await
+        .unwrap();
+    db.update_stripe_event_cursor("billing", "evt_2", datetime!(2024-09-01 0:05))
+        .await
+        .unwrap();
+    db.update_stripe_event_cursor("other", "evt_3", datetime!(2024-09-01 0:10))
+        .await
+        .unwrap();
+
+    let cursor = db
+        .get_stripe_event_cursor("billing")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(cursor.last_event_id, "evt_2");
+    assert_eq!(cursor.last_event_created_at, datetime!(2024-09-01 0:05));
+
+    let cursor = db.get_stripe_event_cursor("other").await.unwrap().unwrap();
+    assert_eq!(cursor.last_event_id, "evt_3");
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::complimentary_subscription::ComplimentarySubscriptionKind;
+use crate::db::tests::new_test_user;
+use crate::db::trial_abuse_flag::{TrialAbuseDecision, TrialAbuseReason};
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams,
+    CreateComplimentarySubscriptionParams, UserId,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_past_trials,
+    test_past_trials_postgres,
+    test_past_trials_sqlite
+);
+
+async fn test_past_trials(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@zed.dev").await;
+    let trial_user_id = new_test_user(db, "jdoe@example.com").await;
+    let uppercase_trial_user_id = new_test_user(db, "JRoe@EXAMPLE.com").await;
+    let granted_user_id = new_test_user(db, "granted@example.com").await;
+    let paying_user_id = new_test_user(db, "paying@example.com").await;
+    let other_domain_user_id = new_test_user(db, "other@example.org").await;
+
+    let subscribe = |user_id: UserId, trial: bool| async move {
+        let customer = db
+            .create_billing_customer(&CreateBillingCustomerParams {
+                user_id,
+                stripe_customer_id: format!("cus_{user_id}"),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        db.create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: format!("sub_{user_id}"),
+            stripe_subscription_status: StripeSubscriptionStatus::Trialing,
+            trial_end: trial.then_some(datetime!(2024-09-22 0:00)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        customer
+    };
+    let trial_customer = subscribe(trial_user_id, true).await;
+    subscribe(uppercase_trial_user_id, true).await;
+    let paying_customer = subscribe(paying_user_id, false).await;
+    subscribe(other_domain_user_id, true).await;
+    db.create_complimentary_subscription(&CreateComplimentarySubscriptionParams {
+        user_id: granted_user_id,
+        plan: SubscriptionPlan::Pro,
+        kind: ComplimentarySubscriptionKind::Giveaway,
+        reason: None,
+        granted_by_user_id: admin_id,
+        expires_at: None,
+    })
+    .await
+    .unwrap();
+
+    // Users that have neither had a trial nor a grant aren't included.
+    let mut user_ids = db
+        .get_past_trials_by_email_domain("Example.com")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|trial| trial.user_id)
+        .collect::<Vec<_>>();
+    user_ids.sort();
+    assert_eq!(
+        user_ids,
+        [trial_user_id, uppercase_trial_user_id, granted_user_id]
+    );
+    assert!(db
+        .get_past_trials_by_email_domain("%")
+        .await
+        .unwrap()
+        .is_empty());
+
+    for customer in [&trial_customer, &paying_customer] {
+        db.update_billing_customer_payment_method_fingerprint(customer.id, "fp_shared")
+            .await
+            .unwrap();
+    }
+    let past_trials = db
+        .get_past_trials_by_payment_method_fingerprint("fp_shared")
+        .await
+        .unwrap();
+    assert_eq!(past_trials.len(), 1);
+    assert_eq!(past_trials[0].user_id, trial_user_id);
+    assert_eq!(
+        past_trials[0].email_address.as_deref(),
+        Some("jdoe@example.com")
+    );
+    assert!(db
+        .get_past_trials_by_payment_method_fingerprint("fp_unused")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_trial_abuse_flags,
+    test_trial_abuse_flags_postgres,
+    test_trial_abuse_flags_sqlite
+);
+
+async fn test_trial_abuse_flags(db: &Arc<Database>) {
+    let admin_id = new_test_user(db, "admin@zed.dev").await;
+    let user_id = new_test_user(db, "jdoe+2@example.com").await;
+
+    let flag = db
+        .create_trial_abuse_flag(
+            user_id,
+            TrialAbuseReason::SharedEmailAddress,
+            "jdoe+2@example.com is an alias",
+        )
+        .await
+        .unwrap();
+    assert!(flag.blocks_trials());
+    assert_eq!(
+        db.get_undecided_trial_abuse_flags().await.unwrap(),
+        [flag.clone()]
+    );
+
+    let decided_flag = db
+        .decide_trial_abuse_flag(
+            flag.id,
+            TrialAbuseDecision::Allow,
+            Some("shared family account"),
+            admin_id,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(decided_flag.decision, Some(TrialAbuseDecision::Allow));
+    assert_eq!(decided_flag.decided_by_user_id, Some(admin_id));
+    assert!(decided_flag.decided_at.is_some());
+
+    // The decision is audited, with the admin who made it.
+    let audit_log = db.get_billing_audit_log(Some(user_id), 10).await.unwrap();
+    assert_eq!(audit_log.len(), 1);
+    assert_eq!(audit_log[0].event, BillingAuditEvent::TrialAbuseFlagDecided);
+    assert_eq!(audit_log[0].actor_user_id, Some(admin_id));
+    assert!(!decided_flag.blocks_trials());
+    assert!(db
+        .get_undecided_trial_abuse_flags()
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Decisions are final.
+    assert_eq!(
+        db.decide_trial_abuse_flag(flag.id, TrialAbuseDecision::Block, None, admin_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.get_trial_abuse_flags(user_id).await.unwrap(),
+        [decided_flag]
+    );
+}
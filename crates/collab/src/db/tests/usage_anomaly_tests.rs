@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::db::billing_audit_log_entry::BillingAuditEvent;
+use crate::db::tests::new_test_user;
+use crate::db::CreateLanguageModelUsageParams;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_usage_anomalies,
+    test_usage_anomalies_postgres,
+    test_usage_anomalies_sqlite
+);
+
+async fn test_usage_anomalies(db: &Arc<Database>) {
+    let user_1 = new_test_user(db, "user1@example.com").await;
+    let user_2 = new_test_user(db, "user2@example.com").await;
+
+    for (user_id, input_tokens, output_tokens) in
+        [(user_1, 100, 20), (user_1, 50, 5), (user_2, 7, 3)]
+    {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: "anthropic".into(),
+            model: "claude-3-5-sonnet-20240620".into(),
+            input_tokens,
+            output_tokens,
+            feature: None,
+            upstream_cost_in_millicents: 0,
+        })
+        .await
+        .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let usage = db
+        .get_language_model_token_usage_by_user(now - Duration::days(1), now + Duration::days(1))
+        .await
+        .unwrap();
+    assert_eq!(usage.get(&user_1), Some(&175));
+    assert_eq!(usage.get(&user_2), Some(&10));
+
+    let usage = db
+        .get_language_model_token_usage_by_user(now - Duration::days(2), now - Duration::days(1))
+        .await
+        .unwrap();
+    assert!(usage.is_empty());
+
+    assert!(!db.has_unreviewed_usage_anomaly(user_1).await.unwrap());
+    let anomaly = db.create_usage_anomaly(user_1, 175, 10).await.unwrap();
+    assert!(db.has_unreviewed_usage_anomaly(user_1).await.unwrap());
+    assert_eq!(
+        db.get_unreviewed_usage_anomalies().await.unwrap(),
+        vec![anomaly.clone()]
+    );
+    let audit_log = db.get_billing_audit_log(Some(user_1), 10).await.unwrap();
+    assert_eq!(audit_log.len(), 1);
+    assert_eq!(audit_log[0].event, BillingAuditEvent::UsageAnomalyDetected);
+    assert_eq!(audit_log[0].actor_user_id, None);
+
+    db.mark_usage_anomaly_reviewed(anomaly.id).await.unwrap();
+    assert!(!db.has_unreviewed_usage_anomaly(user_1).await.unwrap());
+    assert!(db
+        .get_unreviewed_usage_anomalies()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+test_both_dbs!(
+    test_language_model_costs_by_feature,
+    test_language_model_costs_by_feature_postgres,
+    test_language_model_costs_by_feature_sqlite
+);
+
+async fn test_language_model_costs_by_feature(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+
+    for (provider, feature, cost) in [
+        ("anthropic", Some("assistant_panel"), 100),
+        ("anthropic", Some("assistant_panel"), 50),
+        ("anthropic", Some("inline_assist"), 20),
+        ("openai", Some("assistant_panel"), 7),
+        ("anthropic", None, 1),
+    ] {
+        db.record_language_model_usage(&CreateLanguageModelUsageParams {
+            user_id,
+            provider: provider.into(),
+            model: "model".into(),
+            input_tokens: 10,
+            output_tokens: 1,
+            feature: feature.map(|feature| feature.to_string()),
+            upstream_cost_in_millicents: cost,
+        })
+        .await
+        .unwrap();
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let costs = db
+        .get_language_model_costs_by_feature(now - Duration::days(1), now + Duration::days(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        costs
+            .iter()
+            .map(|cost| (
+                cost.feature.as_deref(),
+                cost.provider.as_str(),
+                cost.request_count,
+                cost.input_tokens,
+                cost.upstream_cost_in_millicents
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            (None, "anthropic", 1, 10, 1),
+            (Some("assistant_panel"), "anthropic", 2, 20, 150),
+            (Some("assistant_panel"), "openai", 1, 10, 7),
+            (Some("inline_assist"), "anthropic", 1, 10, 20),
+        ]
+    );
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use time::macros::datetime;
+use time::Duration;
+
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::db::tests::new_test_user;
+use crate::db::{
+    CreateBillingCustomerParams, CreateBillingSubscriptionParams, CreateUsageRecordParams,
+};
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_usage_records,
+    test_usage_records_postgres,
+    test_usage_records_sqlite
+);
+
+async fn test_usage_records(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id,
+            stripe_customer_id: "cus_user".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let subscription = db
+        .create_billing_subscription(&CreateBillingSubscriptionParams {
+            billing_customer_id: customer.id,
+            stripe_subscription_id: "sub_user".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Subscriptions on resellers' accounts aren't metered.
+    let reseller_user_id = new_test_user(db, "reseller-user@example.com").await;
+    let reseller_customer = db
+        .create_billing_customer(&CreateBillingCustomerParams {
+            user_id: reseller_user_id,
+            stripe_customer_id: "cus_reseller_user".into(),
+            stripe_account_id: Some("acct_reseller".into()),
+        })
+        .await
+        .unwrap();
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: reseller_customer.id,
+        stripe_subscription_id: "sub_reseller_user".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        stripe_account_id: Some("acct_reseller".into()),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    // Usage is only reported to the customer's oldest subscription.
+    db.create_billing_subscription(&CreateBillingSubscriptionParams {
+        billing_customer_id: customer.id,
+        stripe_subscription_id: "sub_user_2".into(),
+        stripe_subscription_status: StripeSubscriptionStatus::Active,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let metered_subscriptions = db.get_metered_billing_subscriptions().await.unwrap();
+    assert_eq!(metered_subscriptions.len(), 1);
+    assert_eq!(metered_subscriptions[0].0.id, subscription.id);
+    assert_eq!(metered_subscriptions[0].1.user_id, user_id);
+
+    let period_start = datetime!(2024-08-22 13:00);
+    let params = CreateUsageRecordParams {
+        billing_subscription_id: subscription.id,
+        period_start,
+        period_end: period_start + Duration::hours(1),
+        quantity: 1_500,
+    };
+    let record = db.create_usage_record(&params).await.unwrap().unwrap();
+    assert_eq!(record.quantity, 1_500);
+    assert_eq!(
+        db.get_latest_usage_record(subscription.id).await.unwrap(),
+        Some(record.clone())
+    );
+
+    // Usage is only ever recorded once per period.
+    assert_eq!(
+        db.create_usage_record(&CreateUsageRecordParams {
+            quantity: 3_000,
+            ..params
+        })
+        .await
+        .unwrap(),
+        None
+    );
+
+    let now = datetime!(2024-08-22 14:05);
+    assert_eq!(
+        db.get_usage_records_to_report(now, 10).await.unwrap(),
+        vec![record.clone()]
+    );
+
+    // The time the usage is reported at is only chosen once.
+    let stripe_timestamp = now;
+    let record = db
+        .set_usage_record_stripe_timestamp(record.id, stripe_timestamp)
+        .await
+        .unwrap();
+    assert_eq!(record.stripe_timestamp, Some(stripe_timestamp));
+    let record = db
+        .set_usage_record_stripe_timestamp(record.id, now + Duration::hours(1))
+        .await
+        .unwrap();
+    assert_eq!(record.stripe_timestamp, Some(stripe_timestamp));
+
+    // Failed reports are retried once their backoff has elapsed.
+    let record = db
+        .record_usage_record_failure(record.id, "rate limited", now + Duration::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(record.attempt_count, 1);
+    assert_eq!(record.last_error.as_deref(), Some("rate limited"));
+    assert!(db
+        .get_usage_records_to_report(now, 10)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.get_usage_records_to_report(now + Duration::minutes(1), 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    db.mark_usage_record_reported(record.id, Some("mbur_123"), now + Duration::minutes(1))
+        .await
+        .unwrap();
+    assert!(db
+        .get_usage_records_to_report(now + Duration::hours(1), 10)
+        .await
+        .unwrap()
+        .is_empty());
+    let record = db
+        .get_latest_usage_record(subscription.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.stripe_usage_record_id.as_deref(), Some("mbur_123"));
+    assert_eq!(record.next_attempt_at, None);
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use crate::db::tests::new_test_user;
+use crate::test_both_dbs;
+
+use super::Database;
+
+test_both_dbs!(
+    test_user_secrets,
+    test_user_secrets_postgres,
+    test_user_secrets_sqlite
+);
+
+async fn test_user_secrets(db: &Arc<Database>) {
+    let user_id = new_test_user(db, "user@example.com").await;
+    let other_user_id = new_test_user(db, "other@example.com").await;
+
+    assert!(db.get_user_secrets(user_id).await.unwrap().is_empty());
+
+    db.upsert_user_secret(user_id, "openai", vec![1, 2, 3])
+        .await
+        .unwrap();
+    db.upsert_user_secret(user_id, "anthropic", vec![4, 5, 6])
+        .await
+        .unwrap();
+    db.upsert_user_secret(other_user_id, "openai", vec![7, 8, 9])
+        .await
+        .unwrap();
+
+    // Storing a secret with an existing name replaces it.
+    db.upsert_user_secret(user_id, "openai", vec![10, 11, 12])
+        .await
+        .unwrap();
+
+    let secrets = db.get_user_secrets(user_id).await.unwrap();
+    assert_eq!(
+        secrets
+            .iter()
+            .map(|secret| (secret.name.as_str(), secret.ciphertext.as_slice()))
+            .collect::<Vec<_>>(),
+        &[("anthropic", &[4, 5, 6][..]), ("openai", &[10, 11, 12][..])]
+    );
+
+    db.delete_user_secret(user_id, "openai").await.unwrap();
+    let secrets = db.get_user_secrets(user_id).await.unwrap();
+    assert_eq!(
+        secrets
+            .iter()
+            .map(|secret| secret.name.as_str())
+            .collect::<Vec<_>>(),
+        &["anthropic"]
+    );
+
+    // Other users' secrets are unaffected.
+    let secrets = db.get_user_secrets(other_user_id).await.unwrap();
+    assert_eq!(secrets.len(), 1);
+    assert_eq!(secrets[0].ciphertext, vec![7, 8, 9]);
+}
@@ -0,0 +1,188 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use collections::HashSet;
+use parking_lot::Mutex;
+use util::ResultExt;
+use uuid::Uuid;
+
+use crate::executor::Executor;
+use crate::Database;
+
+/// A lock that is shared between all collab instances, used to ensure that
+/// background jobs only run on one instance at a time.
+#[async_trait]
+pub trait DistributedLock: Send + Sync {
+    /// Tries to acquire the lock with the given name without waiting for it.
+    ///
+    /// Returns `None` if the lock is held elsewhere.
+    async fn try_acquire(&self, name: &str) -> Result<Option<Box<dyn DistributedLockGuard>>>;
+}
+
+/// Holds a [`DistributedLock`] until released.
+#[async_trait]
+pub trait DistributedLockGuard: Send {
+    async fn release(self: Box<Self>) -> Result<()>;
+}
+
+/// Runs the given job if the lock with the given name can be acquired,
+/// skipping it otherwise.
+pub async fn run_exclusively<F>(lock: &dyn DistributedLock, name: &str, job: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    let Some(guard) = lock.try_acquire(name).await? else {
+        log::debug!("skipping {name:?}, as it is running on another instance");
+        return Ok(());
+    };
+
+    let result = job.await;
+    guard.release().await.log_err();
+    result
+}
+
+/// How long a lease on a lock lasts unless it's renewed, which bounds how long
+/// a lock stays held after the instance holding it dies.
+const LEASE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// How often the lease on a held lock is renewed.
+const LEASE_RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A [`DistributedLock`] backed by leases stored in the database, which are
+/// renewed for as long as the job holding them runs.
+///
+/// Unlike a transaction-scoped advisory lock, this doesn't keep a transaction
+/// open, or a connection checked out, while the job runs.
+pub struct DatabaseLeaseLock {
+    db: Arc<Database>,
+    executor: Executor,
+}
+
+impl DatabaseLeaseLock {
+    pub fn new(db: Arc<Database>, executor: Executor) -> Self {
+        Self { db, executor }
+    }
+}
+
+#[async_trait]
+impl DistributedLock for DatabaseLeaseLock {
+    async fn try_acquire(&self, name: &str) -> Result<Option<Box<dyn DistributedLockGuard>>> {
+        let holder = Uuid::new_v4().to_string();
+        if !self
+            .db
+            .try_acquire_distributed_lock(name, &holder, LEASE_DURATION)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let released = Arc::new(AtomicBool::new(false));
+        self.executor.spawn_detached({
+            let db = self.db.clone();
+            let executor = self.executor.clone();
+            let name = name.to_string();
+            let holder = holder.clone();
+            let released = released.clone();
+            async move {
+                loop {
+                    executor.sleep(LEASE_RENEWAL_INTERVAL).await;
+                    if released.load(SeqCst) {
+                        break;
+                    }
+                    match db
+                        .renew_distributed_lock(&name, &holder, LEASE_DURATION)
+                        .await
+                    {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            log::error!("lost the lease on lock {name:?} while holding it");
+                            break;
+                        }
+                        Err(error) => log::error!("failed to renew lock {name:?}: {error}"),
+                    }
+                }
+            }
+        });
+
+        Ok(Some(Box::new(DatabaseLeaseLockGuard {
+            db: self.db.clone(),
+            name: name.to_string(),
+            holder,
+            released,
+        })))
+    }
+}
+
+struct DatabaseLeaseLockGuard {
+    db: Arc<Database>,
+    name: String,
+    holder: String,
+    released: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl DistributedLockGuard for DatabaseLeaseLockGuard {
+    async fn release(self: Box<Self>) -> Result<()> {
+        self.released.store(true, SeqCst);
+        self.db
+            .release_distributed_lock(&self.name, &self.holder)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A [`DistributedLock`] that only excludes other holders within this process.
+///
+/// Used in tests, and in development where only one instance runs.
+#[derive(Default)]
+pub struct InMemoryLock {
+    held: Arc<Mutex<HashSet<String>>>,
+}
+
+#[async_trait]
+impl DistributedLock for InMemoryLock {
+    async fn try_acquire(&self, name: &str) -> Result<Option<Box<dyn DistributedLockGuard>>> {
+        if !self.held.lock().insert(name.to_string()) {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(InMemoryLockGuard {
+            held: self.held.clone(),
+            name: name.to_string(),
+        })))
+    }
+}
+
+struct InMemoryLockGuard {
+    held: Arc<Mutex<HashSet<String>>>,
+    name: String,
+}
+
+#[async_trait]
+impl DistributedLockGuard for InMemoryLockGuard {
+    async fn release(self: Box<Self>) -> Result<()> {
+        self.held.lock().remove(&self.name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[gpui::test]
+    async fn test_in_memory_lock() {
+        let lock = InMemoryLock::default();
+
+        let guard = lock.try_acquire("job").await.unwrap().unwrap();
+        assert!(lock.try_acquire("job").await.unwrap().is_none());
+        assert!(lock.try_acquire("other-job").await.unwrap().is_some());
+
+        guard.release().await.unwrap();
+        assert!(lock.try_acquire("job").await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,254 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use stripe::{Invoice, InvoiceStatus, ListInvoices, SubscriptionId};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::api::billing::{payment_provider_for_account, sync_billing_subscription};
+use crate::billing_notifications::{send_billing_notification, BillingNotification};
+use crate::db::{billing_customer, billing_subscription, UserId};
+use crate::distributed_lock::run_exclusively;
+use crate::plan_enforcement;
+use crate::stripe_connect::client_for_account;
+use crate::AppState;
+
+const DUNNING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically retries collecting payment for unpaid subscriptions,
+/// suspending those we fail to, and reinstates those that were paid for.
+pub fn run_dunning_periodically(app: Arc<AppState>, rpc_server: Option<Arc<crate::rpc::Server>>) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "dunning",
+                        run_dunning(&app, rpc_server.as_ref(), &stripe_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, DUNNING_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn run_dunning(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    stripe_client: &stripe::Client,
+) -> anyhow::Result<()> {
+    let settings = app.server_settings.get();
+    let interval = time::Duration::days(settings.unpaid_dunning_interval_in_days);
+    let now = now();
+
+    // Collecting payment pays invoices in Stripe, and suspending the
+    // subscriptions we didn't get to retry would be unfair, so both wait
+    // until billing is writable again.
+    let subscriptions_due = if settings.billing_read_only() {
+        log::info!("not collecting payment for unpaid subscriptions while billing is read-only");
+        Vec::new()
+    } else {
+        app.db
+            .get_billing_subscriptions_due_for_dunning(now)
+            .await?
+    };
+    for (subscription, customer) in subscriptions_due {
+        if app.shutdown.is_shutting_down() {
+            return Ok(());
+        }
+
+        if subscription.dunning_attempt_count as i64 >= settings.unpaid_dunning_attempts {
+            suspend_subscription(app, rpc_server, &subscription, &customer, now)
+                .await
+                .with_context(|| format!("failed to suspend subscription {}", subscription.id))
+                .log_err();
+            continue;
+        }
+
+        let stripe_account_id = subscription.stripe_account_id.as_deref();
+        let stripe_client = &client_for_account(stripe_client, stripe_account_id)?;
+        let paid = collect_payment(stripe_client, &subscription)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to collect payment for subscription {}",
+                    subscription.id
+                )
+            })
+            .log_err()
+            .unwrap_or(false);
+        if paid {
+            // Paying the invoice makes the subscription active again, which
+            // syncing it picks up, reinstating it.
+            let payment_provider = payment_provider_for_account(app, stripe_account_id)?;
+            let payment_subscription = payment_provider
+                .get_subscription(&subscription.stripe_subscription_id)
+                .await?;
+            sync_billing_subscription(
+                app,
+                rpc_server,
+                payment_provider.as_ref(),
+                stripe_account_id,
+                &payment_subscription,
+            )
+            .await?;
+        } else {
+            app.db
+                .record_billing_subscription_dunning_attempt(subscription.id, now + interval)
+                .await?;
+        }
+    }
+
+    for subscription in app.db.get_billing_subscriptions_to_reinstate().await? {
+        reinstate_subscription(app, rpc_server, &subscription.stripe_subscription_id)
+            .await
+            .with_context(|| format!("failed to reinstate subscription {}", subscription.id))
+            .log_err();
+    }
+
+    Ok(())
+}
+
+/// Tries paying the subscription's open invoices with the customer's payment
+/// method. Returns whether they were all paid.
+async fn collect_payment(
+    stripe_client: &stripe::Client,
+    subscription: &billing_subscription::Model,
+) -> anyhow::Result<bool> {
+    let mut params = ListInvoices::new();
+    params.subscription = Some(SubscriptionId::from_str(
+        &subscription.stripe_subscription_id,
+    )?);
+    params.status = Some(InvoiceStatus::Open);
+    let invoices = Invoice::list(stripe_client, &params).await?;
+    if invoices.data.is_empty() {
+        log::warn!(
+            "unpaid subscription {} has no open invoices to collect payment for",
+            subscription.id
+        );
+        return Ok(false);
+    }
+
+    for invoice in invoices.data {
+        // A declined payment is reported as an error, which is just a failed
+        // attempt.
+        match Invoice::pay(stripe_client, &invoice.id).await {
+            Ok(invoice) if invoice.paid == Some(true) => {}
+            Ok(_) => return Ok(false),
+            Err(error) => {
+                log::info!(
+                    "failed to collect payment for invoice {}: {error}",
+                    invoice.id
+                );
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+async fn suspend_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    subscription: &billing_subscription::Model,
+    customer: &billing_customer::Model,
+    now: PrimitiveDateTime,
+) -> anyhow::Result<()> {
+    let Some(subscription) = app
+        .db
+        .suspend_billing_subscription(subscription.id, now)
+        .await?
+    else {
+        return Ok(());
+    };
+    log::info!(
+        "suspended unpaid subscription {} of user {} after {} attempts at collecting payment",
+        subscription.id,
+        customer.user_id,
+        subscription.dunning_attempt_count
+    );
+
+    billing_status_updated(app, rpc_server, customer.user_id).await;
+    send_billing_notification(
+        app,
+        customer.user_id,
+        &BillingNotification::SubscriptionSuspended {
+            stripe_subscription_id: subscription.stripe_subscription_id,
+            suspended_at: now.assume_utc(),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Reinstates the subscription if it's no longer unpaid, but is still
+/// suspended or being retried.
+pub(crate) async fn reinstate_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    stripe_subscription_id: &str,
+) -> anyhow::Result<()> {
+    let Some((subscription, customer)) = app
+        .db
+        .reinstate_billing_subscription(stripe_subscription_id)
+        .await?
+    else {
+        return Ok(());
+    };
+    // Subscriptions that were paid for while being retried never stopped
+    // granting access.
+    let Some(suspended_at) = subscription.suspended_at else {
+        return Ok(());
+    };
+    log::info!(
+        "reinstated subscription {} of user {}",
+        subscription.id,
+        customer.user_id
+    );
+
+    billing_status_updated(app, rpc_server, customer.user_id).await;
+    send_billing_notification(
+        app,
+        customer.user_id,
+        &BillingNotification::SubscriptionReinstated {
+            stripe_subscription_id: subscription.stripe_subscription_id,
+            suspended_at: suspended_at.assume_utc(),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Follows up on a change to what the user's subscriptions grant.
+async fn billing_status_updated(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    user_id: UserId,
+) {
+    plan_enforcement::plan_changed(app, user_id).await.log_err();
+    if let Some(rpc_server) = rpc_server {
+        rpc_server.billing_status_updated(user_id).await.log_err();
+    }
+}
+
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::db::{self, email_suppression::EmailSuppressionReason, CreateEmailDeliveryParams};
+use crate::distributed_lock::run_exclusively;
+use crate::{AppState, Config};
+
+const POSTMARK_API_URL: &str = "https://api.postmarkapp.com/email";
+
+/// Postmark's error code for a recipient that previously bounced or complained.
+const POSTMARK_INACTIVE_RECIPIENT_ERROR_CODE: i64 = 406;
+
+const DELIVER_EMAILS_INTERVAL: Duration = Duration::from_secs(30);
+const DELIVER_EMAILS_BATCH_SIZE: u64 = 100;
+
+/// The number of delivery attempts after which an email is marked as failed.
+const MAX_DELIVERY_ATTEMPTS: i32 = 6;
+
+/// The delay before the first retry. Each subsequent retry doubles it.
+const INITIAL_RETRY_DELAY: time::Duration = time::Duration::minutes(1);
+
+/// The outcome of a failed attempt to send an email.
+#[derive(Debug)]
+pub enum SendEmailError {
+    /// The failure may resolve itself, so the email should be retried.
+    Transient(anyhow::Error),
+    /// Retrying would fail the same way.
+    Permanent(anyhow::Error),
+    /// The provider refuses to send to this recipient.
+    InactiveRecipient(anyhow::Error),
+}
+
+/// A client for sending transactional emails through Postmark.
+pub struct EmailClient {
+    http_client: reqwest::Client,
+    server_token: String,
+    from_address: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEmail<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text_body: &'a str,
+    message_stream: &'static str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResponse {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
+impl EmailClient {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            http_client: reqwest::Client::new(),
+            server_token: config
+                .postmark_server_token
+                .clone()
+                .ok_or_else(|| anyhow!("missing postmark_server_token"))?,
+            from_address: config
+                .email_from_address
+                .clone()
+                .ok_or_else(|| anyhow!("missing email_from_address"))?,
+        })
+    }
+
+    /// Sends the email, returning the ID Postmark assigned to the message.
+    pub async fn send(&self, email: &db::email_delivery::Model) -> Result<String, SendEmailError> {
+        let response = self
+            .http_client
+            .post(POSTMARK_API_URL)
+            .header("Accept", "application/json")
+            .header("X-Postmark-Server-Token", &self.server_token)
+            .json(&PostmarkEmail {
+                from: &self.from_address,
+                to: &email.recipient,
+                subject: &email.subject,
+                text_body: &email.body,
+                message_stream: "outbound",
+            })
+            .send()
+            .await
+            .map_err(|error| SendEmailError::Transient(error.into()))?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Err(SendEmailError::Transient(anyhow!(
+                "Postmark responded with {status}"
+            )));
+        }
+
+        let body = response
+            .json::<PostmarkResponse>()
+            .await
+            .context("failed to parse Postmark response")
+            .map_err(SendEmailError::Transient)?;
+        match body.error_code {
+            0 => body.message_id.ok_or_else(|| {
+                SendEmailError::Permanent(anyhow!("Postmark response is missing a message ID"))
+            }),
+            POSTMARK_INACTIVE_RECIPIENT_ERROR_CODE => Err(SendEmailError::InactiveRecipient(
+                anyhow!("{}", body.message),
+            )),
+            error_code => Err(SendEmailError::Permanent(anyhow!(
+                "Postmark error {error_code}: {}",
+                body.message
+            ))),
+        }
+    }
+}
+
+/// Queues an email to be sent by [`deliver_emails_periodically`].
+pub async fn enqueue_email(
+    app: &AppState,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    app.db
+        .enqueue_email(&CreateEmailDeliveryParams {
+            recipient: recipient.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Periodically sends queued emails, retrying transient failures with
+/// exponential backoff.
+pub fn deliver_emails_periodically(app: Arc<AppState>) {
+    let Some(email_client) = app.email_client.clone() else {
+        log::warn!("failed to retrieve email client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "deliver_emails",
+                        deliver_emails(&app, &email_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, DELIVER_EMAILS_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn deliver_emails(app: &AppState, email_client: &EmailClient) -> anyhow::Result<()> {
+    let emails = app
+        .db
+        .get_due_email_deliveries(DELIVER_EMAILS_BATCH_SIZE)
+        .await?;
+
+    for email in emails {
+        match email_client.send(&email).await {
+            Ok(message_id) => {
+                app.db
+                    .mark_email_delivery_sent(email.id, &message_id)
+                    .await?;
+            }
+            Err(SendEmailError::Transient(error)) => {
+                let attempt_count = email.attempt_count + 1;
+                let retry_at = (attempt_count < MAX_DELIVERY_ATTEMPTS).then(|| {
+                    OffsetDateTime::now_utc()
+                        + INITIAL_RETRY_DELAY * 2i32.pow(email.attempt_count as u32)
+                });
+                log::warn!(
+                    "failed to send email {} (attempt {attempt_count}): {error:?}",
+                    email.id
+                );
+                app.db
+                    .mark_email_delivery_failed(email.id, &error.to_string(), retry_at)
+                    .await?;
+            }
+            Err(SendEmailError::Permanent(error)) => {
+                log::error!("failed to send email {}: {error:?}", email.id);
+                app.db
+                    .mark_email_delivery_failed(email.id, &error.to_string(), None)
+                    .await?;
+            }
+            Err(SendEmailError::InactiveRecipient(error)) => {
+                log::info!(
+                    "not sending email {} to inactive recipient: {error}",
+                    email.id
+                );
+                app.db
+                    .mark_email_delivery_failed(email.id, &error.to_string(), None)
+                    .await?;
+                app.db
+                    .suppress_email_address(&email.recipient, EmailSuppressionReason::Bounce, None)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use rpc::{proto::ErrorCode, ErrorCodeExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::{Database, UserId};
+use crate::Result;
+
+/// The plan a user is on, as determined by their billing subscriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Plan {
+    Free,
+    Pro,
+    Team,
+}
+
+impl Plan {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Plan::Free => "free",
+            Plan::Pro => "pro",
+            Plan::Team => "team",
+        }
+    }
+}
+
+impl From<SubscriptionPlan> for Plan {
+    fn from(plan: SubscriptionPlan) -> Self {
+        match plan {
+            SubscriptionPlan::Free => Plan::Free,
+            SubscriptionPlan::Pro => Plan::Pro,
+            SubscriptionPlan::Team => Plan::Team,
+        }
+    }
+}
+
+/// The `billing_mode` a server is configured with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BillingModeKind {
+    Disabled,
+    Mock,
+    #[default]
+    Stripe,
+}
+
+/// How users get their plans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillingMode {
+    /// There is no billing, and nothing is limited by plan.
+    Disabled,
+    /// Everyone is on the given plan, without paying for it.
+    Mock(Plan),
+    /// Users are on the plans they subscribe to through Stripe.
+    Stripe,
+}
+
+impl BillingMode {
+    pub fn is_stripe(&self) -> bool {
+        *self == BillingMode::Stripe
+    }
+}
+
+/// A feature whose usage is limited by plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitedFeature {
+    /// The number of participants in a call, including pending invitations.
+    CallParticipants,
+    /// The number of root channels a user can administer.
+    RootChannels,
+    /// The number of language model tokens a user can use within a usage
+    /// period.
+    MonthlyLanguageModelTokens,
+}
+
+impl LimitedFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitedFeature::CallParticipants => "call_participants",
+            LimitedFeature::RootChannels => "root_channels",
+            LimitedFeature::MonthlyLanguageModelTokens => "monthly_language_model_tokens",
+        }
+    }
+}
+
+/// What a user is entitled to on their current plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entitlements {
+    pub plan: Plan,
+    pub max_call_participants: u64,
+    pub max_root_channels: u64,
+    /// The number of language model tokens included in the plan for each
+    /// usage period.
+    pub monthly_language_model_tokens: u64,
+}
+
+impl Entitlements {
+    pub fn for_plan(plan: Plan) -> Self {
+        match plan {
+            Plan::Free => Self {
+                plan,
+                max_call_participants: 5,
+                max_root_channels: 3,
+                monthly_language_model_tokens: 1_000_000,
+            },
+            Plan::Pro => Self {
+                plan,
+                max_call_participants: 50,
+                max_root_channels: 100,
+                monthly_language_model_tokens: 25_000_000,
+            },
+            Plan::Team => Self {
+                plan,
+                max_call_participants: 100,
+                max_root_channels: 500,
+                monthly_language_model_tokens: 50_000_000,
+            },
+        }
+    }
+
+    /// Entitlements without any limits, for servers without billing.
+    pub fn unlimited() -> Self {
+        Self {
+            plan: Plan::Team,
+            max_call_participants: u64::MAX,
+            max_root_channels: u64::MAX,
+            monthly_language_model_tokens: u64::MAX,
+        }
+    }
+
+    /// Returns the entitlements of the given user. With Stripe billing, those
+    /// are the entitlements of the highest plan they have an active
+    /// subscription to, whether paid for by them or by an organization they're
+    /// a member of, complimentary, or granted through an external source such
+    /// as GitHub Sponsors.
+    pub async fn for_user(
+        db: &Database,
+        billing_mode: BillingMode,
+        user_id: UserId,
+    ) -> Result<Self> {
+        let plan = match billing_mode {
+            BillingMode::Disabled => return Ok(Self::unlimited()),
+            BillingMode::Mock(plan) => return Ok(Self::for_plan(plan)),
+            BillingMode::Stripe => {
+                let subscription_plans = db
+                    .get_active_billing_subscriptions(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|subscription| subscription.plan);
+                let organization_plans = db
+                    .get_organization_billing_subscriptions_for_member(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|subscription| subscription.plan);
+                let complimentary_plans = db
+                    .get_active_complimentary_subscriptions(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|subscription| subscription.plan);
+                let external_plans = db
+                    .get_active_external_entitlements(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|entitlement| entitlement.plan);
+                subscription_plans
+                    .chain(organization_plans)
+                    .chain(complimentary_plans)
+                    .chain(external_plans)
+                    .map(Plan::from)
+                    .max()
+                    .unwrap_or(Plan::Free)
+            }
+        };
+        Ok(Self::for_plan(plan))
+    }
+
+    pub fn limit(&self, feature: LimitedFeature) -> u64 {
+        match feature {
+            LimitedFeature::CallParticipants => self.max_call_participants,
+            LimitedFeature::RootChannels => self.max_root_channels,
+            LimitedFeature::MonthlyLanguageModelTokens => self.monthly_language_model_tokens,
+        }
+    }
+
+    /// Returns an "upgrade required" error if using one more of the given
+    /// feature would exceed the user's limit.
+    pub fn check(&self, feature: LimitedFeature, current_usage: u64) -> anyhow::Result<()> {
+        let limit = self.limit(feature);
+        if current_usage < limit {
+            return Ok(());
+        }
+
+        Err(ErrorCode::PlanUpgradeRequired
+            .message(format!(
+                "the {} plan is limited to {limit} {}",
+                self.plan.as_str(),
+                feature.as_str().replace('_', " ")
+            ))
+            .with_tag("feature", feature.as_str())
+            .with_tag("limit", &limit.to_string())
+            .with_tag("plan", self.plan.as_str())
+            .anyhow())
+    }
+}
+
+/// The calendar month, in UTC, that language model usage is counted over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsagePeriod {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+}
+
+impl UsagePeriod {
+    /// Returns the usage period the given time falls in.
+    pub fn containing(time: OffsetDateTime) -> Self {
+        let start = first_day_of_month(time.to_offset(time::UtcOffset::UTC).date());
+        let end = first_day_of_month(start + Duration::days(32));
+        Self {
+            start: start.midnight().assume_utc(),
+            end: end.midnight().assume_utc(),
+        }
+    }
+}
+
+fn first_day_of_month(date: Date) -> Date {
+    date - Duration::days(date.day() as i64 - 1)
+}
+
+/// A user's language model token allowance for a usage period, and how much
+/// of it they've used.
+#[derive(Debug, Clone, Copy)]
+pub struct LanguageModelQuota {
+    pub entitlements: Entitlements,
+    pub period: UsagePeriod,
+    /// The tokens the user bought on top of their plan for the period.
+    pub top_up_tokens: u64,
+    pub used_tokens: u64,
+}
+
+impl LanguageModelQuota {
+    pub async fn for_user(
+        db: &Database,
+        billing_mode: BillingMode,
+        user_id: UserId,
+        now: OffsetDateTime,
+    ) -> Result<Self> {
+        let entitlements = Entitlements::for_user(db, billing_mode, user_id).await?;
+        let period = UsagePeriod::containing(now);
+        let top_up_tokens = db
+            .get_language_model_top_up_tokens(user_id, period.start.date())
+            .await?;
+        let used_tokens = db
+            .get_language_model_token_usage_for_user(user_id, period.start, period.end)
+            .await?;
+        Ok(Self {
+            entitlements,
+            period,
+            top_up_tokens: top_up_tokens.max(0) as u64,
+            used_tokens: used_tokens.max(0) as u64,
+        })
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.entitlements
+            .monthly_language_model_tokens
+            .saturating_add(self.top_up_tokens)
+    }
+
+    pub fn remaining_tokens(&self) -> u64 {
+        self.total_tokens().saturating_sub(self.used_tokens)
+    }
+
+    /// Returns how many of the topped-up tokens are left. The tokens included
+    /// in the plan are used up before any topped-up ones.
+    pub fn remaining_top_up_tokens(&self) -> u64 {
+        self.remaining_tokens().min(self.top_up_tokens)
+    }
+
+    /// Returns an "upgrade required" error if the user has used up their
+    /// allowance for the period.
+    pub fn check(&self) -> anyhow::Result<()> {
+        Entitlements {
+            monthly_language_model_tokens: self.total_tokens(),
+            ..self.entitlements
+        }
+        .check(LimitedFeature::MonthlyLanguageModelTokens, self.used_tokens)
+    }
+
+    /// Returns an "upgrade required" error if a request estimated to use the
+    /// given number of input tokens would exceed what's left of the user's
+    /// allowance. The error is tagged with the estimate, so that clients can
+    /// tell the user how far over they'd go.
+    pub fn check_request(&self, estimated_tokens: u64) -> anyhow::Result<()> {
+        let remaining_tokens = self.remaining_tokens();
+        if estimated_tokens <= remaining_tokens {
+            return Ok(());
+        }
+
+        let feature = LimitedFeature::MonthlyLanguageModelTokens;
+        let plan = self.entitlements.plan;
+        Err(ErrorCode::PlanUpgradeRequired
+            .message(format!(
+                "the request needs an estimated {estimated_tokens} language model tokens, \
+                but only {remaining_tokens} are left on the {} plan",
+                plan.as_str(),
+            ))
+            .with_tag("feature", feature.as_str())
+            .with_tag("limit", &self.total_tokens().to_string())
+            .with_tag("plan", plan.as_str())
+            .with_tag("estimated_tokens", &estimated_tokens.to_string())
+            .with_tag("remaining_tokens", &remaining_tokens.to_string())
+            .anyhow())
+    }
+}
+
+/// Resolves what users are entitled to under the server's billing mode.
+///
+/// Everything that depends on a user's plan should go through this, rather
+/// than looking at their subscriptions directly, so that complimentary plans,
+/// grace periods and the billing mode are accounted for the same way
+/// everywhere.
+pub struct EntitlementsService {
+    db: Arc<Database>,
+    billing_mode: BillingMode,
+}
+
+impl EntitlementsService {
+    pub fn new(db: Arc<Database>, billing_mode: BillingMode) -> Self {
+        Self { db, billing_mode }
+    }
+
+    pub fn billing_mode(&self) -> BillingMode {
+        self.billing_mode
+    }
+
+    /// Returns what the user is entitled to on their current plan.
+    pub async fn for_user(&self, user_id: UserId) -> Result<Entitlements> {
+        Entitlements::for_user(&self.db, self.billing_mode, user_id).await
+    }
+
+    /// Returns the user's language model allowance for the usage period
+    /// containing `now`, and how much of it they've used.
+    pub async fn language_model_quota(
+        &self,
+        user_id: UserId,
+        now: OffsetDateTime,
+    ) -> Result<LanguageModelQuota> {
+        LanguageModelQuota::for_user(&self.db, self.billing_mode, user_id, now).await
+    }
+
+    /// Returns whether the user is on a plan above the free one, whether
+    /// they pay for it or not.
+    pub async fn has_paid_plan(&self, user_id: UserId) -> Result<bool> {
+        Ok(self.for_user(user_id).await?.plan > Plan::Free)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpc::ErrorExt;
+
+    #[test]
+    fn test_check_limits() {
+        let entitlements = Entitlements::for_plan(Plan::Free);
+        assert!(entitlements
+            .check(
+                LimitedFeature::RootChannels,
+                entitlements.max_root_channels - 1
+            )
+            .is_ok());
+
+        let error = entitlements
+            .check(LimitedFeature::RootChannels, entitlements.max_root_channels)
+            .unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::PlanUpgradeRequired);
+        assert_eq!(error.error_tag("feature"), Some("root_channels"));
+        assert_eq!(error.error_tag("plan"), Some("free"));
+    }
+
+    #[test]
+    fn test_usage_period() {
+        use time::macros::datetime;
+
+        let period = UsagePeriod::containing(datetime!(2024-12-31 23:59 UTC));
+        assert_eq!(period.start, datetime!(2024-12-01 0:00 UTC));
+        assert_eq!(period.end, datetime!(2025-01-01 0:00 UTC));
+
+        let period = UsagePeriod::containing(datetime!(2024-02-01 0:00 UTC));
+        assert_eq!(period.start, datetime!(2024-02-01 0:00 UTC));
+        assert_eq!(period.end, datetime!(2024-03-01 0:00 UTC));
+    }
+
+    #[test]
+    fn test_language_model_quota() {
+        let quota = |top_up_tokens, used_tokens| LanguageModelQuota {
+            entitlements: Entitlements::for_plan(Plan::Free),
+            period: UsagePeriod::containing(OffsetDateTime::now_utc()),
+            top_up_tokens,
+            used_tokens,
+        };
+
+        // The plan's tokens are used before the topped-up ones.
+        let q = quota(500_000, 400_000);
+        assert_eq!(q.remaining_tokens(), 1_100_000);
+        assert_eq!(q.remaining_top_up_tokens(), 500_000);
+        assert!(q.check().is_ok());
+
+        let q = quota(500_000, 1_200_000);
+        assert_eq!(q.remaining_tokens(), 300_000);
+        assert_eq!(q.remaining_top_up_tokens(), 300_000);
+        assert!(q.check().is_ok());
+
+        let q = quota(500_000, 1_500_000);
+        assert_eq!(q.remaining_tokens(), 0);
+        assert_eq!(q.remaining_top_up_tokens(), 0);
+        let error = q.check().unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::PlanUpgradeRequired);
+        assert_eq!(error.error_tag("limit"), Some("1500000"));
+
+        let q = quota(0, 2_000_000);
+        assert_eq!(q.remaining_tokens(), 0);
+        assert!(q.check().is_err());
+    }
+
+    #[test]
+    fn test_check_language_model_request() {
+        let quota = LanguageModelQuota {
+            entitlements: Entitlements::for_plan(Plan::Free),
+            period: UsagePeriod::containing(OffsetDateTime::now_utc()),
+            top_up_tokens: 0,
+            used_tokens: 900_000,
+        };
+        assert!(quota.check_request(100_000).is_ok());
+
+        let error = quota.check_request(100_001).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::PlanUpgradeRequired);
+        assert_eq!(
+            error.error_tag("feature"),
+            Some("monthly_language_model_tokens")
+        );
+        assert_eq!(error.error_tag("estimated_tokens"), Some("100001"));
+        assert_eq!(error.error_tag("remaining_tokens"), Some("100000"));
+    }
+
+    #[test]
+    fn test_unlimited_language_model_quota() {
+        let quota = LanguageModelQuota {
+            entitlements: Entitlements::unlimited(),
+            period: UsagePeriod::containing(OffsetDateTime::now_utc()),
+            top_up_tokens: 1_000_000,
+            used_tokens: 1_000_000_000,
+        };
+        assert_eq!(quota.total_tokens(), u64::MAX);
+        assert!(quota.check().is_ok());
+        assert!(quota.check_request(u32::MAX as u64).is_ok());
+    }
+}
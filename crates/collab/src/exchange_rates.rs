@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context as _};
+use collections::HashMap;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use time::{macros::format_description, Date};
+use util::ResultExt;
+
+use crate::currency::minor_units;
+use crate::{AppState, Config};
+
+/// How many days of rates are kept in memory.
+const MAX_CACHED_DAYS: usize = 31;
+
+pub struct ExchangeRateClient {
+    http_client: reqwest::Client,
+    url: String,
+    rates_by_date: Mutex<HashMap<Date, Arc<HashMap<String, f64>>>>,
+}
+
+#[derive(Deserialize)]
+struct ExchangeRatesResponse {
+    /// How many units of each currency a US dollar buys, keyed by the
+    /// currency's ISO 4217 code.
+    rates: HashMap<String, f64>,
+}
+
+impl ExchangeRateClient {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            http_client: reqwest::Client::new(),
+            url: config
+                .exchange_rates_url
+                .clone()
+                .ok_or_else(|| anyhow!("missing exchange_rates_url"))?,
+            rates_by_date: Default::default(),
+        })
+    }
+
+    /// Returns how many units of the currency a US dollar bought on the given
+    /// day.
+    pub async fn usd_rate(&self, currency: &str, date: Date) -> anyhow::Result<f64> {
+        let currency = currency.to_ascii_uppercase();
+        if currency == "USD" {
+            return Ok(1.0);
+        }
+
+        let rates = self.rates_on(date).await?;
+        let rate = rates
+            .get(&currency)
+            .copied()
+            .ok_or_else(|| anyhow!("no exchange rate for {currency} on {date}"))?;
+        if !rate.is_finite() || rate <= 0. {
+            bail!("invalid exchange rate {rate} for {currency} on {date}");
+        }
+        Ok(rate)
+    }
+
+    async fn rates_on(&self, date: Date) -> anyhow::Result<Arc<HashMap<String, f64>>> {
+        if let Some(rates) = self.rates_by_date.lock().get(&date) {
+            return Ok(rates.clone());
+        }
+
+        // The URL may contain an API key, so it's left out of errors.
+        let url = self.url.replace(
+            "{date}",
+            &date.format(format_description!("[year]-[month]-[day]"))?,
+        );
+        let response = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|error| anyhow!("failed to fetch exchange rates: {}", error.without_url()))?
+            .json::<ExchangeRatesResponse>()
+            .await
+            .context("failed to parse exchange rates")?;
+        let rates = Arc::new(
+            response
+                .rates
+                .into_iter()
+                .map(|(currency, rate)| (currency.to_ascii_uppercase(), rate))
+                .collect::<HashMap<_, _>>(),
+        );
+
+        let mut rates_by_date = self.rates_by_date.lock();
+        if rates_by_date.len() >= MAX_CACHED_DAYS {
+            rates_by_date.clear();
+        }
+        rates_by_date.insert(date, rates.clone());
+        Ok(rates)
+    }
+}
+
+/// An amount converted to US dollars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsdAmount {
+    /// How many units of the original currency a US dollar bought.
+    pub exchange_rate: f64,
+    pub amount_in_cents: i64,
+}
+
+/// Converts an amount, in the smallest unit of its currency, to US dollars at
+/// the rate of the given day.
+///
+/// Returns `None` if the rate couldn't be looked up, as failing to do so
+/// shouldn't stop the payment from being recorded.
+pub async fn usd_amount_on(
+    app: &AppState,
+    amount: i64,
+    currency: &str,
+    date: Date,
+) -> Option<UsdAmount> {
+    let exchange_rate = if currency.eq_ignore_ascii_case("usd") {
+        1.0
+    } else {
+        let Some(client) = app.exchange_rate_client.as_ref() else {
+            log::warn!("no exchange rate client to convert {currency} with");
+            return None;
+        };
+        client.usd_rate(currency, date).await.log_err()?
+    };
+
+    Some(UsdAmount {
+        exchange_rate,
+        amount_in_cents: usd_cents(amount, currency, exchange_rate),
+    })
+}
+
+/// Converts an amount, in the smallest unit of its currency, to US cents,
+/// given how many units of the currency a US dollar buys.
+pub fn usd_cents(amount: i64, currency: &str, usd_rate: f64) -> i64 {
+    let units = amount as f64 / 10f64.powi(minor_units(currency) as i32);
+    (units / usd_rate * 100.).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usd_cents() {
+        assert_eq!(usd_cents(2000, "usd", 1.0), 2000);
+        assert_eq!(usd_cents(2000, "eur", 0.9), 2222);
+        // Yen have no minor unit, so 3000 is ¥3,000.
+        assert_eq!(usd_cents(3000, "jpy", 150.0), 2000);
+        // Dinar have three decimal places, so 6150 is 6.150 KWD.
+        assert_eq!(usd_cents(6150, "kwd", 0.3075), 2000);
+    }
+}
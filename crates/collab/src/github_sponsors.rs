@@ -0,0 +1,465 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context as _};
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+use util::ResultExt;
+
+use crate::db::billing_subscription::SubscriptionPlan;
+use crate::db::external_entitlement::ExternalEntitlementSource;
+use crate::db::{GrantExternalEntitlementParams, UserId};
+use crate::distributed_lock::run_exclusively;
+use crate::{plan_enforcement, AppState, Config};
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const GRAPHQL_URL: &str = "https://api.github.com/graphql";
+
+/// The number of sponsorships retrieved from GitHub in one request.
+const PAGE_SIZE: u32 = 100;
+
+const SPONSORSHIPS_QUERY: &str = "
+query($login: String!, $first: Int!, $after: String) {
+  repositoryOwner(login: $login) {
+    ... on Sponsorable {
+      sponsorshipsAsMaintainer(first: $first, after: $after, activeOnly: true) {
+        nodes {
+          isOneTimePayment
+          tier { monthlyPriceInCents }
+          sponsorEntity { __typename ... on User { databaseId login } }
+        }
+        pageInfo { hasNextPage endCursor }
+      }
+    }
+  }
+}
+";
+
+/// The plans sponsors are entitled to, by how much they sponsor us for each
+/// month.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SponsorTiers {
+    /// The least a sponsor must sponsor us for to be entitled to each plan,
+    /// most expensive first.
+    minimum_amounts: Vec<(SubscriptionPlan, i64)>,
+}
+
+impl SponsorTiers {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Self::parse(config.github_sponsors_plans.as_deref())
+    }
+
+    fn parse(sponsors_plans: Option<&str>) -> anyhow::Result<Self> {
+        let mut tiers = Self::default();
+        for entry in sponsors_plans.unwrap_or_default().split(',').map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let (plan, amount) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("invalid GitHub Sponsors plan entry {entry:?}"))?;
+            let plan =
+                <SubscriptionPlan as sea_orm::ActiveEnum>::try_from_value(&plan.trim().to_string())
+                    .map_err(|_| anyhow!("unknown plan {plan:?}"))?;
+            if plan == SubscriptionPlan::Free {
+                bail!("the free plan can't be granted to sponsors");
+            }
+            let amount = amount
+                .trim()
+                .parse::<i64>()
+                .with_context(|| format!("invalid amount in {entry:?}"))?;
+            if amount <= 0 {
+                bail!("the amount in {entry:?} must be positive");
+            }
+            tiers.minimum_amounts.push((plan, amount));
+        }
+        tiers
+            .minimum_amounts
+            .sort_unstable_by_key(|(_, amount)| std::cmp::Reverse(*amount));
+        Ok(tiers)
+    }
+
+    /// Returns the plan a monthly sponsorship of the given amount entitles
+    /// the sponsor to, if any.
+    pub fn plan_for_amount(&self, monthly_price_in_cents: i64) -> Option<SubscriptionPlan> {
+        self.minimum_amounts
+            .iter()
+            .find(|(_, minimum_amount)| monthly_price_in_cents >= *minimum_amount)
+            .map(|(plan, _)| *plan)
+    }
+}
+
+/// A GitHub user with an active, recurring sponsorship of us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sponsor {
+    pub github_user_id: i32,
+    pub github_login: String,
+    pub monthly_price_in_cents: i64,
+}
+
+pub struct GithubSponsorsClient {
+    http_client: reqwest::Client,
+    token: String,
+    /// The login of the account that's sponsored.
+    login: String,
+}
+
+#[derive(Serialize)]
+struct GraphQlRequest<'a> {
+    query: &'a str,
+    variables: SponsorshipsVariables<'a>,
+}
+
+#[derive(Serialize)]
+struct SponsorshipsVariables<'a> {
+    login: &'a str,
+    first: u32,
+    after: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlResponse {
+    data: Option<SponsorshipsData>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SponsorshipsData {
+    repository_owner: Option<SponsorableOwner>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SponsorableOwner {
+    /// Missing for accounts that can't be sponsored.
+    sponsorships_as_maintainer: Option<SponsorshipConnection>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SponsorshipConnection {
+    nodes: Vec<Sponsorship>,
+    page_info: PageInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sponsorship {
+    is_one_time_payment: bool,
+    tier: Option<SponsorsTier>,
+    sponsor_entity: Option<SponsorEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SponsorsTier {
+    monthly_price_in_cents: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "__typename")]
+enum SponsorEntity {
+    #[serde(rename_all = "camelCase")]
+    User { database_id: i32, login: String },
+    /// Organizations can't be matched to a single user.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+impl GithubSponsorsClient {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            http_client: reqwest::Client::new(),
+            token: config
+                .github_sponsors_token
+                .clone()
+                .ok_or_else(|| anyhow!("missing github_sponsors_token"))?,
+            login: config
+                .github_sponsors_login
+                .clone()
+                .ok_or_else(|| anyhow!("missing github_sponsors_login"))?,
+        })
+    }
+
+    /// Returns every user with an active, recurring sponsorship of us.
+    pub async fn sponsors(&self) -> anyhow::Result<Vec<Sponsor>> {
+        let mut sponsors = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let response = self
+                .http_client
+                .post(GRAPHQL_URL)
+                .bearer_auth(&self.token)
+                .header("User-Agent", "Zed")
+                .json(&GraphQlRequest {
+                    query: SPONSORSHIPS_QUERY,
+                    variables: SponsorshipsVariables {
+                        login: &self.login,
+                        first: PAGE_SIZE,
+                        after: after.as_deref(),
+                    },
+                })
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .context("failed to fetch GitHub sponsors")?
+                .json::<GraphQlResponse>()
+                .await
+                .context("failed to parse GitHub sponsors")?;
+
+            let page_info = sponsors_from_response(response, &mut sponsors)?;
+            match page_info.end_cursor {
+                Some(end_cursor) if page_info.has_next_page => after = Some(end_cursor),
+                _ => break,
+            }
+        }
+        Ok(sponsors)
+    }
+}
+
+/// Collects the sponsors in a page of sponsorships, returning where the next
+/// page starts.
+fn sponsors_from_response(
+    response: GraphQlResponse,
+    sponsors: &mut Vec<Sponsor>,
+) -> anyhow::Result<PageInfo> {
+    if let Some(error) = response.errors.first() {
+        bail!("failed to fetch GitHub sponsors: {}", error.message);
+    }
+    let connection = response
+        .data
+        .and_then(|data| data.repository_owner)
+        .and_then(|owner| owner.sponsorships_as_maintainer)
+        .ok_or_else(|| anyhow!("the sponsored account can't be sponsored"))?;
+
+    for sponsorship in connection.nodes {
+        if sponsorship.is_one_time_payment {
+            continue;
+        }
+        let (Some(SponsorEntity::User { database_id, login }), Some(tier)) =
+            (sponsorship.sponsor_entity, sponsorship.tier)
+        else {
+            continue;
+        };
+        sponsors.push(Sponsor {
+            github_user_id: database_id,
+            github_login: login,
+            monthly_price_in_cents: tier.monthly_price_in_cents,
+        });
+    }
+    Ok(connection.page_info)
+}
+
+/// Periodically syncs the entitlements of our GitHub sponsors.
+pub fn sync_github_sponsors_periodically(
+    app: Arc<AppState>,
+    rpc_server: Option<Arc<crate::rpc::Server>>,
+) {
+    let Some((client, tiers)) = GithubSponsorsClient::new(&app.config)
+        .and_then(|client| Ok((client, SponsorTiers::from_config(&app.config)?)))
+        .log_err()
+    else {
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "sync_github_sponsors",
+                        sync_github_sponsors(&app, rpc_server.as_ref(), &client, &tiers),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, SYNC_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn sync_github_sponsors(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    client: &GithubSponsorsClient,
+    tiers: &SponsorTiers,
+) -> anyhow::Result<()> {
+    let sponsors = client.sponsors().await?;
+    let mut entitlements_by_external_id = app
+        .db
+        .get_active_external_entitlements_from_source(ExternalEntitlementSource::GithubSponsors)
+        .await?
+        .into_iter()
+        .map(|entitlement| (entitlement.external_id.clone(), entitlement))
+        .collect::<HashMap<_, _>>();
+
+    for sponsor in sponsors {
+        let Some(plan) = tiers.plan_for_amount(sponsor.monthly_price_in_cents) else {
+            continue;
+        };
+        let Some(user) = app
+            .db
+            .get_user_by_github_user_id(sponsor.github_user_id)
+            .await?
+        else {
+            continue;
+        };
+
+        let external_id = sponsor.github_user_id.to_string();
+        let previous_entitlement = entitlements_by_external_id.remove(&external_id);
+        if let Some(entitlement) = &previous_entitlement {
+            if entitlement.user_id == user.id && entitlement.plan == plan {
+                continue;
+            }
+        }
+
+        app.db
+            .grant_external_entitlement(&GrantExternalEntitlementParams {
+                user_id: user.id,
+                source: ExternalEntitlementSource::GithubSponsors,
+                external_id,
+                plan,
+            })
+            .await?;
+        log::info!(
+            "granted GitHub sponsor {} the {} plan",
+            sponsor.github_login,
+            plan.as_str()
+        );
+
+        if let Some(entitlement) = previous_entitlement {
+            if entitlement.user_id != user.id {
+                billing_status_updated(app, rpc_server, entitlement.user_id).await;
+            }
+        }
+        billing_status_updated(app, rpc_server, user.id).await;
+    }
+
+    // Whoever is left has stopped sponsoring us, or no longer sponsors us
+    // enough for any plan.
+    for entitlement in entitlements_by_external_id.into_values() {
+        app.db.end_external_entitlement(entitlement.id).await?;
+        log::info!(
+            "ended the GitHub Sponsors entitlement of user {}",
+            entitlement.user_id
+        );
+        billing_status_updated(app, rpc_server, entitlement.user_id).await;
+    }
+
+    Ok(())
+}
+
+async fn billing_status_updated(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    user_id: UserId,
+) {
+    plan_enforcement::plan_changed(app, user_id).await.log_err();
+    if let Some(rpc_server) = rpc_server {
+        rpc_server.billing_status_updated(user_id).await.log_err();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sponsor_tiers() {
+        assert_eq!(SponsorTiers::parse(None).unwrap(), SponsorTiers::default());
+
+        let tiers = SponsorTiers::parse(Some("pro:2000, team:10000")).unwrap();
+        assert_eq!(tiers.plan_for_amount(500), None);
+        assert_eq!(tiers.plan_for_amount(2000), Some(SubscriptionPlan::Pro));
+        assert_eq!(tiers.plan_for_amount(9999), Some(SubscriptionPlan::Pro));
+        assert_eq!(tiers.plan_for_amount(25000), Some(SubscriptionPlan::Team));
+
+        assert!(SponsorTiers::parse(Some("pro")).is_err());
+        assert!(SponsorTiers::parse(Some("free:100")).is_err());
+        assert!(SponsorTiers::parse(Some("gold:100")).is_err());
+        assert!(SponsorTiers::parse(Some("pro:-5")).is_err());
+    }
+
+    #[test]
+    fn test_sponsors_from_response() {
+        let response: GraphQlResponse = serde_json::from_value(json!({
+            "data": {
+                "repositoryOwner": {
+                    "sponsorshipsAsMaintainer": {
+                        "nodes": [
+                            {
+                                "isOneTimePayment": false,
+                                "tier": { "monthlyPriceInCents": 2000 },
+                                "sponsorEntity": {
+                                    "__typename": "User",
+                                    "databaseId": 1234,
+                                    "login": "ferris"
+                                }
+                            },
+                            {
+                                "isOneTimePayment": true,
+                                "tier": { "monthlyPriceInCents": 10000 },
+                                "sponsorEntity": {
+                                    "__typename": "User",
+                                    "databaseId": 5678,
+                                    "login": "once"
+                                }
+                            },
+                            {
+                                "isOneTimePayment": false,
+                                "tier": { "monthlyPriceInCents": 10000 },
+                                "sponsorEntity": { "__typename": "Organization" }
+                            }
+                        ],
+                        "pageInfo": { "hasNextPage": true, "endCursor": "Y3Vyc29y" }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let mut sponsors = Vec::new();
+        let page_info = sponsors_from_response(response, &mut sponsors).unwrap();
+        assert_eq!(
+            sponsors,
+            [Sponsor {
+                github_user_id: 1234,
+                github_login: "ferris".into(),
+                monthly_price_in_cents: 2000,
+            }]
+        );
+        assert!(page_info.has_next_page);
+        assert_eq!(page_info.end_cursor.as_deref(), Some("Y3Vyc29y"));
+
+        let response: GraphQlResponse = serde_json::from_value(json!({
+            "data": null,
+            "errors": [{ "message": "Bad credentials" }]
+        }))
+        .unwrap();
+        assert!(sponsors_from_response(response, &mut sponsors).is_err());
+    }
+}
@@ -1,11 +1,42 @@
 pub mod api;
 pub mod auth;
+pub mod bench;
+pub mod billing_notifications;
+pub mod column_rename_backfills;
+pub mod currency;
 pub mod db;
+pub mod distributed_lock;
+pub mod dunning;
+pub mod email;
+pub mod entitlements;
 pub mod env;
+pub mod exchange_rates;
 pub mod executor;
+pub mod github_sponsors;
+pub mod llm_failover;
+pub mod llm_preflight;
+pub mod llm_pricing;
+pub mod metered_billing;
+pub mod model_experiments;
+pub mod payment_provider;
+pub mod plan_enforcement;
 mod rate_limiter;
+pub mod retention;
 pub mod rpc;
+pub mod seat_true_ups;
 pub mod seed;
+pub mod server_settings;
+pub mod shutdown;
+pub mod spending_caps;
+pub mod stripe_connect;
+pub mod stripe_key_rotation;
+pub mod stripe_reconciliation;
+pub mod stripe_retry;
+pub mod stripe_test_clocks;
+pub mod stripe_webhook;
+pub mod tenant;
+pub mod trial_abuse;
+pub mod usage_rollups;
 
 #[cfg(test)]
 mod tests;
@@ -14,9 +45,12 @@ use anyhow::anyhow;
 use aws_config::{BehaviorVersion, Region};
 use axum::{http::StatusCode, response::IntoResponse};
 use db::{ChannelId, Database};
+use entitlements::{BillingMode, BillingModeKind, EntitlementsService, Plan};
 use executor::Executor;
 pub use rate_limiter::*;
 use serde::Deserialize;
+use server_settings::ServerSettingsStore;
+use shutdown::Shutdown;
 use std::{path::PathBuf, sync::Arc};
 use util::ResultExt;
 
@@ -151,15 +185,158 @@ pub struct Config {
     pub openai_api_key: Option<Arc<str>>,
     pub google_ai_api_key: Option<Arc<str>>,
     pub anthropic_api_key: Option<Arc<str>>,
+    /// The base URL of the Anthropic API, for pointing the server at a fake one.
+    pub anthropic_api_url: Option<String>,
     pub zed_client_checksum_seed: Option<String>,
     pub slack_panics_webhook: Option<String>,
     pub auto_join_channel_id: Option<ChannelId>,
     pub stripe_api_key: Option<String>,
+    /// The base URL of the Stripe API, for pointing the server at a fake one.
+    pub stripe_api_url: Option<String>,
+    /// The Stripe price of the Pro plan, unless `stripe_plan_price_ids` sets one.
     pub stripe_price_id: Option<Arc<str>>,
+    /// A comma-separated list of `<plan>:<price ID>` pairs for the plans users
+    /// can subscribe to (e.g. "pro:price_123,team:price_456").
+    ///
+    /// Prices in currencies other than USD are listed as
+    /// `<plan>/<currency>:<price ID>` (e.g. "pro/eur:price_789"), and prices on
+    /// a reseller's connected account as `<plan>/<currency>@<account ID>:<price ID>`
+    /// (e.g. "pro/brl@acct_123:price_321").
+    pub stripe_plan_price_ids: Option<String>,
+    /// A comma-separated list of `<country>:<account ID>` pairs, routing the
+    /// customers in each country to a reseller's connected Stripe account
+    /// (e.g. "BR:acct_123,IN:acct_456"). Countries are ISO 3166-1 alpha-2
+    /// codes.
+    pub stripe_connect_accounts: Option<String>,
+    /// The Stripe coupon applied to the first subscription of a referred user.
+    pub stripe_referral_coupon_id: Option<Arc<str>>,
+    /// The amount credited to a referrer once the referred user subscribes.
+    pub stripe_referral_credit_in_cents: Option<i64>,
+    /// The length of the free trial new subscribers get, in days.
+    pub stripe_trial_period_days: Option<u32>,
+    /// The Stripe price of a one-time language model token top-up.
+    pub stripe_top_up_price_id: Option<Arc<str>>,
+    /// The number of language model tokens a single top-up adds to the
+    /// user's quota for the current usage period.
+    pub top_up_tokens: Option<i64>,
+    /// The Stripe metered price that the language model tokens used by
+    /// subscribers are reported against.
+    pub stripe_metered_price_id: Option<Arc<str>>,
+    /// What the metered price charges per million language model tokens,
+    /// which users' spending caps are enforced against. Spending caps can
+    /// only be set when it's configured.
+    pub metered_price_per_million_tokens_in_cents: Option<i64>,
+    /// Whether Stripe Tax calculates and collects tax on checkout, based on
+    /// the billing address customers enter there.
+    pub stripe_automatic_tax: Option<bool>,
+    /// The signing secret of our Stripe webhook endpoint. Webhooks are only
+    /// accepted when it's set.
+    pub stripe_webhook_secret: Option<String>,
+    /// The signing secret of our Stripe Connect webhook endpoint, which
+    /// receives the events of resellers' connected accounts.
+    pub stripe_connect_webhook_secret: Option<String>,
+    /// The page users are sent to after completing checkout.
+    pub billing_success_url: Option<String>,
+    /// The page users return to from the billing portal.
+    pub billing_return_url: Option<String>,
+    /// The customer portal configuration that billing portal sessions are
+    /// created with, rather than our Stripe account's default one. Sessions
+    /// on resellers' connected accounts always use their own default.
+    pub stripe_billing_portal_configuration_id: Option<String>,
+    /// A comma-separated list of origins, such as `https://staging.zed.dev`,
+    /// that clients may ask for users to be sent back to from checkout or the
+    /// billing portal.
+    pub billing_redirect_origins: Option<String>,
+    /// A comma-separated list of `<key_id>:<base64 key>` pairs used to encrypt
+    /// sensitive billing columns. The first key is used for new writes.
+    pub billing_encryption_keys: Option<String>,
+    /// How users get their plans: `stripe` (the default) bills them through
+    /// Stripe, `mock` puts everyone on `billing_mock_plan`, and `disabled`
+    /// turns billing off along with all plan limits.
+    pub billing_mode: Option<BillingModeKind>,
+    /// The plan everyone is on when `billing_mode` is `mock`. Defaults to Pro.
+    pub billing_mock_plan: Option<Plan>,
+    /// Whether admins can provision sandbox organizations to run end-to-end
+    /// billing tests against. Only honored with a test-mode Stripe API key.
+    pub enable_sandbox_organizations: Option<bool>,
+    /// Whether new Stripe customers are attached to test clocks, so that
+    /// renewals, trials, and dunning can be simulated. Only honored with a
+    /// test-mode Stripe API key.
+    pub enable_stripe_test_clocks: Option<bool>,
+    /// A GitHub token of the sponsored account, allowed to read its sponsors.
+    pub github_sponsors_token: Option<String>,
+    /// The login of the GitHub account whose sponsors are entitled to plans.
+    pub github_sponsors_login: Option<String>,
+    /// A comma-separated list of `<plan>:<amount>` pairs, entitling GitHub
+    /// sponsors to the highest plan whose amount, in US cents per month, they
+    /// sponsor us for at least (e.g. "pro:2000,team:10000").
+    pub github_sponsors_plans: Option<String>,
+    /// Whether users with unreviewed usage anomalies should be throttled.
+    pub throttle_usage_anomalies: Option<bool>,
+    /// Whether to limit collaboration features based on the user's plan.
+    pub enforce_plan_limits: Option<bool>,
+    /// The version of the terms of service users must accept before subscribing.
+    pub terms_of_service_version: Option<Arc<str>>,
+    /// The version of the pricing terms users must accept before subscribing.
+    pub pricing_terms_version: Option<Arc<str>>,
+    pub postmark_server_token: Option<String>,
+    /// The `<username>:<password>` Postmark authenticates its webhooks with,
+    /// using HTTP basic auth. Webhooks are only accepted when it's set.
+    pub postmark_webhook_credentials: Option<String>,
+    /// The address transactional emails are sent from.
+    pub email_from_address: Option<String>,
+    /// Where to look up exchange rates for converting payments to USD, which
+    /// must respond with how many units of each currency a US dollar buys, as
+    /// `{"rates": {"EUR": 0.92, ...}}`. Any `{date}` in it is replaced with
+    /// the day rates are needed for, as `YYYY-MM-DD`.
+    pub exchange_rates_url: Option<String>,
     pub supermaven_admin_api_key: Option<Arc<str>>,
 }
 
 impl Config {
+    pub fn anthropic_api_url(&self) -> &str {
+        self.anthropic_api_url
+            .as_deref()
+            .unwrap_or(anthropic::ANTHROPIC_API_URL)
+    }
+
+    pub fn billing_mode(&self) -> BillingMode {
+        match self.billing_mode.unwrap_or_default() {
+            BillingModeKind::Disabled => BillingMode::Disabled,
+            BillingModeKind::Mock => BillingMode::Mock(self.billing_mock_plan.unwrap_or(Plan::Pro)),
+            BillingModeKind::Stripe => BillingMode::Stripe,
+        }
+    }
+
+    /// Returns whether sandbox organizations can be provisioned, which
+    /// requires Stripe billing in test mode, so that nothing is ever charged.
+    pub fn sandbox_organizations_enabled(&self) -> bool {
+        self.enable_sandbox_organizations.unwrap_or(false) && self.is_stripe_test_mode()
+    }
+
+    /// Returns whether new Stripe customers are attached to test clocks,
+    /// which only exist in test mode.
+    pub fn stripe_test_clocks_enabled(&self) -> bool {
+        self.enable_stripe_test_clocks.unwrap_or(false) && self.is_stripe_test_mode()
+    }
+
+    /// Returns whether GitHub sponsors are entitled to plans, which requires
+    /// Stripe billing, as plans aren't tracked otherwise.
+    pub fn github_sponsors_enabled(&self) -> bool {
+        self.billing_mode().is_stripe()
+            && self.github_sponsors_token.is_some()
+            && self.github_sponsors_login.is_some()
+            && self.github_sponsors_plans.is_some()
+    }
+
+    fn is_stripe_test_mode(&self) -> bool {
+        self.billing_mode().is_stripe()
+            && self
+                .stripe_api_key
+                .as_deref()
+                .map_or(false, |key| key.starts_with("sk_test_"))
+    }
+
     pub fn is_development(&self) -> bool {
         self.zed_environment == "development".into()
     }
@@ -170,7 +347,21 @@ pub struct AppState {
     pub live_kit_client: Option<Arc<dyn live_kit_server::api::Client>>,
     pub blob_store_client: Option<aws_sdk_s3::Client>,
     pub stripe_client: Option<Arc<stripe::Client>>,
+    /// The processor the billing API bills customers through.
+    pub payment_provider: Option<Arc<dyn payment_provider::PaymentProvider>>,
+    pub email_client: Option<Arc<email::EmailClient>>,
+    pub exchange_rate_client: Option<Arc<exchange_rates::ExchangeRateClient>>,
+    /// The prices of plans, as last retrieved from the payment provider.
+    pub plan_prices: Arc<api::billing::PlanPriceCache>,
     pub rate_limiter: Arc<RateLimiter>,
+    /// Resolves what users are entitled to on their plans.
+    pub entitlements: Arc<EntitlementsService>,
+    /// Operational settings that can be changed at runtime.
+    pub server_settings: Arc<ServerSettingsStore>,
+    /// Ensures background jobs only run on one instance at a time.
+    pub distributed_lock: Arc<dyn distributed_lock::DistributedLock>,
+    /// Lets background jobs finish their work before the server exits.
+    pub shutdown: Shutdown,
     pub executor: Executor,
     pub clickhouse_client: Option<clickhouse::Client>,
     pub config: Config,
@@ -182,6 +373,9 @@ impl AppState {
         db_options.max_connections(config.database_max_connections);
         let mut db = Database::new(db_options, Executor::Production).await?;
         db.initialize_notification_kinds().await?;
+        if let Some(keys) = config.billing_encryption_keys.as_deref() {
+            db.set_column_cipher(db::ColumnCipher::from_config(keys)?);
+        }
 
         let live_kit_client = if let Some(((server, key), secret)) = config
             .live_kit_server
@@ -198,16 +392,51 @@ impl AppState {
             None
         };
 
+        let stripe_client = if config.billing_mode().is_stripe() {
+            build_stripe_client(&config)
+                .await
+                .map(|client| Arc::new(client))
+                .log_err()
+        } else {
+            None
+        };
+        let payment_provider = stripe_client.as_ref().map(|client| {
+            Arc::new(payment_provider::StripePaymentProvider::new(
+                client.as_ref().clone(),
+                executor.clone(),
+            )) as Arc<dyn payment_provider::PaymentProvider>
+        });
+
         let db = Arc::new(db);
+        let server_settings = Arc::new(ServerSettingsStore::new(db.clone()));
+        server_settings.reload().await.log_err();
         let this = Self {
             db: db.clone(),
             live_kit_client,
             blob_store_client: build_blob_store_client(&config).await.log_err(),
-            stripe_client: build_stripe_client(&config)
-                .await
-                .map(|client| Arc::new(client))
-                .log_err(),
-            rate_limiter: Arc::new(RateLimiter::new(db)),
+            stripe_client,
+            payment_provider,
+            email_client: config
+                .postmark_server_token
+                .as_ref()
+                .and_then(|_| email::EmailClient::new(&config).log_err())
+                .map(Arc::new),
+            exchange_rate_client: config
+                .exchange_rates_url
+                .as_ref()
+                .and_then(|_| exchange_rates::ExchangeRateClient::new(&config).log_err())
+                .map(Arc::new),
+            plan_prices: Default::default(),
+            rate_limiter: Arc::new(
+                RateLimiter::new(db.clone()).with_server_settings(server_settings.subscribe()),
+            ),
+            entitlements: Arc::new(EntitlementsService::new(db.clone(), config.billing_mode())),
+            server_settings,
+            distributed_lock: Arc::new(distributed_lock::DatabaseLeaseLock::new(
+                db,
+                executor.clone(),
+            )),
+            shutdown: Shutdown::default(),
             executor,
             clickhouse_client: config
                 .clickhouse_url
@@ -225,7 +454,10 @@ async fn build_stripe_client(config: &Config) -> anyhow::Result<stripe::Client>
         .as_ref()
         .ok_or_else(|| anyhow!("missing stripe_api_key"))?;
 
-    Ok(stripe::Client::new(api_key))
+    Ok(match config.stripe_api_url.as_deref() {
+        Some(api_url) => stripe::Client::from_url(api_url, api_key),
+        None => stripe::Client::new(api_key),
+    })
 }
 
 async fn build_blob_store_client(config: &Config) -> anyhow::Result<aws_sdk_s3::Client> {
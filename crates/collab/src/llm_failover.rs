@@ -0,0 +1,186 @@
+/// The OpenAI models Anthropic requests fail over to, keyed by Anthropic model
+/// ID prefix.
+///
+/// More specific prefixes must come before less specific ones.
+const OPEN_AI_FAILOVER_MODELS: &[(&str, &str)] = &[
+    ("claude-3-5-sonnet", "gpt-4o"),
+    ("claude-3-opus", "gpt-4o"),
+    ("claude-3-sonnet", "gpt-4o"),
+    ("claude-3-haiku", "gpt-4o-mini"),
+];
+
+/// The temperature Anthropic uses when a request doesn't specify one.
+const ANTHROPIC_DEFAULT_TEMPERATURE: f32 = 1.;
+
+/// Returns whether a request that failed with the given error should be
+/// retried with another vendor, which is only when the vendor is unavailable
+/// rather than when the request itself was rejected.
+pub fn should_fail_over(error: &anyhow::Error) -> bool {
+    // Errors without a response are the vendor being unreachable.
+    should_fail_over_on_status(
+        error
+            .downcast_ref::<anthropic::RequestError>()
+            .map(|error| error.status),
+    )
+}
+
+/// Returns whether a request should be retried with another vendor, given
+/// the HTTP status it failed with, if the vendor responded at all.
+fn should_fail_over_on_status(status: Option<u16>) -> bool {
+    match status {
+        None => true,
+        Some(status) => status == 429 || (500..600).contains(&status),
+    }
+}
+
+/// Returns the OpenAI model an Anthropic model fails over to, if any.
+pub fn open_ai_failover_model(anthropic_model: &str) -> Option<&'static str> {
+    OPEN_AI_FAILOVER_MODELS
+        .iter()
+        .find(|(prefix, _)| anthropic_model.starts_with(prefix))
+        .map(|(_, model)| *model)
+}
+
+/// Converts an Anthropic request to the equivalent OpenAI request.
+///
+/// Returns `None` for requests that can't be served by OpenAI without changing
+/// their meaning, such as requests that use tools or contain images.
+pub fn anthropic_request_to_open_ai(request: &anthropic::Request) -> Option<open_ai::Request> {
+    if !request.tools.is_empty() || request.tool_choice.is_some() {
+        return None;
+    }
+    let model = open_ai_failover_model(&request.model)?;
+
+    let mut messages = Vec::new();
+    if let Some(system) = request.system.clone() {
+        messages.push(open_ai::RequestMessage::System { content: system });
+    }
+    for message in &request.messages {
+        let mut content = String::new();
+        for part in &message.content {
+            match part {
+                anthropic::Content::Text { text } => content.push_str(text),
+                _ => return None,
+            }
+        }
+        messages.push(match message.role {
+            anthropic::Role::User => open_ai::RequestMessage::User { content },
+            anthropic::Role::Assistant => open_ai::RequestMessage::Assistant {
+                content: Some(content),
+                tool_calls: Vec::new(),
+            },
+        });
+    }
+
+    Some(open_ai::Request {
+        model: model.to_string(),
+        messages,
+        stream: true,
+        stop: request.stop_sequences.clone(),
+        temperature: request.temperature.unwrap_or(ANTHROPIC_DEFAULT_TEMPERATURE),
+        max_tokens: Some(request.max_tokens),
+        tool_choice: None,
+        tools: Vec::new(),
+        user: request
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.user_id.clone()),
+        stream_options: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anthropic_request(content: Vec<anthropic::Content>) -> anthropic::Request {
+        anthropic::Request {
+            model: "claude-3-5-sonnet-20240620".into(),
+            max_tokens: 4096,
+            messages: vec![anthropic::Message {
+                role: anthropic::Role::User,
+                content,
+            }],
+            tools: Vec::new(),
+            tool_choice: None,
+            system: Some("Be brief.".into()),
+            metadata: None,
+            stop_sequences: vec!["\n\n".into()],
+            temperature: None,
+            top_k: None,
+            top_p: None,
+        }
+    }
+
+    #[test]
+    fn test_should_fail_over_on_status() {
+        assert!(should_fail_over_on_status(None));
+        assert!(should_fail_over_on_status(Some(429)));
+        assert!(should_fail_over_on_status(Some(500)));
+        assert!(should_fail_over_on_status(Some(529)));
+        assert!(!should_fail_over_on_status(Some(400)));
+        assert!(!should_fail_over_on_status(Some(401)));
+        assert!(!should_fail_over_on_status(Some(413)));
+    }
+
+    #[test]
+    fn test_open_ai_failover_model() {
+        assert_eq!(
+            open_ai_failover_model("claude-3-5-sonnet-20240620"),
+            Some("gpt-4o")
+        );
+        assert_eq!(
+            open_ai_failover_model("claude-3-haiku-20240307"),
+            Some("gpt-4o-mini")
+        );
+        assert_eq!(open_ai_failover_model("claude-2.1"), None);
+    }
+
+    #[test]
+    fn test_anthropic_request_to_open_ai() {
+        let request = anthropic_request(vec![
+            anthropic::Content::Text {
+                text: "Hello, ".into(),
+            },
+            anthropic::Content::Text {
+                text: "world".into(),
+            },
+        ]);
+        let open_ai_request = anthropic_request_to_open_ai(&request).unwrap();
+        assert_eq!(open_ai_request.model, "gpt-4o");
+        assert_eq!(open_ai_request.stop, ["\n\n"]);
+        assert_eq!(open_ai_request.temperature, ANTHROPIC_DEFAULT_TEMPERATURE);
+        assert_eq!(
+            open_ai_request.messages,
+            [
+                open_ai::RequestMessage::System {
+                    content: "Be brief.".into()
+                },
+                open_ai::RequestMessage::User {
+                    content: "Hello, world".into()
+                },
+            ]
+        );
+        assert_eq!(open_ai_request.user, None);
+
+        // The tag used to attribute usage is carried over.
+        let mut request = anthropic_request(vec![anthropic::Content::Text {
+            text: "Hello".into(),
+        }]);
+        request.metadata = Some(anthropic::Metadata {
+            user_id: Some("acme-backend".into()),
+        });
+        let open_ai_request = anthropic_request_to_open_ai(&request).unwrap();
+        assert_eq!(open_ai_request.user.as_deref(), Some("acme-backend"));
+
+        // Requests with images can't fail over.
+        let request = anthropic_request(vec![anthropic::Content::Image {
+            source: anthropic::ImageSource {
+                source_type: "base64".into(),
+                media_type: "image/png".into(),
+                data: String::new(),
+            },
+        }]);
+        assert!(anthropic_request_to_open_ai(&request).is_none());
+    }
+}
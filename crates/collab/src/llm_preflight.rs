@@ -0,0 +1,139 @@
+use anyhow::{Context as _, Result};
+use http_client::HttpClient;
+use rpc::proto;
+
+use crate::Config;
+
+/// The model whose tokenizer is used for OpenAI models that tiktoken doesn't
+/// know about.
+const OPEN_AI_FALLBACK_TOKENIZER_MODEL: &str = "gpt-4";
+
+/// Returns whether a request could use more input tokens than are left.
+///
+/// Every token takes up at least one byte of the serialized request, so only
+/// requests larger than the remaining tokens need their tokens estimated.
+pub fn needs_estimate(request_len: usize, remaining_tokens: u64) -> bool {
+    request_len as u64 > remaining_tokens
+}
+
+/// Estimates the number of input tokens of a serialized request to the given
+/// provider, with the provider's token counting API where it has one.
+pub async fn estimate_input_tokens(
+    http_client: &dyn HttpClient,
+    config: &Config,
+    provider: proto::LanguageModelProvider,
+    request: &str,
+) -> Result<u64> {
+    match provider {
+        proto::LanguageModelProvider::Anthropic => {
+            let api_key = config
+                .anthropic_api_key
+                .as_ref()
+                .context("no Anthropic AI API key configured on the server")?;
+            let response = anthropic::count_tokens(
+                http_client,
+                config.anthropic_api_url(),
+                api_key,
+                &serde_json::from_str(request)?,
+            )
+            .await?;
+            Ok(response.input_tokens as u64)
+        }
+        proto::LanguageModelProvider::OpenAi => {
+            estimate_open_ai_input_tokens(&serde_json::from_str(request)?)
+        }
+        proto::LanguageModelProvider::Google => {
+            let api_key = config
+                .google_ai_api_key
+                .as_ref()
+                .context("no Google AI API key configured on the server")?;
+            let request: google_ai::GenerateContentRequest = serde_json::from_str(request)?;
+            let response = google_ai::count_tokens(
+                http_client,
+                google_ai::API_URL,
+                api_key,
+                google_ai::CountTokensRequest {
+                    contents: request.contents,
+                },
+            )
+            .await?;
+            Ok(response.total_tokens as u64)
+        }
+    }
+}
+
+/// Estimates the input tokens of an OpenAI request with tiktoken, as OpenAI
+/// has no API for counting them.
+fn estimate_open_ai_input_tokens(request: &open_ai::Request) -> Result<u64> {
+    let messages = request
+        .messages
+        .iter()
+        .map(|message| {
+            let (role, content) = match message {
+                open_ai::RequestMessage::Assistant { content, .. } => {
+                    ("assistant", content.clone())
+                }
+                open_ai::RequestMessage::User { content } => ("user", Some(content.clone())),
+                open_ai::RequestMessage::System { content } => ("system", Some(content.clone())),
+                open_ai::RequestMessage::Tool { content, .. } => ("tool", Some(content.clone())),
+            };
+            tiktoken_rs::ChatCompletionRequestMessage {
+                role: role.into(),
+                content,
+                name: None,
+                function_call: None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let token_count =
+        tiktoken_rs::num_tokens_from_messages(&request.model, &messages).or_else(|_| {
+            tiktoken_rs::num_tokens_from_messages(OPEN_AI_FALLBACK_TOKENIZER_MODEL, &messages)
+        })?;
+    Ok(token_count as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_estimate() {
+        assert!(!needs_estimate(1_000, 1_000));
+        assert!(needs_estimate(1_001, 1_000));
+        assert!(!needs_estimate(1_000_000, u64::MAX));
+    }
+
+    #[test]
+    fn test_estimate_open_ai_input_tokens() {
+        let request = |model: &str, content: String| open_ai::Request {
+            model: model.into(),
+            messages: vec![
+                open_ai::RequestMessage::System {
+                    content: "You are a helpful assistant.".into(),
+                },
+                open_ai::RequestMessage::User { content },
+            ],
+            stream: true,
+            stop: Vec::new(),
+            temperature: 1.,
+            max_tokens: None,
+            tool_choice: None,
+            tools: Vec::new(),
+            user: None,
+            stream_options: None,
+        };
+
+        let short = estimate_open_ai_input_tokens(&request("gpt-4o", "Hello".into())).unwrap();
+        let long =
+            estimate_open_ai_input_tokens(&request("gpt-4o", "Hello ".repeat(1_000))).unwrap();
+        assert!(short > 0);
+        assert!(long > short + 900);
+
+        // Models tiktoken doesn't know about are estimated with GPT-4's
+        // tokenizer.
+        assert!(
+            estimate_open_ai_input_tokens(&request("some-future-model", "Hello".into())).is_ok()
+        );
+    }
+}
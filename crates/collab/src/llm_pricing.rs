@@ -0,0 +1,87 @@
+/// The price of a model, in cents per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_cents_per_million_tokens: f64,
+    pub output_cents_per_million_tokens: f64,
+}
+
+impl ModelPrice {
+    const fn new(input: f64, output: f64) -> Self {
+        Self {
+            input_cents_per_million_tokens: input,
+            output_cents_per_million_tokens: output,
+        }
+    }
+}
+
+/// Model prices keyed by provider and model ID prefix.
+///
+/// More specific prefixes must come before less specific ones.
+const MODEL_PRICES: &[(&str, &str, ModelPrice)] = &[
+    (
+        "anthropic",
+        "claude-3-5-sonnet",
+        ModelPrice::new(300., 1500.),
+    ),
+    ("anthropic", "claude-3-opus", ModelPrice::new(1500., 7500.)),
+    ("anthropic", "claude-3-sonnet", ModelPrice::new(300., 1500.)),
+    ("anthropic", "claude-3-haiku", ModelPrice::new(25., 125.)),
+    ("openai", "gpt-4o-mini", ModelPrice::new(15., 60.)),
+    ("openai", "gpt-4o", ModelPrice::new(500., 1500.)),
+    ("openai", "gpt-4-turbo", ModelPrice::new(1000., 3000.)),
+    ("openai", "gpt-4", ModelPrice::new(3000., 6000.)),
+    ("openai", "gpt-3.5-turbo", ModelPrice::new(50., 150.)),
+    ("google", "gemini-1.5-pro", ModelPrice::new(350., 1050.)),
+    ("google", "gemini-1.5-flash", ModelPrice::new(35., 105.)),
+];
+
+/// Returns the price of the given model, if known.
+pub fn model_price(provider: &str, model: &str) -> Option<ModelPrice> {
+    MODEL_PRICES
+        .iter()
+        .find(|(price_provider, prefix, _)| {
+            *price_provider == provider && model.starts_with(prefix)
+        })
+        .map(|(_, _, price)| *price)
+}
+
+/// Returns the upstream cost of a request, in thousandths of a cent.
+///
+/// Returns zero for models we don't have a price for.
+pub fn upstream_cost_in_millicents(
+    provider: &str,
+    model: &str,
+    input_tokens: i64,
+    output_tokens: i64,
+) -> i64 {
+    let Some(price) = model_price(provider, model) else {
+        log::warn!("no price known for {provider} model {model}");
+        return 0;
+    };
+
+    let cost_in_cents = (input_tokens as f64 * price.input_cents_per_million_tokens
+        + output_tokens as f64 * price.output_cents_per_million_tokens)
+        / 1_000_000.;
+    (cost_in_cents * 1000.).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_cost() {
+        assert_eq!(
+            upstream_cost_in_millicents("anthropic", "claude-3-5-sonnet-20240620", 1_000, 1_000),
+            1_800
+        );
+        assert_eq!(
+            upstream_cost_in_millicents("openai", "gpt-4o-mini", 1_000_000, 0),
+            15_000
+        );
+        assert_eq!(
+            upstream_cost_in_millicents("openai", "unknown-model", 1_000, 1_000),
+            0
+        );
+    }
+}
@@ -6,9 +6,23 @@ use axum::{
     Extension, Router,
 };
 use collab::api::billing::poll_stripe_events_periodically;
+use collab::api::sandbox_organizations::delete_expired_sandbox_organizations_periodically;
+use collab::api::usage_anomalies::detect_usage_anomalies_periodically;
+use collab::column_rename_backfills::backfill_renamed_columns_periodically;
+use collab::dunning::run_dunning_periodically;
+use collab::email::deliver_emails_periodically;
+use collab::github_sponsors::sync_github_sponsors_periodically;
+use collab::metered_billing::report_usage_periodically;
+use collab::plan_enforcement::enforce_plan_limits_periodically;
+use collab::retention::archive_old_records_periodically;
+use collab::seat_true_ups::true_up_organization_seats_periodically;
+use collab::stripe_key_rotation::complete_stripe_key_rotations_periodically;
+use collab::stripe_reconciliation::reconcile_stripe_subscriptions_periodically;
+use collab::usage_rollups::roll_up_organization_usage_periodically;
 use collab::{
-    api::fetch_extensions_from_blob_store_periodically, db, env, executor::Executor,
-    rpc::ResultExt, AppState, Config, RateLimiter, Result,
+    api::fetch_extensions_from_blob_store_periodically, bench::BenchOptions, db, env,
+    executor::Executor, rpc::ResultExt, server_settings::ServerSettingsStore, AppState, Config,
+    RateLimiter, Result,
 };
 use db::Database;
 use std::{
@@ -29,6 +43,9 @@ use util::ResultExt as _;
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const REVISION: Option<&'static str> = option_env!("GITHUB_SHA");
 
+/// How long to wait for background jobs to finish their work on shutdown.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     if let Err(error) = env::load_dotenv() {
@@ -55,6 +72,14 @@ async fn main() -> Result<()> {
 
             collab::seed::seed(&config, &db, true).await?;
         }
+        Some("bench") => {
+            let config = envy::from_env::<Config>().expect("error loading config");
+            init_tracing(&config);
+
+            let options = BenchOptions::parse(args)?;
+            run_migrations(&config).await?;
+            collab::bench::run(config, options).await?;
+        }
         Some("serve") => {
             let (is_api, is_collab) = if let Some(next) = args.next() {
                 (next == "api", next == "collab")
@@ -63,7 +88,7 @@ async fn main() -> Result<()> {
             };
             if !is_api && !is_collab {
                 Err(anyhow!(
-                    "usage: collab <version | migrate | seed | serve [api|collab]>"
+                    "usage: collab <version | migrate | seed | serve [api|collab] | bench>"
                 ))?;
             }
 
@@ -90,14 +115,45 @@ async fn main() -> Result<()> {
                 None
             };
 
+            ServerSettingsStore::reload_periodically(
+                state.server_settings.clone(),
+                state.executor.clone(),
+            );
+
             if is_collab {
                 state.db.purge_old_embeddings().await.trace_err();
                 RateLimiter::save_periodically(state.rate_limiter.clone(), state.executor.clone());
             }
 
             if is_api {
-                poll_stripe_events_periodically(state.clone());
+                if state.config.billing_encryption_keys.is_some() {
+                    state
+                        .db
+                        .reencrypt_billing_customer_sensitive_details()
+                        .await
+                        .trace_err();
+                }
+                if state.config.billing_mode().is_stripe() {
+                    poll_stripe_events_periodically(state.clone(), rpc_server.clone());
+                    reconcile_stripe_subscriptions_periodically(state.clone(), rpc_server.clone());
+                    complete_stripe_key_rotations_periodically(state.clone());
+                    report_usage_periodically(state.clone());
+                    true_up_organization_seats_periodically(state.clone());
+                    run_dunning_periodically(state.clone(), rpc_server.clone());
+                }
+                if state.config.github_sponsors_enabled() {
+                    sync_github_sponsors_periodically(state.clone(), rpc_server.clone());
+                }
+                if state.config.sandbox_organizations_enabled() {
+                    delete_expired_sandbox_organizations_periodically(state.clone());
+                }
                 fetch_extensions_from_blob_store_periodically(state.clone());
+                backfill_renamed_columns_periodically(state.clone());
+                detect_usage_anomalies_periodically(state.clone());
+                deliver_emails_periodically(state.clone());
+                archive_old_records_periodically(state.clone());
+                enforce_plan_limits_periodically(state.clone());
+                roll_up_organization_usage_periodically(state.clone());
             }
 
             let mut app = collab::api::routes(rpc_server.clone(), state.clone());
@@ -170,20 +226,37 @@ async fn main() -> Result<()> {
             axum::Server::from_tcp(listener)
                 .map_err(|e| anyhow!(e))?
                 .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-                .with_graceful_shutdown(async move {
-                    signal.await;
-                    tracing::info!("Received interrupt signal");
+                .with_graceful_shutdown({
+                    let state = state.clone();
+                    async move {
+                        signal.await;
+                        tracing::info!("Received interrupt signal");
 
-                    if let Some(rpc_server) = rpc_server {
-                        rpc_server.teardown();
+                        state.shutdown.begin();
+                        if let Some(rpc_server) = rpc_server {
+                            rpc_server.teardown();
+                        }
                     }
                 })
                 .await
                 .map_err(|e| anyhow!(e))?;
+
+            // The server has stopped accepting requests and finished the ones
+            // in flight. Let background jobs finish what they're doing, and
+            // persist what's buffered in memory before exiting.
+            state
+                .shutdown
+                .drain(&state.executor, SHUTDOWN_DEADLINE)
+                .await;
+            if is_collab {
+                state.rate_limiter.save().await.trace_err();
+            }
+            state.db.close().await.trace_err();
+            tracing::info!("Shut down");
         }
         _ => {
             Err(anyhow!(
-                "usage: collab <version | migrate | seed | serve [api|collab]>"
+                "usage: collab <version | migrate | seed | serve [api|collab] | bench>"
             ))?;
         }
     }
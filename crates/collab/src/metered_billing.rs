@@ -0,0 +1,522 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+use stripe::{Invoice, InvoiceStatus, Subscription, SubscriptionId, SubscriptionItemId};
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::api::billing::idempotent_client;
+use crate::db::{
+    usage_record, BillingSubscriptionId, CreateBillingInvoiceLineItemParams,
+    CreateUsageRecordParams,
+};
+use crate::distributed_lock::run_exclusively;
+use crate::payment_provider::PaymentProvider;
+use crate::AppState;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The longest billing period, whose usage is recorded as it accrues.
+pub(crate) const MAX_BILLING_PERIOD_IN_DAYS: i64 = 31;
+
+/// The length of the periods usage is recorded and reported for.
+const USAGE_PERIOD: time::Duration = time::Duration::hours(1);
+
+/// The number of periods recorded for a single subscription in one run, so
+/// that catching up after an outage happens gradually.
+const MAX_PERIODS_PER_RUN: usize = 24;
+
+/// The number of usage records reported to Stripe in one run.
+const REPORT_BATCH_SIZE: u64 = 100;
+
+/// The longest we wait before retrying to report a usage record.
+const MAX_REPORT_BACKOFF: time::Duration = time::Duration::hours(6);
+
+/// The number of models broken out in a usage line's description, with the
+/// rest summed up together, to keep the description readable on the invoice.
+const MAX_DESCRIBED_MODELS: usize = 5;
+
+/// Periodically records and reports the usage of metered subscriptions, when
+/// a metered price is configured.
+pub fn report_usage_periodically(app: Arc<AppState>) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+    let Some(metered_price_id) = app.config.stripe_metered_price_id.clone() else {
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "report_usage",
+                        report_usage(&app, &stripe_client, &metered_price_id),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, REPORT_INTERVAL).await;
+            }
+        }
+    });
+}
+
+/// Records the usage of every metered subscription for the periods that have
+/// ended since the last run, and reports the records that are due to Stripe.
+///
+/// While billing is read-only, usage is only recorded, and reported once it's
+/// writable again.
+pub async fn report_usage(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    metered_price_id: &str,
+) -> anyhow::Result<()> {
+    let now = OffsetDateTime::now_utc();
+    record_usage(app, now).await?;
+    if app.server_settings.get().billing_read_only() {
+        log::info!("not reporting usage to Stripe while billing is read-only");
+        return Ok(());
+    }
+    report_usage_records(app, stripe_client, metered_price_id, now).await
+}
+
+async fn record_usage(app: &AppState, now: OffsetDateTime) -> anyhow::Result<()> {
+    let current_period_start = start_of_hour(now);
+
+    for (subscription, billing_customer) in app.db.get_metered_billing_subscriptions().await? {
+        if app.shutdown.is_shutting_down() {
+            break;
+        }
+
+        // Subscriptions that predate metering only have their usage recorded
+        // from the last completed period onwards.
+        let mut period_start = match app.db.get_latest_usage_record(subscription.id).await? {
+            Some(record) => record.period_end.assume_utc(),
+            None => start_of_hour(subscription.created_at.assume_utc())
+                .max(current_period_start - USAGE_PERIOD),
+        };
+
+        for _ in 0..MAX_PERIODS_PER_RUN {
+            let period_end = period_start + USAGE_PERIOD;
+            if period_end > current_period_start {
+                break;
+            }
+
+            let quantity = app
+                .db
+                .get_language_model_token_usage_for_user(
+                    billing_customer.user_id,
+                    period_start,
+                    period_end,
+                )
+                .await?;
+            app.db
+                .create_usage_record(&CreateUsageRecordParams {
+                    billing_subscription_id: subscription.id,
+                    period_start: PrimitiveDateTime::new(period_start.date(), period_start.time()),
+                    period_end: PrimitiveDateTime::new(period_end.date(), period_end.time()),
+                    quantity,
+                })
+                .await?;
+            period_start = period_end;
+        }
+    }
+
+    Ok(())
+}
+
+async fn report_usage_records(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    metered_price_id: &str,
+    now: OffsetDateTime,
+) -> anyhow::Result<()> {
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let records = app
+        .db
+        .get_usage_records_to_report(now, REPORT_BATCH_SIZE)
+        .await?;
+
+    let mut subscription_items = HashMap::default();
+    for (index, record) in records.into_iter().enumerate() {
+        if app.shutdown.is_shutting_down() {
+            break;
+        }
+
+        if record.quantity == 0 {
+            app.db
+                .mark_usage_record_reported(record.id, None, now)
+                .await?;
+            continue;
+        }
+
+        // Stripe keeps a single quantity per subscription item and time, so
+        // each record in the batch gets a time of its own.
+        let record = app
+            .db
+            .set_usage_record_stripe_timestamp(
+                record.id,
+                now - time::Duration::seconds(index as i64),
+            )
+            .await?;
+
+        let result = report_usage_record(
+            app,
+            stripe_client,
+            metered_price_id,
+            &record,
+            &mut subscription_items,
+        )
+        .await;
+        match result {
+            Ok(Some(stripe_usage_record)) => {
+                app.db
+                    .mark_usage_record_reported(record.id, Some(&stripe_usage_record.id), now)
+                    .await?;
+            }
+            Ok(None) => {
+                // Subscriptions that predate metering have nowhere to report
+                // their usage to, so it goes unbilled rather than being
+                // retried forever.
+                log::warn!(
+                    "subscription {} has no metered item, so usage record {} won't be reported",
+                    record.billing_subscription_id,
+                    record.id
+                );
+                app.db
+                    .mark_usage_record_reported(record.id, None, now)
+                    .await?;
+            }
+            Err(error) => {
+                let backoff = usage_report_backoff(record.attempt_count + 1);
+                log::error!(
+                    "failed to report usage record {} (attempt {}), retrying in {backoff}: {error:?}",
+                    record.id,
+                    record.attempt_count + 1
+                );
+                app.db
+                    .record_usage_record_failure(record.id, &format!("{error:#}"), now + backoff)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct CreateUsageRecord {
+    quantity: i64,
+    timestamp: i64,
+    action: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+struct StripeUsageRecord {
+    id: String,
+}
+
+async fn report_usage_record(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    metered_price_id: &str,
+    record: &usage_record::Model,
+    subscription_items: &mut HashMap<BillingSubscriptionId, Option<SubscriptionItemId>>,
+) -> anyhow::Result<Option<StripeUsageRecord>> {
+    let subscription_item_id = match subscription_items.get(&record.billing_subscription_id) {
+        Some(subscription_item_id) => subscription_item_id.clone(),
+        None => {
+            let subscription_item_id = find_metered_subscription_item(
+                app,
+                stripe_client,
+                metered_price_id,
+                record.billing_subscription_id,
+            )
+            .await?;
+            subscription_items.insert(record.billing_subscription_id, subscription_item_id.clone());
+            subscription_item_id
+        }
+    };
+    let Some(subscription_item_id) = subscription_item_id else {
+        return Ok(None);
+    };
+    let timestamp = record
+        .stripe_timestamp
+        .ok_or_else(|| anyhow!("usage record {} has no Stripe timestamp", record.id))?;
+
+    // The usage is set at the record's own time, so reporting it again, even
+    // long after Stripe has forgotten an idempotency key, doesn't count it
+    // twice. That time is chosen when the record is first reported, rather
+    // than being the period it was recorded for, as Stripe rejects usage
+    // from before the subscription's current billing period.
+    let stripe_client = idempotent_client(stripe_client, format!("usage_record:{}", record.id));
+    Ok(Some(
+        stripe_client
+            .post_form(
+                &format!("/subscription_items/{subscription_item_id}/usage_records"),
+                CreateUsageRecord {
+                    quantity: record.quantity,
+                    timestamp: timestamp.assume_utc().unix_timestamp(),
+                    action: "set",
+                },
+            )
+            .await?,
+    ))
+}
+
+/// Returns the item of the subscription that's billed at the metered price,
+/// or `None` if it has none.
+async fn find_metered_subscription_item(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    metered_price_id: &str,
+    billing_subscription_id: BillingSubscriptionId,
+) -> anyhow::Result<Option<SubscriptionItemId>> {
+    let subscription = app
+        .db
+        .get_billing_subscription_by_id(billing_subscription_id)
+        .await?
+        .ok_or_else(|| anyhow!("billing subscription {billing_subscription_id} not found"))?;
+    let stripe_subscription_id = SubscriptionId::from_str(&subscription.stripe_subscription_id)
+        .context("failed to parse subscription ID")?;
+    let subscription = Subscription::retrieve(stripe_client, &stripe_subscription_id, &[]).await?;
+
+    Ok(subscription
+        .items
+        .data
+        .into_iter()
+        .find(|item| {
+            item.price
+                .as_ref()
+                .map_or(false, |price| price.id.as_str() == metered_price_id)
+        })
+        .map(|item| item.id))
+}
+
+/// Describes the lines of a draft invoice that bill for metered usage with
+/// the models the usage was for, and records the descriptions.
+///
+/// Stripe only allows lines to be updated while the invoice is a draft, which
+/// subscription invoices are for an hour after they're created.
+pub async fn describe_invoice_usage(
+    app: &AppState,
+    payment_provider: &dyn PaymentProvider,
+    metered_price_id: &str,
+    invoice: &Invoice,
+) -> anyhow::Result<()> {
+    if invoice.status != Some(InvoiceStatus::Draft) {
+        return Ok(());
+    }
+    let (Some(customer), Some(subscription), Some(lines)) =
+        (&invoice.customer, &invoice.subscription, &invoice.lines)
+    else {
+        return Ok(());
+    };
+
+    let Some(billing_customer) = app
+        .db
+        .get_billing_customer_by_stripe_customer_id(customer.id().as_str())
+        .await?
+    else {
+        return Ok(());
+    };
+    let billing_subscription = app
+        .db
+        .get_billing_subscriptions(billing_customer.user_id)
+        .await?
+        .into_iter()
+        .find(|billing_subscription| {
+            billing_subscription.stripe_subscription_id == subscription.id().as_str()
+        })
+        .ok_or_else(|| anyhow!("billing subscription {} not found", subscription.id()))?;
+
+    for line in &lines.data {
+        let is_metered = line
+            .price
+            .as_ref()
+            .map_or(false, |price| price.id.as_str() == metered_price_id);
+        if !is_metered {
+            continue;
+        }
+        let (Some(period_start), Some(period_end)) = (
+            line.period.as_ref().and_then(|period| period.start),
+            line.period.as_ref().and_then(|period| period.end),
+        ) else {
+            continue;
+        };
+
+        let period_start = OffsetDateTime::from_unix_timestamp(period_start)?;
+        let period_end = OffsetDateTime::from_unix_timestamp(period_end)?;
+        let usage_by_model = app
+            .db
+            .get_language_model_token_usage_by_model_for_user(
+                billing_customer.user_id,
+                period_start,
+                period_end,
+            )
+            .await?;
+        let description = usage_line_description(period_start, period_end, &usage_by_model);
+
+        payment_provider
+            .update_invoice_line_description(invoice.id.as_str(), line.id.as_str(), &description)
+            .await?;
+        app.db
+            .record_billing_invoice_line_item(&CreateBillingInvoiceLineItemParams {
+                billing_subscription_id: billing_subscription.id,
+                stripe_invoice_id: invoice.id.to_string(),
+                stripe_invoice_line_item_id: line.id.to_string(),
+                period_start: PrimitiveDateTime::new(period_start.date(), period_start.time()),
+                period_end: PrimitiveDateTime::new(period_end.date(), period_end.time()),
+                quantity: line.quantity.unwrap_or_default() as i64,
+                description,
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the description of an invoice line for the usage in the given
+/// period, such as "Language model usage, Aug 1, 2024 – Sep 1, 2024:
+/// claude-3-5-sonnet 1.2M tokens, gpt-4o 30K tokens".
+fn usage_line_description(
+    period_start: OffsetDateTime,
+    period_end: OffsetDateTime,
+    usage_by_model: &[(String, i64)],
+) -> String {
+    let date_format = format_description!("[month repr:short] [day padding:none], [year]");
+    let mut description = format!(
+        "Language model usage, {} – {}",
+        period_start.format(date_format).unwrap_or_default(),
+        period_end.format(date_format).unwrap_or_default()
+    );
+
+    let mut models = usage_by_model
+        .iter()
+        .filter(|(_, tokens)| *tokens > 0)
+        .map(|(model, tokens)| (model.as_str(), *tokens))
+        .collect::<Vec<_>>();
+    if models.len() > MAX_DESCRIBED_MODELS {
+        let other_models = models.split_off(MAX_DESCRIBED_MODELS - 1);
+        models.push((
+            "other models",
+            other_models.iter().map(|(_, tokens)| tokens).sum(),
+        ));
+    }
+    for (ix, (model, tokens)) in models.into_iter().enumerate() {
+        description.push_str(if ix == 0 { ": " } else { ", " });
+        description.push_str(&format!("{model} {} tokens", format_token_count(tokens)));
+    }
+
+    description
+}
+
+/// Formats a number of tokens compactly, such as "1.2M" or "30K".
+fn format_token_count(tokens: i64) -> String {
+    let (value, suffix) = if tokens >= 1_000_000 {
+        (tokens as f64 / 1_000_000., "M")
+    } else if tokens >= 1_000 {
+        (tokens as f64 / 1_000., "K")
+    } else {
+        return tokens.to_string();
+    };
+    let value = format!("{value:.1}");
+    format!("{}{suffix}", value.trim_end_matches(".0"))
+}
+
+fn start_of_hour(time: OffsetDateTime) -> OffsetDateTime {
+    let time = time.to_offset(time::UtcOffset::UTC);
+    time.date()
+        .with_hms(time.hour(), 0, 0)
+        .unwrap()
+        .assume_utc()
+}
+
+/// Returns how long to wait before retrying to report a usage record after
+/// the given number of failed attempts, doubling with each attempt.
+fn usage_report_backoff(attempt_count: i32) -> time::Duration {
+    let exponent = attempt_count.saturating_sub(1).clamp(0, 16) as u32;
+    (time::Duration::minutes(1) * 2_i32.pow(exponent)).min(MAX_REPORT_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_start_of_hour() {
+        assert_eq!(
+            start_of_hour(datetime!(2024-08-22 13:45:12 UTC)),
+            datetime!(2024-08-22 13:00 UTC)
+        );
+        assert_eq!(
+            start_of_hour(datetime!(2024-08-22 13:00 UTC)),
+            datetime!(2024-08-22 13:00 UTC)
+        );
+    }
+
+    #[test]
+    fn test_usage_report_backoff() {
+        assert_eq!(usage_report_backoff(1), time::Duration::minutes(1));
+        assert_eq!(usage_report_backoff(2), time::Duration::minutes(2));
+        assert_eq!(usage_report_backoff(5), time::Duration::minutes(16));
+        assert_eq!(usage_report_backoff(10), time::Duration::hours(6));
+        assert_eq!(usage_report_backoff(i32::MAX), time::Duration::hours(6));
+    }
+
+    #[test]
+    fn test_usage_line_description() {
+        let period_start = datetime!(2024-08-01 0:00 UTC);
+        let period_end = datetime!(2024-09-01 0:00 UTC);
+
+        assert_eq!(
+            usage_line_description(
+                period_start,
+                period_end,
+                &[
+                    ("claude-3-5-sonnet".into(), 1_234_567),
+                    ("gpt-4o".into(), 30_000),
+                    ("gemini-1.5-flash".into(), 0),
+                ]
+            ),
+            "Language model usage, Aug 1, 2024 – Sep 1, 2024: claude-3-5-sonnet 1.2M tokens, gpt-4o 30K tokens"
+        );
+        assert_eq!(
+            usage_line_description(period_start, period_end, &[]),
+            "Language model usage, Aug 1, 2024 – Sep 1, 2024"
+        );
+
+        let usage_by_model = (1..=7)
+            .rev()
+            .map(|ix| (format!("model-{ix}"), ix * 100))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            usage_line_description(period_start, period_end, &usage_by_model),
+            "Language model usage, Aug 1, 2024 – Sep 1, 2024: model-7 700 tokens, model-6 600 tokens, model-5 500 tokens, model-4 400 tokens, other models 600 tokens"
+        );
+    }
+
+    #[test]
+    fn test_format_token_count() {
+        assert_eq!(format_token_count(999), "999");
+        assert_eq!(format_token_count(1_000), "1K");
+        assert_eq!(format_token_count(30_400), "30.4K");
+        assert_eq!(format_token_count(1_234_567), "1.2M");
+    }
+}
@@ -0,0 +1,117 @@
+use rpc::proto;
+use sha2::{Digest, Sha256};
+
+use crate::db::{model_experiment_variant, Database, UserId};
+use crate::Result;
+
+/// Deterministically assigns the user to one of the variants, in proportion to
+/// their weights.
+///
+/// The same user is always assigned to the same variant of an experiment, as
+/// long as its variants don't change.
+pub fn assign_variant<'a>(
+    experiment_name: &str,
+    user_id: UserId,
+    variants: &'a [model_experiment_variant::Model],
+) -> Option<&'a model_experiment_variant::Model> {
+    let total_weight = variants
+        .iter()
+        .map(|variant| variant.weight.max(0) as u64)
+        .sum::<u64>();
+    if total_weight == 0 {
+        return None;
+    }
+
+    let digest = Sha256::digest(format!("{experiment_name}:{user_id}").as_bytes());
+    let mut bucket = u64::from_be_bytes(digest[..8].try_into().unwrap()) % total_weight;
+    for variant in variants {
+        let weight = variant.weight.max(0) as u64;
+        if bucket < weight {
+            return Some(variant);
+        }
+        bucket -= weight;
+    }
+
+    None
+}
+
+/// Returns the default language model to recommend to the user, based on the
+/// active experiment, and records their exposure to it.
+pub async fn recommended_model_for_user(
+    db: &Database,
+    user_id: UserId,
+) -> Result<Option<proto::RecommendedLanguageModel>> {
+    let Some((experiment, variants)) = db.get_active_model_experiment().await? else {
+        return Ok(None);
+    };
+
+    // Keep serving the variant a user was first exposed to, even if the
+    // weights have changed since.
+    let previous_variant_id = db
+        .get_model_experiment_exposure(experiment.id, user_id)
+        .await?
+        .map(|exposure| exposure.variant_id);
+    let variant = match previous_variant_id
+        .and_then(|variant_id| variants.iter().find(|variant| variant.id == variant_id))
+    {
+        Some(variant) => variant,
+        None => {
+            let Some(variant) = assign_variant(&experiment.name, user_id, &variants) else {
+                return Ok(None);
+            };
+            db.record_model_experiment_exposure(experiment.id, variant.id, user_id)
+                .await?;
+            variant
+        }
+    };
+
+    Ok(Some(proto::RecommendedLanguageModel {
+        experiment: experiment.name,
+        variant: variant.name.clone(),
+        provider: variant.provider.clone(),
+        model: variant.model.clone(),
+        temperature: variant.temperature,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ModelExperimentId, ModelExperimentVariantId};
+
+    fn variant(id: i32, weight: i32) -> model_experiment_variant::Model {
+        model_experiment_variant::Model {
+            id: ModelExperimentVariantId(id),
+            experiment_id: ModelExperimentId(1),
+            name: format!("variant-{id}"),
+            weight,
+            provider: "zed.dev".into(),
+            model: format!("model-{id}"),
+            temperature: None,
+        }
+    }
+
+    #[test]
+    fn test_assign_variant() {
+        let variants = [variant(1, 3), variant(2, 1), variant(3, 0)];
+
+        let mut counts = [0; 3];
+        for user_id in 0..4000 {
+            let assigned = assign_variant("experiment", UserId(user_id), &variants).unwrap();
+            assert_eq!(
+                assign_variant("experiment", UserId(user_id), &variants)
+                    .unwrap()
+                    .id,
+                assigned.id,
+                "assignment should be stable"
+            );
+            counts[assigned.id.0 as usize - 1] += 1;
+        }
+
+        assert_eq!(counts[2], 0, "zero-weight variants are never assigned");
+        assert!((2700..3300).contains(&counts[0]), "{counts:?}");
+        assert!((700..1300).contains(&counts[1]), "{counts:?}");
+
+        assert!(assign_variant("experiment", UserId(1), &[variant(1, 0)]).is_none());
+    }
+}
@@ -0,0 +1,1335 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _, Result};
+use async_trait::async_trait;
+use collections::BTreeMap;
+#[cfg(any(test, feature = "test-support"))]
+use collections::HashMap;
+#[cfg(any(test, feature = "test-support"))]
+use parking_lot::{Mutex, MutexGuard};
+use serde::Serialize;
+use stripe::{
+    BillingPortalSession, BillingPortalSessionLocale, CancelSubscription, CheckoutSession,
+    CheckoutSessionBillingAddressCollection, CheckoutSessionLocale, CheckoutSessionMode,
+    CreateBillingPortalSession, CreateBillingPortalSessionFlowData,
+    CreateBillingPortalSessionFlowDataAfterCompletion,
+    CreateBillingPortalSessionFlowDataAfterCompletionRedirect,
+    CreateBillingPortalSessionFlowDataAfterCompletionType,
+    CreateBillingPortalSessionFlowDataSubscriptionCancel,
+    CreateBillingPortalSessionFlowDataSubscriptionUpdateConfirm,
+    CreateBillingPortalSessionFlowDataSubscriptionUpdateConfirmItems,
+    CreateBillingPortalSessionFlowDataType, CreateCheckoutSession,
+    CreateCheckoutSessionAutomaticTax, CreateCheckoutSessionCustomerUpdate,
+    CreateCheckoutSessionCustomerUpdateAddress, CreateCheckoutSessionCustomerUpdateName,
+    CreateCheckoutSessionDiscounts, CreateCheckoutSessionLineItems,
+    CreateCheckoutSessionSubscriptionData, CreateCheckoutSessionTaxIdCollection, CreateCustomer,
+    Currency, Customer, CustomerBalanceTransaction, CustomerId, EventId, InvoiceLineItem, List,
+    ListEvents, PaymentMethod, PaymentMethodId, Price, PriceId, PromotionCode, RangeBounds,
+    RangeQuery, Subscription, SubscriptionId, SubscriptionProrationBehavior, UpdateCustomer,
+    UpdateSubscription, UpdateSubscriptionItems,
+};
+use time::OffsetDateTime;
+
+use crate::api::billing::{idempotent_client, stripe_locale};
+use crate::db::billing_subscription::StripeSubscriptionStatus;
+use crate::executor::Executor;
+use crate::stripe_connect::client_for_account;
+use crate::stripe_retry::with_retries;
+use crate::stripe_test_clocks;
+
+/// A processor of payments, such as Stripe.
+///
+/// Requests that create something take an idempotency key, so that a retried
+/// request returns what the original one created rather than a duplicate.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Returns a provider that acts on behalf of the given connected account,
+    /// or on our own account for `None`.
+    fn for_account(&self, account_id: Option<&str>) -> Result<Arc<dyn PaymentProvider>>;
+
+    async fn get_price(&self, price_id: &str) -> Result<PaymentPrice>;
+
+    /// Returns the ID of the test clock with the given name, creating it if
+    /// there's none, for simulating the passage of time in test mode.
+    async fn find_or_create_test_clock(&self, name: &str) -> Result<String>;
+
+    /// Creates a customer, returning their ID.
+    async fn create_customer(
+        &self,
+        params: &CreateCustomerParams,
+        idempotency_key: String,
+    ) -> Result<String>;
+
+    /// Returns the customer's email address, if they have one.
+    async fn get_customer_email(&self, customer_id: &str) -> Result<Option<String>>;
+
+    /// Returns the currency the customer is billed in, once they've been
+    /// billed at all.
+    async fn get_customer_currency(&self, customer_id: &str) -> Result<Option<String>>;
+
+    /// Changes the address receipts and invoices are sent to, or removes it
+    /// when `email` is `None`.
+    async fn update_customer_email(&self, customer_id: &str, email: Option<&str>) -> Result<()>;
+
+    /// Adds an amount to the customer's balance, which is applied to their
+    /// next invoice. Negative amounts are credits.
+    async fn adjust_customer_balance(
+        &self,
+        params: &AdjustCustomerBalanceParams,
+        idempotency_key: String,
+    ) -> Result<()>;
+
+    /// Returns the ID of the active promotion code with the given
+    /// customer-facing code, if any.
+    async fn find_promotion_code(&self, code: &str) -> Result<Option<String>>;
+
+    /// Returns the fingerprint of the payment method's card, which is the
+    /// same for every payment method with that card number, or `None` if it
+    /// isn't a card.
+    async fn get_payment_method_fingerprint(
+        &self,
+        payment_method_id: &str,
+    ) -> Result<Option<String>>;
+
+    async fn create_checkout_session(
+        &self,
+        params: &CreateCheckoutSessionParams,
+        idempotency_key: String,
+    ) -> Result<PaymentCheckoutSession>;
+
+    /// Creates a session of the customer portal, returning its URL.
+    async fn create_portal_session(
+        &self,
+        params: &CreatePortalSessionParams,
+        idempotency_key: String,
+    ) -> Result<String>;
+
+    async fn get_subscription(&self, subscription_id: &str) -> Result<PaymentSubscription>;
+
+    /// Cancels the subscription, either right away or at the end of its
+    /// current billing period, and returns it as updated.
+    async fn cancel_subscription(
+        &self,
+        subscription_id: &str,
+        at_period_end: bool,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription>;
+
+    /// Undoes the cancellation of a subscription at the end of its period.
+    async fn resume_subscription(
+        &self,
+        subscription_id: &str,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription>;
+
+    /// Changes the quantity of the subscription's item, charging or
+    /// crediting a prorated amount for the rest of the billing period.
+    async fn update_subscription_seats(
+        &self,
+        subscription_id: &str,
+        item_id: &str,
+        seat_count: u64,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription>;
+
+    /// Changes the description of a line of a draft invoice.
+    async fn update_invoice_line_description(
+        &self,
+        invoice_id: &str,
+        line_id: &str,
+        description: &str,
+    ) -> Result<()>;
+
+    /// Lists a page of the account's events, newest first.
+    async fn list_events(&self, params: &ListEventsParams) -> Result<PaymentEventPage>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListEventsParams {
+    pub types: Vec<String>,
+    /// Only lists events created after this Unix timestamp.
+    pub created_after: Option<i64>,
+    /// Only lists the events that happened after the one with this ID.
+    pub ending_before: Option<String>,
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PaymentEventPage {
+    pub events: Vec<stripe::Event>,
+    /// Whether there are more events to list, past the ones on this page.
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentPrice {
+    /// The price per unit, in the currency's smallest unit, or `None` for
+    /// prices without a single amount, such as tiered ones.
+    pub unit_amount: Option<i64>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CreateCustomerParams {
+    pub email: Option<String>,
+    /// Attaches the customer to this test clock, for simulating the passage
+    /// of time in test mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_clock_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AdjustCustomerBalanceParams {
+    pub customer_id: String,
+    /// The amount in the currency's smallest unit.
+    pub amount: i64,
+    pub currency: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CheckoutMode {
+    Subscription,
+    /// A one-time payment.
+    Payment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CheckoutCustomer {
+    Existing {
+        customer_id: String,
+    },
+    /// A customer created by completing the checkout session.
+    New {
+        email: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckoutLineItem {
+    pub price_id: String,
+    /// `None` for metered prices, which are billed for the usage reported to
+    /// them rather than for a quantity.
+    pub quantity: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CheckoutDiscount {
+    PromotionCode { promotion_code_id: String },
+    Coupon { coupon_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CreateCheckoutSessionParams {
+    pub mode: CheckoutMode,
+    pub customer: CheckoutCustomer,
+    /// Identifies the user the session is for, in the processor's dashboard.
+    pub client_reference_id: String,
+    pub line_items: Vec<CheckoutLineItem>,
+    pub metadata: BTreeMap<String, String>,
+    /// The metadata of the subscription, in subscription mode.
+    pub subscription_metadata: BTreeMap<String, String>,
+    pub trial_period_days: Option<u32>,
+    pub discount: Option<CheckoutDiscount>,
+    /// Whether the customer can enter a promotion code during checkout.
+    pub allow_promotion_codes: bool,
+    pub success_url: String,
+    pub locale: Option<String>,
+    /// Whether tax is calculated, in which case checkout requires a billing
+    /// address and offers to collect a tax ID.
+    pub automatic_tax: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentCheckoutSession {
+    pub id: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatePortalSessionParams {
+    pub customer_id: String,
+    /// What the customer is taken straight to, or `None` for the portal's
+    /// home page.
+    pub flow: Option<PortalFlow>,
+    /// The portal configuration to use, rather than the default one.
+    pub configuration_id: Option<String>,
+    /// Where the customer is sent once they're done.
+    pub return_url: String,
+    pub locale: Option<String>,
+}
+
+/// What the customer is taken straight to in the portal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortalFlow {
+    CancelSubscription {
+        subscription_id: String,
+    },
+    /// Shows the customer the prorated cost of changing the subscription's
+    /// item before they confirm it.
+    ConfirmSubscriptionUpdate {
+        subscription_id: String,
+        item_id: String,
+        price_id: String,
+        quantity: u64,
+    },
+    UpdatePaymentMethod,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentSubscription {
+    pub id: String,
+    pub customer_id: String,
+    pub status: StripeSubscriptionStatus,
+    pub items: Vec<PaymentSubscriptionItem>,
+    pub metadata: BTreeMap<String, String>,
+    pub coupon_id: Option<String>,
+    pub promotion_code_id: Option<String>,
+    pub trial_end: Option<OffsetDateTime>,
+    pub current_period_start: OffsetDateTime,
+    pub current_period_end: OffsetDateTime,
+    pub cancel_at_period_end: bool,
+    pub canceled_at: Option<OffsetDateTime>,
+    /// The payment method the subscription is charged to, when it isn't
+    /// charged to the customer's default.
+    pub default_payment_method_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentSubscriptionItem {
+    pub id: String,
+    pub price_id: Option<String>,
+    pub quantity: Option<u64>,
+}
+
+impl PaymentSubscription {
+    /// Returns the item that's billed for the plan, as opposed to a metered
+    /// item billed for usage, which has no quantity.
+    pub fn licensed_item(&self) -> Result<&PaymentSubscriptionItem> {
+        self.items
+            .iter()
+            .find(|item| item.quantity.is_some())
+            .or_else(|| self.items.first())
+            .ok_or_else(|| anyhow!("subscription has no items"))
+    }
+
+    /// Returns the number of seats paid for, which is the quantity of the
+    /// licensed item.
+    pub fn seat_count(&self) -> i32 {
+        self.items
+            .iter()
+            .find_map(|item| item.quantity)
+            .map_or(1, |quantity| quantity as i32)
+    }
+}
+
+impl TryFrom<&Subscription> for PaymentSubscription {
+    type Error = anyhow::Error;
+
+    fn try_from(subscription: &Subscription) -> Result<Self> {
+        Ok(Self {
+            id: subscription.id.to_string(),
+            customer_id: subscription.customer.id().to_string(),
+            status: subscription.status.into(),
+            items: subscription
+                .items
+                .data
+                .iter()
+                .map(|item| PaymentSubscriptionItem {
+                    id: item.id.to_string(),
+                    price_id: item.price.as_ref().map(|price| price.id.to_string()),
+                    quantity: item.quantity,
+                })
+                .collect(),
+            metadata: subscription
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            coupon_id: subscription
+                .discount
+                .as_ref()
+                .map(|discount| discount.coupon.id.to_string()),
+            promotion_code_id: subscription
+                .discount
+                .as_ref()
+                .and_then(|discount| discount.promotion_code.as_ref())
+                .map(|promotion_code| promotion_code.id().to_string()),
+            trial_end: subscription
+                .trial_end
+                .map(OffsetDateTime::from_unix_timestamp)
+                .transpose()?,
+            current_period_start: OffsetDateTime::from_unix_timestamp(
+                subscription.current_period_start,
+            )?,
+            current_period_end: OffsetDateTime::from_unix_timestamp(
+                subscription.current_period_end,
+            )?,
+            cancel_at_period_end: subscription.cancel_at_period_end,
+            canceled_at: subscription
+                .canceled_at
+                .map(OffsetDateTime::from_unix_timestamp)
+                .transpose()?,
+            default_payment_method_id: subscription
+                .default_payment_method
+                .as_ref()
+                .map(|payment_method| payment_method.id().to_string()),
+        })
+    }
+}
+
+/// A [`PaymentProvider`] backed by Stripe.
+///
+/// Requests that are safe to repeat are retried when they fail transiently.
+pub struct StripePaymentProvider {
+    client: stripe::Client,
+    executor: Executor,
+}
+
+impl StripePaymentProvider {
+    pub fn new(client: stripe::Client, executor: Executor) -> Self {
+        Self { client, executor }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ListPromotionCodes<'a> {
+    code: &'a str,
+    active: bool,
+    limit: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct UpdateInvoiceLineItem<'a> {
+    description: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateCustomerBalanceTransaction<'a> {
+    amount: i64,
+    currency: Currency,
+    description: &'a str,
+}
+
+#[async_trait]
+impl PaymentProvider for StripePaymentProvider {
+    fn for_account(&self, account_id: Option<&str>) -> Result<Arc<dyn PaymentProvider>> {
+        Ok(Arc::new(Self::new(
+            client_for_account(&self.client, account_id)?,
+            self.executor.clone(),
+        )))
+    }
+
+    async fn get_price(&self, price_id: &str) -> Result<PaymentPrice> {
+        let price_id = PriceId::from_str(price_id).context("failed to parse price ID")?;
+        let price = with_retries(&self.executor, "retrieve price", || {
+            Price::retrieve(&self.client, &price_id, &[])
+        })
+        .await?;
+        Ok(PaymentPrice {
+            unit_amount: price.unit_amount,
+            currency: price.currency.map(|currency| currency.to_string()),
+        })
+    }
+
+    async fn find_or_create_test_clock(&self, name: &str) -> Result<String> {
+        Ok(
+            stripe_test_clocks::find_or_create_test_clock(&self.client, name)
+                .await?
+                .to_string(),
+        )
+    }
+
+    async fn create_customer(
+        &self,
+        params: &CreateCustomerParams,
+        idempotency_key: String,
+    ) -> Result<String> {
+        let client = idempotent_client(&self.client, idempotency_key);
+        let customer = with_retries(&self.executor, "create customer", || {
+            Customer::create(
+                &client,
+                CreateCustomer {
+                    email: params.email.as_deref(),
+                    test_clock: params.test_clock_id.as_deref(),
+                    ..Default::default()
+                },
+            )
+        })
+        .await?;
+        Ok(customer.id.to_string())
+    }
+
+    async fn get_customer_email(&self, customer_id: &str) -> Result<Option<String>> {
+        let customer_id =
+            CustomerId::from_str(customer_id).context("failed to parse customer ID")?;
+        let customer = with_retries(&self.executor, "retrieve customer", || {
+            Customer::retrieve(&self.client, &customer_id, &[])
+        })
+        .await?;
+        Ok(customer.email)
+    }
+
+    async fn get_customer_currency(&self, customer_id: &str) -> Result<Option<String>> {
+        let customer_id =
+            CustomerId::from_str(customer_id).context("failed to parse customer ID")?;
+        let customer = with_retries(&self.executor, "retrieve customer", || {
+            Customer::retrieve(&self.client, &customer_id, &[])
+        })
+        .await?;
+        Ok(customer.currency.map(|currency| currency.to_string()))
+    }
+
+    async fn update_customer_email(&self, customer_id: &str, email: Option<&str>) -> Result<()> {
+        let customer_id =
+            CustomerId::from_str(customer_id).context("failed to parse customer ID")?;
+        with_retries(&self.executor, "update customer", || {
+            Customer::update(
+                &self.client,
+                &customer_id,
+                UpdateCustomer {
+                    // Stripe leaves the email unchanged when it's left out,
+                    // and removes it when it's empty.
+                    email: Some(email.unwrap_or_default()),
+                    ..Default::default()
+                },
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn adjust_customer_balance(
+        &self,
+        params: &AdjustCustomerBalanceParams,
+        idempotency_key: String,
+    ) -> Result<()> {
+        let customer_id =
+            CustomerId::from_str(&params.customer_id).context("failed to parse customer ID")?;
+        let currency = Currency::from_str(&params.currency).context("failed to parse currency")?;
+        let client = idempotent_client(&self.client, idempotency_key);
+        with_retries(
+            &self.executor,
+            "create customer balance transaction",
+            || {
+                client.post_form::<CustomerBalanceTransaction, _>(
+                    &format!("/customers/{customer_id}/balance_transactions"),
+                    CreateCustomerBalanceTransaction {
+                        amount: params.amount,
+                        currency,
+                        description: &params.description,
+                    },
+                )
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn find_promotion_code(&self, code: &str) -> Result<Option<String>> {
+        let promotion_codes = with_retries(&self.executor, "list promotion codes", || {
+            self.client.get_query::<List<PromotionCode>, _>(
+                "/promotion_codes",
+                ListPromotionCodes {
+                    code,
+                    active: true,
+                    limit: 1,
+                },
+            )
+        })
+        .await?;
+        Ok(promotion_codes
+            .data
+            .into_iter()
+            .next()
+            .map(|promotion_code| promotion_code.id.to_string()))
+    }
+
+    async fn get_payment_method_fingerprint(
+        &self,
+        payment_method_id: &str,
+    ) -> Result<Option<String>> {
+        let payment_method_id = PaymentMethodId::from_str(payment_method_id)
+            .context("failed to parse payment method ID")?;
+        let payment_method = with_retries(&self.executor, "retrieve payment method", || {
+            PaymentMethod::retrieve(&self.client, &payment_method_id, &[])
+        })
+        .await?;
+        Ok(payment_method.card.and_then(|card| card.fingerprint))
+    }
+
+    async fn create_checkout_session(
+        &self,
+        params: &CreateCheckoutSessionParams,
+        idempotency_key: String,
+    ) -> Result<PaymentCheckoutSession> {
+        let mut stripe_params = CreateCheckoutSession::new();
+        stripe_params.mode = Some(match params.mode {
+            CheckoutMode::Subscription => CheckoutSessionMode::Subscription,
+            CheckoutMode::Payment => CheckoutSessionMode::Payment,
+        });
+        match &params.customer {
+            CheckoutCustomer::Existing { customer_id } => {
+                stripe_params.customer =
+                    Some(CustomerId::from_str(customer_id).context("failed to parse customer ID")?)
+            }
+            CheckoutCustomer::New { email } => stripe_params.customer_email = email.as_deref(),
+        }
+        stripe_params.client_reference_id = Some(&params.client_reference_id);
+        stripe_params.line_items = Some(
+            params
+                .line_items
+                .iter()
+                .map(|line_item| CreateCheckoutSessionLineItems {
+                    price: Some(line_item.price_id.clone()),
+                    quantity: line_item.quantity,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        if !params.metadata.is_empty() {
+            stripe_params.metadata = Some(params.metadata.clone().into_iter().collect());
+        }
+        if params.mode == CheckoutMode::Subscription {
+            stripe_params.subscription_data = Some(CreateCheckoutSessionSubscriptionData {
+                metadata: Some(params.subscription_metadata.clone().into_iter().collect()),
+                trial_period_days: params.trial_period_days,
+                ..Default::default()
+            });
+        }
+        stripe_params.success_url = Some(&params.success_url);
+        stripe_params.locale = params
+            .locale
+            .as_deref()
+            .and_then(stripe_locale::<CheckoutSessionLocale>);
+        apply_automatic_tax(&mut stripe_params, params.automatic_tax);
+        match &params.discount {
+            Some(CheckoutDiscount::PromotionCode { promotion_code_id }) => {
+                stripe_params.discounts = Some(vec![CreateCheckoutSessionDiscounts {
+                    promotion_code: Some(promotion_code_id.clone()),
+                    ..Default::default()
+                }])
+            }
+            Some(CheckoutDiscount::Coupon { coupon_id }) => {
+                stripe_params.discounts = Some(vec![CreateCheckoutSessionDiscounts {
+                    coupon: Some(coupon_id.clone()),
+                    ..Default::default()
+                }])
+            }
+            None => {}
+        }
+        if params.allow_promotion_codes {
+            stripe_params.allow_promotion_codes = Some(true);
+        }
+
+        let client = idempotent_client(&self.client, idempotency_key);
+        let session = with_retries(&self.executor, "create checkout session", || {
+            CheckoutSession::create(&client, stripe_params.clone())
+        })
+        .await?;
+        Ok(PaymentCheckoutSession {
+            id: session.id.to_string(),
+            url: session
+                .url
+                .ok_or_else(|| anyhow!("no checkout session URL"))?,
+        })
+    }
+
+    async fn create_portal_session(
+        &self,
+        params: &CreatePortalSessionParams,
+        idempotency_key: String,
+    ) -> Result<String> {
+        let after_completion = CreateBillingPortalSessionFlowDataAfterCompletion {
+            type_: CreateBillingPortalSessionFlowDataAfterCompletionType::Redirect,
+            redirect: Some(CreateBillingPortalSessionFlowDataAfterCompletionRedirect {
+                return_url: params.return_url.clone(),
+            }),
+            ..Default::default()
+        };
+        let flow = params.flow.as_ref().map(|flow| match flow {
+            PortalFlow::CancelSubscription { subscription_id } => {
+                CreateBillingPortalSessionFlowData {
+                    type_: CreateBillingPortalSessionFlowDataType::SubscriptionCancel,
+                    after_completion: Some(after_completion),
+                    subscription_cancel: Some(
+                        CreateBillingPortalSessionFlowDataSubscriptionCancel {
+                            subscription: subscription_id.clone(),
+                            retention: None,
+                        },
+                    ),
+                    ..Default::default()
+                }
+            }
+            PortalFlow::ConfirmSubscriptionUpdate {
+                subscription_id,
+                item_id,
+                price_id,
+                quantity,
+            } => CreateBillingPortalSessionFlowData {
+                type_: CreateBillingPortalSessionFlowDataType::SubscriptionUpdateConfirm,
+                after_completion: Some(after_completion),
+                subscription_update_confirm: Some(
+                    CreateBillingPortalSessionFlowDataSubscriptionUpdateConfirm {
+                        subscription: subscription_id.clone(),
+                        items: vec![
+                            CreateBillingPortalSessionFlowDataSubscriptionUpdateConfirmItems {
+                                id: item_id.clone(),
+                                price: Some(price_id.clone()),
+                                quantity: Some(*quantity),
+                            },
+                        ],
+                        discounts: None,
+                    },
+                ),
+                ..Default::default()
+            },
+            PortalFlow::UpdatePaymentMethod => CreateBillingPortalSessionFlowData {
+                type_: CreateBillingPortalSessionFlowDataType::PaymentMethodUpdate,
+                after_completion: Some(after_completion),
+                ..Default::default()
+            },
+        });
+
+        let customer_id =
+            CustomerId::from_str(&params.customer_id).context("failed to parse customer ID")?;
+        let mut stripe_params = CreateBillingPortalSession::new(customer_id);
+        stripe_params.flow_data = flow;
+        stripe_params.configuration = params.configuration_id.as_deref();
+        stripe_params.return_url = Some(&params.return_url);
+        stripe_params.locale = params
+            .locale
+            .as_deref()
+            .and_then(stripe_locale::<BillingPortalSessionLocale>);
+        let client = idempotent_client(&self.client, idempotency_key);
+        let session = with_retries(&self.executor, "create billing portal session", || {
+            BillingPortalSession::create(&client, stripe_params.clone())
+        })
+        .await?;
+        Ok(session.url)
+    }
+
+    async fn get_subscription(&self, subscription_id: &str) -> Result<PaymentSubscription> {
+        let subscription_id =
+            SubscriptionId::from_str(subscription_id).context("failed to parse subscription ID")?;
+        let subscription = with_retries(&self.executor, "retrieve subscription", || {
+            Subscription::retrieve(&self.client, &subscription_id, &[])
+        })
+        .await?;
+        PaymentSubscription::try_from(&subscription)
+    }
+
+    async fn cancel_subscription(
+        &self,
+        subscription_id: &str,
+        at_period_end: bool,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        let subscription_id =
+            SubscriptionId::from_str(subscription_id).context("failed to parse subscription ID")?;
+        // The key makes retries return the result of a cancellation that went
+        // through, rather than failing because it's already canceled.
+        let client = idempotent_client(&self.client, idempotency_key);
+        let subscription = if at_period_end {
+            let mut params = UpdateSubscription::new();
+            params.cancel_at_period_end = Some(true);
+            with_retries(&self.executor, "update subscription", || {
+                Subscription::update(&client, &subscription_id, params.clone())
+            })
+            .await?
+        } else {
+            with_retries(&self.executor, "cancel subscription", || {
+                Subscription::cancel(&client, &subscription_id, CancelSubscription::new())
+            })
+            .await?
+        };
+        PaymentSubscription::try_from(&subscription)
+    }
+
+    async fn resume_subscription(
+        &self,
+        subscription_id: &str,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        let subscription_id =
+            SubscriptionId::from_str(subscription_id).context("failed to parse subscription ID")?;
+        let mut params = UpdateSubscription::new();
+        params.cancel_at_period_end = Some(false);
+        let client = idempotent_client(&self.client, idempotency_key);
+        let subscription = with_retries(&self.executor, "update subscription", || {
+            Subscription::update(&client, &subscription_id, params.clone())
+        })
+        .await?;
+        PaymentSubscription::try_from(&subscription)
+    }
+
+    async fn update_subscription_seats(
+        &self,
+        subscription_id: &str,
+        item_id: &str,
+        seat_count: u64,
+        idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        let subscription_id =
+            SubscriptionId::from_str(subscription_id).context("failed to parse subscription ID")?;
+        let client = idempotent_client(&self.client, idempotency_key);
+        let mut params = UpdateSubscription::new();
+        params.items = Some(vec![UpdateSubscriptionItems {
+            id: Some(item_id.to_string()),
+            quantity: Some(seat_count),
+            ..Default::default()
+        }]);
+        params.proration_behavior = Some(SubscriptionProrationBehavior::CreateProrations);
+        let subscription = with_retries(&self.executor, "update subscription", || {
+            Subscription::update(&client, &subscription_id, params.clone())
+        })
+        .await?;
+        PaymentSubscription::try_from(&subscription)
+    }
+
+    async fn update_invoice_line_description(
+        &self,
+        invoice_id: &str,
+        line_id: &str,
+        description: &str,
+    ) -> Result<()> {
+        with_retries(&self.executor, "update invoice line item", || {
+            self.client.post_form::<InvoiceLineItem, _>(
+                &format!("/invoices/{invoice_id}/lines/{line_id}"),
+                UpdateInvoiceLineItem { description },
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn list_events(&self, params: &ListEventsParams) -> Result<PaymentEventPage> {
+        let mut list_params = ListEvents::new();
+        list_params.types = Some(params.types.clone());
+        list_params.limit = Some(params.limit);
+        list_params.created = params.created_after.map(|created_after| {
+            RangeQuery::Bounds(RangeBounds {
+                gt: Some(created_after),
+                ..Default::default()
+            })
+        });
+        list_params.ending_before = params
+            .ending_before
+            .as_deref()
+            .map(EventId::from_str)
+            .transpose()
+            .context("failed to parse event ID")?;
+        let page = with_retries(&self.executor, "list events", || {
+            stripe::Event::list(&self.client, &list_params)
+        })
+        .await?;
+        Ok(PaymentEventPage {
+            events: page.data,
+            has_more: page.has_more,
+        })
+    }
+}
+
+/// Has Stripe Tax calculate the tax due on a checkout session, when enabled.
+///
+/// Checkout then requires a billing address and offers to collect a tax ID,
+/// both of which are saved to the customer so that their invoices show them.
+fn apply_automatic_tax(params: &mut CreateCheckoutSession, enabled: bool) {
+    if !enabled {
+        return;
+    }
+
+    params.automatic_tax = Some(CreateCheckoutSessionAutomaticTax {
+        enabled: true,
+        ..Default::default()
+    });
+    params.billing_address_collection = Some(CheckoutSessionBillingAddressCollection::Required);
+    params.tax_id_collection = Some(CreateCheckoutSessionTaxIdCollection { enabled: true });
+    // Existing customers keep the details they had unless we let checkout
+    // update them.
+    if params.customer.is_some() {
+        params.customer_update = Some(CreateCheckoutSessionCustomerUpdate {
+            address: Some(CreateCheckoutSessionCustomerUpdateAddress::Auto),
+            name: Some(CreateCheckoutSessionCustomerUpdateName::Auto),
+            ..Default::default()
+        });
+    }
+}
+
+/// A [`PaymentProvider`] that keeps everything in memory.
+///
+/// Used in tests, where its state can be seeded and inspected.
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Clone, Default)]
+pub struct FakePaymentProvider {
+    state: Arc<Mutex<FakePaymentProviderState>>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Default)]
+pub struct FakePaymentProviderState {
+    pub prices: HashMap<String, PaymentPrice>,
+    pub customers: HashMap<String, FakeCustomer>,
+    /// Test clock IDs, by their names.
+    pub test_clocks: HashMap<String, String>,
+    /// Promotion code IDs, by their customer-facing codes.
+    pub promotion_codes: HashMap<String, String>,
+    /// Card fingerprints, by the IDs of the payment methods with those cards.
+    pub payment_method_fingerprints: HashMap<String, String>,
+    pub subscriptions: HashMap<String, PaymentSubscription>,
+    pub checkout_sessions: Vec<CreateCheckoutSessionParams>,
+    pub portal_sessions: Vec<CreatePortalSessionParams>,
+    /// Descriptions of invoice lines, by the IDs of the lines.
+    pub invoice_line_descriptions: HashMap<String, String>,
+    /// Events, in the order they happened.
+    pub events: Vec<stripe::Event>,
+    /// What each idempotency key has been used to create.
+    idempotency_keys: HashMap<String, String>,
+    next_id: u64,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeCustomer {
+    pub email: Option<String>,
+    pub currency: Option<String>,
+    pub balance: i64,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl FakePaymentProvider {
+    pub fn state(&self) -> MutexGuard<'_, FakePaymentProviderState> {
+        self.state.lock()
+    }
+
+    fn update_subscription(
+        &self,
+        subscription_id: &str,
+        update: impl FnOnce(&mut PaymentSubscription),
+    ) -> Result<PaymentSubscription> {
+        let mut state = self.state.lock();
+        let subscription = state
+            .subscriptions
+            .get_mut(subscription_id)
+            .ok_or_else(|| anyhow!("no such subscription {subscription_id}"))?;
+        update(subscription);
+        Ok(subscription.clone())
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl FakePaymentProviderState {
+    /// Returns what was created with the idempotency key, or creates it.
+    fn idempotent(
+        &mut self,
+        idempotency_key: String,
+        create: impl FnOnce(&mut Self) -> String,
+    ) -> String {
+        if let Some(id) = self.idempotency_keys.get(&idempotency_key) {
+            return id.clone();
+        }
+        let id = create(self);
+        self.idempotency_keys.insert(idempotency_key, id.clone());
+        id
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}_{}", self.next_id)
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+#[async_trait]
+impl PaymentProvider for FakePaymentProvider {
+    fn for_account(&self, _account_id: Option<&str>) -> Result<Arc<dyn PaymentProvider>> {
+        Ok(Arc::new(self.clone()))
+    }
+
+    async fn get_price(&self, price_id: &str) -> Result<PaymentPrice> {
+        self.state
+            .lock()
+            .prices
+            .get(price_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no such price {price_id}"))
+    }
+
+    async fn find_or_create_test_clock(&self, name: &str) -> Result<String> {
+        let mut state = self.state.lock();
+        if let Some(test_clock_id) = state.test_clocks.get(name) {
+            return Ok(test_clock_id.clone());
+        }
+        let test_clock_id = state.next_id("clock");
+        state
+            .test_clocks
+            .insert(name.to_string(), test_clock_id.clone());
+        Ok(test_clock_id)
+    }
+
+    async fn create_customer(
+        &self,
+        params: &CreateCustomerParams,
+        idempotency_key: String,
+    ) -> Result<String> {
+        Ok(self.state.lock().idempotent(idempotency_key, |state| {
+            let customer_id = state.next_id("cus");
+            state.customers.insert(
+                customer_id.clone(),
+                FakeCustomer {
+                    email: params.email.clone(),
+                    ..Default::default()
+                },
+            );
+            customer_id
+        }))
+    }
+
+    async fn get_customer_email(&self, customer_id: &str) -> Result<Option<String>> {
+        let state = self.state.lock();
+        let customer = state
+            .customers
+            .get(customer_id)
+            .ok_or_else(|| anyhow!("no such customer {customer_id}"))?;
+        Ok(customer.email.clone())
+    }
+
+    async fn get_customer_currency(&self, customer_id: &str) -> Result<Option<String>> {
+        let state = self.state.lock();
+        let customer = state
+            .customers
+            .get(customer_id)
+            .ok_or_else(|| anyhow!("no such customer {customer_id}"))?;
+        Ok(customer.currency.clone())
+    }
+
+    async fn update_customer_email(&self, customer_id: &str, email: Option<&str>) -> Result<()> {
+        let mut state = self.state.lock();
+        let customer = state
+            .customers
+            .get_mut(customer_id)
+            .ok_or_else(|| anyhow!("no such customer {customer_id}"))?;
+        customer.email = email.map(str::to_string);
+        Ok(())
+    }
+
+    async fn adjust_customer_balance(
+        &self,
+        params: &AdjustCustomerBalanceParams,
+        idempotency_key: String,
+    ) -> Result<()> {
+        let mut state = self.state.lock();
+        if !state.customers.contains_key(&params.customer_id) {
+            return Err(anyhow!("no such customer {}", params.customer_id));
+        }
+        state.idempotent(idempotency_key, |state| {
+            if let Some(customer) = state.customers.get_mut(&params.customer_id) {
+                customer.balance += params.amount;
+            }
+            state.next_id("cbtxn")
+        });
+        Ok(())
+    }
+
+    async fn find_promotion_code(&self, code: &str) -> Result<Option<String>> {
+        Ok(self.state.lock().promotion_codes.get(code).cloned())
+    }
+
+    async fn get_payment_method_fingerprint(
+        &self,
+        payment_method_id: &str,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .state
+            .lock()
+            .payment_method_fingerprints
+            .get(payment_method_id)
+            .cloned())
+    }
+
+    async fn create_checkout_session(
+        &self,
+        params: &CreateCheckoutSessionParams,
+        idempotency_key: String,
+    ) -> Result<PaymentCheckoutSession> {
+        let id = self.state.lock().idempotent(idempotency_key, |state| {
+            state.checkout_sessions.push(params.clone());
+            state.next_id("cs")
+        });
+        Ok(PaymentCheckoutSession {
+            url: format!("https://checkout.example.com/{id}"),
+            id,
+        })
+    }
+
+    async fn create_portal_session(
+        &self,
+        params: &CreatePortalSessionParams,
+        idempotency_key: String,
+    ) -> Result<String> {
+        let id = self.state.lock().idempotent(idempotency_key, |state| {
+            state.portal_sessions.push(params.clone());
+            state.next_id("bps")
+        });
+        Ok(format!("https://portal.example.com/{id}"))
+    }
+
+    async fn get_subscription(&self, subscription_id: &str) -> Result<PaymentSubscription> {
+        self.update_subscription(subscription_id, |_| {})
+    }
+
+    async fn cancel_subscription(
+        &self,
+        subscription_id: &str,
+        at_period_end: bool,
+        _idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        self.update_subscription(subscription_id, |subscription| {
+            if at_period_end {
+                subscription.cancel_at_period_end = true;
+            } else {
+                subscription.status = StripeSubscriptionStatus::Canceled;
+                subscription.canceled_at = Some(OffsetDateTime::now_utc());
+            }
+        })
+    }
+
+    async fn resume_subscription(
+        &self,
+        subscription_id: &str,
+        _idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        self.update_subscription(subscription_id, |subscription| {
+            subscription.cancel_at_period_end = false;
+        })
+    }
+
+    async fn update_subscription_seats(
+        &self,
+        subscription_id: &str,
+        item_id: &str,
+        seat_count: u64,
+        _idempotency_key: String,
+    ) -> Result<PaymentSubscription> {
+        let subscription = self.update_subscription(subscription_id, |subscription| {
+            for item in &mut subscription.items {
+                if item.id == item_id {
+                    item.quantity = Some(seat_count);
+                }
+            }
+        })?;
+        if subscription.items.iter().all(|item| item.id != item_id) {
+            return Err(anyhow!("no such subscription item {item_id}"));
+        }
+        Ok(subscription)
+    }
+
+    async fn update_invoice_line_description(
+        &self,
+        _invoice_id: &str,
+        line_id: &str,
+        description: &str,
+    ) -> Result<()> {
+        self.state
+            .lock()
+            .invoice_line_descriptions
+            .insert(line_id.to_string(), description.to_string());
+        Ok(())
+    }
+
+    async fn list_events(&self, params: &ListEventsParams) -> Result<PaymentEventPage> {
+        let state = self.state.lock();
+        let mut events = state
+            .events
+            .iter()
+            .filter(|event| {
+                // Stripe's event types serialize to quoted strings.
+                let event_type = event.type_.to_string();
+                params
+                    .types
+                    .iter()
+                    .any(|event_type_filter| event_type_filter == event_type.trim_matches('"'))
+                    && params
+                        .created_after
+                        .map_or(true, |created_after| event.created > created_after)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let limit = params.limit as usize;
+        let has_more = if let Some(ending_before) = &params.ending_before {
+            // The page right after the given event, up to the limit.
+            let position = events
+                .iter()
+                .position(|event| event.id.as_str() == ending_before)
+                .ok_or_else(|| anyhow!("no such event {ending_before}"))?;
+            events.drain(..=position);
+            let has_more = events.len() > limit;
+            events.truncate(limit);
+            events.reverse();
+            has_more
+        } else {
+            events.reverse();
+            let has_more = events.len() > limit;
+            events.truncate(limit);
+            has_more
+        };
+        Ok(PaymentEventPage { events, has_more })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn subscription(id: &str, items: Vec<PaymentSubscriptionItem>) -> PaymentSubscription {
+        PaymentSubscription {
+            id: id.into(),
+            customer_id: "cus_1".into(),
+            status: StripeSubscriptionStatus::Active,
+            items,
+            metadata: BTreeMap::default(),
+            coupon_id: None,
+            promotion_code_id: None,
+            trial_end: None,
+            current_period_start: datetime!(2024-09-01 0:00 UTC),
+            current_period_end: datetime!(2024-10-01 0:00 UTC),
+            cancel_at_period_end: false,
+            canceled_at: None,
+            default_payment_method_id: None,
+        }
+    }
+
+    #[test]
+    fn test_subscription_seats() {
+        let subscription = subscription(
+            "sub_1",
+            vec![
+                PaymentSubscriptionItem {
+                    id: "si_metered".into(),
+                    price_id: Some("price_metered".into()),
+                    quantity: None,
+                },
+                PaymentSubscriptionItem {
+                    id: "si_licensed".into(),
+                    price_id: Some("price_team".into()),
+                    quantity: Some(3),
+                },
+            ],
+        );
+        assert_eq!(subscription.licensed_item().unwrap().id, "si_licensed");
+        assert_eq!(subscription.seat_count(), 3);
+
+        let subscription = self::subscription("sub_2", Vec::new());
+        assert!(subscription.licensed_item().is_err());
+        assert_eq!(subscription.seat_count(), 1);
+    }
+
+    #[gpui::test]
+    async fn test_fake_payment_provider() {
+        let provider = FakePaymentProvider::default();
+        let params = CreateCustomerParams {
+            email: Some("user@example.com".into()),
+            test_clock_id: None,
+        };
+        let customer_id = provider
+            .create_customer(&params, "create_customer:1".into())
+            .await
+            .unwrap();
+        // Retrying with the same key returns the original customer.
+        assert_eq!(
+            provider
+                .create_customer(&params, "create_customer:1".into())
+                .await
+                .unwrap(),
+            customer_id
+        );
+        assert_eq!(provider.state().customers.len(), 1);
+
+        // Test clocks are reused by name.
+        let test_clock_id = provider.find_or_create_test_clock("user").await.unwrap();
+        assert_eq!(
+            provider.find_or_create_test_clock("user").await.unwrap(),
+            test_clock_id
+        );
+
+        provider
+            .adjust_customer_balance(
+                &AdjustCustomerBalanceParams {
+                    customer_id: customer_id.clone(),
+                    amount: -500,
+                    currency: "usd".into(),
+                    description: "Referral credit".into(),
+                },
+                "credit:1".into(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(provider.state().customers[&customer_id].balance, -500);
+
+        provider.state().subscriptions.insert(
+            "sub_1".into(),
+            subscription(
+                "sub_1",
+                vec![PaymentSubscriptionItem {
+                    id: "si_1".into(),
+                    price_id: Some("price_team".into()),
+                    quantity: Some(1),
+                }],
+            ),
+        );
+        let subscription = provider
+            .update_subscription_seats("sub_1", "si_1", 4, "seats:1".into())
+            .await
+            .unwrap();
+        assert_eq!(subscription.seat_count(), 4);
+        assert!(provider
+            .update_subscription_seats("sub_1", "si_unknown", 4, "seats:2".into())
+            .await
+            .is_err());
+
+        let subscription = provider
+            .cancel_subscription("sub_1", true, "cancel:1".into())
+            .await
+            .unwrap();
+        assert!(subscription.cancel_at_period_end);
+        assert_eq!(subscription.status, StripeSubscriptionStatus::Active);
+        let subscription = provider
+            .resume_subscription("sub_1", "resume:1".into())
+            .await
+            .unwrap();
+        assert!(!subscription.cancel_at_period_end);
+        let subscription = provider
+            .cancel_subscription("sub_1", false, "cancel:2".into())
+            .await
+            .unwrap();
+        assert_eq!(subscription.status, StripeSubscriptionStatus::Canceled);
+    }
+
+    #[test]
+    fn test_apply_automatic_tax() {
+        let mut params = CreateCheckoutSession::new();
+        apply_automatic_tax(&mut params, false);
+        assert!(params.automatic_tax.is_none());
+        assert!(params.billing_address_collection.is_none());
+
+        apply_automatic_tax(&mut params, true);
+        assert!(params.automatic_tax.as_ref().unwrap().enabled);
+        assert_eq!(
+            params.billing_address_collection,
+            Some(CheckoutSessionBillingAddressCollection::Required)
+        );
+        assert!(params.customer_update.is_none());
+
+        let mut params = CreateCheckoutSession::new();
+        params.customer = Some("cus_123".parse().unwrap());
+        apply_automatic_tax(&mut params, true);
+        let customer_update = params.customer_update.unwrap();
+        assert_eq!(
+            customer_update.address,
+            Some(CreateCheckoutSessionCustomerUpdateAddress::Auto)
+        );
+    }
+}
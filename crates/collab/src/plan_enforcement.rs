@@ -0,0 +1,288 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use time::{OffsetDateTime, PrimitiveDateTime};
+use util::ResultExt;
+
+use crate::db::plan_enforcement_action::{self, PlanEnforcementActionKind};
+use crate::db::UserId;
+use crate::distributed_lock::run_exclusively;
+use crate::email::enqueue_email;
+use crate::entitlements::{Entitlements, Plan};
+use crate::AppState;
+
+const ENFORCEMENT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Re-evaluates the user's enforcement actions after a change to their
+/// subscriptions.
+///
+/// Actions that the user's plan no longer covers are scheduled for the end
+/// of the grace period, while those it covers again are canceled or, if
+/// already enforced, reverted.
+///
+/// Does nothing unless `enforce_plan_limits` is enabled.
+pub async fn plan_changed(app: &AppState, user_id: UserId) -> anyhow::Result<()> {
+    if !app.config.enforce_plan_limits.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let entitlements = app.entitlements.for_user(user_id).await?;
+    let required_actions = required_actions(app, user_id, &entitlements).await?;
+    let now = now();
+
+    for action in app.db.get_plan_enforcement_actions(user_id).await? {
+        if required_actions.contains(&action.action) {
+            continue;
+        }
+        if action.is_pending() {
+            app.db
+                .mark_plan_enforcement_action_canceled(action.id, now)
+                .await?;
+        } else {
+            revert_action(app, &action).await?;
+            app.db
+                .mark_plan_enforcement_action_reverted(action.id, now)
+                .await?;
+        }
+    }
+
+    let grace_period =
+        time::Duration::days(app.server_settings.get().downgrade_grace_period_in_days);
+    for action in required_actions {
+        app.db
+            .schedule_plan_enforcement_action(user_id, action, now + grace_period)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the actions needed to bring what the user has within the limits
+/// of their plan.
+///
+/// What was archived or disabled by enforced actions still counts, so that
+/// those actions are only reverted once the plan covers it again.
+async fn required_actions(
+    app: &AppState,
+    user_id: UserId,
+    entitlements: &Entitlements,
+) -> anyhow::Result<Vec<PlanEnforcementActionKind>> {
+    let mut actions = Vec::new();
+
+    // Only private channels are archived, so public ones don't count.
+    let private_root_channel_count = app
+        .db
+        .get_administered_private_root_channel_count_including_archived(user_id)
+        .await?;
+    if private_root_channel_count > entitlements.max_root_channels {
+        actions.push(PlanEnforcementActionKind::ArchiveExcessChannels);
+    }
+
+    if entitlements.plan < Plan::Team {
+        let administers_organization = app
+            .db
+            .get_organization_memberships_for_user(user_id)
+            .await?
+            .iter()
+            .any(|membership| membership.role.can_administer());
+        if administers_organization {
+            actions.push(PlanEnforcementActionKind::DisableOrganizationFeatures);
+        }
+    }
+
+    Ok(actions)
+}
+
+/// Periodically notifies users of upcoming enforcement actions, and enforces
+/// those whose grace period is over.
+///
+/// This also re-evaluates the plans of users whose past-due subscriptions
+/// stopped granting access.
+///
+/// Does nothing unless `enforce_plan_limits` is enabled.
+pub fn enforce_plan_limits_periodically(app: Arc<AppState>) {
+    if !app.config.enforce_plan_limits.unwrap_or(false) {
+        return;
+    }
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "enforce_plan_limits",
+                        enforce_plan_limits(&app),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, ENFORCEMENT_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn enforce_plan_limits(app: &AppState) -> anyhow::Result<()> {
+    if !app.config.enforce_plan_limits.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let settings = app.server_settings.get();
+    let now = now();
+
+    for user_id in app
+        .db
+        .end_expired_billing_subscription_grace_periods(now)
+        .await?
+    {
+        plan_changed(app, user_id).await.log_err();
+    }
+
+    let notice = time::Duration::days(settings.downgrade_notice_in_days);
+    for action in app
+        .db
+        .get_plan_enforcement_actions_to_notify(now + notice)
+        .await?
+    {
+        notify_user(app, &action).await.log_err();
+        app.db
+            .mark_plan_enforcement_action_notified(action.id, now)
+            .await?;
+    }
+
+    for action in app.db.get_due_plan_enforcement_actions(now).await? {
+        if app.shutdown.is_shutting_down() {
+            break;
+        }
+
+        // The user may have subscribed again since the action was scheduled,
+        // without us having processed the subscription's events yet.
+        let entitlements = app.entitlements.for_user(action.user_id).await?;
+        if !required_actions(app, action.user_id, &entitlements)
+            .await?
+            .contains(&action.action)
+        {
+            app.db
+                .mark_plan_enforcement_action_canceled(action.id, now)
+                .await?;
+            continue;
+        }
+
+        enforce_action(app, &action, &entitlements).await?;
+        app.db
+            .mark_plan_enforcement_action_enforced(action.id, now)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn enforce_action(
+    app: &AppState,
+    action: &plan_enforcement_action::Model,
+    entitlements: &Entitlements,
+) -> anyhow::Result<()> {
+    match action.action {
+        PlanEnforcementActionKind::ArchiveExcessChannels => {
+            let channel_ids = app
+                .db
+                .archive_excess_root_channels(action.user_id, entitlements.max_root_channels)
+                .await?;
+            log::info!(
+                "archived channels {channel_ids:?} administered by user {} on the {} plan",
+                action.user_id,
+                entitlements.plan.as_str()
+            );
+        }
+        PlanEnforcementActionKind::DisableOrganizationFeatures => {
+            let organization_ids = app
+                .db
+                .set_administered_organization_features_disabled(action.user_id, true)
+                .await?;
+            log::info!(
+                "disabled the features of organizations {organization_ids:?} administered by user {} on the {} plan",
+                action.user_id,
+                entitlements.plan.as_str()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn revert_action(
+    app: &AppState,
+    action: &plan_enforcement_action::Model,
+) -> anyhow::Result<()> {
+    match action.action {
+        PlanEnforcementActionKind::ArchiveExcessChannels => {
+            let channel_ids = app
+                .db
+                .unarchive_administered_root_channels(action.user_id)
+                .await?;
+            log::info!(
+                "restored channels {channel_ids:?} administered by user {}",
+                action.user_id
+            );
+        }
+        PlanEnforcementActionKind::DisableOrganizationFeatures => {
+            let organization_ids = app
+                .db
+                .set_administered_organization_features_disabled(action.user_id, false)
+                .await?;
+            log::info!(
+                "re-enabled the features of organizations {organization_ids:?} administered by user {}",
+                action.user_id
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn notify_user(
+    app: &AppState,
+    action: &plan_enforcement_action::Model,
+) -> anyhow::Result<()> {
+    let Some(user) = app.db.get_user_by_id(action.user_id).await? else {
+        return Ok(());
+    };
+    let Some(email_address) = user.email_address.as_deref() else {
+        return Ok(());
+    };
+
+    let (subject, body) = notice_email(action.action, action.scheduled_for.date());
+    enqueue_email(app, email_address, subject, &body).await
+}
+
+/// Returns the subject and body of the email telling a user about an upcoming
+/// enforcement action.
+fn notice_email(action: PlanEnforcementActionKind, date: time::Date) -> (&'static str, String) {
+    let consequence = match action {
+        PlanEnforcementActionKind::ArchiveExcessChannels => {
+            "the private channels you administer beyond the limit of your plan will be archived"
+        }
+        PlanEnforcementActionKind::DisableOrganizationFeatures => {
+            "the features of the organizations you administer will be disabled"
+        }
+    };
+    (
+        "Your Zed plan no longer covers everything you have",
+        format!(
+            "Your Zed subscription has lapsed or changed to a plan with lower limits. \
+             On {date}, {consequence}.\n\n\
+             Subscribe again before then to keep everything as it is. \
+             You can also subscribe later to restore anything that was affected."
+        ),
+    )
+}
+
+fn now() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
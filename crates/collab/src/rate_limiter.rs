@@ -1,9 +1,12 @@
-use crate::{db::UserId, executor::Executor, Database, Error, Result};
+use crate::{
+    db::UserId, executor::Executor, server_settings::ServerSettings, Database, Error, Result,
+};
 use anyhow::anyhow;
 use chrono::{DateTime, Duration, Utc};
 use dashmap::{DashMap, DashSet};
 use sea_orm::prelude::DateTimeUtc;
 use std::sync::Arc;
+use tokio::sync::watch;
 use util::ResultExt;
 
 pub trait RateLimit: 'static {
@@ -17,6 +20,8 @@ pub struct RateLimiter {
     buckets: DashMap<(UserId, String), RateBucket>,
     dirty_buckets: DashSet<(UserId, String)>,
     db: Arc<Database>,
+    /// The server settings, which may override the capacity of rate limits.
+    server_settings: Option<watch::Receiver<ServerSettings>>,
 }
 
 impl RateLimiter {
@@ -25,9 +30,30 @@ impl RateLimiter {
             buckets: DashMap::new(),
             dirty_buckets: DashSet::new(),
             db,
+            server_settings: None,
         }
     }
 
+    /// Applies the capacity overrides in the given server settings as they
+    /// change.
+    pub fn with_server_settings(
+        mut self,
+        server_settings: watch::Receiver<ServerSettings>,
+    ) -> Self {
+        self.server_settings = Some(server_settings);
+        self
+    }
+
+    /// Returns the capacity of the given rate limit, as overridden by the
+    /// server settings.
+    fn capacity<T: RateLimit>(&self) -> usize {
+        self.server_settings
+            .as_ref()
+            .and_then(|settings| settings.borrow().rate_limits.get(T::db_name()).copied())
+            .filter(|capacity| *capacity > 0)
+            .unwrap_or_else(T::capacity)
+    }
+
     /// Spawns a new task that periodically saves rate limit data to the database.
     pub fn save_periodically(rate_limiter: Arc<Self>, executor: Executor) {
         const RATE_LIMITER_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
@@ -48,12 +74,18 @@ impl RateLimiter {
 
     async fn check_internal<T: RateLimit>(&self, user_id: UserId, now: DateTimeUtc) -> Result<()> {
         let bucket_key = (user_id, T::db_name().to_string());
+        let capacity = self.capacity::<T>();
 
         // Attempt to fetch the bucket from the database if it hasn't been cached.
         // For now, we keep buckets in memory for the lifetime of the process rather than expiring them,
         // but this enforces limits across restarts so long as the database is reachable.
         if !self.buckets.contains_key(&bucket_key) {
-            if let Some(bucket) = self.load_bucket::<T>(user_id).await.log_err().flatten() {
+            if let Some(bucket) = self
+                .load_bucket::<T>(user_id, capacity)
+                .await
+                .log_err()
+                .flatten()
+            {
                 self.buckets.insert(bucket_key.clone(), bucket);
                 self.dirty_buckets.insert(bucket_key.clone());
             }
@@ -62,7 +94,8 @@ impl RateLimiter {
         let mut bucket = self
             .buckets
             .entry(bucket_key.clone())
-            .or_insert_with(|| RateBucket::new::<T>(now));
+            .or_insert_with(|| RateBucket::new::<T>(capacity, now));
+        bucket.value_mut().set_capacity::<T>(capacity);
 
         if bucket.value_mut().allow(now) {
             self.dirty_buckets.insert(bucket_key);
@@ -75,6 +108,7 @@ impl RateLimiter {
     async fn load_bucket<T: RateLimit>(
         &self,
         user_id: UserId,
+        capacity: usize,
     ) -> Result<Option<RateBucket>, Error> {
         Ok(self
             .db
@@ -82,6 +116,7 @@ impl RateLimiter {
             .await?
             .map(|saved_bucket| {
                 RateBucket::from_db::<T>(
+                    capacity,
                     saved_bucket.token_count as usize,
                     DateTime::from_naive_utc_and_offset(saved_bucket.last_refill, Utc),
                 )
@@ -124,24 +159,38 @@ struct RateBucket {
 }
 
 impl RateBucket {
-    fn new<T: RateLimit>(now: DateTimeUtc) -> Self {
+    fn new<T: RateLimit>(capacity: usize, now: DateTimeUtc) -> Self {
         Self {
-            capacity: T::capacity(),
-            token_count: T::capacity(),
-            refill_time_per_token: T::refill_duration() / T::capacity() as i32,
+            capacity,
+            token_count: capacity,
+            refill_time_per_token: T::refill_duration() / capacity as i32,
             last_refill: now,
         }
     }
 
-    fn from_db<T: RateLimit>(token_count: usize, last_refill: DateTimeUtc) -> Self {
+    fn from_db<T: RateLimit>(
+        capacity: usize,
+        token_count: usize,
+        last_refill: DateTimeUtc,
+    ) -> Self {
         Self {
-            capacity: T::capacity(),
-            token_count,
-            refill_time_per_token: T::refill_duration() / T::capacity() as i32,
+            capacity,
+            token_count: token_count.min(capacity),
+            refill_time_per_token: T::refill_duration() / capacity as i32,
             last_refill,
         }
     }
 
+    /// Changes the capacity of the bucket, e.g. when it's overridden in the
+    /// server settings.
+    fn set_capacity<T: RateLimit>(&mut self, capacity: usize) {
+        if capacity != self.capacity {
+            self.capacity = capacity;
+            self.token_count = self.token_count.min(capacity);
+            self.refill_time_per_token = T::refill_duration() / capacity as i32;
+        }
+    }
+
     fn allow(&mut self, now: DateTimeUtc) -> bool {
         self.refill(now);
         if self.token_count > 0 {
@@ -274,6 +323,57 @@ mod tests {
             .unwrap_err();
     }
 
+    #[gpui::test]
+    async fn test_rate_limit_overrides(cx: &mut TestAppContext) {
+        let test_db = TestDb::sqlite(cx.executor().clone());
+        let db = test_db.db().clone();
+        let user = db
+            .create_user(
+                "user-1@zed.dev",
+                false,
+                NewUserParams {
+                    github_login: "user-1".into(),
+                    github_user_id: 1,
+                },
+            )
+            .await
+            .unwrap()
+            .user_id;
+
+        let now = Utc::now();
+        let (settings_tx, settings_rx) = watch::channel(ServerSettings {
+            rate_limits: [("rate-limit-a".to_string(), 3)].into_iter().collect(),
+            ..Default::default()
+        });
+        let rate_limiter = RateLimiter::new(db.clone()).with_server_settings(settings_rx);
+
+        // The overridden capacity applies instead of the rate limit's own.
+        for _ in 0..3 {
+            rate_limiter
+                .check_internal::<RateLimitA>(user, now)
+                .await
+                .unwrap();
+        }
+        rate_limiter
+            .check_internal::<RateLimitA>(user, now)
+            .await
+            .unwrap_err();
+
+        // Changes to the override apply to existing buckets.
+        settings_tx.send_modify(|settings| {
+            settings.rate_limits.insert("rate-limit-a".to_string(), 1);
+        });
+        let later = now + Duration::seconds(2);
+        rate_limiter
+            .check_internal::<RateLimitA>(user, later)
+            .await
+            .unwrap();
+        rate_limiter
+            .check_internal::<RateLimitA>(user, later)
+            .await
+            .unwrap_err();
+    }
+
     struct RateLimitA;
 
     impl RateLimit for RateLimitA {
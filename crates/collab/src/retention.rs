@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use aws_sdk_s3::primitives::ByteStream;
+use serde::Serialize;
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::db::{server_setting_change, ServerSettingChangeId, UserId};
+use crate::distributed_lock::run_exclusively;
+use crate::AppState;
+
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of rows archived in a single transaction.
+const ARCHIVE_BATCH_SIZE: u64 = 1_000;
+
+/// Periodically archives the rows that are past their retention window.
+pub fn archive_old_records_periodically(app: Arc<AppState>) {
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "archive_old_records",
+                        archive_old_records(&app),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, ARCHIVE_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn archive_old_records(app: &AppState) -> anyhow::Result<()> {
+    let settings = app.server_settings.get();
+    // A negative retention window would archive records as they're made.
+    settings.validate()?;
+    let now = OffsetDateTime::now_utc();
+
+    // Only usages from months that are entirely past the retention window
+    // are archived, so that each month is rolled up in one go.
+    let usage_cutoff =
+        (now - time::Duration::days(settings.language_model_usage_retention_in_days)).date();
+    let usage_cutoff = usage_cutoff - time::Duration::days(usage_cutoff.day() as i64 - 1);
+    archive_language_model_usages(app, usage_cutoff.midnight().assume_utc()).await?;
+
+    archive_server_setting_changes(
+        app,
+        now - time::Duration::days(settings.audit_log_retention_in_days),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn archive_language_model_usages(
+    app: &AppState,
+    before: OffsetDateTime,
+) -> anyhow::Result<()> {
+    let mut archived_count = 0;
+    while !app.shutdown.is_shutting_down() {
+        let count = app
+            .db
+            .archive_language_model_usages(before, ARCHIVE_BATCH_SIZE)
+            .await?;
+        archived_count += count;
+        if count < ARCHIVE_BATCH_SIZE as usize {
+            break;
+        }
+    }
+
+    if archived_count > 0 {
+        log::info!("archived {archived_count} language model usages recorded before {before}");
+    }
+    Ok(())
+}
+
+/// A server setting change as exported to the blob store.
+#[derive(Debug, Serialize)]
+struct ArchivedServerSettingChange {
+    id: ServerSettingChangeId,
+    key: String,
+    previous_value: Option<String>,
+    value: Option<String>,
+    changed_by_user_id: Option<UserId>,
+    #[serde(with = "time::serde::rfc3339")]
+    changed_at: OffsetDateTime,
+}
+
+impl From<server_setting_change::Model> for ArchivedServerSettingChange {
+    fn from(change: server_setting_change::Model) -> Self {
+        Self {
+            id: change.id,
+            key: change.key,
+            previous_value: change.previous_value,
+            value: change.value,
+            changed_by_user_id: change.changed_by_user_id,
+            changed_at: change.created_at.assume_utc(),
+        }
+    }
+}
+
+async fn archive_server_setting_changes(
+    app: &AppState,
+    before: OffsetDateTime,
+) -> anyhow::Result<()> {
+    // Audit records are only ever deleted once they've been exported.
+    let Some((blob_store_client, bucket)) = app
+        .blob_store_client
+        .clone()
+        .zip(app.config.blob_store_bucket.clone())
+    else {
+        return Ok(());
+    };
+
+    let mut archived_count = 0;
+    while !app.shutdown.is_shutting_down() {
+        let changes = app
+            .db
+            .get_server_setting_changes_before(before, ARCHIVE_BATCH_SIZE)
+            .await?;
+        let (Some(first), Some(last)) = (changes.first(), changes.last()) else {
+            break;
+        };
+        let key = format!(
+            "archives/server_setting_changes/{}-{}.jsonl",
+            first.id, last.id
+        );
+        let ids = changes.iter().map(|change| change.id).collect::<Vec<_>>();
+
+        let mut body = Vec::new();
+        for change in changes {
+            serde_json::to_writer(&mut body, &ArchivedServerSettingChange::from(change))?;
+            body.push(b'\n');
+        }
+        blob_store_client
+            .put_object()
+            .bucket(&bucket)
+            .key(key)
+            .content_type("application/x-ndjson")
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|error| anyhow!("failed to export server setting changes: {error}"))?;
+
+        app.db.delete_server_setting_changes(&ids).await?;
+        archived_count += ids.len();
+        if ids.len() < ARCHIVE_BATCH_SIZE as usize {
+            break;
+        }
+    }
+
+    if archived_count > 0 {
+        log::info!("archived {archived_count} server setting changes made before {before}");
+    }
+    Ok(())
+}
@@ -1,15 +1,24 @@
+mod completion_streams;
 mod connection_pool;
+mod streamed_usage;
+mod usage_updates;
 
 use crate::{
     auth,
     db::{
-        self, dev_server, BufferId, Capability, Channel, ChannelId, ChannelRole, ChannelsForUser,
+        self, billing_subscription::StripeSubscriptionStatus, dev_server, BufferId, Capability,
+        Channel, ChannelId, ChannelRole, ChannelsForUser, CreateLanguageModelUsageParams,
         CreatedChannelMessage, Database, DevServerId, DevServerProjectId, InviteMemberResult,
         MembershipUpdated, MessageId, NotificationId, PrincipalId, Project, ProjectId,
         RejoinedProject, RemoveChannelMemberResult, ReplicaId, RespondToChannelInvite, RoomId,
         ServerId, UpdatedChannelMessage, User, UserId,
     },
+    entitlements::{EntitlementsService, LimitedFeature},
     executor::Executor,
+    llm_failover, llm_preflight, model_experiments,
+    server_settings::ServerSettingsStore,
+    spending_caps::MeteredSpend,
+    tenant::Tenant,
     AppState, Config, Error, RateLimit, RateLimiter, Result,
 };
 use anyhow::{anyhow, bail, Context as _};
@@ -75,7 +84,12 @@ use tracing::{
     info_span, instrument, Instrument,
 };
 
-use self::connection_pool::VersionedMessage;
+use self::{
+    completion_streams::CompletionStreams,
+    connection_pool::VersionedMessage,
+    streamed_usage::{language_model_usage_params, StreamedUsage},
+    usage_updates::{UsageUpdates, USAGE_UPDATE_DEBOUNCE},
+};
 
 pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -152,7 +166,11 @@ struct Session {
     supermaven_client: Option<Arc<SupermavenAdminApi>>,
     http_client: Arc<IsahcHttpClient>,
     rate_limiter: Arc<RateLimiter>,
-    _executor: Executor,
+    server_settings: Arc<ServerSettingsStore>,
+    entitlements: Arc<EntitlementsService>,
+    completion_streams: Arc<CompletionStreams>,
+    usage_updates: Arc<UsageUpdates>,
+    executor: Executor,
 }
 
 impl Session {
@@ -377,6 +395,8 @@ pub struct Server {
     id: parking_lot::Mutex<ServerId>,
     peer: Arc<Peer>,
     pub(crate) connection_pool: Arc<parking_lot::Mutex<ConnectionPool>>,
+    completion_streams: Arc<CompletionStreams>,
+    usage_updates: Arc<UsageUpdates>,
     app_state: Arc<AppState>,
     handlers: HashMap<TypeId, MessageHandler>,
     teardown: watch::Sender<bool>,
@@ -410,6 +430,8 @@ impl Server {
             peer: Peer::new(id.0 as u32),
             app_state: app_state.clone(),
             connection_pool: Default::default(),
+            completion_streams: Default::default(),
+            usage_updates: Default::default(),
             handlers: Default::default(),
             teardown: watch::channel(false).0,
         };
@@ -421,7 +443,12 @@ impl Server {
             .add_request_handler(user_handler(rejoin_room))
             .add_request_handler(user_handler(leave_room))
             .add_request_handler(user_handler(set_room_participant_role))
-            .add_request_handler(user_handler(call))
+            .add_request_handler({
+                let enforce_plan_limits = app_state.config.enforce_plan_limits.unwrap_or(false);
+                user_handler(move |request, response, session| {
+                    call(request, response, session, enforce_plan_limits)
+                })
+            })
             .add_request_handler(user_handler(cancel_call))
             .add_message_handler(user_message_handler(decline_call))
             .add_request_handler(user_handler(update_participant_location))
@@ -566,7 +593,12 @@ impl Server {
             .add_request_handler(user_handler(remove_contact))
             .add_request_handler(user_handler(respond_to_contact_request))
             .add_message_handler(subscribe_to_channels)
-            .add_request_handler(user_handler(create_channel))
+            .add_request_handler({
+                let enforce_plan_limits = app_state.config.enforce_plan_limits.unwrap_or(false);
+                user_handler(move |request, response, session| {
+                    create_channel(request, response, session, enforce_plan_limits)
+                })
+            })
             .add_request_handler(user_handler(delete_channel))
             .add_request_handler(user_handler(invite_channel_member))
             .add_request_handler(user_handler(remove_channel_member))
@@ -594,6 +626,12 @@ impl Server {
             .add_message_handler(user_message_handler(unfollow))
             .add_message_handler(user_message_handler(update_followers))
             .add_request_handler(user_handler(get_private_user_info))
+            .add_request_handler(user_handler(get_billing_status))
+            .add_request_handler(user_handler(get_recommended_language_model))
+            .add_request_handler(user_handler(get_user_secrets))
+            .add_request_handler(user_handler(update_user_secret))
+            .add_request_handler(user_handler(delete_user_secret))
+            .add_request_handler(user_handler(lease_organization_secret))
             .add_message_handler(user_message_handler(acknowledge_channel_message))
             .add_message_handler(user_message_handler(acknowledge_buffer_version))
             .add_request_handler(user_handler(get_supermaven_api_key))
@@ -623,16 +661,12 @@ impl Server {
                 move |request, response, session| {
                     let app_state = app_state.clone();
                     async move {
-                        stream_complete_with_language_model(
-                            request,
-                            response,
-                            session,
-                            &app_state.config,
-                        )
-                        .await
+                        stream_complete_with_language_model(request, response, session, app_state)
+                            .await
                     }
                 }
             })
+            .add_streaming_request_handler(resume_language_model_stream)
             .add_request_handler({
                 let app_state = app_state.clone();
                 move |request, response, session| {
@@ -966,6 +1000,7 @@ impl Server {
         connection: Connection,
         address: String,
         principal: Principal,
+        tenant: Option<Tenant>,
         zed_version: ZedVersion,
         send_connection_id: Option<oneshot::Sender<ConnectionId>>,
         executor: Executor,
@@ -976,9 +1011,14 @@ impl Server {
             user_id=field::Empty,
             login=field::Empty,
             impersonator=field::Empty,
-            dev_server_id=field::Empty
+            dev_server_id=field::Empty,
+            org_id=field::Empty,
+            plan=field::Empty
         );
         principal.update_span(&span);
+        if let Some(tenant) = &tenant {
+            tenant.record(&span);
+        }
 
         let mut teardown = self.teardown.subscribe();
         async move {
@@ -1021,7 +1061,11 @@ impl Server {
                 live_kit_client: this.app_state.live_kit_client.clone(),
                 http_client,
                 rate_limiter: this.app_state.rate_limiter.clone(),
-                _executor: executor.clone(),
+                server_settings: this.app_state.server_settings.clone(),
+                entitlements: this.app_state.entitlements.clone(),
+                completion_streams: this.completion_streams.clone(),
+                usage_updates: this.usage_updates.clone(),
+                executor: executor.clone(),
                 supermaven_client,
             };
 
@@ -1069,9 +1113,14 @@ impl Server {
                                 user_id=field::Empty,
                                 login=field::Empty,
                                 impersonator=field::Empty,
-                                dev_server_id=field::Empty
+                                dev_server_id=field::Empty,
+                                org_id=field::Empty,
+                                plan=field::Empty
                             );
                             principal.update_span(&span);
+                            if let Some(tenant) = &tenant {
+                                tenant.record(&span);
+                            }
                             let span_enter = span.enter();
                             if let Some(handler) = this.handlers.get(&message.payload_type_id()) {
                                 let is_background = message.is_background();
@@ -1164,6 +1213,19 @@ impl Server {
                 }
 
                 update_user_contacts(user.id, &session).await?;
+
+                // Failing to compute the user's usage shouldn't fail their
+                // connection, as it's pushed again after their next request.
+                if let Some(usage) =
+                    language_model_usage_for_user(&self.app_state.entitlements, user.id)
+                        .await
+                        .trace_err()
+                {
+                    self.peer.send(
+                        connection_id,
+                        proto::UpdateLanguageModelUsage { usage: Some(usage) },
+                    )?;
+                }
             }
             Principal::DevServer(dev_server) => {
                 {
@@ -1254,6 +1316,23 @@ impl Server {
         Ok(())
     }
 
+    /// Pushes the user's current billing status to all of their connections.
+    pub async fn billing_status_updated(self: &Arc<Self>, user_id: UserId) -> Result<()> {
+        let status =
+            billing_status_for_user(&self.app_state.db, &self.app_state.entitlements, user_id)
+                .await?;
+        let pool = self.connection_pool.lock();
+        for connection_id in pool.user_connection_ids(user_id) {
+            self.peer.send(
+                connection_id,
+                proto::UpdateBillingStatus {
+                    status: Some(status.clone()),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
     pub async fn snapshot<'a>(self: &'a Arc<Self>) -> ServerSnapshot<'a> {
         ServerSnapshot {
             connection_pool: ConnectionPoolGuard {
@@ -1375,6 +1454,7 @@ pub async fn handle_websocket_request(
     ConnectInfo(socket_address): ConnectInfo<SocketAddr>,
     Extension(server): Extension<Arc<Server>>,
     Extension(principal): Extension<Principal>,
+    tenant: Option<Extension<Tenant>>,
     ws: WebSocketUpgrade,
 ) -> axum::response::Response {
     if protocol_version != rpc::PROTOCOL_VERSION {
@@ -1414,6 +1494,7 @@ pub async fn handle_websocket_request(
                     connection,
                     socket_address,
                     principal,
+                    tenant.map(|Extension(tenant)| tenant),
                     version,
                     None,
                     Executor::Production,
@@ -1870,6 +1951,7 @@ async fn call(
     request: proto::Call,
     response: Response<proto::Call>,
     session: UserSession,
+    enforce_plan_limits: bool,
 ) -> Result<()> {
     let room_id = RoomId::from_proto(request.room_id);
     let calling_user_id = session.user_id();
@@ -1885,6 +1967,16 @@ async fn call(
         return Err(anyhow!("cannot call a user who isn't a contact"))?;
     }
 
+    if enforce_plan_limits && !session.is_staff() {
+        let db = session.db().await;
+        let participant_count = db.get_room_participant_count(room_id).await?;
+        session
+            .entitlements
+            .for_user(calling_user_id)
+            .await?
+            .check(LimitedFeature::CallParticipants, participant_count)?;
+    }
+
     let incoming_call = {
         let (room, incoming_call) = &mut *session
             .db()
@@ -3565,10 +3657,22 @@ async fn create_channel(
     request: proto::CreateChannel,
     response: Response<proto::CreateChannel>,
     session: UserSession,
+    enforce_plan_limits: bool,
 ) -> Result<()> {
     let db = session.db().await;
 
     let parent_id = request.parent_id.map(|id| ChannelId::from_proto(id));
+    if parent_id.is_none() && enforce_plan_limits && !session.is_staff() {
+        let root_channel_count = db
+            .get_administered_root_channel_count(session.user_id())
+            .await?;
+        session
+            .entitlements
+            .for_user(session.user_id())
+            .await?
+            .check(LimitedFeature::RootChannels, root_channel_count)?;
+    }
+
     let (channel, membership) = db
         .create_channel(&request.name, parent_id, session.user_id())
         .await?;
@@ -4542,6 +4646,11 @@ async fn complete_with_language_model(
         .rate_limiter
         .check::<CompleteWithLanguageModelRateLimit>(session.user_id())
         .await?;
+    throttle_anomalous_usage(&session, config).await?;
+    enforce_language_model_quota(&session, config, request.provider, &request.request).await?;
+    enforce_spending_cap(&session, config, request.provider, &request.request).await?;
+
+    let feature = request.feature.clone();
 
     let result = match proto::LanguageModelProvider::from_i32(request.provider) {
         Some(proto::LanguageModelProvider::Anthropic) => {
@@ -4549,13 +4658,23 @@ async fn complete_with_language_model(
                 .anthropic_api_key
                 .as_ref()
                 .context("no Anthropic AI API key configured on the server")?;
-            anthropic::complete(
+            let result = anthropic::complete(
                 session.http_client.as_ref(),
-                anthropic::ANTHROPIC_API_URL,
+                config.anthropic_api_url(),
                 api_key,
                 serde_json::from_str(&request.request)?,
             )
-            .await?
+            .await?;
+            record_language_model_usage(
+                &session,
+                "anthropic",
+                &result.model,
+                feature.as_deref(),
+                result.usage.input_tokens.unwrap_or(0),
+                result.usage.output_tokens.unwrap_or(0),
+            )
+            .await;
+            result
         }
         _ => return Err(anyhow!("unsupported provider"))?,
     };
@@ -4567,11 +4686,15 @@ async fn complete_with_language_model(
     Ok(())
 }
 
+/// How long the events of a resumable completion stream are kept after the
+/// completion finishes.
+const RESUMABLE_COMPLETION_STREAM_TTL: Duration = Duration::from_secs(5 * 60);
+
 async fn stream_complete_with_language_model(
     request: proto::StreamCompleteWithLanguageModel,
     response: StreamingResponse<proto::StreamCompleteWithLanguageModel>,
     session: Session,
-    config: &Config,
+    app_state: Arc<AppState>,
 ) -> Result<()> {
     let Some(session) = session.for_user() else {
         return Err(anyhow!("user not found"))?;
@@ -4582,65 +4705,163 @@ async fn stream_complete_with_language_model(
         .rate_limiter
         .check::<CompleteWithLanguageModelRateLimit>(session.user_id())
         .await?;
+    throttle_anomalous_usage(&session, &app_state.config).await?;
+    enforce_language_model_quota(
+        &session,
+        &app_state.config,
+        request.provider,
+        &request.request,
+    )
+    .await?;
+    enforce_spending_cap(
+        &session,
+        &app_state.config,
+        request.provider,
+        &request.request,
+    )
+    .await?;
+
+    let Some(stream_id) = request.stream_id.clone() else {
+        let mut transcript = proto::LanguageModelStreamTranscript::default();
+        return stream_language_model_events(
+            request,
+            &session,
+            &app_state.config,
+            |event, failover_provider| {
+                let mut event = transcript.push(event);
+                event.failover_provider = failover_provider.map(|provider| provider as i32);
+                response.send(event)
+            },
+        )
+        .await;
+    };
+
+    // Buffer the stream's events, so the client can resume the stream if its
+    // connection drops. The completion keeps going even if the client is gone.
+    let user_id = session.user_id();
+    let completion_streams = session.completion_streams.clone();
+    let executor = session.executor.clone();
+    let stream = completion_streams.start(user_id, stream_id.clone())?;
+    session.executor.clone().spawn_detached({
+        let stream = stream.clone();
+        async move {
+            let result = stream_language_model_events(
+                request,
+                &session,
+                &app_state.config,
+                |event, failover_provider| {
+                    stream.push(event, failover_provider);
+                    Ok(())
+                },
+            )
+            .await;
+            stream.finish(result);
+            executor.sleep(RESUMABLE_COMPLETION_STREAM_TTL).await;
+            completion_streams.remove(user_id, &stream_id);
+        }
+    });
+
+    stream.forward(0, |event| response.send(event)).await
+}
+
+async fn resume_language_model_stream(
+    request: proto::ResumeLanguageModelStream,
+    response: StreamingResponse<proto::ResumeLanguageModelStream>,
+    session: Session,
+) -> Result<()> {
+    let Some(session) = session.for_user() else {
+        return Err(anyhow!("user not found"))?;
+    };
+
+    let stream = session
+        .completion_streams
+        .get(session.user_id(), &request.stream_id)
+        .ok_or_else(|| anyhow!("completion stream not found"))?;
+    stream.verify_transcript(request.offset, request.checksum)?;
+    stream
+        .forward(request.offset as usize, |event| response.send(event))
+        .await
+}
+
+async fn stream_language_model_events(
+    request: proto::StreamCompleteWithLanguageModel,
+    session: &UserSession,
+    config: &Config,
+    mut send_event: impl FnMut(String, Option<proto::LanguageModelProvider>) -> Result<()>,
+) -> Result<()> {
+    let feature = request.feature.as_deref();
 
     match proto::LanguageModelProvider::from_i32(request.provider) {
         Some(proto::LanguageModelProvider::Anthropic) => {
-            let api_key = config
-                .anthropic_api_key
-                .as_ref()
-                .context("no Anthropic AI API key configured on the server")?;
-            let mut chunks = anthropic::stream_completion(
-                session.http_client.as_ref(),
-                anthropic::ANTHROPIC_API_URL,
-                api_key,
-                serde_json::from_str(&request.request)?,
-                None,
+            let anthropic_request: anthropic::Request = serde_json::from_str(&request.request)?;
+            let failover_request =
+                if request.allow_vendor_failover && config.openai_api_key.is_some() {
+                    llm_failover::anthropic_request_to_open_ai(&anthropic_request)
+                } else {
+                    None
+                };
+
+            // Only fail over while nothing has been streamed to the client.
+            let error = match stream_anthropic_events(
+                anthropic_request,
+                session,
+                config,
+                feature,
+                |event| send_event(event, None),
             )
+            .await?
+            {
+                None => return Ok(()),
+                Some(error) => error,
+            };
+            let Some(failover_request) =
+                failover_request.filter(|_| llm_failover::should_fail_over(&error))
+            else {
+                return Err(error)?;
+            };
+            tracing::warn!(
+                user_id = session.user_id().0,
+                model = %failover_request.model,
+                %error,
+                "failing over Anthropic completion to OpenAI"
+            );
+            stream_open_ai_events(failover_request, session, config, feature, |event| {
+                send_event(event, Some(proto::LanguageModelProvider::OpenAi))
+            })
             .await?;
-            while let Some(event) = chunks.next().await {
-                let chunk = event?;
-                response.send(proto::StreamCompleteWithLanguageModelResponse {
-                    event: serde_json::to_string(&chunk)?,
-                })?;
-            }
         }
         Some(proto::LanguageModelProvider::OpenAi) => {
-            let api_key = config
-                .openai_api_key
-                .as_ref()
-                .context("no OpenAI API key configured on the server")?;
-            let mut events = open_ai::stream_completion(
-                session.http_client.as_ref(),
-                open_ai::OPEN_AI_API_URL,
-                api_key,
+            stream_open_ai_events(
                 serde_json::from_str(&request.request)?,
-                None,
+                session,
+                config,
+                feature,
+                |event| send_event(event, None),
             )
             .await?;
-            while let Some(event) = events.next().await {
-                let event = event?;
-                response.send(proto::StreamCompleteWithLanguageModelResponse {
-                    event: serde_json::to_string(&event)?,
-                })?;
-            }
         }
         Some(proto::LanguageModelProvider::Google) => {
             let api_key = config
                 .google_ai_api_key
                 .as_ref()
                 .context("no Google AI API key configured on the server")?;
+            let google_request: google_ai::GenerateContentRequest =
+                serde_json::from_str(&request.request)?;
+            let mut recorder = StreamedUsageRecorder::new(
+                session,
+                StreamedUsage::new("google", google_request.model.clone(), feature),
+            );
             let mut events = google_ai::stream_generate_content(
                 session.http_client.as_ref(),
                 google_ai::API_URL,
                 api_key,
-                serde_json::from_str(&request.request)?,
+                google_request,
             )
             .await?;
             while let Some(event) = events.next().await {
                 let event = event?;
-                response.send(proto::StreamCompleteWithLanguageModelResponse {
-                    event: serde_json::to_string(&event)?,
-                })?;
+                recorder.usage.observe_google_response(&event);
+                send_event(serde_json::to_string(&event)?, None)?;
             }
         }
         None => return Err(anyhow!("unknown provider"))?,
@@ -4649,6 +4870,79 @@ async fn stream_complete_with_language_model(
     Ok(())
 }
 
+/// Streams a completion from Anthropic. If the request fails before any event
+/// was sent, returns the error it failed with, so the caller can decide
+/// whether to retry it with another vendor.
+async fn stream_anthropic_events(
+    request: anthropic::Request,
+    session: &UserSession,
+    config: &Config,
+    feature: Option<&str>,
+    mut send_event: impl FnMut(String) -> Result<()>,
+) -> Result<Option<anyhow::Error>> {
+    let api_key = config
+        .anthropic_api_key
+        .as_ref()
+        .context("no Anthropic AI API key configured on the server")?;
+    let mut recorder = StreamedUsageRecorder::new(
+        session,
+        StreamedUsage::new("anthropic", request.model.clone(), feature),
+    );
+    let mut chunks = match anthropic::stream_completion(
+        session.http_client.as_ref(),
+        config.anthropic_api_url(),
+        api_key,
+        request,
+        None,
+    )
+    .await
+    {
+        Ok(chunks) => chunks,
+        Err(error) => return Ok(Some(error)),
+    };
+    while let Some(event) = chunks.next().await {
+        let chunk = event?;
+        recorder.usage.observe_anthropic_event(&chunk);
+        send_event(serde_json::to_string(&chunk)?)?;
+    }
+    Ok(None)
+}
+
+async fn stream_open_ai_events(
+    mut request: open_ai::Request,
+    session: &UserSession,
+    config: &Config,
+    feature: Option<&str>,
+    mut send_event: impl FnMut(String) -> Result<()>,
+) -> Result<()> {
+    let api_key = config
+        .openai_api_key
+        .as_ref()
+        .context("no OpenAI API key configured on the server")?;
+    // OpenAI only reports the usage of streamed completions when asked to.
+    request.stream_options = Some(open_ai::StreamOptions {
+        include_usage: true,
+    });
+    let mut recorder = StreamedUsageRecorder::new(
+        session,
+        StreamedUsage::new("openai", request.model.clone(), feature),
+    );
+    let mut events = open_ai::stream_completion(
+        session.http_client.as_ref(),
+        open_ai::OPEN_AI_API_URL,
+        api_key,
+        request,
+        None,
+    )
+    .await?;
+    while let Some(event) = events.next().await {
+        let event = event?;
+        recorder.usage.observe_open_ai_event(&event);
+        send_event(serde_json::to_string(&event)?)?;
+    }
+    Ok(())
+}
+
 async fn count_language_model_tokens(
     request: proto::CountLanguageModelTokens,
     response: Response<proto::CountLanguageModelTokens>,
@@ -4689,6 +4983,243 @@ async fn count_language_model_tokens(
     Ok(())
 }
 
+/// Records the tokens consumed by a language model request.
+///
+/// Failing to record usage should not fail the request, so errors are only logged.
+async fn record_language_model_usage(
+    session: &UserSession,
+    provider: &str,
+    model: &str,
+    feature: Option<&str>,
+    input_tokens: u32,
+    output_tokens: u32,
+) {
+    let params = language_model_usage_params(
+        session.user_id(),
+        provider,
+        model,
+        feature,
+        input_tokens,
+        output_tokens,
+    );
+    insert_language_model_usage(session, &params).await;
+}
+
+async fn insert_language_model_usage(
+    session: &UserSession,
+    params: &CreateLanguageModelUsageParams,
+) {
+    session
+        .db()
+        .await
+        .record_language_model_usage(params)
+        .await
+        .trace_err();
+    schedule_language_model_usage_update(session);
+}
+
+/// Records the usage of a streamed completion when dropped, so that it's
+/// recorded however the stream ends, including when it fails partway through
+/// or the client goes away.
+struct StreamedUsageRecorder {
+    session: UserSession,
+    usage: StreamedUsage,
+}
+
+impl StreamedUsageRecorder {
+    fn new(session: &UserSession, usage: StreamedUsage) -> Self {
+        Self {
+            session: UserSession(session.0.clone()),
+            usage,
+        }
+    }
+}
+
+impl Drop for StreamedUsageRecorder {
+    fn drop(&mut self) {
+        let Some(params) = self.usage.params(self.session.user_id()) else {
+            return;
+        };
+        let session = UserSession(self.session.0.clone());
+        self.session.executor.spawn_detached(async move {
+            insert_language_model_usage(&session, &params).await;
+        });
+    }
+}
+
+/// Pushes the user's language model usage to all of their connections once
+/// [`USAGE_UPDATE_DEBOUNCE`] has passed, unless an update is already pending.
+fn schedule_language_model_usage_update(session: &UserSession) {
+    let user_id = session.user_id();
+    if !session.usage_updates.schedule(user_id) {
+        return;
+    }
+
+    let entitlements = session.entitlements.clone();
+    let usage_updates = session.usage_updates.clone();
+    let peer = session.peer.clone();
+    let connection_pool = session.connection_pool.clone();
+    let executor = session.executor.clone();
+    session.executor.spawn_detached(async move {
+        executor.sleep(USAGE_UPDATE_DEBOUNCE).await;
+        usage_updates.take(user_id);
+        let Some(usage) = language_model_usage_for_user(&entitlements, user_id)
+            .await
+            .trace_err()
+        else {
+            return;
+        };
+        let pool = connection_pool.lock();
+        for connection_id in pool.user_connection_ids(user_id) {
+            peer.send(
+                connection_id,
+                proto::UpdateLanguageModelUsage {
+                    usage: Some(usage.clone()),
+                },
+            )
+            .trace_err();
+        }
+    });
+}
+
+async fn language_model_usage_for_user(
+    entitlements: &EntitlementsService,
+    user_id: UserId,
+) -> Result<proto::LanguageModelUsage> {
+    let quota = entitlements
+        .language_model_quota(user_id, OffsetDateTime::now_utc())
+        .await?;
+    Ok(proto::LanguageModelUsage {
+        used_tokens: quota.used_tokens,
+        total_tokens: quota.total_tokens(),
+        period_ends_at: quota.period.end.unix_timestamp().max(0) as u64,
+    })
+}
+
+struct ThrottledLanguageModelRateLimit;
+
+impl RateLimit for ThrottledLanguageModelRateLimit {
+    fn capacity() -> usize {
+        std::env::var("THROTTLED_LANGUAGE_MODEL_RATE_LIMIT_PER_HOUR")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10) // Picked arbitrarily
+    }
+
+    fn refill_duration() -> chrono::Duration {
+        chrono::Duration::hours(1)
+    }
+
+    fn db_name() -> &'static str {
+        "throttled-language-model"
+    }
+}
+
+/// Applies a stricter rate limit to users with unreviewed usage anomalies, when
+/// throttling is enabled.
+async fn throttle_anomalous_usage(session: &UserSession, config: &Config) -> Result<()> {
+    if !config.throttle_usage_anomalies.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let has_unreviewed_anomaly = session
+        .db()
+        .await
+        .has_unreviewed_usage_anomaly(session.user_id())
+        .await?;
+    if has_unreviewed_anomaly {
+        session
+            .rate_limiter
+            .check::<ThrottledLanguageModelRateLimit>(session.user_id())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Refuses language model requests from users who have used up their token
+/// allowance for the current usage period, or whose request is estimated to
+/// use more tokens than they have left, when plan limits are enforced.
+async fn enforce_language_model_quota(
+    session: &UserSession,
+    config: &Config,
+    provider: i32,
+    request: &str,
+) -> Result<()> {
+    if !config.enforce_plan_limits.unwrap_or(false) || session.is_staff() {
+        return Ok(());
+    }
+
+    let quota = session
+        .entitlements
+        .language_model_quota(session.user_id(), OffsetDateTime::now_utc())
+        .await?;
+    quota.check()?;
+
+    let Some(provider) = proto::LanguageModelProvider::from_i32(provider) else {
+        return Ok(());
+    };
+    if !llm_preflight::needs_estimate(request.len(), quota.remaining_tokens()) {
+        return Ok(());
+    }
+    // Failing to estimate a request shouldn't fail it, as its usage is
+    // still recorded once it completes.
+    let Some(estimated_tokens) = llm_preflight::estimate_input_tokens(
+        session.http_client.as_ref(),
+        config,
+        provider,
+        request,
+    )
+    .await
+    .trace_err() else {
+        return Ok(());
+    };
+    quota.check_request(estimated_tokens)?;
+    Ok(())
+}
+
+/// Refuses language model requests from users whose metered usage has cost
+/// as much as the spending cap they set for the current billing period, or
+/// whose request is estimated to make it cost more.
+async fn enforce_spending_cap(
+    session: &UserSession,
+    config: &Config,
+    provider: i32,
+    request: &str,
+) -> Result<()> {
+    let db = session.db().await;
+    let spend =
+        MeteredSpend::for_user(&db, config, session.user_id(), OffsetDateTime::now_utc()).await?;
+    drop(db);
+    let Some(spend) = spend else {
+        return Ok(());
+    };
+    spend.check(0)?;
+
+    let Some(provider) = proto::LanguageModelProvider::from_i32(provider) else {
+        return Ok(());
+    };
+    // Every token takes up at least one byte of the request, so smaller
+    // requests can't exceed the cap.
+    if !spend.would_exceed_cap(request.len() as u64) {
+        return Ok(());
+    }
+    // Failing to estimate a request shouldn't fail it, as its usage is
+    // still recorded once it completes.
+    let Some(estimated_tokens) = llm_preflight::estimate_input_tokens(
+        session.http_client.as_ref(),
+        config,
+        provider,
+        request,
+    )
+    .await
+    .trace_err() else {
+        return Ok(());
+    };
+    spend.check(estimated_tokens)?;
+    Ok(())
+}
+
 struct CountLanguageModelTokensRateLimit;
 
 impl RateLimit for CountLanguageModelTokensRateLimit {
@@ -4807,6 +5338,12 @@ async fn get_cached_embeddings(
 }
 
 async fn authorize_access_to_language_models(session: &UserSession) -> Result<(), Error> {
+    if session.server_settings.get().maintenance_mode {
+        return Err(anyhow!(
+            "language models are temporarily unavailable due to maintenance"
+        ))?;
+    }
+
     let db = session.db().await;
     let flags = db.get_user_flags(session.user_id()).await?;
     if flags.iter().any(|flag| flag == "language-models") {
@@ -4995,6 +5532,170 @@ async fn get_private_user_info(
     Ok(())
 }
 
+/// Get the current user's billing status
+async fn get_billing_status(
+    _request: proto::GetBillingStatus,
+    response: Response<proto::GetBillingStatus>,
+    session: UserSession,
+) -> Result<()> {
+    let status = billing_status_for_user(
+        &session.db().await,
+        &session.entitlements,
+        session.user_id(),
+    )
+    .await?;
+    response.send(proto::GetBillingStatusResponse {
+        status: Some(status),
+    })?;
+    Ok(())
+}
+
+/// Returns the default language model recommended to the user by the active
+/// model experiment, if any.
+async fn get_recommended_language_model(
+    _request: proto::GetRecommendedLanguageModel,
+    response: Response<proto::GetRecommendedLanguageModel>,
+    session: UserSession,
+) -> Result<()> {
+    let model =
+        model_experiments::recommended_model_for_user(&session.db().await, session.user_id())
+            .await?;
+    response.send(proto::GetRecommendedLanguageModelResponse { model })?;
+    Ok(())
+}
+
+/// The maximum size of a single synced secret, in bytes.
+const MAX_USER_SECRET_SIZE: usize = 4 * 1024;
+
+/// The maximum number of secrets a single user may sync.
+const MAX_USER_SECRET_COUNT: usize = 32;
+
+/// Returns the user's synced secrets.
+///
+/// Secrets are encrypted on the client, so we return the ciphertext as-is.
+async fn get_user_secrets(
+    _request: proto::GetUserSecrets,
+    response: Response<proto::GetUserSecrets>,
+    session: UserSession,
+) -> Result<()> {
+    let secrets = session
+        .db()
+        .await
+        .get_user_secrets(session.user_id())
+        .await?
+        .into_iter()
+        .map(|secret| proto::UserSecret {
+            name: secret.name,
+            ciphertext: secret.ciphertext,
+        })
+        .collect();
+    response.send(proto::GetUserSecretsResponse { secrets })?;
+    Ok(())
+}
+
+async fn update_user_secret(
+    request: proto::UpdateUserSecret,
+    response: Response<proto::UpdateUserSecret>,
+    session: UserSession,
+) -> Result<()> {
+    let secret = request.secret.ok_or_else(|| anyhow!("missing secret"))?;
+    if secret.name.is_empty() {
+        Err(anyhow!("secret name must not be empty"))?;
+    }
+    if secret.ciphertext.len() > MAX_USER_SECRET_SIZE {
+        Err(anyhow!("secret is too large"))?;
+    }
+
+    let db = session.db().await;
+    let user_id = session.user_id();
+    let existing_secrets = db.get_user_secrets(user_id).await?;
+    let is_new_secret = !existing_secrets
+        .iter()
+        .any(|existing_secret| existing_secret.name == secret.name);
+    if is_new_secret && existing_secrets.len() >= MAX_USER_SECRET_COUNT {
+        Err(anyhow!("too many secrets"))?;
+    }
+
+    db.upsert_user_secret(user_id, &secret.name, secret.ciphertext)
+        .await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+async fn delete_user_secret(
+    request: proto::DeleteUserSecret,
+    response: Response<proto::DeleteUserSecret>,
+    session: UserSession,
+) -> Result<()> {
+    session
+        .db()
+        .await
+        .delete_user_secret(session.user_id(), &request.name)
+        .await?;
+    response.send(proto::Ack {})?;
+    Ok(())
+}
+
+/// How long a leased organization secret may be used before it must be leased
+/// again.
+const ORGANIZATION_SECRET_LEASE_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/// Leases one of the user's organizations' secrets, so that members can use
+/// shared provider API keys without storing them.
+async fn lease_organization_secret(
+    request: proto::LeaseOrganizationSecret,
+    response: Response<proto::LeaseOrganizationSecret>,
+    session: UserSession,
+) -> Result<()> {
+    let lease = session
+        .db()
+        .await
+        .lease_organization_secret(
+            session.user_id(),
+            request.organization_slug.as_deref(),
+            &request.name,
+            ORGANIZATION_SECRET_LEASE_DURATION,
+        )
+        .await?
+        .ok_or_else(|| anyhow!("no organization secret named {:?}", request.name))?;
+
+    tracing::info!(
+        user_id = session.user_id().0,
+        secret = %request.name,
+        "leased organization secret"
+    );
+    response.send(proto::LeaseOrganizationSecretResponse {
+        value: lease.value,
+        expires_at: lease.expires_at.assume_utc().unix_timestamp() as u64,
+    })?;
+    Ok(())
+}
+
+async fn billing_status_for_user(
+    db: &Database,
+    entitlements: &EntitlementsService,
+    user_id: UserId,
+) -> Result<proto::BillingStatus> {
+    let subscriptions = db.get_billing_subscriptions(user_id).await?;
+    let has_active_subscription = entitlements.has_paid_plan(user_id).await?;
+    let subscription_status = subscriptions.last().map(|subscription| {
+        sea_orm::ActiveEnum::to_value(&subscription.stripe_subscription_status)
+    });
+    let trial_ends_at = subscriptions
+        .last()
+        .filter(|subscription| {
+            subscription.stripe_subscription_status == StripeSubscriptionStatus::Trialing
+        })
+        .and_then(|subscription| subscription.trial_end)
+        .map(|trial_end| trial_end.assume_utc().unix_timestamp() as u64);
+
+    Ok(proto::BillingStatus {
+        has_active_subscription,
+        subscription_status,
+        trial_ends_at,
+    })
+}
+
 fn to_axum_message(message: TungsteniteMessage) -> anyhow::Result<AxumMessage> {
     let message = match message {
         TungsteniteMessage::Text(payload) => AxumMessage::Text(payload),
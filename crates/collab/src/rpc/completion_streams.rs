@@ -0,0 +1,236 @@
+use crate::{db::UserId, Result};
+use anyhow::anyhow;
+use collections::HashMap;
+use parking_lot::Mutex;
+use rpc::proto::{self, LanguageModelStreamTranscript};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// The number of streams a user can have running at once. Finished streams
+/// stay buffered for a while, but don't count toward this.
+const MAX_STREAMS_PER_USER: usize = 8;
+
+/// Language model completion streams that are buffered on the server, so that
+/// clients can resume them after losing their connection.
+#[derive(Default)]
+pub struct CompletionStreams {
+    streams: Mutex<HashMap<(UserId, String), Arc<BufferedCompletionStream>>>,
+}
+
+impl CompletionStreams {
+    pub fn start(
+        &self,
+        user_id: UserId,
+        stream_id: String,
+    ) -> Result<Arc<BufferedCompletionStream>> {
+        let mut streams = self.streams.lock();
+        let running_stream_count = streams
+            .iter()
+            .filter(|((id, _), stream)| *id == user_id && stream.is_running())
+            .count();
+        if running_stream_count >= MAX_STREAMS_PER_USER {
+            Err(anyhow!("too many resumable completion streams"))?;
+        }
+
+        let key = (user_id, stream_id);
+        if streams.contains_key(&key) {
+            Err(anyhow!("completion stream {:?} already exists", key.1))?;
+        }
+        let stream = Arc::new(BufferedCompletionStream::default());
+        streams.insert(key, stream.clone());
+        Ok(stream)
+    }
+
+    pub fn get(&self, user_id: UserId, stream_id: &str) -> Option<Arc<BufferedCompletionStream>> {
+        self.streams
+            .lock()
+            .get(&(user_id, stream_id.to_string()))
+            .cloned()
+    }
+
+    pub fn remove(&self, user_id: UserId, stream_id: &str) {
+        self.streams
+            .lock()
+            .remove(&(user_id, stream_id.to_string()));
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct StreamStatus {
+    event_count: usize,
+    /// Set once the completion has finished, with the error it failed with,
+    /// if any.
+    outcome: Option<Result<(), String>>,
+}
+
+/// The events of a completion stream, kept until some time after the
+/// completion finishes.
+pub struct BufferedCompletionStream {
+    events: Mutex<(
+        LanguageModelStreamTranscript,
+        Vec<proto::StreamCompleteWithLanguageModelResponse>,
+    )>,
+    status: watch::Sender<StreamStatus>,
+}
+
+impl Default for BufferedCompletionStream {
+    fn default() -> Self {
+        Self {
+            events: Default::default(),
+            status: watch::channel(StreamStatus::default()).0,
+        }
+    }
+}
+
+impl BufferedCompletionStream {
+    pub fn push(&self, event: String, failover_provider: Option<proto::LanguageModelProvider>) {
+        let event_count = {
+            let mut events = self.events.lock();
+            let (transcript, events) = &mut *events;
+            let mut response = transcript.push(event);
+            response.failover_provider = failover_provider.map(|provider| provider as i32);
+            events.push(response);
+            events.len()
+        };
+        self.status
+            .send_modify(|status| status.event_count = event_count);
+    }
+
+    /// Returns whether the completion is still running.
+    pub fn is_running(&self) -> bool {
+        self.status.borrow().outcome.is_none()
+    }
+
+    pub fn finish(&self, result: Result<()>) {
+        let outcome = result.map_err(|error| error.to_string());
+        self.status
+            .send_modify(|status| status.outcome = Some(outcome));
+    }
+
+    /// Checks that a client resuming the stream at `offset` has received the
+    /// same events as the ones buffered.
+    pub fn verify_transcript(&self, offset: u64, checksum: u64) -> Result<()> {
+        let events = self.events.lock();
+        let (_, events) = &*events;
+        let expected_checksum = match offset.checked_sub(1) {
+            None => LanguageModelStreamTranscript::default().checksum,
+            Some(last_offset) => {
+                events
+                    .get(last_offset as usize)
+                    .ok_or_else(|| anyhow!("completion stream offset {offset} is out of range"))?
+                    .checksum
+            }
+        };
+        if checksum != expected_checksum {
+            Err(anyhow!(
+                "completion stream checksum mismatch at offset {offset}"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Sends the stream's events from `offset` onward, including the ones
+    /// that are still to come, until the completion finishes. The last event
+    /// is followed by an empty response marking the end of the stream.
+    pub async fn forward(
+        &self,
+        offset: usize,
+        send: impl Fn(proto::StreamCompleteWithLanguageModelResponse) -> Result<()>,
+    ) -> Result<()> {
+        let mut status = self.status.subscribe();
+        let mut next_offset = offset;
+        loop {
+            let current_status = status.borrow_and_update().clone();
+            let events = self
+                .events
+                .lock()
+                .1
+                .get(next_offset..current_status.event_count)
+                .unwrap_or_default()
+                .to_vec();
+            for event in events {
+                send(event)?;
+                next_offset += 1;
+            }
+
+            match current_status.outcome {
+                Some(Ok(())) => {
+                    return send(proto::StreamCompleteWithLanguageModelResponse {
+                        end_of_stream: true,
+                        ..Default::default()
+                    });
+                }
+                Some(Err(error)) => Err(anyhow!(error))?,
+                None => status
+                    .changed()
+                    .await
+                    .map_err(|_| anyhow!("completion stream dropped"))?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_completion_stream() {
+        let streams = CompletionStreams::default();
+        let user_id = UserId(1);
+        let stream = streams.start(user_id, "a".into()).unwrap();
+        assert!(streams.start(user_id, "a".into()).is_err());
+
+        stream.push("one".into(), None);
+        stream.push("two".into(), None);
+        stream.push("three".into(), None);
+        stream.finish(Ok(()));
+
+        let received = Mutex::new(Vec::new());
+        futures::executor::block_on(stream.forward(1, |event| {
+            received.lock().push(event);
+            Ok(())
+        }))
+        .unwrap();
+        let received = received.into_inner();
+        assert_eq!(
+            received
+                .iter()
+                .map(|event| event.event.as_str())
+                .collect::<Vec<_>>(),
+            ["two", "three", ""]
+        );
+        assert!(received.last().unwrap().end_of_stream);
+
+        // Resuming requires the client's transcript to match the server's.
+        let mut transcript = LanguageModelStreamTranscript::default();
+        transcript.push("one".into());
+        stream.verify_transcript(1, transcript.checksum).unwrap();
+        assert!(stream.verify_transcript(1, 42).is_err());
+        assert!(stream.verify_transcript(4, transcript.checksum).is_err());
+        stream
+            .verify_transcript(0, LanguageModelStreamTranscript::default().checksum)
+            .unwrap();
+
+        streams.remove(user_id, "a");
+        assert!(streams.get(user_id, "a").is_none());
+    }
+
+    #[test]
+    fn test_completion_stream_limit() {
+        let streams = CompletionStreams::default();
+        let user_id = UserId(1);
+        let running = (0..MAX_STREAMS_PER_USER)
+            .map(|ix| streams.start(user_id, ix.to_string()).unwrap())
+            .collect::<Vec<_>>();
+        assert!(streams.start(user_id, "next".into()).is_err());
+        // Other users have their own limit.
+        streams.start(UserId(2), "next".into()).unwrap();
+
+        // Finished streams stay buffered, but make room for new ones.
+        running[0].finish(Ok(()));
+        assert!(!running[0].is_running());
+        streams.start(user_id, "next".into()).unwrap();
+        assert!(streams.get(user_id, "0").is_some());
+    }
+}
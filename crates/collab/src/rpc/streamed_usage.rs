@@ -0,0 +1,200 @@
+use crate::db::{CreateLanguageModelUsageParams, UserId};
+use crate::llm_pricing;
+
+/// The tokens a streamed completion has used so far, as reported by the
+/// provider while streaming it.
+pub struct StreamedUsage {
+    provider: &'static str,
+    model: String,
+    feature: Option<String>,
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+impl StreamedUsage {
+    pub fn new(provider: &'static str, model: String, feature: Option<&str>) -> Self {
+        Self {
+            provider,
+            model,
+            feature: feature.map(|feature| feature.to_string()),
+            input_tokens: 0,
+            output_tokens: 0,
+        }
+    }
+
+    pub fn observe_anthropic_event(&mut self, event: &anthropic::Event) {
+        match event {
+            anthropic::Event::MessageStart { message } => {
+                self.input_tokens = message.usage.input_tokens.unwrap_or(0);
+                self.output_tokens = message.usage.output_tokens.unwrap_or(0);
+            }
+            // The usage reported in `message_delta` events is cumulative.
+            anthropic::Event::MessageDelta { usage, .. } => {
+                self.output_tokens = usage.output_tokens.unwrap_or(self.output_tokens);
+            }
+            _ => {}
+        }
+    }
+
+    /// OpenAI only reports usage in the last event of a stream, when the
+    /// request asks for it with `stream_options`.
+    pub fn observe_open_ai_event(&mut self, event: &open_ai::ResponseStreamEvent) {
+        if let Some(usage) = &event.usage {
+            self.input_tokens = usage.prompt_tokens;
+            self.output_tokens = usage.completion_tokens;
+        }
+    }
+
+    /// Google reports the usage so far with every chunk.
+    pub fn observe_google_response(&mut self, response: &google_ai::GenerateContentResponse) {
+        if let Some(usage) = &response.usage_metadata {
+            self.input_tokens = usage.prompt_token_count.unwrap_or(self.input_tokens);
+            self.output_tokens = usage.candidates_token_count.unwrap_or(self.output_tokens);
+        }
+    }
+
+    /// Returns the usage to record for the user, or `None` if the provider
+    /// hasn't reported any.
+    pub fn params(&self, user_id: UserId) -> Option<CreateLanguageModelUsageParams> {
+        if self.input_tokens == 0 && self.output_tokens == 0 {
+            return None;
+        }
+        Some(language_model_usage_params(
+            user_id,
+            self.provider,
+            &self.model,
+            self.feature.as_deref(),
+            self.input_tokens,
+            self.output_tokens,
+        ))
+    }
+}
+
+pub fn language_model_usage_params(
+    user_id: UserId,
+    provider: &str,
+    model: &str,
+    feature: Option<&str>,
+    input_tokens: u32,
+    output_tokens: u32,
+) -> CreateLanguageModelUsageParams {
+    let input_tokens = input_tokens as i64;
+    let output_tokens = output_tokens as i64;
+    CreateLanguageModelUsageParams {
+        user_id,
+        provider: provider.to_string(),
+        model: model.to_string(),
+        input_tokens,
+        output_tokens,
+        feature: feature.map(|feature| feature.to_string()),
+        upstream_cost_in_millicents: llm_pricing::upstream_cost_in_millicents(
+            provider,
+            model,
+            input_tokens,
+            output_tokens,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{NewUserParams, TestDb};
+    use serde_json::json;
+    use time::{Duration, OffsetDateTime};
+
+    #[gpui::test]
+    async fn test_streamed_open_ai_usage(cx: &mut gpui::TestAppContext) {
+        let test_db = TestDb::sqlite(cx.executor().clone());
+        let db = test_db.db();
+        let user_id = db
+            .create_user(
+                "user@example.com",
+                false,
+                NewUserParams {
+                    github_login: "user".into(),
+                    github_user_id: 1,
+                },
+            )
+            .await
+            .unwrap()
+            .user_id;
+
+        let mut usage = StreamedUsage::new("openai", "gpt-4o".into(), Some("assistant_panel"));
+        let events = [
+            json!({
+                "created": 1,
+                "model": "gpt-4o",
+                "choices": [{ "index": 0, "delta": { "content": "Hi" }, "finish_reason": null }],
+                "usage": null
+            }),
+            json!({
+                "created": 1,
+                "model": "gpt-4o",
+                "choices": [],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 }
+            }),
+        ];
+        for (ix, event) in events.into_iter().enumerate() {
+            assert_eq!(usage.params(user_id).is_some(), ix > 0);
+            usage.observe_open_ai_event(&serde_json::from_value(event).unwrap());
+        }
+
+        db.record_language_model_usage(&usage.params(user_id).unwrap())
+            .await
+            .unwrap();
+        let now = OffsetDateTime::now_utc();
+        let usage = db
+            .get_language_model_token_usage_by_user(
+                now - Duration::days(1),
+                now + Duration::days(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(usage.get(&user_id), Some(&15));
+    }
+
+    #[test]
+    fn test_streamed_anthropic_and_google_usage() {
+        let user_id = UserId(1);
+
+        // Usage is known as soon as the message starts, so streams that fail
+        // partway through are still accounted for.
+        let mut usage = StreamedUsage::new("anthropic", "claude-3-5-sonnet".into(), None);
+        usage.observe_anthropic_event(
+            &serde_json::from_value(json!({
+                "type": "message_start",
+                "message": {
+                    "id": "msg_1",
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": "claude-3-5-sonnet",
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 20, "output_tokens": 1 }
+                }
+            }))
+            .unwrap(),
+        );
+        let params = usage.params(user_id).unwrap();
+        assert_eq!((params.input_tokens, params.output_tokens), (20, 1));
+
+        let mut usage = StreamedUsage::new("google", "gemini-1.5-pro".into(), None);
+        for output_tokens in [2, 5] {
+            usage.observe_google_response(
+                &serde_json::from_value(json!({
+                    "candidates": null,
+                    "promptFeedback": null,
+                    "usageMetadata": {
+                        "promptTokenCount": 8,
+                        "candidatesTokenCount": output_tokens
+                    }
+                }))
+                .unwrap(),
+            );
+        }
+        let params = usage.params(user_id).unwrap();
+        assert_eq!((params.input_tokens, params.output_tokens), (8, 5));
+    }
+}
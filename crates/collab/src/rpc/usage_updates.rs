@@ -0,0 +1,44 @@
+use crate::db::UserId;
+use collections::HashSet;
+use parking_lot::Mutex;
+use std::time::Duration;
+
+/// How long after a language model request the user's usage is pushed to
+/// them, so that a burst of requests results in a single update.
+pub const USAGE_UPDATE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// The users whose language model usage is about to be pushed to them.
+#[derive(Default)]
+pub struct UsageUpdates {
+    pending: Mutex<HashSet<UserId>>,
+}
+
+impl UsageUpdates {
+    /// Marks an update as pending for the user. Returns whether one wasn't
+    /// already, in which case the caller is responsible for sending it.
+    pub fn schedule(&self, user_id: UserId) -> bool {
+        self.pending.lock().insert(user_id)
+    }
+
+    /// Clears the user's pending update, right before it's sent, so that any
+    /// usage recorded afterwards schedules another one.
+    pub fn take(&self, user_id: UserId) {
+        self.pending.lock().remove(&user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_updates() {
+        let updates = UsageUpdates::default();
+        assert!(updates.schedule(UserId(1)));
+        assert!(!updates.schedule(UserId(1)));
+        assert!(updates.schedule(UserId(2)));
+
+        updates.take(UserId(1));
+        assert!(updates.schedule(UserId(1)));
+    }
+}
@@ -0,0 +1,246 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use stripe::{
+    Subscription, SubscriptionId, SubscriptionProrationBehavior, UpdateSubscription,
+    UpdateSubscriptionItems,
+};
+use util::ResultExt;
+
+use crate::api::billing::idempotent_client;
+use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+use crate::db::organization::{self, SeatPolicy};
+use crate::db::organization_seat_true_up::SeatTrueUpOutcome;
+use crate::db::CreateOrganizationSeatTrueUpParams;
+use crate::distributed_lock::run_exclusively;
+use crate::payment_provider::PaymentSubscription;
+use crate::stripe_connect::client_for_account;
+use crate::AppState;
+
+const TRUE_UP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Periodically trues up the seats of every organization with a subscription.
+pub fn true_up_organization_seats_periodically(app: Arc<AppState>) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "true_up_organization_seats",
+                        true_up_organization_seats(&app, &stripe_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, TRUE_UP_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn true_up_organization_seats(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+) -> anyhow::Result<()> {
+    // Seats are trued up the next day instead, since adjusting them changes
+    // the subscription in Stripe.
+    if app.server_settings.get().billing_read_only() {
+        log::info!("not truing up organization seats while billing is read-only");
+        return Ok(());
+    }
+
+    for organization in app
+        .db
+        .get_organizations_with_billing_subscriptions()
+        .await?
+    {
+        if app.shutdown.is_shutting_down() {
+            break;
+        }
+
+        true_up_organization(app, stripe_client, &organization)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to true up seats of organization {}",
+                    organization.id
+                )
+            })
+            .log_err();
+    }
+
+    Ok(())
+}
+
+async fn true_up_organization(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+    organization: &organization::Model,
+) -> anyhow::Result<()> {
+    let Some(billing_subscription_id) = organization.billing_subscription_id else {
+        return Ok(());
+    };
+    let Some(subscription) = app
+        .db
+        .get_billing_subscription_by_id(billing_subscription_id)
+        .await?
+    else {
+        return Ok(());
+    };
+    if subscription.plan != SubscriptionPlan::Team
+        || !matches!(
+            subscription.stripe_subscription_status,
+            StripeSubscriptionStatus::Active | StripeSubscriptionStatus::Trialing
+        )
+    {
+        return Ok(());
+    }
+
+    // Stripe is the source of truth for the seat count, which our record
+    // may lag behind until the subscription's events are processed.
+    let stripe_subscription_id = SubscriptionId::from_str(&subscription.stripe_subscription_id)
+        .context("failed to parse subscription ID")?;
+    let stripe_client =
+        &client_for_account(stripe_client, subscription.stripe_account_id.as_deref())?;
+    let stripe_subscription = PaymentSubscription::try_from(
+        &Subscription::retrieve(stripe_client, &stripe_subscription_id, &[]).await?,
+    )?;
+    let seat_count = stripe_subscription.seat_count();
+    let member_count = app
+        .db
+        .get_organization_member_count(organization.id)
+        .await? as i32;
+
+    let Some(outcome) = true_up_outcome(organization.seat_policy, member_count, seat_count) else {
+        return Ok(());
+    };
+
+    match outcome {
+        SeatTrueUpOutcome::Adjusted => {
+            let item = stripe_subscription.licensed_item()?;
+            let new_seat_count = required_seat_count(member_count);
+            let mut params = UpdateSubscription::new();
+            params.items = Some(vec![UpdateSubscriptionItems {
+                id: Some(item.id.clone()),
+                quantity: Some(new_seat_count as u64),
+                ..Default::default()
+            }]);
+            params.proration_behavior = Some(SubscriptionProrationBehavior::CreateProrations);
+            // Runs that saw the same seat count make the same change, so that
+            // a run that's retried doesn't prorate it twice.
+            let stripe_client = idempotent_client(
+                stripe_client,
+                format!("true_up_seats:{stripe_subscription_id}:{seat_count}:{new_seat_count}"),
+            );
+            Subscription::update(&stripe_client, &stripe_subscription_id, params).await?;
+            app.db
+                .update_billing_subscription_seat_count(subscription.id, new_seat_count)
+                .await?;
+            log::info!(
+                "adjusted the seats of organization {} from {seat_count} to {new_seat_count}",
+                organization.id
+            );
+        }
+        SeatTrueUpOutcome::Flagged => {
+            // Only flag each discrepancy once, rather than every night until
+            // it's resolved.
+            let latest_true_up = app
+                .db
+                .get_organization_seat_true_ups(organization.id, 1)
+                .await?
+                .pop();
+            if latest_true_up.is_some_and(|true_up| {
+                true_up.outcome == SeatTrueUpOutcome::Flagged
+                    && true_up.member_count == member_count
+                    && true_up.seat_count == seat_count
+            }) {
+                return Ok(());
+            }
+            log::warn!(
+                "organization {} has {member_count} members, but pays for {seat_count} seats",
+                organization.id
+            );
+        }
+    }
+
+    app.db
+        .record_organization_seat_true_up(&CreateOrganizationSeatTrueUpParams {
+            organization_id: organization.id,
+            billing_subscription_id: subscription.id,
+            member_count,
+            seat_count,
+            outcome,
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the seats an organization with the given number of members needs.
+fn required_seat_count(member_count: i32) -> i32 {
+    // A team subscription always has at least one seat.
+    member_count.max(1)
+}
+
+/// Returns what to do about an organization's member count differing from
+/// the seats it pays for, if it does.
+fn true_up_outcome(
+    policy: SeatPolicy,
+    member_count: i32,
+    seat_count: i32,
+) -> Option<SeatTrueUpOutcome> {
+    if required_seat_count(member_count) == seat_count {
+        return None;
+    }
+
+    Some(match policy {
+        SeatPolicy::AutoAdjust => SeatTrueUpOutcome::Adjusted,
+        SeatPolicy::Flag => SeatTrueUpOutcome::Flagged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_true_up_outcome() {
+        assert_eq!(true_up_outcome(SeatPolicy::AutoAdjust, 5, 5), None);
+        assert_eq!(
+            true_up_outcome(SeatPolicy::AutoAdjust, 6, 5),
+            Some(SeatTrueUpOutcome::Adjusted)
+        );
+        assert_eq!(
+            true_up_outcome(SeatPolicy::AutoAdjust, 3, 5),
+            Some(SeatTrueUpOutcome::Adjusted)
+        );
+        assert_eq!(
+            true_up_outcome(SeatPolicy::Flag, 6, 5),
+            Some(SeatTrueUpOutcome::Flagged)
+        );
+        // An organization without members still pays for one seat.
+        assert_eq!(true_up_outcome(SeatPolicy::AutoAdjust, 0, 1), None);
+        assert_eq!(
+            true_up_outcome(SeatPolicy::AutoAdjust, 0, 3),
+            Some(SeatTrueUpOutcome::Adjusted)
+        );
+        assert_eq!(
+            true_up_outcome(SeatPolicy::Flag, 0, 3),
+            Some(SeatTrueUpOutcome::Flagged)
+        );
+    }
+}
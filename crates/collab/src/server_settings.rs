@@ -0,0 +1,283 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail, Context as _};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use util::ResultExt;
+
+use crate::api::{language_model_costs, usage_anomalies};
+use crate::{db::Database, executor::Executor, metered_billing};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// Whether language model requests are refused while the service is under
+    /// maintenance.
+    pub maintenance_mode: bool,
+    /// How often to poll Stripe for billing events.
+    pub stripe_events_poll_interval_in_seconds: u64,
+    /// Overrides of the number of requests per hour allowed by rate limits,
+    /// keyed by the rate limit's name (e.g. "complete-with-language-model").
+    pub rate_limits: BTreeMap<String, usize>,
+    /// The number of language model tokens per day that users without a
+    /// subscription can use before their usage is flagged as anomalous.
+    pub free_plan_tokens_per_day: i64,
+    /// The number of language model tokens per day that users with a
+    /// subscription can use before their usage is flagged as anomalous.
+    pub paid_plan_tokens_per_day: i64,
+    /// How long individual language model usages are kept before they're
+    /// rolled up into monthly usages.
+    pub language_model_usage_retention_in_days: i64,
+    /// How long audit records are kept in the database before they're
+    /// exported to the blob store and deleted.
+    pub audit_log_retention_in_days: i64,
+    /// How long users keep what exceeds their plan's limits after their
+    /// subscription lapses or is downgraded.
+    pub downgrade_grace_period_in_days: i64,
+    /// How long before the grace period ends that users are told what will
+    /// happen once it does.
+    pub downgrade_notice_in_days: i64,
+    /// How long a subscription keeps granting access after a failed payment
+    /// makes it past due.
+    pub past_due_grace_period_in_days: i64,
+    /// How many times we retry collecting payment for an unpaid subscription
+    /// before suspending it.
+    pub unpaid_dunning_attempts: i64,
+    /// How long we wait between retries of collecting payment for an unpaid
+    /// subscription.
+    pub unpaid_dunning_interval_in_days: i64,
+    /// The names of the column renames whose reads use the new column, once
+    /// it's been backfilled and verified (e.g.
+    /// "billing_subscriptions.current_period_end").
+    pub read_renamed_columns: Vec<String>,
+    /// The last four characters of the Stripe API key being rotated to. While
+    /// set, billing is read-only: requests that would change it are refused,
+    /// and reads are served from the database. It's reset once a server
+    /// configured with the new key has verified it against Stripe.
+    pub stripe_key_rotation: Option<String>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            maintenance_mode: false,
+            stripe_events_poll_interval_in_seconds: 5 * 60,
+            rate_limits: BTreeMap::new(),
+            free_plan_tokens_per_day: 500_000,
+            paid_plan_tokens_per_day: 5_000_000,
+            language_model_usage_retention_in_days: 180,
+            audit_log_retention_in_days: 2 * 365,
+            downgrade_grace_period_in_days: 14,
+            downgrade_notice_in_days: 3,
+            past_due_grace_period_in_days: 7,
+            unpaid_dunning_attempts: 3,
+            unpaid_dunning_interval_in_days: 3,
+            read_renamed_columns: Vec::new(),
+            stripe_key_rotation: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Builds the settings from the stored JSON value of each key. Keys that
+    /// aren't set keep their defaults.
+    pub fn from_values<'a>(
+        values: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> anyhow::Result<Self> {
+        let mut settings = serde_json::to_value(Self::default())?;
+        let object = settings
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("settings are not an object"))?;
+        for (key, value) in values {
+            if !object.contains_key(key) {
+                log::warn!("ignoring unknown server setting {key:?}");
+                continue;
+            }
+            let value = serde_json::from_str(value)
+                .with_context(|| format!("invalid value for server setting {key:?}"))?;
+            object.insert(key.to_string(), value);
+        }
+        Ok(serde_json::from_value(settings)?)
+    }
+
+    /// Returns whether settings can be stored under the given key.
+    pub fn is_known_key(key: &str) -> bool {
+        serde_json::to_value(Self::default())
+            .ok()
+            .and_then(|settings| settings.as_object().map(|object| object.contains_key(key)))
+            .unwrap_or(false)
+    }
+
+    /// Checks that the settings are within their allowed ranges: intervals
+    /// must be positive, durations and limits can't be negative, and
+    /// individual language model usages must be kept for as long as they're
+    /// read.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.stripe_events_poll_interval_in_seconds == 0 {
+            bail!("stripe_events_poll_interval_in_seconds must be positive");
+        }
+        if self.unpaid_dunning_interval_in_days <= 0 {
+            bail!("unpaid_dunning_interval_in_days must be positive");
+        }
+        let min_usage_retention_in_days = min_language_model_usage_retention_in_days();
+        if self.language_model_usage_retention_in_days < min_usage_retention_in_days {
+            bail!(
+                "language_model_usage_retention_in_days must be at least {min_usage_retention_in_days}"
+            );
+        }
+        for (key, value) in [
+            ("free_plan_tokens_per_day", self.free_plan_tokens_per_day),
+            ("paid_plan_tokens_per_day", self.paid_plan_tokens_per_day),
+            (
+                "audit_log_retention_in_days",
+                self.audit_log_retention_in_days,
+            ),
+            (
+                "downgrade_grace_period_in_days",
+                self.downgrade_grace_period_in_days,
+            ),
+            ("downgrade_notice_in_days", self.downgrade_notice_in_days),
+            (
+                "past_due_grace_period_in_days",
+                self.past_due_grace_period_in_days,
+            ),
+            ("unpaid_dunning_attempts", self.unpaid_dunning_attempts),
+        ] {
+            if value < 0 {
+                bail!("{key} can't be negative");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stripe_events_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.stripe_events_poll_interval_in_seconds)
+    }
+
+    /// Returns whether changes to billing are refused, which they are while
+    /// the Stripe API key is rotated.
+    pub fn billing_read_only(&self) -> bool {
+        self.stripe_key_rotation.is_some()
+    }
+}
+
+/// Returns the fewest days individual language model usages can be kept for,
+/// as cost reports, anomaly detection, and metered billing of the current
+/// billing period read them.
+fn min_language_model_usage_retention_in_days() -> i64 {
+    [
+        language_model_costs::MAX_REPORT_DAYS,
+        usage_anomalies::DETECTION_WINDOW.whole_days() + usage_anomalies::BASELINE_PERIOD_IN_DAYS,
+        metered_billing::MAX_BILLING_PERIOD_IN_DAYS,
+    ]
+    .into_iter()
+    .max()
+    .unwrap()
+}
+
+/// Holds the current server settings, and notifies subscribers when they
+/// change.
+pub struct ServerSettingsStore {
+    db: Arc<Database>,
+    settings: watch::Sender<ServerSettings>,
+}
+
+impl ServerSettingsStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self {
+            db,
+            settings: watch::channel(ServerSettings::default()).0,
+        }
+    }
+
+    /// Returns the current settings.
+    pub fn get(&self) -> ServerSettings {
+        self.settings.borrow().clone()
+    }
+
+    /// Returns a receiver that observes every change to the settings.
+    pub fn subscribe(&self) -> watch::Receiver<ServerSettings> {
+        self.settings.subscribe()
+    }
+
+    /// Loads the settings from the database, notifying subscribers if they
+    /// changed.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let rows = self.db.get_server_settings().await?;
+        let settings = ServerSettings::from_values(
+            rows.iter()
+                .map(|row| (row.key.as_str(), row.value.as_str())),
+        )?;
+        self.db
+            .set_renamed_column_reads(settings.read_renamed_columns.iter().cloned());
+        self.settings.send_if_modified(|current| {
+            if *current == settings {
+                false
+            } else {
+                log::info!("server settings changed: {settings:?}");
+                *current = settings;
+                true
+            }
+        });
+        Ok(())
+    }
+
+    /// Spawns a task that periodically reloads the settings, so that changes
+    /// made through other servers are picked up.
+    pub fn reload_periodically(store: Arc<Self>, executor: Executor) {
+        executor.clone().spawn_detached(async move {
+            loop {
+                executor.sleep(RELOAD_INTERVAL).await;
+                store.reload().await.log_err();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_settings_from_values() {
+        let settings = ServerSettings::from_values([
+            ("maintenance_mode", "true"),
+            ("rate_limits", r#"{"complete-with-language-model": 10}"#),
+            ("retired_setting", "1"),
+        ])
+        .unwrap();
+        assert_eq!(
+            settings,
+            ServerSettings {
+                maintenance_mode: true,
+                rate_limits: [("complete-with-language-model".to_string(), 10)]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            }
+        );
+
+        assert!(ServerSettings::from_values([("maintenance_mode", "\"yes\"")]).is_err());
+
+        assert!(!settings.billing_read_only());
+        let settings = ServerSettings::from_values([("stripe_key_rotation", r#""1234""#)]).unwrap();
+        assert!(settings.billing_read_only());
+
+        assert!(ServerSettings::default().validate().is_ok());
+        for (key, value) in [
+            ("stripe_events_poll_interval_in_seconds", "0"),
+            ("unpaid_dunning_interval_in_days", "0"),
+            ("past_due_grace_period_in_days", "-1"),
+            ("audit_log_retention_in_days", "-30"),
+            ("language_model_usage_retention_in_days", "30"),
+        ] {
+            let settings = ServerSettings::from_values([(key, value)]).unwrap();
+            assert!(settings.validate().is_err(), "{key} = {value}");
+        }
+
+        assert!(ServerSettings::is_known_key("free_plan_tokens_per_day"));
+        assert!(!ServerSettings::is_known_key("retired_setting"));
+    }
+}
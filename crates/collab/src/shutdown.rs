@@ -0,0 +1,159 @@
+use std::{future::Future, pin::pin, sync::Arc, time::Duration};
+
+use futures::future::{self, Either};
+use tokio::sync::watch;
+
+use crate::executor::Executor;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ShutdownState {
+    shutting_down: bool,
+    /// The number of units of work that shutdown waits for.
+    in_flight: usize,
+}
+
+/// Coordinates shutting the server down without abandoning work midway.
+///
+/// Once shutdown begins, no new work is started, and the server waits for
+/// the work already in flight, such as handling a batch of Stripe events, to
+/// complete before exiting.
+#[derive(Clone)]
+pub struct Shutdown {
+    state: Arc<watch::Sender<ShutdownState>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(watch::channel(ShutdownState::default()).0),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Stops new work from starting.
+    pub fn begin(&self) {
+        self.state.send_if_modified(|state| {
+            let was_shutting_down = state.shutting_down;
+            state.shutting_down = true;
+            !was_shutting_down
+        });
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.borrow().shutting_down
+    }
+
+    /// Runs the given work unless shutdown has begun, in which case `None` is
+    /// returned. Shutdown waits for the work to complete.
+    pub async fn run<F: Future>(&self, work: F) -> Option<F::Output> {
+        let mut started = false;
+        self.state.send_if_modified(|state| {
+            if !state.shutting_down {
+                state.in_flight += 1;
+                started = true;
+            }
+            started
+        });
+        if !started {
+            return None;
+        }
+
+        let _in_flight = InFlight {
+            state: self.state.clone(),
+        };
+        Some(work.await)
+    }
+
+    /// Sleeps for the given duration, waking up early if shutdown begins.
+    pub async fn sleep(&self, executor: &Executor, duration: Duration) {
+        let mut state = self.state.subscribe();
+        let shutting_down = pin!(state.wait_for(|state| state.shutting_down));
+        future::select(shutting_down, pin!(executor.sleep(duration))).await;
+    }
+
+    /// Waits for the work in flight to complete, for at most `deadline`.
+    ///
+    /// Returns whether all of the work completed.
+    pub async fn drain(&self, executor: &Executor, deadline: Duration) -> bool {
+        let mut state = self.state.subscribe();
+        let drained = pin!(state.wait_for(|state| state.in_flight == 0));
+        match future::select(drained, pin!(executor.sleep(deadline))).await {
+            Either::Left(_) => true,
+            Either::Right(_) => {
+                log::warn!(
+                    "shutting down with {} units of work still in flight",
+                    self.state.borrow().in_flight
+                );
+                false
+            }
+        }
+    }
+}
+
+struct InFlight {
+    state: Arc<watch::Sender<ShutdownState>>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.state.send_modify(|state| state.in_flight -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot;
+    use gpui::TestAppContext;
+
+    #[gpui::test]
+    async fn test_shutdown(cx: &mut TestAppContext) {
+        let executor = Executor::Deterministic(cx.executor());
+        let shutdown = Shutdown::default();
+        assert_eq!(shutdown.run(async { 1 }).await, Some(1));
+
+        let (finish_work, work_finished) = oneshot::channel::<()>();
+        let work = cx.executor().spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.run(work_finished).await }
+        });
+        cx.executor().run_until_parked();
+
+        // No new work starts once shutdown begins, but the work in flight is
+        // waited for.
+        shutdown.begin();
+        assert!(shutdown.is_shutting_down());
+        assert_eq!(shutdown.run(async { 2 }).await, None);
+
+        let drained = cx.executor().spawn({
+            let shutdown = shutdown.clone();
+            let executor = executor.clone();
+            async move { shutdown.drain(&executor, Duration::from_secs(10)).await }
+        });
+        cx.executor().run_until_parked();
+        finish_work.send(()).unwrap();
+        assert!(work.await.is_some());
+        assert!(drained.await);
+    }
+
+    #[gpui::test]
+    async fn test_shutdown_deadline(cx: &mut TestAppContext) {
+        let executor = Executor::Deterministic(cx.executor());
+        let shutdown = Shutdown::default();
+
+        let _work = cx.executor().spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.run(future::pending::<()>()).await }
+        });
+        cx.executor().run_until_parked();
+        shutdown.begin();
+
+        let drained = cx.executor().spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(&executor, Duration::from_secs(10)).await }
+        });
+        cx.executor().advance_clock(Duration::from_secs(10));
+        assert!(!drained.await);
+    }
+}
@@ -0,0 +1,188 @@
+use rpc::{proto::ErrorCode, ErrorCodeExt};
+use time::OffsetDateTime;
+
+use crate::db::{billing_subscription, Database, UserId};
+use crate::entitlements::UsagePeriod;
+use crate::Config;
+
+/// What a user has spent on metered usage within a billing period, and the
+/// most they're willing to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeteredSpend {
+    pub period: UsagePeriod,
+    pub spent_in_cents: i64,
+    pub cap_in_cents: Option<i64>,
+    pub price_per_million_tokens_in_cents: i64,
+}
+
+impl MeteredSpend {
+    /// Returns the user's metered spend for the current billing period of
+    /// their subscription, or `None` if they aren't billed for their usage.
+    pub async fn for_user(
+        db: &Database,
+        config: &Config,
+        user_id: UserId,
+        now: OffsetDateTime,
+    ) -> crate::Result<Option<Self>> {
+        let (Some(_), Some(price_per_million_tokens_in_cents)) = (
+            &config.stripe_metered_price_id,
+            config.metered_price_per_million_tokens_in_cents,
+        ) else {
+            return Ok(None);
+        };
+        // Usage is only reported for customers on our own account.
+        let Some(billing_customer) = db
+            .get_billing_customer_by_user_id(user_id)
+            .await?
+            .filter(|billing_customer| billing_customer.stripe_account_id.is_none())
+        else {
+            return Ok(None);
+        };
+
+        let subscription = db
+            .get_active_billing_subscriptions(user_id)
+            .await?
+            .into_iter()
+            .next();
+        // Subscriptions stored before their period start was recorded are
+        // counted by calendar month until they're next synced.
+        let period = subscription
+            .as_ref()
+            .and_then(billing_period)
+            .unwrap_or_else(|| UsagePeriod::containing(now));
+        let used_tokens = if subscription.is_some() {
+            db.get_language_model_token_usage_for_user(user_id, period.start, period.end)
+                .await?
+        } else {
+            0
+        };
+        Ok(Some(Self {
+            period,
+            spent_in_cents: metered_cost_in_cents(used_tokens, price_per_million_tokens_in_cents),
+            cap_in_cents: billing_customer.spending_cap_in_cents,
+            price_per_million_tokens_in_cents,
+        }))
+    }
+
+    /// Returns whether a request of the given number of tokens would make
+    /// the user spend more than their cap allows within the period.
+    pub fn would_exceed_cap(&self, tokens: u64) -> bool {
+        self.cap_in_cents.map_or(false, |cap_in_cents| {
+            let cost_in_cents = metered_cost_in_cents(
+                tokens.min(i64::MAX as u64) as i64,
+                self.price_per_million_tokens_in_cents,
+            );
+            self.spent_in_cents >= cap_in_cents
+                || self.spent_in_cents.saturating_add(cost_in_cents) > cap_in_cents
+        })
+    }
+
+    /// Returns a "spending cap reached" error if the user has spent as much
+    /// as their cap allows within the period, or if a request estimated to
+    /// use the given number of tokens would make them spend more.
+    pub fn check(&self, estimated_tokens: u64) -> anyhow::Result<()> {
+        let Some(cap_in_cents) = self.cap_in_cents else {
+            return Ok(());
+        };
+        if !self.would_exceed_cap(estimated_tokens) {
+            return Ok(());
+        }
+
+        let message = if self.spent_in_cents >= cap_in_cents {
+            format!(
+                "the spending cap of {cap_in_cents} cents for this billing period has been reached"
+            )
+        } else {
+            format!(
+                "this request would exceed the spending cap of {cap_in_cents} cents \
+                for this billing period"
+            )
+        };
+        Err(ErrorCode::SpendingCapReached
+            .message(message)
+            .with_tag("cap_in_cents", &cap_in_cents.to_string())
+            .with_tag("spent_in_cents", &self.spent_in_cents.to_string())
+            .with_tag(
+                "period_ends_at",
+                &self.period.end.unix_timestamp().to_string(),
+            )
+            .anyhow())
+    }
+}
+
+/// Returns what the given number of metered tokens cost, rounded up to the
+/// next cent, so that the cap is never overshot by a fraction of one.
+pub fn metered_cost_in_cents(tokens: i64, price_per_million_tokens_in_cents: i64) -> i64 {
+    let cost = tokens.max(0) as i128 * price_per_million_tokens_in_cents.max(0) as i128;
+    ((cost + 999_999) / 1_000_000).min(i64::MAX as i128) as i64
+}
+
+/// Returns the subscription's current billing period, if it's known.
+fn billing_period(subscription: &billing_subscription::Model) -> Option<UsagePeriod> {
+    Some(UsagePeriod {
+        start: subscription.current_period_start?.assume_utc(),
+        end: subscription.current_period_end?.assume_utc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_metered_cost_in_cents() {
+        assert_eq!(metered_cost_in_cents(0, 150), 0);
+        assert_eq!(metered_cost_in_cents(1, 150), 1);
+        assert_eq!(metered_cost_in_cents(2_000_000, 150), 300);
+        assert_eq!(metered_cost_in_cents(2_000_001, 150), 301);
+        assert_eq!(metered_cost_in_cents(-5, 150), 0);
+    }
+
+    #[test]
+    fn test_spending_cap() {
+        let spend = |spent_in_cents, cap_in_cents| MeteredSpend {
+            period: UsagePeriod::containing(datetime!(2024-09-15 12:00 UTC)),
+            spent_in_cents,
+            cap_in_cents,
+            price_per_million_tokens_in_cents: 100,
+        };
+
+        assert!(spend(10_000, None).check(1_000_000).is_ok());
+        assert!(spend(4_999, Some(5_000)).check(0).is_ok());
+
+        let error = spend(5_000, Some(5_000)).check(0).unwrap_err();
+        assert_eq!(error.error_code(), ErrorCode::SpendingCapReached);
+        assert!(spend(5_001, Some(5_000)).check(0).is_err());
+
+        // Requests that would take the spend over the cap are refused.
+        assert!(spend(4_900, Some(5_000)).check(100_000_000).is_ok());
+        assert!(spend(4_900, Some(5_000)).check(100_000_001).is_err());
+
+        // Users can stop all metered usage with a cap of zero.
+        assert!(spend(0, Some(0)).check(0).is_err());
+    }
+
+    #[test]
+    fn test_billing_period() {
+        let subscription = billing_subscription::Model {
+            current_period_start: Some(datetime!(2024-09-12 8:00)),
+            current_period_end: Some(datetime!(2024-10-12 8:00)),
+            ..Default::default()
+        };
+        assert_eq!(
+            billing_period(&subscription),
+            Some(UsagePeriod {
+                start: datetime!(2024-09-12 8:00 UTC),
+                end: datetime!(2024-10-12 8:00 UTC),
+            })
+        );
+        assert_eq!(
+            billing_period(&billing_subscription::Model {
+                current_period_start: None,
+                ..subscription
+            }),
+            None
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context as _};
+use collections::BTreeMap;
+use stripe::AccountId;
+
+use crate::Config;
+
+/// The connected accounts of resellers, by the countries they sell in.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ResellerAccounts {
+    /// Keyed by ISO 3166-1 alpha-2 country code, in uppercase.
+    account_ids_by_country: BTreeMap<String, String>,
+}
+
+impl ResellerAccounts {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Self::parse(config.stripe_connect_accounts.as_deref())
+    }
+
+    fn parse(connect_accounts: Option<&str>) -> anyhow::Result<Self> {
+        let mut accounts = Self::default();
+        for entry in connect_accounts
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+        {
+            if entry.is_empty() {
+                continue;
+            }
+            let (country, account_id) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("invalid Stripe Connect account entry {entry:?}"))?;
+            let country = country.trim();
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                bail!("invalid country {country:?} in Stripe Connect account entry {entry:?}");
+            }
+            let account_id = account_id.trim();
+            AccountId::from_str(account_id)
+                .with_context(|| format!("invalid account in {entry:?}"))?;
+            if accounts
+                .account_ids_by_country
+                .insert(country.to_ascii_uppercase(), account_id.to_string())
+                .is_some()
+            {
+                bail!("country {country:?} is routed to more than one Stripe Connect account");
+            }
+        }
+        Ok(accounts)
+    }
+
+    /// Returns the account that customers in the given country are created
+    /// on, or `None` if they're customers of our own account.
+    pub fn account_for_country(&self, country: &str) -> Option<&str> {
+        self.account_ids_by_country
+            .get(&country.to_ascii_uppercase())
+            .map(String::as_str)
+    }
+
+    /// Returns every connected account, once each.
+    pub fn account_ids(&self) -> Vec<&str> {
+        let mut account_ids = self
+            .account_ids_by_country
+            .values()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        account_ids.sort_unstable();
+        account_ids.dedup();
+        account_ids
+    }
+
+    pub fn contains(&self, account_id: &str) -> bool {
+        self.account_ids_by_country
+            .values()
+            .any(|id| id == account_id)
+    }
+}
+
+/// Returns a Stripe client that acts on behalf of the given connected
+/// account, or on our own account for `None`.
+pub fn client_for_account(
+    stripe_client: &stripe::Client,
+    stripe_account_id: Option<&str>,
+) -> anyhow::Result<stripe::Client> {
+    let stripe_client = stripe_client.clone();
+    Ok(match stripe_account_id {
+        Some(account_id) => stripe_client.with_stripe_account(
+            AccountId::from_str(account_id).context("failed to parse account ID")?,
+        ),
+        None => stripe_client,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reseller_accounts() {
+        assert_eq!(
+            ResellerAccounts::parse(None).unwrap(),
+            ResellerAccounts::default()
+        );
+
+        let accounts =
+            ResellerAccounts::parse(Some("BR:acct_123, pt:acct_123,IN:acct_456")).unwrap();
+        assert_eq!(accounts.account_for_country("br"), Some("acct_123"));
+        assert_eq!(accounts.account_for_country("PT"), Some("acct_123"));
+        assert_eq!(accounts.account_for_country("IN"), Some("acct_456"));
+        assert_eq!(accounts.account_for_country("US"), None);
+        assert_eq!(accounts.account_ids(), ["acct_123", "acct_456"]);
+        assert!(accounts.contains("acct_456"));
+        assert!(!accounts.contains("acct_789"));
+
+        assert!(ResellerAccounts::parse(Some("BR")).is_err());
+        assert!(ResellerAccounts::parse(Some("BRA:acct_123")).is_err());
+        assert!(ResellerAccounts::parse(Some("BR:cus_123")).is_err());
+        assert!(ResellerAccounts::parse(Some("BR:acct_123,BR:acct_456")).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _};
+use stripe::{Price, PriceId};
+use util::ResultExt;
+
+use crate::api::billing::configured_price_ids;
+use crate::distributed_lock::run_exclusively;
+use crate::AppState;
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The server setting that holds the key being rotated to.
+const STRIPE_KEY_ROTATION_SETTING: &str = "stripe_key_rotation";
+
+/// The number of characters of the new key that identify it, which is as
+/// many as the Stripe dashboard shows.
+const KEY_SUFFIX_LEN: usize = 4;
+
+/// Periodically verifies this server's Stripe key while it's being rotated
+/// to, and ends the rotation once it passes.
+pub fn complete_stripe_key_rotations_periodically(app: Arc<AppState>) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "complete_stripe_key_rotation",
+                        complete_stripe_key_rotation(&app, &stripe_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, PROBE_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn complete_stripe_key_rotation(
+    app: &AppState,
+    stripe_client: &stripe::Client,
+) -> anyhow::Result<()> {
+    // Another server may have completed the rotation since we last loaded
+    // the settings.
+    app.server_settings.reload().await?;
+    let Some(key_suffix) = app.server_settings.get().stripe_key_rotation else {
+        return Ok(());
+    };
+    let api_key = app
+        .config
+        .stripe_api_key
+        .as_deref()
+        .ok_or_else(|| anyhow!("missing stripe_api_key"))?;
+    if !is_rotating_to(api_key, &key_suffix) {
+        return Ok(());
+    }
+
+    verify_stripe_key(app, stripe_client)
+        .await
+        .context("new Stripe key failed verification")?;
+
+    // The server ends the rotation, not the admin who started it.
+    app.db
+        .set_server_setting(STRIPE_KEY_ROTATION_SETTING, None, None)
+        .await?;
+    app.server_settings.reload().await?;
+    log::info!("verified new Stripe key ending in {key_suffix}, billing is writable again");
+
+    Ok(())
+}
+
+/// Returns whether the given API key is the one being rotated to.
+fn is_rotating_to(api_key: &str, key_suffix: &str) -> bool {
+    key_suffix.len() >= KEY_SUFFIX_LEN && api_key.ends_with(key_suffix)
+}
+
+/// Verifies that the Stripe key is accepted, and belongs to the account our
+/// prices are in, by retrieving each of them.
+async fn verify_stripe_key(app: &AppState, stripe_client: &stripe::Client) -> anyhow::Result<()> {
+    let price_ids = configured_price_ids(&app.config)?;
+    if price_ids.is_empty() {
+        return Err(anyhow!("no Stripe prices are configured"));
+    }
+
+    for price_id in price_ids {
+        let price_id = PriceId::from_str(&price_id).context("failed to parse price ID")?;
+        Price::retrieve(stripe_client, &price_id, &[])
+            .await
+            .with_context(|| format!("failed to retrieve price {price_id}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_rotating_to() {
+        assert!(is_rotating_to("sk_live_abcd1234", "1234"));
+        assert!(is_rotating_to("sk_live_abcd1234", "cd1234"));
+        assert!(!is_rotating_to("sk_live_abcd1234", "4321"));
+        // Suffixes too short to identify a key are never matched.
+        assert!(!is_rotating_to("sk_live_abcd1234", "34"));
+        assert!(!is_rotating_to("sk_live_abcd1234", ""));
+    }
+}
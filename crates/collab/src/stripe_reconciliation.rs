@@ -0,0 +1,269 @@
+use std::iter;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use collections::HashSet;
+use stripe::{ListSubscriptions, Subscription, SubscriptionStatusFilter};
+use util::ResultExt;
+
+use crate::api::billing::{
+    billing_subscription_params, payment_provider_for_account, sync_billing_subscription,
+};
+use crate::db::{billing_subscription, CreateBillingSubscriptionParams};
+use crate::distributed_lock::run_exclusively;
+use crate::payment_provider::PaymentSubscription;
+use crate::stripe_connect::{client_for_account, ResellerAccounts};
+use crate::AppState;
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The number of subscriptions retrieved from Stripe in one request.
+const PAGE_SIZE: u64 = 100;
+
+/// Periodically reconciles our billing subscriptions with Stripe.
+pub fn reconcile_stripe_subscriptions_periodically(
+    app: Arc<AppState>,
+    rpc_server: Option<Arc<crate::rpc::Server>>,
+) {
+    let Some(stripe_client) = app.stripe_client.clone() else {
+        log::warn!("failed to retrieve Stripe client");
+        return;
+    };
+
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "reconcile_stripe_subscriptions",
+                        reconcile_stripe_subscriptions(&app, rpc_server.as_ref(), &stripe_client),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, RECONCILE_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn reconcile_stripe_subscriptions(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    stripe_client: &stripe::Client,
+) -> anyhow::Result<()> {
+    let mut seen_subscription_ids = HashSet::default();
+    let mut subscription_count = 0;
+    let mut drifted_count = 0;
+    // Subscriptions on resellers' connected accounts are listed separately.
+    let reseller_accounts = ResellerAccounts::from_config(&app.config)?;
+    let stripe_account_ids =
+        iter::once(None).chain(reseller_accounts.account_ids().into_iter().map(Some));
+    for stripe_account_id in stripe_account_ids {
+        let stripe_client = &client_for_account(stripe_client, stripe_account_id)?;
+        let mut starting_after = None;
+        loop {
+            let mut params = ListSubscriptions::new();
+            params.status = Some(SubscriptionStatusFilter::All);
+            params.limit = Some(PAGE_SIZE);
+            params.starting_after = starting_after.take();
+
+            let page = Subscription::list(stripe_client, &params).await?;
+            let has_more = page.has_more;
+            starting_after = page.data.last().map(|subscription| subscription.id.clone());
+
+            for subscription in page.data {
+                if app.shutdown.is_shutting_down() {
+                    return Ok(());
+                }
+
+                subscription_count += 1;
+                seen_subscription_ids.insert(subscription.id.to_string());
+                let stripe_subscription_id = subscription.id.clone();
+                match reconcile_subscription(app, rpc_server, stripe_account_id, subscription)
+                    .await
+                    .with_context(|| {
+                        format!("failed to reconcile subscription {stripe_subscription_id}")
+                    })
+                    .log_err()
+                {
+                    Some(true) => drifted_count += 1,
+                    Some(false) | None => {}
+                }
+            }
+
+            if !has_more || starting_after.is_none() {
+                break;
+            }
+        }
+    }
+
+    // Subscriptions we know of that Stripe doesn't can't be fixed from here,
+    // as there's nothing to sync them with.
+    let mut unknown_count = 0;
+    for subscription in app.db.get_unended_billing_subscriptions().await? {
+        if !seen_subscription_ids.contains(&subscription.stripe_subscription_id) {
+            unknown_count += 1;
+            log::warn!(
+                "billing subscription {} refers to Stripe subscription {}, which does not exist",
+                subscription.id,
+                subscription.stripe_subscription_id
+            );
+        }
+    }
+
+    log::info!(
+        "reconciled {subscription_count} Stripe subscriptions: {drifted_count} drifted, {unknown_count} unknown to Stripe"
+    );
+
+    Ok(())
+}
+
+/// Compares the Stripe subscription with our record of it, and syncs it if
+/// they differ. Returns whether they did.
+async fn reconcile_subscription(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    stripe_account_id: Option<&str>,
+    subscription: Subscription,
+) -> anyhow::Result<bool> {
+    let billing_subscription = app
+        .db
+        .get_billing_subscription_by_stripe_subscription_id(subscription.id.as_str())
+        .await?;
+    let discrepancies = match &billing_subscription {
+        Some(billing_subscription) => subscription_discrepancies(
+            billing_subscription,
+            &billing_subscription_params(
+                &app.config,
+                billing_subscription.billing_customer_id,
+                billing_subscription.stripe_account_id.as_deref(),
+                &PaymentSubscription::try_from(&subscription)?,
+            )?,
+        ),
+        None => vec!["missing"],
+    };
+    if discrepancies.is_empty() {
+        return Ok(false);
+    }
+
+    log::warn!(
+        "billing subscription for Stripe subscription {} drifted: {}",
+        subscription.id,
+        discrepancies.join(", ")
+    );
+    let payment_provider = payment_provider_for_account(app, stripe_account_id)?;
+    sync_billing_subscription(
+        app,
+        rpc_server,
+        payment_provider.as_ref(),
+        stripe_account_id,
+        &PaymentSubscription::try_from(&subscription)?,
+    )
+    .await?;
+
+    Ok(true)
+}
+
+/// Returns the columns of our record of a subscription that differ from its
+/// state in Stripe.
+fn subscription_discrepancies(
+    billing_subscription: &billing_subscription::Model,
+    expected: &CreateBillingSubscriptionParams,
+) -> Vec<&'static str> {
+    let mut discrepancies = Vec::new();
+    if billing_subscription.stripe_subscription_status != expected.stripe_subscription_status {
+        discrepancies.push("stripe_subscription_status");
+    }
+    if billing_subscription.plan != expected.plan {
+        discrepancies.push("plan");
+    }
+    if billing_subscription.seat_count != expected.seat_count {
+        discrepancies.push("seat_count");
+    }
+    if billing_subscription.stripe_coupon_id != expected.stripe_coupon_id {
+        discrepancies.push("stripe_coupon_id");
+    }
+    if billing_subscription.stripe_promotion_code_id != expected.stripe_promotion_code_id {
+        discrepancies.push("stripe_promotion_code_id");
+    }
+    if billing_subscription.trial_end != expected.trial_end {
+        discrepancies.push("trial_end");
+    }
+    if billing_subscription.current_period_start != expected.current_period_start {
+        discrepancies.push("current_period_start");
+    }
+    if billing_subscription.current_period_end != expected.current_period_end {
+        discrepancies.push("current_period_end");
+    }
+    if billing_subscription.cancel_at_period_end != expected.cancel_at_period_end {
+        discrepancies.push("cancel_at_period_end");
+    }
+    if billing_subscription.canceled_at != expected.canceled_at {
+        discrepancies.push("canceled_at");
+    }
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::billing_subscription::{StripeSubscriptionStatus, SubscriptionPlan};
+    use crate::db::{BillingCustomerId, BillingSubscriptionId};
+    use time::macros::datetime;
+
+    #[test]
+    fn test_subscription_discrepancies() {
+        let expected = CreateBillingSubscriptionParams {
+            billing_customer_id: BillingCustomerId(1),
+            stripe_subscription_id: "sub_1".into(),
+            stripe_subscription_status: StripeSubscriptionStatus::Active,
+            plan: SubscriptionPlan::Pro,
+            seat_count: 1,
+            stripe_coupon_id: None,
+            stripe_promotion_code_id: None,
+            trial_end: None,
+            current_period_start: Some(datetime!(2024-09-01 0:00)),
+            current_period_end: Some(datetime!(2024-10-01 0:00)),
+            cancel_at_period_end: false,
+            canceled_at: None,
+            stripe_account_id: None,
+        };
+        let billing_subscription = billing_subscription::Model {
+            id: BillingSubscriptionId(1),
+            billing_customer_id: expected.billing_customer_id,
+            stripe_subscription_id: expected.stripe_subscription_id.clone(),
+            stripe_subscription_status: expected.stripe_subscription_status,
+            plan: expected.plan,
+            seat_count: expected.seat_count,
+            stripe_coupon_id: None,
+            stripe_promotion_code_id: None,
+            trial_end: None,
+            current_period_start: expected.current_period_start,
+            current_period_end: expected.current_period_end,
+            current_period_ends_at: expected.current_period_end,
+            cancel_at_period_end: false,
+            canceled_at: None,
+            ..Default::default()
+        };
+        assert!(subscription_discrepancies(&billing_subscription, &expected).is_empty());
+
+        let stale_subscription = billing_subscription::Model {
+            stripe_subscription_status: StripeSubscriptionStatus::PastDue,
+            current_period_end: Some(datetime!(2024-09-01 0:00)),
+            ..billing_subscription
+        };
+        assert_eq!(
+            subscription_discrepancies(&stale_subscription, &expected),
+            ["stripe_subscription_status", "current_period_end"]
+        );
+    }
+}
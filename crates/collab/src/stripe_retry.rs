@@ -0,0 +1,105 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng as _;
+use stripe::StripeError;
+
+use crate::executor::Executor;
+
+/// The most times a request is attempted, including the first.
+const MAX_ATTEMPTS: u32 = 4;
+/// The delay before the first retry. Each subsequent retry doubles it.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// The longest we wait before retrying a request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+/// Sends the request made by `request`, retrying it with jittered
+/// exponential backoff while it fails transiently. The delays are slept on
+/// the given executor, so that tests can advance through them.
+///
+/// The request must be safe to repeat. Requests that create something must
+/// reuse the same idempotency key across attempts, by creating the client
+/// that carries it outside of `request`.
+pub async fn with_retries<T, F, Fut>(
+    executor: &Executor,
+    operation: &str,
+    mut request: F,
+) -> Result<T, StripeError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, StripeError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(error) if attempt < MAX_ATTEMPTS && is_transient(&error) => {
+                let delay = retry_delay(attempt, rand::thread_rng().gen());
+                log::warn!(
+                    "Stripe request to {operation} failed (attempt {attempt}), retrying in {delay:?}: {error}"
+                );
+                executor.sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns whether the request failed in a way that retrying it may fix.
+fn is_transient(error: &StripeError) -> bool {
+    match error {
+        StripeError::Stripe(error) => is_transient_status(error.http_status),
+        // The request didn't reach Stripe, or we gave up on its response.
+        StripeError::ClientError(_) | StripeError::Timeout => true,
+        _ => false,
+    }
+}
+
+/// Returns whether a response with the given status is worth retrying: the
+/// request was rate limited, or Stripe failed to handle it.
+fn is_transient_status(http_status: u16) -> bool {
+    http_status == 429 || (500..600).contains(&http_status)
+}
+
+/// Returns how long to wait before retrying a request after its given
+/// attempt failed, given a random `jitter` between 0 and 1.
+///
+/// The delay is somewhere between half and all of the exponential backoff,
+/// so that requests that failed together don't all retry at the same time.
+fn retry_delay(attempt: u32, jitter: f64) -> Duration {
+    let backoff = INITIAL_RETRY_DELAY
+        .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY);
+    backoff.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(is_transient_status(429));
+        assert!(is_transient_status(500));
+        assert!(is_transient_status(503));
+        assert!(!is_transient_status(400));
+        assert!(!is_transient_status(402));
+        assert!(!is_transient_status(404));
+
+        assert!(is_transient(&StripeError::Timeout));
+        assert!(is_transient(&StripeError::ClientError(
+            "connection reset".into()
+        )));
+        assert!(!is_transient(&StripeError::UnsupportedVersion));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1, 1.0), Duration::from_millis(500));
+        assert_eq!(retry_delay(1, 0.0), Duration::from_millis(250));
+        assert_eq!(retry_delay(2, 1.0), Duration::from_secs(1));
+        assert_eq!(retry_delay(3, 0.5), Duration::from_millis(1500));
+        assert_eq!(retry_delay(10, 1.0), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX, 0.0), MAX_RETRY_DELAY / 2);
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context as _};
+use stripe::{
+    CreateTestClock, Customer, EventObject, ListCustomers, ListSubscriptions, ListTestClocks,
+    Subscription, SubscriptionStatusFilter, TestHelpersTestClock, TestHelpersTestClockId,
+    Timestamp,
+};
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::api::billing::sync_billing_subscription;
+use crate::payment_provider::{PaymentProvider, PaymentSubscription};
+use crate::stripe_connect::client_for_account;
+use crate::AppState;
+
+/// The number of customers retrieved from Stripe in one request.
+const PAGE_SIZE: u64 = 100;
+
+/// Creates a test clock for a new customer, frozen at the current time.
+pub async fn create_test_clock(
+    stripe_client: &stripe::Client,
+    name: &str,
+) -> anyhow::Result<TestHelpersTestClockId> {
+    let test_clock = TestHelpersTestClock::create(
+        stripe_client,
+        &CreateTestClock {
+            frozen_time: OffsetDateTime::now_utc().unix_timestamp(),
+            name,
+        },
+    )
+    .await
+    .context("failed to create test clock")?;
+    log::info!("created Stripe test clock {} for {name}", test_clock.id);
+    Ok(test_clock.id)
+}
+
+/// Returns the test clock with the given name, creating it if there's none,
+/// so that a retried request attaches its customer to the same clock.
+pub async fn find_or_create_test_clock(
+    stripe_client: &stripe::Client,
+    name: &str,
+) -> anyhow::Result<TestHelpersTestClockId> {
+    let mut starting_after = None;
+    loop {
+        let mut params = ListTestClocks::new();
+        params.limit = Some(PAGE_SIZE);
+        params.starting_after = starting_after.take();
+
+        let page = TestHelpersTestClock::list(stripe_client, &params)
+            .await
+            .context("failed to list test clocks")?;
+        if let Some(test_clock) = page
+            .data
+            .iter()
+            .find(|test_clock| test_clock.name.as_deref() == Some(name))
+        {
+            return Ok(test_clock.id.clone());
+        }
+        starting_after = page.data.last().map(|test_clock| test_clock.id.clone());
+        if !page.has_more || starting_after.is_none() {
+            break;
+        }
+    }
+
+    create_test_clock(stripe_client, name).await
+}
+
+/// Returns the time an event can be considered to have happened at, for the
+/// purposes of keeping track of which events have been handled.
+///
+/// Events of objects on an advanced test clock are dated in the future, and
+/// taking them at their word would skip every event that happens between
+/// now and then.
+pub fn event_cursor_timestamp(created: Timestamp, now: OffsetDateTime) -> Timestamp {
+    created.min(now.unix_timestamp())
+}
+
+/// Syncs the subscriptions of the customers on a test clock once it's done
+/// advancing, as its events can arrive in any order while it does, and those
+/// dated ahead of us are only polled for again as time catches up with them.
+pub async fn handle_test_clock_ready_event(
+    app: &Arc<AppState>,
+    rpc_server: Option<&Arc<crate::rpc::Server>>,
+    payment_provider: &dyn PaymentProvider,
+    stripe_account_id: Option<&str>,
+    event: stripe::Event,
+) -> anyhow::Result<()> {
+    let EventObject::TestHelpersTestClock(test_clock) = event.data.object else {
+        bail!("unexpected event payload for {}", event.id);
+    };
+    // Test clocks are specific to Stripe, so their customers are listed
+    // through it directly.
+    let stripe_client = app
+        .stripe_client
+        .as_ref()
+        .context("failed to retrieve Stripe client")?;
+    let stripe_client = &client_for_account(stripe_client, stripe_account_id)?;
+
+    let mut starting_after = None;
+    loop {
+        let mut params = ListCustomers::new();
+        params.test_clock = Some(test_clock.id.as_str());
+        params.limit = Some(PAGE_SIZE);
+        params.starting_after = starting_after.take();
+
+        let page = Customer::list(stripe_client, &params).await?;
+        let has_more = page.has_more;
+        starting_after = page.data.last().map(|customer| customer.id.clone());
+
+        for customer in page.data {
+            let mut params = ListSubscriptions::new();
+            params.customer = Some(customer.id.clone());
+            params.status = Some(SubscriptionStatusFilter::All);
+            params.limit = Some(PAGE_SIZE);
+            for subscription in Subscription::list(stripe_client, &params).await?.data {
+                let stripe_subscription_id = subscription.id.clone();
+                async {
+                    sync_billing_subscription(
+                        app,
+                        rpc_server,
+                        payment_provider,
+                        stripe_account_id,
+                        &PaymentSubscription::try_from(&subscription)?,
+                    )
+                    .await
+                }
+                .await
+                .with_context(|| format!("failed to sync subscription {stripe_subscription_id}"))
+                .log_err();
+            }
+        }
+
+        if !has_more || starting_after.is_none() {
+            break;
+        }
+    }
+
+    log::info!(
+        "synced subscriptions on Stripe test clock {}, now at {:?}",
+        test_clock.id,
+        test_clock.frozen_time
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_event_cursor_timestamp() {
+        let now = datetime!(2024-09-01 0:00 UTC);
+        let earlier = datetime!(2024-08-31 23:59 UTC).unix_timestamp();
+        let advanced = datetime!(2024-10-01 0:00 UTC).unix_timestamp();
+
+        assert_eq!(event_cursor_timestamp(earlier, now), earlier);
+        assert_eq!(event_cursor_timestamp(advanced, now), now.unix_timestamp());
+    }
+}
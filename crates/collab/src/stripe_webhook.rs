@@ -0,0 +1,210 @@
+use std::fmt;
+
+use axum::http::StatusCode;
+use ring::hmac;
+use time::{Duration, OffsetDateTime};
+
+use crate::Error;
+
+/// The header Stripe sends a webhook's signatures in.
+pub const SIGNATURE_HEADER: &str = "Stripe-Signature";
+
+/// How far the time a webhook was signed may be from ours before it's
+/// rejected as a replay. This matches Stripe's own libraries.
+pub const TOLERANCE: Duration = Duration::minutes(5);
+
+/// Why a webhook failed verification.
+#[derive(Debug)]
+pub enum WebhookError {
+    /// The signature header is missing or malformed.
+    InvalidHeader,
+    /// None of the signatures match the payload.
+    InvalidSignature,
+    /// The webhook was signed too long ago, or too far in the future.
+    StaleTimestamp,
+    /// The payload isn't a Stripe event.
+    InvalidPayload(serde_json::Error),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::InvalidHeader => write!(f, "invalid {SIGNATURE_HEADER} header"),
+            WebhookError::InvalidSignature => write!(f, "invalid webhook signature"),
+            WebhookError::StaleTimestamp => write!(f, "webhook timestamp is outside the tolerance"),
+            WebhookError::InvalidPayload(error) => write!(f, "invalid webhook payload: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl From<WebhookError> for Error {
+    fn from(error: WebhookError) -> Self {
+        Error::Http(StatusCode::BAD_REQUEST, error.to_string())
+    }
+}
+
+/// Verifies that a webhook payload was signed by Stripe with the given secret
+/// within the [`TOLERANCE`] of `now`, and parses the event it holds.
+pub fn verify_event(
+    payload: &[u8],
+    signature_header: Option<&str>,
+    secret: &str,
+    now: OffsetDateTime,
+) -> Result<stripe::Event, WebhookError> {
+    let signature_header = signature_header.ok_or(WebhookError::InvalidHeader)?;
+    let (timestamp, signatures) = parse_signature_header(signature_header)?;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut signed_payload = format!("{timestamp}.").into_bytes();
+    signed_payload.extend_from_slice(payload);
+    if !signatures
+        .iter()
+        .any(|signature| hmac::verify(&key, &signed_payload, signature).is_ok())
+    {
+        return Err(WebhookError::InvalidSignature);
+    }
+
+    // The timestamp is only trusted once the signature covering it is.
+    let signed_at =
+        OffsetDateTime::from_unix_timestamp(timestamp).map_err(|_| WebhookError::InvalidHeader)?;
+    if (now - signed_at).abs() > TOLERANCE {
+        return Err(WebhookError::StaleTimestamp);
+    }
+
+    serde_json::from_slice(payload).map_err(WebhookError::InvalidPayload)
+}
+
+/// Parses a signature header of the form `t=<timestamp>,v1=<signature>,...`
+/// into its timestamp and `v1` signatures. Signatures of other schemes are
+/// ignored, as Stripe sends test-mode `v0` signatures alongside.
+fn parse_signature_header(header: &str) -> Result<(i64, Vec<Vec<u8>>), WebhookError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for item in header.split(',') {
+        let (key, value) = item
+            .trim()
+            .split_once('=')
+            .ok_or(WebhookError::InvalidHeader)?;
+        match key {
+            "t" => {
+                timestamp = Some(value.parse().map_err(|_| WebhookError::InvalidHeader)?);
+            }
+            "v1" => {
+                signatures.push(hex::decode(value).map_err(|_| WebhookError::InvalidHeader)?);
+            }
+            _ => {}
+        }
+    }
+
+    match timestamp {
+        Some(timestamp) if !signatures.is_empty() => Ok((timestamp, signatures)),
+        _ => Err(WebhookError::InvalidHeader),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    const SECRET: &str = "whsec_test";
+
+    /// Returns a signature header for the payload, as Stripe would send it.
+    fn sign_payload(payload: &[u8], secret: &str, signed_at: OffsetDateTime) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let timestamp = signed_at.unix_timestamp();
+        let mut signed_payload = format!("{timestamp}.").into_bytes();
+        signed_payload.extend_from_slice(payload);
+        let signature = hmac::sign(&key, &signed_payload);
+        format!("t={timestamp},v1={}", hex::encode(signature.as_ref()))
+    }
+
+    fn payload() -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "id": "evt_1",
+            "object": "event",
+            "api_version": "2024-06-20",
+            "created": 1724025600,
+            "data": {
+                "object": {
+                    "id": "cus_1",
+                    "object": "customer",
+                    "email": "user@example.com"
+                }
+            },
+            "livemode": false,
+            "pending_webhooks": 1,
+            "request": { "id": null, "idempotency_key": null },
+            "type": "customer.updated"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_event() {
+        let now = datetime!(2024-08-19 0:00 UTC);
+        let payload = payload();
+
+        let header = sign_payload(&payload, SECRET, now - Duration::minutes(1));
+        let event = verify_event(&payload, Some(&header), SECRET, now).unwrap();
+        assert_eq!(event.id.as_str(), "evt_1");
+        assert_eq!(event.type_, stripe::EventType::CustomerUpdated);
+
+        // Any of the signatures may match, such as while the secret is being
+        // rolled.
+        let header = sign_payload(&payload, SECRET, now);
+        let old_header = sign_payload(&payload, "whsec_old", now);
+        let header = format!(
+            "{old_header},v1={},v0=abc",
+            header.split_once("v1=").unwrap().1
+        );
+        assert!(verify_event(&payload, Some(&header), SECRET, now).is_ok());
+    }
+
+    #[test]
+    fn test_reject_invalid_events() {
+        let now = datetime!(2024-08-19 0:00 UTC);
+        let payload = payload();
+        let header = sign_payload(&payload, SECRET, now);
+
+        assert!(matches!(
+            verify_event(&payload, None, SECRET, now),
+            Err(WebhookError::InvalidHeader)
+        ));
+        assert!(matches!(
+            verify_event(&payload, Some("t=123"), SECRET, now),
+            Err(WebhookError::InvalidHeader)
+        ));
+        assert!(matches!(
+            verify_event(&payload, Some("v1=zz,t=abc"), SECRET, now),
+            Err(WebhookError::InvalidHeader)
+        ));
+        assert!(matches!(
+            verify_event(&payload, Some(&header), "whsec_other", now),
+            Err(WebhookError::InvalidSignature)
+        ));
+
+        let mut tampered_payload = payload.clone();
+        tampered_payload.extend_from_slice(b" ");
+        assert!(matches!(
+            verify_event(&tampered_payload, Some(&header), SECRET, now),
+            Err(WebhookError::InvalidSignature)
+        ));
+
+        for signed_at in [now - Duration::minutes(6), now + Duration::minutes(6)] {
+            let header = sign_payload(&payload, SECRET, signed_at);
+            assert!(matches!(
+                verify_event(&payload, Some(&header), SECRET, now),
+                Err(WebhookError::StaleTimestamp)
+            ));
+        }
+
+        let header = sign_payload(b"{}", SECRET, now);
+        assert!(matches!(
+            verify_event(b"{}", Some(&header), SECRET, now),
+            Err(WebhookError::InvalidPayload(_))
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use tracing::field;
+
+use crate::db::{Database, OrganizationId, UserId};
+use crate::entitlements::{EntitlementsService, Plan};
+use crate::Result;
+
+/// Who a request is being served for, recorded on the spans of the work done
+/// for it so that logs can be filtered down to a single user, organization or
+/// plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tenant {
+    pub user_id: UserId,
+    /// The first organization the user joined, if any.
+    pub org_id: Option<OrganizationId>,
+    pub plan: Plan,
+}
+
+impl Tenant {
+    pub async fn for_user(
+        db: &Database,
+        entitlements: &EntitlementsService,
+        user_id: UserId,
+    ) -> Result<Self> {
+        let org_id = db
+            .get_organization_memberships_for_user(user_id)
+            .await?
+            .first()
+            .map(|membership| membership.organization_id);
+        let plan = entitlements.for_user(user_id).await?.plan;
+        Ok(Self {
+            user_id,
+            org_id,
+            plan,
+        })
+    }
+
+    /// Records the tenant on a span that declares `user_id`, `org_id` and
+    /// `plan` fields.
+    pub fn record(&self, span: &tracing::Span) {
+        span.record("user_id", self.user_id.0);
+        if let Some(org_id) = self.org_id {
+            span.record("org_id", org_id.0);
+        }
+        span.record("plan", self.plan.as_str());
+    }
+
+    /// Returns a span that attributes everything logged within it to the
+    /// tenant.
+    pub fn span(&self) -> tracing::Span {
+        let span = tracing::info_span!(
+            "tenant",
+            user_id = field::Empty,
+            org_id = field::Empty,
+            plan = field::Empty
+        );
+        self.record(&span);
+        span
+    }
+}
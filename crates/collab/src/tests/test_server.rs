@@ -1,8 +1,11 @@
 use crate::{
     auth::split_dev_server_token,
     db::{tests::TestDb, NewUserParams, UserId},
+    distributed_lock::InMemoryLock,
+    entitlements::EntitlementsService,
     executor::Executor,
     rpc::{Principal, Server, ZedVersion, CLEANUP_TIMEOUT, RECONNECT_TIMEOUT},
+    server_settings::ServerSettingsStore,
     AppState, Config, RateLimiter,
 };
 use anyhow::anyhow;
@@ -33,7 +36,7 @@ use rpc::{
 use semantic_version::SemanticVersion;
 use serde_json::json;
 use session::{AppSession, Session};
-use settings::SettingsStore;
+use settings::{Settings, SettingsStore};
 use std::{
     cell::{Ref, RefCell, RefMut},
     env,
@@ -243,6 +246,7 @@ impl TestServer {
                                 server_conn,
                                 client_name,
                                 Principal::User(user),
+                                None,
                                 ZedVersion(SemanticVersion::new(1, 0, 0)),
                                 Some(connection_id_tx),
                                 Executor::Deterministic(cx.background_executor().clone()),
@@ -302,6 +306,7 @@ impl TestServer {
             language_model::LanguageModelRegistry::test(cx);
             completion::init(cx);
             assistant::context_store::init(&client);
+            assistant::assistant_settings::AssistantSettings::register(cx);
         });
 
         client
@@ -377,6 +382,7 @@ impl TestServer {
                                 server_conn,
                                 "dev-server".to_string(),
                                 Principal::DevServer(dev_server),
+                                None,
                                 ZedVersion(SemanticVersion::new(1, 0, 0)),
                                 Some(connection_id_tx),
                                 Executor::Deterministic(cx.background_executor().clone()),
@@ -633,48 +639,107 @@ impl TestServer {
         live_kit_test_server: &live_kit_client::TestServer,
         executor: Executor,
     ) -> Arc<AppState> {
-        Arc::new(AppState {
+        Self::build_app_state_with(test_db, live_kit_test_server, executor, |_| {}).await
+    }
+
+    /// Builds the app state, letting the test change it first, such as to
+    /// bill through a `FakePaymentProvider`.
+    pub async fn build_app_state_with(
+        test_db: &TestDb,
+        live_kit_test_server: &live_kit_client::TestServer,
+        executor: Executor,
+        configure: impl FnOnce(&mut AppState),
+    ) -> Arc<AppState> {
+        let config = Config {
+            http_port: 0,
+            database_url: "".into(),
+            database_max_connections: 0,
+            api_token: "".into(),
+            invite_link_prefix: "".into(),
+            live_kit_server: None,
+            live_kit_key: None,
+            live_kit_secret: None,
+            rust_log: None,
+            log_json: None,
+            zed_environment: "test".into(),
+            blob_store_url: None,
+            blob_store_region: None,
+            blob_store_access_key: None,
+            blob_store_secret_key: None,
+            blob_store_bucket: None,
+            openai_api_key: None,
+            google_ai_api_key: None,
+            anthropic_api_key: None,
+            anthropic_api_url: None,
+            clickhouse_url: None,
+            clickhouse_user: None,
+            clickhouse_password: None,
+            clickhouse_database: None,
+            zed_client_checksum_seed: None,
+            slack_panics_webhook: None,
+            auto_join_channel_id: None,
+            migrations_path: None,
+            seed_path: None,
+            stripe_api_key: None,
+            stripe_api_url: None,
+            stripe_price_id: None,
+            stripe_plan_price_ids: None,
+            stripe_connect_accounts: None,
+            stripe_referral_coupon_id: None,
+            stripe_referral_credit_in_cents: None,
+            stripe_trial_period_days: None,
+            stripe_top_up_price_id: None,
+            top_up_tokens: None,
+            stripe_metered_price_id: None,
+            metered_price_per_million_tokens_in_cents: None,
+            stripe_automatic_tax: None,
+            stripe_webhook_secret: None,
+            stripe_connect_webhook_secret: None,
+            billing_success_url: None,
+            billing_return_url: None,
+            stripe_billing_portal_configuration_id: None,
+            billing_redirect_origins: None,
+            billing_encryption_keys: None,
+            billing_mode: None,
+            billing_mock_plan: None,
+            enable_sandbox_organizations: None,
+            enable_stripe_test_clocks: None,
+            github_sponsors_token: None,
+            github_sponsors_login: None,
+            github_sponsors_plans: None,
+            throttle_usage_anomalies: None,
+            enforce_plan_limits: None,
+            terms_of_service_version: None,
+            pricing_terms_version: None,
+            postmark_server_token: None,
+            postmark_webhook_credentials: None,
+            email_from_address: None,
+            exchange_rates_url: None,
+            supermaven_admin_api_key: None,
+        };
+        let mut app_state = AppState {
             db: test_db.db().clone(),
             live_kit_client: Some(Arc::new(live_kit_test_server.create_api_client())),
             blob_store_client: None,
             stripe_client: None,
+            payment_provider: None,
+            email_client: None,
+            exchange_rate_client: None,
+            plan_prices: Default::default(),
             rate_limiter: Arc::new(RateLimiter::new(test_db.db().clone())),
+            entitlements: Arc::new(EntitlementsService::new(
+                test_db.db().clone(),
+                config.billing_mode(),
+            )),
+            server_settings: Arc::new(ServerSettingsStore::new(test_db.db().clone())),
+            distributed_lock: Arc::new(InMemoryLock::default()),
+            shutdown: Default::default(),
             executor,
             clickhouse_client: None,
-            config: Config {
-                http_port: 0,
-                database_url: "".into(),
-                database_max_connections: 0,
-                api_token: "".into(),
-                invite_link_prefix: "".into(),
-                live_kit_server: None,
-                live_kit_key: None,
-                live_kit_secret: None,
-                rust_log: None,
-                log_json: None,
-                zed_environment: "test".into(),
-                blob_store_url: None,
-                blob_store_region: None,
-                blob_store_access_key: None,
-                blob_store_secret_key: None,
-                blob_store_bucket: None,
-                openai_api_key: None,
-                google_ai_api_key: None,
-                anthropic_api_key: None,
-                clickhouse_url: None,
-                clickhouse_user: None,
-                clickhouse_password: None,
-                clickhouse_database: None,
-                zed_client_checksum_seed: None,
-                slack_panics_webhook: None,
-                auto_join_channel_id: None,
-                migrations_path: None,
-                seed_path: None,
-                stripe_api_key: None,
-                stripe_price_id: None,
-                supermaven_admin_api_key: None,
-            },
-        })
+            config,
+        };
+        configure(&mut app_state);
+        Arc::new(app_state)
     }
 }
 
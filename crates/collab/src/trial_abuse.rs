@@ -0,0 +1,335 @@
+use collections::BTreeSet;
+use time::OffsetDateTime;
+
+use crate::db::trial_abuse_flag::TrialAbuseReason;
+use crate::db::{billing_customer, PastTrial, User, UserId};
+use crate::AppState;
+
+/// Email providers with too many users for trials at them to say anything
+/// about each other.
+const COMMON_EMAIL_DOMAINS: &[&str] = &[
+    "163.com",
+    "gmail.com",
+    "googlemail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "me.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "qq.com",
+    "yahoo.com",
+];
+
+/// Email providers that ignore dots in the part of an address before the `@`.
+const DOT_INSENSITIVE_EMAIL_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+/// How far back trials at a user's email domain are counted.
+const EMAIL_DOMAIN_WINDOW: time::Duration = time::Duration::days(30);
+
+/// The number of other users at an email domain who must have started trials
+/// within the window for the domain to be suspicious.
+const EMAIL_DOMAIN_TRIAL_THRESHOLD: usize = 3;
+
+/// What a user was found to have in common with users who've had trials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrialAbuseSignal {
+    pub reason: TrialAbuseReason,
+    pub details: String,
+}
+
+/// Returns whether the user may be offered a free trial.
+///
+/// Users with flags that await review, or that staff have upheld, may not.
+/// Others are checked against the users who've had trials at their email
+/// domain, and flagged if they look to be one of them.
+pub async fn may_offer_trial(app: &AppState, user: &User) -> anyhow::Result<bool> {
+    let flags = app.db.get_trial_abuse_flags(user.id).await?;
+    if flags.iter().any(|flag| flag.blocks_trials()) {
+        return Ok(false);
+    }
+
+    let Some(email_address) = user.email_address.as_deref() else {
+        return Ok(true);
+    };
+    let Some((_, domain)) = canonical_email_address(email_address) else {
+        return Ok(true);
+    };
+    let past_trials = app.db.get_past_trials_by_email_domain(&domain).await?;
+    let Some(signal) = email_address_signal(
+        user.id,
+        email_address,
+        &past_trials,
+        OffsetDateTime::now_utc(),
+    ) else {
+        return Ok(true);
+    };
+    if flags.iter().any(|flag| flag.reason == signal.reason) {
+        return Ok(true);
+    }
+
+    flag_user(app, user.id, signal).await?;
+    Ok(false)
+}
+
+/// Records the fingerprint of the card the customer started their trial
+/// with, and flags them if other users have had trials with the same card.
+pub async fn check_trial_payment_method(
+    app: &AppState,
+    billing_customer: &billing_customer::Model,
+    payment_method_id: &str,
+) -> anyhow::Result<()> {
+    let Some(payment_provider) = app.payment_provider.as_ref() else {
+        return Ok(());
+    };
+    let Some(fingerprint) = payment_provider
+        .for_account(billing_customer.stripe_account_id.as_deref())?
+        .get_payment_method_fingerprint(payment_method_id)
+        .await?
+    else {
+        return Ok(());
+    };
+    app.db
+        .update_billing_customer_payment_method_fingerprint(billing_customer.id, &fingerprint)
+        .await?;
+
+    let past_trials = app
+        .db
+        .get_past_trials_by_payment_method_fingerprint(&fingerprint)
+        .await?;
+    let Some(signal) = payment_method_signal(billing_customer.user_id, &past_trials) else {
+        return Ok(());
+    };
+    let flags = app
+        .db
+        .get_trial_abuse_flags(billing_customer.user_id)
+        .await?;
+    if flags.iter().any(|flag| flag.reason == signal.reason) {
+        return Ok(());
+    }
+
+    flag_user(app, billing_customer.user_id, signal).await
+}
+
+async fn flag_user(
+    app: &AppState,
+    user_id: UserId,
+    signal: TrialAbuseSignal,
+) -> anyhow::Result<()> {
+    log::warn!(
+        "flagged user {user_id} for trial abuse ({:?}): {}",
+        signal.reason,
+        signal.details
+    );
+    app.db
+        .create_trial_abuse_flag(user_id, signal.reason, &signal.details)
+        .await?;
+    Ok(())
+}
+
+/// Returns the address that the email address is an alias of, along with
+/// its domain, both in lowercase.
+///
+/// Aliases differ only in a `+` suffix before the `@`, or for some
+/// providers, in their dots.
+fn canonical_email_address(email_address: &str) -> Option<(String, String)> {
+    let (local_part, domain) = email_address.trim().rsplit_once('@')?;
+    let domain = domain.to_lowercase();
+    let mut local_part = local_part.to_lowercase();
+    if let Some(plus_ix) = local_part.find('+') {
+        local_part.truncate(plus_ix);
+    }
+    if DOT_INSENSITIVE_EMAIL_DOMAINS.contains(&domain.as_str()) {
+        local_part.retain(|c| c != '.');
+    }
+    if local_part.is_empty() || domain.is_empty() {
+        return None;
+    }
+    Some((format!("{local_part}@{domain}"), domain))
+}
+
+/// Checks the user's email address against those of the other users at its
+/// domain who've had trials.
+fn email_address_signal(
+    user_id: UserId,
+    email_address: &str,
+    past_trials: &[PastTrial],
+    now: OffsetDateTime,
+) -> Option<TrialAbuseSignal> {
+    let (address, domain) = canonical_email_address(email_address)?;
+    let other_trials = || past_trials.iter().filter(|trial| trial.user_id != user_id);
+
+    let alias_user_ids = other_trials()
+        .filter(|trial| {
+            trial
+                .email_address
+                .as_deref()
+                .and_then(canonical_email_address)
+                .map_or(false, |(other_address, _)| other_address == address)
+        })
+        .map(|trial| trial.user_id)
+        .collect::<BTreeSet<_>>();
+    if !alias_user_ids.is_empty() {
+        return Some(TrialAbuseSignal {
+            reason: TrialAbuseReason::SharedEmailAddress,
+            details: format!(
+                "{email_address} is an alias of the address of {}, who have had trials",
+                users(&alias_user_ids)
+            ),
+        });
+    }
+
+    if COMMON_EMAIL_DOMAINS.contains(&domain.as_str()) {
+        return None;
+    }
+    let since = now - EMAIL_DOMAIN_WINDOW;
+    let recent_user_ids = other_trials()
+        .filter(|trial| trial.started_at.assume_utc() >= since)
+        .map(|trial| trial.user_id)
+        .collect::<BTreeSet<_>>();
+    if recent_user_ids.len() < EMAIL_DOMAIN_TRIAL_THRESHOLD {
+        return None;
+    }
+    Some(TrialAbuseSignal {
+        reason: TrialAbuseReason::EmailDomain,
+        details: format!(
+            "{} other users at {domain} have started trials in the last {} days: {}",
+            recent_user_ids.len(),
+            EMAIL_DOMAIN_WINDOW.whole_days(),
+            users(&recent_user_ids)
+        ),
+    })
+}
+
+/// Checks whether other users have had trials with the card the user
+/// started their trial with.
+fn payment_method_signal(user_id: UserId, past_trials: &[PastTrial]) -> Option<TrialAbuseSignal> {
+    let other_user_ids = past_trials
+        .iter()
+        .filter(|trial| trial.user_id != user_id)
+        .map(|trial| trial.user_id)
+        .collect::<BTreeSet<_>>();
+    if other_user_ids.is_empty() {
+        return None;
+    }
+    Some(TrialAbuseSignal {
+        reason: TrialAbuseReason::SharedPaymentMethod,
+        details: format!(
+            "the trial was started with a card that {} have also had trials with",
+            users(&other_user_ids)
+        ),
+    })
+}
+
+fn users(user_ids: &BTreeSet<UserId>) -> String {
+    let user_ids = user_ids
+        .iter()
+        .map(|user_id| user_id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("users {user_ids}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+    use time::PrimitiveDateTime;
+
+    fn past_trial(user_id: i32, email_address: &str, started_at: PrimitiveDateTime) -> PastTrial {
+        PastTrial {
+            user_id: UserId(user_id),
+            email_address: Some(email_address.into()),
+            started_at,
+        }
+    }
+
+    #[test]
+    fn test_canonical_email_address() {
+        assert_eq!(
+            canonical_email_address("J.Doe+trial2@Gmail.com"),
+            Some(("jdoe@gmail.com".into(), "gmail.com".into()))
+        );
+        assert_eq!(
+            canonical_email_address("j.doe+trial2@example.com"),
+            Some(("j.doe@example.com".into(), "example.com".into()))
+        );
+        assert_eq!(canonical_email_address("+trial@example.com"), None);
+        assert_eq!(canonical_email_address("not an address"), None);
+    }
+
+    #[test]
+    fn test_email_address_signal() {
+        let now = datetime!(2024-09-08 0:00 UTC);
+        let user_id = UserId(100);
+
+        let past_trials = [past_trial(1, "jdoe@gmail.com", datetime!(2024-01-01 0:00))];
+        let signal = email_address_signal(user_id, "j.doe+2@gmail.com", &past_trials, now).unwrap();
+        assert_eq!(signal.reason, TrialAbuseReason::SharedEmailAddress);
+        assert!(signal.details.contains("users 1"));
+        assert_eq!(
+            email_address_signal(user_id, "someone.else@gmail.com", &past_trials, now),
+            None
+        );
+        // Users aren't flagged for their own trials.
+        assert_eq!(
+            email_address_signal(UserId(1), "jdoe@gmail.com", &past_trials, now),
+            None
+        );
+
+        let past_trials = [
+            past_trial(1, "a@example.com", datetime!(2024-09-01 0:00)),
+            past_trial(2, "b@example.com", datetime!(2024-09-02 0:00)),
+            past_trial(2, "b@example.com", datetime!(2024-09-03 0:00)),
+            past_trial(3, "c@example.com", datetime!(2024-07-01 0:00)),
+        ];
+        assert_eq!(
+            email_address_signal(user_id, "d@example.com", &past_trials, now),
+            None
+        );
+        let past_trials = [
+            past_trials.as_slice(),
+            &[past_trial(4, "e@example.com", datetime!(2024-09-04 0:00))],
+        ]
+        .concat();
+        let signal = email_address_signal(user_id, "d@example.com", &past_trials, now).unwrap();
+        assert_eq!(signal.reason, TrialAbuseReason::EmailDomain);
+        assert!(signal.details.contains("users 1, 2, 4"));
+
+        // Trials at common providers say nothing about each other.
+        let past_trials = past_trials
+            .iter()
+            .map(|trial| PastTrial {
+                email_address: trial
+                    .email_address
+                    .as_ref()
+                    .map(|address| address.replace("example.com", "gmail.com")),
+                ..trial.clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            email_address_signal(user_id, "d@gmail.com", &past_trials, now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_payment_method_signal() {
+        let started_at = datetime!(2024-09-01 0:00);
+        assert_eq!(
+            payment_method_signal(UserId(1), &[past_trial(1, "a@example.com", started_at)]),
+            None
+        );
+        let signal = payment_method_signal(
+            UserId(1),
+            &[
+                past_trial(1, "a@example.com", started_at),
+                past_trial(2, "b@example.com", started_at),
+            ],
+        )
+        .unwrap();
+        assert_eq!(signal.reason, TrialAbuseReason::SharedPaymentMethod);
+        assert!(signal.details.contains("users 2"));
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use util::ResultExt;
+
+use crate::distributed_lock::run_exclusively;
+use crate::AppState;
+
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How far back the usage of organizations that were never rolled up is
+/// rolled up from.
+const MAX_BACKFILL_DAYS: i64 = 31;
+
+/// Periodically rolls up the usage of every organization's members.
+pub fn roll_up_organization_usage_periodically(app: Arc<AppState>) {
+    let executor = app.executor.clone();
+    executor.spawn_detached({
+        let executor = executor.clone();
+        async move {
+            loop {
+                let Some(result) = app
+                    .shutdown
+                    .run(run_exclusively(
+                        app.distributed_lock.as_ref(),
+                        "roll_up_organization_usage",
+                        roll_up_organization_usage(&app),
+                    ))
+                    .await
+                else {
+                    break;
+                };
+                result.log_err();
+
+                app.shutdown.sleep(&executor, ROLLUP_INTERVAL).await;
+            }
+        }
+    });
+}
+
+async fn roll_up_organization_usage(app: &AppState) -> anyhow::Result<()> {
+    let today = OffsetDateTime::now_utc().date();
+    let earliest = today - time::Duration::days(MAX_BACKFILL_DAYS);
+
+    for organization in app.db.get_organizations().await? {
+        let mut date = organization
+            .usage_rolled_up_through
+            .map_or(earliest, |date| date.max(earliest));
+        while date <= today {
+            if app.shutdown.is_shutting_down() {
+                return Ok(());
+            }
+
+            app.db
+                .roll_up_organization_member_daily_usages(organization.id, date)
+                .await?;
+            app.db
+                .set_organization_usage_rolled_up_through(organization.id, date)
+                .await?;
+            date = date + time::Duration::days(1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// The plan a billing subscription is for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionPlan {
+    Free,
+    #[default]
+    Pro,
+    Team,
+}
+
+/// The status of a billing subscription, as reported by Stripe.
+///
+/// [Stripe docs](https://docs.stripe.com/api/subscriptions/object#subscription_object-status)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Incomplete,
+    IncompleteExpired,
+    Trialing,
+    Active,
+    PastDue,
+    Canceled,
+    Unpaid,
+    Paused,
+}
+
+/// A user's billing subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillingSubscription {
+    pub id: i32,
+    pub status: SubscriptionStatus,
+    pub plan: SubscriptionPlan,
+    pub seat_count: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub trial_end: Option<OffsetDateTime>,
+    /// When the current billing period ends.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub current_period_end: Option<OffsetDateTime>,
+    /// Whether the subscription ends with the current period, rather than
+    /// renewing.
+    pub cancel_at_period_end: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub canceled_at: Option<OffsetDateTime>,
+    /// When a subscription that's been canceled at the end of its period
+    /// stops granting access.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub access_until: Option<OffsetDateTime>,
+    /// When a past-due subscription stops granting access, unless it's paid
+    /// before then.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub grace_period_ends_at: Option<OffsetDateTime>,
+    /// How many seconds are left in the grace period of a past-due
+    /// subscription.
+    pub grace_period_remaining_seconds: Option<i64>,
+    /// When an unpaid subscription stopped granting access, after we failed
+    /// to collect payment for it. It's reinstated once it's paid for.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub suspended_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListBillingSubscriptionsParams {
+    pub github_user_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListBillingSubscriptionsResponse {
+    pub subscriptions: Vec<BillingSubscription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateBillingSubscriptionBody {
+    pub github_user_id: i32,
+    /// The plan to subscribe to. Defaults to the Pro plan.
+    #[serde(default)]
+    pub plan: SubscriptionPlan,
+    /// The number of seats to pay for. Only team subscriptions can have more
+    /// than one seat.
+    pub seat_count: Option<u32>,
+    /// A promotion code to apply at checkout, as entered by the user.
+    pub promo_code: Option<String>,
+    /// The currency to pay in, as an ISO 4217 code (e.g. "eur"), if the plan
+    /// is priced in it. Defaults to USD.
+    pub currency: Option<String>,
+    /// The user's country, as an ISO 3166-1 alpha-2 code (e.g. "DE"), for
+    /// picking its local currency when no currency is given.
+    pub country: Option<String>,
+    /// The locale to show checkout in, as set in the user's editor. Defaults
+    /// to the locale last given for the user.
+    pub locale: Option<String>,
+    /// The page to send the user to after checkout, which must belong to one
+    /// of the allowed origins. Defaults to the configured success URL.
+    pub success_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateBillingSubscriptionResponse {
+    pub checkout_session_url: String,
+}
+
+/// What a user intends to do with their subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManageSubscriptionIntent {
+    /// The user intends to cancel their subscription through the billing
+    /// portal, which cancels it as configured for the portal.
+    Cancel,
+    /// The user intends to end their subscription right away.
+    CancelImmediately,
+    /// The user intends to keep their subscription until the end of the
+    /// current billing period, and not renew it.
+    CancelAtPeriodEnd,
+    /// The user changed their mind about canceling their subscription.
+    Resume,
+    /// The user intends to switch to a more expensive plan.
+    Upgrade,
+    /// The user intends to switch to a cheaper plan.
+    Downgrade,
+    /// The user intends to change the payment method their subscriptions are
+    /// charged to, such as when their card is about to expire.
+    UpdatePaymentMethod,
+    /// The user intends to browse the billing portal, where they can view
+    /// their invoices, update their billing details, and manage their
+    /// subscriptions in any way the portal supports.
+    Portal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManageBillingSubscriptionBody {
+    pub github_user_id: i32,
+    pub intent: ManageSubscriptionIntent,
+    /// The plan to switch to, when upgrading or downgrading.
+    pub plan: Option<SubscriptionPlan>,
+    /// The ID of the subscription to manage.
+    ///
+    /// If not provided, we will try to use the active subscription (if there is only one).
+    /// Not needed when updating the payment method, which applies to all of
+    /// the user's subscriptions.
+    pub subscription_id: Option<i32>,
+    /// The locale to show the portal in, as set in the user's editor.
+    /// Defaults to the locale last given for the user.
+    pub locale: Option<String>,
+    /// The page to send the user back to from the portal, which must belong
+    /// to one of the allowed origins. Defaults to the configured return URL.
+    pub return_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManageBillingSubscriptionResponse {
+    /// The portal session to send the user to, for intents completed there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billing_portal_session_url: Option<String>,
+    /// The Checkout session to send the user to, when resuming a
+    /// subscription that has already ended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkout_session_url: Option<String>,
+    /// The subscription as updated, for intents carried out right away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription: Option<BillingSubscription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateBillingSubscriptionSeatsBody {
+    pub github_user_id: i32,
+    /// The ID of the team subscription to update.
+    ///
+    /// If not provided, we will try to use the active subscription (if there is only one).
+    pub subscription_id: Option<i32>,
+    /// The number of seats to add or remove.
+    pub seats: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateBillingSubscriptionSeatsResponse {
+    pub seat_count: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateBillingEmailBody {
+    pub github_user_id: i32,
+    /// The address to send receipts and invoices to, such as a finance team's.
+    /// `None` sends them to the user's own email address again.
+    pub billing_email: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateBillingEmailResponse {
+    pub billing_email: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateLanguageModelTopUpBody {
+    pub github_user_id: i32,
+    /// The locale to show checkout in, as set in the user's editor. Defaults
+    /// to the locale last given for the user.
+    pub locale: Option<String>,
+    /// The page to send the user to after checkout, which must belong to one
+    /// of the allowed origins. Defaults to the configured success URL.
+    pub success_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateLanguageModelTopUpResponse {
+    pub checkout_session_url: String,
+    /// The number of tokens the top-up adds once it's paid for.
+    pub tokens: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetLanguageModelUsageParams {
+    pub github_user_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetLanguageModelUsageResponse {
+    pub plan: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub period_start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub period_end: OffsetDateTime,
+    /// The tokens included in the plan for the period.
+    pub plan_tokens: u64,
+    /// The tokens the user topped up for the period.
+    pub top_up_tokens: u64,
+    pub used_tokens: u64,
+    pub remaining_tokens: u64,
+    /// How many of the topped-up tokens are left, as the plan's tokens are
+    /// used first.
+    pub remaining_top_up_tokens: u64,
+}
+
+/// Where a currency's symbol goes relative to an amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrencySymbolPosition {
+    Before,
+    After,
+}
+
+/// How to format amounts of a currency, so that every client formats them
+/// the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrencyFormat {
+    /// The ISO 4217 code of the currency, in uppercase.
+    pub code: String,
+    /// The symbol to show amounts with, which is the code for currencies
+    /// without a well-known symbol.
+    pub symbol: String,
+    pub symbol_position: CurrencySymbolPosition,
+    /// The number of decimal places amounts have, which amounts in the
+    /// currency's smallest unit are divided by 10 to the power of.
+    pub minor_units: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListBillingPlansParams {
+    /// The locale to format prices for. Defaults to English.
+    pub locale: Option<String>,
+}
+
+/// A price of a plan, as configured in Stripe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillingPlanPrice {
+    pub plan: SubscriptionPlan,
+    /// The price per seat per billing period, in the currency's smallest
+    /// unit.
+    pub unit_amount: i64,
+    pub currency: CurrencyFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListBillingPlansResponse {
+    pub prices: Vec<BillingPlanPrice>,
+}
@@ -0,0 +1,376 @@
+mod billing;
+mod sandbox;
+
+pub use billing::*;
+pub use sandbox::*;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub struct CollabApiClient {
+    api_url: String,
+    api_token: String,
+    http_client: Arc<dyn HttpClient>,
+}
+
+impl CollabApiClient {
+    /// Returns a client for the API at `api_url`, such as
+    /// `https://collab.zed.dev/api`, authenticated with `api_token`.
+    pub fn new(api_url: String, api_token: String, http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_token,
+            http_client,
+        }
+    }
+
+    pub async fn list_billing_subscriptions(
+        &self,
+        params: &ListBillingSubscriptionsParams,
+    ) -> Result<ListBillingSubscriptionsResponse> {
+        self.get(&format!(
+            "/billing/subscriptions?github_user_id={}",
+            params.github_user_id
+        ))
+        .await
+    }
+
+    pub async fn create_billing_subscription(
+        &self,
+        body: &CreateBillingSubscriptionBody,
+    ) -> Result<CreateBillingSubscriptionResponse> {
+        self.send(Method::POST, "/billing/subscriptions", body)
+            .await
+    }
+
+    pub async fn manage_billing_subscription(
+        &self,
+        body: &ManageBillingSubscriptionBody,
+    ) -> Result<ManageBillingSubscriptionResponse> {
+        self.send(Method::POST, "/billing/subscriptions/manage", body)
+            .await
+    }
+
+    pub async fn add_billing_subscription_seats(
+        &self,
+        body: &UpdateBillingSubscriptionSeatsBody,
+    ) -> Result<UpdateBillingSubscriptionSeatsResponse> {
+        self.send(Method::POST, "/billing/subscriptions/seats/add", body)
+            .await
+    }
+
+    pub async fn remove_billing_subscription_seats(
+        &self,
+        body: &UpdateBillingSubscriptionSeatsBody,
+    ) -> Result<UpdateBillingSubscriptionSeatsResponse> {
+        self.send(Method::POST, "/billing/subscriptions/seats/remove", body)
+            .await
+    }
+
+    pub async fn update_billing_email(
+        &self,
+        body: &UpdateBillingEmailBody,
+    ) -> Result<UpdateBillingEmailResponse> {
+        self.send(Method::PUT, "/billing/email", body).await
+    }
+
+    pub async fn create_language_model_top_up(
+        &self,
+        body: &CreateLanguageModelTopUpBody,
+    ) -> Result<CreateLanguageModelTopUpResponse> {
+        self.send(Method::POST, "/billing/top_ups", body).await
+    }
+
+    pub async fn get_language_model_usage(
+        &self,
+        params: &GetLanguageModelUsageParams,
+    ) -> Result<GetLanguageModelUsageResponse> {
+        self.get(&format!(
+            "/billing/usage?github_user_id={}",
+            params.github_user_id
+        ))
+        .await
+    }
+
+    pub async fn list_billing_plans(
+        &self,
+        params: &ListBillingPlansParams,
+    ) -> Result<ListBillingPlansResponse> {
+        match &params.locale {
+            Some(locale) => {
+                let locale =
+                    url::form_urlencoded::byte_serialize(locale.as_bytes()).collect::<String>();
+                self.get(&format!("/billing/plans?locale={locale}")).await
+            }
+            None => self.get("/billing/plans").await,
+        }
+    }
+
+    /// Provisions an organization and users to run end-to-end billing tests
+    /// against. Only available on servers using a test-mode Stripe account.
+    pub async fn create_sandbox_organization(
+        &self,
+        body: &CreateSandboxOrganizationBody,
+    ) -> Result<CreateSandboxOrganizationResponse> {
+        self.send(Method::POST, "/admin/sandbox_orgs", body).await
+    }
+
+    pub async fn record_sandbox_usage(
+        &self,
+        organization_id: i32,
+        body: &RecordSandboxUsageBody,
+    ) -> Result<RecordSandboxUsageResponse> {
+        self.send(
+            Method::POST,
+            &format!("/admin/sandbox_orgs/{organization_id}/usage"),
+            body,
+        )
+        .await
+    }
+
+    /// Tears down a sandbox organization, along with its users and their
+    /// Stripe customers.
+    pub async fn delete_sandbox_organization(
+        &self,
+        organization_id: i32,
+        params: &DeleteSandboxOrganizationParams,
+    ) -> Result<DeleteSandboxOrganizationResponse> {
+        self.execute(
+            Method::DELETE,
+            &format!(
+                "/admin/sandbox_orgs/{organization_id}?github_user_id={}",
+                params.github_user_id
+            ),
+            None,
+        )
+        .await
+    }
+
+    async fn get<R: DeserializeOwned>(&self, path: &str) -> Result<R> {
+        self.execute(Method::GET, path, None).await
+    }
+
+    async fn send<B: Serialize, R: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+    ) -> Result<R> {
+        self.execute(method, path, Some(serde_json::to_vec(body)?))
+            .await
+    }
+
+    async fn execute<R: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<R> {
+        let mut request = HttpRequest::builder()
+            .method(method)
+            .uri(format!("{}{}", self.api_url, path))
+            .header("Authorization", format!("token {}", self.api_token));
+        if body.is_some() {
+            request = request.header("Content-Type", "application/json");
+        }
+        let request = request.body(body.map_or_else(AsyncBody::default, AsyncBody::from))?;
+
+        let mut response = self
+            .http_client
+            .send(request)
+            .await
+            .with_context(|| format!("failed to send request to {path}"))?;
+
+        let mut body = Vec::new();
+        response.body_mut().read_to_end(&mut body).await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "collab API error {}: {}",
+                response.status(),
+                String::from_utf8_lossy(&body)
+            ));
+        }
+
+        serde_json::from_slice(&body)
+            .with_context(|| format!("failed to parse response from {path}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use http_client::{FakeHttpClient, Response};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_requests() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let http_client = FakeHttpClient::create({
+            let requests = requests.clone();
+            move |mut request| {
+                let requests = requests.clone();
+                async move {
+                    let mut body = String::new();
+                    request.body_mut().read_to_string(&mut body).await.unwrap();
+                    requests.lock().unwrap().push((
+                        request.method().to_string(),
+                        request.uri().to_string(),
+                        request.headers()["Authorization"]
+                            .to_str()
+                            .unwrap()
+                            .to_string(),
+                        body,
+                    ));
+
+                    let (status, response): (u16, &str) = match request.uri().path() {
+                        "/api/billing/email" => (200, r#"{"billing_email":"finance@example.com"}"#),
+                        "/api/billing/subscriptions" => (200, r#"{"subscriptions":[]}"#),
+                        "/api/billing/plans" => (200, r#"{"prices":[]}"#),
+                        _ => (404, "not found"),
+                    };
+                    Ok(Response::builder()
+                        .status(status)
+                        .body(response.into())
+                        .unwrap())
+                }
+            }
+        });
+        let client = CollabApiClient::new(
+            "https://collab.example.com/api/".into(),
+            "secret".into(),
+            http_client,
+        );
+
+        let response = block_on(
+            client
+                .list_billing_subscriptions(&ListBillingSubscriptionsParams { github_user_id: 1 }),
+        )
+        .unwrap();
+        assert!(response.subscriptions.is_empty());
+
+        let response = block_on(client.update_billing_email(&UpdateBillingEmailBody {
+            github_user_id: 1,
+            billing_email: Some("finance@example.com".into()),
+        }))
+        .unwrap();
+        assert_eq!(
+            response.billing_email.as_deref(),
+            Some("finance@example.com")
+        );
+
+        let error = block_on(
+            client.get_language_model_usage(&GetLanguageModelUsageParams { github_user_id: 1 }),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("404"));
+
+        let response = block_on(client.list_billing_plans(&ListBillingPlansParams {
+            locale: Some("fr-CA&x=1".into()),
+        }))
+        .unwrap();
+        assert!(response.prices.is_empty());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .map(|(method, uri, _, body)| (method.as_str(), uri.as_str(), body.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "GET",
+                    "https://collab.example.com/api/billing/subscriptions?github_user_id=1",
+                    ""
+                ),
+                (
+                    "PUT",
+                    "https://collab.example.com/api/billing/email",
+                    r#"{"github_user_id":1,"billing_email":"finance@example.com"}"#
+                ),
+                (
+                    "GET",
+                    "https://collab.example.com/api/billing/usage?github_user_id=1",
+                    ""
+                ),
+                (
+                    "GET",
+                    "https://collab.example.com/api/billing/plans?locale=fr-CA%26x%3D1",
+                    ""
+                ),
+            ]
+        );
+        assert!(requests
+            .iter()
+            .all(|(_, _, authorization, _)| authorization == "token secret"));
+    }
+
+    #[test]
+    fn test_manage_response_omits_missing_sessions() {
+        let response: ManageBillingSubscriptionResponse =
+            serde_json::from_str(r#"{"billing_portal_session_url":"https://portal"}"#).unwrap();
+        assert_eq!(
+            response.billing_portal_session_url.as_deref(),
+            Some("https://portal")
+        );
+        assert_eq!(response.checkout_session_url, None);
+        assert_eq!(response.subscription, None);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"billing_portal_session_url":"https://portal"}"#
+        );
+    }
+
+    #[test]
+    fn test_plan_prices_include_currency_format() {
+        let response: ListBillingPlansResponse = serde_json::from_str(
+            r#"{"prices":[{"plan":"pro","unit_amount":2000,"currency":{"code":"EUR","symbol":"€","symbol_position":"after","minor_units":2}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response.prices,
+            [BillingPlanPrice {
+                plan: SubscriptionPlan::Pro,
+                unit_amount: 2000,
+                currency: CurrencyFormat {
+                    code: "EUR".into(),
+                    symbol: "€".into(),
+                    symbol_position: CurrencySymbolPosition::After,
+                    minor_units: 2,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_delete_sandbox_organization() {
+        let http_client = FakeHttpClient::create(|request| async move {
+            assert_eq!(request.method().as_str(), "DELETE");
+            assert_eq!(
+                request.uri().to_string(),
+                "https://collab.example.com/api/admin/sandbox_orgs/7?github_user_id=1"
+            );
+            Ok(Response::builder()
+                .status(200)
+                .body(r#"{"deleted_user_count":2}"#.into())
+                .unwrap())
+        });
+        let client = CollabApiClient::new(
+            "https://collab.example.com/api".into(),
+            "secret".into(),
+            http_client,
+        );
+
+        let response = block_on(client.delete_sandbox_organization(
+            7,
+            &DeleteSandboxOrganizationParams { github_user_id: 1 },
+        ))
+        .unwrap();
+        assert_eq!(response.deleted_user_count, 2);
+    }
+}
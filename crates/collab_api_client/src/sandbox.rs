@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateSandboxOrganizationBody {
+    /// The GitHub user ID of the admin provisioning the organization.
+    pub github_user_id: i32,
+    /// How many users to create in the organization, the first of which is
+    /// its admin and has a Stripe customer with a test card.
+    pub member_count: u32,
+    /// How long until the organization is torn down, should the test that
+    /// provisioned it not do so itself. Defaults to an hour.
+    pub expires_in_minutes: Option<u32>,
+}
+
+/// A user created for a sandbox organization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxUser {
+    pub user_id: i32,
+    pub github_user_id: i32,
+    pub github_login: String,
+    /// The user's customer in the test-mode Stripe account, if they have one.
+    pub stripe_customer_id: Option<String>,
+    /// The test clock the user's customer is attached to, if test clocks are
+    /// enabled, which tests can advance to simulate the passage of time.
+    pub stripe_test_clock_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateSandboxOrganizationResponse {
+    pub organization_id: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+    /// The organization's users, its admin first.
+    pub users: Vec<SandboxUser>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordSandboxUsageBody {
+    /// The GitHub user ID of the admin recording the usage.
+    pub github_user_id: i32,
+    /// The GitHub user ID of the sandbox user the usage is recorded for.
+    pub member_github_user_id: i32,
+    /// The number of language model tokens to record.
+    pub tokens: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordSandboxUsageResponse {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteSandboxOrganizationParams {
+    /// The GitHub user ID of the admin tearing down the organization.
+    pub github_user_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteSandboxOrganizationResponse {
+    pub deleted_user_count: usize,
+}
@@ -1584,10 +1584,12 @@ impl CollabPanel {
                         .detach_and_prompt_err(
                             "Failed to create channel",
                             cx,
-                            |_, _| None,
+                            |e, _| plan_upgrade_required_message(e),
                         );
                     } else {
-                        create.detach_and_prompt_err("Failed to create channel", cx, |_, _| None);
+                        create.detach_and_prompt_err("Failed to create channel", cx, |e, _| {
+                            plan_upgrade_required_message(e)
+                        });
                     }
                     cx.notify();
                 }
@@ -2001,7 +2003,7 @@ impl CollabPanel {
             .update(cx, |call, cx| {
                 call.invite(recipient_user_id, Some(self.project.clone()), cx)
             })
-            .detach_and_prompt_err("Call failed", cx, |_, _| None);
+            .detach_and_prompt_err("Call failed", cx, |e, _| plan_upgrade_required_message(e));
     }
 
     fn join_channel(&self, channel_id: ChannelId, cx: &mut ViewContext<Self>) {
@@ -2719,6 +2721,20 @@ impl CollabPanel {
     }
 }
 
+/// Describes why the server refused an action because of the user's plan, so
+/// that they can be pointed at an upgrade.
+fn plan_upgrade_required_message(error: &anyhow::Error) -> Option<String> {
+    if error.error_code() != ErrorCode::PlanUpgradeRequired {
+        return None;
+    }
+
+    let feature = error.error_tag("feature")?.replace('_', " ");
+    let limit = error.error_tag("limit")?;
+    Some(format!(
+        "Your plan is limited to {limit} {feature}. Upgrade to Zed Pro at https://zed.dev/account to raise this limit."
+    ))
+}
+
 fn render_tree_branch(is_last: bool, overdraw: bool, cx: &mut WindowContext) -> impl IntoElement {
     let rem_size = cx.rem_size();
     let line_height = cx.text_style().line_height_in_pixels(rem_size);
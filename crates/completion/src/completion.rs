@@ -1,15 +1,36 @@
+pub mod eval;
+mod request_journal;
+
+pub use request_journal::*;
+
 use anyhow::{anyhow, Result};
 use futures::{future::BoxFuture, stream::BoxStream, StreamExt};
-use gpui::{AppContext, Global, Model, ModelContext, Task};
+use gpui::{AppContext, BackgroundExecutor, Global, Model, ModelContext, Task};
 use language_model::{
-    LanguageModel, LanguageModelProvider, LanguageModelProviderId, LanguageModelRegistry,
-    LanguageModelRequest, LanguageModelTool,
+    circuit_breaker::{CircuitBreakerSettings, CircuitBreakers},
+    moderation::Moderation,
+    org_vault::MissingApiKeyError,
+    output_limits, presets,
+    routing::ModelKey,
+    settings::AllLanguageModelSettings,
+    shared_rate_limit::SharedTokenBucket,
+    stream_metrics::StreamMetrics,
+    Citation, ContextAttachment, ContextBudget, LanguageModel, LanguageModelCompletionEvent,
+    LanguageModelProvider, LanguageModelProviderId, LanguageModelRegistry, LanguageModelRequest,
+    LanguageModelTool,
 };
+use settings::Settings;
 use smol::{
     future::FutureExt,
     lock::{Semaphore, SemaphoreGuardArc},
 };
-use std::{future, pin::Pin, sync::Arc, task::Poll};
+use std::{
+    future::{self, Future},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
 use ui::Context;
 
 pub fn init(cx: &mut AppContext) {
@@ -29,11 +50,23 @@ pub struct LanguageModelCompletionProvider {
 
 const MAX_CONCURRENT_COMPLETION_REQUESTS: usize = 4;
 
+/// How many times a request is sent before giving up on a model that doesn't
+/// start responding within its first token timeout.
+const MAX_FIRST_TOKEN_ATTEMPTS: usize = 2;
+
 pub struct LanguageModelCompletionResponse {
     inner: BoxStream<'static, Result<String>>,
+    citations: Arc<Mutex<Vec<Citation>>>,
     _lock: SemaphoreGuardArc,
 }
 
+impl LanguageModelCompletionResponse {
+    /// Returns the sources the model has cited in the output streamed so far.
+    pub fn citations(&self) -> Vec<Citation> {
+        self.citations.lock().unwrap().clone()
+    }
+}
+
 impl futures::Stream for LanguageModelCompletionResponse {
     type Item = Result<String>;
 
@@ -154,26 +187,209 @@ impl LanguageModelCompletionProvider {
         }
     }
 
+    /// Selects the attachments that fit in the active model's context window.
+    pub fn budget_context_attachments(
+        &self,
+        request: &LanguageModelRequest,
+        attachments: Vec<ContextAttachment>,
+        reserved_output_tokens: usize,
+        cx: &AppContext,
+    ) -> BoxFuture<'static, Result<ContextBudget>> {
+        if let Some(model) = self.active_model() {
+            language_model::budget_context_attachments(
+                model.as_ref(),
+                request,
+                attachments,
+                reserved_output_tokens,
+                cx,
+            )
+        } else {
+            future::ready(Err(anyhow!("no active model"))).boxed()
+        }
+    }
+
     pub fn stream_completion(
         &self,
         request: LanguageModelRequest,
         cx: &AppContext,
     ) -> Task<Result<LanguageModelCompletionResponse>> {
-        if let Some(language_model) = self.active_model() {
-            let rate_limiter = self.request_limiter.clone();
-            cx.spawn(|cx| async move {
-                let lock = rate_limiter.acquire_arc().await;
-                let response = language_model.stream_completion(request, &cx).await?;
-                Ok(LanguageModelCompletionResponse {
-                    inner: response,
-                    _lock: lock,
-                })
-            })
+        let registry = LanguageModelRegistry::read_global(cx);
+        let routed_model = request
+            .feature
+            .and_then(|feature| registry.model_for_feature(feature, cx));
+        if let Some(language_model) = routed_model.or_else(|| self.active_model()) {
+            self.stream_completion_with_model(language_model, request, cx)
         } else {
             Task::ready(Err(anyhow!("No active model set")))
         }
     }
 
+    /// Streams a completion from the given model rather than the active one.
+    pub fn stream_completion_with_model(
+        &self,
+        language_model: Arc<dyn LanguageModel>,
+        mut request: LanguageModelRequest,
+        cx: &AppContext,
+    ) -> Task<Result<LanguageModelCompletionResponse>> {
+        if let Err(error) = check_provider_allowed(language_model.as_ref(), cx) {
+            return Task::ready(Err(error));
+        }
+        presets::apply_preset(&mut request, language_model.as_ref(), cx);
+        if let Err(error) =
+            output_limits::clamp_max_output_tokens(&mut request, language_model.as_ref(), cx)
+        {
+            return Task::ready(Err(error));
+        }
+        let registry = LanguageModelRegistry::read_global(cx);
+        let circuit_breakers = registry.circuit_breakers();
+        let circuit_breaker_settings = AllLanguageModelSettings::get_global(cx).circuit_breaker;
+        if !circuit_breakers.lock().unwrap().try_acquire(
+            &language_model.provider_id(),
+            &circuit_breaker_settings,
+            Instant::now(),
+        ) {
+            return Task::ready(Err(provider_disabled_error(language_model.as_ref())));
+        }
+        let rate_limiter = self.request_limiter.clone();
+        let stream_metrics = registry.stream_metrics();
+        let first_token_timeout = registry
+            .provider(&language_model.provider_id())
+            .and_then(|provider| provider.first_token_timeout(cx));
+        let shared_rate_limit = SharedTokenBucket::for_provider(&language_model.provider_id(), cx);
+        let journal = AllLanguageModelSettings::get_global(cx)
+            .journal
+            .then(RequestJournal::global);
+        cx.spawn(|mut cx| async move {
+            let lock = rate_limiter.acquire_arc().await;
+            if let Some(shared_rate_limit) = shared_rate_limit {
+                shared_rate_limit.acquire(cx.background_executor()).await?;
+            }
+            let moderation =
+                Moderation::for_provider(&language_model.provider_id(), &mut cx).await?;
+            if let Some(moderation) = moderation.as_ref() {
+                moderation.check_prompt(&request).await?;
+            }
+
+            let model_key = (language_model.provider_id(), language_model.id());
+            let citations = Arc::new(Mutex::new(Vec::new()));
+            let mut attempt = 1;
+            let mut response = loop {
+                // A failed attempt may have disabled the provider, in which
+                // case it isn't retried.
+                if attempt > 1
+                    && !circuit_breakers.lock().unwrap().try_acquire(
+                        &model_key.0,
+                        &circuit_breaker_settings,
+                        Instant::now(),
+                    )
+                {
+                    return Err(provider_disabled_error(language_model.as_ref()));
+                }
+                let started_at = Instant::now();
+                citations.lock().unwrap().clear();
+                let response = async {
+                    let events = language_model
+                        .stream_completion_events(request.clone(), &cx)
+                        .await?;
+                    anyhow::Ok(record_stream_metrics(
+                        collect_citations(events, citations.clone()),
+                        model_key.clone(),
+                        started_at,
+                        stream_metrics.clone(),
+                        circuit_breakers.clone(),
+                        circuit_breaker_settings,
+                    ))
+                };
+                // The timeout also covers connecting to the provider. The
+                // request is dropped on timeout, which aborts it.
+                let response = match first_token_timeout {
+                    Some(first_token_timeout) => {
+                        wait_for_first_token(
+                            response,
+                            first_token_timeout,
+                            cx.background_executor(),
+                        )
+                        .await
+                    }
+                    None => Some(response.await),
+                };
+                let result = match response {
+                    Some(Ok(response)) => break response,
+                    Some(Err(error)) => Err(error),
+                    None if attempt < MAX_FIRST_TOKEN_ATTEMPTS => Ok(()),
+                    None => Err(anyhow!(
+                        "no response from {} within {:?}",
+                        language_model.name().0,
+                        first_token_timeout.unwrap_or_default()
+                    )),
+                };
+
+                stream_metrics
+                    .lock()
+                    .unwrap()
+                    .record_failure(model_key.clone());
+                if result.as_ref().err().map_or(true, is_provider_failure) {
+                    circuit_breakers.lock().unwrap().record_failure(
+                        &model_key.0,
+                        &circuit_breaker_settings,
+                        Instant::now(),
+                    );
+                }
+                result?;
+                attempt += 1;
+            };
+            if let Some(moderation) = moderation {
+                response = moderation.moderate_completion(response);
+            }
+            if let Some(journal) = journal {
+                let entry = JournalEntry {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    provider_id: language_model.provider_id().0.to_string(),
+                    model_id: language_model.id().0.to_string(),
+                    request,
+                    output: String::new(),
+                };
+                response = journal_response(response, entry, journal);
+            }
+            Ok(LanguageModelCompletionResponse {
+                inner: response,
+                citations,
+                _lock: lock,
+            })
+        })
+    }
+
+    /// Sends a journaled request to the given model, and compares its output
+    /// with the one the request originally produced.
+    pub fn replay(
+        &self,
+        request_id: &str,
+        target_model: Arc<dyn LanguageModel>,
+        cx: &AppContext,
+    ) -> Task<Result<ReplayResult>> {
+        let request_id = request_id.to_string();
+        let target_model_id = target_model.id().0.to_string();
+        cx.spawn(|cx| async move {
+            let entry = RequestJournal::global().load(&request_id).await?;
+            let response = cx.update(|cx| {
+                Self::read_global(cx).stream_completion_with_model(target_model, entry.request, cx)
+            })?;
+            let mut chunks = response.await?;
+            let mut replayed_output = String::new();
+            while let Some(chunk) = chunks.next().await {
+                replayed_output.push_str(&chunk?);
+            }
+            Ok(ReplayResult {
+                request_id: entry.id,
+                original_model: entry.model_id,
+                target_model: target_model_id,
+                diff: diff_outputs(&entry.output, &replayed_output),
+                original_output: entry.output,
+                replayed_output,
+            })
+        })
+    }
+
     pub fn complete(&self, request: LanguageModelRequest, cx: &AppContext) -> Task<Result<String>> {
         let response = self.stream_completion(request, cx);
         cx.foreground_executor().spawn(async move {
@@ -189,17 +405,80 @@ impl LanguageModelCompletionProvider {
 
     pub fn use_tool<T: LanguageModelTool>(
         &self,
-        request: LanguageModelRequest,
+        mut request: LanguageModelRequest,
         cx: &AppContext,
     ) -> Task<Result<T>> {
         if let Some(language_model) = self.active_model() {
-            cx.spawn(|cx| async move {
+            if let Err(error) = check_provider_allowed(language_model.as_ref(), cx) {
+                return Task::ready(Err(error));
+            }
+            presets::apply_preset(&mut request, language_model.as_ref(), cx);
+            if let Err(error) =
+                output_limits::clamp_max_output_tokens(&mut request, language_model.as_ref(), cx)
+            {
+                return Task::ready(Err(error));
+            }
+            let registry = LanguageModelRegistry::read_global(cx);
+            let circuit_breakers = registry.circuit_breakers();
+            let circuit_breaker_settings = AllLanguageModelSettings::get_global(cx).circuit_breaker;
+            if !circuit_breakers.lock().unwrap().try_acquire(
+                &language_model.provider_id(),
+                &circuit_breaker_settings,
+                Instant::now(),
+            ) {
+                return Task::ready(Err(provider_disabled_error(language_model.as_ref())));
+            }
+            let rate_limiter = self.request_limiter.clone();
+            let stream_metrics = registry.stream_metrics();
+            let shared_rate_limit =
+                SharedTokenBucket::for_provider(&language_model.provider_id(), cx);
+            cx.spawn(|mut cx| async move {
+                let _lock = rate_limiter.acquire_arc().await;
+                if let Some(shared_rate_limit) = shared_rate_limit {
+                    shared_rate_limit.acquire(cx.background_executor()).await?;
+                }
+                let moderation =
+                    Moderation::for_provider(&language_model.provider_id(), &mut cx).await?;
+                if let Some(moderation) = moderation {
+                    moderation.check_prompt(&request).await?;
+                }
+
+                let model_key = (language_model.provider_id(), language_model.id());
                 let schema = schemars::schema_for!(T);
                 let schema_json = serde_json::to_value(&schema).unwrap();
-                let request =
-                    language_model.use_tool(request, T::name(), T::description(), schema_json, &cx);
-                let response = request.await?;
-                Ok(serde_json::from_value(response)?)
+                let started_at = Instant::now();
+                let response = language_model
+                    .use_tool(request, T::name(), T::description(), schema_json, &cx)
+                    .await;
+
+                // Tool responses aren't streamed, so they're recorded by the
+                // time it took for the whole response to arrive.
+                match &response {
+                    Ok(_) => {
+                        stream_metrics
+                            .lock()
+                            .unwrap()
+                            .record_first_token(model_key.clone(), started_at.elapsed());
+                        circuit_breakers
+                            .lock()
+                            .unwrap()
+                            .record_success(&model_key.0, Instant::now());
+                    }
+                    Err(error) => {
+                        stream_metrics
+                            .lock()
+                            .unwrap()
+                            .record_failure(model_key.clone());
+                        if is_provider_failure(error) {
+                            circuit_breakers.lock().unwrap().record_failure(
+                                &model_key.0,
+                                &circuit_breaker_settings,
+                                Instant::now(),
+                            );
+                        }
+                    }
+                }
+                Ok(serde_json::from_value(response?)?)
             })
         } else {
             Task::ready(Err(anyhow!("No active model set")))
@@ -211,18 +490,214 @@ impl LanguageModelCompletionProvider {
     }
 }
 
+/// Fails if the model's provider isn't one of the allowed providers, which an
+/// organization may restrict in the managed settings.
+fn check_provider_allowed(model: &dyn LanguageModel, cx: &AppContext) -> Result<()> {
+    let provider_id = model.provider_id();
+    if AllLanguageModelSettings::get_global(cx).is_provider_allowed(&provider_id) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "the {} provider isn't allowed by your settings",
+            provider_id.0
+        ))
+    }
+}
+
+/// Returns the text of the events, collecting the citations among them.
+fn collect_citations(
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent>>,
+    citations: Arc<Mutex<Vec<Citation>>>,
+) -> BoxStream<'static, Result<String>> {
+    events
+        .filter_map(move |event| {
+            let chunk = match event {
+                Ok(LanguageModelCompletionEvent::Text(text)) => Some(Ok(text)),
+                Ok(LanguageModelCompletionEvent::Citation(citation)) => {
+                    citations.lock().unwrap().push(citation);
+                    None
+                }
+                Ok(LanguageModelCompletionEvent::Raw(_)) => None,
+                Err(error) => Some(Err(error)),
+            };
+            future::ready(chunk)
+        })
+        .boxed()
+}
+
+/// Waits for the request to be sent and the first token of its response to
+/// arrive, returning `None` if that doesn't happen within the timeout.
+async fn wait_for_first_token(
+    response: impl Future<Output = Result<BoxStream<'static, Result<String>>>>,
+    timeout: Duration,
+    executor: &BackgroundExecutor,
+) -> Option<Result<BoxStream<'static, Result<String>>>> {
+    async {
+        let mut response = match response.await {
+            Ok(response) => response,
+            Err(error) => return Some(Err(error)),
+        };
+        let first_chunk = response.next().await;
+        Some(Ok(futures::stream::iter(first_chunk)
+            .chain(response)
+            .boxed()))
+    }
+    .or(async {
+        executor.timer(timeout).await;
+        None
+    })
+    .await
+}
+
+fn provider_disabled_error(language_model: &dyn LanguageModel) -> anyhow::Error {
+    anyhow!(
+        "{} is temporarily disabled after repeated failures",
+        language_model.provider_name().0
+    )
+}
+
+/// Returns whether the error counts towards disabling the provider. Errors
+/// raised before the request was sent, such as a missing API key, say nothing
+/// about whether the provider is healthy.
+fn is_provider_failure(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<MissingApiKeyError>().is_none()
+}
+
+/// Records how long the model took to start responding, how fast it streamed
+/// the response, and whether it failed, so that requests can be routed to the
+/// fastest healthy model and providers that keep failing can be disabled.
+///
+/// Each response is recorded once: as a failure if it fails at any point, or
+/// by its time to first token otherwise.
+fn record_stream_metrics(
+    mut response: BoxStream<'static, Result<String>>,
+    model_key: ModelKey,
+    started_at: Instant,
+    stream_metrics: Arc<Mutex<StreamMetrics>>,
+    circuit_breakers: Arc<Mutex<CircuitBreakers>>,
+    circuit_breaker_settings: CircuitBreakerSettings,
+) -> BoxStream<'static, Result<String>> {
+    let mut recorder = StreamRecorder {
+        model_key,
+        stream_metrics,
+        time_to_first_token: None,
+        first_token_at: None,
+        char_count: 0,
+        is_recorded: false,
+    };
+    futures::stream::poll_fn(move |cx| {
+        let poll = response.poll_next_unpin(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if recorder.first_token_at.is_none() {
+                    recorder.first_token_at = Some(Instant::now());
+                    recorder.time_to_first_token = Some(started_at.elapsed());
+                    circuit_breakers
+                        .lock()
+                        .unwrap()
+                        .record_success(&recorder.model_key.0, Instant::now());
+                }
+                recorder.char_count += chunk.chars().count();
+            }
+            Poll::Ready(Some(Err(_))) if !recorder.is_recorded => {
+                recorder.record_failure();
+                circuit_breakers.lock().unwrap().record_failure(
+                    &recorder.model_key.0,
+                    &circuit_breaker_settings,
+                    Instant::now(),
+                );
+            }
+            Poll::Ready(None) => recorder.record_completion(),
+            _ => {}
+        }
+        poll
+    })
+    .boxed()
+}
+
+/// The metrics of a streamed response, which are recorded once it ends.
+///
+/// A response that's dropped after it started, such as when the user cancels
+/// it, is recorded by its time to first token.
+struct StreamRecorder {
+    model_key: ModelKey,
+    stream_metrics: Arc<Mutex<StreamMetrics>>,
+    time_to_first_token: Option<Duration>,
+    first_token_at: Option<Instant>,
+    char_count: usize,
+    is_recorded: bool,
+}
+
+impl StreamRecorder {
+    fn record_failure(&mut self) {
+        self.is_recorded = true;
+        self.stream_metrics
+            .lock()
+            .unwrap()
+            .record_failure(self.model_key.clone());
+    }
+
+    fn record_completion(&mut self) {
+        if self.is_recorded {
+            return;
+        }
+        self.is_recorded = true;
+        let (Some(time_to_first_token), Some(first_token_at)) =
+            (self.time_to_first_token, self.first_token_at)
+        else {
+            return;
+        };
+        let mut stream_metrics = self.stream_metrics.lock().unwrap();
+        stream_metrics.record_first_token(self.model_key.clone(), time_to_first_token);
+        // Responses are streamed as text, so their tokens are estimated at
+        // four characters each.
+        stream_metrics.record_completion(
+            self.model_key.clone(),
+            self.char_count / 4,
+            first_token_at.elapsed(),
+        );
+    }
+}
+
+impl Drop for StreamRecorder {
+    fn drop(&mut self) {
+        if self.is_recorded {
+            return;
+        }
+        if let Some(time_to_first_token) = self.time_to_first_token {
+            self.stream_metrics
+                .lock()
+                .unwrap()
+                .record_first_token(self.model_key.clone(), time_to_first_token);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use futures::StreamExt;
-    use gpui::AppContext;
+    use futures::{stream::BoxStream, StreamExt};
+    use gpui::{AppContext, TestAppContext};
     use settings::SettingsStore;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
     use ui::Context;
 
     use crate::{
+        collect_citations, is_provider_failure, record_stream_metrics, wait_for_first_token,
         LanguageModelCompletionProvider, LanguageModelRequest, MAX_CONCURRENT_COMPLETION_REQUESTS,
     };
 
-    use language_model::LanguageModelRegistry;
+    use language_model::{
+        circuit_breaker::{CircuitBreakerSettings, CircuitBreakers},
+        org_vault::MissingApiKeyError,
+        stream_metrics::StreamMetrics,
+        Citation, LanguageModelCompletionEvent, LanguageModelId, LanguageModelProviderId,
+        LanguageModelRegistry, LanguageModelTool,
+    };
+    use schemars::JsonSchema;
+    use serde::Deserialize;
 
     #[gpui::test]
     fn test_rate_limiting(cx: &mut AppContext) {
@@ -309,4 +784,201 @@ mod tests {
 
         assert_eq!(fake_model.completion_count(), 0);
     }
+
+    #[derive(Deserialize, JsonSchema)]
+    struct TestTool {}
+
+    impl LanguageModelTool for TestTool {
+        fn name() -> String {
+            "test_tool".into()
+        }
+
+        fn description() -> String {
+            "A tool for testing".into()
+        }
+    }
+
+    #[gpui::test]
+    async fn test_use_tool_circuit_breaker(cx: &mut TestAppContext) {
+        let provider = cx.update(|cx| {
+            SettingsStore::test(cx);
+            LanguageModelRegistry::test(cx);
+            let model = LanguageModelRegistry::read_global(cx)
+                .available_models(cx)
+                .first()
+                .cloned()
+                .unwrap();
+            cx.new_model(|cx| {
+                let mut provider = LanguageModelCompletionProvider::new(cx);
+                provider.set_active_model(model, cx);
+                provider
+            })
+        });
+
+        // The fake model fails every tool use, which disables its provider
+        // after the default threshold of 5 consecutive failures.
+        for _ in 0..5 {
+            let error = provider
+                .update(cx, |provider, cx| {
+                    provider.use_tool::<TestTool>(LanguageModelRequest::default(), cx)
+                })
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(error.to_string(), "not implemented");
+        }
+        let error = provider
+            .update(cx, |provider, cx| {
+                provider.use_tool::<TestTool>(LanguageModelRequest::default(), cx)
+            })
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("temporarily disabled"));
+
+        let model_key = (
+            language_model::provider::fake::provider_id(),
+            language_model::provider::fake::language_model_id(),
+        );
+        cx.update(|cx| {
+            let stream_metrics = LanguageModelRegistry::read_global(cx).stream_metrics();
+            let stream_metrics = stream_metrics.lock().unwrap();
+            let stats = stream_metrics.stats(&model_key).unwrap();
+            assert_eq!(stats.sample_count(), 5);
+            assert_eq!(stats.error_rate(), 1.);
+        });
+    }
+
+    #[gpui::test]
+    async fn test_wait_for_first_token(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+
+        let response = futures::stream::iter([Ok("Hello".to_string()), Ok(" world".to_string())]);
+        let response = wait_for_first_token(
+            async { anyhow::Ok(response.boxed()) },
+            Duration::from_secs(5),
+            &executor,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            response.map(Result::unwrap).collect::<Vec<_>>().await,
+            ["Hello", " world"]
+        );
+
+        // The response never starts.
+        let response = futures::stream::pending().boxed();
+        let task = executor.spawn({
+            let executor = executor.clone();
+            async move {
+                wait_for_first_token(
+                    async { anyhow::Ok(response) },
+                    Duration::from_secs(5),
+                    &executor,
+                )
+                .await
+                .is_none()
+            }
+        });
+        executor.advance_clock(Duration::from_secs(6));
+        assert!(task.await);
+
+        // The request is never sent, such as when connecting hangs.
+        let task = executor.spawn({
+            let executor = executor.clone();
+            async move {
+                wait_for_first_token(
+                    futures::future::pending::<Result<BoxStream<'static, Result<String>>>>(),
+                    Duration::from_secs(5),
+                    &executor,
+                )
+                .await
+                .is_none()
+            }
+        });
+        executor.advance_clock(Duration::from_secs(6));
+        assert!(task.await);
+    }
+
+    #[gpui::test]
+    async fn test_collect_citations(_: &mut TestAppContext) {
+        let citation = Citation {
+            url: Some("https://www.rust-lang.org".into()),
+            output_range: Some(0..4),
+            ..Default::default()
+        };
+        let events = futures::stream::iter([
+            Ok(LanguageModelCompletionEvent::Text("Rust is fast.".into())),
+            Ok(LanguageModelCompletionEvent::Citation(citation.clone())),
+        ]);
+        let citations = Arc::new(Mutex::new(Vec::new()));
+        let text = collect_citations(events.boxed(), citations.clone())
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(text, ["Rust is fast."]);
+        assert_eq!(*citations.lock().unwrap(), [citation]);
+    }
+
+    #[gpui::test]
+    async fn test_record_stream_metrics(_: &mut TestAppContext) {
+        let model_key = (
+            LanguageModelProviderId::from("provider".to_string()),
+            LanguageModelId::from("model".to_string()),
+        );
+        let stream_metrics = Arc::new(Mutex::new(StreamMetrics::default()));
+        let circuit_breakers = Arc::new(Mutex::new(CircuitBreakers::default()));
+        let response = futures::stream::iter([
+            Ok("Hello".to_string()),
+            Err(anyhow::anyhow!("connection reset")),
+        ]);
+        let chunks = record_stream_metrics(
+            response.boxed(),
+            model_key.clone(),
+            std::time::Instant::now(),
+            stream_metrics.clone(),
+            circuit_breakers,
+            CircuitBreakerSettings::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(chunks.len(), 2);
+
+        // The response started, then failed, which is recorded as a failure.
+        let summary = stream_metrics.lock().unwrap().summary(&model_key).unwrap();
+        assert_eq!(summary.sample_count, 1);
+        assert_eq!(summary.time_to_first_token, None);
+        assert_eq!(summary.tokens_per_second, None);
+        assert_eq!(summary.error_rate, 1.);
+
+        // A response that streams to the end is recorded by its time to
+        // first token.
+        let chunks = record_stream_metrics(
+            futures::stream::iter([Ok("Hello".to_string()), Ok(" world".to_string())]).boxed(),
+            model_key.clone(),
+            std::time::Instant::now(),
+            stream_metrics.clone(),
+            Arc::new(Mutex::new(CircuitBreakers::default())),
+            CircuitBreakerSettings::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(chunks.len(), 2);
+        let summary = stream_metrics.lock().unwrap().summary(&model_key).unwrap();
+        assert_eq!(summary.sample_count, 2);
+        assert!(summary.time_to_first_token.is_some());
+        assert_eq!(summary.error_rate, 0.5);
+    }
+
+    #[test]
+    fn test_is_provider_failure() {
+        assert!(is_provider_failure(&anyhow::anyhow!("connection reset")));
+        assert!(!is_provider_failure(
+            &MissingApiKeyError {
+                provider_id: Some("openai"),
+            }
+            .into()
+        ));
+    }
 }
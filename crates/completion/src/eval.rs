@@ -0,0 +1,513 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use gpui::{AppContext, AsyncAppContext, Task};
+use language_model::{LanguageModel, LanguageModelRequest, LanguageModelRequestMessage, Role};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::LanguageModelCompletionProvider;
+
+const JUDGE_PROMPT: &str = "You are grading the output of a language model against a \
+criterion. Reply with PASS or FAIL on the first line, followed by a one sentence reason.";
+
+/// A named set of cases, typically loaded from a JSON file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvalSuite {
+    pub name: String,
+    pub cases: Vec<EvalCase>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    pub request: LanguageModelRequest,
+    pub expectations: Vec<Expectation>,
+}
+
+/// A property the output of a case is expected to have.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Expectation {
+    /// The output matches the regular expression.
+    Regex { pattern: String },
+    /// The output is JSON that conforms to the schema. Only the `type`,
+    /// `enum`, `properties`, `required`, `additionalProperties` and `items`
+    /// keywords are supported, along with annotations such as `description`;
+    /// suites whose schemas use others are rejected.
+    JsonSchema { schema: Value },
+    /// The judge model considers the output to meet the criterion.
+    Judge { criterion: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub suite: String,
+    pub models: Vec<ModelReport>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelReport {
+    pub provider_id: String,
+    pub model_id: String,
+    pub cases: Vec<CaseReport>,
+    /// The average score of the model's cases, between 0 and 1. Models whose
+    /// requests failed score 0.
+    pub score: f32,
+    /// Why the model's cases couldn't be run, e.g. because its provider was
+    /// unavailable.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaseReport {
+    pub name: String,
+    pub output: String,
+    pub checks: Vec<CheckReport>,
+    /// The fraction of the case's expectations that were met.
+    pub score: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub expectation: Expectation,
+    pub passed: bool,
+    /// Why the check failed.
+    pub reason: Option<String>,
+}
+
+impl EvalReport {
+    /// Returns whether every model scored at least `min_score`.
+    pub fn meets(&self, min_score: f32) -> bool {
+        self.models.iter().all(|model| model.score >= min_score)
+    }
+}
+
+/// Runs the suite against each of the models, grading judged expectations
+/// with `judge`.
+///
+/// The suite is validated before any requests are sent. A model whose
+/// requests fail is reported with the error, and the others are still run.
+pub fn run_eval(
+    suite: EvalSuite,
+    models: Vec<Arc<dyn LanguageModel>>,
+    judge: Option<Arc<dyn LanguageModel>>,
+    cx: &AppContext,
+) -> Task<Result<EvalReport>> {
+    if let Err(error) = validate_suite(&suite, judge.is_some()) {
+        return Task::ready(Err(error));
+    }
+    cx.spawn(|mut cx| async move {
+        let mut model_reports = Vec::new();
+        for model in models {
+            let mut case_reports = Vec::new();
+            let mut error = None;
+            for case in &suite.cases {
+                match run_case(case, model.clone(), judge.clone(), &mut cx).await {
+                    Ok(case_report) => case_reports.push(case_report),
+                    Err(case_error) => {
+                        error = Some(format!("{:?} failed: {case_error}", case.name));
+                        break;
+                    }
+                }
+            }
+            model_reports.push(ModelReport {
+                provider_id: model.provider_id().0.to_string(),
+                model_id: model.id().0.to_string(),
+                score: if error.is_some() {
+                    0.
+                } else {
+                    score(case_reports.iter().map(|case| case.score))
+                },
+                cases: case_reports,
+                error,
+            });
+        }
+        Ok(EvalReport {
+            suite: suite.name,
+            models: model_reports,
+        })
+    })
+}
+
+async fn run_case(
+    case: &EvalCase,
+    model: Arc<dyn LanguageModel>,
+    judge: Option<Arc<dyn LanguageModel>>,
+    cx: &mut AsyncAppContext,
+) -> Result<CaseReport> {
+    let output = complete(model, case.request.clone(), cx).await?;
+    let mut checks = Vec::new();
+    for expectation in &case.expectations {
+        let check = match expectation {
+            Expectation::Regex { pattern } => check_regex(pattern, &output)?,
+            Expectation::JsonSchema { schema } => check_json_schema(schema, &output),
+            Expectation::Judge { criterion } => {
+                let judge = judge
+                    .clone()
+                    .ok_or_else(|| anyhow!("no judge model to grade {:?}", case.name))?;
+                let verdict = complete(judge, judge_request(criterion, &output), cx).await?;
+                parse_verdict(&verdict)
+            }
+        };
+        checks.push(CheckReport {
+            expectation: expectation.clone(),
+            passed: check.is_ok(),
+            reason: check.err(),
+        });
+    }
+    Ok(CaseReport {
+        name: case.name.clone(),
+        output,
+        score: score(checks.iter().map(|check| check.passed as u32 as f32)),
+        checks,
+    })
+}
+
+/// Checks that every expectation of the suite can be evaluated, so that a
+/// mistake in the suite isn't only discovered partway through a run.
+fn validate_suite(suite: &EvalSuite, has_judge: bool) -> Result<()> {
+    for case in &suite.cases {
+        for expectation in &case.expectations {
+            match expectation {
+                Expectation::Regex { pattern } => {
+                    Regex::new(pattern).map_err(|error| {
+                        anyhow!("{:?} has an invalid pattern: {error}", case.name)
+                    })?;
+                }
+                Expectation::JsonSchema { schema } => {
+                    validate_schema(schema, "$").map_err(|error| {
+                        anyhow!("{:?} has an invalid schema: {error}", case.name)
+                    })?;
+                }
+                Expectation::Judge { .. } if !has_judge => {
+                    return Err(anyhow!("no judge model to grade {:?}", case.name));
+                }
+                Expectation::Judge { .. } => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The keywords that only describe a schema, which aren't checked.
+const ANNOTATION_KEYWORDS: &[&str] = &["$schema", "title", "description", "default", "examples"];
+
+/// Checks that the schema only uses the keywords [`validate_json`] supports,
+/// with values of the right shape.
+fn validate_schema(schema: &Value, path: &str) -> Result<(), String> {
+    let schema = schema
+        .as_object()
+        .ok_or_else(|| format!("{path} isn't an object"))?;
+    for (keyword, value) in schema {
+        let path = format!("{path}.{keyword}");
+        match keyword.as_str() {
+            "type" => {
+                let types = match value {
+                    Value::String(ty) => vec![ty.as_str()],
+                    Value::Array(types) => types
+                        .iter()
+                        .map(|ty| ty.as_str().ok_or_else(|| format!("{path} isn't a string")))
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(format!("{path} isn't a string or an array")),
+                };
+                if let Some(ty) = types.iter().find(|ty| !JSON_TYPES.contains(ty)) {
+                    return Err(format!("{path} has unknown type {ty:?}"));
+                }
+            }
+            "enum" => {
+                value
+                    .as_array()
+                    .ok_or_else(|| format!("{path} isn't an array"))?;
+            }
+            "required" => {
+                let required = value
+                    .as_array()
+                    .ok_or_else(|| format!("{path} isn't an array"))?;
+                if !required.iter().all(Value::is_string) {
+                    return Err(format!("{path} isn't an array of strings"));
+                }
+            }
+            "properties" => {
+                let properties = value
+                    .as_object()
+                    .ok_or_else(|| format!("{path} isn't an object"))?;
+                for (key, property_schema) in properties {
+                    validate_schema(property_schema, &format!("{path}.{key}"))?;
+                }
+            }
+            "additionalProperties" => {
+                if !value.is_boolean() {
+                    validate_schema(value, &path)?;
+                }
+            }
+            "items" => validate_schema(value, &path)?,
+            keyword if ANNOTATION_KEYWORDS.contains(&keyword) => {}
+            keyword => return Err(format!("{path} uses unsupported keyword {keyword:?}")),
+        }
+    }
+    Ok(())
+}
+
+async fn complete(
+    model: Arc<dyn LanguageModel>,
+    request: LanguageModelRequest,
+    cx: &mut AsyncAppContext,
+) -> Result<String> {
+    let response = cx.update(|cx| {
+        LanguageModelCompletionProvider::read_global(cx)
+            .stream_completion_with_model(model, request, cx)
+    })?;
+    let mut chunks = response.await?;
+    let mut output = String::new();
+    while let Some(chunk) = chunks.next().await {
+        output.push_str(&chunk?);
+    }
+    Ok(output)
+}
+
+/// Returns the mean of the scores, or 1 if there are none.
+fn score(scores: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = scores.fold((0., 0), |(sum, count), score| (sum + score, count + 1));
+    if count == 0 {
+        1.
+    } else {
+        sum / count as f32
+    }
+}
+
+fn check_regex(pattern: &str, output: &str) -> Result<Result<(), String>> {
+    let regex = Regex::new(pattern)?;
+    Ok(if regex.is_match(output) {
+        Ok(())
+    } else {
+        Err(format!("output doesn't match {pattern:?}"))
+    })
+}
+
+fn check_json_schema(schema: &Value, output: &str) -> Result<(), String> {
+    // Models often wrap JSON in a Markdown code block.
+    let json = output
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```");
+    let value = serde_json::from_str::<Value>(json)
+        .map_err(|error| format!("output isn't valid JSON: {error}"))?;
+    validate_json(schema, &value, "$")
+}
+
+fn validate_json(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type") {
+        let types = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            Value::String(ty) => vec![ty.as_str()],
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|ty| json_has_type(value, ty)) {
+            return Err(format!("{path} isn't of type {}", types.join(" or ")));
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{path} isn't one of the allowed values"));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("{path} is missing {key:?}"));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, property) in object {
+            match (
+                properties.and_then(|properties| properties.get(key)),
+                schema.get("additionalProperties"),
+            ) {
+                (Some(property_schema), _) => {
+                    validate_json(property_schema, property, &format!("{path}.{key}"))?
+                }
+                (None, Some(Value::Bool(false))) => {
+                    return Err(format!("{path} has unexpected property {key:?}"));
+                }
+                (None, Some(additional_schema @ Value::Object(_))) => {
+                    validate_json(additional_schema, property, &format!("{path}.{key}"))?
+                }
+                (None, _) => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (ix, item) in items.iter().enumerate() {
+            validate_json(item_schema, item, &format!("{path}[{ix}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+const JSON_TYPES: &[&str] = &[
+    "null", "boolean", "object", "array", "number", "integer", "string",
+];
+
+fn json_has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "string" => value.is_string(),
+        _ => false,
+    }
+}
+
+fn judge_request(criterion: &str, output: &str) -> LanguageModelRequest {
+    LanguageModelRequest {
+        messages: vec![
+            LanguageModelRequestMessage {
+                role: Role::System,
+                content: JUDGE_PROMPT.into(),
+            },
+            LanguageModelRequestMessage {
+                role: Role::User,
+                content: format!("Criterion: {criterion}\n\nOutput:\n{output}"),
+            },
+        ],
+        temperature: 0.,
+        ..Default::default()
+    }
+}
+
+fn parse_verdict(verdict: &str) -> Result<(), String> {
+    let verdict = verdict.trim();
+    let (first_line, reason) = verdict.split_once('\n').unwrap_or((verdict, ""));
+    let reason = reason.trim().to_string();
+    match first_line.trim().to_ascii_uppercase().as_str() {
+        "PASS" => Ok(()),
+        "FAIL" => Err(reason),
+        _ => Err(format!("the judge gave no verdict: {verdict}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_json_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string" },
+                "kind": { "enum": ["fn", "struct"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        });
+        assert_eq!(
+            check_json_schema(
+                &schema,
+                "```json\n{\"name\": \"a\", \"tags\": [\"x\"]}\n```"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_json_schema(&schema, r#"{"name": "a"}"#),
+            Err("$ is missing \"tags\"".into())
+        );
+        assert_eq!(
+            check_json_schema(&schema, r#"{"name": "a", "tags": [1]}"#),
+            Err("$.tags[0] isn't of type string".into())
+        );
+        assert_eq!(
+            check_json_schema(&schema, r#"{"name": "a", "tags": [], "kind": "enum"}"#),
+            Err("$.kind isn't one of the allowed values".into())
+        );
+        assert_eq!(
+            check_json_schema(&schema, r#"{"name": "a", "tags": [], "extra": 1}"#),
+            Err("$ has unexpected property \"extra\"".into())
+        );
+        assert!(check_json_schema(&schema, "not json").is_err());
+
+        let schema = json!({ "additionalProperties": { "type": "integer" } });
+        assert_eq!(check_json_schema(&schema, r#"{"a": 1}"#), Ok(()));
+        assert_eq!(
+            check_json_schema(&schema, r#"{"a": "1"}"#),
+            Err("$.a isn't of type integer".into())
+        );
+    }
+
+    #[test]
+    fn test_validate_suite() {
+        let suite = |expectation| EvalSuite {
+            name: "suite".into(),
+            cases: vec![EvalCase {
+                name: "case".into(),
+                request: LanguageModelRequest::default(),
+                expectations: vec![expectation],
+            }],
+        };
+        let schema_suite = |schema| suite(Expectation::JsonSchema { schema });
+
+        assert!(validate_suite(
+            &schema_suite(json!({
+                "type": ["object", "null"],
+                "description": "A symbol",
+                "properties": { "tags": { "type": "array", "items": { "type": "string" } } },
+                "additionalProperties": { "type": "integer" }
+            })),
+            false
+        )
+        .is_ok());
+        assert!(validate_suite(&schema_suite(json!("object")), false).is_err());
+        assert!(validate_suite(&schema_suite(json!({ "type": "strin" })), false).is_err());
+        assert!(validate_suite(&schema_suite(json!({ "required": "name" })), false).is_err());
+        assert!(validate_suite(
+            &schema_suite(json!({ "properties": { "name": { "minLength": 1 } } })),
+            false
+        )
+        .is_err());
+
+        assert!(validate_suite(
+            &suite(Expectation::Regex {
+                pattern: "(".into()
+            }),
+            false
+        )
+        .is_err());
+
+        let judged = suite(Expectation::Judge {
+            criterion: "It's concise.".into(),
+        });
+        assert!(validate_suite(&judged, false).is_err());
+        assert!(validate_suite(&judged, true).is_ok());
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        assert_eq!(parse_verdict("PASS\nIt's concise."), Ok(()));
+        assert_eq!(
+            parse_verdict("fail\nIt rambles."),
+            Err("It rambles.".into())
+        );
+        assert!(parse_verdict("Maybe?").is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score([1., 0., 1., 0.].into_iter()), 0.5);
+        assert_eq!(score(std::iter::empty()), 1.);
+    }
+}
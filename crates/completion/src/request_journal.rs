@@ -0,0 +1,270 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::{stream::BoxStream, StreamExt};
+use language_model::LanguageModelRequest;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use smol::fs;
+
+/// How long journaled requests are kept before they're deleted.
+const JOURNAL_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A journaled request, along with the output it produced.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub provider_id: String,
+    pub model_id: String,
+    pub request: LanguageModelRequest,
+    pub output: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestJournal {
+    dir: PathBuf,
+}
+
+impl RequestJournal {
+    pub fn global() -> Self {
+        Self::new(paths::language_model_journal_dir().clone())
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Writes the entry to the journal, deleting the entries that have been
+    /// kept for longer than [`JOURNAL_RETENTION`].
+    pub async fn record(&self, entry: &JournalEntry) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(
+            self.entry_path(&entry.id)?,
+            serde_json::to_vec_pretty(entry)?,
+        )
+        .await?;
+        self.prune(SystemTime::now() - JOURNAL_RETENTION).await
+    }
+
+    pub async fn load(&self, id: &str) -> Result<JournalEntry> {
+        let path = self.entry_path(id)?;
+        let entry = fs::read(&path)
+            .await
+            .with_context(|| format!("no journaled request with ID {id:?}"))?;
+        Ok(serde_json::from_slice(&entry)?)
+    }
+
+    /// Returns the IDs of the journaled requests.
+    pub async fn entry_ids(&self) -> Result<Vec<String>> {
+        let mut ids = self
+            .entry_paths()
+            .await?
+            .iter()
+            .filter_map(|path| path.file_stem()?.to_str().map(|id| id.to_string()))
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Deletes the entries that were journaled before `cutoff`.
+    pub async fn prune(&self, cutoff: SystemTime) -> Result<()> {
+        for path in self.entry_paths().await? {
+            if fs::metadata(&path).await?.modified()? < cutoff {
+                fs::remove_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn entry_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(false, |extension| extension == "json")
+            {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn entry_path(&self, id: &str) -> Result<PathBuf> {
+        // IDs come from callers, so make sure they can't escape the journal.
+        if id.is_empty() || Path::new(id).file_name() != Some(id.as_ref()) {
+            return Err(anyhow!("invalid journal entry ID {id:?}"));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+}
+
+/// Journals the request once the response has been received in full.
+/// Responses that fail or are dropped early aren't journaled.
+pub(crate) fn journal_response(
+    response: BoxStream<'static, Result<String>>,
+    mut entry: JournalEntry,
+    journal: RequestJournal,
+) -> BoxStream<'static, Result<String>> {
+    let output = Arc::new(Mutex::new(Some(String::new())));
+    response
+        .inspect({
+            let output = output.clone();
+            move |chunk| {
+                let mut output = output.lock().unwrap();
+                match chunk {
+                    Ok(chunk) => {
+                        if let Some(output) = output.as_mut() {
+                            output.push_str(chunk);
+                        }
+                    }
+                    Err(_) => *output = None,
+                }
+            }
+        })
+        .chain(
+            futures::stream::once(async move {
+                let output = output.lock().unwrap().take();
+                if let Some(output) = output {
+                    entry.output = output;
+                    if let Err(error) = journal.record(&entry).await {
+                        log::error!("failed to journal request {}: {error}", entry.id);
+                    }
+                }
+                None
+            })
+            .filter_map(futures::future::ready),
+        )
+        .boxed()
+}
+
+/// The outcome of replaying a journaled request against another model.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub request_id: String,
+    pub original_model: String,
+    pub target_model: String,
+    pub original_output: String,
+    pub replayed_output: String,
+    /// The line-by-line changes from the original output to the replayed one.
+    pub diff: Vec<OutputChange>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputChangeKind {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChange {
+    pub kind: OutputChangeKind,
+    pub text: String,
+}
+
+/// Diffs two outputs by line, merging consecutive lines with the same kind
+/// of change.
+pub fn diff_outputs(original: &str, replayed: &str) -> Vec<OutputChange> {
+    let mut changes: Vec<OutputChange> = Vec::new();
+    for change in TextDiff::from_lines(original, replayed).iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => OutputChangeKind::Unchanged,
+            ChangeTag::Insert => OutputChangeKind::Added,
+            ChangeTag::Delete => OutputChangeKind::Removed,
+        };
+        match changes.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(change.value()),
+            _ => changes.push(OutputChange {
+                kind,
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_journal_response() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = RequestJournal::new(dir.path().into());
+        let entry = JournalEntry {
+            id: "a".into(),
+            provider_id: "openai".into(),
+            model_id: "gpt-4o".into(),
+            request: LanguageModelRequest::default(),
+            output: String::new(),
+        };
+
+        let response = futures::stream::iter([Ok("Hello, ".into()), Ok("world".into())]).boxed();
+        let chunks = block_on(
+            journal_response(response, entry.clone(), journal.clone()).collect::<Vec<_>>(),
+        );
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(block_on(journal.load("a")).unwrap().output, "Hello, world");
+
+        // Failed responses aren't journaled.
+        let response =
+            futures::stream::iter([Ok("Hi".into()), Err(anyhow!("disconnected"))]).boxed();
+        let entry = JournalEntry {
+            id: "b".into(),
+            ..entry
+        };
+        block_on(journal_response(response, entry, journal.clone()).collect::<Vec<_>>());
+        assert!(block_on(journal.load("b")).is_err());
+
+        assert_eq!(block_on(journal.entry_ids()).unwrap(), ["a"]);
+        assert!(block_on(journal.load("../a")).is_err());
+
+        // Only entries journaled before the cutoff are pruned.
+        let now = SystemTime::now();
+        block_on(journal.prune(now - Duration::from_secs(60 * 60))).unwrap();
+        assert_eq!(block_on(journal.entry_ids()).unwrap(), ["a"]);
+        block_on(journal.prune(now + Duration::from_secs(60))).unwrap();
+        assert!(block_on(journal.entry_ids()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_outputs() {
+        assert_eq!(
+            diff_outputs("one\ntwo\nthree\n", "one\n2\nthree\nfour\n"),
+            [
+                OutputChange {
+                    kind: OutputChangeKind::Unchanged,
+                    text: "one\n".into()
+                },
+                OutputChange {
+                    kind: OutputChangeKind::Removed,
+                    text: "two\n".into()
+                },
+                OutputChange {
+                    kind: OutputChangeKind::Added,
+                    text: "2\n".into()
+                },
+                OutputChange {
+                    kind: OutputChangeKind::Unchanged,
+                    text: "three\n".into()
+                },
+                OutputChange {
+                    kind: OutputChangeKind::Added,
+                    text: "four\n".into()
+                },
+            ]
+        );
+    }
+}
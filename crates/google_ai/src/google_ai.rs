@@ -9,8 +9,25 @@ pub async fn stream_generate_content(
     client: &dyn HttpClient,
     api_url: &str,
     api_key: &str,
-    mut request: GenerateContentRequest,
+    request: GenerateContentRequest,
 ) -> Result<BoxStream<'static, Result<GenerateContentResponse>>> {
+    let responses = stream_raw_generate_content(client, api_url, api_key, request).await?;
+    Ok(responses
+        .map(|response| -> Result<GenerateContentResponse> {
+            Ok(serde_json::from_value(response?)?)
+        })
+        .boxed())
+}
+
+/// Streams the chunks of generated content as they were sent, without
+/// parsing them into [`GenerateContentResponse`]s, for reading fields that
+/// it omits.
+pub async fn stream_raw_generate_content(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    mut request: GenerateContentRequest,
+) -> Result<BoxStream<'static, Result<serde_json::Value>>> {
     let uri = format!(
         "{api_url}/v1beta/models/{model}:streamGenerateContent?alt=sse&key={api_key}",
         model = request.model
@@ -104,6 +121,17 @@ pub struct GenerateContentRequest {
 pub struct GenerateContentResponse {
     pub candidates: Option<Vec<GenerateContentCandidate>>,
     pub prompt_feedback: Option<PromptFeedback>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<UsageMetadata>,
+}
+
+/// The tokens used by a request so far, which is sent with every streamed
+/// chunk.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    pub prompt_token_count: Option<u32>,
+    pub candidates_token_count: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -115,6 +143,7 @@ pub struct GenerateContentCandidate {
     pub finish_message: Option<String>,
     pub safety_ratings: Option<Vec<SafetyRating>>,
     pub citation_metadata: Option<CitationMetadata>,
+    pub grounding_metadata: Option<GroundingMetadata>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -172,6 +201,47 @@ pub struct CitationMetadata {
     pub citation_sources: Vec<CitationSource>,
 }
 
+/// The sources a response grounded with Google Search is based on.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingMetadata {
+    #[serde(default)]
+    pub grounding_chunks: Vec<GroundingChunk>,
+    #[serde(default)]
+    pub grounding_supports: Vec<GroundingSupport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingChunk {
+    pub web: Option<WebSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSource {
+    pub uri: Option<String>,
+    pub title: Option<String>,
+}
+
+/// A segment of the response, supported by some of the grounding chunks.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingSupport {
+    pub segment: Segment,
+    #[serde(default)]
+    pub grounding_chunk_indices: Vec<usize>,
+}
+
+/// A byte range of the response.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    #[serde(default)]
+    pub start_index: usize,
+    pub end_index: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptFeedback {
@@ -1,4 +1,5 @@
 pub mod github;
+mod response_limits;
 
 pub use anyhow::{anyhow, Result};
 use derive_more::Deref;
@@ -9,6 +10,7 @@ pub use isahc::{
     http::{Method, StatusCode, Uri},
     AsyncBody, Error, HttpClient as IsahcHttpClient, Request, Response,
 };
+pub use response_limits::{LimitedHttpClient, ResponseTooLarge};
 #[cfg(feature = "test-support")]
 use std::fmt;
 use std::{
@@ -61,6 +63,14 @@ pub trait HttpClient: Send + Sync {
     }
 
     fn proxy(&self) -> Option<&Uri>;
+
+    /// Returns a client that sends requests like this one, through the same
+    /// proxy, but keeps its connections with the given options. Options left
+    /// unset keep this client's. Returns `None` for clients that can't be
+    /// rebuilt, such as fakes.
+    fn with_connection_options(&self, _options: ConnectionOptions) -> Option<Arc<dyn HttpClient>> {
+        None
+    }
 }
 
 /// An [`HttpClient`] that may have a proxy.
@@ -69,6 +79,9 @@ pub struct HttpClientWithProxy {
     #[deref]
     client: Arc<dyn HttpClient>,
     proxy: Option<Uri>,
+    /// The options the client was built with, or `None` if it wasn't built
+    /// here, such as a fake.
+    options: Option<ConnectionOptions>,
 }
 
 impl HttpClientWithProxy {
@@ -86,6 +99,7 @@ impl HttpClientWithProxy {
         Self {
             client: client(proxy_url.clone()),
             proxy: proxy_url,
+            options: Some(ConnectionOptions::default()),
         }
     }
 }
@@ -101,6 +115,14 @@ impl HttpClient for HttpClientWithProxy {
     fn proxy(&self) -> Option<&Uri> {
         self.proxy.as_ref()
     }
+
+    fn with_connection_options(&self, options: ConnectionOptions) -> Option<Arc<dyn HttpClient>> {
+        let base_options = self.options?;
+        Some(client_with_options(
+            self.proxy.clone(),
+            options.or(base_options),
+        ))
+    }
 }
 
 impl HttpClient for Arc<HttpClientWithProxy> {
@@ -114,6 +136,10 @@ impl HttpClient for Arc<HttpClientWithProxy> {
     fn proxy(&self) -> Option<&Uri> {
         self.proxy.as_ref()
     }
+
+    fn with_connection_options(&self, options: ConnectionOptions) -> Option<Arc<dyn HttpClient>> {
+        (**self).with_connection_options(options)
+    }
 }
 
 /// An [`HttpClient`] that has a base URL.
@@ -184,6 +210,10 @@ impl HttpClient for Arc<HttpClientWithUrl> {
     fn proxy(&self) -> Option<&Uri> {
         self.client.proxy.as_ref()
     }
+
+    fn with_connection_options(&self, options: ConnectionOptions) -> Option<Arc<dyn HttpClient>> {
+        self.client.with_connection_options(options)
+    }
 }
 
 impl HttpClient for HttpClientWithUrl {
@@ -197,19 +227,62 @@ impl HttpClient for HttpClientWithUrl {
     fn proxy(&self) -> Option<&Uri> {
         self.client.proxy.as_ref()
     }
+
+    fn with_connection_options(&self, options: ConnectionOptions) -> Option<Arc<dyn HttpClient>> {
+        self.client.with_connection_options(options)
+    }
+}
+
+/// Tuning for the connections an [`HttpClient`] keeps open, for servers that
+/// drop long-lived connections under the default settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConnectionOptions {
+    /// How often to send TCP keep-alive probes on idle connections.
+    pub tcp_keepalive: Option<Duration>,
+    /// The most connections to keep open to each host.
+    pub max_connections_per_host: Option<usize>,
+    /// How long an idle connection is kept for reuse.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl ConnectionOptions {
+    /// Returns these options, with those left unset taken from `other`.
+    pub fn or(self, other: ConnectionOptions) -> ConnectionOptions {
+        ConnectionOptions {
+            tcp_keepalive: self.tcp_keepalive.or(other.tcp_keepalive),
+            max_connections_per_host: self
+                .max_connections_per_host
+                .or(other.max_connections_per_host),
+            idle_timeout: self.idle_timeout.or(other.idle_timeout),
+        }
+    }
 }
 
 pub fn client(proxy: Option<Uri>) -> Arc<dyn HttpClient> {
+    client_with_options(proxy, ConnectionOptions::default())
+}
+
+pub fn client_with_options(proxy: Option<Uri>, options: ConnectionOptions) -> Arc<dyn HttpClient> {
+    let mut builder = isahc::HttpClient::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .low_speed_timeout(100, Duration::from_secs(5))
+        .proxy(proxy.clone());
+    if let Some(tcp_keepalive) = options.tcp_keepalive {
+        builder = builder.tcp_keepalive(tcp_keepalive);
+    }
+    if let Some(max_connections_per_host) = options.max_connections_per_host {
+        builder = builder
+            .max_connections_per_host(max_connections_per_host)
+            .connection_cache_size(max_connections_per_host);
+    }
+    if let Some(idle_timeout) = options.idle_timeout {
+        builder = builder.connection_cache_ttl(idle_timeout);
+    }
+
     Arc::new(HttpClientWithProxy {
-        client: Arc::new(
-            isahc::HttpClient::builder()
-                .connect_timeout(Duration::from_secs(5))
-                .low_speed_timeout(100, Duration::from_secs(5))
-                .proxy(proxy.clone())
-                .build()
-                .unwrap(),
-        ),
+        client: Arc::new(builder.build().unwrap()),
         proxy,
+        options: Some(options),
     })
 }
 
@@ -273,6 +346,7 @@ impl FakeHttpClient {
                     handler: Box::new(move |req| Box::pin(handler(req))),
                 }),
                 proxy: None,
+                options: None,
             },
         })
     }
@@ -0,0 +1,270 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_compression::futures::bufread::{GzipDecoder, ZlibDecoder};
+use futures::{
+    future::BoxFuture, io::BufReader, ready, AsyncBufReadExt as _, AsyncRead, FutureExt as _,
+};
+use isahc::http::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH};
+
+use crate::{AsyncBody, Error, HttpClient, Request, Response, Uri};
+
+/// The error reading a response body fails with once it's larger than the
+/// limit, wrapped in an [`io::Error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResponseTooLarge {
+    /// The most bytes the body could have, once decoded.
+    pub limit: u64,
+}
+
+impl ResponseTooLarge {
+    /// Returns the [`ResponseTooLarge`] error the given error was caused by,
+    /// if any.
+    pub fn find(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|error| {
+            error.downcast_ref::<Self>().copied().or_else(|| {
+                error
+                    .downcast_ref::<io::Error>()
+                    .and_then(|error| error.get_ref())
+                    .and_then(|error| error.downcast_ref::<Self>())
+                    .copied()
+            })
+        })
+    }
+}
+
+impl fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "response body is larger than the limit of {} bytes",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+/// An [`HttpClient`] that decodes response bodies compressed with gzip or
+/// deflate, and stops reading bodies larger than a limit, so that a
+/// misbehaving server can't flood us with output.
+pub struct LimitedHttpClient {
+    client: Arc<dyn HttpClient>,
+    max_response_size: u64,
+}
+
+impl LimitedHttpClient {
+    /// Returns a client that sends requests with the given client, and
+    /// limits the bodies of their responses to `max_response_size` bytes.
+    pub fn new(client: Arc<dyn HttpClient>, max_response_size: u64) -> Self {
+        Self {
+            client,
+            max_response_size,
+        }
+    }
+}
+
+impl HttpClient for LimitedHttpClient {
+    fn send(
+        &self,
+        req: Request<AsyncBody>,
+    ) -> BoxFuture<'static, Result<Response<AsyncBody>, Error>> {
+        let response = self.client.send(req);
+        let max_response_size = self.max_response_size;
+        async move { limit_response(response.await?, max_response_size).await }.boxed()
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        self.client.proxy()
+    }
+}
+
+async fn limit_response(
+    response: Response<AsyncBody>,
+    max_response_size: u64,
+) -> Result<Response<AsyncBody>, Error> {
+    let (mut parts, body) = response.into_parts();
+
+    // Bodies that say they're too large fail on their first read, without
+    // waiting for the rest of them.
+    let content_length = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.map_or(false, |content_length| content_length > max_response_size) {
+        return Ok(Response::from_parts(
+            parts,
+            AsyncBody::from_reader(LimitedReader {
+                inner: Box::new(body),
+                remaining: 0,
+                limit: max_response_size,
+            }),
+        ));
+    }
+
+    let mut body = BufReader::new(body);
+    let body: Box<dyn AsyncRead + Send + Sync + Unpin> =
+        match ContentEncoding::from_headers(&parts.headers) {
+            Some(encoding) if encoding.is_encoded(body.fill_buf().await?) => {
+                parts.headers.remove(CONTENT_ENCODING);
+                parts.headers.remove(CONTENT_LENGTH);
+                match encoding {
+                    ContentEncoding::Gzip => Box::new(GzipDecoder::new(body)),
+                    ContentEncoding::Deflate => Box::new(ZlibDecoder::new(body)),
+                }
+            }
+            _ => Box::new(body),
+        };
+    Ok(Response::from_parts(
+        parts,
+        AsyncBody::from_reader(LimitedReader {
+            inner: body,
+            remaining: max_response_size,
+            limit: max_response_size,
+        }),
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(CONTENT_ENCODING)?.to_str().ok()?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a body starting with the given bytes is still
+    /// encoded. The connection may have decoded it already, while leaving
+    /// the header in place.
+    fn is_encoded(self, prefix: &[u8]) -> bool {
+        match self {
+            Self::Gzip => prefix.starts_with(&[0x1f, 0x8b]),
+            // The header of a zlib stream compressed with deflate.
+            Self::Deflate => match prefix {
+                [cmf, flg, ..] => {
+                    cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+struct LimitedReader {
+    inner: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    remaining: u64,
+    limit: u64,
+}
+
+impl AsyncRead for LimitedReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if read as u64 > this.remaining {
+            this.remaining = 0;
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Other,
+                ResponseTooLarge { limit: this.limit },
+            )));
+        }
+        this.remaining -= read as u64;
+        Poll::Ready(Ok(read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::futures::bufread::GzipEncoder;
+    use futures::{executor::block_on, AsyncReadExt as _};
+
+    fn response(headers: &[(&str, &str)], body: Vec<u8>) -> Response<AsyncBody> {
+        let mut builder = Response::builder().status(200);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(AsyncBody::from(body)).unwrap()
+    }
+
+    fn read_body(response: Response<AsyncBody>, max_response_size: u64) -> io::Result<String> {
+        block_on(async {
+            let response = limit_response(response, max_response_size)
+                .await
+                .map_err(io::Error::other)?;
+            let mut body = String::new();
+            response.into_body().read_to_string(&mut body).await?;
+            Ok(body)
+        })
+    }
+
+    #[test]
+    fn test_decoding_compressed_responses() {
+        let text = "data: {\"choices\": []}\n\n".repeat(100);
+        let mut gzipped = Vec::new();
+        block_on(GzipEncoder::new(text.as_bytes()).read_to_end(&mut gzipped)).unwrap();
+
+        let body = read_body(response(&[("Content-Encoding", "gzip")], gzipped), 1 << 20);
+        assert_eq!(body.unwrap(), text);
+
+        // Bodies that were already decoded are left as they are.
+        let body = read_body(
+            response(&[("Content-Encoding", "gzip")], text.clone().into_bytes()),
+            1 << 20,
+        );
+        assert_eq!(body.unwrap(), text);
+    }
+
+    #[test]
+    fn test_response_size_limit() {
+        let text = "x".repeat(1000);
+        assert_eq!(
+            read_body(response(&[], text.clone().into_bytes()), 1000).unwrap(),
+            text
+        );
+
+        let error = read_body(response(&[], text.clone().into_bytes()), 999).unwrap_err();
+        assert_eq!(
+            ResponseTooLarge::find(&error.into()),
+            Some(ResponseTooLarge { limit: 999 })
+        );
+
+        let error = read_body(
+            response(&[("Content-Length", "1000")], text.into_bytes()),
+            10,
+        )
+        .unwrap_err();
+        assert_eq!(
+            ResponseTooLarge::find(&error.into()),
+            Some(ResponseTooLarge { limit: 10 })
+        );
+
+        // The limit applies to the decoded body.
+        let mut gzipped = Vec::new();
+        block_on(GzipEncoder::new("x".repeat(10_000).as_bytes()).read_to_end(&mut gzipped))
+            .unwrap();
+        assert!(gzipped.len() < 1000);
+        let error =
+            read_body(response(&[("Content-Encoding", "gzip")], gzipped), 1000).unwrap_err();
+        assert_eq!(
+            ResponseTooLarge::find(&error.into()),
+            Some(ResponseTooLarge { limit: 1000 })
+        );
+    }
+}
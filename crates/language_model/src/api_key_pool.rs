@@ -0,0 +1,248 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use gpui::AsyncAppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::org_vault::{resolve_api_key, OrgVault};
+
+/// How long a key is skipped after the provider rate limits it.
+const RATE_LIMITED_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// How long a key is skipped after the provider rejects it. Rejected keys
+/// rarely start working again until they're replaced.
+const REJECTED_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// The environment variables to read a provider's API key from: a single one,
+/// or several whose keys are rotated between when one is rejected or rate
+/// limited.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ApiKeyEnv {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl ApiKeyEnv {
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            ApiKeyEnv::Single(name) => vec![name.clone()],
+            ApiKeyEnv::Multiple(names) => names.clone(),
+        }
+    }
+}
+
+/// Reads a provider's API keys from the environment variables in its
+/// settings, or from `default_name` when none are configured.
+pub(crate) fn api_keys_from_env(
+    api_key_env: Option<&ApiKeyEnv>,
+    default_name: &str,
+) -> Option<ApiKeyPool> {
+    let names = api_key_env.map_or_else(|| vec![default_name.to_string()], ApiKeyEnv::names);
+    let pool = ApiKeyPool::from_env(&names);
+    (!pool.is_empty()).then_some(pool)
+}
+
+struct PooledKey {
+    key: String,
+    /// When the key can be used again, if it's cooling down.
+    cooldown_until: Option<Instant>,
+}
+
+/// The API keys a provider's requests can be sent with, in order of
+/// preference.
+#[derive(Default)]
+pub struct ApiKeyPool {
+    keys: Vec<PooledKey>,
+    current: usize,
+}
+
+impl ApiKeyPool {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| PooledKey {
+                    key,
+                    cooldown_until: None,
+                })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    /// Reads the keys from the given environment variables, skipping those
+    /// that aren't set.
+    pub fn from_env(names: &[String]) -> Self {
+        Self::new(names.iter().filter_map(|name| std::env::var(name).ok()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the key to send the next request with: the current key, or the
+    /// next one that isn't cooling down.
+    pub fn key(&mut self, now: Instant) -> Option<String> {
+        let len = self.keys.len();
+        let index = (0..len)
+            .map(|offset| (self.current + offset) % len)
+            .find(|index| {
+                self.keys[*index]
+                    .cooldown_until
+                    .map_or(true, |cooldown_until| now >= cooldown_until)
+            })?;
+        self.current = index;
+        self.keys[index].cooldown_until = None;
+        Some(self.keys[index].key.clone())
+    }
+
+    /// Records that a request sent with the key failed with the given HTTP
+    /// status. Keys that were rejected or rate limited cool down, so that
+    /// requests move on to the next key.
+    ///
+    /// Returns whether the request should be retried with another key.
+    pub fn record_failure(&mut self, key: &str, status: u16, now: Instant) -> bool {
+        let cooldown = match status {
+            401 | 403 => REJECTED_COOLDOWN,
+            429 => RATE_LIMITED_COOLDOWN,
+            _ => return false,
+        };
+        let Some(pooled_key) = self
+            .keys
+            .iter_mut()
+            .find(|pooled_key| pooled_key.key == key)
+        else {
+            return false;
+        };
+        pooled_key.cooldown_until = Some(now + cooldown);
+        true
+    }
+}
+
+/// Sends a request with the pool's keys in turn, until one of them isn't
+/// rejected or rate limited. `status` returns the HTTP status an error was
+/// caused by, if any.
+pub async fn send_with_failover<T, F, Fut>(
+    pool: &Mutex<ApiKeyPool>,
+    status: impl Fn(&anyhow::Error) -> Option<u16>,
+    mut send: F,
+) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut last_error = None;
+    loop {
+        let key = pool.lock().unwrap().key(Instant::now());
+        let Some(key) = key else {
+            return Err(last_error.unwrap_or_else(|| {
+                anyhow!("all API keys are cooling down after being rejected or rate limited")
+            }));
+        };
+        match send(key.clone()).await {
+            Err(error) => {
+                let should_retry = status(&error).map_or(false, |status| {
+                    pool.lock()
+                        .unwrap()
+                        .record_failure(&key, status, Instant::now())
+                });
+                if !should_retry {
+                    return Err(error);
+                }
+                last_error = Some(error);
+            }
+            response => return response,
+        }
+    }
+}
+
+/// Sends a provider request with the leased organization secret when the
+/// settings refer to one, with the keys from the environment in turn when
+/// there are any, or else with the key the user entered.
+///
+/// A leased secret the provider rejects as unauthorized is leased again and
+/// the request retried once, as the secret may have been rotated since.
+pub(crate) async fn send_with_api_key<T, F, Fut>(
+    api_key_ref: Option<String>,
+    api_key: Option<String>,
+    api_key_pool: Option<Arc<Mutex<ApiKeyPool>>>,
+    status: impl Fn(&anyhow::Error) -> Option<u16>,
+    cx: &AsyncAppContext,
+    mut send: F,
+) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    match (api_key_ref, api_key_pool) {
+        (None, Some(api_key_pool)) => send_with_failover(&api_key_pool, status, send).await,
+        (Some(api_key_ref), _) => {
+            let api_key = OrgVault::lease(&api_key_ref, cx).await?;
+            match send(api_key).await {
+                Err(error) if status(&error) == Some(401) => {
+                    OrgVault::invalidate(&api_key_ref, cx);
+                    send(OrgVault::lease(&api_key_ref, cx).await?).await
+                }
+                response => response,
+            }
+        }
+        (None, None) => send(resolve_api_key(None, api_key, cx).await?).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_key_rotation() {
+        let mut pool = ApiKeyPool::new(["a".to_string(), "b".to_string()]);
+        let now = Instant::now();
+        assert_eq!(pool.key(now).as_deref(), Some("a"));
+
+        // Other failures don't move requests to another key.
+        assert!(!pool.record_failure("a", 500, now));
+        assert_eq!(pool.key(now).as_deref(), Some("a"));
+
+        assert!(pool.record_failure("a", 429, now));
+        assert_eq!(pool.key(now).as_deref(), Some("b"));
+        assert!(pool.record_failure("b", 401, now));
+        assert_eq!(pool.key(now), None);
+
+        // Keys are used again once they cool down.
+        let later = now + RATE_LIMITED_COOLDOWN;
+        assert_eq!(pool.key(later).as_deref(), Some("a"));
+        let much_later = now + REJECTED_COOLDOWN;
+        assert_eq!(pool.key(much_later).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_send_with_failover() {
+        let pool = Mutex::new(ApiKeyPool::new(["a".to_string(), "b".to_string()]));
+        let status = |error: &anyhow::Error| error.to_string().parse::<u16>().ok();
+
+        let response = block_on(send_with_failover(&pool, status, |key| async move {
+            match key.as_str() {
+                "a" => Err(anyhow!("429")),
+                _ => Ok(key),
+            }
+        }));
+        assert_eq!(response.unwrap(), "b");
+
+        // Once every key is exhausted, the last error is returned.
+        let response = block_on(send_with_failover(&pool, status, |key| async move {
+            match key.as_str() {
+                "b" => Err::<String, _>(anyhow!("401")),
+                _ => unreachable!(),
+            }
+        }));
+        assert_eq!(response.unwrap_err().to_string(), "401");
+    }
+}
@@ -0,0 +1,372 @@
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use client::{proto, Client};
+use gpui::{AppContext, AsyncAppContext, Global, Model, ModelContext, Task};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use settings::Settings;
+use ui::Context;
+
+use crate::settings::AllLanguageModelSettings;
+
+/// The keychain entry under which the sync passphrase is remembered on this device.
+const PASSPHRASE_CREDENTIALS_URL: &str = "https://zed.dev/api-key-sync";
+
+const CIPHERTEXT_VERSION: u8 = 1;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+/// Guards against ciphertexts that would make us spin forever deriving a key.
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 1 + 4 + SALT_LEN + NONCE_LEN;
+
+pub fn init(client: Arc<Client>, cx: &mut AppContext) {
+    let api_key_sync = cx.new_model(|cx| {
+        let read_passphrase = cx.read_credentials(PASSPHRASE_CREDENTIALS_URL);
+        cx.spawn(|this, mut cx| async move {
+            if let Some((_, passphrase)) = read_passphrase.await? {
+                let passphrase = String::from_utf8(passphrase)?;
+                this.update(&mut cx, |this, cx| {
+                    this.passphrase = Some(passphrase.into());
+                    cx.notify();
+                })?;
+            }
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+
+        ApiKeySync {
+            client,
+            passphrase: None,
+        }
+    });
+    cx.set_global(GlobalApiKeySync(api_key_sync));
+}
+
+struct GlobalApiKeySync(Model<ApiKeySync>);
+
+impl Global for GlobalApiKeySync {}
+
+/// Syncs provider API keys between a user's devices via zed.dev.
+///
+/// Keys are encrypted with a key derived from a passphrase before they leave
+/// the device, so the server only ever sees ciphertext. Syncing is opt-in via
+/// the `language_models.sync_api_keys` setting.
+pub struct ApiKeySync {
+    client: Arc<Client>,
+    passphrase: Option<Arc<str>>,
+}
+
+impl ApiKeySync {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalApiKeySync>()
+            .map(|api_key_sync| api_key_sync.0.clone())
+    }
+
+    pub fn is_enabled(cx: &AppContext) -> bool {
+        AllLanguageModelSettings::get_global(cx).sync_api_keys
+    }
+
+    /// Returns whether the user needs to enter their passphrase before keys
+    /// can be synced.
+    pub fn needs_passphrase(cx: &AppContext) -> bool {
+        Self::is_enabled(cx)
+            && Self::global(cx).map_or(false, |this| this.read(cx).passphrase.is_none())
+    }
+
+    /// Uses the given passphrase to sync keys from now on, remembering it in
+    /// the system keychain.
+    ///
+    /// Fails if the passphrase can't decrypt the keys that were already synced.
+    pub fn unlock(&mut self, passphrase: String, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let passphrase: Arc<str> = passphrase.into();
+        let secrets = self.client.request(proto::GetUserSecrets {});
+        cx.spawn(|this, mut cx| async move {
+            let secrets = secrets.await?.secrets;
+            if let Some(secret) = secrets.into_iter().next() {
+                let passphrase = passphrase.clone();
+                cx.background_executor()
+                    .spawn(async move { decrypt(&passphrase, &secret.name, &secret.ciphertext) })
+                    .await
+                    .context("incorrect passphrase")?;
+            }
+
+            cx.update(|cx| {
+                cx.write_credentials(
+                    PASSPHRASE_CREDENTIALS_URL,
+                    "passphrase",
+                    passphrase.as_bytes(),
+                )
+            })?
+            .await?;
+
+            this.update(&mut cx, |this, cx| {
+                this.passphrase = Some(passphrase);
+                cx.notify();
+            })
+        })
+    }
+
+    fn download_api_key(
+        &self,
+        provider_id: &'static str,
+        cx: &AppContext,
+    ) -> Task<Result<Option<String>>> {
+        let Some(passphrase) = self.passphrase.clone() else {
+            return Task::ready(Ok(None));
+        };
+        let secrets = self.client.request(proto::GetUserSecrets {});
+        cx.background_executor().spawn(async move {
+            let Some(secret) = secrets
+                .await?
+                .secrets
+                .into_iter()
+                .find(|secret| secret.name == provider_id)
+            else {
+                return Ok(None);
+            };
+            let api_key = decrypt(&passphrase, provider_id, &secret.ciphertext)?;
+            Ok(Some(String::from_utf8(api_key)?))
+        })
+    }
+
+    fn upload_api_key(
+        &self,
+        provider_id: &'static str,
+        api_key: String,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let Some(passphrase) = self.passphrase.clone() else {
+            return Task::ready(Ok(()));
+        };
+        let client = self.client.clone();
+        cx.background_executor().spawn(async move {
+            let ciphertext = encrypt(
+                &passphrase,
+                provider_id,
+                api_key.as_bytes(),
+                PBKDF2_ITERATIONS,
+            )?;
+            client
+                .request(proto::UpdateUserSecret {
+                    secret: Some(proto::UserSecret {
+                        name: provider_id.to_string(),
+                        ciphertext,
+                    }),
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn delete_api_key(&self, provider_id: &'static str, cx: &AppContext) -> Task<Result<()>> {
+        if self.passphrase.is_none() {
+            return Task::ready(Ok(()));
+        }
+        let request = self.client.request(proto::DeleteUserSecret {
+            name: provider_id.to_string(),
+        });
+        cx.background_executor().spawn(async move {
+            request.await?;
+            Ok(())
+        })
+    }
+}
+
+/// Loads a provider's API key from the system keychain, falling back to the
+/// key synced from the user's other devices.
+///
+/// A synced key is written to the keychain, so that it is available offline.
+pub(crate) async fn load_api_key(
+    provider_id: &'static str,
+    credentials_url: String,
+    cx: &mut AsyncAppContext,
+) -> Result<Option<String>> {
+    if let Some((_, api_key)) = cx
+        .update(|cx| cx.read_credentials(&credentials_url))?
+        .await?
+    {
+        return Ok(Some(String::from_utf8(api_key)?));
+    }
+
+    let Some(download_api_key) = cx.update(|cx| {
+        let api_key_sync = ApiKeySync::global(cx).filter(|_| ApiKeySync::is_enabled(cx))?;
+        Some(api_key_sync.read(cx).download_api_key(provider_id, cx))
+    })?
+    else {
+        return Ok(None);
+    };
+    let Some(api_key) = download_api_key.await? else {
+        return Ok(None);
+    };
+
+    cx.update(|cx| cx.write_credentials(&credentials_url, "Bearer", api_key.as_bytes()))?
+        .await?;
+    Ok(Some(api_key))
+}
+
+/// Saves a provider's API key entered by the user, returning the saved key.
+///
+/// If a passphrase is given, it unlocks syncing first. If no API key is given,
+/// the key synced from the user's other devices is used instead.
+pub(crate) fn save_api_key(
+    provider_id: &'static str,
+    credentials_url: String,
+    api_key: String,
+    passphrase: Option<String>,
+    cx: &mut AppContext,
+) -> Task<Result<String>> {
+    let api_key_sync = ApiKeySync::global(cx).filter(|_| ApiKeySync::is_enabled(cx));
+    let unlock = passphrase
+        .zip(api_key_sync.clone())
+        .map(|(passphrase, api_key_sync)| {
+            api_key_sync.update(cx, |api_key_sync, cx| api_key_sync.unlock(passphrase, cx))
+        });
+
+    cx.spawn(|mut cx| async move {
+        if let Some(unlock) = unlock {
+            unlock.await?;
+        }
+
+        if api_key.is_empty() {
+            return load_api_key(provider_id, credentials_url, &mut cx)
+                .await?
+                .ok_or_else(|| anyhow!("no API key has been synced"));
+        }
+
+        cx.update(|cx| cx.write_credentials(&credentials_url, "Bearer", api_key.as_bytes()))?
+            .await?;
+        if let Some(api_key_sync) = api_key_sync {
+            api_key_sync
+                .update(&mut cx, |api_key_sync, cx| {
+                    api_key_sync.upload_api_key(provider_id, api_key.clone(), cx)
+                })?
+                .await?;
+        }
+        Ok(api_key)
+    })
+}
+
+/// Deletes a provider's API key from the system keychain and from the keys
+/// synced to the user's other devices.
+pub(crate) fn delete_api_key(
+    provider_id: &'static str,
+    credentials_url: &str,
+    cx: &AppContext,
+) -> Task<Result<()>> {
+    let delete_credentials = cx.delete_credentials(credentials_url);
+    let delete_synced_api_key = ApiKeySync::global(cx)
+        .filter(|_| ApiKeySync::is_enabled(cx))
+        .map(|api_key_sync| api_key_sync.read(cx).delete_api_key(provider_id, cx));
+    cx.background_executor().spawn(async move {
+        delete_credentials.await?;
+        if let Some(delete_synced_api_key) = delete_synced_api_key {
+            delete_synced_api_key.await?;
+        }
+        Ok(())
+    })
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| anyhow!("invalid iteration count"))?;
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypts a secret with a key derived from the passphrase.
+///
+/// The result is laid out as `version || iterations || salt || nonce ||
+/// ciphertext || tag`. The secret's name is used as associated data, so that
+/// a ciphertext can't be passed off as a different secret.
+fn encrypt(passphrase: &str, name: &str, plaintext: &[u8], iterations: u32) -> Result<Vec<u8>> {
+    let rng = SystemRandom::new();
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow!("failed to generate random bytes"))?;
+
+    let key = derive_key(passphrase, &salt, iterations)?;
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(name.as_bytes()),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow!("failed to encrypt {name}"))?;
+
+    let mut ciphertext = Vec::with_capacity(HEADER_LEN + in_out.len());
+    ciphertext.push(CIPHERTEXT_VERSION);
+    ciphertext.extend_from_slice(&iterations.to_be_bytes());
+    ciphertext.extend_from_slice(&salt);
+    ciphertext.extend_from_slice(&nonce);
+    ciphertext.extend_from_slice(&in_out);
+    Ok(ciphertext)
+}
+
+/// Decrypts a secret that was previously produced by [`encrypt`].
+fn decrypt(passphrase: &str, name: &str, ciphertext: &[u8]) -> Result<Vec<u8>> {
+    if ciphertext.len() < HEADER_LEN {
+        bail!("malformed ciphertext for {name}");
+    }
+    let (version, rest) = ciphertext.split_at(1);
+    if version[0] != CIPHERTEXT_VERSION {
+        bail!("unsupported ciphertext version {} for {name}", version[0]);
+    }
+    let (iterations, rest) = rest.split_at(4);
+    let iterations = u32::from_be_bytes(iterations.try_into()?);
+    if iterations > MAX_PBKDF2_ITERATIONS {
+        bail!("too many iterations for {name}");
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt, iterations)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow!("malformed nonce for {name}"))?;
+    let mut in_out = sealed.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(name.as_bytes()), &mut in_out)
+        .map_err(|_| anyhow!("failed to decrypt {name}"))?;
+    Ok(plaintext.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let ciphertext = encrypt("correct horse", "openai", b"sk-1234", 1_000).unwrap();
+        assert!(!ciphertext
+            .windows(b"sk-1234".len())
+            .any(|window| window == b"sk-1234"));
+        assert_eq!(
+            decrypt("correct horse", "openai", &ciphertext).unwrap(),
+            b"sk-1234"
+        );
+
+        // The wrong passphrase can't decrypt the secret.
+        assert!(decrypt("battery staple", "openai", &ciphertext).is_err());
+
+        // A ciphertext can't be passed off as another secret.
+        assert!(decrypt("correct horse", "anthropic", &ciphertext).is_err());
+
+        // Tampering with the ciphertext is detected.
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt("correct horse", "openai", &tampered).is_err());
+        assert!(decrypt("correct horse", "openai", &ciphertext[..HEADER_LEN - 1]).is_err());
+    }
+}
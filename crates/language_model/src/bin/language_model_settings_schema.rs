@@ -0,0 +1,15 @@
+use std::{env, fs};
+
+use anyhow::Context as _;
+
+/// Usage: language_model_settings_schema [OUTPUT_PATH]
+fn main() -> anyhow::Result<()> {
+    let schema =
+        serde_json::to_string_pretty(&language_model::settings::settings_file_json_schema())?;
+    match env::args().nth(1) {
+        Some(path) => fs::write(&path, format!("{schema}\n"))
+            .with_context(|| format!("failed to write schema to {path}"))?,
+        None => println!("{schema}"),
+    }
+    Ok(())
+}
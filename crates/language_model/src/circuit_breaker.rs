@@ -0,0 +1,241 @@
+use std::time::{Duration, Instant};
+
+use collections::HashMap;
+use futures::channel::mpsc::UnboundedSender;
+
+use crate::LanguageModelProviderId;
+
+/// When to stop sending requests to a provider that keeps failing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CircuitBreakerSettings {
+    /// The number of consecutive failed requests after which the provider is
+    /// disabled, or 0 to never disable it.
+    pub failure_threshold: u32,
+    /// How long the provider stays disabled before a request is let through
+    /// to probe whether it has recovered.
+    pub cooldown: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent to the provider as usual.
+    #[default]
+    Closed,
+    /// The provider is disabled after repeated failures, and requests to it
+    /// fail immediately.
+    Open,
+    /// The provider's cooldown has elapsed, and a single request is being let
+    /// through to probe whether it has recovered.
+    HalfOpen,
+}
+
+/// Emitted by the [`crate::LanguageModelRegistry`] when a provider is disabled
+/// or re-enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitStateChanged {
+    pub provider_id: LanguageModelProviderId,
+    pub state: CircuitState,
+}
+
+#[derive(Default)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// When the circuit was opened, or when the current probe was let through.
+    changed_at: Option<Instant>,
+}
+
+impl Circuit {
+    fn is_available(&self, settings: &CircuitBreakerSettings, now: Instant) -> bool {
+        let cooldown_elapsed = self
+            .changed_at
+            .map_or(true, |changed_at| now >= changed_at + settings.cooldown);
+        match self.state {
+            CircuitState::Closed => true,
+            // A probe that hasn't completed within the cooldown was most
+            // likely cancelled, so another one is let through.
+            CircuitState::Open | CircuitState::HalfOpen => cooldown_elapsed,
+        }
+    }
+}
+
+/// Tracks consecutive failures per provider, disabling providers that keep
+/// failing so that requests fail fast rather than waiting on them.
+#[derive(Default)]
+pub struct CircuitBreakers {
+    circuits: HashMap<LanguageModelProviderId, Circuit>,
+    state_changes: Option<UnboundedSender<CircuitStateChanged>>,
+}
+
+impl CircuitBreakers {
+    pub(crate) fn new(state_changes: UnboundedSender<CircuitStateChanged>) -> Self {
+        Self {
+            circuits: HashMap::default(),
+            state_changes: Some(state_changes),
+        }
+    }
+
+    pub fn state(&self, provider_id: &LanguageModelProviderId) -> CircuitState {
+        self.circuits
+            .get(provider_id)
+            .map_or(CircuitState::Closed, |circuit| circuit.state)
+    }
+
+    /// Returns whether requests may currently be sent to the provider.
+    pub fn is_available(
+        &self,
+        provider_id: &LanguageModelProviderId,
+        settings: &CircuitBreakerSettings,
+        now: Instant,
+    ) -> bool {
+        settings.failure_threshold == 0
+            || self
+                .circuits
+                .get(provider_id)
+                .map_or(true, |circuit| circuit.is_available(settings, now))
+    }
+
+    /// Returns whether a request may be sent to the provider. Once a disabled
+    /// provider's cooldown elapses, a single request is let through as a
+    /// probe.
+    pub fn try_acquire(
+        &mut self,
+        provider_id: &LanguageModelProviderId,
+        settings: &CircuitBreakerSettings,
+        now: Instant,
+    ) -> bool {
+        if !self.is_available(provider_id, settings, now) {
+            return false;
+        }
+        if self.state(provider_id) != CircuitState::Closed {
+            self.transition(provider_id, CircuitState::HalfOpen, now);
+        }
+        true
+    }
+
+    pub fn record_success(&mut self, provider_id: &LanguageModelProviderId, now: Instant) {
+        let circuit = self.circuits.entry(provider_id.clone()).or_default();
+        circuit.consecutive_failures = 0;
+        if circuit.state != CircuitState::Closed {
+            self.transition(provider_id, CircuitState::Closed, now);
+        }
+    }
+
+    pub fn record_failure(
+        &mut self,
+        provider_id: &LanguageModelProviderId,
+        settings: &CircuitBreakerSettings,
+        now: Instant,
+    ) {
+        if settings.failure_threshold == 0 {
+            return;
+        }
+        let circuit = self.circuits.entry(provider_id.clone()).or_default();
+        circuit.consecutive_failures += 1;
+        let should_open = match circuit.state {
+            CircuitState::Closed => circuit.consecutive_failures >= settings.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            self.transition(provider_id, CircuitState::Open, now);
+        }
+    }
+
+    fn transition(
+        &mut self,
+        provider_id: &LanguageModelProviderId,
+        state: CircuitState,
+        now: Instant,
+    ) {
+        let circuit = self.circuits.entry(provider_id.clone()).or_default();
+        let changed = circuit.state != state;
+        circuit.state = state;
+        circuit.changed_at = Some(now);
+        if changed {
+            if let Some(state_changes) = self.state_changes.as_ref() {
+                state_changes
+                    .unbounded_send(CircuitStateChanged {
+                        provider_id: provider_id.clone(),
+                        state,
+                    })
+                    .ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc, StreamExt};
+
+    #[test]
+    fn test_circuit_breaker() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut breakers = CircuitBreakers::new(tx);
+        let provider_id = LanguageModelProviderId::from("openai".to_string());
+        let settings = CircuitBreakerSettings {
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+        };
+        let now = Instant::now();
+
+        // Successes reset the count of consecutive failures.
+        breakers.record_failure(&provider_id, &settings, now);
+        breakers.record_failure(&provider_id, &settings, now);
+        breakers.record_success(&provider_id, now);
+        breakers.record_failure(&provider_id, &settings, now);
+        breakers.record_failure(&provider_id, &settings, now);
+        assert_eq!(breakers.state(&provider_id), CircuitState::Closed);
+        assert!(breakers.try_acquire(&provider_id, &settings, now));
+
+        breakers.record_failure(&provider_id, &settings, now);
+        assert_eq!(breakers.state(&provider_id), CircuitState::Open);
+        assert!(!breakers.try_acquire(&provider_id, &settings, now + Duration::from_secs(10)));
+
+        // After the cooldown, a single probe is let through, and a failed
+        // probe disables the provider again.
+        let later = now + Duration::from_secs(30);
+        assert!(breakers.try_acquire(&provider_id, &settings, later));
+        assert_eq!(breakers.state(&provider_id), CircuitState::HalfOpen);
+        assert!(!breakers.try_acquire(&provider_id, &settings, later));
+        breakers.record_failure(&provider_id, &settings, later);
+        assert_eq!(breakers.state(&provider_id), CircuitState::Open);
+
+        // A successful probe re-enables the provider.
+        let even_later = later + Duration::from_secs(30);
+        assert!(breakers.try_acquire(&provider_id, &settings, even_later));
+        breakers.record_success(&provider_id, even_later);
+        assert_eq!(breakers.state(&provider_id), CircuitState::Closed);
+
+        drop(breakers);
+        let states = futures::executor::block_on(rx.collect::<Vec<_>>())
+            .into_iter()
+            .map(|change| change.state)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            [
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_circuit_breaker() {
+        let mut breakers = CircuitBreakers::default();
+        let provider_id = LanguageModelProviderId::from("openai".to_string());
+        let settings = CircuitBreakerSettings::default();
+        let now = Instant::now();
+        for _ in 0..10 {
+            breakers.record_failure(&provider_id, &settings, now);
+        }
+        assert_eq!(breakers.state(&provider_id), CircuitState::Closed);
+        assert!(breakers.try_acquire(&provider_id, &settings, now));
+    }
+}
@@ -0,0 +1,98 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// An event in a streamed completion.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum LanguageModelCompletionEvent {
+    /// The next chunk of the output.
+    Text(String),
+    /// A source the model attributed some of its output to.
+    Citation(Citation),
+    /// An event as the provider sent it, before being translated into the
+    /// events that follow it. Only streamed for requests that opt into
+    /// [`raw_events`](crate::LanguageModelRequest::raw_events), so that
+    /// fields we don't translate yet, such as log probabilities or safety
+    /// ratings, can still be read.
+    Raw(serde_json::Value),
+}
+
+/// A source that a model attributed some of its output to, such as a web page
+/// it searched or a file it retrieved.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    /// The URL of the source, for web sources.
+    pub url: Option<String>,
+    /// The title of the source, or the name of the file for file sources.
+    pub title: Option<String>,
+    /// The provider's ID of the file, for file sources.
+    pub file_id: Option<String>,
+    /// The byte range of the output that is attributed to the source, when
+    /// the provider reports one.
+    pub output_range: Option<Range<usize>>,
+}
+
+/// Returns the text of the events, dropping everything else.
+pub fn text_from_events(
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent>>,
+) -> BoxStream<'static, Result<String>> {
+    events
+        .filter_map(|event| async move {
+            match event {
+                Ok(LanguageModelCompletionEvent::Text(text)) => Some(Ok(text)),
+                Ok(
+                    LanguageModelCompletionEvent::Citation(_)
+                    | LanguageModelCompletionEvent::Raw(_),
+                ) => None,
+                Err(error) => Some(Err(error)),
+            }
+        })
+        .boxed()
+}
+
+/// Converts a character offset into the output into a byte offset, for
+/// providers that report citations in characters.
+pub fn char_offset_to_byte_offset(output: &str, char_offset: usize) -> usize {
+    output
+        .char_indices()
+        .nth(char_offset)
+        .map_or(output.len(), |(byte_offset, _)| byte_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_text_from_events() {
+        let events = futures::stream::iter([
+            Ok(LanguageModelCompletionEvent::Raw(serde_json::json!({
+                "choices": [{ "delta": { "content": "Rust is " }, "logprobs": null }]
+            }))),
+            Ok(LanguageModelCompletionEvent::Text("Rust is ".into())),
+            Ok(LanguageModelCompletionEvent::Citation(Citation {
+                url: Some("https://www.rust-lang.org".into()),
+                ..Default::default()
+            })),
+            Ok(LanguageModelCompletionEvent::Text("fast.".into())),
+        ])
+        .boxed();
+        let text = block_on(text_from_events(events).collect::<Vec<_>>())
+            .into_iter()
+            .collect::<Result<String>>()
+            .unwrap();
+        assert_eq!(text, "Rust is fast.");
+    }
+
+    #[test]
+    fn test_char_offset_to_byte_offset() {
+        assert_eq!(char_offset_to_byte_offset("héllo", 0), 0);
+        assert_eq!(char_offset_to_byte_offset("héllo", 2), 3);
+        assert_eq!(char_offset_to_byte_offset("héllo", 5), 6);
+        assert_eq!(char_offset_to_byte_offset("héllo", 10), 6);
+    }
+}
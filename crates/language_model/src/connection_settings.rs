@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use gpui::AppContext;
+use http_client::{ConnectionOptions, HttpClient, LimitedHttpClient, Uri};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use crate::settings::AllLanguageModelSettings;
+
+/// Tuning for the HTTP connections to a provider. Some gateways drop
+/// long-lived streaming connections under the default settings, and
+/// reconnecting delays the first token of the next response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ConnectionSettings {
+    /// How often to send TCP keep-alive probes on idle connections.
+    pub keep_alive_in_seconds: Option<u64>,
+    /// The most connections to keep open to the provider. Each provider has
+    /// a pool of its own.
+    pub pool_size: Option<usize>,
+    /// How long an idle connection is kept for reuse.
+    pub idle_timeout_in_seconds: Option<u64>,
+    /// The largest response to read from the provider, once decoded, before
+    /// giving up on it.
+    ///
+    /// Default: 32
+    pub max_response_size_in_megabytes: Option<u64>,
+}
+
+/// The largest response read from a provider whose connection settings
+/// don't say otherwise.
+const DEFAULT_MAX_RESPONSE_SIZE_IN_MEGABYTES: u64 = 32;
+
+impl ConnectionSettings {
+    fn options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            tcp_keepalive: self.keep_alive_in_seconds.map(Duration::from_secs),
+            max_connections_per_host: self.pool_size.filter(|size| *size > 0),
+            idle_timeout: self.idle_timeout_in_seconds.map(Duration::from_secs),
+        }
+    }
+}
+
+/// Returns the HTTP client to send the provider's requests with: the given
+/// client, rebuilt with the provider's connection settings if it has any and
+/// the client can be rebuilt. Responses are decoded if they're compressed,
+/// and limited in size.
+pub fn http_client_for_provider(
+    provider_id: &str,
+    http_client: Arc<dyn HttpClient>,
+    cx: &AppContext,
+) -> Arc<dyn HttpClient> {
+    let settings = AllLanguageModelSettings::get_global(cx)
+        .connections
+        .get(provider_id);
+    let http_client = settings
+        .and_then(|settings| tuned_http_client(provider_id, settings.options(), &http_client))
+        .unwrap_or(http_client);
+    let max_response_size_in_megabytes = settings
+        .and_then(|settings| settings.max_response_size_in_megabytes)
+        .unwrap_or(DEFAULT_MAX_RESPONSE_SIZE_IN_MEGABYTES);
+    Arc::new(LimitedHttpClient::new(
+        http_client,
+        max_response_size_in_megabytes.saturating_mul(1024 * 1024),
+    ))
+}
+
+/// Returns the given client, rebuilt with the provider's connection options,
+/// or `None` if it can't be rebuilt.
+///
+/// Clients are shared between the provider's requests, so that they reuse
+/// their connections, but not between providers, so that each provider's
+/// pool size applies to its connections alone.
+fn tuned_http_client(
+    provider_id: &str,
+    options: ConnectionOptions,
+    http_client: &Arc<dyn HttpClient>,
+) -> Option<Arc<dyn HttpClient>> {
+    static CLIENTS: OnceLock<
+        Mutex<HashMap<(String, ConnectionOptions, Option<Uri>), Arc<dyn HttpClient>>>,
+    > = OnceLock::new();
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    let key = (
+        provider_id.to_string(),
+        options,
+        http_client.proxy().cloned(),
+    );
+    if let Some(client) = clients.get(&key) {
+        return Some(client.clone());
+    }
+    let client = http_client.with_connection_options(options)?;
+    clients.insert(key, client.clone());
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuned_http_clients_are_shared() {
+        let settings = ConnectionSettings {
+            keep_alive_in_seconds: Some(30),
+            pool_size: Some(4),
+            idle_timeout_in_seconds: Some(90),
+            max_response_size_in_megabytes: None,
+        };
+        assert_eq!(
+            settings.options(),
+            ConnectionOptions {
+                tcp_keepalive: Some(Duration::from_secs(30)),
+                max_connections_per_host: Some(4),
+                idle_timeout: Some(Duration::from_secs(90)),
+            }
+        );
+
+        let http_client = http_client::client(None);
+        let client = tuned_http_client("provider", settings.options(), &http_client).unwrap();
+        assert!(Arc::ptr_eq(
+            &client,
+            &tuned_http_client("provider", settings.options(), &http_client).unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &client,
+            &tuned_http_client("provider", ConnectionOptions::default(), &http_client).unwrap()
+        ));
+        // Each provider keeps a pool of its own.
+        assert!(!Arc::ptr_eq(
+            &client,
+            &tuned_http_client("other_provider", settings.options(), &http_client).unwrap()
+        ));
+
+        // Fakes are used as they are.
+        let fake_http_client: Arc<dyn HttpClient> =
+            http_client::FakeHttpClient::with_200_response();
+        assert!(
+            tuned_http_client("fake_provider", settings.options(), &fake_http_client).is_none()
+        );
+    }
+}
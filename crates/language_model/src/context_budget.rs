@@ -0,0 +1,202 @@
+use std::cmp::Reverse;
+
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use gpui::{AppContext, SharedString};
+
+use crate::{LanguageModel, LanguageModelRequest, LanguageModelRequestMessage, Role};
+
+/// A piece of context (such as a file or a diagnostic) that could be attached
+/// to a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContextAttachment {
+    /// An identifier chosen by the caller, to tell attachments apart.
+    pub id: SharedString,
+    pub content: String,
+    /// Attachments with a higher priority are included first.
+    pub priority: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncludedAttachment {
+    pub attachment: ContextAttachment,
+    pub token_count: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExclusionReason {
+    /// The attachment doesn't fit in the context window on its own.
+    TooLarge,
+    /// Attachments with a higher priority used up the context window.
+    OverBudget,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExcludedAttachment {
+    pub attachment: ContextAttachment,
+    pub token_count: usize,
+    pub reason: ExclusionReason,
+}
+
+/// The attachments that fit in a model's context window, and those that didn't.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContextBudget {
+    /// The number of tokens available to attachments.
+    pub available_tokens: usize,
+    /// The number of tokens used by the included attachments.
+    pub used_tokens: usize,
+    /// The included attachments, in the order they were given.
+    pub included: Vec<IncludedAttachment>,
+    /// The excluded attachments, in the order they were given.
+    pub excluded: Vec<ExcludedAttachment>,
+}
+
+impl ContextBudget {
+    pub fn is_truncated(&self) -> bool {
+        !self.excluded.is_empty()
+    }
+}
+
+/// Selects the attachments that fit in the remaining context window of the
+/// model, once the request and `reserved_output_tokens` are accounted for.
+///
+/// Attachments are counted with the model's own tokenizer and considered in
+/// order of priority. An attachment that doesn't fit is skipped, so that
+/// smaller attachments with a lower priority may still be included.
+pub fn budget_context_attachments(
+    model: &dyn LanguageModel,
+    request: &LanguageModelRequest,
+    attachments: Vec<ContextAttachment>,
+    reserved_output_tokens: usize,
+    cx: &AppContext,
+) -> BoxFuture<'static, Result<ContextBudget>> {
+    let max_token_count = model.max_token_count();
+    let request_token_count = model.count_tokens(request.clone(), cx);
+    let attachment_token_counts = attachments
+        .iter()
+        .map(|attachment| {
+            model.count_tokens(
+                LanguageModelRequest {
+                    messages: vec![LanguageModelRequestMessage {
+                        role: Role::User,
+                        content: attachment.content.clone(),
+                    }],
+                    ..Default::default()
+                },
+                cx,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    async move {
+        let request_token_count = request_token_count.await?;
+        let attachment_token_counts =
+            futures::future::try_join_all(attachment_token_counts).await?;
+        let available_tokens = max_token_count
+            .saturating_sub(request_token_count)
+            .saturating_sub(reserved_output_tokens);
+        Ok(select_attachments(
+            available_tokens,
+            attachments
+                .into_iter()
+                .zip(attachment_token_counts)
+                .collect(),
+        ))
+    }
+    .boxed()
+}
+
+fn select_attachments(
+    available_tokens: usize,
+    attachments: Vec<(ContextAttachment, usize)>,
+) -> ContextBudget {
+    let mut order = (0..attachments.len()).collect::<Vec<_>>();
+    order.sort_by_key(|ix| Reverse(attachments[*ix].0.priority));
+
+    let mut used_tokens = 0;
+    let mut is_included = vec![false; attachments.len()];
+    for ix in order {
+        let token_count = attachments[ix].1;
+        if used_tokens + token_count <= available_tokens {
+            used_tokens += token_count;
+            is_included[ix] = true;
+        }
+    }
+
+    let mut budget = ContextBudget {
+        available_tokens,
+        used_tokens,
+        ..Default::default()
+    };
+    for ((attachment, token_count), is_included) in attachments.into_iter().zip(is_included) {
+        if is_included {
+            budget.included.push(IncludedAttachment {
+                attachment,
+                token_count,
+            });
+        } else {
+            let reason = if token_count > available_tokens {
+                ExclusionReason::TooLarge
+            } else {
+                ExclusionReason::OverBudget
+            };
+            budget.excluded.push(ExcludedAttachment {
+                attachment,
+                token_count,
+                reason,
+            });
+        }
+    }
+    budget
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(id: &'static str, priority: i32) -> ContextAttachment {
+        ContextAttachment {
+            id: id.into(),
+            content: String::new(),
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_select_attachments() {
+        let budget = select_attachments(
+            100,
+            vec![
+                (attachment("a", 0), 30),
+                (attachment("b", 2), 60),
+                (attachment("c", 1), 50),
+                (attachment("d", 5), 150),
+                (attachment("e", 0), 10),
+            ],
+        );
+
+        // Higher priority attachments win, and smaller attachments still fill
+        // the remaining space.
+        assert_eq!(
+            budget
+                .included
+                .iter()
+                .map(|included| included.attachment.id.as_ref())
+                .collect::<Vec<_>>(),
+            ["a", "b", "e"]
+        );
+        assert_eq!(budget.used_tokens, 100);
+        assert_eq!(
+            budget
+                .excluded
+                .iter()
+                .map(|excluded| (excluded.attachment.id.as_ref(), excluded.reason))
+                .collect::<Vec<_>>(),
+            [
+                ("c", ExclusionReason::OverBudget),
+                ("d", ExclusionReason::TooLarge)
+            ]
+        );
+        assert!(budget.is_truncated());
+    }
+}
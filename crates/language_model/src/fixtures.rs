@@ -0,0 +1,404 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt, FutureExt};
+use http_client::{AsyncBody, Error, HttpClient, Request, Response, Uri, Url};
+use serde::{Deserialize, Serialize};
+
+pub const RECORD_FIXTURES_ENV_VAR: &str = "ZED_RECORD_FIXTURES";
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Query parameters that carry credentials, such as Google AI's `key`.
+const SECRET_QUERY_PARAMS: &[&str] = &["key", "api_key", "access_token"];
+
+/// Environment variables holding provider API keys. Their values are scrubbed
+/// from recorded fixtures.
+const SECRET_ENV_VARS: &[&str] = &["ANTHROPIC_API_KEY", "GOOGLE_AI_API_KEY", "OPENAI_API_KEY"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Send requests to the provider and record the exchanges.
+    Record,
+    /// Answer requests with the recorded responses.
+    Replay,
+}
+
+impl FixtureMode {
+    pub fn from_env() -> Self {
+        if std::env::var(RECORD_FIXTURES_ENV_VAR).map_or(false, |value| !value.is_empty()) {
+            Self::Record
+        } else {
+            Self::Replay
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fixture {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+    #[serde(skip)]
+    replayed: bool,
+}
+
+/// A recorded request. Headers aren't recorded, as that's where credentials
+/// usually live.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    uri: String,
+    body: String,
+}
+
+impl RecordedRequest {
+    /// Requests match if their bodies are equal as JSON, so recorded fixtures
+    /// don't depend on how fields happen to be ordered or formatted.
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        if self.method != other.method || self.uri != other.uri {
+            return false;
+        }
+        match (
+            serde_json::from_str::<serde_json::Value>(&self.body),
+            serde_json::from_str::<serde_json::Value>(&other.body),
+        ) {
+            (Ok(body), Ok(other_body)) => body == other_body,
+            _ => self.body == other.body,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    content_type: Option<String>,
+    body: String,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response<AsyncBody>, Error> {
+        let mut builder = Response::builder().status(self.status);
+        if let Some(content_type) = self.content_type.as_ref() {
+            builder = builder.header("Content-Type", content_type);
+        }
+        builder
+            .body(AsyncBody::from(self.body.clone()))
+            .map_err(|error| fixture_error(error.to_string()))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Scrubber {
+    secrets: Vec<String>,
+}
+
+impl Scrubber {
+    fn scrub(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        text
+    }
+
+    fn scrub_uri(&self, uri: &Uri) -> String {
+        let uri = uri.to_string();
+        let Ok(mut url) = Url::parse(&uri) else {
+            return self.scrub(&uri);
+        };
+        if url.query().is_some() {
+            let query = url
+                .query_pairs()
+                .map(|(name, value)| {
+                    if SECRET_QUERY_PARAMS.contains(&name.as_ref()) {
+                        (name.into_owned(), REDACTED.to_string())
+                    } else {
+                        (name.into_owned(), value.into_owned())
+                    }
+                })
+                .collect::<Vec<_>>();
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        self.scrub(url.as_str())
+    }
+}
+
+/// An HTTP client that records provider responses to a fixture file, or
+/// replays them from it.
+pub struct FixtureHttpClient {
+    path: PathBuf,
+    mode: FixtureMode,
+    inner: Arc<dyn HttpClient>,
+    scrubber: Scrubber,
+    fixture: Arc<Mutex<Fixture>>,
+}
+
+impl FixtureHttpClient {
+    /// Creates a client for the fixture at `path`, in the mode selected by the
+    /// `ZED_RECORD_FIXTURES` environment variable. `inner` is only used when
+    /// recording.
+    pub fn new(path: impl Into<PathBuf>, inner: Arc<dyn HttpClient>) -> Result<Self> {
+        Self::with_mode(path, FixtureMode::from_env(), inner)
+    }
+
+    pub fn with_mode(
+        path: impl Into<PathBuf>,
+        mode: FixtureMode,
+        inner: Arc<dyn HttpClient>,
+    ) -> Result<Self> {
+        let path = path.into();
+        let fixture = match mode {
+            FixtureMode::Record => Fixture::default(),
+            FixtureMode::Replay => load_fixture(&path)?,
+        };
+        let secrets = SECRET_ENV_VARS
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .filter(|secret| !secret.is_empty())
+            .collect();
+
+        Ok(Self {
+            path,
+            mode,
+            inner,
+            scrubber: Scrubber { secrets },
+            fixture: Arc::new(Mutex::new(fixture)),
+        })
+    }
+
+    /// Scrubs the given secret from recorded requests and responses, in
+    /// addition to the provider API keys found in the environment.
+    pub fn scrub(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.scrubber.secrets.push(secret);
+        }
+        self
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    /// Writes the recorded exchanges to the fixture file when recording. When
+    /// replaying, fails if some of the recorded exchanges were never requested,
+    /// as that means the fixture is out of date.
+    pub fn finish(&self) -> Result<()> {
+        let fixture = self.fixture.lock().unwrap();
+        match self.mode {
+            FixtureMode::Record => {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let json = serde_json::to_string_pretty(&*fixture)?;
+                std::fs::write(&self.path, json)
+                    .with_context(|| format!("failed to write fixture {:?}", self.path))
+            }
+            FixtureMode::Replay => {
+                let unused = fixture
+                    .interactions
+                    .iter()
+                    .filter(|interaction| !interaction.replayed)
+                    .map(|interaction| {
+                        format!("{} {}", interaction.request.method, interaction.request.uri)
+                    })
+                    .collect::<Vec<_>>();
+                if unused.is_empty() {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "fixture {:?} has requests that were never made: {}. \
+                        Set {RECORD_FIXTURES_ENV_VAR}=1 to record it again.",
+                        self.path,
+                        unused.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl HttpClient for FixtureHttpClient {
+    fn send(
+        &self,
+        request: Request<AsyncBody>,
+    ) -> BoxFuture<'static, Result<Response<AsyncBody>, Error>> {
+        let mode = self.mode;
+        let path = self.path.clone();
+        let inner = self.inner.clone();
+        let scrubber = self.scrubber.clone();
+        let fixture = self.fixture.clone();
+        async move {
+            let (parts, mut body) = request.into_parts();
+            let mut request_body = Vec::new();
+            body.read_to_end(&mut request_body).await?;
+            let recorded_request = RecordedRequest {
+                method: parts.method.to_string(),
+                uri: scrubber.scrub_uri(&parts.uri),
+                body: scrubber.scrub(&String::from_utf8_lossy(&request_body)),
+            };
+
+            match mode {
+                FixtureMode::Record => {
+                    let request = Request::from_parts(parts, AsyncBody::from(request_body));
+                    let response = inner.send(request).await?;
+                    let (parts, mut body) = response.into_parts();
+                    let mut response_body = Vec::new();
+                    body.read_to_end(&mut response_body).await?;
+
+                    fixture.lock().unwrap().interactions.push(Interaction {
+                        request: recorded_request,
+                        response: RecordedResponse {
+                            status: parts.status.as_u16(),
+                            content_type: parts
+                                .headers
+                                .get("Content-Type")
+                                .and_then(|value| value.to_str().ok())
+                                .map(str::to_string),
+                            body: scrubber.scrub(&String::from_utf8_lossy(&response_body)),
+                        },
+                        replayed: false,
+                    });
+
+                    Ok(Response::from_parts(parts, AsyncBody::from(response_body)))
+                }
+                FixtureMode::Replay => {
+                    let mut fixture = fixture.lock().unwrap();
+                    let interaction = fixture
+                        .interactions
+                        .iter_mut()
+                        .find(|interaction| {
+                            !interaction.replayed && interaction.request.matches(&recorded_request)
+                        })
+                        .ok_or_else(|| {
+                            fixture_error(format!(
+                                "no response recorded in {path:?} for {} {}. \
+                                Set {RECORD_FIXTURES_ENV_VAR}=1 to record it.",
+                                recorded_request.method, recorded_request.uri
+                            ))
+                        })?;
+                    interaction.replayed = true;
+                    interaction.response.to_response()
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        None
+    }
+}
+
+fn load_fixture(path: &Path) -> Result<Fixture> {
+    let json = std::fs::read_to_string(path).with_context(|| {
+        format!("failed to read fixture {path:?}. Set {RECORD_FIXTURES_ENV_VAR}=1 to record it.")
+    })?;
+    serde_json::from_str(&json).with_context(|| format!("failed to parse fixture {path:?}"))
+}
+
+fn fixture_error(message: String) -> Error {
+    io::Error::new(io::ErrorKind::Other, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use http_client::FakeHttpClient;
+    use serde_json::json;
+
+    fn post(client: &FixtureHttpClient, uri: &str, body: &str) -> (u16, String) {
+        block_on(async {
+            let response = client
+                .post_json(uri, body.to_string().into())
+                .await
+                .unwrap();
+            let status = response.status().as_u16();
+            let mut text = String::new();
+            response
+                .into_body()
+                .read_to_string(&mut text)
+                .await
+                .unwrap();
+            (status, text)
+        })
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = util::test::temp_tree(json!({}));
+        let path = dir.path().join("fixtures/completion.json");
+        let provider = FakeHttpClient::create(|request| async move {
+            let secret_in_query = request.uri().query() == Some("key=sk-secret");
+            Ok(Response::builder()
+                .status(if secret_in_query { 200 } else { 400 })
+                .header("Content-Type", "text/event-stream")
+                .body("data: {\"text\":\"hi, sk-secret\"}\n\n".into())
+                .unwrap())
+        });
+
+        let recorder = FixtureHttpClient::with_mode(&path, FixtureMode::Record, provider.clone())
+            .unwrap()
+            .scrub("sk-secret");
+        let recorded = post(
+            &recorder,
+            "https://api.example.com/v1/complete?key=sk-secret",
+            r#"{"model": "m", "prompt": "hello"}"#,
+        );
+        assert_eq!(
+            recorded,
+            (200, "data: {\"text\":\"hi, sk-secret\"}\n\n".into())
+        );
+        recorder.finish().unwrap();
+
+        // Secrets are scrubbed from the fixture.
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        assert!(!fixture.contains("sk-secret"));
+        assert!(fixture.contains(REDACTED));
+
+        // Requests are matched regardless of query secrets and JSON formatting.
+        let player = FixtureHttpClient::with_mode(&path, FixtureMode::Replay, provider.clone())
+            .unwrap()
+            .scrub("sk-other");
+        assert!(player.finish().is_err());
+        let replayed = post(
+            &player,
+            "https://api.example.com/v1/complete?key=sk-other",
+            r#"{"prompt":"hello","model":"m"}"#,
+        );
+        assert_eq!(
+            replayed,
+            (200, "data: {\"text\":\"hi, [REDACTED]\"}\n\n".into())
+        );
+        player.finish().unwrap();
+
+        // Requests that weren't recorded fail.
+        let player = FixtureHttpClient::with_mode(&path, FixtureMode::Replay, provider).unwrap();
+        let result = block_on(player.post_json(
+            "https://api.example.com/v1/complete",
+            r#"{"prompt":"goodbye","model":"m"}"#.to_string().into(),
+        ));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_missing_fixture() {
+        let dir = util::test::temp_tree(json!({}));
+        let result = FixtureHttpClient::with_mode(
+            dir.path().join("missing.json"),
+            FixtureMode::Replay,
+            FakeHttpClient::with_404_response(),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,97 @@
+mod open_ai;
+mod stability;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use gpui::{AppContext, AsyncAppContext};
+use settings::Settings;
+
+use crate::{settings::AllLanguageModelSettings, LanguageModelProviderId};
+
+pub use self::open_ai::{OpenAiImageGenerationProvider, OpenAiImageGenerationSettings};
+pub use self::stability::{StabilityImageGenerationProvider, StabilityImageGenerationSettings};
+
+/// A provider that generates images from text prompts.
+///
+/// This is deliberately separate from [`crate::LanguageModel`]: image
+/// generation is a single request and response, and its parameters have
+/// nothing in common with those of chat completions.
+pub trait ImageGenerationProvider: Send + Sync {
+    /// The provider's ID, which it shares with the language model provider of
+    /// the same vendor (e.g. "openai"), if any.
+    fn id(&self) -> LanguageModelProviderId;
+    fn name(&self) -> &'static str;
+    fn generate_image(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<GeneratedImage>>;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    /// The size of the image, or `None` for the model's default.
+    pub size: Option<ImageSize>,
+    pub quality: ImageQuality,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageQuality {
+    #[default]
+    Standard,
+    /// Finer detail, at the cost of a slower and more expensive request.
+    High,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedImage {
+    pub image: ImageData,
+    /// The prompt the provider actually used, when it rewrote the one it was
+    /// given.
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageData {
+    /// The encoded image, e.g. a PNG.
+    Bytes(Vec<u8>),
+    /// A URL the image can be downloaded from for a limited time.
+    Url(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageGenerationSettings {
+    pub openai: OpenAiImageGenerationSettings,
+    pub stability: StabilityImageGenerationSettings,
+}
+
+/// Returns the image generation providers that may be used.
+pub fn image_generation_providers(cx: &AppContext) -> Vec<Arc<dyn ImageGenerationProvider>> {
+    let settings = AllLanguageModelSettings::get_global(cx);
+    [
+        Arc::new(OpenAiImageGenerationProvider) as Arc<dyn ImageGenerationProvider>,
+        Arc::new(StabilityImageGenerationProvider),
+    ]
+    .into_iter()
+    .filter(|provider| settings.is_provider_allowed(&provider.id()))
+    .collect()
+}
+
+/// Returns the image generation provider with the given ID, if it may be used.
+pub fn image_generation_provider(
+    id: &LanguageModelProviderId,
+    cx: &AppContext,
+) -> Option<Arc<dyn ImageGenerationProvider>> {
+    image_generation_providers(cx)
+        .into_iter()
+        .find(|provider| provider.id() == *id)
+}
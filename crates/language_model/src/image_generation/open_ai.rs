@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Result};
+use base64::prelude::*;
+use futures::{future::BoxFuture, FutureExt};
+use gpui::AsyncAppContext;
+use open_ai::{ImageGenerationData, ImageResponseFormat};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use super::{
+    GeneratedImage, ImageData, ImageGenerationProvider, ImageGenerationRequest, ImageQuality,
+};
+use crate::{
+    connection_settings::http_client_for_provider, org_vault::load_api_key,
+    settings::AllLanguageModelSettings, LanguageModelProviderId,
+};
+
+const PROVIDER_ID: &str = "openai";
+
+/// Image generation through OpenAI's Images API, or a compatible one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct OpenAiImageGenerationSettings {
+    /// The URL of the API, or null to use the `api_url` of the OpenAI
+    /// language model provider.
+    ///
+    /// Default: null
+    pub api_url: Option<String>,
+    /// The model to generate images with.
+    ///
+    /// Default: "dall-e-3"
+    pub model: String,
+}
+
+impl Default for OpenAiImageGenerationSettings {
+    fn default() -> Self {
+        Self {
+            api_url: None,
+            model: "dall-e-3".into(),
+        }
+    }
+}
+
+pub struct OpenAiImageGenerationProvider;
+
+impl ImageGenerationProvider for OpenAiImageGenerationProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn generate_image(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<GeneratedImage>> {
+        let mut cx = cx.clone();
+        async move {
+            let (settings, api_url, api_key_ref, auth, http_client) = cx.update(|cx| {
+                let settings = AllLanguageModelSettings::get_global(cx);
+                (
+                    settings.image_generation.openai.clone(),
+                    settings.openai.api_url.clone(),
+                    settings.openai.api_key_ref.clone(),
+                    settings.openai.auth.clone(),
+                    http_client_for_provider(PROVIDER_ID, cx.http_client(), cx),
+                )
+            })?;
+            // Keys are stored in the keychain under the language model
+            // provider's URL, even when images are generated elsewhere.
+            let api_key = load_api_key(
+                PROVIDER_ID,
+                "OPENAI_API_KEY",
+                api_key_ref,
+                api_url.clone(),
+                &mut cx,
+            )
+            .await?;
+            let api_url = settings.api_url.unwrap_or(api_url);
+            // Requests are authenticated like the provider's chat requests,
+            // such as by signing them for gateways that require it.
+            let http_client = auth.http_client(http_client, &api_key);
+
+            let response = open_ai::generate_image(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                image_request(&settings.model, request),
+            )
+            .await?;
+            let data = response
+                .data
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("OpenAI didn't return an image"))?;
+            generated_image(data)
+        }
+        .boxed()
+    }
+}
+
+fn image_request(model: &str, request: ImageGenerationRequest) -> open_ai::ImageGenerationRequest {
+    // DALL·E models take "standard" and "hd" qualities and can respond with
+    // either format. Later models take "medium" and "high" and always respond
+    // with base64.
+    let is_dall_e = model.starts_with("dall-e");
+    let quality = match (request.quality, is_dall_e) {
+        (ImageQuality::Standard, true) => "standard",
+        (ImageQuality::High, true) => "hd",
+        (ImageQuality::Standard, false) => "medium",
+        (ImageQuality::High, false) => "high",
+    };
+    open_ai::ImageGenerationRequest {
+        model: model.to_string(),
+        prompt: request.prompt,
+        n: 1,
+        size: request
+            .size
+            .map(|size| format!("{}x{}", size.width, size.height)),
+        quality: Some(quality.to_string()),
+        response_format: is_dall_e.then_some(ImageResponseFormat::B64Json),
+    }
+}
+
+fn generated_image(data: ImageGenerationData) -> Result<GeneratedImage> {
+    let image = match (data.b64_json, data.url) {
+        (Some(b64_json), _) => ImageData::Bytes(BASE64_STANDARD.decode(b64_json)?),
+        (None, Some(url)) => ImageData::Url(url),
+        (None, None) => return Err(anyhow!("OpenAI returned an image without any data")),
+    };
+    Ok(GeneratedImage {
+        image,
+        revised_prompt: data.revised_prompt,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_generation::ImageSize;
+
+    #[test]
+    fn test_image_request() {
+        let request = ImageGenerationRequest {
+            prompt: "a crab".into(),
+            size: Some(ImageSize {
+                width: 1024,
+                height: 1792,
+            }),
+            quality: ImageQuality::High,
+        };
+
+        let dall_e = image_request("dall-e-3", request.clone());
+        assert_eq!(dall_e.size.as_deref(), Some("1024x1792"));
+        assert_eq!(dall_e.quality.as_deref(), Some("hd"));
+        assert_eq!(dall_e.response_format, Some(ImageResponseFormat::B64Json));
+
+        let gpt_image = image_request("gpt-image-1", request);
+        assert_eq!(gpt_image.quality.as_deref(), Some("high"));
+        assert_eq!(gpt_image.response_format, None);
+    }
+
+    #[test]
+    fn test_generated_image() {
+        let image = generated_image(ImageGenerationData {
+            url: None,
+            b64_json: Some(BASE64_STANDARD.encode(b"png")),
+            revised_prompt: Some("a red crab".into()),
+        })
+        .unwrap();
+        assert_eq!(
+            image,
+            GeneratedImage {
+                image: ImageData::Bytes(b"png".to_vec()),
+                revised_prompt: Some("a red crab".into()),
+            }
+        );
+
+        let image = generated_image(ImageGenerationData {
+            url: Some("https://images.example.com/crab.png".into()),
+            b64_json: None,
+            revised_prompt: None,
+        })
+        .unwrap();
+        assert_eq!(
+            image.image,
+            ImageData::Url("https://images.example.com/crab.png".into())
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, Context as _, Result};
+use base64::prelude::*;
+use futures::{future::BoxFuture, AsyncReadExt, Future, FutureExt};
+use gpui::AsyncAppContext;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use super::{
+    GeneratedImage, ImageData, ImageGenerationProvider, ImageGenerationRequest, ImageQuality,
+};
+use crate::{
+    connection_settings::http_client_for_provider, org_vault::load_api_key,
+    settings::AllLanguageModelSettings, LanguageModelProviderId,
+};
+
+const PROVIDER_ID: &str = "stability";
+
+/// Image generation through Stability AI's text-to-image API, or a compatible
+/// one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct StabilityImageGenerationSettings {
+    /// The URL of the API.
+    ///
+    /// Default: "https://api.stability.ai"
+    pub api_url: String,
+    /// The engine to generate images with.
+    ///
+    /// Default: "stable-diffusion-xl-1024-v1-0"
+    pub model: String,
+    /// A reference to an organization secret to use as the API key instead
+    /// of the `STABILITY_API_KEY` environment variable or the keychain, e.g.
+    /// "org:stability".
+    pub api_key_ref: Option<String>,
+}
+
+impl Default for StabilityImageGenerationSettings {
+    fn default() -> Self {
+        Self {
+            api_url: "https://api.stability.ai".into(),
+            model: "stable-diffusion-xl-1024-v1-0".into(),
+            api_key_ref: None,
+        }
+    }
+}
+
+pub struct StabilityImageGenerationProvider;
+
+impl ImageGenerationProvider for StabilityImageGenerationProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "Stability AI"
+    }
+
+    fn generate_image(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<GeneratedImage>> {
+        let mut cx = cx.clone();
+        async move {
+            let (settings, http_client) = cx.update(|cx| {
+                (
+                    AllLanguageModelSettings::get_global(cx)
+                        .image_generation
+                        .stability
+                        .clone(),
+                    http_client_for_provider(PROVIDER_ID, cx.http_client(), cx),
+                )
+            })?;
+            let api_key = load_api_key(
+                PROVIDER_ID,
+                "STABILITY_API_KEY",
+                settings.api_key_ref,
+                settings.api_url.clone(),
+                &mut cx,
+            )
+            .await?;
+            text_to_image(
+                http_client.as_ref(),
+                &settings.api_url,
+                &api_key,
+                &settings.model,
+                request,
+            )
+            .await
+        }
+        .boxed()
+    }
+}
+
+#[derive(Serialize)]
+struct TextToImageRequest {
+    text_prompts: Vec<TextPrompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    steps: u32,
+    samples: u32,
+}
+
+#[derive(Serialize)]
+struct TextPrompt {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct TextToImageResponse {
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    base64: String,
+    #[serde(rename = "finishReason")]
+    finish_reason: String,
+}
+
+fn text_to_image(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    model: &str,
+    request: ImageGenerationRequest,
+) -> impl 'static + Future<Output = Result<GeneratedImage>> {
+    let uri = format!("{api_url}/v1/generation/{model}/text-to-image");
+
+    let request = TextToImageRequest {
+        text_prompts: vec![TextPrompt {
+            text: request.prompt,
+        }],
+        width: request.size.map(|size| size.width),
+        height: request.size.map(|size| size.height),
+        // More diffusion steps give finer detail, but take longer.
+        steps: match request.quality {
+            ImageQuality::Standard => 30,
+            ImageQuality::High => 50,
+        },
+        samples: 1,
+    };
+    let body = AsyncBody::from(serde_json::to_string(&request).unwrap());
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .body(body)
+        .map(|request| client.send(request));
+
+    async move {
+        let mut response = request?.await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "error during image generation, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ));
+        }
+
+        let response: TextToImageResponse = serde_json::from_str(&body)
+            .context("failed to parse Stability AI text-to-image response")?;
+        let artifact = response
+            .artifacts
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Stability AI didn't return an image"))?;
+        if artifact.finish_reason != "SUCCESS" {
+            return Err(anyhow!(
+                "Stability AI didn't generate the image: {}",
+                artifact.finish_reason
+            ));
+        }
+        Ok(GeneratedImage {
+            image: ImageData::Bytes(BASE64_STANDARD.decode(artifact.base64)?),
+            revised_prompt: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_generation::ImageSize;
+    use futures::executor::block_on;
+    use http_client::{FakeHttpClient, Response};
+    use serde_json::json;
+
+    #[test]
+    fn test_text_to_image() {
+        let client = FakeHttpClient::create(|mut request| async move {
+            assert_eq!(
+                request.uri().to_string(),
+                "https://api.stability.ai/v1/generation/sdxl/text-to-image"
+            );
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await.unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+                json!({
+                    "text_prompts": [{ "text": "a crab" }],
+                    "width": 1024,
+                    "height": 1024,
+                    "steps": 50,
+                    "samples": 1
+                })
+            );
+
+            let body = json!({
+                "artifacts": [{
+                    "base64": BASE64_STANDARD.encode(b"png"),
+                    "finishReason": "SUCCESS",
+                    "seed": 1
+                }]
+            });
+            Ok(Response::builder()
+                .status(200)
+                .body(body.to_string().into())
+                .unwrap())
+        });
+
+        let image = block_on(text_to_image(
+            client.as_ref(),
+            "https://api.stability.ai",
+            "sk-stability",
+            "sdxl",
+            ImageGenerationRequest {
+                prompt: "a crab".into(),
+                size: Some(ImageSize {
+                    width: 1024,
+                    height: 1024,
+                }),
+                quality: ImageQuality::High,
+            },
+        ))
+        .unwrap();
+        assert_eq!(image.image, ImageData::Bytes(b"png".to_vec()));
+    }
+}
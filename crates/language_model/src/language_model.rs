@@ -1,17 +1,39 @@
+pub mod api_key_pool;
+mod api_key_sync;
+pub mod circuit_breaker;
+mod completion_event;
+pub mod connection_settings;
+mod context_budget;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fixtures;
+pub mod image_generation;
+pub mod managed_settings;
 mod model;
+pub mod moderation;
+pub mod org_vault;
+pub mod output_limits;
+pub mod presets;
 pub mod provider;
 mod registry;
 mod request;
+pub mod request_signing;
 mod role;
+pub mod routing;
 pub mod settings;
+pub mod shared_rate_limit;
+pub mod stream_metrics;
+pub mod transcription;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use client::Client;
-use futures::{future::BoxFuture, stream::BoxStream};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
 use gpui::{AnyView, AppContext, AsyncAppContext, SharedString, Task, WindowContext};
 
+pub use api_key_sync::ApiKeySync;
+pub use completion_event::*;
+pub use context_budget::*;
 pub use model::*;
 pub use registry::*;
 pub use request::*;
@@ -21,6 +43,8 @@ use serde::de::DeserializeOwned;
 
 pub fn init(client: Arc<Client>, cx: &mut AppContext) {
     settings::init(cx);
+    api_key_sync::init(client.clone(), cx);
+    org_vault::init(client.clone(), cx);
     registry::init(client, cx);
 }
 
@@ -45,6 +69,24 @@ pub trait LanguageModel: Send + Sync {
         cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<String>>>>;
 
+    /// Streams the completion as events, which, unlike the chunks of
+    /// [`LanguageModel::stream_completion`], include the sources the model
+    /// cited for providers that report them.
+    fn stream_completion_events(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<LanguageModelCompletionEvent>>>> {
+        let response = self.stream_completion(request, cx);
+        async move {
+            Ok(response
+                .await?
+                .map(|chunk| chunk.map(LanguageModelCompletionEvent::Text))
+                .boxed())
+        }
+        .boxed()
+    }
+
     fn use_tool(
         &self,
         request: LanguageModelRequest,
@@ -69,6 +111,12 @@ pub trait LanguageModelProvider: 'static {
     fn authenticate(&self, cx: &AppContext) -> Task<Result<()>>;
     fn authentication_prompt(&self, cx: &mut WindowContext) -> AnyView;
     fn reset_credentials(&self, cx: &AppContext) -> Task<Result<()>>;
+
+    /// How long to wait for the first token of a response before retrying
+    /// the request.
+    fn first_token_timeout(&self, _cx: &AppContext) -> Option<Duration> {
+        None
+    }
 }
 
 pub trait LanguageModelProviderState: 'static {
@@ -0,0 +1,156 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::SettingsSources;
+
+use crate::settings::{AllLanguageModelSettings, AllLanguageModelSettingsContent};
+
+/// A language model setting that an administrator can lock in the managed
+/// settings, so that users can't override it.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+pub enum LockableSetting {
+    #[serde(rename = "allowed_providers")]
+    AllowedProviders,
+    #[serde(rename = "anthropic.api_url")]
+    AnthropicApiUrl,
+    #[serde(rename = "ollama.api_url")]
+    OllamaApiUrl,
+    #[serde(rename = "openai.api_url")]
+    OpenAiApiUrl,
+    #[serde(rename = "google.api_url")]
+    GoogleApiUrl,
+}
+
+impl LockableSetting {
+    /// The setting's key within `language_models`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            LockableSetting::AllowedProviders => "allowed_providers",
+            LockableSetting::AnthropicApiUrl => "anthropic.api_url",
+            LockableSetting::OllamaApiUrl => "ollama.api_url",
+            LockableSetting::OpenAiApiUrl => "openai.api_url",
+            LockableSetting::GoogleApiUrl => "google.api_url",
+        }
+    }
+}
+
+/// Reapplies the managed values of the settings locked in the managed
+/// settings, undoing any overrides from the user or project settings.
+pub(crate) fn apply_locked_settings(
+    settings: &mut AllLanguageModelSettings,
+    sources: &SettingsSources<AllLanguageModelSettingsContent>,
+) {
+    let Some(managed) = sources.managed else {
+        return;
+    };
+
+    // Locked values that the managed settings don't set stay at their defaults.
+    let api_url = |content: fn(&AllLanguageModelSettingsContent) -> Option<&String>| {
+        content(managed)
+            .or_else(|| content(sources.default))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    for setting in managed.locked.iter().flatten().copied() {
+        let overridden = match setting {
+            LockableSetting::AllowedProviders => lock(
+                &mut settings.allowed_providers,
+                managed.allowed_providers.clone(),
+            ),
+            LockableSetting::AnthropicApiUrl => lock(
+                &mut settings.anthropic.api_url,
+                api_url(|content| content.anthropic.as_ref()?.api_url.as_ref()),
+            ),
+            LockableSetting::OllamaApiUrl => lock(
+                &mut settings.ollama.api_url,
+                api_url(|content| content.ollama.as_ref()?.api_url.as_ref()),
+            ),
+            LockableSetting::OpenAiApiUrl => lock(
+                &mut settings.openai.api_url,
+                api_url(|content| content.openai.as_ref()?.api_url.as_ref()),
+            ),
+            LockableSetting::GoogleApiUrl => lock(
+                &mut settings.google.api_url,
+                api_url(|content| content.google.as_ref()?.api_url.as_ref()),
+            ),
+        };
+        if overridden {
+            log::warn!(
+                "ignoring the configured value of language_models.{}, which is managed by your organization",
+                setting.key()
+            );
+        }
+        settings.locked.insert(setting);
+    }
+}
+
+/// Sets the target to the locked value, returning whether it was overridden.
+fn lock<T: PartialEq>(target: &mut T, value: T) -> bool {
+    let overridden = *target != value;
+    *target = value;
+    overridden
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::{AppContext, UpdateGlobal};
+    use settings::{Settings, SettingsStore};
+
+    use super::*;
+    use crate::LanguageModelProviderId;
+
+    #[gpui::test]
+    fn test_locked_settings(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        crate::settings::init(cx);
+
+        SettingsStore::update_global(cx, |store, cx| {
+            store
+                .set_user_settings(
+                    r#"{
+                        "language_models": {
+                            "allowed_providers": ["openai", "ollama"],
+                            "anthropic": { "api_url": "https://user.example.com/anthropic" },
+                            "openai": { "api_url": "https://user.example.com/v1" },
+                            "google": { "api_url": "https://user.example.com/google" }
+                        }
+                    }"#,
+                    cx,
+                )
+                .unwrap();
+            store
+                .set_managed_settings(
+                    r#"{
+                        "language_models": {
+                            "allowed_providers": ["openai", "zed.dev"],
+                            "openai": { "api_url": "https://gateway.example.com/v1" },
+                            "locked": ["allowed_providers", "openai.api_url", "google.api_url"]
+                        }
+                    }"#,
+                    cx,
+                )
+                .unwrap();
+        });
+
+        let settings = AllLanguageModelSettings::get_global(cx);
+        assert_eq!(settings.openai.api_url, "https://gateway.example.com/v1");
+        // Locked settings that aren't set in the managed settings keep their
+        // default values.
+        assert_eq!(
+            settings.google.api_url,
+            "https://generativelanguage.googleapis.com"
+        );
+        // Settings that aren't locked can still be overridden.
+        assert_eq!(
+            settings.anthropic.api_url,
+            "https://user.example.com/anthropic"
+        );
+        assert!(settings.is_locked(LockableSetting::OpenAiApiUrl));
+        assert!(!settings.is_locked(LockableSetting::AnthropicApiUrl));
+        assert!(settings.is_provider_allowed(&LanguageModelProviderId::from("zed.dev".to_string())));
+        assert!(!settings.is_provider_allowed(&LanguageModelProviderId::from("ollama".to_string())));
+    }
+}
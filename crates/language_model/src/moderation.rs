@@ -0,0 +1,389 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use collections::BTreeMap;
+use futures::{future, stream::BoxStream, StreamExt};
+use gpui::AsyncAppContext;
+use http_client::HttpClient;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+use util::ResultExt;
+
+use crate::{
+    api_key_sync, settings::AllLanguageModelSettings, LanguageModelProviderId, LanguageModelRequest,
+};
+
+const OPENAI_PROVIDER_ID: &str = "openai";
+
+/// How text sent to or received from a provider is classified.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ModerationClassifier {
+    /// Use OpenAI's moderation endpoint, authenticated with the OpenAI API key.
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Flag text containing any of the configured `blocked_terms`, without
+    /// sending it anywhere.
+    #[serde(rename = "local")]
+    Local,
+}
+
+/// What to do when text is flagged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Log a warning and carry on.
+    Warn,
+    /// Fail the request.
+    Block,
+}
+
+/// Which text to moderate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationTarget {
+    /// Moderate prompts before they are sent to the provider.
+    #[default]
+    Prompts,
+    /// Moderate completions before they are shown.
+    Completions,
+    /// Moderate both prompts and completions.
+    Both,
+}
+
+impl ModerationTarget {
+    fn includes_prompts(&self) -> bool {
+        matches!(self, Self::Prompts | Self::Both)
+    }
+
+    fn includes_completions(&self) -> bool {
+        matches!(self, Self::Completions | Self::Both)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProviderModerationSettings {
+    pub action: ModerationAction,
+    #[serde(default)]
+    pub apply_to: ModerationTarget,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ModerationSettings {
+    pub classifier: ModerationClassifier,
+    pub blocked_terms: Vec<String>,
+    /// Moderation settings keyed by provider ID. Requests to providers that
+    /// aren't listed are not moderated.
+    pub providers: BTreeMap<String, ProviderModerationSettings>,
+}
+
+/// The error returned when moderation blocks a prompt or a completion.
+#[derive(Debug)]
+pub struct ModerationError {
+    pub categories: Vec<String>,
+}
+
+impl std::fmt::Display for ModerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "blocked by moderation policy ({})",
+            self.categories.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ModerationError {}
+
+enum Classifier {
+    OpenAi {
+        http_client: Arc<dyn HttpClient>,
+        api_url: String,
+        api_key: String,
+    },
+    Local {
+        blocked_terms: Vec<String>,
+    },
+}
+
+/// Applies the moderation policy configured for a provider to its prompts
+/// and completions.
+pub struct Moderation {
+    provider_id: LanguageModelProviderId,
+    settings: ProviderModerationSettings,
+    classifier: Arc<Classifier>,
+}
+
+impl Moderation {
+    /// Returns the moderation configured for the given provider, if any.
+    pub async fn for_provider(
+        provider_id: &LanguageModelProviderId,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Option<Self>> {
+        let (moderation_settings, openai_api_url, openai_auth, http_client) = cx.update(|cx| {
+            let settings = AllLanguageModelSettings::get_global(cx);
+            (
+                settings.moderation.clone(),
+                settings.openai.api_url.clone(),
+                settings.openai.auth.clone(),
+                cx.http_client(),
+            )
+        })?;
+        let Some(settings) = moderation_settings
+            .providers
+            .get(&provider_id.0.to_string())
+        else {
+            return Ok(None);
+        };
+
+        let classifier = match moderation_settings.classifier {
+            ModerationClassifier::OpenAi => {
+                let api_key = if let Ok(api_key) = std::env::var("OPENAI_API_KEY") {
+                    api_key
+                } else {
+                    api_key_sync::load_api_key(OPENAI_PROVIDER_ID, openai_api_url.clone(), cx)
+                        .await?
+                        .ok_or_else(|| anyhow!("moderation requires an OpenAI API key"))?
+                };
+                Classifier::OpenAi {
+                    http_client: openai_auth.http_client(http_client, &api_key),
+                    api_url: openai_api_url,
+                    api_key,
+                }
+            }
+            ModerationClassifier::Local => Classifier::Local {
+                blocked_terms: moderation_settings
+                    .blocked_terms
+                    .iter()
+                    .map(|term| term.to_lowercase())
+                    .collect(),
+            },
+        };
+
+        Ok(Some(Self {
+            provider_id: provider_id.clone(),
+            settings: settings.clone(),
+            classifier: Arc::new(classifier),
+        }))
+    }
+
+    /// Checks the prompt before it is sent to the provider.
+    pub async fn check_prompt(&self, request: &LanguageModelRequest) -> Result<()> {
+        if !self.settings.apply_to.includes_prompts() {
+            return Ok(());
+        }
+
+        let prompt = request
+            .messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        check(
+            &self.classifier,
+            self.settings.action,
+            &self.provider_id,
+            "prompt",
+            &prompt,
+        )
+        .await
+    }
+
+    /// Checks the completion streamed back from the provider.
+    ///
+    /// Blocked completions are withheld until the whole completion has been
+    /// checked. Otherwise, the completion streams through as usual and is
+    /// checked once it is done.
+    pub fn moderate_completion(
+        self,
+        completion: BoxStream<'static, Result<String>>,
+    ) -> BoxStream<'static, Result<String>> {
+        if !self.settings.apply_to.includes_completions() {
+            return completion;
+        }
+
+        match self.settings.action {
+            ModerationAction::Block => futures::stream::once(async move {
+                let mut completion = completion;
+                let mut text = String::new();
+                while let Some(chunk) = completion.next().await {
+                    text.push_str(&chunk?);
+                }
+                check(
+                    &self.classifier,
+                    ModerationAction::Block,
+                    &self.provider_id,
+                    "completion",
+                    &text,
+                )
+                .await?;
+                Ok(text)
+            })
+            .boxed(),
+            ModerationAction::Warn => {
+                let text = Arc::new(Mutex::new(String::new()));
+                completion
+                    .inspect({
+                        let text = text.clone();
+                        move |chunk| {
+                            if let Ok(chunk) = chunk {
+                                text.lock().unwrap().push_str(chunk);
+                            }
+                        }
+                    })
+                    .chain(
+                        futures::stream::once(async move {
+                            let text = std::mem::take(&mut *text.lock().unwrap());
+                            check(
+                                &self.classifier,
+                                ModerationAction::Warn,
+                                &self.provider_id,
+                                "completion",
+                                &text,
+                            )
+                            .await
+                            .log_err();
+                            None
+                        })
+                        .filter_map(future::ready),
+                    )
+                    .boxed()
+            }
+        }
+    }
+}
+
+async fn check(
+    classifier: &Classifier,
+    action: ModerationAction,
+    provider_id: &LanguageModelProviderId,
+    kind: &str,
+    text: &str,
+) -> Result<()> {
+    let categories = classify(classifier, text).await?;
+    if categories.is_empty() {
+        return Ok(());
+    }
+
+    match action {
+        ModerationAction::Warn => {
+            log::warn!(
+                "{kind} for {} was flagged by moderation: {}",
+                provider_id.0,
+                categories.join(", ")
+            );
+            Ok(())
+        }
+        ModerationAction::Block => Err(ModerationError { categories }.into()),
+    }
+}
+
+/// Returns the categories the text was flagged for.
+async fn classify(classifier: &Classifier, text: &str) -> Result<Vec<String>> {
+    match classifier {
+        Classifier::OpenAi {
+            http_client,
+            api_url,
+            api_key,
+        } => {
+            let response = open_ai::moderate(http_client.as_ref(), api_url, api_key, text).await?;
+            Ok(response
+                .results
+                .iter()
+                .filter(|result| result.flagged)
+                .flat_map(|result| result.flagged_categories())
+                .map(str::to_string)
+                .collect())
+        }
+        Classifier::Local { blocked_terms } => {
+            let text = text.to_lowercase();
+            Ok(blocked_terms
+                .iter()
+                .any(|term| !term.is_empty() && text.contains(term.as_str()))
+                .then(|| "blocked_term".to_string())
+                .into_iter()
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn local_moderation(action: ModerationAction, apply_to: ModerationTarget) -> Moderation {
+        Moderation {
+            provider_id: LanguageModelProviderId("anthropic".into()),
+            settings: ProviderModerationSettings { action, apply_to },
+            classifier: Arc::new(Classifier::Local {
+                blocked_terms: vec!["forbidden".into()],
+            }),
+        }
+    }
+
+    fn completion(chunks: &[&str]) -> BoxStream<'static, Result<String>> {
+        futures::stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok(chunk.to_string()))
+                .collect::<Vec<_>>(),
+        )
+        .boxed()
+    }
+
+    #[test]
+    fn test_block_completion() {
+        let moderation = local_moderation(ModerationAction::Block, ModerationTarget::Both);
+        let chunks = block_on(
+            moderation
+                .moderate_completion(completion(&["a FORBID", "DEN word"]))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(chunks.len(), 1);
+        let error = chunks[0].as_ref().unwrap_err();
+        assert!(error.downcast_ref::<ModerationError>().is_some());
+
+        let moderation = local_moderation(ModerationAction::Block, ModerationTarget::Both);
+        let chunks = block_on(
+            moderation
+                .moderate_completion(completion(&["a harmless", " word"]))
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(chunks, ["a harmless word"]);
+    }
+
+    #[test]
+    fn test_warn_completion() {
+        let moderation = local_moderation(ModerationAction::Warn, ModerationTarget::Completions);
+        let chunks = block_on(
+            moderation
+                .moderate_completion(completion(&["a forbidden", " word"]))
+                .map(Result::unwrap)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(chunks, ["a forbidden", " word"]);
+    }
+
+    #[test]
+    fn test_prompt() {
+        let request = LanguageModelRequest {
+            messages: vec![crate::LanguageModelRequestMessage {
+                role: crate::Role::User,
+                content: "something forbidden".into(),
+            }],
+            ..Default::default()
+        };
+
+        let moderation = local_moderation(ModerationAction::Block, ModerationTarget::Prompts);
+        assert!(block_on(moderation.check_prompt(&request)).is_err());
+
+        let moderation = local_moderation(ModerationAction::Warn, ModerationTarget::Prompts);
+        assert!(block_on(moderation.check_prompt(&request)).is_ok());
+
+        let moderation = local_moderation(ModerationAction::Block, ModerationTarget::Completions);
+        assert!(block_on(moderation.check_prompt(&request)).is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use client::{proto, Client};
+use gpui::{AppContext, AsyncAppContext, Global};
+
+use crate::api_key_sync;
+
+/// The prefix of settings values that refer to an organization secret.
+const REFERENCE_PREFIX: &str = "org:";
+
+/// How long before a lease expires to lease the secret again, so that it
+/// doesn't expire while a request is in flight.
+const LEASE_RENEWAL_MARGIN: Duration = Duration::from_secs(60);
+
+pub fn init(client: Arc<Client>, cx: &mut AppContext) {
+    cx.set_global(OrgVault {
+        client,
+        leases: Default::default(),
+    });
+}
+
+/// A reference to a secret in the vault of one of the user's organizations,
+/// written `org:<name>` or `org:<organization slug>/<name>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretReference {
+    pub organization_slug: Option<String>,
+    pub name: String,
+}
+
+impl SecretReference {
+    pub fn parse(reference: &str) -> Result<Self> {
+        let path = reference
+            .strip_prefix(REFERENCE_PREFIX)
+            .ok_or_else(|| anyhow!("secret reference {reference:?} must start with \"org:\""))?;
+        let (organization_slug, name) = match path.split_once('/') {
+            Some((slug, name)) => (Some(slug.to_string()), name),
+            None => (None, path),
+        };
+        if name.is_empty() || organization_slug.as_deref() == Some("") {
+            return Err(anyhow!("invalid secret reference {reference:?}"));
+        }
+        Ok(Self {
+            organization_slug,
+            name: name.to_string(),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Lease {
+    value: String,
+    expires_at: SystemTime,
+}
+
+/// Leases secrets, such as provider API keys, from the user's organizations
+/// via zed.dev.
+///
+/// Leased secrets are only kept in memory, and only until their lease is about
+/// to expire, so they are never written to the keychain or shown to the user.
+pub struct OrgVault {
+    client: Arc<Client>,
+    leases: Arc<Mutex<HashMap<String, Lease>>>,
+}
+
+impl Global for OrgVault {}
+
+impl OrgVault {
+    /// Returns the value of the referenced secret, leasing it if there's no
+    /// current lease.
+    pub async fn lease(reference: &str, cx: &AsyncAppContext) -> Result<String> {
+        let (client, leases) = cx
+            .try_read_global(|vault: &OrgVault, _| (vault.client.clone(), vault.leases.clone()))
+            .ok_or_else(|| anyhow!("organization secrets are unavailable"))?;
+        if let Some(value) = current_lease(&leases.lock().unwrap(), reference, SystemTime::now()) {
+            return Ok(value);
+        }
+
+        let secret_reference = SecretReference::parse(reference)?;
+        let response = client
+            .request(proto::LeaseOrganizationSecret {
+                organization_slug: secret_reference.organization_slug,
+                name: secret_reference.name,
+            })
+            .await?;
+        leases.lock().unwrap().insert(
+            reference.to_string(),
+            Lease {
+                value: response.value.clone(),
+                expires_at: UNIX_EPOCH + Duration::from_secs(response.expires_at),
+            },
+        );
+        Ok(response.value)
+    }
+
+    /// Forgets the current lease of the referenced secret, so that the next
+    /// request leases it again, e.g. after the provider rejected the leased
+    /// value because the secret was rotated.
+    pub fn invalidate(reference: &str, cx: &AsyncAppContext) {
+        if let Some(leases) = cx.try_read_global(|vault: &OrgVault, _| vault.leases.clone()) {
+            leases.lock().unwrap().remove(reference);
+        }
+    }
+}
+
+fn current_lease(
+    leases: &HashMap<String, Lease>,
+    reference: &str,
+    now: SystemTime,
+) -> Option<String> {
+    let lease = leases.get(reference)?;
+    (now + LEASE_RENEWAL_MARGIN < lease.expires_at).then(|| lease.value.clone())
+}
+
+/// The error returned when a provider request can't be sent because no API
+/// key is configured for it, which says nothing about the provider's health.
+#[derive(Debug)]
+pub struct MissingApiKeyError {
+    pub provider_id: Option<&'static str>,
+}
+
+impl std::fmt::Display for MissingApiKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.provider_id {
+            Some(provider_id) => write!(f, "{provider_id} requires an API key"),
+            None => write!(f, "missing api key"),
+        }
+    }
+}
+
+impl std::error::Error for MissingApiKeyError {}
+
+/// Returns the API key to send a provider request with: the leased
+/// organization secret when the provider's settings refer to one, or else the
+/// key the user entered.
+pub(crate) async fn resolve_api_key(
+    api_key_ref: Option<String>,
+    api_key: Option<String>,
+    cx: &AsyncAppContext,
+) -> Result<String> {
+    match api_key_ref {
+        Some(api_key_ref) => OrgVault::lease(&api_key_ref, cx).await,
+        None => api_key.ok_or_else(|| MissingApiKeyError { provider_id: None }.into()),
+    }
+}
+
+/// Returns the API key to send a provider's requests with: the leased
+/// organization secret when the settings refer to one, or else the key in the
+/// environment variable or the keychain.
+pub(crate) async fn load_api_key(
+    provider_id: &'static str,
+    env_var_name: &str,
+    api_key_ref: Option<String>,
+    api_url: String,
+    cx: &mut AsyncAppContext,
+) -> Result<String> {
+    let api_key = match std::env::var(env_var_name) {
+        Ok(api_key) => Some(api_key),
+        Err(_) if api_key_ref.is_none() => {
+            api_key_sync::load_api_key(provider_id, api_url, cx).await?
+        }
+        Err(_) => None,
+    };
+    if api_key_ref.is_none() && api_key.is_none() {
+        return Err(MissingApiKeyError {
+            provider_id: Some(provider_id),
+        }
+        .into());
+    }
+    resolve_api_key(api_key_ref, api_key, cx).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_reference() {
+        assert_eq!(
+            SecretReference::parse("org:anthropic").unwrap(),
+            SecretReference {
+                organization_slug: None,
+                name: "anthropic".into(),
+            }
+        );
+        assert_eq!(
+            SecretReference::parse("org:acme/openai").unwrap(),
+            SecretReference {
+                organization_slug: Some("acme".into()),
+                name: "openai".into(),
+            }
+        );
+        assert!(SecretReference::parse("anthropic").is_err());
+        assert!(SecretReference::parse("org:").is_err());
+        assert!(SecretReference::parse("org:/openai").is_err());
+    }
+
+    #[test]
+    fn test_current_lease() {
+        let now = SystemTime::now();
+        let leases = HashMap::from_iter([(
+            "org:anthropic".to_string(),
+            Lease {
+                value: "sk-ant".into(),
+                expires_at: now + Duration::from_secs(15 * 60),
+            },
+        )]);
+        assert_eq!(
+            current_lease(&leases, "org:anthropic", now),
+            Some("sk-ant".into())
+        );
+        assert_eq!(current_lease(&leases, "org:openai", now), None);
+
+        // Leases are renewed shortly before they expire.
+        let later = now + Duration::from_secs(15 * 60) - LEASE_RENEWAL_MARGIN;
+        assert_eq!(current_lease(&leases, "org:anthropic", later), None);
+    }
+}
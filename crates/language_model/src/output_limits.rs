@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use collections::BTreeMap;
+use gpui::AppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use crate::{settings::AllLanguageModelSettings, LanguageModel, LanguageModelRequest};
+
+/// The most tokens a completion may generate, so that a request can't
+/// accidentally ask an expensive model for an enormous output.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MaxOutputTokens {
+    /// The ceiling for models that don't have their own.
+    pub default: Option<u32>,
+    /// Ceilings for specific models, keyed by model ID (e.g. "gpt-4o").
+    #[serde(default)]
+    pub models: BTreeMap<String, u32>,
+}
+
+impl MaxOutputTokens {
+    /// Returns the ceiling configured for the given model, if any.
+    pub fn ceiling_for_model(&self, model_id: &str) -> Option<u32> {
+        self.models.get(model_id).copied().or(self.default)
+    }
+}
+
+/// Validates the request's maximum output tokens and clamps them to the
+/// ceiling configured for the given model.
+pub fn clamp_max_output_tokens(
+    request: &mut LanguageModelRequest,
+    model: &dyn LanguageModel,
+    cx: &AppContext,
+) -> Result<()> {
+    let ceiling = AllLanguageModelSettings::get_global(cx)
+        .max_output_tokens
+        .ceiling_for_model(&model.id().0);
+    clamp(request, ceiling, &model.id().0)
+}
+
+fn clamp(request: &mut LanguageModelRequest, ceiling: Option<u32>, model_id: &str) -> Result<()> {
+    if request.max_output_tokens == Some(0) {
+        return Err(anyhow!("max output tokens must be greater than zero"));
+    }
+    let Some(ceiling) = ceiling else {
+        return Ok(());
+    };
+    if ceiling == 0 {
+        return Err(anyhow!(
+            "the max output tokens ceiling for {model_id} must be greater than zero"
+        ));
+    }
+
+    match request.max_output_tokens {
+        Some(requested) if requested > ceiling => {
+            log::warn!(
+                "clamping max output tokens for {model_id} from {requested} to the configured ceiling of {ceiling}"
+            );
+            request.max_output_tokens = Some(ceiling);
+        }
+        Some(_) => {}
+        None => request.max_output_tokens = Some(ceiling),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_max_output_tokens() {
+        let limits = MaxOutputTokens {
+            default: Some(4096),
+            models: BTreeMap::from_iter([("o1-preview".to_string(), 1024)]),
+        };
+        assert_eq!(limits.ceiling_for_model("gpt-4o"), Some(4096));
+        assert_eq!(limits.ceiling_for_model("o1-preview"), Some(1024));
+        assert_eq!(MaxOutputTokens::default().ceiling_for_model("gpt-4o"), None);
+
+        let mut request = LanguageModelRequest {
+            max_output_tokens: Some(100_000),
+            ..Default::default()
+        };
+        clamp(&mut request, Some(1024), "o1-preview").unwrap();
+        assert_eq!(request.max_output_tokens, Some(1024));
+
+        let mut request = LanguageModelRequest {
+            max_output_tokens: Some(512),
+            ..Default::default()
+        };
+        clamp(&mut request, Some(1024), "o1-preview").unwrap();
+        assert_eq!(request.max_output_tokens, Some(512));
+
+        // Requests that don't ask for a maximum get the ceiling.
+        let mut request = LanguageModelRequest::default();
+        clamp(&mut request, Some(4096), "gpt-4o").unwrap();
+        assert_eq!(request.max_output_tokens, Some(4096));
+        let mut request = LanguageModelRequest::default();
+        clamp(&mut request, None, "gpt-4o").unwrap();
+        assert_eq!(request.max_output_tokens, None);
+
+        let mut request = LanguageModelRequest {
+            max_output_tokens: Some(0),
+            ..Default::default()
+        };
+        assert!(clamp(&mut request, None, "gpt-4o").is_err());
+        let mut request = LanguageModelRequest::default();
+        assert!(clamp(&mut request, Some(0), "gpt-4o").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use collections::BTreeMap;
+use gpui::AppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use crate::{settings::AllLanguageModelSettings, LanguageModel, LanguageModelRequest};
+
+/// The parameters a preset sets on a request. Parameters that are left unset
+/// keep the value the request was built with.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PresetParameters {
+    pub temperature: Option<f32>,
+    pub stop: Option<Vec<String>>,
+}
+
+impl PresetParameters {
+    fn merge(&mut self, other: &PresetParameters) {
+        if other.temperature.is_some() {
+            self.temperature = other.temperature;
+        }
+        if other.stop.is_some() {
+            self.stop.clone_from(&other.stop);
+        }
+    }
+}
+
+/// A named bundle of request parameters, such as "precise" or "creative".
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ParameterPreset {
+    pub temperature: Option<f32>,
+    pub stop: Option<Vec<String>>,
+    /// Parameters that take precedence for specific models, keyed by model ID
+    /// (e.g. "gpt-4o").
+    #[serde(default)]
+    pub model_overrides: BTreeMap<String, PresetParameters>,
+}
+
+impl ParameterPreset {
+    /// Returns the preset's parameters for the given model.
+    pub fn parameters_for_model(&self, model_id: &str) -> PresetParameters {
+        let mut parameters = PresetParameters {
+            temperature: self.temperature,
+            stop: self.stop.clone(),
+        };
+        if let Some(overrides) = self.model_overrides.get(model_id) {
+            parameters.merge(overrides);
+        }
+        parameters
+    }
+}
+
+/// Applies the preset the request refers to, as configured for the given
+/// model, to the request.
+pub fn apply_preset(
+    request: &mut LanguageModelRequest,
+    model: &dyn LanguageModel,
+    cx: &AppContext,
+) {
+    let Some(name) = request.preset.as_ref() else {
+        return;
+    };
+    let Some(preset) = AllLanguageModelSettings::get_global(cx).presets.get(name) else {
+        log::warn!("unknown language model preset {name:?}");
+        return;
+    };
+
+    let parameters = preset.parameters_for_model(&model.id().0);
+    if let Some(temperature) = parameters.temperature {
+        request.temperature = temperature;
+    }
+    if let Some(stop) = parameters.stop {
+        request.stop = stop;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters_for_model() {
+        let preset = ParameterPreset {
+            temperature: Some(0.2),
+            stop: Some(vec!["\n\n".into()]),
+            model_overrides: BTreeMap::from_iter([(
+                "o1-mini".to_string(),
+                PresetParameters {
+                    temperature: Some(1.0),
+                    stop: None,
+                },
+            )]),
+        };
+
+        assert_eq!(
+            preset.parameters_for_model("gpt-4o"),
+            PresetParameters {
+                temperature: Some(0.2),
+                stop: Some(vec!["\n\n".into()]),
+            }
+        );
+        assert_eq!(
+            preset.parameters_for_model("o1-mini"),
+            PresetParameters {
+                temperature: Some(1.0),
+                stop: Some(vec!["\n\n".into()]),
+            }
+        );
+    }
+}
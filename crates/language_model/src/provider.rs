@@ -1,4 +1,5 @@
 pub mod anthropic;
+mod api_url_field;
 pub mod cloud;
 pub mod copilot_chat;
 #[cfg(any(test, feature = "test-support"))]
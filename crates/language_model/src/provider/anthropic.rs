@@ -1,7 +1,14 @@
 use crate::{
-    settings::AllLanguageModelSettings, LanguageModel, LanguageModelId, LanguageModelName,
-    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest, Role,
+    api_key_pool::{api_keys_from_env, send_with_api_key, ApiKeyEnv, ApiKeyPool},
+    api_key_sync,
+    connection_settings::http_client_for_provider,
+    managed_settings::LockableSetting,
+    provider::api_url_field::ApiUrlField,
+    request_signing::RequestAuth,
+    settings::AllLanguageModelSettings,
+    ApiKeySync, LanguageModel, LanguageModelId, LanguageModelName, LanguageModelProvider,
+    LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
+    LanguageModelRequest, Role,
 };
 use anyhow::{anyhow, Context as _, Result};
 use collections::BTreeMap;
@@ -13,7 +20,10 @@ use gpui::{
 };
 use http_client::HttpClient;
 use settings::{Settings, SettingsStore};
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use strum::IntoEnumIterator;
 use theme::ThemeSettings;
 use ui::prelude::*;
@@ -26,7 +36,13 @@ const PROVIDER_NAME: &str = "Anthropic";
 pub struct AnthropicSettings {
     pub api_url: String,
     pub low_speed_timeout: Option<Duration>,
+    pub first_token_timeout: Option<Duration>,
     pub available_models: Vec<anthropic::Model>,
+    pub auth: RequestAuth,
+    pub api_key_ref: Option<String>,
+    pub api_key_env: Option<ApiKeyEnv>,
+    /// The tag sent as the `user_id` metadata of every request.
+    pub user: Option<String>,
 }
 
 pub struct AnthropicLanguageModelProvider {
@@ -36,6 +52,8 @@ pub struct AnthropicLanguageModelProvider {
 
 struct State {
     api_key: Option<String>,
+    /// The keys from the environment, when requests are sent with them.
+    api_key_pool: Option<Arc<Mutex<ApiKeyPool>>>,
     _subscription: Subscription,
 }
 
@@ -43,6 +61,7 @@ impl AnthropicLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut AppContext) -> Self {
         let state = cx.new_model(|cx| State {
             api_key: None,
+            api_key_pool: None,
             _subscription: cx.observe_global::<SettingsStore>(|_, cx| {
                 cx.notify();
             }),
@@ -100,32 +119,45 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
             .collect()
     }
 
+    fn first_token_timeout(&self, cx: &AppContext) -> Option<Duration> {
+        AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .first_token_timeout
+    }
+
     fn is_authenticated(&self, cx: &AppContext) -> bool {
         self.state.read(cx).api_key.is_some()
+            || AllLanguageModelSettings::get_global(cx)
+                .anthropic
+                .api_key_ref
+                .is_some()
     }
 
     fn authenticate(&self, cx: &AppContext) -> Task<Result<()>> {
         if self.is_authenticated(cx) {
             Task::ready(Ok(()))
         } else {
-            let api_url = AllLanguageModelSettings::get_global(cx)
-                .anthropic
-                .api_url
-                .clone();
+            let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
+            let api_url = settings.api_url.clone();
+            let api_key_pool =
+                api_keys_from_env(settings.api_key_env.as_ref(), "ANTHROPIC_API_KEY");
             let state = self.state.clone();
             cx.spawn(|mut cx| async move {
-                let api_key = if let Ok(api_key) = std::env::var("ANTHROPIC_API_KEY") {
-                    api_key
+                let (api_key, api_key_pool) = if let Some(mut api_key_pool) = api_key_pool {
+                    (
+                        api_key_pool.key(Instant::now()),
+                        Some(Arc::new(Mutex::new(api_key_pool))),
+                    )
                 } else {
-                    let (_, api_key) = cx
-                        .update(|cx| cx.read_credentials(&api_url))?
+                    let api_key = api_key_sync::load_api_key(PROVIDER_ID, api_url, &mut cx)
                         .await?
                         .ok_or_else(|| anyhow!("credentials not found"))?;
-                    String::from_utf8(api_key)?
+                    (Some(api_key), None)
                 };
 
                 state.update(&mut cx, |this, cx| {
-                    this.api_key = Some(api_key);
+                    this.api_key = api_key;
+                    this.api_key_pool = api_key_pool;
                     cx.notify();
                 })
             })
@@ -139,12 +171,16 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
 
     fn reset_credentials(&self, cx: &AppContext) -> Task<Result<()>> {
         let state = self.state.clone();
-        let delete_credentials =
-            cx.delete_credentials(&AllLanguageModelSettings::get_global(cx).anthropic.api_url);
+        let delete_credentials = api_key_sync::delete_api_key(
+            PROVIDER_ID,
+            &AllLanguageModelSettings::get_global(cx).anthropic.api_url,
+            cx,
+        );
         cx.spawn(|mut cx| async move {
             delete_credentials.await.log_err();
             state.update(&mut cx, |this, cx| {
                 this.api_key = None;
+                this.api_key_pool = None;
                 cx.notify();
             })
         })
@@ -194,16 +230,42 @@ impl AnthropicModel {
     ) -> BoxFuture<'static, Result<anthropic::Response>> {
         let http_client = self.http_client.clone();
 
-        let Ok((api_key, api_url)) = cx.read_model(&self.state, |state, cx| {
-            let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
-            (state.api_key.clone(), settings.api_url.clone())
-        }) else {
+        let Ok((http_client, api_key, api_key_pool, api_key_ref, api_url, auth, user)) = cx
+            .read_model(&self.state, |state, cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
+                (
+                    http_client_for_provider(PROVIDER_ID, http_client, cx),
+                    state.api_key.clone(),
+                    state.api_key_pool.clone(),
+                    settings.api_key_ref.clone(),
+                    settings.api_url.clone(),
+                    settings.auth.clone(),
+                    settings.user.clone(),
+                )
+            })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
+        let request = tag_request(request, user);
 
+        let cx = cx.clone();
         async move {
-            let api_key = api_key.ok_or_else(|| anyhow!("missing api key"))?;
-            anthropic::complete(http_client.as_ref(), &api_url, &api_key, request).await
+            send_with_api_key(
+                api_key_ref,
+                api_key,
+                api_key_pool,
+                api_error_status,
+                &cx,
+                |api_key| {
+                    let http_client = auth.http_client(http_client.clone(), &api_key);
+                    let api_url = api_url.clone();
+                    let request = request.clone();
+                    async move {
+                        anthropic::complete(http_client.as_ref(), &api_url, &api_key, request).await
+                    }
+                },
+            )
+            .await
         }
         .boxed()
     }
@@ -215,32 +277,81 @@ impl AnthropicModel {
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<anthropic::Event>>>> {
         let http_client = self.http_client.clone();
 
-        let Ok((api_key, api_url, low_speed_timeout)) = cx.read_model(&self.state, |state, cx| {
+        let Ok((
+            http_client,
+            api_key,
+            api_key_pool,
+            api_key_ref,
+            api_url,
+            low_speed_timeout,
+            auth,
+            user,
+        )) = cx.read_model(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
             (
+                http_client_for_provider(PROVIDER_ID, http_client, cx),
                 state.api_key.clone(),
+                state.api_key_pool.clone(),
+                settings.api_key_ref.clone(),
                 settings.api_url.clone(),
                 settings.low_speed_timeout,
+                settings.auth.clone(),
+                settings.user.clone(),
             )
-        }) else {
+        })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
+        let request = tag_request(request, user);
 
+        let cx = cx.clone();
         async move {
-            let api_key = api_key.ok_or_else(|| anyhow!("missing api key"))?;
-            let request = anthropic::stream_completion(
-                http_client.as_ref(),
-                &api_url,
-                &api_key,
-                request,
-                low_speed_timeout,
-            );
-            request.await
+            send_with_api_key(
+                api_key_ref,
+                api_key,
+                api_key_pool,
+                api_error_status,
+                &cx,
+                |api_key| {
+                    let http_client = auth.http_client(http_client.clone(), &api_key);
+                    let api_url = api_url.clone();
+                    let request = request.clone();
+                    async move {
+                        anthropic::stream_completion(
+                            http_client.as_ref(),
+                            &api_url,
+                            &api_key,
+                            request,
+                            low_speed_timeout,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await
         }
         .boxed()
     }
 }
 
+/// Attaches the configured tag to the request's metadata, unless the request
+/// already has metadata of its own.
+fn tag_request(mut request: anthropic::Request, user: Option<String>) -> anthropic::Request {
+    if request.metadata.is_none() {
+        request.metadata = user.map(|user| anthropic::Metadata {
+            user_id: Some(user),
+        });
+    }
+    request
+}
+
+/// Returns the HTTP status of a failed Anthropic API request.
+fn api_error_status(error: &anyhow::Error) -> Option<u16> {
+    error
+        .downcast_ref::<anthropic::RequestError>()
+        .map(|error| error.status)
+}
+
 impl LanguageModel for AnthropicModel {
     fn id(&self) -> LanguageModelId {
         self.id.clone()
@@ -331,6 +442,8 @@ impl LanguageModel for AnthropicModel {
 
 struct AuthenticationPrompt {
     api_key: View<Editor>,
+    api_url: View<ApiUrlField>,
+    passphrase: Option<View<Editor>>,
     state: gpui::Model<State>,
 }
 
@@ -345,28 +458,51 @@ impl AuthenticationPrompt {
                 );
                 editor
             }),
+            api_url: cx.new_view(|cx| {
+                ApiUrlField::new(
+                    LockableSetting::AnthropicApiUrl,
+                    |settings| &settings.anthropic.api_url,
+                    |content, api_url| {
+                        content
+                            .anthropic
+                            .get_or_insert_with(Default::default)
+                            .api_url = Some(api_url)
+                    },
+                    cx,
+                )
+            }),
+            passphrase: ApiKeySync::needs_passphrase(cx).then(|| {
+                cx.new_view(|cx| {
+                    let mut editor = Editor::single_line(cx);
+                    editor.set_placeholder_text("Passphrase for synced API keys", cx);
+                    editor.set_masked(true, cx);
+                    editor
+                })
+            }),
             state,
         }
     }
 
     fn save_api_key(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
         let api_key = self.api_key.read(cx).text(cx);
-        if api_key.is_empty() {
+        let passphrase = self
+            .passphrase
+            .as_ref()
+            .map(|passphrase| passphrase.read(cx).text(cx))
+            .filter(|passphrase| !passphrase.is_empty());
+        if api_key.is_empty() && passphrase.is_none() {
             return;
         }
 
-        let write_credentials = cx.write_credentials(
-            AllLanguageModelSettings::get_global(cx)
-                .anthropic
-                .api_url
-                .as_str(),
-            "Bearer",
-            api_key.as_bytes(),
-        );
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .api_url
+            .clone();
+        let save_api_key =
+            api_key_sync::save_api_key(PROVIDER_ID, api_url, api_key, passphrase, cx);
         let state = self.state.clone();
         cx.spawn(|_, mut cx| async move {
-            write_credentials.await?;
-
+            let api_key = save_api_key.await?;
             state.update(&mut cx, |this, cx| {
                 this.api_key = Some(api_key);
                 cx.notify();
@@ -375,7 +511,7 @@ impl AuthenticationPrompt {
         .detach_and_log_err(cx);
     }
 
-    fn render_api_key_editor(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+    fn render_editor(&self, editor: &View<Editor>, cx: &mut ViewContext<Self>) -> impl IntoElement {
         let settings = ThemeSettings::get_global(cx);
         let text_style = TextStyle {
             color: cx.theme().colors().text,
@@ -392,7 +528,7 @@ impl AuthenticationPrompt {
             white_space: WhiteSpace::Normal,
         };
         EditorElement::new(
-            &self.api_key,
+            editor,
             EditorStyle {
                 background: cx.theme().colors().editor_background,
                 local_player: cx.theme().players().local(),
@@ -427,8 +563,28 @@ impl Render for AuthenticationPrompt {
                     .py_1()
                     .bg(cx.theme().colors().editor_background)
                     .rounded_md()
-                    .child(self.render_api_key_editor(cx)),
+                    .child(self.render_editor(&self.api_key, cx)),
             )
+            .when_some(self.passphrase.clone(), |this, passphrase| {
+                this.child(
+                    Label::new(
+                        "Enter the passphrase you use to sync API keys between your devices. \
+                         If you've already synced a key, you can leave the API key empty.",
+                    )
+                    .size(LabelSize::Small),
+                )
+                .child(
+                    h_flex()
+                        .w_full()
+                        .my_2()
+                        .px_2()
+                        .py_1()
+                        .bg(cx.theme().colors().editor_background)
+                        .rounded_md()
+                        .child(self.render_editor(&passphrase, cx)),
+                )
+            })
+            .child(self.api_url.clone())
             .child(
                 Label::new(
                     "You can also assign the ANTHROPIC_API_KEY environment variable and restart Zed.",
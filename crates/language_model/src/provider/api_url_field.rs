@@ -0,0 +1,218 @@
+use editor::{Editor, EditorElement, EditorStyle};
+use fs::Fs;
+use gpui::{AppContext, FocusableView, FontStyle, Subscription, TextStyle, View, WhiteSpace};
+use settings::{update_settings_file, Settings, SettingsStore};
+use theme::ThemeSettings;
+use ui::prelude::*;
+
+use crate::managed_settings::LockableSetting;
+use crate::settings::{AllLanguageModelSettings, AllLanguageModelSettingsContent};
+
+/// The URL a provider sends its requests to. Users can change it, unless it's
+/// locked by the managed settings.
+pub(crate) struct ApiUrlField {
+    editor: View<Editor>,
+    setting: LockableSetting,
+    api_url: fn(&AllLanguageModelSettings) -> &String,
+    set_api_url: fn(&mut AllLanguageModelSettingsContent, String),
+    _settings_subscription: Subscription,
+}
+
+impl ApiUrlField {
+    pub fn new(
+        setting: LockableSetting,
+        api_url: fn(&AllLanguageModelSettings) -> &String,
+        set_api_url: fn(&mut AllLanguageModelSettingsContent, String),
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let mut this = Self {
+            editor: cx.new_view(|cx| Editor::single_line(cx)),
+            setting,
+            api_url,
+            set_api_url,
+            _settings_subscription: cx.observe_global::<SettingsStore>(Self::sync_with_settings),
+        };
+        this.sync_with_settings(cx);
+        this
+    }
+
+    fn is_locked(&self, cx: &AppContext) -> bool {
+        AllLanguageModelSettings::get_global(cx).is_locked(self.setting)
+    }
+
+    /// Shows the configured URL, unless the user is editing it.
+    fn sync_with_settings(&mut self, cx: &mut ViewContext<Self>) {
+        let is_locked = self.is_locked(cx);
+        let api_url = (self.api_url)(AllLanguageModelSettings::get_global(cx)).clone();
+        self.editor.update(cx, |editor, cx| {
+            editor.set_read_only(is_locked);
+            if is_locked || !editor.focus_handle(cx).is_focused(cx) {
+                editor.set_text(api_url, cx);
+            }
+        });
+        cx.notify();
+    }
+
+    fn save_api_url(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
+        // Keep the prompt from treating the URL as an API key.
+        cx.stop_propagation();
+        if self.is_locked(cx) {
+            return;
+        }
+
+        let api_url = self.editor.read(cx).text(cx).trim().to_string();
+        if api_url.is_empty()
+            || api_url == *(self.api_url)(AllLanguageModelSettings::get_global(cx))
+        {
+            return;
+        }
+        let set_api_url = self.set_api_url;
+        update_settings_file::<AllLanguageModelSettings>(
+            <dyn Fs>::global(cx),
+            cx,
+            move |content, _| set_api_url(content, api_url),
+        );
+    }
+
+    fn render_editor(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let settings = ThemeSettings::get_global(cx);
+        let text_style = TextStyle {
+            color: if self.is_locked(cx) {
+                cx.theme().colors().text_muted
+            } else {
+                cx.theme().colors().text
+            },
+            font_family: settings.ui_font.family.clone(),
+            font_features: settings.ui_font.features.clone(),
+            font_fallbacks: settings.ui_font.fallbacks.clone(),
+            font_size: rems(0.875).into(),
+            font_weight: settings.ui_font.weight,
+            font_style: FontStyle::Normal,
+            line_height: relative(1.3),
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+            white_space: WhiteSpace::Normal,
+        };
+        EditorElement::new(
+            &self.editor,
+            EditorStyle {
+                background: cx.theme().colors().editor_background,
+                local_player: cx.theme().players().local(),
+                text: text_style,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+impl Render for ApiUrlField {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let is_locked = self.is_locked(cx);
+        v_flex()
+            .w_full()
+            .on_action(cx.listener(Self::save_api_url))
+            .child(
+                h_flex()
+                    .gap_1()
+                    .child(Label::new("API URL").size(LabelSize::Small))
+                    .when(is_locked, |this| {
+                        this.child(
+                            Label::new("(managed by your organization)")
+                                .size(LabelSize::Small)
+                                .color(Color::Muted),
+                        )
+                    }),
+            )
+            .child(
+                h_flex()
+                    .w_full()
+                    .my_2()
+                    .px_2()
+                    .py_1()
+                    .bg(cx.theme().colors().editor_background)
+                    .rounded_md()
+                    .child(self.render_editor(cx)),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::{TestAppContext, UpdateGlobal, VisualTestContext};
+
+    use super::*;
+
+    #[gpui::test]
+    fn test_locked_api_url_field(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(theme::LoadThemes::JustBase, cx);
+            language::init(cx);
+            editor::init_settings(cx);
+            crate::settings::init(cx);
+        });
+        let update_settings = |cx: &mut VisualTestContext, user: &str, managed: &str| {
+            cx.update(|cx| {
+                SettingsStore::update_global(cx, |store, cx| {
+                    store.set_user_settings(user, cx).unwrap();
+                    store.set_managed_settings(managed, cx).unwrap();
+                })
+            });
+            cx.run_until_parked();
+        };
+
+        let (field, cx) = cx.add_window_view(|cx| {
+            ApiUrlField::new(
+                LockableSetting::OpenAiApiUrl,
+                |settings| &settings.openai.api_url,
+                |content, api_url| {
+                    content.openai.get_or_insert_with(Default::default).api_url = Some(api_url)
+                },
+                cx,
+            )
+        });
+        let field_state = |cx: &mut VisualTestContext| {
+            field.update(cx, |field, cx| {
+                let editor = field.editor.read(cx);
+                (editor.text(cx), editor.read_only(cx), field.is_locked(cx))
+            })
+        };
+
+        update_settings(
+            cx,
+            r#"{ "language_models": { "openai": { "api_url": "https://user.example.com/v1" } } }"#,
+            "",
+        );
+        assert_eq!(
+            field_state(cx),
+            ("https://user.example.com/v1".into(), false, false)
+        );
+
+        update_settings(
+            cx,
+            r#"{ "language_models": { "openai": { "api_url": "https://user.example.com/v1" } } }"#,
+            r#"{
+                "language_models": {
+                    "openai": { "api_url": "https://gateway.example.com/v1" },
+                    "locked": ["openai.api_url"]
+                }
+            }"#,
+        );
+        assert_eq!(
+            field_state(cx),
+            ("https://gateway.example.com/v1".into(), true, true)
+        );
+
+        // Confirming a locked URL doesn't write it to the settings.
+        field.update(cx, |field, cx| {
+            field.save_api_url(&menu::Confirm, cx);
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            field_state(cx),
+            ("https://gateway.example.com/v1".into(), true, true)
+        );
+    }
+}
@@ -2,19 +2,22 @@ use super::open_ai::count_open_ai_tokens;
 use crate::{
     settings::AllLanguageModelSettings, CloudModel, LanguageModel, LanguageModelId,
     LanguageModelName, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest,
+    LanguageModelProviderState, LanguageModelRegistry, LanguageModelRequest,
+    RecommendedLanguageModel,
 };
 use anyhow::{anyhow, Context as _, Result};
 use client::Client;
 use collections::BTreeMap;
 use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
-use gpui::{AnyView, AppContext, AsyncAppContext, Subscription, Task};
+use gpui::{AnyView, AppContext, AsyncAppContext, BackgroundExecutor, Subscription, Task};
+use ring::rand::{SecureRandom, SystemRandom};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
-use std::{future, sync::Arc};
+use std::{future, sync::Arc, time::Duration};
 use strum::IntoEnumIterator;
 use ui::prelude::*;
+use util::ResultExt;
 
 use crate::LanguageModelProvider;
 
@@ -26,6 +29,8 @@ pub const PROVIDER_NAME: &str = "zed.dev";
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct ZedDotDevSettings {
     pub available_models: Vec<AvailableModel>,
+    pub resumable_streaming: bool,
+    pub allow_vendor_failover: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -76,15 +81,23 @@ impl CloudLanguageModelProvider {
         });
 
         let state_ref = state.downgrade();
-        let maintain_client_status = cx.spawn(|mut cx| async move {
-            while let Some(status) = status_rx.next().await {
-                if let Some(this) = state_ref.upgrade() {
-                    _ = this.update(&mut cx, |this, cx| {
-                        this.status = status;
-                        cx.notify();
-                    });
-                } else {
-                    break;
+        let maintain_client_status = cx.spawn({
+            let client = client.clone();
+            |mut cx| async move {
+                while let Some(status) = status_rx.next().await {
+                    if status.is_connected() {
+                        fetch_recommended_default_model(&client, &mut cx)
+                            .await
+                            .log_err();
+                    }
+                    if let Some(this) = state_ref.upgrade() {
+                        _ = this.update(&mut cx, |this, cx| {
+                            this.status = status;
+                            cx.notify();
+                        });
+                    } else {
+                        break;
+                    }
                 }
             }
         });
@@ -97,6 +110,25 @@ impl CloudLanguageModelProvider {
     }
 }
 
+/// Asks the server for the default model to use when the user hasn't chosen one.
+async fn fetch_recommended_default_model(client: &Client, cx: &mut AsyncAppContext) -> Result<()> {
+    let response = client
+        .request(proto::GetRecommendedLanguageModel {})
+        .await?;
+    let recommended_model = response.model.map(|model| RecommendedLanguageModel {
+        provider: LanguageModelProviderId(model.provider.into()),
+        model: LanguageModelId(model.model.into()),
+        temperature: model.temperature,
+        experiment: model.experiment,
+        variant: model.variant,
+    });
+    cx.update(|cx| {
+        LanguageModelRegistry::global(cx).update(cx, |registry, cx| {
+            registry.set_recommended_default_model(recommended_model, cx)
+        })
+    })
+}
+
 impl LanguageModelProviderState for CloudLanguageModelProvider {
     fn subscribe<T: 'static>(&self, cx: &mut gpui::ModelContext<T>) -> Option<gpui::Subscription> {
         Some(cx.observe(&self.state, |_, _, cx| {
@@ -228,6 +260,7 @@ impl LanguageModel for CloudLanguageModel {
             CloudModel::OpenAi(model) => count_open_ai_tokens(request, model, cx),
             CloudModel::Google(model) => {
                 let client = self.client.clone();
+                let feature = request.feature.map(|feature| feature.as_str().to_string());
                 let request = request.into_google(model.id().into());
                 let request = google_ai::CountTokensRequest {
                     contents: request.contents,
@@ -238,6 +271,7 @@ impl LanguageModel for CloudLanguageModel {
                         .request(proto::CountLanguageModelTokens {
                             provider: proto::LanguageModelProvider::Google as i32,
                             request,
+                            feature,
                         })
                         .await?;
                     Ok(response.token_count as usize)
@@ -250,64 +284,47 @@ impl LanguageModel for CloudLanguageModel {
     fn stream_completion(
         &self,
         request: LanguageModelRequest,
-        _: &AsyncAppContext,
+        cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<String>>>> {
-        match &self.model {
-            CloudModel::Anthropic(model) => {
-                let client = self.client.clone();
-                let request = request.into_anthropic(model.id().into());
-                async move {
-                    let request = serde_json::to_string(&request)?;
-                    let stream = client
-                        .request_stream(proto::StreamCompleteWithLanguageModel {
-                            provider: proto::LanguageModelProvider::Anthropic as i32,
-                            request,
-                        })
-                        .await?;
-                    Ok(anthropic::extract_text_from_events(
-                        stream.map(|item| Ok(serde_json::from_str(&item?.event)?)),
-                    )
-                    .boxed())
-                }
-                .boxed()
-            }
-            CloudModel::OpenAi(model) => {
-                let client = self.client.clone();
-                let request = request.into_open_ai(model.id().into());
-                async move {
-                    let request = serde_json::to_string(&request)?;
-                    let stream = client
-                        .request_stream(proto::StreamCompleteWithLanguageModel {
-                            provider: proto::LanguageModelProvider::OpenAi as i32,
-                            request,
-                        })
-                        .await?;
-                    Ok(open_ai::extract_text_from_events(
-                        stream.map(|item| Ok(serde_json::from_str(&item?.event)?)),
-                    )
-                    .boxed())
-                }
-                .boxed()
-            }
-            CloudModel::Google(model) => {
-                let client = self.client.clone();
-                let request = request.into_google(model.id().into());
-                async move {
-                    let request = serde_json::to_string(&request)?;
-                    let stream = client
-                        .request_stream(proto::StreamCompleteWithLanguageModel {
-                            provider: proto::LanguageModelProvider::Google as i32,
-                            request,
-                        })
-                        .await?;
-                    Ok(google_ai::extract_text_from_events(
-                        stream.map(|item| Ok(serde_json::from_str(&item?.event)?)),
-                    )
-                    .boxed())
-                }
-                .boxed()
-            }
+        let (resumable, allow_vendor_failover) = cx
+            .update(|cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).zed_dot_dev;
+                (settings.resumable_streaming, settings.allow_vendor_failover)
+            })
+            .unwrap_or((false, false));
+        let resume_executor = resumable.then(|| cx.background_executor().clone());
+        let client = self.client.clone();
+        let feature = request.feature.map(|feature| feature.as_str().to_string());
+        let (provider, request) = match &self.model {
+            CloudModel::Anthropic(model) => (
+                proto::LanguageModelProvider::Anthropic,
+                serde_json::to_string(&request.into_anthropic(model.id().into())),
+            ),
+            CloudModel::OpenAi(model) => (
+                proto::LanguageModelProvider::OpenAi,
+                serde_json::to_string(&request.into_open_ai(model.id().into())),
+            ),
+            CloudModel::Google(model) => (
+                proto::LanguageModelProvider::Google,
+                serde_json::to_string(&request.into_google(model.id().into())),
+            ),
+        };
+        async move {
+            let events = stream_completion_events(
+                client,
+                proto::StreamCompleteWithLanguageModel {
+                    provider: provider as i32,
+                    request: request?,
+                    feature,
+                    stream_id: None,
+                    allow_vendor_failover,
+                },
+                resume_executor,
+            )
+            .await?;
+            extract_text_from_events(provider, events).await
         }
+        .boxed()
     }
 
     fn use_tool(
@@ -321,6 +338,7 @@ impl LanguageModel for CloudLanguageModel {
         match &self.model {
             CloudModel::Anthropic(model) => {
                 let client = self.client.clone();
+                let feature = request.feature.map(|feature| feature.as_str().to_string());
                 let mut request = request.into_anthropic(model.id().into());
                 request.tool_choice = Some(anthropic::ToolChoice::Tool {
                     name: tool_name.clone(),
@@ -337,6 +355,7 @@ impl LanguageModel for CloudLanguageModel {
                         .request(proto::CompleteWithLanguageModel {
                             provider: proto::LanguageModelProvider::Anthropic as i32,
                             request,
+                            feature,
                         })
                         .await?;
                     let response: anthropic::Response = serde_json::from_str(&response.completion)?;
@@ -368,6 +387,150 @@ impl LanguageModel for CloudLanguageModel {
     }
 }
 
+/// How many times in a row a resumable stream is resumed before giving up.
+const MAX_STREAM_RESUME_ATTEMPTS: u32 = 5;
+
+/// The delay before resuming a stream, multiplied by the attempt number.
+const STREAM_RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// Streams the events of a completion from the server. When given an executor,
+/// the server buffers the stream and it is resumed from the last received
+/// event if the connection drops, rather than restarting the completion.
+async fn stream_completion_events(
+    client: Arc<Client>,
+    mut request: proto::StreamCompleteWithLanguageModel,
+    resume_executor: Option<BackgroundExecutor>,
+) -> Result<BoxStream<'static, Result<proto::StreamCompleteWithLanguageModelResponse>>> {
+    let Some(executor) = resume_executor else {
+        return Ok(client.request_stream(request).await?.boxed());
+    };
+
+    let stream_id = new_stream_id()?;
+    request.stream_id = Some(stream_id.clone());
+    let events = client.request_stream(request).await?.boxed();
+    let stream = ResumableStream {
+        client,
+        executor,
+        stream_id,
+        transcript: Default::default(),
+        events,
+        done: false,
+    };
+    Ok(futures::stream::unfold(stream, |mut stream| async move {
+        if stream.done {
+            return None;
+        }
+        match stream.next_event().await {
+            Ok(Some(event)) => Some((Ok(event), stream)),
+            Ok(None) => None,
+            Err(error) => {
+                stream.done = true;
+                Some((Err(error), stream))
+            }
+        }
+    })
+    .boxed())
+}
+
+struct ResumableStream {
+    client: Arc<Client>,
+    executor: BackgroundExecutor,
+    stream_id: String,
+    transcript: proto::LanguageModelStreamTranscript,
+    events: BoxStream<'static, Result<proto::StreamCompleteWithLanguageModelResponse>>,
+    done: bool,
+}
+
+impl ResumableStream {
+    async fn next_event(
+        &mut self,
+    ) -> Result<Option<proto::StreamCompleteWithLanguageModelResponse>> {
+        let mut attempt = 0;
+        loop {
+            match self.events.next().await {
+                Some(Ok(response)) if response.end_of_stream => return Ok(None),
+                Some(Ok(response)) => {
+                    self.transcript.receive(&response)?;
+                    return Ok(Some(response));
+                }
+                Some(Err(error)) => return Err(error),
+                // The stream ended without the server marking its end, so the
+                // connection dropped.
+                None => {}
+            }
+
+            attempt += 1;
+            if attempt > MAX_STREAM_RESUME_ATTEMPTS {
+                return Err(anyhow!("lost the connection to the completion stream"));
+            }
+            self.executor.timer(STREAM_RESUME_DELAY * attempt).await;
+            let resumed = self
+                .client
+                .request_stream(proto::ResumeLanguageModelStream {
+                    stream_id: self.stream_id.clone(),
+                    offset: self.transcript.offset,
+                    checksum: self.transcript.checksum,
+                })
+                .await;
+            self.events = match resumed {
+                Ok(events) => events.boxed(),
+                Err(error) => {
+                    log::info!("failed to resume completion stream: {error}");
+                    futures::stream::empty().boxed()
+                }
+            };
+        }
+    }
+}
+
+/// Extracts the text of a completion from its events, which are in the format
+/// of the vendor that served the request. That's the requested vendor, unless
+/// the server failed the request over to another one.
+async fn extract_text_from_events(
+    requested_provider: proto::LanguageModelProvider,
+    mut events: BoxStream<'static, Result<proto::StreamCompleteWithLanguageModelResponse>>,
+) -> Result<BoxStream<'static, Result<String>>> {
+    let first_event = events.next().await.transpose()?;
+    let provider = first_event
+        .as_ref()
+        .and_then(|event| event.failover_provider)
+        .and_then(proto::LanguageModelProvider::from_i32)
+        .unwrap_or(requested_provider);
+    if provider != requested_provider {
+        log::info!(
+            "zed.dev served a {:?} completion with {:?}",
+            requested_provider,
+            provider
+        );
+    }
+
+    let events = futures::stream::iter(first_event.map(Ok))
+        .chain(events)
+        .map(|event| event.map(|event| event.event));
+    Ok(match provider {
+        proto::LanguageModelProvider::Anthropic => anthropic::extract_text_from_events(
+            events.map(|event| Ok(serde_json::from_str(&event?)?)),
+        )
+        .boxed(),
+        proto::LanguageModelProvider::OpenAi => open_ai::extract_text_from_events(
+            events.map(|event| Ok(serde_json::from_str(&event?)?)),
+        )
+        .boxed(),
+        proto::LanguageModelProvider::Google => google_ai::extract_text_from_events(
+            events.map(|event| Ok(serde_json::from_str(&event?)?)),
+        )
+        .boxed(),
+    })
+}
+
+fn new_stream_id() -> Result<String> {
+    let mut bytes = [0; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("failed to generate a stream ID"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
 struct AuthenticationPrompt {
     state: gpui::Model<State>,
 }
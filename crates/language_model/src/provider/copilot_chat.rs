@@ -38,6 +38,7 @@ const PROVIDER_NAME: &str = "GitHub Copilot Chat";
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct CopilotChatSettings {
     pub low_speed_timeout: Option<Duration>,
+    pub first_token_timeout: Option<Duration>,
 }
 
 pub struct CopilotChatLanguageModelProvider {
@@ -89,6 +90,12 @@ impl LanguageModelProvider for CopilotChatLanguageModelProvider {
             .collect()
     }
 
+    fn first_token_timeout(&self, cx: &AppContext) -> Option<Duration> {
+        AllLanguageModelSettings::get_global(cx)
+            .copilot_chat
+            .first_token_timeout
+    }
+
     fn is_authenticated(&self, cx: &AppContext) -> bool {
         CopilotChat::global(cx)
             .map(|m| m.read(cx).is_authenticated())
@@ -1,8 +1,8 @@
 use anyhow::{anyhow, Result};
 use collections::BTreeMap;
 use editor::{Editor, EditorElement, EditorStyle};
-use futures::{future::BoxFuture, FutureExt, StreamExt};
-use google_ai::stream_generate_content;
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use google_ai::{stream_raw_generate_content, GenerateContentResponse, Part, TextPart};
 use gpui::{
     AnyView, AppContext, AsyncAppContext, FontStyle, Subscription, Task, TextStyle, View,
     WhiteSpace,
@@ -16,9 +16,12 @@ use ui::prelude::*;
 use util::ResultExt;
 
 use crate::{
-    settings::AllLanguageModelSettings, LanguageModel, LanguageModelId, LanguageModelName,
-    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest,
+    connection_settings::http_client_for_provider, managed_settings::LockableSetting,
+    org_vault::resolve_api_key, provider::api_url_field::ApiUrlField,
+    settings::AllLanguageModelSettings, text_from_events, Citation, LanguageModel,
+    LanguageModelCompletionEvent, LanguageModelId, LanguageModelName, LanguageModelProvider,
+    LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
+    LanguageModelRequest,
 };
 
 const PROVIDER_ID: &str = "google";
@@ -28,7 +31,9 @@ const PROVIDER_NAME: &str = "Google AI";
 pub struct GoogleSettings {
     pub api_url: String,
     pub low_speed_timeout: Option<Duration>,
+    pub first_token_timeout: Option<Duration>,
     pub available_models: Vec<google_ai::Model>,
+    pub api_key_ref: Option<String>,
 }
 
 pub struct GoogleLanguageModelProvider {
@@ -102,8 +107,18 @@ impl LanguageModelProvider for GoogleLanguageModelProvider {
             .collect()
     }
 
+    fn first_token_timeout(&self, cx: &AppContext) -> Option<Duration> {
+        AllLanguageModelSettings::get_global(cx)
+            .google
+            .first_token_timeout
+    }
+
     fn is_authenticated(&self, cx: &AppContext) -> bool {
         self.state.read(cx).api_key.is_some()
+            || AllLanguageModelSettings::get_global(cx)
+                .google
+                .api_key_ref
+                .is_some()
     }
 
     fn authenticate(&self, cx: &AppContext) -> Task<Result<()>> {
@@ -191,15 +206,15 @@ impl LanguageModel for GoogleLanguageModel {
         cx: &AppContext,
     ) -> BoxFuture<'static, Result<usize>> {
         let request = request.into_google(self.model.id().to_string());
-        let http_client = self.http_client.clone();
+        let http_client = http_client_for_provider(PROVIDER_ID, self.http_client.clone(), cx);
         let api_key = self.state.read(cx).api_key.clone();
-        let api_url = AllLanguageModelSettings::get_global(cx)
-            .google
-            .api_url
-            .clone();
+        let settings = &AllLanguageModelSettings::get_global(cx).google;
+        let api_key_ref = settings.api_key_ref.clone();
+        let api_url = settings.api_url.clone();
 
+        let cx = cx.to_async();
         async move {
-            let api_key = api_key.ok_or_else(|| anyhow!("missing api key"))?;
+            let api_key = resolve_api_key(api_key_ref, api_key, &cx).await?;
             let response = google_ai::count_tokens(
                 http_client.as_ref(),
                 &api_url,
@@ -219,22 +234,56 @@ impl LanguageModel for GoogleLanguageModel {
         request: LanguageModelRequest,
         cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<futures::stream::BoxStream<'static, Result<String>>>> {
+        let events = self.stream_completion_events(request, cx);
+        async move { Ok(text_from_events(events.await?)) }.boxed()
+    }
+
+    fn stream_completion_events(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<LanguageModelCompletionEvent>>>> {
+        let raw_events = request.raw_events;
         let request = request.into_google(self.model.id().to_string());
 
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url)) = cx.read_model(&self.state, |state, cx| {
-            let settings = &AllLanguageModelSettings::get_global(cx).google;
-            (state.api_key.clone(), settings.api_url.clone())
-        }) else {
+        let Ok((http_client, api_key, api_key_ref, api_url)) =
+            cx.read_model(&self.state, |state, cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).google;
+                (
+                    http_client_for_provider(PROVIDER_ID, http_client, cx),
+                    state.api_key.clone(),
+                    settings.api_key_ref.clone(),
+                    settings.api_url.clone(),
+                )
+            })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
+        let cx = cx.clone();
         async move {
-            let api_key = api_key.ok_or_else(|| anyhow!("missing api key"))?;
+            let api_key = resolve_api_key(api_key_ref, api_key, &cx).await?;
             let response =
-                stream_generate_content(http_client.as_ref(), &api_url, &api_key, request);
+                stream_raw_generate_content(http_client.as_ref(), &api_url, &api_key, request);
             let events = response.await?;
-            Ok(google_ai::extract_text_from_events(events).boxed())
+            Ok(events
+                .flat_map(move |event| {
+                    let mut events = Vec::new();
+                    if raw_events {
+                        if let Ok(event) = &event {
+                            events.push(Ok(LanguageModelCompletionEvent::Raw(event.clone())));
+                        }
+                    }
+                    match event.and_then(|event| {
+                        Ok(serde_json::from_value::<GenerateContentResponse>(event)?)
+                    }) {
+                        Ok(event) => events.extend(events_from_response(event).into_iter().map(Ok)),
+                        Err(error) => events.push(Err(error)),
+                    }
+                    futures::stream::iter(events)
+                })
+                .boxed())
         }
         .boxed()
     }
@@ -251,8 +300,89 @@ impl LanguageModel for GoogleLanguageModel {
     }
 }
 
+/// Converts a chunk of a response into events, including the sources the
+/// response cites and, for responses grounded with Google Search, the
+/// search results it's based on.
+fn events_from_response(response: GenerateContentResponse) -> Vec<LanguageModelCompletionEvent> {
+    let Some(candidate) = response
+        .candidates
+        .and_then(|candidates| candidates.into_iter().next())
+    else {
+        return Vec::new();
+    };
+
+    let mut events = candidate
+        .content
+        .parts
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::TextPart(TextPart { text }) => Some(LanguageModelCompletionEvent::Text(text)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(citation_metadata) = candidate.citation_metadata {
+        events.extend(
+            citation_metadata
+                .citation_sources
+                .into_iter()
+                .map(|source| {
+                    LanguageModelCompletionEvent::Citation(Citation {
+                        url: source.uri,
+                        output_range: source
+                            .start_index
+                            .zip(source.end_index)
+                            .map(|(start, end)| start..end),
+                        ..Default::default()
+                    })
+                }),
+        );
+    }
+
+    if let Some(grounding_metadata) = candidate.grounding_metadata {
+        let mut is_supporting = vec![false; grounding_metadata.grounding_chunks.len()];
+        let chunk_citation = |chunk: &google_ai::GroundingChunk| {
+            let web = chunk.web.as_ref()?;
+            Some(Citation {
+                url: web.uri.clone(),
+                title: web.title.clone(),
+                ..Default::default()
+            })
+        };
+        for support in &grounding_metadata.grounding_supports {
+            for &ix in &support.grounding_chunk_indices {
+                let Some(citation) = grounding_metadata
+                    .grounding_chunks
+                    .get(ix)
+                    .and_then(chunk_citation)
+                else {
+                    continue;
+                };
+                is_supporting[ix] = true;
+                events.push(LanguageModelCompletionEvent::Citation(Citation {
+                    output_range: Some(support.segment.start_index..support.segment.end_index),
+                    ..citation
+                }));
+            }
+        }
+        // Cite the search results that don't support a specific segment too.
+        for (chunk, is_supporting) in grounding_metadata
+            .grounding_chunks
+            .iter()
+            .zip(is_supporting)
+        {
+            if let Some(citation) = chunk_citation(chunk).filter(|_| !is_supporting) {
+                events.push(LanguageModelCompletionEvent::Citation(citation));
+            }
+        }
+    }
+
+    events
+}
+
 struct AuthenticationPrompt {
     api_key: View<Editor>,
+    api_url: View<ApiUrlField>,
     state: gpui::Model<State>,
 }
 
@@ -264,6 +394,16 @@ impl AuthenticationPrompt {
                 editor.set_placeholder_text("AIzaSy...", cx);
                 editor
             }),
+            api_url: cx.new_view(|cx| {
+                ApiUrlField::new(
+                    LockableSetting::GoogleApiUrl,
+                    |settings| &settings.google.api_url,
+                    |content, api_url| {
+                        content.google.get_or_insert_with(Default::default).api_url = Some(api_url)
+                    },
+                    cx,
+                )
+            }),
             state,
         }
     }
@@ -342,6 +482,7 @@ impl Render for AuthenticationPrompt {
                     .rounded_md()
                     .child(self.render_api_key_editor(cx)),
             )
+            .child(self.api_url.clone())
             .child(
                 Label::new(
                     "You can also assign the GOOGLE_AI_API_KEY environment variable and restart Zed.",
@@ -360,3 +501,47 @@ impl Render for AuthenticationPrompt {
             .into_any()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_from_response() {
+        let response = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "index": 0,
+                "content": { "role": "model", "parts": [{ "text": "Zed is fast." }] },
+                "groundingMetadata": {
+                    "groundingChunks": [
+                        { "web": { "uri": "https://zed.dev", "title": "zed.dev" } },
+                        { "web": { "uri": "https://example.com", "title": "example.com" } }
+                    ],
+                    "groundingSupports": [{
+                        "segment": { "startIndex": 0, "endIndex": 12, "text": "Zed is fast." },
+                        "groundingChunkIndices": [0]
+                    }]
+                }
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            events_from_response(response),
+            [
+                LanguageModelCompletionEvent::Text("Zed is fast.".into()),
+                LanguageModelCompletionEvent::Citation(Citation {
+                    url: Some("https://zed.dev".into()),
+                    title: Some("zed.dev".into()),
+                    file_id: None,
+                    output_range: Some(0..12),
+                }),
+                LanguageModelCompletionEvent::Citation(Citation {
+                    url: Some("https://example.com".into()),
+                    title: Some("example.com".into()),
+                    file_id: None,
+                    output_range: None,
+                }),
+            ]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
-use gpui::{AnyView, AppContext, AsyncAppContext, ModelContext, Subscription, Task};
+use gpui::{AnyView, AppContext, AsyncAppContext, ModelContext, Subscription, Task, View};
 use http_client::HttpClient;
 use ollama::{
     get_models, preload_model, stream_chat_completion, ChatMessage, ChatOptions, ChatRequest,
@@ -10,9 +10,10 @@ use std::{future, sync::Arc, time::Duration};
 use ui::{prelude::*, ButtonLike, ElevationIndex};
 
 use crate::{
-    settings::AllLanguageModelSettings, LanguageModel, LanguageModelId, LanguageModelName,
-    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest, Role,
+    connection_settings::http_client_for_provider, managed_settings::LockableSetting,
+    provider::api_url_field::ApiUrlField, settings::AllLanguageModelSettings, LanguageModel,
+    LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
+    LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest, Role,
 };
 
 const OLLAMA_DOWNLOAD_URL: &str = "https://ollama.com/download";
@@ -25,6 +26,7 @@ const PROVIDER_NAME: &str = "Ollama";
 pub struct OllamaSettings {
     pub api_url: String,
     pub low_speed_timeout: Option<Duration>,
+    pub first_token_timeout: Option<Duration>,
 }
 
 pub struct OllamaLanguageModelProvider {
@@ -154,6 +156,12 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
             .detach_and_log_err(cx);
     }
 
+    fn first_token_timeout(&self, cx: &AppContext) -> Option<Duration> {
+        AllLanguageModelSettings::get_global(cx)
+            .ollama
+            .first_token_timeout
+    }
+
     fn is_authenticated(&self, cx: &AppContext) -> bool {
         !self.state.read(cx).available_models.is_empty()
     }
@@ -210,6 +218,7 @@ impl OllamaLanguageModel {
             stream: true,
             options: Some(ChatOptions {
                 num_ctx: Some(self.model.max_tokens),
+                num_predict: request.max_output_tokens.map(|tokens| tokens as isize),
                 stop: Some(request.stop),
                 temperature: Some(request.temperature),
                 ..Default::default()
@@ -268,9 +277,13 @@ impl LanguageModel for OllamaLanguageModel {
         let request = self.to_ollama_request(request);
 
         let http_client = self.http_client.clone();
-        let Ok((api_url, low_speed_timeout)) = cx.update(|cx| {
+        let Ok((http_client, api_url, low_speed_timeout)) = cx.update(|cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).ollama;
-            (settings.api_url.clone(), settings.low_speed_timeout)
+            (
+                http_client_for_provider(PROVIDER_ID, http_client, cx),
+                settings.api_url.clone(),
+                settings.low_speed_timeout,
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
@@ -313,14 +326,27 @@ impl LanguageModel for OllamaLanguageModel {
 
 struct DownloadOllamaMessage {
     retry_connection: Box<dyn Fn(&mut WindowContext) -> Task<Result<()>>>,
+    api_url: View<ApiUrlField>,
 }
 
 impl DownloadOllamaMessage {
     pub fn new(
         retry_connection: Box<dyn Fn(&mut WindowContext) -> Task<Result<()>>>,
-        _cx: &mut ViewContext<Self>,
+        cx: &mut ViewContext<Self>,
     ) -> Self {
-        Self { retry_connection }
+        Self {
+            retry_connection,
+            api_url: cx.new_view(|cx| {
+                ApiUrlField::new(
+                    LockableSetting::OllamaApiUrl,
+                    |settings| &settings.ollama.api_url,
+                    |content, api_url| {
+                        content.ollama.get_or_insert_with(Default::default).api_url = Some(api_url)
+                    },
+                    cx,
+                )
+            }),
+        }
     }
 
     fn render_download_button(&self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
@@ -378,6 +404,7 @@ impl Render for DownloadOllamaMessage {
             .size_full()
             .gap_2()
             .child(Label::new("To use Ollama models via the assistant, Ollama must be running on your machine with at least one model downloaded.").size(LabelSize::Large))
+            .child(self.api_url.clone())
             .child(
                 h_flex()
                     .w_full()
@@ -1,24 +1,42 @@
 use anyhow::{anyhow, Result};
-use collections::BTreeMap;
+use collections::{BTreeMap, HashMap};
 use editor::{Editor, EditorElement, EditorStyle};
-use futures::{future::BoxFuture, FutureExt, StreamExt};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
 use gpui::{
     AnyView, AppContext, AsyncAppContext, FontStyle, Subscription, Task, TextStyle, View,
     WhiteSpace,
 };
 use http_client::HttpClient;
-use open_ai::stream_completion;
+use open_ai::{
+    stream_raw_completion, stream_response, ResponseStreamEvent, ResponsesAnnotation,
+    ResponsesInputMessage, ResponsesStreamEvent,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
-use std::{future, sync::Arc, time::Duration};
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    future,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use strum::IntoEnumIterator;
 use theme::ThemeSettings;
 use ui::prelude::*;
 use util::ResultExt;
 
 use crate::{
-    settings::AllLanguageModelSettings, LanguageModel, LanguageModelId, LanguageModelName,
-    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest, Role,
+    api_key_pool::{api_keys_from_env, send_with_api_key, ApiKeyEnv, ApiKeyPool},
+    api_key_sync, char_offset_to_byte_offset,
+    connection_settings::http_client_for_provider,
+    managed_settings::LockableSetting,
+    provider::api_url_field::ApiUrlField,
+    request_signing::RequestAuth,
+    settings::AllLanguageModelSettings,
+    text_from_events, ApiKeySync, Citation, LanguageModel, LanguageModelCompletionEvent,
+    LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
+    LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest, Role,
 };
 
 const PROVIDER_ID: &str = "openai";
@@ -28,9 +46,28 @@ const PROVIDER_NAME: &str = "OpenAI";
 pub struct OpenAiSettings {
     pub api_url: String,
     pub low_speed_timeout: Option<Duration>,
+    pub first_token_timeout: Option<Duration>,
     pub available_models: Vec<open_ai::Model>,
+    pub auth: RequestAuth,
+    pub api_key_ref: Option<String>,
+    pub api_key_env: Option<ApiKeyEnv>,
+    pub responses_api: BTreeMap<String, ResponsesApiSettings>,
+    /// The tag sent as the `user` of every request.
+    pub user: Option<String>,
+}
+
+/// How a model is requested through the Responses API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResponsesApiSettings {
+    /// Built-in tools the model may use, as the API expects them, e.g.
+    /// `{ "type": "web_search_preview" }`.
+    #[serde(default)]
+    pub tools: Vec<serde_json::Value>,
 }
 
+/// The maximum number of conversations whose server-side state is tracked.
+const MAX_STORED_RESPONSES: usize = 64;
+
 pub struct OpenAiLanguageModelProvider {
     http_client: Arc<dyn HttpClient>,
     state: gpui::Model<State>,
@@ -38,6 +75,9 @@ pub struct OpenAiLanguageModelProvider {
 
 struct State {
     api_key: Option<String>,
+    /// The keys from the environment, when requests are sent with them.
+    api_key_pool: Option<Arc<Mutex<ApiKeyPool>>>,
+    stored_responses: Arc<Mutex<StoredResponses>>,
     _subscription: Subscription,
 }
 
@@ -45,6 +85,8 @@ impl OpenAiLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut AppContext) -> Self {
         let state = cx.new_model(|cx| State {
             api_key: None,
+            api_key_pool: None,
+            stored_responses: Default::default(),
             _subscription: cx.observe_global::<SettingsStore>(|_this: &mut State, cx| {
                 cx.notify();
             }),
@@ -102,31 +144,43 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
             .collect()
     }
 
+    fn first_token_timeout(&self, cx: &AppContext) -> Option<Duration> {
+        AllLanguageModelSettings::get_global(cx)
+            .openai
+            .first_token_timeout
+    }
+
     fn is_authenticated(&self, cx: &AppContext) -> bool {
         self.state.read(cx).api_key.is_some()
+            || AllLanguageModelSettings::get_global(cx)
+                .openai
+                .api_key_ref
+                .is_some()
     }
 
     fn authenticate(&self, cx: &AppContext) -> Task<Result<()>> {
         if self.is_authenticated(cx) {
             Task::ready(Ok(()))
         } else {
-            let api_url = AllLanguageModelSettings::get_global(cx)
-                .openai
-                .api_url
-                .clone();
+            let settings = &AllLanguageModelSettings::get_global(cx).openai;
+            let api_url = settings.api_url.clone();
+            let api_key_pool = api_keys_from_env(settings.api_key_env.as_ref(), "OPENAI_API_KEY");
             let state = self.state.clone();
             cx.spawn(|mut cx| async move {
-                let api_key = if let Ok(api_key) = std::env::var("OPENAI_API_KEY") {
-                    api_key
+                let (api_key, api_key_pool) = if let Some(mut api_key_pool) = api_key_pool {
+                    (
+                        api_key_pool.key(Instant::now()),
+                        Some(Arc::new(Mutex::new(api_key_pool))),
+                    )
                 } else {
-                    let (_, api_key) = cx
-                        .update(|cx| cx.read_credentials(&api_url))?
+                    let api_key = api_key_sync::load_api_key(PROVIDER_ID, api_url, &mut cx)
                         .await?
                         .ok_or_else(|| anyhow!("credentials not found"))?;
-                    String::from_utf8(api_key)?
+                    (Some(api_key), None)
                 };
                 state.update(&mut cx, |this, cx| {
-                    this.api_key = Some(api_key);
+                    this.api_key = api_key;
+                    this.api_key_pool = api_key_pool;
                     cx.notify();
                 })
             })
@@ -140,12 +194,13 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
 
     fn reset_credentials(&self, cx: &AppContext) -> Task<Result<()>> {
         let settings = &AllLanguageModelSettings::get_global(cx).openai;
-        let delete_credentials = cx.delete_credentials(&settings.api_url);
+        let delete_credentials = api_key_sync::delete_api_key(PROVIDER_ID, &settings.api_url, cx);
         let state = self.state.clone();
         cx.spawn(|mut cx| async move {
             delete_credentials.await.log_err();
             state.update(&mut cx, |this, cx| {
                 this.api_key = None;
+                this.api_key_pool = None;
                 cx.notify();
             })
         })
@@ -197,31 +252,95 @@ impl LanguageModel for OpenAiLanguageModel {
         request: LanguageModelRequest,
         cx: &AsyncAppContext,
     ) -> BoxFuture<'static, Result<futures::stream::BoxStream<'static, Result<String>>>> {
-        let request = request.into_open_ai(self.model.id().into());
+        let events = self.stream_completion_events(request, cx);
+        async move { Ok(text_from_events(events.await?)) }.boxed()
+    }
 
+    fn stream_completion_events(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<LanguageModelCompletionEvent>>>> {
+        let model_id = self.model.id().to_string();
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url, low_speed_timeout)) = cx.read_model(&self.state, |state, cx| {
+        let Ok((
+            http_client,
+            api_key,
+            api_key_pool,
+            api_key_ref,
+            api_url,
+            low_speed_timeout,
+            auth,
+            responses_api,
+            stored_responses,
+            user,
+        )) = cx.read_model(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).openai;
             (
+                http_client_for_provider(PROVIDER_ID, http_client, cx),
                 state.api_key.clone(),
+                state.api_key_pool.clone(),
+                settings.api_key_ref.clone(),
                 settings.api_url.clone(),
                 settings.low_speed_timeout,
+                settings.auth.clone(),
+                settings.responses_api.get(&model_id).cloned(),
+                state.stored_responses.clone(),
+                settings.user.clone(),
             )
-        }) else {
+        })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
+        let cx = cx.clone();
         async move {
-            let api_key = api_key.ok_or_else(|| anyhow!("missing api key"))?;
-            let request = stream_completion(
-                http_client.as_ref(),
-                &api_url,
-                &api_key,
-                request,
-                low_speed_timeout,
-            );
-            let response = request.await?;
-            Ok(open_ai::extract_text_from_events(response).boxed())
+            send_with_api_key(
+                api_key_ref,
+                api_key,
+                api_key_pool,
+                api_error_status,
+                &cx,
+                |api_key| {
+                    let http_client = auth.http_client(http_client.clone(), &api_key);
+                    let api_url = api_url.clone();
+                    let model_id = model_id.clone();
+                    let request = request.clone();
+                    let responses_api = responses_api.clone();
+                    let stored_responses = stored_responses.clone();
+                    let user = user.clone();
+                    async move {
+                        if let Some(responses_api) = responses_api {
+                            return stream_completion_with_responses_api(
+                                http_client.as_ref(),
+                                &api_url,
+                                &api_key,
+                                model_id,
+                                request,
+                                responses_api,
+                                stored_responses,
+                                user,
+                                low_speed_timeout,
+                            )
+                            .await;
+                        }
+
+                        let raw_events = request.raw_events;
+                        let mut request = request.into_open_ai(model_id);
+                        request.user = user;
+                        let response = stream_raw_completion(
+                            http_client.as_ref(),
+                            &api_url,
+                            &api_key,
+                            request,
+                            low_speed_timeout,
+                        )
+                        .await?;
+                        Ok(events_from_chat_completion(response, raw_events))
+                    }
+                },
+            )
+            .await
         }
         .boxed()
     }
@@ -238,6 +357,235 @@ impl LanguageModel for OpenAiLanguageModel {
     }
 }
 
+/// Returns the HTTP status of a failed OpenAI API request.
+fn api_error_status(error: &anyhow::Error) -> Option<u16> {
+    error
+        .downcast_ref::<open_ai::ApiError>()
+        .map(|error| error.status)
+}
+
+/// Streams a completion through the Responses API. When an earlier response
+/// in the conversation is still stored on the server, only the messages that
+/// came after it are sent.
+#[allow(clippy::too_many_arguments)]
+async fn stream_completion_with_responses_api(
+    http_client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    model: String,
+    request: LanguageModelRequest,
+    settings: ResponsesApiSettings,
+    stored_responses: Arc<Mutex<StoredResponses>>,
+    user: Option<String>,
+    low_speed_timeout: Option<Duration>,
+) -> Result<BoxStream<'static, Result<LanguageModelCompletionEvent>>> {
+    // Instructions aren't carried over from previous responses, so system
+    // messages are sent with every request.
+    let mut instructions = Vec::new();
+    let mut messages = Vec::new();
+    for message in request.messages {
+        match message.role {
+            Role::System => instructions.push(message.content),
+            Role::User => messages.push(ResponsesInputMessage {
+                role: open_ai::Role::User,
+                content: message.content,
+            }),
+            Role::Assistant => messages.push(ResponsesInputMessage {
+                role: open_ai::Role::Assistant,
+                content: message.content,
+            }),
+        }
+    }
+
+    let (previous_response_id, new_messages_ix) = stored_responses
+        .lock()
+        .unwrap()
+        .find_previous_response(&messages)
+        .unzip();
+    let input = messages.split_off(new_messages_ix.unwrap_or(0));
+    let response_request = open_ai::ResponsesRequest {
+        model,
+        input: input.clone(),
+        instructions: (!instructions.is_empty()).then(|| instructions.join("\n\n")),
+        previous_response_id,
+        stream: true,
+        store: true,
+        temperature: request.temperature,
+        max_output_tokens: request.max_output_tokens,
+        tools: settings.tools,
+        user,
+    };
+    let events = stream_response(
+        http_client,
+        api_url,
+        api_key,
+        response_request,
+        low_speed_timeout,
+    )
+    .await?;
+
+    messages.extend(input);
+    let mut output = String::new();
+    Ok(events
+        .filter_map(move |event| {
+            let event = match event {
+                Ok(ResponsesStreamEvent::OutputTextDelta { delta }) => {
+                    output.push_str(&delta);
+                    Some(Ok(LanguageModelCompletionEvent::Text(delta)))
+                }
+                Ok(ResponsesStreamEvent::OutputTextAnnotationAdded { annotation }) => {
+                    citation_from_annotation(annotation, &output)
+                        .map(|citation| Ok(LanguageModelCompletionEvent::Citation(citation)))
+                }
+                Ok(ResponsesStreamEvent::Completed { response }) => {
+                    // Remember the response, so that the next turn of the
+                    // conversation can continue from it.
+                    messages.push(ResponsesInputMessage {
+                        role: open_ai::Role::Assistant,
+                        content: std::mem::take(&mut output),
+                    });
+                    stored_responses
+                        .lock()
+                        .unwrap()
+                        .insert(&messages, response.id);
+                    None
+                }
+                Ok(ResponsesStreamEvent::Failed { response }) => Some(Err(anyhow!(
+                    "OpenAI response failed: {}",
+                    response
+                        .error
+                        .map_or_else(|| "unknown error".to_string(), |error| error.message)
+                ))),
+                Ok(ResponsesStreamEvent::Error { message }) => {
+                    Some(Err(anyhow!("OpenAI response failed: {message}")))
+                }
+                Ok(_) => None,
+                Err(error) => Some(Err(error)),
+            };
+            future::ready(event)
+        })
+        .boxed())
+}
+
+/// Converts the events of a chat completion, along with the sources cited by
+/// OpenAI-compatible APIs that report them. With `raw_events`, each event is
+/// also streamed as it was sent, ahead of what it's converted into.
+fn events_from_chat_completion(
+    response: BoxStream<'static, Result<serde_json::Value>>,
+    raw_events: bool,
+) -> BoxStream<'static, Result<LanguageModelCompletionEvent>> {
+    // Every chunk of the response carries the same citations.
+    let mut has_cited = false;
+    response
+        .flat_map(move |event| {
+            let mut events = Vec::new();
+            if raw_events {
+                if let Ok(event) = &event {
+                    events.push(Ok(LanguageModelCompletionEvent::Raw(event.clone())));
+                }
+            }
+            let event =
+                event.and_then(|event| Ok(serde_json::from_value::<ResponseStreamEvent>(event)?));
+            match event {
+                Ok(mut event) => {
+                    if let Some(text) = event.choices.pop().and_then(|choice| choice.delta.content)
+                    {
+                        events.push(Ok(LanguageModelCompletionEvent::Text(text)));
+                    }
+                    if let Some(citations) = event.citations.filter(|_| !has_cited) {
+                        has_cited = true;
+                        events.extend(citations.into_iter().map(|url| {
+                            Ok(LanguageModelCompletionEvent::Citation(Citation {
+                                url: Some(url),
+                                ..Default::default()
+                            }))
+                        }));
+                    }
+                }
+                Err(error) => events.push(Err(error)),
+            }
+            futures::stream::iter(events)
+        })
+        .boxed()
+}
+
+fn citation_from_annotation(annotation: ResponsesAnnotation, output: &str) -> Option<Citation> {
+    match annotation {
+        ResponsesAnnotation::UrlCitation {
+            url,
+            title,
+            start_index,
+            end_index,
+        } => Some(Citation {
+            url: Some(url),
+            title,
+            file_id: None,
+            output_range: Some(
+                char_offset_to_byte_offset(output, start_index)
+                    ..char_offset_to_byte_offset(output, end_index),
+            ),
+        }),
+        ResponsesAnnotation::FileCitation { file_id, filename } => Some(Citation {
+            title: filename,
+            file_id: Some(file_id),
+            ..Default::default()
+        }),
+        ResponsesAnnotation::Other => None,
+    }
+}
+
+/// The responses stored on the server, keyed by a hash of the conversation
+/// up to and including them.
+#[derive(Default)]
+struct StoredResponses {
+    response_ids: HashMap<u64, String>,
+    insertion_order: VecDeque<u64>,
+}
+
+impl StoredResponses {
+    fn insert(&mut self, messages: &[ResponsesInputMessage], response_id: String) {
+        let key = hash_messages(messages);
+        if self.response_ids.insert(key, response_id).is_none() {
+            self.insertion_order.push_back(key);
+        }
+        while self.insertion_order.len() > MAX_STORED_RESPONSES {
+            if let Some(key) = self.insertion_order.pop_front() {
+                self.response_ids.remove(&key);
+            }
+        }
+    }
+
+    /// Returns the latest stored response the conversation continues from,
+    /// along with the index of the first message that came after it.
+    fn find_previous_response(
+        &self,
+        messages: &[ResponsesInputMessage],
+    ) -> Option<(String, usize)> {
+        (1..messages.len()).rev().find_map(|ix| {
+            if messages[ix - 1].role != open_ai::Role::Assistant {
+                return None;
+            }
+            let response_id = self.response_ids.get(&hash_messages(&messages[..ix]))?;
+            Some((response_id.clone(), ix))
+        })
+    }
+}
+
+fn hash_messages(messages: &[ResponsesInputMessage]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for message in messages {
+        let role = match message.role {
+            open_ai::Role::User => "user",
+            open_ai::Role::Assistant => "assistant",
+            open_ai::Role::System => "system",
+            open_ai::Role::Tool => "tool",
+        };
+        role.hash(&mut hasher);
+        message.content.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
 pub fn count_open_ai_tokens(
     request: LanguageModelRequest,
     model: open_ai::Model,
@@ -271,6 +619,8 @@ pub fn count_open_ai_tokens(
 
 struct AuthenticationPrompt {
     api_key: View<Editor>,
+    api_url: View<ApiUrlField>,
+    passphrase: Option<View<Editor>>,
     state: gpui::Model<State>,
 }
 
@@ -285,22 +635,48 @@ impl AuthenticationPrompt {
                 );
                 editor
             }),
+            api_url: cx.new_view(|cx| {
+                ApiUrlField::new(
+                    LockableSetting::OpenAiApiUrl,
+                    |settings| &settings.openai.api_url,
+                    |content, api_url| {
+                        content.openai.get_or_insert_with(Default::default).api_url = Some(api_url)
+                    },
+                    cx,
+                )
+            }),
+            passphrase: ApiKeySync::needs_passphrase(cx).then(|| {
+                cx.new_view(|cx| {
+                    let mut editor = Editor::single_line(cx);
+                    editor.set_placeholder_text("Passphrase for synced API keys", cx);
+                    editor.set_masked(true, cx);
+                    editor
+                })
+            }),
             state,
         }
     }
 
     fn save_api_key(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
         let api_key = self.api_key.read(cx).text(cx);
-        if api_key.is_empty() {
+        let passphrase = self
+            .passphrase
+            .as_ref()
+            .map(|passphrase| passphrase.read(cx).text(cx))
+            .filter(|passphrase| !passphrase.is_empty());
+        if api_key.is_empty() && passphrase.is_none() {
             return;
         }
 
-        let settings = &AllLanguageModelSettings::get_global(cx).openai;
-        let write_credentials =
-            cx.write_credentials(&settings.api_url, "Bearer", api_key.as_bytes());
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .openai
+            .api_url
+            .clone();
+        let save_api_key =
+            api_key_sync::save_api_key(PROVIDER_ID, api_url, api_key, passphrase, cx);
         let state = self.state.clone();
         cx.spawn(|_, mut cx| async move {
-            write_credentials.await?;
+            let api_key = save_api_key.await?;
             state.update(&mut cx, |this, cx| {
                 this.api_key = Some(api_key);
                 cx.notify();
@@ -309,7 +685,7 @@ impl AuthenticationPrompt {
         .detach_and_log_err(cx);
     }
 
-    fn render_api_key_editor(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+    fn render_editor(&self, editor: &View<Editor>, cx: &mut ViewContext<Self>) -> impl IntoElement {
         let settings = ThemeSettings::get_global(cx);
         let text_style = TextStyle {
             color: cx.theme().colors().text,
@@ -326,7 +702,7 @@ impl AuthenticationPrompt {
             white_space: WhiteSpace::Normal,
         };
         EditorElement::new(
-            &self.api_key,
+            editor,
             EditorStyle {
                 background: cx.theme().colors().editor_background,
                 local_player: cx.theme().players().local(),
@@ -363,8 +739,28 @@ impl Render for AuthenticationPrompt {
                     .py_1()
                     .bg(cx.theme().colors().editor_background)
                     .rounded_md()
-                    .child(self.render_api_key_editor(cx)),
+                    .child(self.render_editor(&self.api_key, cx)),
             )
+            .when_some(self.passphrase.clone(), |this, passphrase| {
+                this.child(
+                    Label::new(
+                        "Enter the passphrase you use to sync API keys between your devices. \
+                         If you've already synced a key, you can leave the API key empty.",
+                    )
+                    .size(LabelSize::Small),
+                )
+                .child(
+                    h_flex()
+                        .w_full()
+                        .my_2()
+                        .px_2()
+                        .py_1()
+                        .bg(cx.theme().colors().editor_background)
+                        .rounded_md()
+                        .child(self.render_editor(&passphrase, cx)),
+                )
+            })
+            .child(self.api_url.clone())
             .child(
                 Label::new(
                     "You can also assign the OPENAI_API_KEY environment variable and restart Zed.",
@@ -383,3 +779,125 @@ impl Render for AuthenticationPrompt {
             .into_any()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: open_ai::Role, content: &str) -> ResponsesInputMessage {
+        ResponsesInputMessage {
+            role,
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn test_find_previous_response() {
+        let mut stored_responses = StoredResponses::default();
+        let mut messages = vec![
+            message(open_ai::Role::User, "Hi"),
+            message(open_ai::Role::Assistant, "Hello!"),
+        ];
+        stored_responses.insert(&messages, "resp_1".into());
+
+        messages.push(message(open_ai::Role::User, "How are you?"));
+        assert_eq!(
+            stored_responses.find_previous_response(&messages),
+            Some(("resp_1".into(), 2))
+        );
+
+        messages.push(message(open_ai::Role::Assistant, "Good."));
+        stored_responses.insert(&messages, "resp_2".into());
+        messages.push(message(open_ai::Role::User, "Great"));
+        assert_eq!(
+            stored_responses.find_previous_response(&messages),
+            Some(("resp_2".into(), 4))
+        );
+
+        // Editing an earlier message starts over.
+        messages[0].content = "Hey".into();
+        assert_eq!(stored_responses.find_previous_response(&messages), None);
+    }
+
+    #[test]
+    fn test_chat_completion_citations() {
+        let chunk = |content: &str| -> Result<serde_json::Value> {
+            Ok(serde_json::json!({
+                "created": 0,
+                "model": "sonar",
+                "choices": [{ "index": 0, "delta": { "content": content } }],
+                "citations": ["https://a.example.com", "https://b.example.com"]
+            }))
+        };
+        let events = futures::stream::iter([chunk("Rust "), chunk("is fast.")]).boxed();
+        let events = futures::executor::block_on(
+            events_from_chat_completion(events, false).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        let citation = |url: &str| {
+            LanguageModelCompletionEvent::Citation(Citation {
+                url: Some(url.into()),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            events,
+            [
+                LanguageModelCompletionEvent::Text("Rust ".into()),
+                citation("https://a.example.com"),
+                citation("https://b.example.com"),
+                LanguageModelCompletionEvent::Text("is fast.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chat_completion_raw_events() {
+        let chunk = serde_json::json!({
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "delta": { "content": "Hi" },
+                "logprobs": { "content": [{ "token": "Hi", "logprob": -0.01 }] }
+            }]
+        });
+        let events = futures::stream::iter([Ok(chunk.clone())]).boxed();
+        let events = futures::executor::block_on(
+            events_from_chat_completion(events, true).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(
+            events,
+            [
+                LanguageModelCompletionEvent::Raw(chunk),
+                LanguageModelCompletionEvent::Text("Hi".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_citation_from_annotation() {
+        let annotation = serde_json::from_value::<ResponsesAnnotation>(serde_json::json!({
+            "type": "url_citation",
+            "url": "https://example.com",
+            "title": "Example",
+            "start_index": 6,
+            "end_index": 11
+        }))
+        .unwrap();
+        assert_eq!(
+            citation_from_annotation(annotation, "Über: cited"),
+            Some(Citation {
+                url: Some("https://example.com".into()),
+                title: Some("Example".into()),
+                file_id: None,
+                output_range: Some(7..12),
+            })
+        );
+    }
+}
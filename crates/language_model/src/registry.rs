@@ -1,20 +1,36 @@
 use crate::{
+    circuit_breaker::{CircuitBreakers, CircuitStateChanged},
     provider::{
         anthropic::AnthropicLanguageModelProvider, cloud::CloudLanguageModelProvider,
         copilot_chat::CopilotChatLanguageModelProvider, google::GoogleLanguageModelProvider,
         ollama::OllamaLanguageModelProvider, open_ai::OpenAiLanguageModelProvider,
     },
-    LanguageModel, LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderState,
+    routing::{ModelRouter, RoutingCandidate},
+    settings::AllLanguageModelSettings,
+    stream_metrics::StreamMetrics,
+    LanguageModel, LanguageModelId, LanguageModelProvider, LanguageModelProviderId,
+    LanguageModelProviderState, LanguageModelRequestFeature,
 };
 use client::Client;
-use collections::BTreeMap;
-use gpui::{AppContext, Global, Model, ModelContext};
-use std::sync::Arc;
+use collections::{BTreeMap, HashMap};
+use futures::{channel::mpsc, StreamExt};
+use gpui::{AppContext, EventEmitter, Global, Model, ModelContext};
+use settings::Settings;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use ui::Context;
+use util::ResultExt;
+
+/// How often the stream metrics are saved, if they changed.
+const SAVE_STREAM_METRICS_INTERVAL: Duration = Duration::from_secs(60);
 
 pub fn init(client: Arc<Client>, cx: &mut AppContext) {
     let registry = cx.new_model(|cx| {
         let mut registry = LanguageModelRegistry::default();
+        registry.forward_circuit_state_changes(cx);
+        registry.persist_stream_metrics(cx);
         register_language_model_providers(&mut registry, client, cx);
         registry
     });
@@ -71,6 +87,24 @@ impl Global for GlobalLanguageModelRegistry {}
 #[derive(Default)]
 pub struct LanguageModelRegistry {
     providers: BTreeMap<LanguageModelProviderId, Arc<dyn LanguageModelProvider>>,
+    recommended_default_model: Option<RecommendedLanguageModel>,
+    router: Arc<Mutex<ModelRouter>>,
+    stream_metrics: Arc<Mutex<StreamMetrics>>,
+    circuit_breakers: Arc<Mutex<CircuitBreakers>>,
+}
+
+impl EventEmitter<CircuitStateChanged> for LanguageModelRegistry {}
+
+/// A default model recommended by the server, to be used when the user hasn't
+/// chosen one themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecommendedLanguageModel {
+    pub provider: LanguageModelProviderId,
+    pub model: LanguageModelId,
+    pub temperature: Option<f32>,
+    /// The experiment and variant the recommendation comes from.
+    pub experiment: String,
+    pub variant: String,
 }
 
 impl LanguageModelRegistry {
@@ -84,9 +118,16 @@ impl LanguageModelRegistry {
 
     #[cfg(any(test, feature = "test-support"))]
     pub fn test(cx: &mut AppContext) -> crate::provider::fake::FakeLanguageModelProvider {
+        // Completions read the language model settings (e.g. for moderation),
+        // so they need to be registered.
+        if cx.has_global::<settings::SettingsStore>() {
+            crate::settings::init(cx);
+        }
+
         let fake_provider = crate::provider::fake::FakeLanguageModelProvider::default();
         let registry = cx.new_model(|cx| {
             let mut registry = Self::default();
+            registry.forward_circuit_state_changes(cx);
             registry.register_provider(fake_provider.clone(), cx);
             registry
         });
@@ -124,8 +165,10 @@ impl LanguageModelRegistry {
     }
 
     pub fn available_models(&self, cx: &AppContext) -> Vec<Arc<dyn LanguageModel>> {
+        let settings = AllLanguageModelSettings::get_global(cx);
         self.providers
             .values()
+            .filter(|provider| settings.is_provider_allowed(&provider.id()))
             .flat_map(|provider| provider.provided_models(cx))
             .collect()
     }
@@ -136,12 +179,202 @@ impl LanguageModelRegistry {
     ) -> Option<Arc<dyn LanguageModelProvider>> {
         self.providers.get(name).cloned()
     }
+
+    pub fn recommended_default_model(&self) -> Option<&RecommendedLanguageModel> {
+        self.recommended_default_model.as_ref()
+    }
+
+    pub fn set_recommended_default_model(
+        &mut self,
+        recommended_model: Option<RecommendedLanguageModel>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if self.recommended_default_model != recommended_model {
+            self.recommended_default_model = recommended_model;
+            cx.notify();
+        }
+    }
+
+    /// Returns the time-to-first-token, throughput and error-rate statistics
+    /// of recent requests, which are used to route requests.
+    ///
+    /// These are shared with in-flight requests, which record their outcome
+    /// as they stream.
+    pub fn stream_metrics(&self) -> Arc<Mutex<StreamMetrics>> {
+        self.stream_metrics.clone()
+    }
+
+    /// Loads the stream metrics saved by a previous session, and saves them
+    /// periodically and when the app quits.
+    fn persist_stream_metrics(&mut self, cx: &mut ModelContext<Self>) {
+        let path = paths::language_model_stream_metrics_file();
+        let stream_metrics = self.stream_metrics.clone();
+        cx.spawn(|_, cx| async move {
+            let saved = cx
+                .background_executor()
+                .spawn(async move { StreamMetrics::load(path) })
+                .await;
+            if let Some(saved) = saved.log_err() {
+                stream_metrics.lock().unwrap().merge(saved);
+            }
+            loop {
+                cx.background_executor()
+                    .timer(SAVE_STREAM_METRICS_INTERVAL)
+                    .await;
+                let unsaved = stream_metrics.lock().unwrap().take_unsaved();
+                if let Some(unsaved) = unsaved {
+                    cx.background_executor()
+                        .spawn(async move { save_stream_metrics(unsaved) })
+                        .await
+                        .log_err();
+                }
+            }
+        })
+        .detach();
+
+        cx.on_app_quit(|this, _| {
+            if let Some(unsaved) = this.stream_metrics.lock().unwrap().take_unsaved() {
+                save_stream_metrics(unsaved).log_err();
+            }
+            futures::future::ready(())
+        })
+        .detach();
+    }
+
+    /// Returns the circuit breakers that disable providers after repeated
+    /// failures.
+    ///
+    /// Like the stream metrics, these are shared with in-flight requests.
+    pub fn circuit_breakers(&self) -> Arc<Mutex<CircuitBreakers>> {
+        self.circuit_breakers.clone()
+    }
+
+    /// Re-emits the circuit breakers' state changes as events of the registry,
+    /// so that the UI can tell users when a provider is disabled.
+    fn forward_circuit_state_changes(&mut self, cx: &mut ModelContext<Self>) {
+        let (state_changes_tx, mut state_changes_rx) = mpsc::unbounded();
+        self.circuit_breakers = Arc::new(Mutex::new(CircuitBreakers::new(state_changes_tx)));
+        cx.spawn(|this, mut cx| async move {
+            while let Some(state_change) = state_changes_rx.next().await {
+                let emitted = this.update(&mut cx, |_, cx| {
+                    cx.emit(state_change);
+                    cx.notify();
+                });
+                if emitted.is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Returns the model that requests from the given feature should be sent
+    /// to, if the user pinned one for it or configured a routing policy for
+    /// it. Otherwise, requests go to the active model.
+    pub fn model_for_feature(
+        &self,
+        feature: LanguageModelRequestFeature,
+        cx: &AppContext,
+    ) -> Option<Arc<dyn LanguageModel>> {
+        self.feature_model(feature, cx)
+            .or_else(|| self.route_model(feature, cx))
+    }
+
+    /// Returns the model the user pinned for requests from the given feature,
+    /// if it's available.
+    pub fn feature_model(
+        &self,
+        feature: LanguageModelRequestFeature,
+        cx: &AppContext,
+    ) -> Option<Arc<dyn LanguageModel>> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        let (provider_id, model_id) = settings.feature_models.get(&feature)?.key();
+        let provider = self.provider(&provider_id).filter(|provider| {
+            settings.is_provider_allowed(&provider_id) && provider.is_authenticated(cx)
+        })?;
+        provider
+            .provided_models(cx)
+            .into_iter()
+            .find(|model| model.id() == model_id)
+    }
+
+    /// Returns the model that requests from the given feature should be routed
+    /// to, if a routing policy is configured for it.
+    pub fn route_model(
+        &self,
+        feature: LanguageModelRequestFeature,
+        cx: &AppContext,
+    ) -> Option<Arc<dyn LanguageModel>> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        let policy = settings.routing.get(feature.as_str())?;
+
+        let mut models = HashMap::default();
+        for candidate in &policy.candidates {
+            let (provider_id, model_id) = candidate.key();
+            let Some(provider) = self.provider(&provider_id).filter(|provider| {
+                settings.is_provider_allowed(&provider_id) && provider.is_authenticated(cx)
+            }) else {
+                continue;
+            };
+            if let Some(model) = provider
+                .provided_models(cx)
+                .into_iter()
+                .find(|model| model.id() == model_id)
+            {
+                models.insert((provider_id, model_id), model);
+            }
+        }
+
+        let circuit_breakers = self.circuit_breakers.lock().unwrap();
+        let now = Instant::now();
+        let candidates = policy
+            .candidates
+            .iter()
+            .map(RoutingCandidate::key)
+            .filter(|key| {
+                models.contains_key(key)
+                    && circuit_breakers.is_available(&key.0, &settings.circuit_breaker, now)
+            })
+            .collect::<Vec<_>>();
+        drop(circuit_breakers);
+        let route = self.router.lock().unwrap().route(
+            feature.as_str(),
+            &candidates,
+            policy,
+            &self.stream_metrics.lock().unwrap(),
+        )?;
+        models.remove(&route)
+    }
+
+    /// Returns the temperature recommended for the given model, if it is the
+    /// recommended default model.
+    pub fn recommended_temperature(&self, model: &dyn LanguageModel) -> Option<f32> {
+        let recommended_model = self.recommended_default_model.as_ref()?;
+        if recommended_model.provider == model.provider_id()
+            && recommended_model.model == model.id()
+        {
+            recommended_model.temperature
+        } else {
+            None
+        }
+    }
+}
+
+fn save_stream_metrics(contents: Vec<u8>) -> anyhow::Result<()> {
+    let path = paths::language_model_stream_metrics_file();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::provider::fake::FakeLanguageModelProvider;
+    use gpui::UpdateGlobal;
+    use settings::SettingsStore;
 
     #[gpui::test]
     fn test_register_providers(cx: &mut AppContext) {
@@ -162,4 +395,47 @@ mod tests {
         let providers = registry.read(cx).providers().collect::<Vec<_>>();
         assert!(providers.is_empty());
     }
+
+    #[gpui::test]
+    fn test_feature_models(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        LanguageModelRegistry::test(cx);
+
+        let set_feature_models = |feature_models: &str, cx: &mut AppContext| {
+            SettingsStore::update_global(cx, |store, cx| {
+                store
+                    .set_user_settings(
+                        &format!(
+                            r#"{{ "language_models": {{ "feature_models": {feature_models} }} }}"#
+                        ),
+                        cx,
+                    )
+                    .unwrap();
+            });
+        };
+
+        set_feature_models(
+            r#"{ "inline_assist": { "provider": "fake", "model": "fake" } }"#,
+            cx,
+        );
+        let registry = LanguageModelRegistry::read_global(cx);
+        let model = registry
+            .model_for_feature(LanguageModelRequestFeature::InlineAssist, cx)
+            .unwrap();
+        assert_eq!(model.provider_id(), crate::provider::fake::provider_id());
+        assert_eq!(model.id(), crate::provider::fake::language_model_id());
+        assert!(registry
+            .model_for_feature(LanguageModelRequestFeature::AssistantPanel, cx)
+            .is_none());
+
+        // Models that aren't available are ignored.
+        set_feature_models(
+            r#"{ "inline_assist": { "provider": "ollama", "model": "qwen2.5-coder:1.5b" } }"#,
+            cx,
+        );
+        assert!(LanguageModelRegistry::read_global(cx)
+            .feature_model(LanguageModelRequestFeature::InlineAssist, cx)
+            .is_none());
+    }
 }
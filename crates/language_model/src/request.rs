@@ -1,17 +1,59 @@
 use crate::role::Role;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct LanguageModelRequestMessage {
     pub role: Role,
     pub content: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// The product feature a language model request originates from.
+///
+/// This is used to attribute usage and cost to individual features.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageModelRequestFeature {
+    AssistantPanel,
+    InlineAssist,
+    TerminalInlineAssist,
+    Summarization,
+}
+
+impl LanguageModelRequestFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AssistantPanel => "assistant_panel",
+            Self::InlineAssist => "inline_assist",
+            Self::TerminalInlineAssist => "terminal_inline_assist",
+            Self::Summarization => "summarization",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct LanguageModelRequest {
     pub messages: Vec<LanguageModelRequestMessage>,
     pub stop: Vec<String>,
     pub temperature: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature: Option<LanguageModelRequestFeature>,
+    /// The name of the parameter preset to apply to the request, once the
+    /// model it is sent to is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// The most tokens the completion may generate. Clamped to the ceiling
+    /// configured for the model the request is sent to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Whether to stream the provider's untranslated events, as
+    /// [`LanguageModelCompletionEvent::Raw`](crate::LanguageModelCompletionEvent::Raw),
+    /// alongside the events they're translated into. Providers that don't
+    /// support raw events ignore this.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_events: bool,
 }
 
 impl LanguageModelRequest {
@@ -37,8 +79,11 @@ impl LanguageModelRequest {
             stream: true,
             stop: self.stop,
             temperature: self.temperature,
+            max_tokens: self.max_output_tokens,
             tools: Vec::new(),
             tool_choice: None,
+            user: None,
+            stream_options: None,
         }
     }
 
@@ -62,7 +107,7 @@ impl LanguageModelRequest {
             generation_config: Some(google_ai::GenerationConfig {
                 candidate_count: Some(1),
                 stop_sequences: Some(self.stop),
-                max_output_tokens: None,
+                max_output_tokens: self.max_output_tokens.map(|tokens| tokens as usize),
                 temperature: Some(self.temperature as f64),
                 top_p: None,
                 top_k: None,
@@ -118,7 +163,7 @@ impl LanguageModelRequest {
                     })
                 })
                 .collect(),
-            max_tokens: 4092,
+            max_tokens: self.max_output_tokens.unwrap_or(4092),
             system: Some(system_message),
             tools: Vec::new(),
             tool_choice: None,
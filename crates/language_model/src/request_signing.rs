@@ -0,0 +1,193 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::{future::BoxFuture, AsyncReadExt, FutureExt};
+use http_client::{AsyncBody, Error, HttpClient, Request, Response, Uri};
+use ring::hmac;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The header carrying the request's signature.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+/// The header carrying the Unix time, in seconds, the request was signed at.
+pub const SIGNATURE_TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+/// The header carrying the ID of the key the request was signed with.
+pub const SIGNATURE_KEY_ID_HEADER: &str = "X-Signature-Key-Id";
+
+/// The headers providers send API keys in, which are left out of signed
+/// requests.
+const API_KEY_HEADERS: &[&str] = &["Authorization", "X-Api-Key"];
+
+/// How requests to a provider are authenticated.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RequestAuth {
+    /// Send the API key with each request, as the provider expects.
+    #[default]
+    ApiKey,
+    /// Sign each request with an HMAC-SHA256 over its timestamp and body,
+    /// using the API key as the shared secret. The key itself is never sent.
+    /// This is what some self-hosted gateways require.
+    Signed {
+        /// The ID of the shared secret, for gateways that issue several.
+        key_id: Option<String>,
+    },
+}
+
+impl RequestAuth {
+    /// Returns an HTTP client that authenticates requests made with the given
+    /// API key as configured.
+    pub fn http_client(
+        &self,
+        http_client: Arc<dyn HttpClient>,
+        api_key: &str,
+    ) -> Arc<dyn HttpClient> {
+        match self {
+            RequestAuth::ApiKey => http_client,
+            RequestAuth::Signed { key_id } => Arc::new(SigningHttpClient {
+                inner: http_client,
+                key: hmac::Key::new(hmac::HMAC_SHA256, api_key.as_bytes()),
+                key_id: key_id.clone(),
+            }),
+        }
+    }
+}
+
+/// An HTTP client that signs requests instead of sending the API key with
+/// them.
+struct SigningHttpClient {
+    inner: Arc<dyn HttpClient>,
+    key: hmac::Key,
+    key_id: Option<String>,
+}
+
+impl HttpClient for SigningHttpClient {
+    fn send(
+        &self,
+        request: Request<AsyncBody>,
+    ) -> BoxFuture<'static, Result<Response<AsyncBody>, Error>> {
+        let inner = self.inner.clone();
+        let key = self.key.clone();
+        let key_id = self.key_id.clone();
+        async move {
+            let (mut parts, mut body) = request.into_parts();
+            let mut body_bytes = Vec::new();
+            body.read_to_end(&mut body_bytes).await?;
+
+            for header in API_KEY_HEADERS {
+                parts.headers.remove(*header);
+            }
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let signature = sign(&key, timestamp, &body_bytes);
+            parts
+                .headers
+                .insert(SIGNATURE_TIMESTAMP_HEADER, timestamp.into());
+            parts.headers.insert(
+                SIGNATURE_HEADER,
+                signature
+                    .try_into()
+                    .map_err(|_| invalid_header("signature"))?,
+            );
+            if let Some(key_id) = key_id {
+                parts.headers.insert(
+                    SIGNATURE_KEY_ID_HEADER,
+                    key_id.try_into().map_err(|_| invalid_header("key ID"))?,
+                );
+            }
+
+            inner
+                .send(Request::from_parts(parts, AsyncBody::from(body_bytes)))
+                .await
+        }
+        .boxed()
+    }
+
+    fn proxy(&self) -> Option<&Uri> {
+        self.inner.proxy()
+    }
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of `"{timestamp}.{body}"`.
+fn sign(key: &hmac::Key, timestamp: u64, body: &[u8]) -> String {
+    let mut context = hmac::Context::with_key(key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body);
+    context
+        .sign()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn invalid_header(name: &str) -> Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid request {name} header"),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use http_client::FakeHttpClient;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_signed_requests() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let inner = FakeHttpClient::create({
+            let received = received.clone();
+            move |request| {
+                let received = received.clone();
+                async move {
+                    received.lock().unwrap().push(request.headers().clone());
+                    Ok(Response::builder().status(200).body("".into()).unwrap())
+                }
+            }
+        });
+        let auth = RequestAuth::Signed {
+            key_id: Some("gateway-1".into()),
+        };
+        let client = auth.http_client(inner, "secret");
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("https://gateway.example.com/v1/chat/completions")
+            .header("Authorization", "Bearer secret")
+            .body(AsyncBody::from("{\"model\":\"gpt-4o\"}"))
+            .unwrap();
+        block_on(client.send(request)).unwrap();
+
+        let headers = received.lock().unwrap().pop().unwrap();
+        assert!(headers.get("Authorization").is_none());
+        assert_eq!(headers[SIGNATURE_KEY_ID_HEADER], "gateway-1");
+        let timestamp = headers[SIGNATURE_TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        assert!(timestamp > 0);
+
+        // The gateway can verify the signature with the shared secret.
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+        let mut message = format!("{timestamp}.").into_bytes();
+        message.extend_from_slice(b"{\"model\":\"gpt-4o\"}");
+        let signature = (0..signature.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        hmac::verify(&key, &message, &signature).unwrap();
+        assert!(hmac::verify(&key, b"tampered", &signature).is_err());
+    }
+}
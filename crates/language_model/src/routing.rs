@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use collections::HashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{stream_metrics::StreamMetrics, LanguageModelId, LanguageModelProviderId};
+
+/// The number of requests needed before a model's statistics are trusted.
+const MIN_SAMPLES: usize = 5;
+
+/// A policy that routes the requests of a profile to the fastest healthy model
+/// out of a set of candidates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RoutingPolicy {
+    /// The models requests may be routed to, in order of preference.
+    pub candidates: Vec<RoutingCandidate>,
+    /// The error rate above which a model is considered unhealthy.
+    #[serde(default = "default_max_error_rate")]
+    pub max_error_rate: f32,
+    /// How much faster, as a fraction of its latency, another model must be
+    /// before requests are moved to it. This keeps requests from flapping
+    /// between models with similar latencies.
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f32,
+}
+
+fn default_max_error_rate() -> f32 {
+    0.25
+}
+
+fn default_hysteresis() -> f32 {
+    0.2
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RoutingCandidate {
+    pub provider: String,
+    pub model: String,
+}
+
+impl RoutingCandidate {
+    pub fn key(&self) -> ModelKey {
+        (
+            LanguageModelProviderId::from(self.provider.clone()),
+            LanguageModelId::from(self.model.clone()),
+        )
+    }
+}
+
+pub type ModelKey = (LanguageModelProviderId, LanguageModelId);
+
+/// The model that requests from a feature are always sent to, regardless of
+/// the model selected in the assistant panel.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FeatureModel {
+    /// The ID of the model's provider, e.g. "ollama".
+    pub provider: String,
+    /// The ID of the model, e.g. "qwen2.5-coder:1.5b".
+    pub model: String,
+}
+
+impl FeatureModel {
+    pub fn key(&self) -> ModelKey {
+        (
+            LanguageModelProviderId::from(self.provider.clone()),
+            LanguageModelId::from(self.model.clone()),
+        )
+    }
+}
+
+/// Tracks which model each profile is currently routed to.
+#[derive(Default)]
+pub struct ModelRouter {
+    routes: HashMap<String, ModelKey>,
+}
+
+fn is_healthy(metrics: &StreamMetrics, model: &ModelKey, policy: &RoutingPolicy) -> bool {
+    metrics.stats(model).map_or(true, |stats| {
+        stats.sample_count() < MIN_SAMPLES || stats.error_rate() <= policy.max_error_rate
+    })
+}
+
+fn latency(metrics: &StreamMetrics, model: &ModelKey) -> Option<Duration> {
+    metrics
+        .stats(model)
+        .filter(|stats| stats.sample_count() >= MIN_SAMPLES)
+        .and_then(|stats| stats.mean_time_to_first_token())
+}
+
+impl ModelRouter {
+    /// Returns the model the profile's next request should be routed to, out
+    /// of the given candidates, based on the time to first token and error
+    /// rate of their recent requests.
+    ///
+    /// Requests stay with the current model until it becomes unhealthy or
+    /// another healthy model is faster by more than the policy's hysteresis.
+    /// Models we don't have enough statistics for are healthy, but are never
+    /// considered faster than the current model.
+    pub fn route(
+        &mut self,
+        profile: &str,
+        candidates: &[ModelKey],
+        policy: &RoutingPolicy,
+        metrics: &StreamMetrics,
+    ) -> Option<ModelKey> {
+        let current = self
+            .routes
+            .get(profile)
+            .filter(|current| candidates.contains(current))
+            .cloned();
+
+        let healthy = candidates
+            .iter()
+            .filter(|candidate| is_healthy(metrics, candidate, policy))
+            .collect::<Vec<_>>();
+        let fastest = healthy
+            .iter()
+            .filter_map(|candidate| Some((*candidate, latency(metrics, candidate)?)))
+            .min_by_key(|(_, latency)| *latency);
+
+        let next = match current {
+            Some(current) if healthy.contains(&&current) => {
+                let is_fastest_clearly_faster = fastest.zip(latency(metrics, &current)).map_or(
+                    false,
+                    |((_, fastest_latency), current_latency)| {
+                        fastest_latency.mul_f32(1. + policy.hysteresis) < current_latency
+                    },
+                );
+                match fastest {
+                    Some((fastest, _)) if is_fastest_clearly_faster => fastest.clone(),
+                    _ => current,
+                }
+            }
+            // When every candidate is unhealthy, there's nothing to gain from
+            // moving requests around.
+            Some(current) if healthy.is_empty() => current,
+            _ => fastest
+                .map(|(fastest, _)| fastest)
+                .or_else(|| healthy.first().copied())
+                .or_else(|| candidates.first())?
+                .clone(),
+        };
+
+        self.routes.insert(profile.to_string(), next.clone());
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_metrics::STATS_WINDOW;
+
+    fn key(model: &str) -> ModelKey {
+        (
+            LanguageModelProviderId::from("provider".to_string()),
+            LanguageModelId::from(model.to_string()),
+        )
+    }
+
+    fn record(metrics: &mut StreamMetrics, model: &str, latency_ms: u64, count: usize) {
+        for _ in 0..count {
+            metrics.record_first_token(key(model), Duration::from_millis(latency_ms));
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let policy = RoutingPolicy {
+            candidates: Vec::new(),
+            max_error_rate: 0.25,
+            hysteresis: 0.2,
+        };
+        let candidates = [key("a"), key("b")];
+        let mut router = ModelRouter::default();
+        let mut metrics = StreamMetrics::default();
+
+        // Without statistics, requests go to the first candidate.
+        assert_eq!(
+            router.route("fast", &candidates, &policy, &metrics),
+            Some(key("a"))
+        );
+
+        // Requests stay put while the other candidate is only slightly faster.
+        record(&mut metrics, "a", 110, MIN_SAMPLES);
+        record(&mut metrics, "b", 100, MIN_SAMPLES);
+        assert_eq!(
+            router.route("fast", &candidates, &policy, &metrics),
+            Some(key("a"))
+        );
+
+        // Requests move once the other candidate is clearly faster.
+        record(&mut metrics, "a", 200, STATS_WINDOW);
+        assert_eq!(
+            router.route("fast", &candidates, &policy, &metrics),
+            Some(key("b"))
+        );
+
+        // Requests move away from a model that starts failing, even if it is faster.
+        for _ in 0..STATS_WINDOW / 2 {
+            metrics.record_failure(key("b"));
+        }
+        assert_eq!(
+            router.route("fast", &candidates, &policy, &metrics),
+            Some(key("a"))
+        );
+
+        // Profiles are routed independently.
+        assert_eq!(
+            router.route("other", &[key("b")], &policy, &metrics),
+            Some(key("b"))
+        );
+    }
+}
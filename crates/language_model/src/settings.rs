@@ -1,18 +1,40 @@
 use std::time::Duration;
 
 use anyhow::Result;
+use collections::{BTreeMap, BTreeSet};
 use gpui::AppContext;
-use schemars::JsonSchema;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsSources};
 
+use crate::managed_settings::{apply_locked_settings, LockableSetting};
+use crate::moderation::{ModerationClassifier, ModerationSettings, ProviderModerationSettings};
 use crate::provider::{
     anthropic::AnthropicSettings,
     cloud::{self, ZedDotDevSettings},
     copilot_chat::CopilotChatSettings,
     google::GoogleSettings,
     ollama::OllamaSettings,
-    open_ai::OpenAiSettings,
+    open_ai::{OpenAiSettings, ResponsesApiSettings},
+};
+use crate::{
+    api_key_pool::ApiKeyEnv,
+    circuit_breaker::CircuitBreakerSettings,
+    connection_settings::ConnectionSettings,
+    image_generation::{
+        ImageGenerationSettings, OpenAiImageGenerationSettings, StabilityImageGenerationSettings,
+    },
+    output_limits::MaxOutputTokens,
+    presets::ParameterPreset,
+    request_signing::RequestAuth,
+    routing::{FeatureModel, RoutingPolicy},
+    shared_rate_limit::RequestRateLimit,
+    transcription::{LocalWhisperSettings, OpenAiTranscriptionSettings, TranscriptionSettings},
+    LanguageModelProviderId, LanguageModelRequestFeature,
 };
 
 /// Initializes the language model settings.
@@ -20,8 +42,95 @@ pub fn init(cx: &mut AppContext) {
     AllLanguageModelSettings::register(cx);
 }
 
+/// Returns the JSON schema of a settings file's language model settings,
+/// including the shapes of each provider's models, so that settings files
+/// can be validated outside of Zed.
+///
+/// Settings other than those under "language_models" are allowed, but not
+/// validated.
+pub fn settings_file_json_schema() -> serde_json::Value {
+    // Subschemas are inlined, as types of the same name, such as each
+    // provider's `Model`, would otherwise share a single definition.
+    let mut root_schema = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.option_add_null_type = false;
+            settings.inline_subschemas = true;
+        })
+        .into_generator()
+        .into_root_schema_for::<AllLanguageModelSettingsContent>();
+
+    let content_schema = std::mem::replace(
+        &mut root_schema.schema,
+        SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        },
+    );
+    root_schema.schema.object().properties.insert(
+        AllLanguageModelSettings::KEY.unwrap().to_string(),
+        Schema::Object(content_schema),
+    );
+    root_schema.schema.metadata().title = Some("Zed language model settings".into());
+
+    serde_json::to_value(root_schema).unwrap()
+}
+
+/// Deserializes a setting that can be explicitly set to `null`, to tell it
+/// apart from one that's absent: `null` becomes `Some(None)`, so that it
+/// clears a value set by an earlier settings file when merged.
+fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Restricts the keys of "feature_models" to known features, so that
+/// misspelled ones are flagged rather than silently ignored.
+fn feature_models_schema(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    let object = schema.object();
+    object.property_names = Some(Box::new(gen.subschema_for::<LanguageModelRequestFeature>()));
+    object.additional_properties = Some(Box::new(gen.subschema_for::<FeatureModel>()));
+    Schema::Object(schema)
+}
+
+impl AllLanguageModelSettings {
+    /// Returns whether the provider may be used.
+    pub fn is_provider_allowed(&self, provider_id: &LanguageModelProviderId) -> bool {
+        self.allowed_providers.as_ref().map_or(true, |allowed| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.as_str() == provider_id.0.as_ref())
+        })
+    }
+
+    /// Returns whether the setting is locked by the managed settings.
+    pub fn is_locked(&self, setting: LockableSetting) -> bool {
+        self.locked.contains(&setting)
+    }
+}
+
 #[derive(Default)]
 pub struct AllLanguageModelSettings {
+    pub sync_api_keys: bool,
+    pub allowed_providers: Option<Vec<String>>,
+    pub locked: BTreeSet<LockableSetting>,
+    pub moderation: ModerationSettings,
+    pub routing: BTreeMap<String, RoutingPolicy>,
+    pub feature_models: BTreeMap<LanguageModelRequestFeature, FeatureModel>,
+    pub presets: BTreeMap<String, ParameterPreset>,
+    pub rate_limits: BTreeMap<String, RequestRateLimit>,
+    pub connections: BTreeMap<String, ConnectionSettings>,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub max_output_tokens: MaxOutputTokens,
+    pub journal: bool,
+    pub image_generation: ImageGenerationSettings,
+    pub transcription: TranscriptionSettings,
     pub anthropic: AnthropicSettings,
     pub ollama: OllamaSettings,
     pub openai: OpenAiSettings,
@@ -32,6 +141,73 @@ pub struct AllLanguageModelSettings {
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct AllLanguageModelSettingsContent {
+    /// Whether to sync provider API keys between your devices via zed.dev.
+    ///
+    /// Keys are encrypted with a passphrase that never leaves your devices.
+    ///
+    /// Default: false
+    pub sync_api_keys: Option<bool>,
+    /// The IDs of the providers that may be used (e.g. "anthropic"), or null
+    /// to allow every provider.
+    ///
+    /// Default: null
+    pub allowed_providers: Option<Vec<String>>,
+    /// Settings that users can't override, such as "openai.api_url" or
+    /// "allowed_providers". Only honored in the managed settings file.
+    pub locked: Option<Vec<LockableSetting>>,
+    /// Moderation of prompts and completions, as required by some
+    /// organizations' policies.
+    pub moderation: Option<ModerationSettingsContent>,
+    /// Policies that route requests to the fastest healthy model out of a set
+    /// of candidates, keyed by the feature the requests come from (e.g.
+    /// "inline_assist").
+    ///
+    /// Default: {}
+    pub routing: Option<BTreeMap<String, RoutingPolicy>>,
+    /// The model to always send the requests of a feature to, keyed by the
+    /// feature (e.g. "inline_assist"), so that a small local model can serve
+    /// inline assists while conversations use a large cloud model. Takes
+    /// precedence over the feature's routing policy, unless the model isn't
+    /// available.
+    ///
+    /// Default: {}
+    #[schemars(schema_with = "feature_models_schema")]
+    pub feature_models: Option<BTreeMap<LanguageModelRequestFeature, FeatureModel>>,
+    /// Named bundles of request parameters, such as "precise" or "creative",
+    /// that features refer to when building requests. Presets defined here
+    /// replace the built-in presets of the same name.
+    pub presets: Option<BTreeMap<String, ParameterPreset>>,
+    /// Request rate limits keyed by provider ID (e.g. "anthropic"), shared
+    /// by every Zed window on this machine, so that windows using the same
+    /// API key take turns rather than tripping the provider's limits.
+    ///
+    /// Default: {}
+    pub rate_limits: Option<BTreeMap<String, RequestRateLimit>>,
+    /// HTTP connection tuning keyed by provider ID (e.g. "openai"), for
+    /// gateways that drop long-lived streaming connections under the
+    /// default settings.
+    ///
+    /// Default: {}
+    pub connections: Option<BTreeMap<String, ConnectionSettings>>,
+    /// When to temporarily disable a provider that keeps failing, so that
+    /// requests fail fast rather than waiting on it.
+    pub circuit_breaker: Option<CircuitBreakerSettingsContent>,
+    /// The most tokens a completion may generate, globally and for specific
+    /// models. Requests asking for more are clamped to the ceiling.
+    ///
+    /// Default: { "default": null, "models": {} }
+    pub max_output_tokens: Option<MaxOutputTokens>,
+    /// Whether to journal completion requests and their outputs on disk, so
+    /// that they can be replayed against other models.
+    ///
+    /// Default: false
+    pub journal: Option<bool>,
+    /// The providers that generate images from text prompts, keyed by
+    /// provider ID ("openai" or "stability").
+    pub image_generation: Option<ImageGenerationSettingsContent>,
+    /// The providers that transcribe speech into text, keyed by provider ID
+    /// ("openai" or "local_whisper").
+    pub transcription: Option<TranscriptionSettingsContent>,
     pub anthropic: Option<AnthropicSettingsContent>,
     pub ollama: Option<OllamaSettingsContent>,
     pub openai: Option<OpenAiSettingsContent>,
@@ -41,41 +217,177 @@ pub struct AllLanguageModelSettingsContent {
     pub copilot_chat: Option<CopilotChatSettingsContent>,
 }
 
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct ModerationSettingsContent {
+    /// The classifier used to moderate text.
+    ///
+    /// Default: "openai"
+    pub classifier: Option<ModerationClassifier>,
+    /// The terms flagged by the "local" classifier, matched case-insensitively.
+    ///
+    /// Default: []
+    pub blocked_terms: Option<Vec<String>>,
+    /// How to moderate each provider, keyed by provider ID (e.g. "anthropic").
+    /// Providers that aren't listed are not moderated.
+    ///
+    /// Default: {}
+    pub providers: Option<BTreeMap<String, ProviderModerationSettings>>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct CircuitBreakerSettingsContent {
+    /// The number of consecutive failed requests after which a provider is
+    /// disabled, or 0 to never disable providers.
+    ///
+    /// Default: 5
+    pub failure_threshold: Option<u32>,
+    /// How long a disabled provider stays disabled before a request is let
+    /// through to probe whether it has recovered.
+    ///
+    /// Default: 30
+    pub cooldown_in_seconds: Option<u64>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct ImageGenerationSettingsContent {
+    pub openai: Option<OpenAiImageGenerationSettings>,
+    pub stability: Option<StabilityImageGenerationSettings>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct TranscriptionSettingsContent {
+    pub openai: Option<OpenAiTranscriptionSettings>,
+    pub local_whisper: Option<LocalWhisperSettings>,
+}
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct AnthropicSettingsContent {
     pub api_url: Option<String>,
     pub low_speed_timeout_in_seconds: Option<u64>,
+    /// How long to wait for the first token of a response before giving up
+    /// on the request and retrying it.
+    pub first_token_timeout_in_seconds: Option<u64>,
     pub available_models: Option<Vec<anthropic::Model>>,
+    /// How requests are authenticated: with the API key, or by signing them
+    /// with it, as some self-hosted gateways require.
+    ///
+    /// Default: { "mode": "api_key" }
+    pub auth: Option<RequestAuth>,
+    /// A reference to an organization secret to use as the API key instead
+    /// of the one stored in the keychain, e.g. "org:anthropic" or
+    /// "org:acme/anthropic". The secret is leased from zed.dev for a few minutes
+    /// at a time and never stored locally. Set it to `null` to go back to the
+    /// key in the keychain.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_nullable"
+    )]
+    pub api_key_ref: Option<Option<String>>,
+    /// The environment variable to read the API key from, or a list of them
+    /// to rotate between when a key is rejected or rate limited, e.g. for
+    /// keys from several organization seats.
+    ///
+    /// Default: "ANTHROPIC_API_KEY"
+    pub api_key_env: Option<ApiKeyEnv>,
+    /// A tag sent as the `user_id` metadata of every request, so that usage
+    /// can be attributed to a team in Anthropic's console, e.g. "acme-backend".
+    pub user: Option<String>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct OllamaSettingsContent {
     pub api_url: Option<String>,
     pub low_speed_timeout_in_seconds: Option<u64>,
+    /// How long to wait for the first token of a response before giving up
+    /// on the request and retrying it.
+    pub first_token_timeout_in_seconds: Option<u64>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct OpenAiSettingsContent {
     pub api_url: Option<String>,
     pub low_speed_timeout_in_seconds: Option<u64>,
+    /// How long to wait for the first token of a response before giving up
+    /// on the request and retrying it.
+    pub first_token_timeout_in_seconds: Option<u64>,
     pub available_models: Option<Vec<open_ai::Model>>,
+    /// How requests are authenticated: with the API key, or by signing them
+    /// with it, as some self-hosted gateways require.
+    ///
+    /// Default: { "mode": "api_key" }
+    pub auth: Option<RequestAuth>,
+    /// A reference to an organization secret to use as the API key instead
+    /// of the one stored in the keychain, e.g. "org:openai" or
+    /// "org:acme/openai". The secret is leased from zed.dev for a few minutes
+    /// at a time and never stored locally. Set it to `null` to go back to the
+    /// key in the keychain.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_nullable"
+    )]
+    pub api_key_ref: Option<Option<String>>,
+    /// The environment variable to read the API key from, or a list of them
+    /// to rotate between when a key is rejected or rate limited, e.g. for
+    /// keys from several organization seats.
+    ///
+    /// Default: "OPENAI_API_KEY"
+    pub api_key_env: Option<ApiKeyEnv>,
+    /// Models to request through the Responses API instead of chat
+    /// completions, keyed by model ID (e.g. "gpt-4o"). The Responses API keeps
+    /// conversation state on the server and offers built-in tools.
+    ///
+    /// Default: {}
+    pub responses_api: Option<BTreeMap<String, ResponsesApiSettings>>,
+    /// A tag sent as the `user` of every request, so that usage can be
+    /// attributed to a team in OpenAI's dashboard, e.g. "acme-backend".
+    pub user: Option<String>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct GoogleSettingsContent {
     pub api_url: Option<String>,
     pub low_speed_timeout_in_seconds: Option<u64>,
+    /// How long to wait for the first token of a response before giving up
+    /// on the request and retrying it.
+    pub first_token_timeout_in_seconds: Option<u64>,
     pub available_models: Option<Vec<google_ai::Model>>,
+    /// A reference to an organization secret to use as the API key instead
+    /// of the one stored in the keychain, e.g. "org:google" or
+    /// "org:acme/google". The secret is leased from zed.dev for a few minutes
+    /// at a time and never stored locally. Set it to `null` to go back to the
+    /// key in the keychain.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_nullable"
+    )]
+    pub api_key_ref: Option<Option<String>>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct ZedDotDevSettingsContent {
     available_models: Option<Vec<cloud::AvailableModel>>,
+    /// Whether to resume completions from where they left off when the
+    /// connection drops, rather than restarting them. The server keeps the
+    /// completion going while the connection is down.
+    ///
+    /// Default: false
+    resumable_streaming: Option<bool>,
+    /// Whether zed.dev may serve a request with a different upstream vendor
+    /// (e.g. OpenAI instead of Anthropic) when the requested one is
+    /// unavailable. Turn this off if your data may only be sent to the vendor
+    /// of the model you chose.
+    ///
+    /// Default: false
+    allow_vendor_failover: Option<bool>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct CopilotChatSettingsContent {
     low_speed_timeout_in_seconds: Option<u64>,
+    first_token_timeout_in_seconds: Option<u64>,
 }
 
 impl settings::Settings for AllLanguageModelSettings {
@@ -93,6 +405,81 @@ impl settings::Settings for AllLanguageModelSettings {
         let mut settings = AllLanguageModelSettings::default();
 
         for value in sources.defaults_and_customizations() {
+            merge(&mut settings.sync_api_keys, value.sync_api_keys);
+            if let Some(allowed_providers) = value.allowed_providers.clone() {
+                settings.allowed_providers = Some(allowed_providers);
+            }
+
+            merge(
+                &mut settings.moderation.classifier,
+                value.moderation.as_ref().and_then(|s| s.classifier),
+            );
+            merge(
+                &mut settings.moderation.blocked_terms,
+                value
+                    .moderation
+                    .as_ref()
+                    .and_then(|s| s.blocked_terms.clone()),
+            );
+            merge(
+                &mut settings.moderation.providers,
+                value.moderation.as_ref().and_then(|s| s.providers.clone()),
+            );
+
+            merge(&mut settings.routing, value.routing.clone());
+            merge(&mut settings.rate_limits, value.rate_limits.clone());
+            merge(&mut settings.connections, value.connections.clone());
+            merge(
+                &mut settings.circuit_breaker.failure_threshold,
+                value
+                    .circuit_breaker
+                    .as_ref()
+                    .and_then(|s| s.failure_threshold),
+            );
+            if let Some(cooldown_in_seconds) = value
+                .circuit_breaker
+                .as_ref()
+                .and_then(|s| s.cooldown_in_seconds)
+            {
+                settings.circuit_breaker.cooldown = Duration::from_secs(cooldown_in_seconds);
+            }
+            merge(
+                &mut settings.max_output_tokens,
+                value.max_output_tokens.clone(),
+            );
+            merge(&mut settings.journal, value.journal);
+            merge(
+                &mut settings.image_generation.openai,
+                value
+                    .image_generation
+                    .as_ref()
+                    .and_then(|s| s.openai.clone()),
+            );
+            merge(
+                &mut settings.image_generation.stability,
+                value
+                    .image_generation
+                    .as_ref()
+                    .and_then(|s| s.stability.clone()),
+            );
+            merge(
+                &mut settings.transcription.openai,
+                value.transcription.as_ref().and_then(|s| s.openai.clone()),
+            );
+            merge(
+                &mut settings.transcription.local_whisper,
+                value
+                    .transcription
+                    .as_ref()
+                    .and_then(|s| s.local_whisper.clone()),
+            );
+            if let Some(feature_models) = value.feature_models.as_ref() {
+                settings.feature_models.extend(feature_models.clone());
+            }
+            if let Some(presets) = value.presets.as_ref() {
+                settings.presets.extend(presets.clone());
+            }
+
             merge(
                 &mut settings.anthropic.api_url,
                 value.anthropic.as_ref().and_then(|s| s.api_url.clone()),
@@ -105,6 +492,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 settings.anthropic.low_speed_timeout =
                     Some(Duration::from_secs(low_speed_timeout_in_seconds));
             }
+            if let Some(first_token_timeout_in_seconds) = value
+                .anthropic
+                .as_ref()
+                .and_then(|s| s.first_token_timeout_in_seconds)
+            {
+                settings.anthropic.first_token_timeout =
+                    Some(Duration::from_secs(first_token_timeout_in_seconds));
+            }
             merge(
                 &mut settings.anthropic.available_models,
                 value
@@ -112,6 +507,21 @@ impl settings::Settings for AllLanguageModelSettings {
                     .as_ref()
                     .and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.anthropic.auth,
+                value.anthropic.as_ref().and_then(|s| s.auth.clone()),
+            );
+            merge(
+                &mut settings.anthropic.api_key_ref,
+                value.anthropic.as_ref().and_then(|s| s.api_key_ref.clone()),
+            );
+            if let Some(api_key_env) = value.anthropic.as_ref().and_then(|s| s.api_key_env.clone())
+            {
+                settings.anthropic.api_key_env = Some(api_key_env);
+            }
+            if let Some(user) = value.anthropic.as_ref().and_then(|s| s.user.clone()) {
+                settings.anthropic.user = Some(user);
+            }
 
             merge(
                 &mut settings.ollama.api_url,
@@ -125,6 +535,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 settings.ollama.low_speed_timeout =
                     Some(Duration::from_secs(low_speed_timeout_in_seconds));
             }
+            if let Some(first_token_timeout_in_seconds) = value
+                .ollama
+                .as_ref()
+                .and_then(|s| s.first_token_timeout_in_seconds)
+            {
+                settings.ollama.first_token_timeout =
+                    Some(Duration::from_secs(first_token_timeout_in_seconds));
+            }
 
             merge(
                 &mut settings.openai.api_url,
@@ -138,6 +556,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 settings.openai.low_speed_timeout =
                     Some(Duration::from_secs(low_speed_timeout_in_seconds));
             }
+            if let Some(first_token_timeout_in_seconds) = value
+                .openai
+                .as_ref()
+                .and_then(|s| s.first_token_timeout_in_seconds)
+            {
+                settings.openai.first_token_timeout =
+                    Some(Duration::from_secs(first_token_timeout_in_seconds));
+            }
             merge(
                 &mut settings.openai.available_models,
                 value
@@ -145,6 +571,24 @@ impl settings::Settings for AllLanguageModelSettings {
                     .as_ref()
                     .and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.openai.auth,
+                value.openai.as_ref().and_then(|s| s.auth.clone()),
+            );
+            merge(
+                &mut settings.openai.api_key_ref,
+                value.openai.as_ref().and_then(|s| s.api_key_ref.clone()),
+            );
+            if let Some(api_key_env) = value.openai.as_ref().and_then(|s| s.api_key_env.clone()) {
+                settings.openai.api_key_env = Some(api_key_env);
+            }
+            merge(
+                &mut settings.openai.responses_api,
+                value.openai.as_ref().and_then(|s| s.responses_api.clone()),
+            );
+            if let Some(user) = value.openai.as_ref().and_then(|s| s.user.clone()) {
+                settings.openai.user = Some(user);
+            }
 
             merge(
                 &mut settings.zed_dot_dev.available_models,
@@ -153,6 +597,20 @@ impl settings::Settings for AllLanguageModelSettings {
                     .as_ref()
                     .and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.zed_dot_dev.resumable_streaming,
+                value
+                    .zed_dot_dev
+                    .as_ref()
+                    .and_then(|s| s.resumable_streaming),
+            );
+            merge(
+                &mut settings.zed_dot_dev.allow_vendor_failover,
+                value
+                    .zed_dot_dev
+                    .as_ref()
+                    .and_then(|s| s.allow_vendor_failover),
+            );
 
             merge(
                 &mut settings.google.api_url,
@@ -166,6 +624,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 settings.google.low_speed_timeout =
                     Some(Duration::from_secs(low_speed_timeout_in_seconds));
             }
+            if let Some(first_token_timeout_in_seconds) = value
+                .google
+                .as_ref()
+                .and_then(|s| s.first_token_timeout_in_seconds)
+            {
+                settings.google.first_token_timeout =
+                    Some(Duration::from_secs(first_token_timeout_in_seconds));
+            }
             merge(
                 &mut settings.google.available_models,
                 value
@@ -173,6 +639,10 @@ impl settings::Settings for AllLanguageModelSettings {
                     .as_ref()
                     .and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.google.api_key_ref,
+                value.google.as_ref().and_then(|s| s.api_key_ref.clone()),
+            );
 
             if let Some(low_speed_timeout) = value
                 .copilot_chat
@@ -182,8 +652,60 @@ impl settings::Settings for AllLanguageModelSettings {
                 settings.copilot_chat.low_speed_timeout =
                     Some(Duration::from_secs(low_speed_timeout));
             }
+            if let Some(first_token_timeout) = value
+                .copilot_chat
+                .as_ref()
+                .and_then(|s| s.first_token_timeout_in_seconds)
+            {
+                settings.copilot_chat.first_token_timeout =
+                    Some(Duration::from_secs(first_token_timeout));
+            }
         }
 
+        apply_locked_settings(&mut settings, &sources);
+
         Ok(settings)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_file_json_schema() {
+        let schema = settings_file_json_schema();
+        let settings = &schema["properties"]["language_models"]["properties"];
+        assert!(settings.get("sync_api_keys").is_some());
+        assert!(settings.get("zed.dev").is_some());
+
+        // Each provider's models have a shape of their own.
+        let anthropic_models = settings
+            .pointer("/anthropic/properties/available_models/items")
+            .unwrap();
+        let open_ai_models = settings
+            .pointer("/openai/properties/available_models/items")
+            .unwrap();
+        assert_ne!(anthropic_models, open_ai_models);
+
+        // Only known features can be given a model.
+        let features = settings
+            .pointer("/feature_models/propertyNames/enum")
+            .unwrap();
+        assert!(features
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("inline_assist")));
+    }
+
+    #[test]
+    fn test_api_key_ref_can_be_cleared() {
+        let parse = |json| serde_json::from_str::<OpenAiSettingsContent>(json).unwrap();
+        assert_eq!(parse("{}").api_key_ref, None);
+        assert_eq!(
+            parse(r#"{"api_key_ref": "org:openai"}"#).api_key_ref,
+            Some(Some("org:openai".into()))
+        );
+        assert_eq!(parse(r#"{"api_key_ref": null}"#).api_key_ref, Some(None));
+    }
+}
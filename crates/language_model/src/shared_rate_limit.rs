@@ -0,0 +1,219 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use gpui::{AppContext, BackgroundExecutor};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use crate::{settings::AllLanguageModelSettings, LanguageModelProviderId};
+
+/// How long to wait for another process to release a bucket's lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait before checking whether a bucket's lock was released.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How old a lock file must be before it's considered to have been left
+/// behind by a process that exited while holding it.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10);
+
+/// The rate at which requests can be sent to a provider.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RequestRateLimit {
+    /// The number of requests that can be sent per minute.
+    pub requests_per_minute: u32,
+    /// The number of requests that can be sent at once after a quiet period.
+    /// Defaults to `requests_per_minute`.
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+impl RequestRateLimit {
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.requests_per_minute).max(1) as f64
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        self.requests_per_minute.max(1) as f64 / 60.
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BucketState {
+    tokens: f64,
+    updated_at_ms: u64,
+}
+
+/// A token bucket shared with the other Zed processes on the machine.
+#[derive(Clone, Debug)]
+pub struct SharedTokenBucket {
+    state_path: PathBuf,
+    lock_path: PathBuf,
+    limit: RequestRateLimit,
+}
+
+impl SharedTokenBucket {
+    /// Returns the bucket for the provider, if it has a rate limit configured.
+    pub fn for_provider(provider_id: &LanguageModelProviderId, cx: &AppContext) -> Option<Self> {
+        let limit = AllLanguageModelSettings::get_global(cx)
+            .rate_limits
+            .get(provider_id.0.as_ref())?;
+        Some(Self::new(
+            paths::language_model_rate_limits_dir(),
+            &provider_id.0,
+            limit.clone(),
+        ))
+    }
+
+    pub fn new(dir: &Path, name: &str, limit: RequestRateLimit) -> Self {
+        // Provider IDs such as "zed.dev" are safe to use in file names, but
+        // don't rely on it.
+        let name = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        Self {
+            state_path: dir.join(format!("{name}.json")),
+            lock_path: dir.join(format!("{name}.lock")),
+            limit,
+        }
+    }
+
+    /// Waits until a request can be sent.
+    pub async fn acquire(&self, executor: &BackgroundExecutor) -> Result<()> {
+        loop {
+            let bucket = self.clone();
+            let wait = executor
+                .spawn(async move { bucket.try_acquire(SystemTime::now()) })
+                .await?;
+            match wait {
+                Some(wait) => executor.timer(wait).await,
+                None => return Ok(()),
+            };
+        }
+    }
+
+    /// Takes a token from the bucket. If the bucket is empty, returns how long
+    /// to wait before trying again.
+    pub fn try_acquire(&self, now: SystemTime) -> Result<Option<Duration>> {
+        if let Some(dir) = self.state_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = LockFile::acquire(&self.lock_path)?;
+
+        let now_ms = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let capacity = self.limit.capacity();
+        let mut state = fs::read(&self.state_path)
+            .ok()
+            .and_then(|state| serde_json::from_slice::<BucketState>(&state).ok())
+            .unwrap_or(BucketState {
+                tokens: capacity,
+                updated_at_ms: now_ms,
+            });
+
+        let elapsed = now_ms.saturating_sub(state.updated_at_ms) as f64 / 1000.;
+        state.tokens = (state.tokens + elapsed * self.limit.tokens_per_second()).min(capacity);
+        state.updated_at_ms = state.updated_at_ms.max(now_ms);
+        let wait = if state.tokens >= 1. {
+            state.tokens -= 1.;
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1. - state.tokens) / self.limit.tokens_per_second(),
+            ))
+        };
+
+        fs::write(&self.state_path, serde_json::to_vec(&state)?)?;
+        Ok(wait)
+    }
+}
+
+/// A lock held by creating a file, which is removed when the lock is dropped.
+struct LockFile<'a> {
+    path: &'a Path,
+}
+
+impl<'a> LockFile<'a> {
+    fn acquire(path: &'a Path) -> Result<Self> {
+        let started_at = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    if Self::is_stale(path) {
+                        fs::remove_file(path).ok();
+                        continue;
+                    }
+                    if started_at.elapsed() > LOCK_TIMEOUT {
+                        return Err(anyhow!("timed out waiting for the lock on {path:?}"));
+                    }
+                    std::thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn is_stale(path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map_or(false, |age| age > STALE_LOCK_AGE)
+    }
+}
+
+impl Drop for LockFile<'_> {
+    fn drop(&mut self) {
+        fs::remove_file(self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_token_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let limit = RequestRateLimit {
+            requests_per_minute: 60,
+            burst: Some(2),
+        };
+        // Two processes sharing a bucket.
+        let a = SharedTokenBucket::new(dir.path(), "anthropic", limit.clone());
+        let b = SharedTokenBucket::new(dir.path(), "anthropic", limit.clone());
+        let other = SharedTokenBucket::new(dir.path(), "openai", limit);
+
+        let now = SystemTime::now();
+        assert_eq!(a.try_acquire(now).unwrap(), None);
+        assert_eq!(b.try_acquire(now).unwrap(), None);
+        assert_eq!(a.try_acquire(now).unwrap(), Some(Duration::from_secs(1)));
+        assert_eq!(other.try_acquire(now).unwrap(), None);
+
+        // The bucket refills over time.
+        let later = now + Duration::from_millis(500);
+        assert_eq!(
+            b.try_acquire(later).unwrap(),
+            Some(Duration::from_millis(500))
+        );
+        let later = now + Duration::from_secs(1);
+        assert_eq!(b.try_acquire(later).unwrap(), None);
+        assert!(!dir.path().join("anthropic.lock").exists());
+    }
+}
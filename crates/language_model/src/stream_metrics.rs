@@ -0,0 +1,305 @@
+use std::{collections::VecDeque, fs, path::Path, time::Duration};
+
+use anyhow::Result;
+use collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{routing::ModelKey, LanguageModelId, LanguageModelProviderId};
+
+/// The number of most recent requests the statistics are computed over.
+pub(crate) const STATS_WINDOW: usize = 20;
+
+/// Rolling statistics for a model.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelStreamStats {
+    /// The time to first token of recent requests, or `None` for requests
+    /// that failed.
+    samples: VecDeque<Option<Duration>>,
+    /// The rate at which recent responses streamed once they started, in
+    /// tokens per second.
+    throughput: VecDeque<f32>,
+}
+
+impl ModelStreamStats {
+    fn record(&mut self, sample: Option<Duration>) {
+        if self.samples.len() == STATS_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn record_throughput(&mut self, tokens_per_second: f32) {
+        if self.throughput.len() == STATS_WINDOW {
+            self.throughput.pop_front();
+        }
+        self.throughput.push_back(tokens_per_second);
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The mean time to first token of the recent requests that succeeded.
+    pub fn mean_time_to_first_token(&self) -> Option<Duration> {
+        let latencies = self.samples.iter().flatten().collect::<Vec<_>>();
+        if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().copied().sum::<Duration>() / latencies.len() as u32)
+        }
+    }
+
+    /// The mean rate at which recent responses streamed, in tokens per second.
+    pub fn tokens_per_second(&self) -> Option<f32> {
+        if self.throughput.is_empty() {
+            None
+        } else {
+            Some(self.throughput.iter().sum::<f32>() / self.throughput.len() as f32)
+        }
+    }
+
+    pub fn error_rate(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.
+        } else {
+            let errors = self
+                .samples
+                .iter()
+                .filter(|sample| sample.is_none())
+                .count();
+            errors as f32 / self.samples.len() as f32
+        }
+    }
+}
+
+/// A summary of how a model has performed over its recent requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelStreamSummary {
+    pub provider_id: LanguageModelProviderId,
+    pub model_id: LanguageModelId,
+    pub sample_count: usize,
+    pub time_to_first_token: Option<Duration>,
+    pub tokens_per_second: Option<f32>,
+    pub error_rate: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedModelStreamStats {
+    provider: String,
+    model: String,
+    #[serde(flatten)]
+    stats: ModelStreamStats,
+}
+
+/// The statistics of every model requests were recently streamed from.
+#[derive(Default)]
+pub struct StreamMetrics {
+    models: HashMap<ModelKey, ModelStreamStats>,
+    has_unsaved_changes: bool,
+}
+
+impl StreamMetrics {
+    pub fn record_first_token(&mut self, model: ModelKey, time_to_first_token: Duration) {
+        self.models
+            .entry(model)
+            .or_default()
+            .record(Some(time_to_first_token));
+        self.has_unsaved_changes = true;
+    }
+
+    pub fn record_failure(&mut self, model: ModelKey) {
+        self.models.entry(model).or_default().record(None);
+        self.has_unsaved_changes = true;
+    }
+
+    /// Records that a response of `token_count` tokens finished streaming
+    /// `duration` after its first token.
+    pub fn record_completion(&mut self, model: ModelKey, token_count: usize, duration: Duration) {
+        // Responses that arrive in a single chunk say nothing about throughput.
+        if token_count == 0 || duration.is_zero() {
+            return;
+        }
+        self.models
+            .entry(model)
+            .or_default()
+            .record_throughput(token_count as f32 / duration.as_secs_f32());
+        self.has_unsaved_changes = true;
+    }
+
+    pub fn stats(&self, model: &ModelKey) -> Option<&ModelStreamStats> {
+        self.models.get(model)
+    }
+
+    pub fn summary(&self, model: &ModelKey) -> Option<ModelStreamSummary> {
+        let stats = self.models.get(model)?;
+        Some(ModelStreamSummary {
+            provider_id: model.0.clone(),
+            model_id: model.1.clone(),
+            sample_count: stats.sample_count(),
+            time_to_first_token: stats.mean_time_to_first_token(),
+            tokens_per_second: stats.tokens_per_second(),
+            error_rate: stats.error_rate(),
+        })
+    }
+
+    /// Returns the summaries of every model, ordered by provider and model.
+    pub fn summaries(&self) -> Vec<ModelStreamSummary> {
+        let mut summaries = self
+            .models
+            .keys()
+            .filter_map(|model| self.summary(model))
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| {
+            (&a.provider_id.0, &a.model_id.0).cmp(&(&b.provider_id.0, &b.model_id.0))
+        });
+        summaries
+    }
+
+    /// Loads the statistics saved at the given path. A missing file yields
+    /// empty statistics.
+    pub fn load(path: &Path) -> Result<Self> {
+        let models = match fs::read(path) {
+            Ok(contents) => serde_json::from_slice::<Vec<SerializedModelStreamStats>>(&contents)?
+                .into_iter()
+                .map(|model| {
+                    (
+                        (
+                            LanguageModelProviderId::from(model.provider),
+                            LanguageModelId::from(model.model),
+                        ),
+                        model.stats,
+                    )
+                })
+                .collect(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::default(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self {
+            models,
+            has_unsaved_changes: false,
+        })
+    }
+
+    /// Adds the statistics of models that nothing was recorded for yet, such
+    /// as those loaded after requests were already sent.
+    pub fn merge(&mut self, other: StreamMetrics) {
+        for (model, stats) in other.models {
+            self.models.entry(model).or_insert(stats);
+        }
+    }
+
+    /// Returns the statistics to save, if they changed since they were last
+    /// saved.
+    pub fn take_unsaved(&mut self) -> Option<Vec<u8>> {
+        if !self.has_unsaved_changes {
+            return None;
+        }
+        self.has_unsaved_changes = false;
+        let models = self
+            .models
+            .iter()
+            .map(
+                |((provider_id, model_id), stats)| SerializedModelStreamStats {
+                    provider: provider_id.0.to_string(),
+                    model: model_id.0.to_string(),
+                    stats: stats.clone(),
+                },
+            )
+            .collect::<Vec<_>>();
+        serde_json::to_vec(&models).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(model: &str) -> ModelKey {
+        (
+            LanguageModelProviderId::from("provider".to_string()),
+            LanguageModelId::from(model.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_model_stream_stats() {
+        let mut stats = ModelStreamStats::default();
+        assert_eq!(stats.mean_time_to_first_token(), None);
+        assert_eq!(stats.tokens_per_second(), None);
+        assert_eq!(stats.error_rate(), 0.);
+
+        stats.record(Some(Duration::from_millis(100)));
+        stats.record(Some(Duration::from_millis(300)));
+        stats.record(None);
+        stats.record(None);
+        assert_eq!(
+            stats.mean_time_to_first_token(),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(stats.error_rate(), 0.5);
+
+        for _ in 0..STATS_WINDOW {
+            stats.record(Some(Duration::from_millis(50)));
+        }
+        assert_eq!(stats.sample_count(), STATS_WINDOW);
+        assert_eq!(
+            stats.mean_time_to_first_token(),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(stats.error_rate(), 0.);
+    }
+
+    #[test]
+    fn test_stream_metrics() {
+        let mut metrics = StreamMetrics::default();
+        assert_eq!(metrics.take_unsaved(), None);
+
+        metrics.record_first_token(key("a"), Duration::from_millis(400));
+        metrics.record_completion(key("a"), 100, Duration::from_secs(2));
+        metrics.record_completion(key("a"), 300, Duration::from_secs(2));
+        metrics.record_completion(key("a"), 10, Duration::ZERO);
+        metrics.record_failure(key("b"));
+
+        assert_eq!(
+            metrics.summaries(),
+            [
+                ModelStreamSummary {
+                    provider_id: key("a").0,
+                    model_id: key("a").1,
+                    sample_count: 1,
+                    time_to_first_token: Some(Duration::from_millis(400)),
+                    tokens_per_second: Some(100.),
+                    error_rate: 0.,
+                },
+                ModelStreamSummary {
+                    provider_id: key("b").0,
+                    model_id: key("b").1,
+                    sample_count: 1,
+                    time_to_first_token: None,
+                    tokens_per_second: None,
+                    error_rate: 1.,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stream_metrics.json");
+        assert!(StreamMetrics::load(&path).unwrap().summaries().is_empty());
+
+        let mut metrics = StreamMetrics::default();
+        metrics.record_first_token(key("a"), Duration::from_millis(400));
+        metrics.record_completion(key("a"), 100, Duration::from_secs(2));
+        fs::write(&path, metrics.take_unsaved().unwrap()).unwrap();
+        assert_eq!(metrics.take_unsaved(), None);
+
+        // Statistics recorded before the saved ones are loaded take precedence.
+        let mut restored = StreamMetrics::default();
+        restored.record_failure(key("b"));
+        restored.merge(StreamMetrics::load(&path).unwrap());
+        assert_eq!(restored.summary(&key("a")), metrics.summary(&key("a")));
+        assert_eq!(restored.summary(&key("b")).unwrap().error_rate, 1.);
+    }
+}
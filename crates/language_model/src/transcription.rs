@@ -0,0 +1,139 @@
+mod local_whisper;
+mod open_ai;
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use gpui::{AppContext, AsyncAppContext};
+use settings::Settings;
+
+use crate::{settings::AllLanguageModelSettings, LanguageModelProviderId};
+
+pub use self::local_whisper::{LocalWhisperProvider, LocalWhisperSettings};
+pub use self::open_ai::{OpenAiTranscriptionProvider, OpenAiTranscriptionSettings};
+
+/// A provider that transcribes speech into text, for features that take
+/// prompts by voice.
+pub trait TranscriptionProvider: Send + Sync {
+    /// The provider's ID, which it shares with the language model provider of
+    /// the same vendor (e.g. "openai"), if any.
+    fn id(&self) -> LanguageModelProviderId;
+    fn name(&self) -> &'static str;
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<Transcription>>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TranscriptionRequest {
+    /// The encoded audio.
+    pub audio: Vec<u8>,
+    pub format: AudioFormat,
+    /// The language of the audio, as an ISO-639-1 code (e.g. "en"), or
+    /// `None` to detect it.
+    pub language: Option<String>,
+    /// Text that the audio follows on from, or that contains words it uses,
+    /// such as identifiers from the current file.
+    pub prompt: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Flac,
+    M4a,
+    Mp3,
+    Ogg,
+    Wav,
+    Webm,
+}
+
+impl AudioFormat {
+    /// The name to upload audio of this format under, as the APIs infer the
+    /// format from the file extension.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            AudioFormat::Flac => "audio.flac",
+            AudioFormat::M4a => "audio.m4a",
+            AudioFormat::Mp3 => "audio.mp3",
+            AudioFormat::Ogg => "audio.ogg",
+            AudioFormat::Wav => "audio.wav",
+            AudioFormat::Webm => "audio.webm",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcription {
+    pub text: String,
+    /// The transcription split into segments, such as sentences, with their
+    /// timestamps. Empty when the provider doesn't report timestamps.
+    pub segments: Vec<TranscriptionSegment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscriptionSegment {
+    /// The start of the segment, from the start of the audio.
+    pub start: Duration,
+    /// The end of the segment, from the start of the audio.
+    pub end: Duration,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TranscriptionSettings {
+    pub openai: OpenAiTranscriptionSettings,
+    pub local_whisper: LocalWhisperSettings,
+}
+
+/// Returns the transcription providers that may be used.
+pub fn transcription_providers(cx: &AppContext) -> Vec<Arc<dyn TranscriptionProvider>> {
+    let settings = AllLanguageModelSettings::get_global(cx);
+    [
+        Arc::new(OpenAiTranscriptionProvider) as Arc<dyn TranscriptionProvider>,
+        Arc::new(LocalWhisperProvider),
+    ]
+    .into_iter()
+    .filter(|provider| settings.is_provider_allowed(&provider.id()))
+    .collect()
+}
+
+/// Returns the transcription provider with the given ID, if it may be used.
+pub fn transcription_provider(
+    id: &LanguageModelProviderId,
+    cx: &AppContext,
+) -> Option<Arc<dyn TranscriptionProvider>> {
+    transcription_providers(cx)
+        .into_iter()
+        .find(|provider| provider.id() == *id)
+}
+
+fn audio_transcription_request(
+    model: &str,
+    request: TranscriptionRequest,
+) -> ::open_ai::AudioTranscriptionRequest {
+    ::open_ai::AudioTranscriptionRequest {
+        model: model.to_string(),
+        file_name: request.format.file_name().to_string(),
+        audio: request.audio,
+        language: request.language,
+        prompt: request.prompt,
+    }
+}
+
+fn transcription(response: ::open_ai::AudioTranscriptionResponse) -> Transcription {
+    Transcription {
+        text: response.text.trim().to_string(),
+        segments: response
+            .segments
+            .into_iter()
+            .map(|segment| TranscriptionSegment {
+                start: Duration::from_secs_f64(segment.start.max(0.)),
+                end: Duration::from_secs_f64(segment.end.max(0.)),
+                text: segment.text.trim().to_string(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future::BoxFuture, AsyncReadExt, Future, FutureExt};
+use gpui::AsyncAppContext;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use super::{
+    audio_transcription_request, transcription, Transcription, TranscriptionProvider,
+    TranscriptionRequest,
+};
+use crate::{
+    connection_settings::http_client_for_provider, settings::AllLanguageModelSettings,
+    LanguageModelProviderId,
+};
+
+const PROVIDER_ID: &str = "local_whisper";
+
+/// Transcription through a whisper.cpp server running on this machine, or
+/// elsewhere on the network, so that audio never leaves it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct LocalWhisperSettings {
+    /// The URL of the server.
+    ///
+    /// Default: "http://localhost:8080"
+    pub api_url: String,
+}
+
+impl Default for LocalWhisperSettings {
+    fn default() -> Self {
+        Self {
+            api_url: "http://localhost:8080".into(),
+        }
+    }
+}
+
+pub struct LocalWhisperProvider;
+
+impl TranscriptionProvider for LocalWhisperProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "Local Whisper"
+    }
+
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<Transcription>> {
+        let settings_and_client = cx.update(|cx| {
+            (
+                AllLanguageModelSettings::get_global(cx)
+                    .transcription
+                    .local_whisper
+                    .clone(),
+                http_client_for_provider(PROVIDER_ID, cx.http_client(), cx),
+            )
+        });
+        async move {
+            let (settings, http_client) = settings_and_client?;
+            inference(http_client.as_ref(), &settings.api_url, request).await
+        }
+        .boxed()
+    }
+}
+
+fn inference(
+    client: &dyn HttpClient,
+    api_url: &str,
+    request: TranscriptionRequest,
+) -> impl 'static + Future<Output = Result<Transcription>> {
+    let uri = format!("{api_url}/inference");
+
+    // The server loads a single model when it starts, and ignores the one
+    // requested.
+    let (content_type, body) = audio_transcription_request("", request).multipart_body();
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", content_type)
+        .body(AsyncBody::from(body))
+        .map(|request| client.send(request));
+
+    async move {
+        let mut response = request?.await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        if response.status().is_success() {
+            let response: open_ai::AudioTranscriptionResponse =
+                serde_json::from_str(&body).context("failed to parse whisper server response")?;
+            Ok(transcription(response))
+        } else {
+            Err(anyhow!(
+                "error during transcription, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::{AudioFormat, TranscriptionSegment};
+    use futures::executor::block_on;
+    use http_client::{FakeHttpClient, Response};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_inference() {
+        let client = FakeHttpClient::create(|mut request| async move {
+            assert_eq!(request.uri().to_string(), "http://localhost:8080/inference");
+            let content_type = request.headers()["Content-Type"].to_str().unwrap();
+            assert!(content_type.starts_with("multipart/form-data; boundary="));
+            let mut body = Vec::new();
+            request.body_mut().read_to_end(&mut body).await.unwrap();
+            let body = String::from_utf8_lossy(&body);
+            assert!(body.contains("name=\"response_format\"\r\n\r\nverbose_json\r\n"));
+            assert!(body.contains("name=\"language\"\r\n\r\nen\r\n"));
+            assert!(body.contains("filename=\"audio.wav\""));
+            assert!(body.contains("RIFF"));
+
+            let body = json!({
+                "text": " Rename this function. Then run the tests.",
+                "segments": [
+                    { "start": 0.0, "end": 1.5, "text": " Rename this function." },
+                    { "start": 1.5, "end": 3.25, "text": " Then run the tests." }
+                ]
+            });
+            Ok(Response::builder()
+                .status(200)
+                .body(body.to_string().into())
+                .unwrap())
+        });
+
+        let transcription = block_on(inference(
+            client.as_ref(),
+            "http://localhost:8080",
+            TranscriptionRequest {
+                audio: b"RIFF....WAVE".to_vec(),
+                format: AudioFormat::Wav,
+                language: Some("en".into()),
+                prompt: None,
+            },
+        ))
+        .unwrap();
+        assert_eq!(
+            transcription,
+            Transcription {
+                text: "Rename this function. Then run the tests.".into(),
+                segments: vec![
+                    TranscriptionSegment {
+                        start: Duration::ZERO,
+                        end: Duration::from_millis(1500),
+                        text: "Rename this function.".into(),
+                    },
+                    TranscriptionSegment {
+                        start: Duration::from_millis(1500),
+                        end: Duration::from_millis(3250),
+                        text: "Then run the tests.".into(),
+                    },
+                ],
+            }
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use gpui::AsyncAppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+
+use super::{
+    audio_transcription_request, transcription, Transcription, TranscriptionProvider,
+    TranscriptionRequest,
+};
+use crate::{
+    connection_settings::http_client_for_provider, org_vault::load_api_key,
+    settings::AllLanguageModelSettings, LanguageModelProviderId,
+};
+
+const PROVIDER_ID: &str = "openai";
+
+/// Transcription through OpenAI's Whisper API, or a compatible one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct OpenAiTranscriptionSettings {
+    /// The URL of the API, or null to use the `api_url` of the OpenAI
+    /// language model provider.
+    ///
+    /// Default: null
+    pub api_url: Option<String>,
+    /// The model to transcribe audio with.
+    ///
+    /// Default: "whisper-1"
+    pub model: String,
+}
+
+impl Default for OpenAiTranscriptionSettings {
+    fn default() -> Self {
+        Self {
+            api_url: None,
+            model: "whisper-1".into(),
+        }
+    }
+}
+
+pub struct OpenAiTranscriptionProvider;
+
+impl TranscriptionProvider for OpenAiTranscriptionProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncAppContext,
+    ) -> BoxFuture<'static, Result<Transcription>> {
+        let mut cx = cx.clone();
+        async move {
+            let (settings, api_url, api_key_ref, http_client) = cx.update(|cx| {
+                let settings = AllLanguageModelSettings::get_global(cx);
+                (
+                    settings.transcription.openai.clone(),
+                    settings.openai.api_url.clone(),
+                    settings.openai.api_key_ref.clone(),
+                    http_client_for_provider(PROVIDER_ID, cx.http_client(), cx),
+                )
+            })?;
+            let api_key = load_api_key(
+                PROVIDER_ID,
+                "OPENAI_API_KEY",
+                api_key_ref,
+                api_url.clone(),
+                &mut cx,
+            )
+            .await?;
+            let api_url = settings.api_url.unwrap_or(api_url);
+
+            let response = open_ai::transcribe(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                audio_transcription_request(&settings.model, request),
+            )
+            .await?;
+            Ok(transcription(response))
+        }
+        .boxed()
+    }
+}
@@ -1,10 +1,10 @@
 use anyhow::{anyhow, Context, Result};
 use futures::{io::BufReader, stream::BoxStream, AsyncBufReadExt, AsyncReadExt, Stream, StreamExt};
-use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, Response};
 use isahc::config::Configurable;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use std::{convert::TryFrom, future::Future, time::Duration};
+use std::{collections::BTreeMap, convert::TryFrom, future::Future, time::Duration};
 use strum::EnumIter;
 
 pub const OPEN_AI_API_URL: &str = "https://api.openai.com/v1";
@@ -119,9 +119,24 @@ pub struct Request {
     pub stop: Vec<String>,
     pub temperature: f32,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDefinition>,
+    /// The end user the request is made on behalf of, shown in the usage
+    /// dashboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StreamOptions {
+    /// Whether to send the usage of the whole request in a final chunk, which
+    /// has no choices.
+    pub include_usage: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -222,6 +237,10 @@ pub struct ResponseStreamEvent {
     pub model: String,
     pub choices: Vec<ChoiceDelta>,
     pub usage: Option<Usage>,
+    /// The URLs of the sources the response cites. This isn't part of
+    /// OpenAI's API, but OpenAI-compatible APIs such as Perplexity's return it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<Vec<String>>,
 }
 
 pub async fn stream_completion(
@@ -231,6 +250,22 @@ pub async fn stream_completion(
     request: Request,
     low_speed_timeout: Option<Duration>,
 ) -> Result<BoxStream<'static, Result<ResponseStreamEvent>>> {
+    let events =
+        stream_raw_completion(client, api_url, api_key, request, low_speed_timeout).await?;
+    Ok(events
+        .map(|event| -> Result<ResponseStreamEvent> { Ok(serde_json::from_value(event?)?) })
+        .boxed())
+}
+
+/// Streams a completion's events as they were sent, without parsing them
+/// into [`ResponseStreamEvent`]s, for reading fields that it omits.
+pub async fn stream_raw_completion(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    request: Request,
+    low_speed_timeout: Option<Duration>,
+) -> Result<BoxStream<'static, Result<Value>>> {
     let uri = format!("{api_url}/chat/completions");
     let mut request_builder = HttpRequest::builder()
         .method(Method::POST)
@@ -243,7 +278,7 @@ pub async fn stream_completion(
     };
 
     let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
-    let mut response = client.send(request).await?;
+    let response = client.send(request).await?;
     if response.status().is_success() {
         let reader = BufReader::new(response.into_body());
         Ok(reader
@@ -266,31 +301,187 @@ pub async fn stream_completion(
             })
             .boxed())
     } else {
-        let mut body = String::new();
-        response.body_mut().read_to_string(&mut body).await?;
+        Err(api_error(response).await)
+    }
+}
 
-        #[derive(Deserialize)]
-        struct OpenAiResponse {
-            error: OpenAiError,
-        }
+/// An unsuccessful response from the API.
+#[derive(Debug)]
+pub struct ApiError {
+    /// The HTTP status code of the response.
+    pub status: u16,
+    message: String,
+}
 
-        #[derive(Deserialize)]
-        struct OpenAiError {
-            message: String,
-        }
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to connect to OpenAI API: {}", self.message)
+    }
+}
 
-        match serde_json::from_str::<OpenAiResponse>(&body) {
-            Ok(response) if !response.error.message.is_empty() => Err(anyhow!(
-                "Failed to connect to OpenAI API: {}",
-                response.error.message,
-            )),
+impl std::error::Error for ApiError {}
 
-            _ => Err(anyhow!(
-                "Failed to connect to OpenAI API: {} {}",
-                response.status(),
-                body,
-            )),
-        }
+/// Returns the error an unsuccessful API response failed with.
+async fn api_error(mut response: Response<AsyncBody>) -> anyhow::Error {
+    let mut body = String::new();
+    if let Err(error) = response.body_mut().read_to_string(&mut body).await {
+        return error.into();
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiResponse {
+        error: OpenAiError,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiError {
+        message: String,
+    }
+
+    let message = match serde_json::from_str::<OpenAiResponse>(&body) {
+        Ok(response) if !response.error.message.is_empty() => response.error.message,
+        _ => format!("{} {}", response.status(), body),
+    };
+    ApiError {
+        status: response.status().as_u16(),
+        message,
+    }
+    .into()
+}
+
+/// A request to the Responses API, which keeps conversation state on the
+/// server and offers built-in tools such as web search.
+#[derive(Debug, Serialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    pub input: Vec<ResponsesInputMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// The response this request continues the conversation of. Its input
+    /// and output don't need to be sent again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_response_id: Option<String>,
+    pub stream: bool,
+    /// Whether the server keeps the response, so it can be continued later.
+    pub store: bool,
+    pub temperature: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Built-in tools the model may use, e.g. `{ "type": "web_search_preview" }`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResponsesInputMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ResponsesStreamEvent {
+    #[serde(rename = "response.created")]
+    Created { response: ResponseObject },
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.output_text.annotation.added")]
+    OutputTextAnnotationAdded { annotation: ResponsesAnnotation },
+    #[serde(rename = "response.completed")]
+    Completed { response: ResponseObject },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponseObject },
+    #[serde(rename = "error")]
+    Error { message: String },
+    #[serde(other)]
+    Other,
+}
+
+/// An annotation on the output text, such as a citation of a web search
+/// result.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponsesAnnotation {
+    UrlCitation {
+        url: String,
+        #[serde(default)]
+        title: Option<String>,
+        /// The offset, in characters, of the cited text in the output.
+        start_index: usize,
+        end_index: usize,
+    },
+    FileCitation {
+        file_id: String,
+        #[serde(default)]
+        filename: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResponseObject {
+    pub id: String,
+    #[serde(default)]
+    pub error: Option<ResponseError>,
+    #[serde(default)]
+    pub usage: Option<ResponsesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResponseError {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResponsesUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+pub async fn stream_response(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    request: ResponsesRequest,
+    low_speed_timeout: Option<Duration>,
+) -> Result<BoxStream<'static, Result<ResponsesStreamEvent>>> {
+    let uri = format!("{api_url}/responses");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+
+    if let Some(low_speed_timeout) = low_speed_timeout {
+        request_builder = request_builder.low_speed_timeout(100, low_speed_timeout);
+    };
+
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let response = client.send(request).await?;
+    if response.status().is_success() {
+        let reader = BufReader::new(response.into_body());
+        Ok(reader
+            .lines()
+            .filter_map(|line| async move {
+                match line {
+                    Ok(line) => {
+                        // Each event's type is repeated in an `event:` line,
+                        // which is skipped.
+                        let line = line.strip_prefix("data: ")?;
+                        match serde_json::from_str(line) {
+                            Ok(event) => Some(Ok(event)),
+                            Err(error) => Some(Err(anyhow!(error))),
+                        }
+                    }
+                    Err(error) => Some(Err(anyhow!(error))),
+                }
+            })
+            .boxed())
+    } else {
+        Err(api_error(response).await)
     }
 }
 
@@ -359,6 +550,246 @@ pub fn embed<'a>(
     }
 }
 
+#[derive(Serialize)]
+struct ModerationRequest<'a> {
+    input: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerationResponse {
+    pub results: Vec<ModerationResult>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub categories: BTreeMap<String, bool>,
+}
+
+impl ModerationResult {
+    /// Returns the names of the categories the input was flagged for.
+    pub fn flagged_categories(&self) -> impl Iterator<Item = &str> {
+        self.categories
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(category, _)| category.as_str())
+    }
+}
+
+/// Classifies whether the input violates OpenAI's usage policies.
+pub fn moderate(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    input: &str,
+) -> impl 'static + Future<Output = Result<ModerationResponse>> {
+    let uri = format!("{api_url}/moderations");
+
+    let body = AsyncBody::from(serde_json::to_string(&ModerationRequest { input }).unwrap());
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .body(body)
+        .map(|request| client.send(request));
+
+    async move {
+        let mut response = request?.await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        if response.status().is_success() {
+            let response: ModerationResponse = serde_json::from_str(&body)
+                .context("failed to parse OpenAI moderation response")?;
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "error during moderation, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImageGenerationRequest {
+    pub model: String,
+    pub prompt: String,
+    pub n: u32,
+    /// The size of the image, e.g. "1024x1024".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ImageResponseFormat>,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageResponseFormat {
+    Url,
+    B64Json,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImageGenerationResponse {
+    pub data: Vec<ImageGenerationData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImageGenerationData {
+    pub url: Option<String>,
+    pub b64_json: Option<String>,
+    pub revised_prompt: Option<String>,
+}
+
+/// Generates images from a prompt.
+pub fn generate_image(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    request: ImageGenerationRequest,
+) -> impl 'static + Future<Output = Result<ImageGenerationResponse>> {
+    let uri = format!("{api_url}/images/generations");
+
+    let body = AsyncBody::from(serde_json::to_string(&request).unwrap());
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .body(body)
+        .map(|request| client.send(request));
+
+    async move {
+        let mut response = request?.await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        if response.status().is_success() {
+            let response: ImageGenerationResponse = serde_json::from_str(&body)
+                .context("failed to parse OpenAI image generation response")?;
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "error during image generation, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
+/// Returns a boundary between the parts of a multipart request body. It's
+/// random, so that no audio can contain it other than by chance.
+fn multipart_boundary() -> String {
+    format!("zed-multipart-boundary-{}", uuid::Uuid::new_v4().simple())
+}
+
+pub struct AudioTranscriptionRequest {
+    pub model: String,
+    /// The name of the audio file, whose extension tells the API its format.
+    pub file_name: String,
+    pub audio: Vec<u8>,
+    /// The language of the audio, as an ISO-639-1 code.
+    pub language: Option<String>,
+    /// Text that the audio follows on from, or that contains words it uses.
+    pub prompt: Option<String>,
+}
+
+impl AudioTranscriptionRequest {
+    /// Returns the `multipart/form-data` content type and body of the
+    /// request, asking for the transcription with segment timestamps.
+    pub fn multipart_body(&self) -> (String, Vec<u8>) {
+        let boundary = multipart_boundary();
+        let mut body = Vec::new();
+        let mut field = |name: &str, value: &str| {
+            body.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        };
+        field("model", &self.model);
+        field("response_format", "verbose_json");
+        field("timestamp_granularities[]", "segment");
+        if let Some(language) = &self.language {
+            field("language", language);
+        }
+        if let Some(prompt) = &self.prompt {
+            field("prompt", prompt);
+        }
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                self.file_name
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(&self.audio);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        (format!("multipart/form-data; boundary={boundary}"), body)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AudioTranscriptionResponse {
+    pub text: String,
+    #[serde(default)]
+    pub segments: Vec<AudioTranscriptionSegment>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AudioTranscriptionSegment {
+    /// The start of the segment, in seconds from the start of the audio.
+    pub start: f64,
+    /// The end of the segment, in seconds from the start of the audio.
+    pub end: f64,
+    pub text: String,
+}
+
+/// Transcribes audio into text.
+pub fn transcribe(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    request: AudioTranscriptionRequest,
+) -> impl 'static + Future<Output = Result<AudioTranscriptionResponse>> {
+    let uri = format!("{api_url}/audio/transcriptions");
+
+    let (content_type, body) = request.multipart_body();
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", content_type)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .body(AsyncBody::from(body))
+        .map(|request| client.send(request));
+
+    async move {
+        let mut response = request?.await?;
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        if response.status().is_success() {
+            let response: AudioTranscriptionResponse = serde_json::from_str(&body)
+                .context("failed to parse OpenAI transcription response")?;
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "error during transcription, status: {:?}, body: {:?}",
+                response.status(),
+                body
+            ))
+        }
+    }
+}
+
 pub fn extract_text_from_events(
     response: impl Stream<Item = Result<ResponseStreamEvent>>,
 ) -> impl Stream<Item = Result<String>> {
@@ -369,3 +800,30 @@ pub fn extract_text_from_events(
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_boundary() {
+        let request = AudioTranscriptionRequest {
+            model: "whisper-1".into(),
+            file_name: "audio.wav".into(),
+            audio: b"audio".to_vec(),
+            language: None,
+            prompt: None,
+        };
+        let (content_type, body) = request.multipart_body();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!("--{boundary}\r\n")));
+        assert!(body.ends_with(&format!("\r\n--{boundary}--\r\n")));
+
+        // Each request gets a boundary of its own.
+        let (other_content_type, _) = request.multipart_body();
+        assert_ne!(content_type, other_content_type);
+    }
+}
@@ -128,6 +128,29 @@ pub fn settings_file() -> &'static PathBuf {
     SETTINGS_FILE.get_or_init(|| config_dir().join("settings.json"))
 }
 
+/// Returns the path to the `managed_settings.json` file.
+///
+/// This file holds settings managed by an administrator, so it lives in a
+/// system-wide location that users typically can't write to.
+pub fn managed_settings_file() -> &'static PathBuf {
+    static MANAGED_SETTINGS_FILE: OnceLock<PathBuf> = OnceLock::new();
+    MANAGED_SETTINGS_FILE.get_or_init(|| {
+        if cfg!(target_os = "macos") {
+            return PathBuf::from("/Library/Application Support/Zed/managed_settings.json");
+        }
+
+        if cfg!(target_os = "windows") {
+            return std::env::var("ProgramData")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("C:\\ProgramData"))
+                .join("Zed")
+                .join("managed_settings.json");
+        }
+
+        PathBuf::from("/etc/zed/managed_settings.json")
+    })
+}
+
 /// Returns the path to the `keymap.json` file.
 pub fn keymap_file() -> &'static PathBuf {
     static KEYMAP_FILE: OnceLock<PathBuf> = OnceLock::new();
@@ -156,6 +179,33 @@ pub fn themes_dir() -> &'static PathBuf {
     THEMES_DIR.get_or_init(|| config_dir().join("themes"))
 }
 
+/// Returns the path to the language model rate limits directory.
+///
+/// This is where the request rate limits that Zed processes share are kept.
+pub fn language_model_rate_limits_dir() -> &'static PathBuf {
+    static LANGUAGE_MODEL_RATE_LIMITS_DIR: OnceLock<PathBuf> = OnceLock::new();
+    LANGUAGE_MODEL_RATE_LIMITS_DIR.get_or_init(|| temp_dir().join("language_model_rate_limits"))
+}
+
+/// Returns the path to the language model request journal directory.
+///
+/// This is where completion requests and their outputs are journaled, when
+/// journaling is enabled.
+pub fn language_model_journal_dir() -> &'static PathBuf {
+    static LANGUAGE_MODEL_JOURNAL_DIR: OnceLock<PathBuf> = OnceLock::new();
+    LANGUAGE_MODEL_JOURNAL_DIR.get_or_init(|| support_dir().join("language_model_journal"))
+}
+
+/// Returns the path to the language model stream metrics file.
+///
+/// This is where the rolling latency and error-rate statistics of recent
+/// completion requests are kept between restarts.
+pub fn language_model_stream_metrics_file() -> &'static PathBuf {
+    static LANGUAGE_MODEL_STREAM_METRICS_FILE: OnceLock<PathBuf> = OnceLock::new();
+    LANGUAGE_MODEL_STREAM_METRICS_FILE
+        .get_or_init(|| support_dir().join("language_model_stream_metrics.json"))
+}
+
 /// Returns the path to the contexts directory.
 ///
 /// This is where the saved contexts from the Assistant are stored.
@@ -263,3 +313,9 @@ pub fn local_tasks_file_relative_path() -> &'static Path {
 pub fn local_vscode_tasks_file_relative_path() -> &'static Path {
     Path::new(".vscode/tasks.json")
 }
+
+/// Returns the relative path to the folder within a project where assistant
+/// conversations are saved, when they are saved with the project.
+pub fn local_conversations_folder_relative_path() -> &'static Path {
+    Path::new(".zed/conversations")
+}
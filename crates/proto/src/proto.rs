@@ -226,6 +226,8 @@ messages!(
     (FormatBuffers, Foreground),
     (FormatBuffersResponse, Foreground),
     (FuzzySearchUsers, Foreground),
+    (GetBillingStatus, Foreground),
+    (GetBillingStatusResponse, Foreground),
     (GetCachedEmbeddings, Background),
     (GetCachedEmbeddingsResponse, Background),
     (GetChannelMembers, Foreground),
@@ -298,8 +300,17 @@ messages!(
     (CompleteWithLanguageModelResponse, Background),
     (StreamCompleteWithLanguageModel, Background),
     (StreamCompleteWithLanguageModelResponse, Background),
+    (ResumeLanguageModelStream, Background),
     (CountLanguageModelTokens, Background),
     (CountLanguageModelTokensResponse, Background),
+    (GetRecommendedLanguageModel, Background),
+    (GetRecommendedLanguageModelResponse, Background),
+    (GetUserSecrets, Background),
+    (GetUserSecretsResponse, Background),
+    (UpdateUserSecret, Background),
+    (DeleteUserSecret, Background),
+    (LeaseOrganizationSecret, Background),
+    (LeaseOrganizationSecretResponse, Background),
     (RefreshInlayHints, Foreground),
     (RejoinChannelBuffers, Foreground),
     (RejoinChannelBuffersResponse, Foreground),
@@ -355,6 +366,8 @@ messages!(
     (UpdateDiffBase, Foreground),
     (UpdateFollowers, Foreground),
     (UpdateInviteInfo, Foreground),
+    (UpdateBillingStatus, Foreground),
+    (UpdateLanguageModelUsage, Foreground),
     (UpdateLanguageServer, Foreground),
     (UpdateParticipantLocation, Foreground),
     (UpdateProject, Foreground),
@@ -439,6 +452,7 @@ request_messages!(
     (GetHover, GetHoverResponse),
     (GetNotifications, GetNotificationsResponse),
     (GetPrivateUserInfo, GetPrivateUserInfoResponse),
+    (GetBillingStatus, GetBillingStatusResponse),
     (GetProjectSymbols, GetProjectSymbolsResponse),
     (GetReferences, GetReferencesResponse),
     (GetSignatureHelp, GetSignatureHelpResponse),
@@ -474,7 +488,19 @@ request_messages!(
         StreamCompleteWithLanguageModel,
         StreamCompleteWithLanguageModelResponse
     ),
+    (
+        ResumeLanguageModelStream,
+        StreamCompleteWithLanguageModelResponse
+    ),
     (CountLanguageModelTokens, CountLanguageModelTokensResponse),
+    (
+        GetRecommendedLanguageModel,
+        GetRecommendedLanguageModelResponse
+    ),
+    (GetUserSecrets, GetUserSecretsResponse),
+    (UpdateUserSecret, Ack),
+    (DeleteUserSecret, Ack),
+    (LeaseOrganizationSecret, LeaseOrganizationSecretResponse),
     (RefreshInlayHints, Ack),
     (RejoinChannelBuffers, RejoinChannelBuffersResponse),
     (RejoinRoom, RejoinRoomResponse),
@@ -710,6 +736,68 @@ pub fn split_worktree_update(
     })
 }
 
+/// The offset and checksum of the events received so far on a language model
+/// completion stream, used to resume the stream without gaps or duplicates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LanguageModelStreamTranscript {
+    pub offset: u64,
+    pub checksum: u64,
+}
+
+impl Default for LanguageModelStreamTranscript {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            // The FNV-1a offset basis.
+            checksum: 0xcbf29ce484222325,
+        }
+    }
+}
+
+impl LanguageModelStreamTranscript {
+    /// Appends an event to the transcript, returning the response to send
+    /// for it.
+    pub fn push(&mut self, event: String) -> StreamCompleteWithLanguageModelResponse {
+        self.checksum = fnv1a(self.checksum, event.as_bytes());
+        let response = StreamCompleteWithLanguageModelResponse {
+            event,
+            offset: self.offset,
+            checksum: self.checksum,
+            end_of_stream: false,
+            failover_provider: None,
+        };
+        self.offset += 1;
+        response
+    }
+
+    /// Appends a received event to the transcript, failing if it doesn't
+    /// follow on from the events received before it.
+    pub fn receive(
+        &mut self,
+        response: &StreamCompleteWithLanguageModelResponse,
+    ) -> anyhow::Result<()> {
+        let checksum = fnv1a(self.checksum, response.event.as_bytes());
+        if response.offset != self.offset || response.checksum != checksum {
+            return Err(anyhow!(
+                "completion stream event {} doesn't match the transcript at {}",
+                response.offset,
+                self.offset
+            ));
+        }
+        self.checksum = checksum;
+        self.offset += 1;
+        Ok(())
+    }
+}
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -737,4 +825,30 @@ mod tests {
         };
         assert_eq!(PeerId::from_u64(peer_id.as_u64()), peer_id);
     }
+
+    #[test]
+    fn test_language_model_stream_transcript() {
+        let mut sent = LanguageModelStreamTranscript::default();
+        let first = sent.push("a".into());
+        let second = sent.push("b".into());
+        let third = sent.push("c".into());
+
+        let mut received = LanguageModelStreamTranscript::default();
+        received.receive(&first).unwrap();
+        // Skipping or repeating events is detected.
+        let (mut skipped, mut repeated) = (received, received);
+        assert!(skipped.receive(&third).is_err());
+        assert!(repeated.receive(&first).is_err());
+        received.receive(&second).unwrap();
+        received.receive(&third).unwrap();
+        assert_eq!(received, sent);
+
+        // Events that were altered are detected.
+        let mut received = LanguageModelStreamTranscript::default();
+        let altered = StreamCompleteWithLanguageModelResponse {
+            event: "x".into(),
+            ..first
+        };
+        assert!(received.receive(&altered).is_err());
+    }
 }
@@ -92,6 +92,28 @@ pub fn handle_settings_file_changes(
     .detach();
 }
 
+/// Applies the settings managed by an administrator whenever their file
+/// changes. Unlike the user settings, startup doesn't wait for these.
+pub fn handle_managed_settings_file_changes(
+    mut managed_settings_file_rx: mpsc::UnboundedReceiver<String>,
+    cx: &mut AppContext,
+) {
+    cx.spawn(move |mut cx| async move {
+        while let Some(managed_settings_content) = managed_settings_file_rx.next().await {
+            let result = cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_managed_settings(&managed_settings_content, cx)
+                    .log_err();
+                cx.refresh();
+            });
+            if result.is_err() {
+                break; // App dropped
+            }
+        }
+    })
+    .detach();
+}
+
 pub fn update_settings_file<T: Settings>(
     fs: Arc<dyn Fs>,
     cx: &AppContext,
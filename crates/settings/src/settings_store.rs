@@ -97,6 +97,12 @@ pub struct SettingsSources<'a, T> {
     pub default: &'a T,
     /// Settings provided by extensions.
     pub extensions: Option<&'a T>,
+    /// The settings managed by an administrator.
+    ///
+    /// These are applied before the user settings, so they act as defaults.
+    /// Settings can choose not to let some of their managed values be
+    /// overridden by the user.
+    pub managed: Option<&'a T>,
     /// The user settings.
     pub user: Option<&'a T>,
     /// The user settings for the current release channel.
@@ -115,6 +121,7 @@ impl<'a, T: Serialize> SettingsSources<'a, T> {
     pub fn customizations(&self) -> impl Iterator<Item = &T> {
         self.extensions
             .into_iter()
+            .chain(self.managed)
             .chain(self.user)
             .chain(self.release_channel)
             .chain(self.project.iter().copied())
@@ -154,6 +161,7 @@ pub struct SettingsStore {
     raw_default_settings: serde_json::Value,
     raw_user_settings: serde_json::Value,
     raw_extension_settings: serde_json::Value,
+    raw_managed_settings: serde_json::Value,
     raw_local_settings: BTreeMap<(usize, Arc<Path>), serde_json::Value>,
     tab_size_callback: Option<(
         TypeId,
@@ -203,6 +211,7 @@ impl SettingsStore {
             raw_default_settings: serde_json::json!({}),
             raw_user_settings: serde_json::json!({}),
             raw_extension_settings: serde_json::json!({}),
+            raw_managed_settings: serde_json::json!({}),
             raw_local_settings: Default::default(),
             tab_size_callback: Default::default(),
             setting_file_updates_tx,
@@ -256,12 +265,17 @@ impl SettingsStore {
                 .deserialize_setting(&self.raw_extension_settings)
                 .log_err();
 
+            let managed_value = setting_value
+                .deserialize_setting(&self.raw_managed_settings)
+                .log_err();
+
             if let Some(setting) = setting_value
                 .load_setting(
                     SettingsSources {
                         default: &default_settings,
                         release_channel: release_channel_value.as_ref(),
                         extensions: extension_value.as_ref(),
+                        managed: managed_value.as_ref(),
                         user: user_value.as_ref(),
                         project: &[],
                     },
@@ -504,6 +518,26 @@ impl SettingsStore {
         }
     }
 
+    /// Sets the settings managed by an administrator via a JSON string.
+    pub fn set_managed_settings(
+        &mut self,
+        managed_settings_content: &str,
+        cx: &mut AppContext,
+    ) -> Result<()> {
+        let settings: serde_json::Value = if managed_settings_content.is_empty() {
+            parse_json_with_comments("{}")?
+        } else {
+            parse_json_with_comments(managed_settings_content)?
+        };
+        if settings.is_object() {
+            self.raw_managed_settings = settings;
+            self.recompute_values(None, cx)?;
+            Ok(())
+        } else {
+            Err(anyhow!("settings must be an object"))
+        }
+    }
+
     /// Add or remove a set of local settings via a JSON string.
     pub fn set_local_settings(
         &mut self,
@@ -674,6 +708,10 @@ impl SettingsStore {
                 .deserialize_setting(&self.raw_extension_settings)
                 .log_err();
 
+            let managed_settings = setting_value
+                .deserialize_setting(&self.raw_managed_settings)
+                .log_err();
+
             let user_settings = setting_value
                 .deserialize_setting(&self.raw_user_settings)
                 .log_err();
@@ -698,6 +736,7 @@ impl SettingsStore {
                         SettingsSources {
                             default: &default_settings,
                             extensions: extension_settings.as_ref(),
+                            managed: managed_settings.as_ref(),
                             user: user_settings.as_ref(),
                             release_channel: release_channel_settings.as_ref(),
                             project: &[],
@@ -745,6 +784,7 @@ impl SettingsStore {
                             SettingsSources {
                                 default: &default_settings,
                                 extensions: extension_settings.as_ref(),
+                                managed: managed_settings.as_ref(),
                                 user: user_settings.as_ref(),
                                 release_channel: release_channel_settings.as_ref(),
                                 project: &project_settings_stack.iter().collect::<Vec<_>>(),
@@ -775,6 +815,7 @@ impl Debug for SettingsStore {
             )
             .field("default_settings", &self.raw_default_settings)
             .field("user_settings", &self.raw_user_settings)
+            .field("managed_settings", &self.raw_managed_settings)
             .field("local_settings", &self.raw_local_settings)
             .finish_non_exhaustive()
     }
@@ -800,6 +841,9 @@ impl<T: Settings> AnySettingValue for SettingValue<T> {
                 extensions: values
                     .extensions
                     .map(|value| value.0.downcast_ref::<T::FileContent>().unwrap()),
+                managed: values
+                    .managed
+                    .map(|value| value.0.downcast_ref::<T::FileContent>().unwrap()),
                 user: values
                     .user
                     .map(|value| value.0.downcast_ref::<T::FileContent>().unwrap()),
@@ -1249,6 +1293,60 @@ mod tests {
         );
     }
 
+    #[gpui::test]
+    fn test_setting_store_managed_settings(cx: &mut AppContext) {
+        let mut store = SettingsStore::new(cx);
+        store.register_setting::<UserSettings>(cx);
+        store
+            .set_default_settings(
+                r#"{
+                    "user": {
+                        "name": "John Doe",
+                        "age": 30,
+                        "staff": false
+                    }
+                }"#,
+                cx,
+            )
+            .unwrap();
+
+        // Managed settings override the defaults...
+        store
+            .set_managed_settings(r#"{ "user": { "staff": true, "age": 40 } }"#, cx)
+            .unwrap();
+        assert_eq!(
+            store.get::<UserSettings>(None),
+            &UserSettings {
+                name: "John Doe".to_string(),
+                age: 40,
+                staff: true,
+            }
+        );
+
+        // ...and can be overridden by the user.
+        store
+            .set_user_settings(r#"{ "user": { "age": 31 } }"#, cx)
+            .unwrap();
+        assert_eq!(
+            store.get::<UserSettings>(None),
+            &UserSettings {
+                name: "John Doe".to_string(),
+                age: 31,
+                staff: true,
+            }
+        );
+
+        store.set_managed_settings("", cx).unwrap();
+        assert_eq!(
+            store.get::<UserSettings>(None),
+            &UserSettings {
+                name: "John Doe".to_string(),
+                age: 31,
+                staff: false,
+            }
+        );
+    }
+
     #[gpui::test]
     fn test_setting_store_update(cx: &mut AppContext) {
         let mut store = SettingsStore::new(cx);
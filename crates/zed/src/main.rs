@@ -30,7 +30,10 @@ use parking_lot::Mutex;
 use recent_projects::open_ssh_project;
 use release_channel::{AppCommitSha, AppVersion};
 use session::{AppSession, Session};
-use settings::{handle_settings_file_changes, watch_config_file, Settings, SettingsStore};
+use settings::{
+    handle_managed_settings_file_changes, handle_settings_file_changes, watch_config_file,
+    Settings, SettingsStore,
+};
 use simplelog::ConfigBuilder;
 use smol::process::Command;
 use std::{
@@ -363,6 +366,11 @@ fn main() {
         fs.clone(),
         paths::settings_file().clone(),
     );
+    let managed_settings_file_rx = watch_config_file(
+        &app.background_executor(),
+        fs.clone(),
+        paths::managed_settings_file().clone(),
+    );
     let user_keymap_file_rx = watch_config_file(
         &app.background_executor(),
         fs.clone(),
@@ -412,6 +420,7 @@ fn main() {
 
         settings::init(cx);
         handle_settings_file_changes(user_settings_file_rx, cx);
+        handle_managed_settings_file_changes(managed_settings_file_rx, cx);
         handle_keymap_file_changes(user_keymap_file_rx, cx);
 
         client::init_settings(cx);